@@ -15,7 +15,7 @@ use self::settings::{EndpointSettings, HttpServerSettings, ResponseSettings};
 use anyhow::anyhow;
 use foundations::cli::{Arg, ArgAction, Cli};
 use foundations::settings::collections::Map;
-use foundations::telemetry::{init_with_server, log, tracing, TelemetryContext};
+use foundations::telemetry::{init_with_server, log, tracing, SelfCheckReport, TelemetryContext};
 use foundations::BootstrapResult;
 use futures_util::stream::{FuturesUnordered, StreamExt};
 use hyper::server::conn::Http;
@@ -50,6 +50,14 @@ async fn main() -> BootstrapResult<()> {
     // server yet - we have some extra security-related steps to do.
     let tele_serv_fut = init_with_server(&service_info, &cli.settings.telemetry, vec![])?;
 
+    // Check that the telemetry outputs are reachable and exit, if requested.
+    if cli.telemetry_selfcheck() {
+        let report = SelfCheckReport::run(&cli.settings.telemetry);
+
+        println!("{report}");
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
     if let Some(tele_serv_addr) = tele_serv_fut.server_addr() {
         log::info!("Telemetry server is listening on http://{}", tele_serv_addr);
     }
@@ -4,22 +4,33 @@ use proc_macro2::Span;
 use quote::{quote, ToTokens};
 use syn::punctuated::Punctuated;
 use syn::{
-    parse_macro_input, parse_quote, Attribute, AttributeArgs, ExprStruct, Ident, LitStr, Path,
-    Token, Type, Visibility,
+    parse_macro_input, parse_quote, Attribute, AttributeArgs, Expr, ExprStruct, Ident, LitStr,
+    Path, Token, Type, Visibility,
 };
 
 mod parsing;
 
+mod units;
+
 #[derive(FromMeta)]
 struct MacroArgs {
     #[darling(default = "Self::default_crate_path")]
     crate_path: Path,
+
+    duration_unit: Option<units::DurationUnit>,
+
+    service: Option<LitStr>,
+
+    registry: Option<LitStr>,
 }
 
 impl Default for MacroArgs {
     fn default() -> Self {
         Self {
             crate_path: Self::default_crate_path(),
+            duration_unit: None,
+            service: None,
+            registry: None,
         }
     }
 }
@@ -54,6 +65,13 @@ struct FnAttrs {
     doc: String,
     ctor: Option<ExprStruct>,
     optional: bool,
+    /// Flag in the settings that enables the reporting of the optional metric.
+    optional_flag: Option<LitStr>,
+    /// Whether a `remove_<metric>` function is generated for the metric with labels.
+    removable: bool,
+    /// TTL of the label sets, if the metric has labels.
+    ttl: Option<Expr>,
+    unit: Option<units::MetricUnit>,
 }
 
 struct FnArg {
@@ -68,12 +86,18 @@ struct FnArg {
 struct ArgAttrs {
     serde: Option<Attribute>,
     serde_as: Option<Attribute>,
+    default: Option<Expr>,
 }
 
 enum ArgMode {
     ByValue(Type),
     Clone(Type),
     Into(Type),
+    /// `Option<T>` argument, the label is omitted if the value is `None`.
+    Optional(Type),
+    /// `Option<T>` argument with a `#[default]` attribute, the label has the default value if
+    /// the value is `None`. Contains `T`.
+    Defaulted(Type, Box<Expr>),
 }
 
 pub(crate) fn expand(args: TokenStream, item: TokenStream) -> TokenStream {
@@ -89,8 +113,29 @@ pub(crate) fn expand(args: TokenStream, item: TokenStream) -> TokenStream {
 fn expand_from_parsed(args: MacroArgs, extern_: Mod) -> proc_macro2::TokenStream {
     let MacroArgs {
         crate_path: foundations,
+        duration_unit,
+        service,
+        registry,
     } = &args;
 
+    if let (Some(_), Some(registry)) = (service, registry) {
+        return syn::Error::new_spanned(
+            registry,
+            "`registry` argument can't be used together with `service` argument",
+        )
+        .to_compile_error();
+    }
+
+    if let Some(unit) = duration_unit {
+        if let Err(err) = units::validate(*unit, &extern_.fns) {
+            return err.to_compile_error();
+        }
+
+        if let Err(err) = units::validate_name_suffixes(&extern_.fns) {
+            return err.to_compile_error();
+        }
+    }
+
     let Mod {
         attrs: mod_attrs,
         vis: mod_vis,
@@ -99,8 +144,6 @@ fn expand_from_parsed(args: MacroArgs, extern_: Mod) -> proc_macro2::TokenStream
         fns,
     } = extern_;
 
-    let reexports = quote! { #foundations::reexports_for_macros };
-
     // This should be using `Span::def_site` but it is currently unstable.
     let metrics_struct = Ident::new(&format!("__{mod_name}_Metrics"), Span::call_site());
 
@@ -111,16 +154,37 @@ fn expand_from_parsed(args: MacroArgs, extern_: Mod) -> proc_macro2::TokenStream
 
     let registry_init = |var: &str, kind: &str| {
         let var = Ident::new(var, Span::call_site());
-        let method = Ident::new(&format!("get_{kind}_subsystem"), Span::call_site());
 
-        quote! {
-            let #var = &mut *#foundations::telemetry::metrics::internal::Registries::#method(stringify!(#mod_name));
+        match (service, registry) {
+            (Some(service), _) => {
+                let method = Ident::new(&format!("service_{kind}_subsystem"), Span::call_site());
+
+                quote! {
+                    let #var = &mut *#foundations::telemetry::metrics::internal::Registries::#method(registries, #service, stringify!(#mod_name));
+                }
+            }
+            (None, Some(registry)) => {
+                let method = Ident::new(&format!("named_{kind}_subsystem"), Span::call_site());
+
+                quote! {
+                    let #var = &mut *#foundations::telemetry::metrics::internal::Registries::#method(registries, #registry, stringify!(#mod_name));
+                }
+            }
+            (None, None) => {
+                let method = Ident::new(&format!("{kind}_subsystem"), Span::call_site());
+
+                quote! {
+                    let #var = &mut *#foundations::telemetry::metrics::internal::Registries::#method(registries, stringify!(#mod_name));
+                }
+            }
         }
     };
 
+    // NOTE: the optional metrics with a flag are registered in the main registry if the flag is
+    // enabled.
     let init_registry = fns
         .iter()
-        .any(|fn_| !fn_.attrs.optional)
+        .any(|fn_| !fn_.attrs.optional || fn_.attrs.optional_flag.is_some())
         .then(|| registry_init("registry", "main"));
 
     let init_opt_registry = fns
@@ -128,7 +192,15 @@ fn expand_from_parsed(args: MacroArgs, extern_: Mod) -> proc_macro2::TokenStream
         .any(|fn_| fn_.attrs.optional)
         .then(|| registry_init("opt_registry", "opt"));
 
-    let metric_inits = fns.iter().map(|fn_| metric_init(foundations, fn_));
+    let metric_inits = fns
+        .iter()
+        .map(|fn_| metric_init(foundations, &mod_name, fn_));
+
+    let registries = if fns.is_empty() {
+        quote! { _ }
+    } else {
+        quote! { registries }
+    };
 
     let metric_fns = fns
         .iter()
@@ -146,8 +218,8 @@ fn expand_from_parsed(args: MacroArgs, extern_: Mod) -> proc_macro2::TokenStream
             #(#label_set_structs)*
 
             #[allow(non_upper_case_globals)]
-            static #metrics_struct: #reexports::once_cell::sync::Lazy<#metrics_struct> =
-                #reexports::once_cell::sync::Lazy::new(|| {
+            static #metrics_struct: #foundations::telemetry::metrics::internal::ModuleMetrics<#metrics_struct> =
+                #foundations::telemetry::metrics::internal::ModuleMetrics::new(|#registries| {
                     #init_registry
                     #init_opt_registry
 
@@ -164,38 +236,38 @@ fn expand_from_parsed(args: MacroArgs, extern_: Mod) -> proc_macro2::TokenStream
 /// Gets the type of the metric for its field in metric struct.
 fn metric_field(foundations: &Path, fn_: &ItemFn) -> proc_macro2::TokenStream {
     let ItemFn {
-        attrs: FnAttrs { cfg, ctor, .. },
+        attrs: FnAttrs { cfg, ctor, ttl, .. },
         args,
         ty: metric_ty,
         ident: metric_name,
         ..
     } = fn_;
 
+    let family = family_path(foundations, ttl.is_some());
+
     let field_ty = if args.is_empty() {
         metric_ty.to_token_stream()
     } else if let Some(ExprStruct {
         path: ctor_path, ..
     }) = ctor
     {
-        quote! {
-            #foundations::reexports_for_macros::prometools::serde::Family<
-                #metric_name,
-                #metric_ty,
-                #ctor_path,
-            >
-        }
+        quote! { #family<#metric_name, #metric_ty, #ctor_path,> }
     } else {
-        quote! {
-            #foundations::reexports_for_macros::prometools::serde::Family<
-                #metric_name,
-                #metric_ty,
-            >
-        }
+        quote! { #family<#metric_name, #metric_ty,> }
     };
 
     quote! { #(#cfg)* #metric_name: #field_ty }
 }
 
+/// Returns the path of the family type of the metrics with labels.
+fn family_path(foundations: &Path, expiring: bool) -> proc_macro2::TokenStream {
+    if expiring {
+        quote! { #foundations::telemetry::metrics::ExpiringFamily }
+    } else {
+        quote! { #foundations::telemetry::metrics::LimitedFamily }
+    }
+}
+
 /// Returns the definition for the label set struct, if this metric uses labels.
 fn label_set_struct(foundations: &Path, fn_: &ItemFn) -> Option<proc_macro2::TokenStream> {
     let ItemFn {
@@ -235,9 +307,15 @@ fn label_set_struct(foundations: &Path, fn_: &ItemFn) -> Option<proc_macro2::Tok
             ArgMode::ByValue(ty) => ty,
             ArgMode::Clone(ty) => ty,
             ArgMode::Into(ty) => ty,
+            ArgMode::Optional(ty) => ty,
+            ArgMode::Defaulted(ty, _) => ty,
         };
 
-        quote! { #serde_as #serde #label_name #colon_token #label_type }
+        let skip_none = matches!(mode, ArgMode::Optional(_)).then(|| {
+            quote! { #[serde(skip_serializing_if = "::std::option::Option::is_none")] }
+        });
+
+        quote! { #serde_as #serde #skip_none #label_name #colon_token #label_type }
     });
 
     Some(quote! {
@@ -258,51 +336,107 @@ fn label_set_struct(foundations: &Path, fn_: &ItemFn) -> Option<proc_macro2::Tok
     })
 }
 
-fn metric_init(foundations: &Path, fn_: &ItemFn) -> proc_macro2::TokenStream {
+fn metric_init(foundations: &Path, mod_name: &Ident, fn_: &ItemFn) -> proc_macro2::TokenStream {
     let ItemFn {
         attrs:
             FnAttrs {
                 cfg,
                 doc,
                 optional,
+                optional_flag,
                 ctor,
+                removable: _,
+                ttl,
+                unit,
             },
         ident: field_name,
         args,
+        ty: metric_ty,
         ..
     } = fn_;
 
     let reexports = quote! { #foundations::reexports_for_macros };
-    let registry = Ident::new(
-        if *optional {
-            "opt_registry"
-        } else {
-            "registry"
+    let registry = match (optional, optional_flag) {
+        (true, Some(flag)) => quote! {
+            if #foundations::telemetry::metrics::internal::is_optional_flag_enabled(#flag) {
+                &mut *registry
+            } else {
+                &mut *opt_registry
+            }
         },
-        Span::call_site(),
-    );
+        (true, None) => quote! { opt_registry },
+        (false, _) => quote! { registry },
+    };
 
-    let metric_init = match ctor {
-        Some(ctor) if args.is_empty() => quote! {
+    // NOTE: histogram buckets can be overridden in the settings, so the constructor is looked up
+    // by the metric name at initialization.
+    let ctor = ctor.as_ref().map(|ctor| {
+        let is_histogram_builder = ctor
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "HistogramBuilder");
+
+        if is_histogram_builder {
+            quote! {
+                #foundations::telemetry::metrics::internal::histogram_builder(
+                    ::std::concat!(::std::stringify!(#mod_name), "_", ::std::stringify!(#field_name)),
+                    #ctor
+                )
+            }
+        } else {
+            quote! { #ctor }
+        }
+    });
+
+    let metric_init = match (&ctor, ttl) {
+        (Some(ctor), _) if args.is_empty() => quote! {
             #reexports::prometheus_client::metrics::family::MetricConstructor::new_metric(&(#ctor))
         },
-        Some(ctor) => quote! {
-            #reexports::prometools::serde::Family::new_with_constructor(#ctor)
+        (Some(ctor), Some(ttl)) => quote! {
+            #foundations::telemetry::metrics::ExpiringFamily::new_with_constructor(#ttl, #ctor)
+        },
+        (Some(ctor), None) => quote! {
+            #foundations::telemetry::metrics::LimitedFamily::new_with_constructor(#ctor)
         },
-        None => quote! { ::std::default::Default::default() },
+        (None, Some(ttl)) if !args.is_empty() => quote! {
+            #foundations::telemetry::metrics::ExpiringFamily::new(#ttl)
+        },
+        (None, _) => quote! { ::std::default::Default::default() },
     };
 
-    quote! {
-        #(#cfg)*
-        #field_name: {
-            let metric = #metric_init;
-
+    let register = match unit.or_else(|| units::MetricUnit::of_metric_type(metric_ty)) {
+        Some(unit) => {
+            let name = unit.strip_suffix(&field_name.to_string()).to_string();
+            let name = LitStr::new(&name, field_name.span());
+            let unit = Ident::new(unit.variant(), Span::call_site());
+
+            quote! {
+                #reexports::prometheus_client::registry::Registry::register_with_unit(
+                    #registry,
+                    #name,
+                    str::trim(#doc),
+                    #reexports::prometheus_client::registry::Unit::#unit,
+                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                );
+            }
+        }
+        None => quote! {
             #reexports::prometheus_client::registry::Registry::register(
                 #registry,
                 ::std::stringify!(#field_name),
                 str::trim(#doc),
                 ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
             );
+        },
+    };
+
+    quote! {
+        #(#cfg)*
+        #field_name: {
+            let metric = #metric_init;
+
+            #register
 
             metric
         }
@@ -311,7 +445,14 @@ fn metric_init(foundations: &Path, fn_: &ItemFn) -> proc_macro2::TokenStream {
 
 fn metric_fn(foundations: &Path, metrics_struct: &Ident, fn_: &ItemFn) -> proc_macro2::TokenStream {
     let ItemFn {
-        attrs: FnAttrs { cfg, doc, .. },
+        attrs:
+            FnAttrs {
+                cfg,
+                doc,
+                removable,
+                ttl,
+                ..
+            },
         fn_token,
         vis: fn_vis,
         ident: metric_name,
@@ -320,23 +461,37 @@ fn metric_fn(foundations: &Path, metrics_struct: &Ident, fn_: &ItemFn) -> proc_m
         ty: metric_type,
     } = fn_;
 
-    let fn_args = args.iter().map(|arg| {
-        let FnArg {
-            ident: arg_name,
-            colon_token,
-            ty: arg_ty,
-            ..
-        } = arg;
+    let fn_args: Vec<_> = args
+        .iter()
+        .map(|arg| {
+            let FnArg {
+                ident: arg_name,
+                colon_token,
+                ty: arg_ty,
+                ..
+            } = arg;
 
-        quote! { #arg_name #colon_token #arg_ty }
-    });
+            quote! { #arg_name #colon_token #arg_ty }
+        })
+        .collect();
 
-    let fn_body = if args.is_empty() {
-        quote! {
-            ::std::clone::Clone::clone(&#metrics_struct.#metric_name)
-        }
-    } else {
-        let label_inits = args.iter().map(|arg| {
+    if args.is_empty() {
+        return quote! {
+            #[doc = #doc]
+            #(#cfg)*
+            #[must_use]
+            #fn_vis #fn_token #metric_name() #arrow_token #metric_type {
+                #foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                    &#metrics_struct,
+                    |metrics| ::std::clone::Clone::clone(&metrics.#metric_name),
+                )
+            }
+        };
+    }
+
+    let label_inits: Vec<_> = args
+        .iter()
+        .map(|arg| {
             let FnArg {
                 ident: arg_name,
                 colon_token,
@@ -345,34 +500,70 @@ fn metric_fn(foundations: &Path, metrics_struct: &Ident, fn_: &ItemFn) -> proc_m
             } = arg;
 
             match mode {
-                ArgMode::ByValue(_) => quote! { #arg_name },
+                ArgMode::ByValue(_) | ArgMode::Optional(_) => quote! { #arg_name },
                 ArgMode::Clone(_) => {
                     quote! { #arg_name #colon_token ::std::clone::Clone::clone(#arg_name) }
                 }
                 ArgMode::Into(_) => {
                     quote! { #arg_name #colon_token ::std::convert::Into::into(#arg_name) }
                 }
+                ArgMode::Defaulted(_, default) => quote! {
+                    #arg_name #colon_token ::std::option::Option::unwrap_or_else(
+                        #arg_name,
+                        || ::std::convert::Into::into(#default),
+                    )
+                },
             }
-        });
+        })
+        .collect();
 
-        quote! {
-            ::std::clone::Clone::clone(
-                &#foundations::reexports_for_macros::prometools::serde::Family::get_or_create(
-                    &#metrics_struct.#metric_name,
-                    &#metric_name {
-                        #(#label_inits,)*
-                    },
-                )
+    let family = family_path(foundations, ttl.is_some());
+    let metric_fn = quote! {
+        #[doc = #doc]
+        #(#cfg)*
+        #[must_use]
+        #fn_vis #fn_token #metric_name(#(#fn_args,)*) #arrow_token #metric_type {
+            let label_set = #metric_name {
+                #(#label_inits,)*
+            };
+
+            #foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                &#metrics_struct,
+                |metrics| <#metric_type as ::std::clone::Clone>::clone(
+                    &#family::get_or_create(&metrics.#metric_name, &label_set)
+                ),
             )
         }
     };
 
+    if !removable {
+        return metric_fn;
+    }
+
+    let remove_fn_name = Ident::new(&format!("remove_{metric_name}"), metric_name.span());
+    let remove_doc = LitStr::new(
+        &format!(
+            " Removes the label set from the [`{metric_name}`] metric, returning whether it was \
+            present."
+        ),
+        Span::call_site(),
+    );
+
     quote! {
-        #[doc = #doc]
+        #metric_fn
+
+        #[doc = #remove_doc]
         #(#cfg)*
-        #[must_use]
-        #fn_vis #fn_token #metric_name(#(#fn_args,)*) #arrow_token #metric_type {
-            #fn_body
+        #[allow(dead_code)]
+        #fn_vis #fn_token #remove_fn_name(#(#fn_args,)*) -> bool {
+            let label_set = #metric_name {
+                #(#label_inits,)*
+            };
+
+            #foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                &#metrics_struct,
+                |metrics| #family::remove(&metrics.#metric_name, &label_set),
+            )
         }
     }
 }
@@ -405,8 +596,8 @@ mod tests {
                 struct __empty_Metrics {}
 
                 #[allow(non_upper_case_globals)]
-                static __empty_Metrics: ::foundations::reexports_for_macros::once_cell::sync::Lazy<__empty_Metrics> =
-                    ::foundations::reexports_for_macros::once_cell::sync::Lazy::new(|| { __empty_Metrics {} });
+                static __empty_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__empty_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|_| { __empty_Metrics {} });
             }
         };
 
@@ -440,9 +631,9 @@ mod tests {
                 }
 
                 #[allow(non_upper_case_globals)]
-                static __oxy_Metrics: tarmac::reexports_for_macros::once_cell::sync::Lazy<__oxy_Metrics> =
-                    tarmac::reexports_for_macros::once_cell::sync::Lazy::new(|| {
-                        let registry = &mut *tarmac::telemetry::metrics::internal::Registries::get_main_subsystem(stringify!(oxy));
+                static __oxy_Metrics: tarmac::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    tarmac::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *tarmac::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
 
                         __oxy_Metrics {
                             connections_total: {
@@ -463,7 +654,155 @@ mod tests {
                 #[doc = " Total number of connections"]
                 #[must_use]
                 pub fn connections_total() -> Counter {
-                    ::std::clone::Clone::clone(&__oxy_Metrics.connections_total)
+                    tarmac::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.connections_total),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_hosted_service() {
+        let attr = parse_attr! {
+            #[metrics(service = "auth")]
+        };
+
+        let src = parse_quote! {
+            pub mod oxy {
+                /// Total number of connections
+                pub fn connections_total() -> Counter;
+
+                /// Number of stalled futures
+                #[optional]
+                pub fn stalled_futures_total() -> Counter;
+            }
+        };
+
+        let actual = expand_from_parsed(attr, src).to_string();
+
+        let expected = code_str! {
+            pub mod oxy {
+                use super::*;
+
+                #[allow(non_camel_case_types)]
+                struct __oxy_Metrics {
+                    connections_total: Counter,
+                    stalled_futures_total: Counter,
+                }
+
+                #[allow(non_upper_case_globals)]
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::service_main_subsystem(registries, "auth", stringify!(oxy));
+                        let opt_registry = &mut *::foundations::telemetry::metrics::internal::Registries::service_opt_subsystem(registries, "auth", stringify!(oxy));
+
+                        __oxy_Metrics {
+                            connections_total: {
+                                let metric = ::std::default::Default::default();
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
+                                    registry,
+                                    ::std::stringify!(connections_total),
+                                    str::trim(" Total number of connections"),
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
+                            },
+                            stalled_futures_total: {
+                                let metric = ::std::default::Default::default();
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
+                                    opt_registry,
+                                    ::std::stringify!(stalled_futures_total),
+                                    str::trim(" Number of stalled futures"),
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
+                            },
+                        }
+                    });
+
+                #[doc = " Total number of connections"]
+                #[must_use]
+                pub fn connections_total() -> Counter {
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.connections_total),
+                    )
+                }
+
+                #[doc = " Number of stalled futures"]
+                #[must_use]
+                pub fn stalled_futures_total() -> Counter {
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.stalled_futures_total),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_named_registry() {
+        let attr = parse_attr! {
+            #[metrics(registry = "internal")]
+        };
+
+        let src = parse_quote! {
+            pub mod oxy {
+                /// Total number of connections
+                pub fn connections_total() -> Counter;
+            }
+        };
+
+        let actual = expand_from_parsed(attr, src).to_string();
+
+        let expected = code_str! {
+            pub mod oxy {
+                use super::*;
+
+                #[allow(non_camel_case_types)]
+                struct __oxy_Metrics {
+                    connections_total: Counter,
+                }
+
+                #[allow(non_upper_case_globals)]
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::named_main_subsystem(registries, "internal", stringify!(oxy));
+
+                        __oxy_Metrics {
+                            connections_total: {
+                                let metric = ::std::default::Default::default();
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
+                                    registry,
+                                    ::std::stringify!(connections_total),
+                                    str::trim(" Total number of connections"),
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
+                            },
+                        }
+                    });
+
+                #[doc = " Total number of connections"]
+                #[must_use]
+                pub fn connections_total() -> Counter {
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.connections_total),
+                    )
                 }
             }
         };
@@ -497,9 +836,9 @@ mod tests {
                 }
 
                 #[allow(non_upper_case_globals)]
-                static __oxy_Metrics: ::foundations::reexports_for_macros::once_cell::sync::Lazy<__oxy_Metrics> =
-                    ::foundations::reexports_for_macros::once_cell::sync::Lazy::new(|| {
-                        let opt_registry = &mut *::foundations::telemetry::metrics::internal::Registries::get_opt_subsystem(stringify!(oxy));
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let opt_registry = &mut *::foundations::telemetry::metrics::internal::Registries::opt_subsystem(registries, stringify!(oxy));
 
                         __oxy_Metrics {
                             connections_total: {
@@ -520,7 +859,10 @@ mod tests {
                 #[doc = " Total number of connections"]
                 #[must_use]
                 pub(crate) fn connections_total() -> Counter {
-                    ::std::clone::Clone::clone(&__oxy_Metrics.connections_total)
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.connections_total),
+                    )
                 }
             }
         };
@@ -557,7 +899,7 @@ mod tests {
                 #[allow(non_camel_case_types)]
                 struct __oxy_Metrics {
                     connections_errors_total:
-                        ::foundations::reexports_for_macros::prometools::serde::Family<
+                        ::foundations::telemetry::metrics::LimitedFamily<
                             connections_errors_total,
                             Counter,
                         >,
@@ -583,9 +925,9 @@ mod tests {
                 }
 
                 #[allow(non_upper_case_globals)]
-                static __oxy_Metrics: ::foundations::reexports_for_macros::once_cell::sync::Lazy<__oxy_Metrics> =
-                    ::foundations::reexports_for_macros::once_cell::sync::Lazy::new(|| {
-                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::get_main_subsystem(stringify!(oxy));
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
 
                         __oxy_Metrics {
                             connections_errors_total: {
@@ -611,16 +953,113 @@ mod tests {
                     message: &'static str,
                     error: impl Into<String>,
                 ) -> Counter {
-                    ::std::clone::Clone::clone(
-                        &::foundations::reexports_for_macros::prometools::serde::Family::get_or_create(
-                            &__oxy_Metrics.connections_errors_total,
-                            &connections_errors_total {
-                                endpoint: ::std::clone::Clone::clone(endpoint),
-                                kind,
-                                message,
-                                error: ::std::convert::Into::into(error),
+                    let label_set = connections_errors_total {
+                        endpoint: ::std::clone::Clone::clone(endpoint),
+                        kind,
+                        message,
+                        error: ::std::convert::Into::into(error),
+                    };
+
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| <Counter as ::std::clone::Clone>::clone(
+                            &::foundations::telemetry::metrics::LimitedFamily::get_or_create(&metrics.connections_errors_total, &label_set)
+                        ),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_optional_labels() {
+        let attr = parse_attr! {
+            #[metrics]
+        };
+
+        let src = parse_quote! {
+            pub mod oxy {
+                /// Total number of requests
+                pub fn requests_total(
+                    colo: Option<&'static str>,
+                    #[default = "unknown"]
+                    method: Option<String>,
+                ) -> Counter;
+            }
+        };
+
+        let actual = expand_from_parsed(attr, src).to_string();
+
+        let expected = code_str! {
+            pub mod oxy {
+                use super::*;
+
+                #[allow(non_camel_case_types)]
+                struct __oxy_Metrics {
+                    requests_total:
+                        ::foundations::telemetry::metrics::LimitedFamily<
+                            requests_total,
+                            Counter,
+                        >,
+                }
+
+                #[allow(non_camel_case_types)]
+                #[derive(
+                    ::std::clone::Clone,
+                    ::std::cmp::Eq,
+                    ::std::hash::Hash,
+                    ::std::cmp::PartialEq,
+                    ::foundations::reexports_for_macros::serde::Serialize,
+                )]
+                #[serde(crate = ":: foundations :: reexports_for_macros :: serde")]
+                struct requests_total {
+                    #[serde(skip_serializing_if = "::std::option::Option::is_none")]
+                    colo: Option<&'static str>,
+                    method: String,
+                }
+
+                #[allow(non_upper_case_globals)]
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
+
+                        __oxy_Metrics {
+                            requests_total: {
+                                let metric = ::std::default::Default::default();
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
+                                    registry,
+                                    ::std::stringify!(requests_total),
+                                    str::trim(" Total number of requests"),
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
                             },
-                        )
+                        }
+                    });
+
+                #[doc = " Total number of requests"]
+                #[must_use]
+                pub fn requests_total(
+                    colo: Option<&'static str>,
+                    method: Option<String>,
+                ) -> Counter {
+                    let label_set = requests_total {
+                        colo,
+                        method: ::std::option::Option::unwrap_or_else(
+                            method,
+                            || ::std::convert::Into::into("unknown"),
+                        ),
+                    };
+
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| <Counter as ::std::clone::Clone>::clone(
+                            &::foundations::telemetry::metrics::LimitedFamily::get_or_create(&metrics.requests_total, &label_set)
+                        ),
                     )
                 }
             }
@@ -657,7 +1096,7 @@ mod tests {
                 struct __oxy_Metrics {
                     connections_latency: Histogram,
                     requests_per_connection:
-                        ::foundations::reexports_for_macros::prometools::serde::Family<
+                        ::foundations::telemetry::metrics::LimitedFamily<
                             requests_per_connection,
                             Histogram,
                             HistogramBuilder,
@@ -678,14 +1117,17 @@ mod tests {
                 }
 
                 #[allow(non_upper_case_globals)]
-                static __oxy_Metrics: ::foundations::reexports_for_macros::once_cell::sync::Lazy<__oxy_Metrics> =
-                    ::foundations::reexports_for_macros::once_cell::sync::Lazy::new(|| {
-                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::get_main_subsystem(stringify!(oxy));
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
 
                         __oxy_Metrics {
                             connections_latency: {
                                 let metric = ::foundations::reexports_for_macros::prometheus_client::metrics::family::MetricConstructor::new_metric(
-                                    &(HistogramBuilder { buckets: &[0.5, 1.] })
+                                    &(::foundations::telemetry::metrics::internal::histogram_builder(
+                                        ::std::concat!(::std::stringify!(oxy), "_", ::std::stringify!(connections_latency)),
+                                        HistogramBuilder { buckets: &[0.5, 1.] }
+                                    ))
                                 );
 
                                 ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
@@ -698,8 +1140,11 @@ mod tests {
                                 metric
                             },
                             requests_per_connection: {
-                                let metric = ::foundations::reexports_for_macros::prometools::serde::Family::new_with_constructor(
-                                    HistogramBuilder { buckets: &[2., 3.] }
+                                let metric = ::foundations::telemetry::metrics::LimitedFamily::new_with_constructor(
+                                    ::foundations::telemetry::metrics::internal::histogram_builder(
+                                        ::std::concat!(::std::stringify!(oxy), "_", ::std::stringify!(requests_per_connection)),
+                                        HistogramBuilder { buckets: &[2., 3.] }
+                                    )
                                 );
 
                                 ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
@@ -717,7 +1162,10 @@ mod tests {
                 #[doc = " Latency of connections"]
                 #[must_use]
                 pub fn connections_latency() -> Histogram {
-                    ::std::clone::Clone::clone(&__oxy_Metrics.connections_latency)
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.connections_latency),
+                    )
                 }
 
                 #[doc = " Number of requests per connection"]
@@ -725,13 +1173,241 @@ mod tests {
                 pub fn requests_per_connection(
                     endpoint: String,
                 ) -> Histogram {
-                    ::std::clone::Clone::clone(
-                        &::foundations::reexports_for_macros::prometools::serde::Family::get_or_create(
-                            &__oxy_Metrics.requests_per_connection,
-                            &requests_per_connection {
-                                endpoint,
+                    let label_set = requests_per_connection {
+                        endpoint,
+                    };
+
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| <Histogram as ::std::clone::Clone>::clone(
+                            &::foundations::telemetry::metrics::LimitedFamily::get_or_create(&metrics.requests_per_connection, &label_set)
+                        ),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_ttl() {
+        let attr = parse_attr! {
+            #[metrics]
+        };
+
+        let src = parse_quote! {
+            pub mod oxy {
+                /// Number of requests per tenant
+                #[removable]
+                #[ttl = Duration::from_secs(3600)]
+                pub fn requests_per_tenant_total(tenant: &'static str) -> Counter;
+            }
+        };
+
+        let actual = expand_from_parsed(attr, src).to_string();
+
+        let expected = code_str! {
+            pub mod oxy {
+                use super::*;
+
+                #[allow(non_camel_case_types)]
+                struct __oxy_Metrics {
+                    requests_per_tenant_total:
+                        ::foundations::telemetry::metrics::ExpiringFamily<
+                            requests_per_tenant_total,
+                            Counter,
+                        >,
+                }
+
+                #[allow(non_camel_case_types)]
+                #[derive(
+                    ::std::clone::Clone,
+                    ::std::cmp::Eq,
+                    ::std::hash::Hash,
+                    ::std::cmp::PartialEq,
+                    ::foundations::reexports_for_macros::serde::Serialize,
+                )]
+                #[serde(crate = ":: foundations :: reexports_for_macros :: serde")]
+                struct requests_per_tenant_total {
+                    tenant: &'static str,
+                }
+
+                #[allow(non_upper_case_globals)]
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
+
+                        __oxy_Metrics {
+                            requests_per_tenant_total: {
+                                let metric = ::foundations::telemetry::metrics::ExpiringFamily::new(
+                                    Duration::from_secs(3600)
+                                );
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register(
+                                    registry,
+                                    ::std::stringify!(requests_per_tenant_total),
+                                    str::trim(" Number of requests per tenant"),
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
                             },
-                        )
+                        }
+                    });
+
+                #[doc = " Number of requests per tenant"]
+                #[must_use]
+                pub fn requests_per_tenant_total(tenant: &'static str,) -> Counter {
+                    let label_set = requests_per_tenant_total {
+                        tenant,
+                    };
+
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| <Counter as ::std::clone::Clone>::clone(
+                            &::foundations::telemetry::metrics::ExpiringFamily::get_or_create(&metrics.requests_per_tenant_total, &label_set)
+                        ),
+                    )
+                }
+
+                #[doc = " Removes the label set from the [`requests_per_tenant_total`] metric, returning whether it was present."]
+                #[allow(dead_code)]
+                pub fn remove_requests_per_tenant_total(tenant: &'static str,) -> bool {
+                    let label_set = requests_per_tenant_total {
+                        tenant,
+                    };
+
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::foundations::telemetry::metrics::ExpiringFamily::remove(&metrics.requests_per_tenant_total, &label_set),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_unit_typed() {
+        let attr = parse_attr! {
+            #[metrics]
+        };
+
+        let src = parse_quote! {
+            pub mod oxy {
+                /// Bytes received
+                pub fn received_bytes() -> ByteCounter;
+            }
+        };
+
+        let actual = expand_from_parsed(attr, src).to_string();
+
+        let expected = code_str! {
+            pub mod oxy {
+                use super::*;
+
+                #[allow(non_camel_case_types)]
+                struct __oxy_Metrics {
+                    received_bytes: ByteCounter,
+                }
+
+                #[allow(non_upper_case_globals)]
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
+
+                        __oxy_Metrics {
+                            received_bytes: {
+                                let metric = ::std::default::Default::default();
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register_with_unit(
+                                    registry,
+                                    "received",
+                                    str::trim(" Bytes received"),
+                                    ::foundations::reexports_for_macros::prometheus_client::registry::Unit::Bytes,
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
+                            },
+                        }
+                    });
+
+                #[doc = " Bytes received"]
+                #[must_use]
+                pub fn received_bytes() -> ByteCounter {
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.received_bytes),
+                    )
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_unit_attr_and_optional_flag() {
+        let attr = parse_attr! {
+            #[metrics]
+        };
+
+        let src = parse_quote! {
+            pub mod oxy {
+                /// Uptime
+                #[unit = "seconds"]
+                #[optional = "uptime"]
+                pub fn uptime() -> Gauge;
+            }
+        };
+
+        let actual = expand_from_parsed(attr, src).to_string();
+
+        let expected = code_str! {
+            pub mod oxy {
+                use super::*;
+
+                #[allow(non_camel_case_types)]
+                struct __oxy_Metrics {
+                    uptime: Gauge,
+                }
+
+                #[allow(non_upper_case_globals)]
+                static __oxy_Metrics: ::foundations::telemetry::metrics::internal::ModuleMetrics<__oxy_Metrics> =
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::new(|registries| {
+                        let registry = &mut *::foundations::telemetry::metrics::internal::Registries::main_subsystem(registries, stringify!(oxy));
+                        let opt_registry = &mut *::foundations::telemetry::metrics::internal::Registries::opt_subsystem(registries, stringify!(oxy));
+
+                        __oxy_Metrics {
+                            uptime: {
+                                let metric = ::std::default::Default::default();
+
+                                ::foundations::reexports_for_macros::prometheus_client::registry::Registry::register_with_unit(
+                                    if ::foundations::telemetry::metrics::internal::is_optional_flag_enabled("uptime") {
+                                        &mut *registry
+                                    } else {
+                                        &mut *opt_registry
+                                    },
+                                    "uptime",
+                                    str::trim(" Uptime"),
+                                    ::foundations::reexports_for_macros::prometheus_client::registry::Unit::Seconds,
+                                    ::std::boxed::Box::new(::std::clone::Clone::clone(&metric))
+                                );
+
+                                metric
+                            },
+                        }
+                    });
+
+                #[doc = " Uptime"]
+                #[must_use]
+                pub fn uptime() -> Gauge {
+                    ::foundations::telemetry::metrics::internal::ModuleMetrics::with(
+                        &__oxy_Metrics,
+                        |metrics| ::std::clone::Clone::clone(&metrics.uptime),
                     )
                 }
             }
@@ -1,25 +1,39 @@
+use super::units;
 use super::{ArgAttrs, ArgMode, FnArg, FnAttrs, ItemFn, MacroArgs, Mod};
 use crate::common::{error, parse_attr_value, parse_meta_list, Result};
 use darling::FromMeta;
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::{
-    braced, parenthesized, AngleBracketedGenericArguments, Attribute, GenericArgument, LitBool,
+    braced, parenthesized, AngleBracketedGenericArguments, Attribute, Expr, GenericArgument, Lit,
     LitStr, PathArguments, Token, TraitBound, TraitBoundModifier, Type, TypeImplTrait,
-    TypeParamBound,
+    TypeParamBound, TypePath,
 };
 
 const IMPL_TRAIT_ERROR: &str = "Only `impl Into<T>` is allowed";
 
-const FN_ATTR_ERROR: &str =
-    "Only `#[cfg]`, `#[doc]`, `#[ctor]` and `#[optional]` are allowed on functions";
+const FN_ATTR_ERROR: &str = "Only `#[cfg]`, `#[doc]`, `#[ctor]`, `#[optional]`, `#[removable]`, \
+    `#[ttl]` and `#[unit]` are allowed on functions";
 
 const DUPLICATE_CTOR_ATTR_ERROR: &str = "Duplicate `#[ctor]` attribute";
 const DUPLICATE_OPTIONAL_ATTR_ERROR: &str = "Duplicate `#[optional]` attribute";
+const DUPLICATE_REMOVABLE_ATTR_ERROR: &str = "Duplicate `#[removable]` attribute";
+const DUPLICATE_TTL_ATTR_ERROR: &str = "Duplicate `#[ttl]` attribute";
+const DUPLICATE_UNIT_ATTR_ERROR: &str = "Duplicate `#[unit]` attribute";
 const DUPLICATE_SERDE_ATTR_ERROR: &str = "Duplicate `#[serde]` attribute";
 const DUPLICATE_SERDE_AS_ATTR_ERROR: &str = "Duplicate `#[serde_as]` attribute";
+const DUPLICATE_DEFAULT_ATTR_ERROR: &str = "Duplicate `#[default]` attribute";
 
-const ARG_ATTR_ERROR: &str = "Only `#[serde]` and `#[serde_as]` are allowed on function arguments";
+const ARG_ATTR_ERROR: &str =
+    "Only `#[serde]`, `#[serde_as]` and `#[default]` are allowed on function arguments";
+
+const DEFAULT_ATTR_ERROR: &str = "`#[default]` is only allowed on `Option<T>` arguments";
+
+const REMOVABLE_ATTR_ERROR: &str = "`#[removable]` is only allowed on metrics with labels";
+const TTL_ATTR_ERROR: &str = "`#[ttl]` is only allowed on metrics with labels";
+
+const OPTIONAL_ATTR_ERROR: &str =
+    "`#[optional]` value should be either a boolean or a string with the name of the flag";
 
 impl Parse for MacroArgs {
     fn parse(input: ParseStream) -> Result<Self> {
@@ -58,11 +72,20 @@ impl Parse for Mod {
 
 impl Parse for ItemFn {
     fn parse(input: ParseStream) -> Result<Self> {
-        fn parse_attrs(attrs: Vec<Attribute>) -> Result<FnAttrs> {
+        /// Returns the attributes, the value of the `#[unit]` attribute, which is validated once
+        /// the metric name and type are parsed, and the `#[removable]` attribute, which is
+        /// validated once the labels are parsed.
+        fn parse_attrs(
+            attrs: Vec<Attribute>,
+        ) -> Result<(FnAttrs, Option<LitStr>, Option<Attribute>)> {
             let mut cfg = vec![];
             let mut doc = "".to_owned();
             let mut ctor = None;
             let mut optional = None;
+            let mut optional_flag = None;
+            let mut removable = None;
+            let mut ttl = None;
+            let mut unit = None;
 
             for attr in attrs {
                 if attr.path.is_ident("cfg") {
@@ -83,22 +106,53 @@ impl Parse for ItemFn {
                     if attr.tokens.is_empty() {
                         optional = Some(true);
                     } else {
-                        optional = Some(parse_attr_value::<LitBool>(attr)?.value);
+                        match parse_attr_value::<Lit>(attr)? {
+                            Lit::Bool(value) => optional = Some(value.value),
+                            Lit::Str(flag) => {
+                                optional = Some(true);
+                                optional_flag = Some(flag);
+                            }
+                            lit => return error(&lit, OPTIONAL_ATTR_ERROR),
+                        }
+                    }
+                } else if attr.path.is_ident("removable") {
+                    if removable.is_some() {
+                        return error(&attr, DUPLICATE_REMOVABLE_ATTR_ERROR);
+                    }
+
+                    removable = Some(attr);
+                } else if attr.path.is_ident("ttl") {
+                    if ttl.is_some() {
+                        return error(&attr, DUPLICATE_TTL_ATTR_ERROR);
+                    }
+
+                    ttl = Some(parse_attr_value(attr)?);
+                } else if attr.path.is_ident("unit") {
+                    if unit.is_some() {
+                        return error(&attr, DUPLICATE_UNIT_ATTR_ERROR);
                     }
+
+                    unit = Some(parse_attr_value::<LitStr>(attr)?);
                 } else {
                     return error(&attr, FN_ATTR_ERROR);
                 }
             }
 
-            Ok(FnAttrs {
+            let attrs = FnAttrs {
                 cfg,
                 doc,
                 ctor,
                 optional: optional.unwrap_or(false),
-            })
+                optional_flag,
+                removable: removable.is_some(),
+                ttl,
+                unit: None,
+            };
+
+            Ok((attrs, unit, removable))
         }
 
-        let attrs = parse_attrs(input.call(Attribute::parse_outer)?)?;
+        let (mut attrs, unit, removable) = parse_attrs(input.call(Attribute::parse_outer)?)?;
         let vis = input.parse()?;
         let fn_token = input.parse()?;
         let ident = input.parse()?;
@@ -116,10 +170,22 @@ impl Parse for ItemFn {
             args.push_punct(args_content.parse()?);
         }
 
+        if let (Some(removable), true) = (&removable, args.is_empty()) {
+            return error(removable, REMOVABLE_ATTR_ERROR);
+        }
+
+        if let (Some(ttl), true) = (&attrs.ttl, args.is_empty()) {
+            return error(ttl, TTL_ATTR_ERROR);
+        }
+
         let arrow_token = input.parse()?;
         let ty = input.parse()?;
         let _semi_token = input.parse::<Token![;]>()?;
 
+        if let Some(unit) = unit {
+            attrs.unit = Some(units::parse_unit_attr(&unit, &ident, &ty)?);
+        }
+
         Ok(ItemFn {
             attrs,
             vis,
@@ -150,6 +216,12 @@ impl Parse for FnArg {
                     }
 
                     attrs.serde_as = Some(attr);
+                } else if attr.path.is_ident("default") {
+                    if attrs.default.is_some() {
+                        return error(&attr, DUPLICATE_DEFAULT_ATTR_ERROR);
+                    }
+
+                    attrs.default = Some(parse_attr_value::<Expr>(attr)?);
                 } else {
                     return error(&attr, ARG_ATTR_ERROR);
                 }
@@ -160,10 +232,36 @@ impl Parse for FnArg {
 
         let attrs = parse_attrs(input.call(Attribute::parse_outer)?)?;
 
+        /// Returns `T` if the type is `Option<T>`.
+        fn as_option_inner(ty: &Type) -> Option<&Type> {
+            let Type::Path(TypePath { qself: None, path }) = ty else {
+                return None;
+            };
+
+            let segment = path.segments.last()?;
+
+            if segment.ident != "Option" {
+                return None;
+            }
+
+            let PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) =
+                &segment.arguments
+            else {
+                return None;
+            };
+
+            match args.iter().collect::<Vec<_>>()[..] {
+                [GenericArgument::Type(ty)] => Some(ty),
+                _ => None,
+            }
+        }
+
         /// If the type is a reference with an eluded lifetime, then the argument should be cloned.
         /// If the type is `impl Into<Foo>`, then the Into trait should be used.
+        /// If the type is `Option<Foo>`, then the label is omitted for `None`, or has the default
+        /// value if the argument has the `#[default]` attribute.
         /// Otherwise, use the value directly.
-        fn arg_mode(ty: &Type) -> Result<ArgMode> {
+        fn arg_mode(ty: &Type, default: Option<&Expr>) -> Result<ArgMode> {
             fn as_into_target(impl_: &TypeImplTrait) -> Result<&Type> {
                 if impl_.bounds.len() != 1 {
                     return error(&impl_, IMPL_TRAIT_ERROR);
@@ -208,7 +306,15 @@ impl Parse for FnArg {
                 }
             }
 
+            if let Some(default) = default {
+                return match as_option_inner(ty) {
+                    Some(inner) => Ok(ArgMode::Defaulted(inner.clone(), Box::new(default.clone()))),
+                    None => error(ty, DEFAULT_ATTR_ERROR),
+                };
+            }
+
             Ok(match ty {
+                ty if as_option_inner(ty).is_some() => ArgMode::Optional(ty.clone()),
                 Type::Reference(ref_) if ref_.lifetime.is_none() => {
                     ArgMode::Clone((*ref_.elem).clone())
                 }
@@ -220,7 +326,7 @@ impl Parse for FnArg {
         let ident = input.parse()?;
         let colon_token = input.parse()?;
         let ty = input.parse()?;
-        let mode = arg_mode(&ty)?;
+        let mode = arg_mode(&ty, attrs.default.as_ref())?;
 
         Ok(Self {
             attrs,
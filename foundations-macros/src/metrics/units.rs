@@ -0,0 +1,385 @@
+use super::ItemFn;
+use crate::common::{error, Result};
+use darling::FromMeta;
+use syn::{Ident, LitStr, Type};
+
+const SECONDS_SUFFIXES: &[&str] = &["_seconds", "_secs", "_sec"];
+const MILLISECONDS_SUFFIXES: &[&str] = &["_milliseconds", "_millis", "_ms"];
+
+const SECONDS_IN_MILLISECONDS_MOD_ERROR: &str =
+    "Metric name has a seconds unit suffix in a module with `duration_unit = \"milliseconds\"`";
+
+const MILLISECONDS_IN_SECONDS_MOD_ERROR: &str =
+    "Metric name has a milliseconds unit suffix in a module with `duration_unit = \"seconds\"`";
+
+const UNKNOWN_UNIT_ERROR: &str = "Unknown unit, should be one of `amperes`, `bytes`, `celsius`, \
+    `grams`, `joules`, `meters`, `ratios`, `seconds` or `volts`";
+
+const CONFLICTING_UNIT_ERROR: &str = "Unit contradicts the unit of the metric type";
+
+const UNIT_WITH_TOTAL_SUFFIX_ERROR: &str =
+    "Metric names with `#[unit]` can't have the `_total` suffix, as the unit suffix is appended \
+    to the name";
+
+const NAME_CONTRADICTS_UNIT_ERROR: &str =
+    "Metric name has a unit suffix that contradicts the unit of the metric: `TimeHistogram` and \
+    `DurationHistogram` report seconds, `ByteCounter` reports bytes, other metrics report the \
+    unit of their `#[unit]` attribute";
+
+const TIME_HISTOGRAM_IN_MILLISECONDS_MOD_ERROR: &str =
+    "`TimeHistogram` and `DurationHistogram` report durations in seconds and can't be used in \
+    a module with `duration_unit = \"milliseconds\"`";
+
+/// The unit that all the duration metrics of a module must be reported in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum DurationUnit {
+    Seconds,
+    Milliseconds,
+}
+
+impl FromMeta for DurationUnit {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "seconds" => Ok(Self::Seconds),
+            "milliseconds" => Ok(Self::Milliseconds),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
+}
+
+impl DurationUnit {
+    fn of_metric_name(name: &str) -> Option<Self> {
+        let name = name.strip_suffix("_total").unwrap_or(name);
+        let has_suffix = |suffixes: &[&str]| suffixes.iter().any(|s| name.ends_with(s));
+
+        if has_suffix(SECONDS_SUFFIXES) {
+            Some(Self::Seconds)
+        } else if has_suffix(MILLISECONDS_SUFFIXES) {
+            Some(Self::Milliseconds)
+        } else {
+            None
+        }
+    }
+}
+
+/// Checks that the metric names and types don't contradict the module's duration unit policy.
+pub(super) fn validate(unit: DurationUnit, fns: &[ItemFn]) -> Result<()> {
+    for fn_ in fns {
+        let metric_unit = match fn_.attrs.unit {
+            Some(MetricUnit::Seconds) => Some(DurationUnit::Seconds),
+            _ => DurationUnit::of_metric_name(&fn_.ident.to_string()),
+        };
+
+        match (unit, metric_unit) {
+            (DurationUnit::Seconds, Some(DurationUnit::Milliseconds)) => {
+                return error(&fn_.ident, MILLISECONDS_IN_SECONDS_MOD_ERROR);
+            }
+            (DurationUnit::Milliseconds, Some(DurationUnit::Seconds)) => {
+                return error(&fn_.ident, SECONDS_IN_MILLISECONDS_MOD_ERROR);
+            }
+            _ => (),
+        }
+
+        if unit == DurationUnit::Milliseconds && is_time_histogram(&fn_.ty) {
+            return error(&fn_.ty, TIME_HISTOGRAM_IN_MILLISECONDS_MOD_ERROR);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the unit suffixes of the metric names don't contradict the units the metrics are
+/// reported in, e.g. a `TimeHistogram` can't be named `request_duration_ms`.
+///
+/// Only applies to the modules that opt in to the unit policy with `duration_unit`.
+pub(super) fn validate_name_suffixes(fns: &[ItemFn]) -> Result<()> {
+    for fn_ in fns {
+        let reported_unit = match fn_.attrs.unit {
+            Some(unit) => unit,
+            None if is_time_histogram(&fn_.ty) => MetricUnit::Seconds,
+            None => match MetricUnit::of_metric_type(&fn_.ty) {
+                Some(unit) => unit,
+                None => continue,
+            },
+        };
+
+        let name_unit = name_unit(&fn_.ident.to_string());
+
+        if name_unit.is_some_and(|unit| unit != reported_unit.name()) {
+            return error(&fn_.ident, NAME_CONTRADICTS_UNIT_ERROR);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the unit of the metric name suffix, e.g. `milliseconds` for `request_duration_ms_total`.
+fn name_unit(name: &str) -> Option<&'static str> {
+    match DurationUnit::of_metric_name(name) {
+        Some(DurationUnit::Seconds) => Some(MetricUnit::Seconds.name()),
+        Some(DurationUnit::Milliseconds) => Some("milliseconds"),
+        None => {
+            let name = name.strip_suffix("_total").unwrap_or(name);
+
+            MetricUnit::ALL
+                .into_iter()
+                .map(MetricUnit::name)
+                .find(|unit| {
+                    name.strip_suffix(unit)
+                        .is_some_and(|name| name.ends_with('_'))
+                })
+        }
+    }
+}
+
+/// The unit a metric is registered with, either set with the `#[unit]` attribute or implied by
+/// the unit-typed metrics.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum MetricUnit {
+    Amperes,
+    Bytes,
+    Celsius,
+    Grams,
+    Joules,
+    Meters,
+    Ratios,
+    Seconds,
+    Volts,
+}
+
+impl MetricUnit {
+    const ALL: [Self; 9] = [
+        Self::Amperes,
+        Self::Bytes,
+        Self::Celsius,
+        Self::Grams,
+        Self::Joules,
+        Self::Meters,
+        Self::Ratios,
+        Self::Seconds,
+        Self::Volts,
+    ];
+
+    /// Returns the unit of the metric type, if it's one of the unit-typed metrics.
+    pub(super) fn of_metric_type(ty: &Type) -> Option<Self> {
+        match type_name(ty)?.as_str() {
+            "DurationHistogram" => Some(Self::Seconds),
+            "ByteCounter" => Some(Self::Bytes),
+            _ => None,
+        }
+    }
+
+    /// Name of the variant of `prometheus_client::registry::Unit`.
+    pub(super) fn variant(self) -> &'static str {
+        match self {
+            Self::Amperes => "Amperes",
+            Self::Bytes => "Bytes",
+            Self::Celsius => "Celsius",
+            Self::Grams => "Grams",
+            Self::Joules => "Joules",
+            Self::Meters => "Meters",
+            Self::Ratios => "Ratios",
+            Self::Seconds => "Seconds",
+            Self::Volts => "Volts",
+        }
+    }
+
+    /// Name of the unit in the metric names and the `# UNIT` metadata.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Amperes => "amperes",
+            Self::Bytes => "bytes",
+            Self::Celsius => "celsius",
+            Self::Grams => "grams",
+            Self::Joules => "joules",
+            Self::Meters => "meters",
+            Self::Ratios => "ratios",
+            Self::Seconds => "seconds",
+            Self::Volts => "volts",
+        }
+    }
+
+    /// Returns the metric name without the unit suffix, which is appended on encoding.
+    pub(super) fn strip_suffix(self, name: &str) -> &str {
+        name.strip_suffix(self.name())
+            .and_then(|name| name.strip_suffix('_'))
+            .unwrap_or(name)
+    }
+}
+
+/// Parses the value of the `#[unit]` attribute of the metric.
+pub(super) fn parse_unit_attr(lit: &LitStr, name: &Ident, ty: &Type) -> Result<MetricUnit> {
+    let value = lit.value();
+
+    let Some(unit) = MetricUnit::ALL
+        .into_iter()
+        .find(|unit| unit.name() == value)
+    else {
+        return error(lit, UNKNOWN_UNIT_ERROR);
+    };
+
+    if MetricUnit::of_metric_type(ty).is_some_and(|implied| implied != unit) {
+        return error(lit, CONFLICTING_UNIT_ERROR);
+    }
+
+    if name.to_string().ends_with("_total") {
+        return error(name, UNIT_WITH_TOTAL_SUFFIX_ERROR);
+    }
+
+    Ok(unit)
+}
+
+fn is_time_histogram(ty: &Type) -> bool {
+    type_name(ty).is_some_and(|name| name == "TimeHistogram" || name == "DurationHistogram")
+}
+
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn validate_fn(unit: DurationUnit, fn_: ItemFn) -> Result<()> {
+        validate(unit, &[fn_])
+    }
+
+    #[test]
+    fn metric_name_units() {
+        assert_eq!(
+            DurationUnit::of_metric_name("request_duration_seconds"),
+            Some(DurationUnit::Seconds)
+        );
+        assert_eq!(
+            DurationUnit::of_metric_name("request_duration_ms_total"),
+            Some(DurationUnit::Milliseconds)
+        );
+        assert_eq!(DurationUnit::of_metric_name("requests_total"), None);
+    }
+
+    #[test]
+    fn mixed_units_are_rejected() {
+        let ms_fn = parse_quote! { fn request_duration_ms() -> Histogram; };
+        let secs_fn = parse_quote! { fn request_duration_seconds() -> Histogram; };
+        let time_histogram_fn = parse_quote! { fn request_duration() -> TimeHistogram; };
+
+        assert!(validate_fn(DurationUnit::Seconds, ms_fn).is_err());
+        assert!(validate_fn(DurationUnit::Milliseconds, secs_fn).is_err());
+        assert!(validate_fn(DurationUnit::Milliseconds, time_histogram_fn).is_err());
+
+        let duration_histogram_fn = parse_quote! { fn request_duration() -> DurationHistogram; };
+
+        assert!(validate_fn(DurationUnit::Milliseconds, duration_histogram_fn).is_err());
+    }
+
+    #[test]
+    fn matching_units_are_accepted() {
+        let ms_fn = parse_quote! { fn request_duration_ms() -> Histogram; };
+        let time_histogram_fn = parse_quote! { fn request_duration() -> TimeHistogram; };
+
+        assert!(validate_fn(DurationUnit::Milliseconds, ms_fn).is_ok());
+        assert!(validate_fn(DurationUnit::Seconds, time_histogram_fn).is_ok());
+    }
+
+    #[test]
+    fn name_suffixes_contradicting_units_are_rejected() {
+        let validate_fn = |fn_: ItemFn| validate_name_suffixes(&[fn_]);
+
+        assert!(validate_fn(parse_quote! { fn latency_ms() -> TimeHistogram; }).is_err());
+        assert!(validate_fn(parse_quote! { fn latency_bytes() -> DurationHistogram; }).is_err());
+        assert!(validate_fn(parse_quote! { fn sent_seconds_total() -> ByteCounter; }).is_err());
+
+        let mut fn_: ItemFn = parse_quote! { fn queue_bytes() -> Gauge; };
+
+        assert!(validate_name_suffixes(std::slice::from_ref(&fn_)).is_ok());
+
+        fn_.attrs.unit = Some(MetricUnit::Seconds);
+
+        assert!(validate_fn(fn_).is_err());
+    }
+
+    #[test]
+    fn name_suffixes_matching_units_are_accepted() {
+        let validate_fn = |fn_: ItemFn| validate_name_suffixes(&[fn_]);
+
+        assert!(validate_fn(parse_quote! { fn latency_seconds() -> TimeHistogram; }).is_ok());
+        assert!(validate_fn(parse_quote! { fn latency_secs() -> DurationHistogram; }).is_ok());
+        assert!(validate_fn(parse_quote! { fn latency() -> DurationHistogram; }).is_ok());
+        assert!(validate_fn(parse_quote! { fn sent_bytes() -> ByteCounter; }).is_ok());
+        assert!(validate_fn(parse_quote! { fn received_kilobytes() -> ByteCounter; }).is_ok());
+        assert!(validate_fn(parse_quote! { fn latency_ms() -> Histogram; }).is_ok());
+    }
+
+    #[test]
+    fn unit_typed_metrics() {
+        let ty = parse_quote! { metrics::DurationHistogram };
+
+        assert_eq!(MetricUnit::of_metric_type(&ty), Some(MetricUnit::Seconds));
+        assert_eq!(
+            MetricUnit::of_metric_type(&parse_quote! { ByteCounter }),
+            Some(MetricUnit::Bytes)
+        );
+        assert_eq!(MetricUnit::of_metric_type(&parse_quote! { Counter }), None);
+
+        assert_eq!(
+            MetricUnit::Seconds.strip_suffix("latency_seconds"),
+            "latency"
+        );
+        assert_eq!(MetricUnit::Bytes.strip_suffix("received"), "received");
+        assert_eq!(MetricUnit::Bytes.strip_suffix("kilobytes"), "kilobytes");
+    }
+
+    #[test]
+    fn unit_attrs() {
+        let name = |name| Ident::new(name, proc_macro2::Span::call_site());
+        let lit = |unit| LitStr::new(unit, proc_macro2::Span::call_site());
+
+        assert_eq!(
+            parse_unit_attr(&lit("seconds"), &name("uptime"), &parse_quote! { Gauge }).unwrap(),
+            MetricUnit::Seconds
+        );
+        assert_eq!(
+            parse_unit_attr(
+                &lit("bytes"),
+                &name("received"),
+                &parse_quote! { ByteCounter }
+            )
+            .unwrap(),
+            MetricUnit::Bytes
+        );
+
+        assert!(parse_unit_attr(&lit("hours"), &name("uptime"), &parse_quote! { Gauge }).is_err());
+        assert!(parse_unit_attr(
+            &lit("bytes"),
+            &name("latency"),
+            &parse_quote! { DurationHistogram }
+        )
+        .is_err());
+        assert!(parse_unit_attr(
+            &lit("seconds"),
+            &name("busy_seconds_total"),
+            &parse_quote! { Counter }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn seconds_unit_attr_in_milliseconds_mod_is_rejected() {
+        let mut fn_: ItemFn = parse_quote! { fn uptime() -> Gauge; };
+
+        assert!(validate(DurationUnit::Milliseconds, std::slice::from_ref(&fn_)).is_ok());
+
+        fn_.attrs.unit = Some(MetricUnit::Seconds);
+
+        assert!(validate_fn(DurationUnit::Milliseconds, fn_).is_err());
+    }
+}
@@ -6,7 +6,7 @@ use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
 use syn::{
     parse_macro_input, parse_quote, Attribute, Field, Fields, Ident, Item, ItemEnum, ItemStruct,
-    Lit, LitStr, Meta, MetaNameValue, NestedMeta, Path,
+    Lit, LitStr, Meta, MetaNameValue, NestedMeta, Path, Type,
 };
 
 const ERR_NOT_STRUCT_OR_ENUM: &str = "Settings should be either structure or enum.";
@@ -18,6 +18,12 @@ const ERR_NON_UNIT_OR_NEW_TYPE_VARIANT: &str =
 const ERR_TUPLE_STRUCT: &str =
     "Settings with unnamed fields can only be new type structures (e.g. `struct Millimeters(u8)`).";
 
+const ERR_CONFLICTING_MERGE_STRATEGIES: &str =
+    "Only one of `merge` and `merge_by_key` can be specified for a settings field.";
+
+const ERR_UNKNOWN_MERGE_STRATEGY: &str =
+    "Unknown merge strategy, expected either `replace` or `append`.";
+
 #[derive(FromMeta)]
 struct Options {
     #[darling(default = "Options::default_impl_default")]
@@ -52,6 +58,47 @@ impl Default for Options {
     }
 }
 
+#[derive(Default, FromMeta)]
+struct FieldOptions {
+    merge: Option<LitStr>,
+    merge_by_key: Option<LitStr>,
+}
+
+impl FieldOptions {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let mut options = FieldOptions::default();
+
+        for attr in attrs.iter().filter(|a| a.path.is_ident("settings")) {
+            let Meta::List(list) = attr.parse_meta()? else {
+                continue;
+            };
+
+            let nested: Vec<_> = list.nested.into_iter().collect();
+            let parsed = Self::from_list(&nested)?;
+
+            options.merge = parsed.merge.or(options.merge);
+            options.merge_by_key = parsed.merge_by_key.or(options.merge_by_key);
+        }
+
+        Ok(options)
+    }
+
+    fn merge_strategy(&self, crate_path: &Path) -> Result<Option<proc_macro2::TokenStream>> {
+        let strategy = quote! { #crate_path::settings::MergeStrategy };
+
+        match (&self.merge, &self.merge_by_key) {
+            (Some(merge), Some(_)) => error(merge, ERR_CONFLICTING_MERGE_STRATEGIES),
+            (Some(merge), None) => match merge.value().as_str() {
+                "replace" => Ok(Some(quote! { #strategy::Replace })),
+                "append" => Ok(Some(quote! { #strategy::Append })),
+                _ => error(merge, ERR_UNKNOWN_MERGE_STRATEGY),
+            },
+            (None, Some(key)) => Ok(Some(quote! { #strategy::MergeByKey(#key) })),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
 impl Parse for Options {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let options = if input.is_empty() {
@@ -151,6 +198,11 @@ fn expand_struct(options: Options, item: &mut ItemStruct) -> Result<proc_macro2:
         quote!()
     };
 
+    // NOTE: remove the field options, as they are not a valid attribute on their own.
+    for field in item.fields.iter_mut() {
+        field.attrs.retain(|attr| !attr.path.is_ident("settings"));
+    }
+
     Ok(quote! {
         #item
 
@@ -185,12 +237,20 @@ fn impl_settings_trait(options: &Options, item: &ItemStruct) -> Result<proc_macr
     let crate_path = &options.crate_path;
     let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
     let mut doc_comments_impl = quote! {};
+    let mut merge_strategies_impl = quote! {};
+    let mut types_impl = quote! {};
 
     for field in &item.fields {
         if let Some(name) = &field.ident {
             let impl_for_field = impl_settings_trait_for_field(options, field, name);
 
             doc_comments_impl.append_all(impl_for_field);
+
+            let impl_for_field = impl_merge_strategies_for_field(options, field, name)?;
+
+            merge_strategies_impl.append_all(impl_for_field);
+
+            types_impl.append_all(impl_types_for_field(options, field, name));
         }
     }
 
@@ -203,6 +263,20 @@ fn impl_settings_trait(options: &Options, item: &ItemStruct) -> Result<proc_macr
             {
                 #doc_comments_impl
             }
+
+            fn add_merge_strategies(
+                parent_key: &[String],
+                strategies: &mut ::std::collections::HashMap<Vec<String>, #crate_path::settings::MergeStrategy>)
+            {
+                #merge_strategies_impl
+            }
+
+            fn add_types(
+                parent_key: &[String],
+                types: &mut ::std::collections::HashMap<Vec<String>, &'static str>)
+            {
+                #types_impl
+            }
         }
     })
 }
@@ -250,6 +324,109 @@ fn impl_settings_trait_for_field(
     impl_for_field
 }
 
+fn impl_merge_strategies_for_field(
+    options: &Options,
+    field: &Field,
+    name: &Ident,
+) -> Result<proc_macro2::TokenStream> {
+    let crate_path = &options.crate_path;
+    let span = field.ty.span();
+    let ty = &field.ty;
+    let name_str = name.to_string();
+    let strategy = FieldOptions::from_attrs(&field.attrs)?.merge_strategy(crate_path)?;
+
+    let cfg_attrs = field
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("cfg"))
+        .collect::<Vec<_>>();
+
+    let mut impl_for_field = quote_spanned! { span=>
+        let mut key = parent_key.to_vec();
+
+        key.push(#name_str.into());
+
+        <#ty as #crate_path::settings::Settings>::add_merge_strategies(&key, strategies);
+    };
+
+    if let Some(strategy) = strategy {
+        impl_for_field.append_all(quote! {
+            strategies.insert(key, #strategy);
+        });
+    }
+
+    if !cfg_attrs.is_empty() {
+        impl_for_field = quote! {
+            #(#cfg_attrs)*
+            {
+                #impl_for_field
+            }
+        }
+    }
+
+    Ok(impl_for_field)
+}
+
+fn impl_types_for_field(
+    options: &Options,
+    field: &Field,
+    name: &Ident,
+) -> proc_macro2::TokenStream {
+    let crate_path = &options.crate_path;
+    let span = field.ty.span();
+    let ty = &field.ty;
+    let name_str = name.to_string();
+    let type_str = type_name(ty);
+
+    let cfg_attrs = field
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("cfg"))
+        .collect::<Vec<_>>();
+
+    let mut impl_for_field = quote_spanned! { span=>
+        let mut key = parent_key.to_vec();
+
+        key.push(#name_str.into());
+
+        <#ty as #crate_path::settings::Settings>::add_types(&key, types);
+    };
+
+    impl_for_field.append_all(quote! {
+        types.insert(key, #type_str);
+    });
+
+    if !cfg_attrs.is_empty() {
+        impl_for_field = quote! {
+            #(#cfg_attrs)*
+            {
+                #impl_for_field
+            }
+        }
+    }
+
+    impl_for_field
+}
+
+/// Spells the type the way it's written in the code, e.g. `Vec<String>`.
+fn type_name(ty: &Type) -> String {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut name = String::new();
+
+    // NOTE: the string representation of the tokens separates all of them with spaces.
+    for token in quote!(#ty).to_string().split_whitespace() {
+        if name.ends_with(is_ident_char) && token.starts_with(is_ident_char)
+            || name.ends_with([',', ';'])
+        {
+            name.push(' ');
+        }
+
+        name.push_str(token);
+    }
+
+    name
+}
+
 fn extract_doc_comments(attrs: &[Attribute]) -> Vec<LitStr> {
     let mut comments = vec![];
 
@@ -384,6 +561,32 @@ mod tests {
                     ::foundations::settings::Settings::add_docs(&self.integer, &key, docs);
                     docs.insert(key, &[r" An integer value.",][..]);
                 }
+
+                fn add_merge_strategies(
+                    parent_key: &[String],
+                    strategies: &mut ::std::collections::HashMap<Vec<String>, ::foundations::settings::MergeStrategy>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                }
+
+                fn add_types(
+                    parent_key: &[String],
+                    types: &mut ::std::collections::HashMap<Vec<String>, &'static str>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "bool");
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "i32");
+                }
             }
 
             impl Default for TestStruct {
@@ -461,6 +664,46 @@ mod tests {
                         docs.insert(key, &[r" An integer value.",][..]);
                     }
                 }
+
+                fn add_merge_strategies(
+                    parent_key: &[String],
+                    strategies: &mut ::std::collections::HashMap<Vec<String>, ::foundations::settings::MergeStrategy>
+                ) {
+                    #[cfg(feature = "foobar")]
+                    {
+                        let mut key = parent_key.to_vec();
+                        key.push("boolean".into());
+                        <bool as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                    }
+                    #[cfg(test)]
+                    #[cfg(target_os = "linux")]
+                    {
+                        let mut key = parent_key.to_vec();
+                        key.push("integer".into());
+                        <i32 as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                    }
+                }
+
+                fn add_types(
+                    parent_key: &[String],
+                    types: &mut ::std::collections::HashMap<Vec<String>, &'static str>
+                ) {
+                    #[cfg(feature = "foobar")]
+                    {
+                        let mut key = parent_key.to_vec();
+                        key.push("boolean".into());
+                        <bool as ::foundations::settings::Settings>::add_types(&key, types);
+                        types.insert(key, "bool");
+                    }
+                    #[cfg(test)]
+                    #[cfg(target_os = "linux")]
+                    {
+                        let mut key = parent_key.to_vec();
+                        key.push("integer".into());
+                        <i32 as ::foundations::settings::Settings>::add_types(&key, types);
+                        types.insert(key, "i32");
+                    }
+                }
             }
 
             impl Default for TestStruct {
@@ -528,6 +771,32 @@ mod tests {
                     ::custom::path::settings::Settings::add_docs(&self.integer, &key, docs);
                     docs.insert(key, &[r" An integer value.",][..]);
                 }
+
+                fn add_merge_strategies(
+                    parent_key: &[String],
+                    strategies: &mut ::std::collections::HashMap<Vec<String>, ::custom::path::settings::MergeStrategy>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::custom::path::settings::Settings>::add_merge_strategies(&key, strategies);
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::custom::path::settings::Settings>::add_merge_strategies(&key, strategies);
+                }
+
+                fn add_types(
+                    parent_key: &[String],
+                    types: &mut ::std::collections::HashMap<Vec<String>, &'static str>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::custom::path::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "bool");
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::custom::path::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "i32");
+                }
             }
 
             impl Default for TestStruct {
@@ -592,6 +861,32 @@ mod tests {
                     ::foundations::settings::Settings::add_docs(&self.integer, &key, docs);
                     docs.insert(key, &[r" An integer value.",][..]);
                 }
+
+                fn add_merge_strategies(
+                    parent_key: &[String],
+                    strategies: &mut ::std::collections::HashMap<Vec<String>, ::foundations::settings::MergeStrategy>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                }
+
+                fn add_types(
+                    parent_key: &[String],
+                    types: &mut ::std::collections::HashMap<Vec<String>, &'static str>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "bool");
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "i32");
+                }
             }
         };
 
@@ -871,6 +1166,32 @@ mod tests {
                     key.push("integer".into());
                     ::foundations::settings::Settings::add_docs(&self.integer, &key, docs);
                 }
+
+                fn add_merge_strategies(
+                    parent_key: &[String],
+                    strategies: &mut ::std::collections::HashMap<Vec<String>, ::foundations::settings::MergeStrategy>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                }
+
+                fn add_types(
+                    parent_key: &[String],
+                    types: &mut ::std::collections::HashMap<Vec<String>, &'static str>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("boolean".into());
+                    <bool as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "bool");
+                    let mut key = parent_key.to_vec();
+                    key.push("integer".into());
+                    <i32 as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "i32");
+                }
             }
 
             impl Default for TestStruct {
@@ -885,4 +1206,108 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn expand_structure_with_merge_strategies() {
+        let options = parse_attr! {
+            #[settings]
+        };
+
+        let src = parse_quote! {
+            struct TestStruct {
+                #[settings(merge_by_key = "name")]
+                upstreams: Vec<Upstream>,
+            }
+        };
+
+        let actual = expand_from_parsed(options, src).unwrap().to_string();
+
+        let expected = code_str! {
+            #[derive(
+                Clone,
+                ::foundations::reexports_for_macros::serde::Serialize,
+                ::foundations::reexports_for_macros::serde::Deserialize,
+            )]
+            #[derive(Debug)]
+            #[serde(crate = ":: foundations :: reexports_for_macros :: serde")]
+            #[serde(default)]
+            struct TestStruct {
+                upstreams: Vec<Upstream>,
+            }
+
+            impl ::foundations::settings::Settings for TestStruct {
+                fn add_docs(
+                    &self,
+                    parent_key: &[String],
+                    docs: &mut ::std::collections::HashMap<Vec<String>, &'static [&'static str]>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("upstreams".into());
+                    ::foundations::settings::Settings::add_docs(&self.upstreams, &key, docs);
+                }
+
+                fn add_merge_strategies(
+                    parent_key: &[String],
+                    strategies: &mut ::std::collections::HashMap<Vec<String>, ::foundations::settings::MergeStrategy>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("upstreams".into());
+                    <Vec<Upstream> as ::foundations::settings::Settings>::add_merge_strategies(&key, strategies);
+                    strategies.insert(key, ::foundations::settings::MergeStrategy::MergeByKey("name"));
+                }
+
+                fn add_types(
+                    parent_key: &[String],
+                    types: &mut ::std::collections::HashMap<Vec<String>, &'static str>
+                ) {
+                    let mut key = parent_key.to_vec();
+                    key.push("upstreams".into());
+                    <Vec<Upstream> as ::foundations::settings::Settings>::add_types(&key, types);
+                    types.insert(key, "Vec<Upstream>");
+                }
+            }
+
+            impl Default for TestStruct {
+                fn default() -> Self {
+                    Self {
+                        upstreams: Default::default(),
+                    }
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn expand_structure_with_conflicting_merge_strategies() {
+        let options = parse_attr! {
+            #[settings]
+        };
+
+        let src = parse_quote! {
+            struct TestStruct {
+                #[settings(merge = "append", merge_by_key = "name")]
+                upstreams: Vec<Upstream>,
+            }
+        };
+
+        let err = expand_from_parsed(options, src).unwrap_err().to_string();
+
+        assert_eq!(err, ERR_CONFLICTING_MERGE_STRATEGIES);
+    }
+
+    #[test]
+    fn type_names() {
+        let name = |ty: Type| type_name(&ty);
+
+        assert_eq!(name(parse_quote!(Vec<String>)), "Vec<String>");
+        assert_eq!(
+            name(parse_quote!(HashMap<String, Option<u32> >)),
+            "HashMap<String, Option<u32>>"
+        );
+        assert_eq!(name(parse_quote!([u8; 4])), "[u8; 4]");
+        assert_eq!(name(parse_quote!(&'static str)), "&'static str");
+        assert_eq!(name(parse_quote!(net::SocketAddr)), "net::SocketAddr");
+    }
 }
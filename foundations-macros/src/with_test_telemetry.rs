@@ -98,8 +98,10 @@ fn expand_from_parsed(args: Args, item_fn: ItemFn) -> TokenStream2 {
             __ctx.clone().apply(async move { #inner_fn_ident(__ctx).await; }).await;
         ),
         None => quote!(
-            let __scope = __ctx.scope();
-            #inner_fn_ident(__ctx);
+            {
+                let __scope = __ctx.scope();
+                #inner_fn_ident(__ctx);
+            }
         ),
     };
 
@@ -111,9 +113,12 @@ fn expand_from_parsed(args: Args, item_fn: ItemFn) -> TokenStream2 {
         {
             #inner_fn
 
+            let __scope_leak_detector = #crate_path::telemetry::ScopeLeakDetector::new();
             let __ctx = #crate_path::telemetry::TelemetryContext::test();
 
             #wrapped_call
+
+            __scope_leak_detector.assert_no_leaks();
         }
     )
 }
@@ -144,9 +149,12 @@ mod tests {
                     assert!(false);
                 }
 
+                let __scope_leak_detector = ::foundations::telemetry::ScopeLeakDetector::new();
                 let __ctx = ::foundations::telemetry::TelemetryContext::test();
 
                 __ctx.clone().apply(async move { __some_test(__ctx).await; }).await;
+
+                __scope_leak_detector.assert_no_leaks();
             }
         };
 
@@ -174,10 +182,15 @@ mod tests {
                     assert!(false);
                 }
 
+                let __scope_leak_detector = ::foundations::telemetry::ScopeLeakDetector::new();
                 let __ctx = ::foundations::telemetry::TelemetryContext::test();
-                let __scope = __ctx.scope();
 
-                __some_test(__ctx);
+                {
+                    let __scope = __ctx.scope();
+                    __some_test(__ctx);
+                }
+
+                __scope_leak_detector.assert_no_leaks();
             }
         };
 
@@ -205,10 +218,15 @@ mod tests {
                     assert!(false);
                 }
 
+                let __scope_leak_detector = ::foo::bar::telemetry::ScopeLeakDetector::new();
                 let __ctx = ::foo::bar::telemetry::TelemetryContext::test();
-                let __scope = __ctx.scope();
 
-                __some_test(__ctx);
+                {
+                    let __scope = __ctx.scope();
+                    __some_test(__ctx);
+                }
+
+                __scope_leak_detector.assert_no_leaks();
             }
         };
 
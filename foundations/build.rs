@@ -1,8 +1,11 @@
 use std::env;
 use std::path::PathBuf;
+use std::process::Command;
 
 fn main() {
     ensure_seccomp_sources_fetched();
+    emit_rustc_version();
+    emit_features();
 
     #[cfg(feature = "security")]
     security::build()
@@ -21,6 +24,41 @@ fn ensure_seccomp_sources_fetched() {
     }
 }
 
+// NOTE: the version of the compiler is reported in the build info metric.
+fn emit_rustc_version() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|version| Some(version.split_whitespace().nth(1)?.to_string()))
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=FOUNDATIONS_RUSTC_VERSION={version}");
+}
+
+// NOTE: the enabled features are reported in the startup report and the diagnostics bundle.
+fn emit_features() {
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(var, _)| {
+            let feature = var.strip_prefix("CARGO_FEATURE_")?;
+
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        // NOTE: the feature sets are not reported, only the features they enable.
+        .filter(|feature| feature != "default" && !feature.ends_with("-default"))
+        .collect();
+
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=FOUNDATIONS_FEATURES={}",
+        features.join(",")
+    );
+}
+
 #[cfg(feature = "security")]
 mod security {
     use super::*;
@@ -161,17 +199,23 @@ mod security {
                     .display()
                     .to_string(),
             )
+            // NOTE: required for `unshare` and `CLONE_*` flags to be declared.
+            .clang_arg("-D_GNU_SOURCE")
             .allowlist_function("seccomp_rule_add_exact_array")
             .allowlist_function("seccomp_init")
             .allowlist_function("seccomp_load")
             .allowlist_function("SCMP_ACT_ERRNO")
             .allowlist_function("prctl")
+            .allowlist_function("unshare")
+            .allowlist_function("mount")
             .allowlist_type("scmp_arg_cmp")
             .allowlist_var("SCMP_ACT_LOG")
             .allowlist_var("SCMP_ACT_KILL_PROCESS")
             .allowlist_var("SCMP_ACT_ALLOW")
+            .allowlist_var("SCMP_ACT_TRAP")
             .allowlist_var("PR_SET_TSC")
             .allowlist_var("PR_TSC_SIGSEGV")
+            .allowlist_var("CLONE_NEWNS")
             .derive_default(true)
             .parse_callbacks(Box::new(CargoCallbacks))
             .generate()
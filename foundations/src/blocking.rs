@@ -0,0 +1,270 @@
+//! Instrumented and bounded use of the Tokio blocking thread pool.
+//!
+//! Tasks spawned with [`tokio::task::spawn_blocking`] are queued without limit once all
+//! the threads of the blocking pool are busy, so a burst of slow blocking calls, e.g. file IO on
+//! a degraded disk, silently delays all the other users of the pool, including `tokio::fs` and
+//! DNS resolution. [`BlockingPool`] caps the number of the concurrently running tasks spawned with
+//! it, so the rest of the pool remains available, and makes the waiting visible.
+//!
+//! With the `metrics` feature, the following metrics labeled with the pool name are reported:
+//!
+//! - `<prefix>_foundations_blocking_queue_depth` range gauge with the number of tasks waiting
+//!   to start;
+//! - `<prefix>_foundations_blocking_running_tasks` gauge with the number of running tasks;
+//! - `<prefix>_foundations_blocking_wait_time` histogram with the time the tasks waited before
+//!   starting, additionally labeled with the `call_site` of [`BlockingPool::spawn`] in
+//!   the `<file>:<line>` form.
+//!
+//! # Examples
+//! ```
+//! use foundations::blocking::{BlockingPool, BlockingPoolSettings};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let pool = BlockingPool::new("disk_io", &BlockingPoolSettings::default());
+//!
+//! let len = pool
+//!     .spawn(|| std::fs::read("/etc/hostname").map(|data| data.len()).unwrap_or_default())
+//!     .await
+//!     .unwrap();
+//! # let _ = len;
+//! # }
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{DurationHistogram, Gauge, HistogramBuilder, RangeGauge};
+
+#[cfg(feature = "metrics")]
+use std::panic::Location;
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_blocking {
+    /// Number of the tasks waiting to start.
+    pub fn queue_depth(pool: &'static str) -> RangeGauge;
+
+    /// Number of the running tasks.
+    pub fn running_tasks(pool: &'static str) -> Gauge;
+
+    /// Time the tasks waited before starting, by the call site that spawned them.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0],
+    }]
+    pub fn wait_time(pool: &'static str, call_site: CallSite) -> DurationHistogram;
+}
+
+/// Settings of a [`BlockingPool`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct BlockingPoolSettings {
+    /// Maximum number of the concurrently running tasks of the pool, the other tasks wait for
+    /// the running ones to finish.
+    ///
+    /// Should be lower than the maximum number of blocking threads of the Tokio runtime (512 by
+    /// default), so the tasks of the pool can't exhaust the blocking threads.
+    pub max_concurrency: usize,
+}
+
+impl Default for BlockingPoolSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 64,
+        }
+    }
+}
+
+/// A bounded and instrumented group of the tasks running on the Tokio blocking thread pool.
+///
+/// The pool is a cheaply cloneable handle, all the clones share the concurrency limit. See
+/// the [module-level documentation] for more details.
+///
+/// [module-level documentation]: crate::blocking
+#[derive(Clone, Debug)]
+pub struct BlockingPool {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    permits: Arc<Semaphore>,
+}
+
+impl BlockingPool {
+    /// Creates a new pool with the given settings.
+    ///
+    /// The `name` is used as a `pool` label of the metrics.
+    pub fn new(name: &'static str, settings: &BlockingPoolSettings) -> Self {
+        Self {
+            name,
+            permits: Arc::new(Semaphore::new(settings.max_concurrency.max(1))),
+        }
+    }
+
+    /// Runs the closure on the blocking thread pool once the running tasks of the pool are below
+    /// the concurrency limit, and returns its result.
+    ///
+    /// The closure is spawned lazily, so it never runs if the returned future is dropped while
+    /// waiting for the running tasks to finish. Once spawned, the closure runs to completion even
+    /// if the future is dropped, same as with [`tokio::task::spawn_blocking`].
+    ///
+    /// # Panics
+    /// If called outside of a Tokio runtime.
+    #[track_caller]
+    pub fn spawn<F, R>(&self, f: F) -> impl Future<Output = Result<R, JoinError>> + Send + 'static
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        #[cfg(feature = "metrics")]
+        let queued = Queued::new(self.name, Location::caller());
+
+        let permits = Arc::clone(&self.permits);
+
+        async move {
+            let permit = permits
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+
+                #[cfg(feature = "metrics")]
+                let _running = queued.start();
+
+                f()
+            })
+            .await
+        }
+    }
+
+    /// Returns the number of the tasks that can be started before reaching the concurrency limit.
+    pub fn available_permits(&self) -> usize {
+        self.permits.available_permits()
+    }
+}
+
+/// Tracks a task in the queue of the pool until it's started or dropped.
+#[cfg(feature = "metrics")]
+struct Queued {
+    pool: &'static str,
+    call_site: CallSite,
+    queued_at: Instant,
+    started: bool,
+}
+
+#[cfg(feature = "metrics")]
+impl Queued {
+    fn new(pool: &'static str, call_site: &'static Location<'static>) -> Self {
+        foundations_blocking::queue_depth(pool).inc();
+
+        Self {
+            pool,
+            call_site: CallSite(call_site),
+            queued_at: Instant::now(),
+            started: false,
+        }
+    }
+
+    fn start(mut self) -> Running {
+        self.started = true;
+
+        foundations_blocking::queue_depth(self.pool).dec();
+        foundations_blocking::wait_time(self.pool, self.call_site)
+            .observe(self.queued_at.elapsed());
+
+        Running::new(self.pool)
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for Queued {
+    fn drop(&mut self) {
+        if !self.started {
+            foundations_blocking::queue_depth(self.pool).dec();
+        }
+    }
+}
+
+/// Tracks a running task of the pool.
+#[cfg(feature = "metrics")]
+struct Running(&'static str);
+
+#[cfg(feature = "metrics")]
+impl Running {
+    fn new(pool: &'static str) -> Self {
+        foundations_blocking::running_tasks(pool).inc();
+
+        Self(pool)
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for Running {
+    fn drop(&mut self) {
+        foundations_blocking::running_tasks(self.0).dec();
+    }
+}
+
+/// Location of the [`BlockingPool::spawn`] call reported as a `<file>:<line>` label.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct CallSite(&'static Location<'static>);
+
+#[cfg(feature = "metrics")]
+impl serde::Serialize for CallSite {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}:{}", self.0.file(), self.0.line()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn limits_concurrency() {
+        let pool = BlockingPool::new(
+            "test_limits_concurrency",
+            &BlockingPoolSettings { max_concurrency: 2 },
+        );
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let running = Arc::clone(&running);
+                let max_running = Arc::clone(&max_running);
+
+                tokio::spawn(pool.spawn(move || {
+                    let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+
+                    max_running.fetch_max(current, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                }))
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.available_permits(), 2);
+    }
+}
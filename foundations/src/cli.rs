@@ -1,16 +1,25 @@
 //! Command line interface-related functionality.
 
-use super::settings::Settings;
+use super::settings::{
+    Settings, SettingsProvenance, SettingsReference, SettingsReferenceFormat, TrustBundle,
+};
 use super::{BootstrapResult, ServiceInfo};
-use anyhow::anyhow;
 use clap::error::ErrorKind;
 use clap::Command;
 use std::ffi::OsString;
+use std::path::Path;
 
 pub use clap::{Arg, ArgAction, ArgMatches};
 
 const GENERATE_CONFIG_OPT_ID: &str = "generate";
 const USE_CONFIG_OPT_ID: &str = "config";
+const PROFILE_OPT_ID: &str = "profile";
+const CHECK_CONFIG_OPT_ID: &str = "check-config";
+const TELEMETRY_SELFCHECK_OPT_ID: &str = "telemetry-selfcheck";
+const TRUST_BUNDLE_OPT_ID: &str = "trust-bundle";
+const OPENSSL_PATH_OPT_ID: &str = "openssl-path";
+const SOPS_PATH_OPT_ID: &str = "sops-path";
+const SETTINGS_REFERENCE_OPT_ID: &str = "settings-reference";
 
 /// A command line interface (CLI) helper that takes care of the command line arguments parsing
 /// basics.
@@ -23,18 +32,40 @@ const USE_CONFIG_OPT_ID: &str = "config";
 ///
 /// - `-c`, `--config` - specifies an existing configuration file for the service.
 /// - `-g`, `--generate` - generates a new default configuration file for the service.
+/// - `--profile` - selects the [settings profile] applied on top of the configuration.
+/// - `--trust-bundle` - specifies a file with the public keys the configuration file must be
+///   signed with, see [`from_signed_file`].
+/// - `--openssl-path` - specifies the absolute path of the `openssl` binary the signature of
+///   the configuration file is verified with, `/usr/bin/openssl` by default.
+/// - `--sops-path` - specifies the absolute path of the `sops` binary the configuration file is
+///   decrypted with if it's encrypted, `/usr/bin/sops` by default, see [`set_sops_path`].
+/// - `--check-config` - checks the configuration file, prints the [sources] of the settings values
+///   and exits.
+/// - `--settings-reference` - prints the [reference] of all the settings fields with their types,
+///   default values and documentation as a `markdown` or `csv` table and exits.
+/// - `--telemetry-selfcheck` - requests the service to run the telemetry self-check and exit,
+///   see [`Cli::telemetry_selfcheck`].
 /// - `-h`, `--help` - prints CLI help information and exits.
 /// - `-v`, `--version` - prints the service version and exits.
 ///
 /// Additional arguments can be added via `custom_args` argument of the [`Cli::new`] function.
 ///
 /// [`Settings`]: crate::settings::Settings
+/// [settings profile]: crate::settings#profiles
+/// [`from_signed_file`]: crate::settings::from_signed_file
+/// [`set_sops_path`]: crate::settings::set_sops_path
+/// [sources]: crate::settings::SettingsProvenance
+/// [reference]: crate::settings::SettingsReference
 pub struct Cli<S: Settings> {
     /// Parsed service settings.
     pub settings: S,
 
     /// Parsed service arguments.
     pub arg_matches: ArgMatches,
+
+    // NOTE: the decrypted contents of the configuration file, so the provenance of the settings
+    // is computed from the same contents the settings are parsed from.
+    settings_data: Option<String>,
 }
 
 impl<S: Settings> Cli<S> {
@@ -43,7 +74,9 @@ impl<S: Settings> Cli<S> {
     /// `custom_args` argument can be used to add extra service-specific arguments to the CLI.
     ///
     /// The function will implicitly print relevant information and exit the process if
-    /// `--help` or `--version` command line options are specified.
+    /// `--help`, `--version`, `--check-config` or `--settings-reference` command line options
+    /// are specified. With `--check-config` the process exits with a non-zero status code if
+    /// the configuration is invalid.
     ///
     /// Any command line parsing errors are intentionally propagated as a [`BootstrapResult`],
     /// so they can be reported to a panic handler (e.g. [Sentry]) if the service uses one.
@@ -70,7 +103,10 @@ impl<S: Settings> Cli<S> {
             .about(service_info.description)
             .arg(
                 Arg::new("config")
-                    .required_unless_present(GENERATE_CONFIG_OPT_ID)
+                    .required_unless_present_any([
+                        GENERATE_CONFIG_OPT_ID,
+                        SETTINGS_REFERENCE_OPT_ID,
+                    ])
                     .action(ArgAction::Set)
                     .long("config")
                     .short('c')
@@ -82,6 +118,59 @@ impl<S: Settings> Cli<S> {
                     .long("generate")
                     .short('g')
                     .help("Generates a new default config for the service"),
+            )
+            .arg(
+                Arg::new(PROFILE_OPT_ID)
+                    .action(ArgAction::Set)
+                    .long("profile")
+                    .conflicts_with(GENERATE_CONFIG_OPT_ID)
+                    .help("Specifies the settings profile applied on top of the config"),
+            )
+            .arg(
+                Arg::new(TRUST_BUNDLE_OPT_ID)
+                    .action(ArgAction::Set)
+                    .long("trust-bundle")
+                    .conflicts_with(GENERATE_CONFIG_OPT_ID)
+                    .help(
+                        "Verifies the signature of the config with the public keys of the bundle",
+                    ),
+            )
+            .arg(
+                Arg::new(OPENSSL_PATH_OPT_ID)
+                    .action(ArgAction::Set)
+                    .long("openssl-path")
+                    .requires(TRUST_BUNDLE_OPT_ID)
+                    .help("Specifies the absolute path of the openssl binary verifying the config"),
+            )
+            .arg(
+                Arg::new(SOPS_PATH_OPT_ID)
+                    .action(ArgAction::Set)
+                    .long("sops-path")
+                    .conflicts_with(GENERATE_CONFIG_OPT_ID)
+                    .help("Specifies the absolute path of the sops binary decrypting the config"),
+            )
+            .arg(
+                Arg::new(CHECK_CONFIG_OPT_ID)
+                    .action(ArgAction::SetTrue)
+                    .long("check-config")
+                    .conflicts_with(GENERATE_CONFIG_OPT_ID)
+                    .help("Checks the config, prints the sources of the settings values and exits"),
+            )
+            .arg(
+                Arg::new(SETTINGS_REFERENCE_OPT_ID)
+                    .action(ArgAction::Set)
+                    .long("settings-reference")
+                    .value_name("FORMAT")
+                    .value_parser(["markdown", "csv"])
+                    .conflicts_with_all([GENERATE_CONFIG_OPT_ID, USE_CONFIG_OPT_ID])
+                    .help("Prints the reference of the settings in the format and exits"),
+            )
+            .arg(
+                Arg::new(TELEMETRY_SELFCHECK_OPT_ID)
+                    .action(ArgAction::SetTrue)
+                    .long("telemetry-selfcheck")
+                    .conflicts_with(GENERATE_CONFIG_OPT_ID)
+                    .help("Checks the telemetry outputs of the service and exits"),
             );
 
         for arg in custom_args {
@@ -89,13 +178,74 @@ impl<S: Settings> Cli<S> {
         }
 
         let arg_matches = get_arg_matches(cmd, os_args)?;
-        let settings = get_settings(&arg_matches)?;
+
+        if let Some(format) = arg_matches.get_one::<String>(SETTINGS_REFERENCE_OPT_ID) {
+            let format = match format.as_str() {
+                "csv" => SettingsReferenceFormat::Csv,
+                _ => SettingsReferenceFormat::Markdown,
+            };
+
+            print!(
+                "{}",
+                SettingsReference::new(&S::default())?.to_table(format)
+            );
+
+            std::process::exit(0);
+        }
+
+        if arg_matches.get_flag(CHECK_CONFIG_OPT_ID) {
+            check_config::<S>(arg_matches);
+        }
+
+        let (settings, settings_data) = get_settings(&arg_matches)?;
 
         Ok(Self {
             settings,
             arg_matches,
+            settings_data,
         })
     }
+
+    /// Returns the path of the configuration file specified with `--config`.
+    ///
+    /// Returns `None` if the service runs with the default settings generated by `--generate`.
+    pub fn settings_path(&self) -> Option<&Path> {
+        self.arg_matches
+            .get_one::<String>(USE_CONFIG_OPT_ID)
+            .filter(|_| !self.arg_matches.contains_id(GENERATE_CONFIG_OPT_ID))
+            .map(Path::new)
+    }
+
+    /// Returns the settings profile specified with `--profile`, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.arg_matches
+            .get_one::<String>(PROFILE_OPT_ID)
+            .map(String::as_str)
+    }
+
+    /// Returns the sources of the values of the settings, see [`SettingsProvenance`].
+    ///
+    /// The provenance is computed from the contents of the configuration file the settings are
+    /// parsed from, decrypted if the file is encrypted with [sops]. All the values are reported
+    /// as the default ones if the service runs with the settings generated by `--generate`.
+    ///
+    /// [sops]: https://github.com/getsops/sops
+    pub fn settings_provenance(&self) -> BootstrapResult<SettingsProvenance> {
+        match (self.settings_path(), &self.settings_data) {
+            (Some(path), Some(data)) => {
+                SettingsProvenance::from_file_data(path, data, self.profile())
+            }
+            _ => Ok(SettingsProvenance::default()),
+        }
+    }
+
+    /// Returns `true` if `--telemetry-selfcheck` is specified.
+    ///
+    /// The service is expected to initialize telemetry, print the `telemetry::SelfCheckReport`
+    /// and exit with a non-zero status code if any of the checks fail.
+    pub fn telemetry_selfcheck(&self) -> bool {
+        self.arg_matches.get_flag(TELEMETRY_SELFCHECK_OPT_ID)
+    }
 }
 
 fn get_arg_matches(
@@ -116,17 +266,61 @@ fn get_arg_matches(
     })
 }
 
-fn get_settings<S: Settings>(arg_matches: &ArgMatches) -> BootstrapResult<S> {
+// NOTE: the settings are valid if they are parsed, so only the provenance is printed.
+fn check_config<S: Settings>(arg_matches: ArgMatches) -> ! {
+    let res = get_settings::<S>(&arg_matches).and_then(|(settings, settings_data)| {
+        let cli = Cli {
+            settings,
+            arg_matches,
+            settings_data,
+        };
+
+        cli.settings_provenance()
+    });
+
+    match res {
+        Ok(provenance) => {
+            println!("{provenance}");
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("invalid config: {e:#}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// NOTE: returns the settings along with the decrypted contents of the configuration file, if any.
+fn get_settings<S: Settings>(arg_matches: &ArgMatches) -> BootstrapResult<(S, Option<String>)> {
     if let Some(path) = arg_matches.get_one::<String>(GENERATE_CONFIG_OPT_ID) {
         let settings = S::default();
 
         crate::settings::to_yaml_file(&settings, path)?;
 
-        return Ok(settings);
+        return Ok((settings, None));
     }
 
     if let Some(path) = arg_matches.get_one::<String>(USE_CONFIG_OPT_ID) {
-        return crate::settings::from_file(path).map_err(|e| anyhow!(e));
+        let profile = arg_matches.get_one::<String>(PROFILE_OPT_ID);
+        let trust_bundle = arg_matches
+            .get_one::<String>(TRUST_BUNDLE_OPT_ID)
+            .map(|bundle| {
+                let bundle = TrustBundle::new(bundle);
+
+                match arg_matches.get_one::<String>(OPENSSL_PATH_OPT_ID) {
+                    Some(openssl_path) => bundle.with_openssl_path(openssl_path),
+                    None => bundle,
+                }
+            });
+
+        if let Some(sops_path) = arg_matches.get_one::<String>(SOPS_PATH_OPT_ID) {
+            crate::settings::set_sops_path(sops_path);
+        }
+
+        let (data, value) = crate::settings::read_file(Path::new(path), trust_bundle.as_ref())?;
+        let settings = crate::settings::parse_value(value, profile.map(String::as_str))?;
+
+        return Ok((settings, Some(data)));
     }
 
     unreachable!("clap should require config options to be present")
@@ -0,0 +1,555 @@
+//! Compression and decompression streams with metrics.
+//!
+//! The module provides a single set of codecs, so the telemetry server, the exporters and the
+//! service itself share the same implementations instead of each pulling its own. The algorithm
+//! and the level of the compression are configured with [`CompressionSettings`].
+//!
+//! [`Encoder`] and [`Decoder`] wrap a writer and a reader respectively, and [`compress`] and
+//! [`decompress`] process the data in memory. Each of them takes the name of its user, e.g.
+//! `telemetry_server`, reported in the `user` label of the metrics.
+//!
+//! Brotli (the `br` HTTP content coding) is intentionally not supported for now: the
+//! [`brotli`] crate is not yet vetted as a dependency of the library, and gzip and zstd cover
+//! the peers the services talk to. [`CompressionAlgorithm::from_content_coding`] returns
+//! `None` for `br`, and the telemetry server responds uncompressed to the clients that only
+//! accept brotli.
+//!
+//! [`brotli`]: https://crates.io/crates/brotli
+//!
+//! With the `metrics` feature, the following metrics labeled with the user, the algorithm and
+//! the operation (`compress` or `decompress`) are reported once a stream is finished:
+//!
+//! - `<prefix>_foundations_compression_uncompressed_bytes_total` counter with the number of the
+//!   uncompressed bytes;
+//! - `<prefix>_foundations_compression_compressed_bytes_total` counter with the number of the
+//!   compressed bytes;
+//! - `<prefix>_foundations_compression_seconds_total` counter with the time spent in the codec,
+//!   so the throughput is the number of the uncompressed bytes divided by the time;
+//! - `<prefix>_foundations_compression_ratio` histogram with the compression ratios of the
+//!   compressed streams, labeled only with the user and the algorithm.
+//!
+//! # Examples
+//! ```
+//! use foundations::compression::{self, CompressionAlgorithm, CompressionSettings};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let settings = CompressionSettings {
+//!     algorithm: CompressionAlgorithm::Zstd,
+//!     level: Some(9),
+//! };
+//!
+//! let data = "a highly compressible payload ".repeat(100);
+//! let compressed = compression::compress(data.as_bytes(), &settings, "example")?;
+//!
+//! assert!(compressed.len() < data.len());
+//!
+//! let decompressed = compression::decompress(&compressed, settings.algorithm, "example")?;
+//!
+//! assert_eq!(decompressed, data.as_bytes());
+//! # Ok(())
+//! # }
+//! ```
+
+use flate2::Compression;
+use std::io::{self, BufReader, Read, Write};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, Histogram, HistogramBuilder};
+
+#[cfg(feature = "metrics")]
+use std::sync::atomic::AtomicU64;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_compression {
+    /// Number of the uncompressed bytes of the finished streams.
+    pub fn uncompressed_bytes_total(
+        user: &'static str,
+        algorithm: &'static str,
+        operation: &'static str,
+    ) -> Counter;
+
+    /// Number of the compressed bytes of the finished streams.
+    pub fn compressed_bytes_total(
+        user: &'static str,
+        algorithm: &'static str,
+        operation: &'static str,
+    ) -> Counter;
+
+    /// Time spent compressing or decompressing the finished streams.
+    pub fn seconds_total(
+        user: &'static str,
+        algorithm: &'static str,
+        operation: &'static str,
+    ) -> Counter<f64, AtomicU64>;
+
+    /// Ratio of the uncompressed size to the compressed size of the compressed streams.
+    #[ctor = HistogramBuilder {
+        buckets: &[1.0, 1.5, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0],
+    }]
+    pub fn ratio(user: &'static str, algorithm: &'static str) -> Histogram;
+}
+
+/// Compression algorithm.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[derive(Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// Gzip, as specified in [RFC 1952].
+    ///
+    /// [RFC 1952]: https://www.rfc-editor.org/rfc/rfc1952
+    #[default]
+    Gzip,
+    /// Zlib-wrapped deflate, as specified in [RFC 1950], that is used by the `deflate` HTTP
+    /// content coding.
+    ///
+    /// [RFC 1950]: https://www.rfc-editor.org/rfc/rfc1950
+    Deflate,
+    /// Zstandard, as specified in [RFC 8878].
+    ///
+    /// [RFC 8878]: https://www.rfc-editor.org/rfc/rfc8878
+    Zstd,
+}
+
+impl CompressionAlgorithm {
+    /// All the supported algorithms.
+    pub const ALL: [Self; 3] = [Self::Gzip, Self::Deflate, Self::Zstd];
+
+    /// Returns the name of the algorithm as it appears in the settings, which is also its HTTP
+    /// content coding, e.g. in the `Content-Encoding` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Deflate => "deflate",
+            CompressionAlgorithm::Zstd => "zstd",
+        }
+    }
+
+    /// Returns the algorithm of the HTTP content coding, e.g. from the `Content-Encoding`
+    /// header, or `None` if the coding is not supported.
+    pub fn from_content_coding(coding: &str) -> Option<Self> {
+        let coding = coding.trim();
+
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.as_str().eq_ignore_ascii_case(coding))
+    }
+
+    /// Returns the level used if the level is not specified in the settings.
+    pub fn default_level(&self) -> i32 {
+        match self {
+            CompressionAlgorithm::Gzip | CompressionAlgorithm::Deflate => 6,
+            CompressionAlgorithm::Zstd => zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+/// Compression settings.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+pub struct CompressionSettings {
+    /// Compression algorithm.
+    pub algorithm: CompressionAlgorithm,
+
+    /// Compression level, from `0` (no compression) to `9` (smallest) for gzip and deflate, and
+    /// from `1` (fastest) to `22` (smallest) for zstd.
+    ///
+    /// The default level of the algorithm is used if not specified.
+    pub level: Option<i32>,
+}
+
+impl CompressionSettings {
+    /// Returns the compression level, validated for the algorithm.
+    fn level(&self) -> io::Result<i32> {
+        let level = self.level.unwrap_or_else(|| self.algorithm.default_level());
+
+        let valid = match self.algorithm {
+            CompressionAlgorithm::Gzip | CompressionAlgorithm::Deflate => (0..=9).contains(&level),
+            CompressionAlgorithm::Zstd => zstd::compression_level_range().contains(&level),
+        };
+
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "compression level {level} is not valid for {}",
+                    self.algorithm.as_str()
+                ),
+            ));
+        }
+
+        Ok(level)
+    }
+}
+
+/// Compresses the data in memory.
+pub fn compress(
+    data: &[u8],
+    settings: &CompressionSettings,
+    user: &'static str,
+) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(Vec::with_capacity(data.len() / 4), settings, user)?;
+
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Decompresses the data in memory.
+pub fn decompress(
+    data: &[u8],
+    algorithm: CompressionAlgorithm,
+    user: &'static str,
+) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::with_capacity(data.len() * 4);
+
+    Decoder::new(data, algorithm, user).read_to_end(&mut decompressed)?;
+
+    Ok(decompressed)
+}
+
+/// A writer that compresses the data written to it.
+///
+/// The encoder must be finished with [`Encoder::finish`] to write the end of the compressed
+/// stream. The metrics are reported when the encoder is finished.
+pub struct Encoder<W: Write> {
+    codec: EncoderCodec<W>,
+    stats: Stats,
+}
+
+enum EncoderCodec<W: Write> {
+    Gzip(flate2::write::GzEncoder<CountingWriter<W>>),
+    Deflate(flate2::write::ZlibEncoder<CountingWriter<W>>),
+    Zstd(zstd::stream::write::Encoder<'static, CountingWriter<W>>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Creates an encoder that writes the compressed data to the writer.
+    ///
+    /// Returns an error if the compression level is not valid for the algorithm.
+    pub fn new(writer: W, settings: &CompressionSettings, user: &'static str) -> io::Result<Self> {
+        let level = settings.level()?;
+        let writer = CountingWriter {
+            inner: writer,
+            written: 0,
+        };
+
+        // NOTE: the level is validated to be in the range of the algorithm.
+        let codec = match settings.algorithm {
+            CompressionAlgorithm::Gzip => EncoderCodec::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                Compression::new(level as u32),
+            )),
+            CompressionAlgorithm::Deflate => EncoderCodec::Deflate(
+                flate2::write::ZlibEncoder::new(writer, Compression::new(level as u32)),
+            ),
+            CompressionAlgorithm::Zstd => {
+                EncoderCodec::Zstd(zstd::stream::write::Encoder::new(writer, level)?)
+            }
+        };
+
+        Ok(Self {
+            codec,
+            stats: Stats::new(user, settings.algorithm, "compress"),
+        })
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer().inner
+    }
+
+    /// Returns the number of the compressed bytes written to the underlying writer so far.
+    ///
+    /// The codecs buffer the data internally, so the number lags behind the written data until
+    /// the encoder is flushed.
+    pub fn compressed_len(&self) -> u64 {
+        self.writer().written
+    }
+
+    /// Writes the end of the compressed stream and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let started_at = Instant::now();
+
+        let writer = match self.codec {
+            EncoderCodec::Gzip(encoder) => encoder.finish()?,
+            EncoderCodec::Deflate(encoder) => encoder.finish()?,
+            EncoderCodec::Zstd(encoder) => encoder.finish()?,
+        };
+
+        self.stats.elapsed += started_at.elapsed();
+        self.stats.compressed = writer.written;
+        self.stats.report();
+
+        Ok(writer.inner)
+    }
+
+    fn writer(&self) -> &CountingWriter<W> {
+        match &self.codec {
+            EncoderCodec::Gzip(encoder) => encoder.get_ref(),
+            EncoderCodec::Deflate(encoder) => encoder.get_ref(),
+            EncoderCodec::Zstd(encoder) => encoder.get_ref(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let started_at = Instant::now();
+
+        let written = match &mut self.codec {
+            EncoderCodec::Gzip(encoder) => encoder.write(buf),
+            EncoderCodec::Deflate(encoder) => encoder.write(buf),
+            EncoderCodec::Zstd(encoder) => encoder.write(buf),
+        }?;
+
+        self.stats.elapsed += started_at.elapsed();
+        self.stats.uncompressed += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let started_at = Instant::now();
+
+        let res = match &mut self.codec {
+            EncoderCodec::Gzip(encoder) => encoder.flush(),
+            EncoderCodec::Deflate(encoder) => encoder.flush(),
+            EncoderCodec::Zstd(encoder) => encoder.flush(),
+        };
+
+        self.stats.elapsed += started_at.elapsed();
+
+        res
+    }
+}
+
+/// A reader that decompresses the data read from the underlying reader.
+///
+/// The metrics are reported when the decoder is dropped.
+pub struct Decoder<R: Read> {
+    codec: DecoderCodec<R>,
+    stats: Stats,
+}
+
+enum DecoderCodec<R: Read> {
+    Gzip(flate2::read::GzDecoder<CountingReader<R>>),
+    Deflate(flate2::read::ZlibDecoder<CountingReader<R>>),
+    Zstd(zstd::stream::read::Decoder<'static, BufReader<CountingReader<R>>>),
+}
+
+impl<R: Read> Decoder<R> {
+    /// Creates a decoder that reads the compressed data from the reader.
+    pub fn new(reader: R, algorithm: CompressionAlgorithm, user: &'static str) -> Self {
+        let reader = CountingReader {
+            inner: reader,
+            read: 0,
+        };
+
+        let codec = match algorithm {
+            CompressionAlgorithm::Gzip => DecoderCodec::Gzip(flate2::read::GzDecoder::new(reader)),
+            CompressionAlgorithm::Deflate => {
+                DecoderCodec::Deflate(flate2::read::ZlibDecoder::new(reader))
+            }
+            CompressionAlgorithm::Zstd => DecoderCodec::Zstd(
+                zstd::stream::read::Decoder::with_buffer(BufReader::new(reader))
+                    .expect("zstd decompression context should be allocated"),
+            ),
+        };
+
+        Self {
+            codec,
+            stats: Stats::new(user, algorithm, "decompress"),
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader().inner
+    }
+
+    fn reader(&self) -> &CountingReader<R> {
+        match &self.codec {
+            DecoderCodec::Gzip(decoder) => decoder.get_ref(),
+            DecoderCodec::Deflate(decoder) => decoder.get_ref(),
+            DecoderCodec::Zstd(decoder) => decoder.get_ref().get_ref(),
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let started_at = Instant::now();
+
+        let read = match &mut self.codec {
+            DecoderCodec::Gzip(decoder) => decoder.read(buf),
+            DecoderCodec::Deflate(decoder) => decoder.read(buf),
+            DecoderCodec::Zstd(decoder) => decoder.read(buf),
+        }?;
+
+        self.stats.elapsed += started_at.elapsed();
+        self.stats.uncompressed += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl<R: Read> Drop for Decoder<R> {
+    fn drop(&mut self) {
+        self.stats.compressed = self.reader().read;
+        self.stats.report();
+    }
+}
+
+/// Statistics of a stream reported in the metrics.
+struct Stats {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    user: &'static str,
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    algorithm: CompressionAlgorithm,
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    operation: &'static str,
+    uncompressed: u64,
+    compressed: u64,
+    elapsed: Duration,
+}
+
+impl Stats {
+    fn new(user: &'static str, algorithm: CompressionAlgorithm, operation: &'static str) -> Self {
+        Self {
+            user,
+            algorithm,
+            operation,
+            uncompressed: 0,
+            compressed: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn report(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            let (user, algorithm, operation) = (self.user, self.algorithm.as_str(), self.operation);
+
+            foundations_compression::uncompressed_bytes_total(user, algorithm, operation)
+                .inc_by(self.uncompressed);
+            foundations_compression::compressed_bytes_total(user, algorithm, operation)
+                .inc_by(self.compressed);
+            foundations_compression::seconds_total(user, algorithm, operation)
+                .inc_by(self.elapsed.as_secs_f64());
+
+            if self.operation == "compress" && self.compressed > 0 {
+                foundations_compression::ratio(user, algorithm)
+                    .observe(self.uncompressed as f64 / self.compressed as f64);
+            }
+        }
+    }
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+
+        self.written += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        self.read += read as u64;
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_all_algorithms() {
+        let data = "compressible telemetry payload\n".repeat(1000);
+
+        for algorithm in CompressionAlgorithm::ALL {
+            for level in [None, Some(1)] {
+                let settings = CompressionSettings { algorithm, level };
+                let mut encoder = Encoder::new(vec![], &settings, "test").unwrap();
+
+                encoder.write_all(data.as_bytes()).unwrap();
+                encoder.flush().unwrap();
+
+                assert_eq!(encoder.compressed_len(), encoder.get_ref().len() as u64);
+
+                let compressed = encoder.finish().unwrap();
+
+                assert!(compressed.len() < data.len() / 10, "{}", algorithm.as_str());
+
+                let decompressed = decompress(&compressed, algorithm, "test").unwrap();
+
+                assert_eq!(decompressed, data.as_bytes(), "{}", algorithm.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn validates_levels() {
+        let settings = |algorithm, level| CompressionSettings {
+            algorithm,
+            level: Some(level),
+        };
+
+        assert!(compress(b"data", &settings(CompressionAlgorithm::Gzip, 10), "test").is_err());
+        assert!(compress(
+            b"data",
+            &settings(CompressionAlgorithm::Deflate, -1),
+            "test"
+        )
+        .is_err());
+        assert!(compress(b"data", &settings(CompressionAlgorithm::Zstd, 23), "test").is_err());
+        assert!(compress(b"data", &settings(CompressionAlgorithm::Zstd, 22), "test").is_ok());
+    }
+
+    #[test]
+    fn parses_content_codings() {
+        assert_eq!(
+            CompressionAlgorithm::from_content_coding(" GZIP"),
+            Some(CompressionAlgorithm::Gzip)
+        );
+        assert_eq!(
+            CompressionAlgorithm::from_content_coding("zstd"),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(CompressionAlgorithm::from_content_coding("br"), None);
+    }
+
+    #[test]
+    fn decoder_rejects_corrupted_data() {
+        for algorithm in CompressionAlgorithm::ALL {
+            let mut out = vec![];
+            let res = Decoder::new(&b"not compressed"[..], algorithm, "test").read_to_end(&mut out);
+
+            assert!(res.is_err(), "{}", algorithm.as_str());
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! CPU affinity of the worker threads.
+//!
+//! Latency-sensitive network services often benefit from pinning their worker threads to
+//! a dedicated set of CPUs, e.g. to the CPUs of the NUMA node the network card is attached to,
+//! to avoid cross-node memory accesses and interference with the other processes.
+//!
+//! [`CpuAffinity`] resolves the CPU set configured with [`CpuAffinitySettings`] at startup and
+//! pins the threads to it, usually from the thread start hook of the runtime. With the **logging**
+//! feature the applied topology is logged, and with the **metrics** feature it's exported as
+//! the `cpu_affinity_info` info metric.
+//!
+//! # Examples
+//! ```
+//! use foundations::cpu_affinity::{CpuAffinity, CpuAffinitySettings};
+//!
+//! # fn main() -> foundations::BootstrapResult<()> {
+//! let settings = CpuAffinitySettings {
+//!     enabled: true,
+//!     // Pin each worker thread to its own CPU out of the CPUs available to the process.
+//!     pin_each_thread_to_cpu: true,
+//!     ..Default::default()
+//! };
+//!
+//! let affinity = CpuAffinity::new(&settings)?;
+//!
+//! let runtime = tokio::runtime::Builder::new_multi_thread()
+//!     .on_thread_start(move || {
+//!         affinity
+//!             .pin_current_thread()
+//!             .expect("should pin worker thread");
+//!     })
+//!     .build()?;
+//! # drop(runtime);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::BootstrapResult;
+use anyhow::bail;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+/// Information about CPU affinity of the worker threads
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::info_metric(crate_path = "crate")]
+struct CpuAffinityInfo {
+    cpus: String,
+    numa_nodes: String,
+    pin_each_thread_to_cpu: bool,
+}
+
+/// CPU affinity settings.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+pub struct CpuAffinitySettings {
+    /// Enables pinning of the worker threads.
+    pub enabled: bool,
+
+    /// CPUs the worker threads are pinned to, in the Linux CPU list format, e.g. `0-3,8-11`.
+    pub cpus: Option<String>,
+
+    /// NUMA nodes whose CPUs the worker threads are pinned to, in addition to the ones specified
+    /// in `cpus`.
+    ///
+    /// The threads are pinned to all the CPUs available to the process if neither CPUs nor NUMA
+    /// nodes are specified.
+    pub numa_nodes: Vec<usize>,
+
+    /// Pins each worker thread to a single CPU of the set in the round-robin order, instead of
+    /// allowing the threads to run on any CPU of the set.
+    pub pin_each_thread_to_cpu: bool,
+}
+
+/// A set of CPUs.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct CpuSet(BTreeSet<usize>);
+
+impl CpuSet {
+    /// Parses the CPU set from the Linux CPU list format, e.g. `0-3,8-11`.
+    pub fn parse(list: &str) -> BootstrapResult<Self> {
+        let mut cpus = BTreeSet::new();
+
+        for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+            let (start, end): (usize, usize) = match range.split_once('-') {
+                Some((start, end)) => (start.trim().parse()?, end.trim().parse()?),
+                None => {
+                    let cpu = range.trim().parse()?;
+
+                    (cpu, cpu)
+                }
+            };
+
+            if start > end {
+                bail!("invalid CPU range `{range}`");
+            }
+
+            cpus.extend(start..=end);
+        }
+
+        Ok(Self(cpus))
+    }
+
+    /// Returns the CPUs the current thread is allowed to run on.
+    pub fn current() -> BootstrapResult<Self> {
+        // SAFETY: `cpu_set_t` is a plain bit mask for which zeroed memory is a valid value.
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the pointer is valid for the size of the set passed to the syscall.
+        let res =
+            unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+
+        if res != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let cpus = (0..libc::CPU_SETSIZE as usize)
+            // SAFETY: the CPU index is within the bounds of the set.
+            .filter(|cpu| unsafe { libc::CPU_ISSET(*cpu, &set) })
+            .collect();
+
+        Ok(Self(cpus))
+    }
+
+    /// Returns the CPUs of the NUMA node.
+    pub fn numa_node(node: usize) -> BootstrapResult<Self> {
+        let path = format!("/sys/devices/system/node/node{node}/cpulist");
+
+        match std::fs::read_to_string(&path) {
+            Ok(list) => Self::parse(&list),
+            Err(err) => bail!("failed to read CPUs of NUMA node {node} from {path}: {err}"),
+        }
+    }
+
+    /// Returns the number of CPUs in the set.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the set contains no CPUs.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `true` if the set contains the CPU.
+    pub fn contains(&self, cpu: usize) -> bool {
+        self.0.contains(&cpu)
+    }
+
+    /// Returns an iterator over the CPUs of the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Pins the current thread to the CPUs of the set.
+    pub fn pin_current_thread(&self) -> BootstrapResult<()> {
+        // SAFETY: `cpu_set_t` is a plain bit mask for which zeroed memory is a valid value.
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+
+        for cpu in self.iter() {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                bail!("CPU {cpu} is out of the supported range");
+            }
+
+            // SAFETY: the CPU index is within the bounds of the set.
+            unsafe { libc::CPU_SET(cpu, &mut set) };
+        }
+
+        // SAFETY: the pointer is valid for the size of the set passed to the syscall.
+        let res =
+            unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+
+        if res != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CpuSet {
+    // NOTE: formats the set in the Linux CPU list format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cpus = self.iter().peekable();
+        let mut first = true;
+
+        while let Some(start) = cpus.next() {
+            let mut end = start;
+
+            while cpus.peek() == Some(&(end + 1)) {
+                end = cpus.next().unwrap_or(end);
+            }
+
+            if !first {
+                f.write_str(",")?;
+            }
+
+            first = false;
+
+            if start == end {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CpuSet({self})")
+    }
+}
+
+/// CPU affinity of the worker threads resolved from [`CpuAffinitySettings`].
+///
+/// The affinity is a cheaply cloneable handle, so it can be moved to the thread start hooks of
+/// multiple runtimes. See [module-level documentation] for an example.
+///
+/// [module-level documentation]: crate::cpu_affinity
+#[derive(Clone, Debug)]
+pub struct CpuAffinity {
+    cpus: Option<CpuSet>,
+    pin_each_thread_to_cpu: bool,
+    next_cpu: Arc<AtomicUsize>,
+}
+
+impl CpuAffinity {
+    /// Resolves the CPUs the worker threads are pinned to.
+    ///
+    /// Returns an error if the configured CPUs or NUMA nodes are not available to the process.
+    pub fn new(settings: &CpuAffinitySettings) -> BootstrapResult<Self> {
+        let mut affinity = Self {
+            cpus: None,
+            pin_each_thread_to_cpu: settings.pin_each_thread_to_cpu,
+            next_cpu: Default::default(),
+        };
+
+        if !settings.enabled {
+            return Ok(affinity);
+        }
+
+        let available = CpuSet::current()?;
+        let mut cpus = match &settings.cpus {
+            Some(list) => CpuSet::parse(list)?,
+            None => CpuSet::default(),
+        };
+
+        for node in &settings.numa_nodes {
+            cpus.0.extend(CpuSet::numa_node(*node)?.0);
+        }
+
+        if cpus.is_empty() {
+            cpus = available.clone();
+        }
+
+        let unavailable = CpuSet(cpus.0.difference(&available.0).copied().collect());
+
+        if !unavailable.is_empty() {
+            bail!("CPUs {unavailable} are not available to the process (available: {available})");
+        }
+
+        #[cfg(feature = "logging")]
+        crate::telemetry::log::info!(
+            "pinning worker threads to CPUs";
+            "cpus" => cpus.to_string(),
+            "numa_nodes" => format!("{:?}", settings.numa_nodes),
+            "pin_each_thread_to_cpu" => settings.pin_each_thread_to_cpu
+        );
+
+        #[cfg(feature = "metrics")]
+        crate::telemetry::metrics::report_info(CpuAffinityInfo {
+            cpus: cpus.to_string(),
+            numa_nodes: settings
+                .numa_nodes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            pin_each_thread_to_cpu: settings.pin_each_thread_to_cpu,
+        });
+
+        affinity.cpus = Some(cpus);
+
+        Ok(affinity)
+    }
+
+    /// Returns the CPUs the worker threads are pinned to, or `None` if pinning is disabled.
+    pub fn cpus(&self) -> Option<&CpuSet> {
+        self.cpus.as_ref()
+    }
+
+    /// Pins the current thread to the configured CPUs. No-op if pinning is disabled.
+    ///
+    /// If [`CpuAffinitySettings::pin_each_thread_to_cpu`] is enabled, each call pins the thread to
+    /// the next CPU of the set.
+    pub fn pin_current_thread(&self) -> BootstrapResult<()> {
+        let Some(cpus) = &self.cpus else {
+            return Ok(());
+        };
+
+        if !self.pin_each_thread_to_cpu {
+            return cpus.pin_current_thread();
+        }
+
+        let idx = self.next_cpu.fetch_add(1, Ordering::Relaxed) % cpus.len();
+        let cpu = cpus.iter().nth(idx).unwrap_or_default();
+
+        CpuSet(BTreeSet::from([cpu])).pin_current_thread()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_cpu_lists() {
+        let set = CpuSet::parse("0-3, 8,10-11\n").unwrap();
+
+        assert_eq!(set.iter().collect::<Vec<_>>(), [0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(set.to_string(), "0-3,8,10-11");
+
+        assert!(CpuSet::parse("3-1").is_err());
+        assert!(CpuSet::parse("a").is_err());
+    }
+
+    #[test]
+    fn pins_threads_round_robin() {
+        let available = CpuSet::current().unwrap();
+        let first = available.iter().next().unwrap();
+
+        let affinity = CpuAffinity::new(&CpuAffinitySettings {
+            enabled: true,
+            cpus: Some(first.to_string()),
+            pin_each_thread_to_cpu: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        std::thread::spawn(move || {
+            affinity.pin_current_thread().unwrap();
+
+            assert_eq!(CpuSet::current().unwrap().to_string(), first.to_string());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn rejects_unavailable_cpus() {
+        let res = CpuAffinity::new(&CpuAffinitySettings {
+            enabled: true,
+            cpus: Some("100000".into()),
+            ..Default::default()
+        });
+
+        assert!(res.is_err());
+    }
+}
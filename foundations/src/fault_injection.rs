@@ -0,0 +1,316 @@
+//! Fault injection for resilience testing.
+//!
+//! Services declare named injection points in the code paths that can fail in production, e.g.
+//! before calls to the upstream services. Faults injected at the points are configured with
+//! [`FaultInjectionSettings`] and, if the service uses the telemetry server, can be changed at
+//! runtime with the [`telemetry_server_route`], so resilience tests can be run against
+//! the real service binaries.
+//!
+//! The following faults can be injected at each point:
+//! - **latency**: the execution is delayed for the configured time;
+//! - **error**: the point reports that the operation should fail with an error;
+//! - **drop**: the point reports that the operation should be abandoned, e.g. the connection
+//!   should be closed without a response.
+//!
+//! Fault injection is disabled by default and all the injection points are no-op unless
+//! [`FaultInjectionSettings::enabled`] is set.
+//!
+//! # Examples
+//! ```
+//! use foundations::fault_injection::{
+//!     self, FaultInjectionSettings, FaultSettings, InjectedFault,
+//! };
+//!
+//! async fn fetch_from_upstream() -> Result<String, foundations::Error> {
+//!     if let Some(fault) = fault_injection::inject("upstream").await {
+//!         return Err(fault.into());
+//!     }
+//!
+//!     Ok("data".into())
+//! }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! fault_injection::init(&FaultInjectionSettings {
+//!     enabled: true,
+//!     points: vec![FaultSettings {
+//!         point: "upstream".into(),
+//!         error_probability: 1.0,
+//!         ..Default::default()
+//!     }],
+//! });
+//!
+//! assert!(fetch_from_upstream().await.is_err());
+//!
+//! fault_injection::clear_fault("upstream");
+//!
+//! assert_eq!(fetch_from_upstream().await.unwrap(), "data");
+//! # }
+//! ```
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(all(
+    feature = "telemetry-server",
+    any(feature = "logging", feature = "metrics", feature = "tracing")
+))]
+use crate::telemetry::TelemetryServerRoute;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static FAULTS: Lazy<RwLock<HashMap<String, FaultSettings>>> = Lazy::new(Default::default);
+
+/// Fault injection settings.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+pub struct FaultInjectionSettings {
+    /// Enables fault injection.
+    ///
+    /// Should never be enabled in production.
+    pub enabled: bool,
+
+    /// Faults injected at the injection points.
+    pub points: Vec<FaultSettings>,
+}
+
+/// Faults injected at an injection point.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(
+    not(feature = "settings"),
+    derive(Clone, Default, Debug, serde::Serialize, serde::Deserialize)
+)]
+pub struct FaultSettings {
+    /// Name of the injection point.
+    pub point: String,
+
+    /// Latency in milliseconds added to each pass through the injection point.
+    pub latency_ms: Option<u64>,
+
+    /// Probability of an error, from `0.0` to `1.0`.
+    pub error_probability: f64,
+
+    /// Probability of the operation being dropped, from `0.0` to `1.0`.
+    pub drop_probability: f64,
+}
+
+/// A fault injected at an injection point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectedFault {
+    /// The operation should fail with an error.
+    Error,
+
+    /// The operation should be abandoned.
+    Drop,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InjectedFault::Error => write!(f, "injected error"),
+            InjectedFault::Drop => write!(f, "injected drop"),
+        }
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+/// Initializes fault injection with the provided settings, replacing all the previously
+/// configured faults.
+pub fn init(settings: &FaultInjectionSettings) {
+    let mut faults = FAULTS.write();
+
+    faults.clear();
+
+    for fault in &settings.points {
+        faults.insert(fault.point.clone(), fault.clone());
+    }
+
+    ENABLED.store(settings.enabled, Ordering::Relaxed);
+}
+
+/// Sets the faults injected at the point, replacing the previously configured ones.
+///
+/// Faults are not injected if fault injection is not enabled in the settings.
+pub fn set_fault(fault: FaultSettings) {
+    FAULTS.write().insert(fault.point.clone(), fault);
+}
+
+/// Removes the faults injected at the point.
+pub fn clear_fault(point: &str) {
+    FAULTS.write().remove(point);
+}
+
+/// Returns the faults injected at all the points.
+pub fn faults() -> Vec<FaultSettings> {
+    let mut faults: Vec<_> = FAULTS.read().values().cloned().collect();
+
+    faults.sort_by(|f1, f2| f1.point.cmp(&f2.point));
+
+    faults
+}
+
+/// Passes through the injection point, applying the faults configured for it.
+///
+/// Returns the fault that the caller should simulate, or `None` if the operation should
+/// proceed normally. If latency is configured for the point, the returned future completes after
+/// the latency elapses.
+pub async fn inject(point: &str) -> Option<InjectedFault> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let fault = FAULTS.read().get(point).cloned()?;
+
+    if let Some(latency_ms) = fault.latency_ms {
+        tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+    }
+
+    if fault.drop_probability > 0.0 && rand::random::<f64>() < fault.drop_probability {
+        return Some(InjectedFault::Drop);
+    }
+
+    if fault.error_probability > 0.0 && rand::random::<f64>() < fault.error_probability {
+        return Some(InjectedFault::Error);
+    }
+
+    None
+}
+
+/// Returns a telemetry server route that controls fault injection at runtime.
+///
+/// The route should be passed to [`init_with_server`] and handles the following requests to
+/// `/fault_injection` path:
+/// - `GET` returns the faults injected at all the points as JSON.
+/// - `PUT` with a JSON [`FaultSettings`] body sets the faults injected at the point.
+/// - `DELETE` with the `point` query parameter removes the faults injected at the point, and
+///   without parameters removes all the faults.
+///
+/// Modifications are rejected with `403 Forbidden` if fault injection is not enabled in
+/// the settings.
+///
+/// [`init_with_server`]: crate::telemetry::init_with_server
+#[cfg(all(
+    feature = "telemetry-server",
+    any(feature = "logging", feature = "metrics", feature = "tracing")
+))]
+pub fn telemetry_server_route() -> TelemetryServerRoute {
+    use futures_util::FutureExt;
+    use hyper::{Body, Method, Response, StatusCode};
+
+    fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .body(body.into())
+            .unwrap()
+    }
+
+    TelemetryServerRoute {
+        path: "/fault_injection".into(),
+        methods: vec![Method::GET, Method::PUT, Method::DELETE],
+        handler: Box::new(|req, _| {
+            async move {
+                if req.method() == Method::GET {
+                    let faults = serde_json::to_string(&faults()).unwrap_or_default();
+
+                    return Ok(Response::builder()
+                        .header(hyper::header::CONTENT_TYPE, "application/json")
+                        .body(faults.into())
+                        .unwrap());
+                }
+
+                if !ENABLED.load(Ordering::Relaxed) {
+                    return Ok(response(
+                        StatusCode::FORBIDDEN,
+                        "fault injection is disabled",
+                    ));
+                }
+
+                if req.method() == Method::DELETE {
+                    let point = req.uri().query().and_then(|query| {
+                        query
+                            .split('&')
+                            .find_map(|param| param.strip_prefix("point="))
+                    });
+
+                    match point {
+                        Some(point) => clear_fault(point),
+                        None => FAULTS.write().clear(),
+                    }
+
+                    return Ok(response(StatusCode::OK, ""));
+                }
+
+                let res = match hyper::body::to_bytes(req.into_body()).await {
+                    Ok(body) => match serde_json::from_slice::<FaultSettings>(&body) {
+                        Ok(fault) => {
+                            set_fault(fault);
+                            response(StatusCode::OK, "")
+                        }
+                        Err(err) => response(StatusCode::BAD_REQUEST, err.to_string()),
+                    },
+                    Err(err) => response(StatusCode::BAD_REQUEST, err.to_string()),
+                };
+
+                Ok(res)
+            }
+            .boxed()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn injects_configured_faults() {
+        init(&FaultInjectionSettings {
+            enabled: true,
+            points: vec![
+                FaultSettings {
+                    point: "error".into(),
+                    error_probability: 1.0,
+                    ..Default::default()
+                },
+                FaultSettings {
+                    point: "drop".into(),
+                    latency_ms: Some(20),
+                    drop_probability: 1.0,
+                    ..Default::default()
+                },
+            ],
+        });
+
+        assert_eq!(inject("error").await, Some(InjectedFault::Error));
+        assert_eq!(inject("unknown").await, None);
+
+        let start = std::time::Instant::now();
+
+        assert_eq!(inject("drop").await, Some(InjectedFault::Drop));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        clear_fault("error");
+
+        assert_eq!(inject("error").await, None);
+        assert_eq!(faults().len(), 1);
+
+        init(&FaultInjectionSettings {
+            enabled: false,
+            points: vec![FaultSettings {
+                point: "error".into(),
+                error_probability: 1.0,
+                ..Default::default()
+            }],
+        });
+
+        assert_eq!(inject("error").await, None);
+    }
+}
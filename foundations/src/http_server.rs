@@ -0,0 +1,404 @@
+//! Graceful shutdown helpers for [hyper]-based HTTP servers.
+//!
+//! Shutting an HTTP server down gracefully involves several steps: the server needs to stop
+//! accepting new connections, notify the clients of the existing connections that no new
+//! requests will be accepted (by sending `GOAWAY` frames to HTTP/2 clients and disabling
+//! keep-alive for HTTP/1) and wait for the in-flight requests to finish. The latter usually
+//! needs to be limited in time, so a slow client can't indefinitely block the shutdown.
+//!
+//! [`ConnectionDrainer`] tracks the connections served with it and implements the draining
+//! logic, while [`serve`] provides a ready-to-use accept loop on top of it.
+//!
+//! With the `metrics` feature, the number of connections that remain open during draining is
+//! reported by the `<prefix>_foundations_http_server_draining_connections` gauge labeled with
+//! the server name.
+//!
+//! The bytes received and sent in each request can be counted with [`ByteAccounting`].
+//!
+//! # Examples
+//! ```
+//! use foundations::http_server::{self, ConnectionDrainer};
+//! use hyper::server::conn::Http;
+//! use hyper::service::service_fn;
+//! use hyper::{Body, Response};
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//! use tokio::net::TcpListener;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! let listener = TcpListener::bind("127.0.0.1:0").await?;
+//! let drainer = ConnectionDrainer::new("api");
+//!
+//! let server = http_server::serve(
+//!     listener,
+//!     Http::new(),
+//!     drainer.clone(),
+//!     |_peer_addr| {
+//!         service_fn(|_req| async { Ok::<_, Infallible>(Response::new(Body::from("Hello"))) })
+//!     },
+//!     // Shut down right away for the demonstration purposes.
+//!     async {},
+//! );
+//!
+//! server.await?;
+//!
+//! assert_eq!(drainer.drain(Duration::from_secs(30)).await, 0);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [hyper]: https://docs.rs/hyper
+
+mod byte_accounting;
+
+pub use self::byte_accounting::{ByteAccounting, CountingBody, CountingIo};
+
+use futures_util::future::{self, Either};
+use hyper::server::conn::Http;
+use hyper::service::Service;
+use hyper::{Body, Request, Response};
+use std::error::Error as StdError;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{watch, Notify};
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Gauge, Histogram, HistogramBuilder};
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_http_server {
+    /// Number of connections that remain open while the server is draining.
+    pub fn draining_connections(server: &'static str) -> Gauge;
+
+    /// Size of the requests counted with `ByteAccounting`.
+    #[ctor = HistogramBuilder {
+        buckets: &[64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0],
+    }]
+    pub fn request_size_bytes(server: &'static str) -> Histogram;
+
+    /// Size of the responses counted with `ByteAccounting`.
+    #[ctor = HistogramBuilder {
+        buckets: &[64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0],
+    }]
+    pub fn response_size_bytes(server: &'static str) -> Histogram;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DrainState {
+    Serving,
+    Draining,
+    Closed,
+}
+
+struct Shared {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    state: watch::Sender<DrainState>,
+    active: AtomicUsize,
+    connection_closed: Notify,
+}
+
+impl Shared {
+    #[cfg(feature = "metrics")]
+    fn report_draining_connections(&self) {
+        if *self.state.borrow() == DrainState::Draining {
+            foundations_http_server::draining_connections(self.name)
+                .set(self.active.load(Ordering::SeqCst) as u64);
+        }
+    }
+}
+
+/// Tracks HTTP connections and gracefully shuts them down on [`ConnectionDrainer::drain`].
+///
+/// The drainer is a cheaply cloneable handle, all the clones track the same set of connections.
+#[derive(Clone)]
+pub struct ConnectionDrainer {
+    shared: Arc<Shared>,
+}
+
+impl ConnectionDrainer {
+    /// Creates a new connection drainer.
+    ///
+    /// The `name` is used as a `server` label of the drain metrics.
+    pub fn new(name: &'static str) -> Self {
+        let (state, _) = watch::channel(DrainState::Serving);
+
+        Self {
+            shared: Arc::new(Shared {
+                name,
+                state,
+                active: AtomicUsize::new(0),
+                connection_closed: Notify::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of open connections served with the drainer.
+    pub fn active_connections(&self) -> usize {
+        self.shared.active.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if [`ConnectionDrainer::drain`] has been called.
+    pub fn is_draining(&self) -> bool {
+        *self.shared.state.borrow() != DrainState::Serving
+    }
+
+    /// Serves the connection with the given service, gracefully shutting it down on drain.
+    ///
+    /// The returned future needs to be polled to drive the connection, it resolves once
+    /// the connection is closed.
+    pub fn serve_connection<I, S>(
+        &self,
+        http: &Http,
+        io: I,
+        service: S,
+    ) -> impl Future<Output = hyper::Result<()>> + Send + 'static
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S::Future: Send + 'static,
+    {
+        let conn = http.serve_connection(io, service);
+        let guard = ConnectionGuard::new(Arc::clone(&self.shared));
+        let mut state = self.shared.state.subscribe();
+
+        async move {
+            let _guard = guard;
+            let mut conn = pin!(conn);
+
+            match future::select(
+                conn.as_mut(),
+                pin!(wait_for(&mut state, DrainState::Draining)),
+            )
+            .await
+            {
+                Either::Left((res, _)) => return res,
+                Either::Right(_) => conn.as_mut().graceful_shutdown(),
+            }
+
+            // NOTE: the connection is dropped if it doesn't finish within the drain budget.
+            match future::select(conn, pin!(wait_for(&mut state, DrainState::Closed))).await {
+                Either::Left((res, _)) => res,
+                Either::Right(_) => Ok(()),
+            }
+        }
+    }
+
+    /// Gracefully shuts down all the connections served with the drainer.
+    ///
+    /// HTTP/2 clients receive a `GOAWAY` frame and keep-alive is disabled for HTTP/1 connections,
+    /// so the connections are closed once their in-flight requests are finished. Connections that
+    /// remain open after the `budget` elapses are closed forcibly.
+    ///
+    /// Returns the number of connections that have been closed forcibly.
+    pub async fn drain(&self, budget: Duration) -> usize {
+        self.shared.state.send_replace(DrainState::Draining);
+
+        #[cfg(feature = "metrics")]
+        self.shared.report_draining_connections();
+
+        let mut deadline = pin!(tokio::time::sleep(budget));
+
+        let remaining = loop {
+            let closed = self.shared.connection_closed.notified();
+            let active = self.active_connections();
+
+            if active == 0 {
+                break 0;
+            }
+
+            if let Either::Right(_) = future::select(pin!(closed), deadline.as_mut()).await {
+                break self.active_connections();
+            }
+        };
+
+        self.shared.state.send_replace(DrainState::Closed);
+
+        #[cfg(feature = "metrics")]
+        foundations_http_server::draining_connections(self.shared.name).set(0);
+
+        remaining
+    }
+}
+
+struct ConnectionGuard(Arc<Shared>);
+
+impl ConnectionGuard {
+    fn new(shared: Arc<Shared>) -> Self {
+        shared.active.fetch_add(1, Ordering::SeqCst);
+
+        Self(shared)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+
+        #[cfg(feature = "metrics")]
+        self.0.report_draining_connections();
+
+        self.0.connection_closed.notify_waiters();
+    }
+}
+
+async fn wait_for(state: &mut watch::Receiver<DrainState>, target: DrainState) {
+    loop {
+        let current = *state.borrow_and_update();
+
+        if current == target || current == DrainState::Closed {
+            return;
+        }
+
+        if state.changed().await.is_err() {
+            // NOTE: the drainer is gone, so the state will never change.
+            return future::pending().await;
+        }
+    }
+}
+
+/// Accepts connections on the listener and serves them with the services created by
+/// `new_service` until the `shutdown` future completes.
+///
+/// Connections are spawned as separate tasks on the current Tokio runtime and are tracked by
+/// the `drainer`. The function stops accepting new connections and returns once `shutdown`
+/// completes, and [`ConnectionDrainer::drain`] should then be called to gracefully shut down
+/// the open connections.
+///
+/// Returns an error if accepting a connection fails with an error other than the one caused
+/// by the peer.
+pub async fn serve<S, F>(
+    listener: TcpListener,
+    http: Http,
+    drainer: ConnectionDrainer,
+    mut new_service: F,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<()>
+where
+    F: FnMut(SocketAddr) -> S,
+    S: Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S::Future: Send + 'static,
+{
+    let mut shutdown = pin!(shutdown);
+
+    loop {
+        let (stream, peer_addr) =
+            match future::select(pin!(listener.accept()), shutdown.as_mut()).await {
+                Either::Left((Ok(conn), _)) => conn,
+                Either::Left((Err(err), _)) if is_peer_error(&err) => continue,
+                Either::Left((Err(err), _)) => return Err(err),
+                Either::Right(_) => return Ok(()),
+            };
+
+        let conn = drainer.serve_connection(&http, stream, new_service(peer_addr));
+
+        tokio::spawn(conn);
+    }
+}
+
+fn is_peer_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::service_fn;
+    use std::convert::Infallible;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn closes_idle_connections_on_drain() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let drainer = ConnectionDrainer::new("test_idle");
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let server = tokio::spawn(serve(
+            listener,
+            Http::new(),
+            drainer.clone(),
+            |_| service_fn(|_| async { Ok::<_, Infallible>(Response::new(Body::from("ok"))) }),
+            async {
+                let _ = shutdown_rx.await;
+            },
+        ));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut buf = [0; 1024];
+        let n = client.read(&mut buf).await.unwrap();
+
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(drainer.active_connections(), 1);
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        assert_eq!(drainer.drain(Duration::from_secs(5)).await, 0);
+        assert_eq!(drainer.active_connections(), 0);
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn closes_connections_forcibly_after_budget() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let drainer = ConnectionDrainer::new("test_budget");
+
+        let server = tokio::spawn(serve(
+            listener,
+            Http::new(),
+            drainer.clone(),
+            |_| {
+                service_fn(|_| async {
+                    future::pending::<()>().await;
+
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                })
+            },
+            future::pending(),
+        ));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        while drainer.active_connections() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(drainer.drain(Duration::from_millis(50)).await, 1);
+
+        let mut buf = [0; 1024];
+
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+
+        server.abort();
+    }
+}
@@ -0,0 +1,345 @@
+use hyper::body::{Buf, HttpBody, SizeHint};
+use hyper::HeaderMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[cfg(any(feature = "logging", feature = "tracing"))]
+use crate::telemetry::TelemetryContext;
+
+/// Byte accounting of a request.
+///
+/// The bytes received and sent in the request are counted by the request and response bodies
+/// wrapped with [`ByteAccounting::count_received`] and [`ByteAccounting::count_sent`], or by
+/// the I/O object wrapped with [`ByteAccounting::count_io`].
+///
+/// Once the accounting and all the wrappers are dropped, i.e. the response has been sent,
+/// the totals are:
+/// - added as the `request.size_bytes` and `response.size_bytes` tags to the tracing span and
+///   as the `request_size_bytes` and `response_size_bytes` fields to the log of the telemetry
+///   context the accounting has been created in;
+/// - with the `metrics` feature, reported by the
+///   `<prefix>_foundations_http_server_request_size_bytes` and
+///   `<prefix>_foundations_http_server_response_size_bytes` histograms labeled with the server
+///   name.
+///
+/// # Examples
+/// ```
+/// use foundations::http_server::ByteAccounting;
+/// use hyper::service::service_fn;
+/// use hyper::{Body, Request, Response};
+/// use std::convert::Infallible;
+///
+/// let service = service_fn(|req: Request<Body>| async move {
+///     let accounting = ByteAccounting::new("api");
+///     let body = hyper::body::to_bytes(accounting.count_received(req.into_body())).await;
+///
+///     assert_eq!(accounting.received(), body.unwrap().len() as u64);
+///
+///     Ok::<_, Infallible>(Response::new(accounting.count_sent(Body::from("Hello"))))
+/// });
+/// ```
+#[derive(Clone)]
+pub struct ByteAccounting {
+    totals: Arc<Totals>,
+}
+
+struct Totals {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    server: &'static str,
+    received: AtomicU64,
+    sent: AtomicU64,
+
+    #[cfg(any(feature = "logging", feature = "tracing"))]
+    ctx: TelemetryContext,
+}
+
+impl ByteAccounting {
+    /// Creates the accounting of a request in the current telemetry context.
+    ///
+    /// The `server` is used as a `server` label of the metrics.
+    pub fn new(server: &'static str) -> Self {
+        Self {
+            totals: Arc::new(Totals {
+                server,
+                received: Default::default(),
+                sent: Default::default(),
+
+                #[cfg(any(feature = "logging", feature = "tracing"))]
+                ctx: TelemetryContext::current(),
+            }),
+        }
+    }
+
+    /// Returns the number of bytes received so far.
+    pub fn received(&self) -> u64 {
+        self.totals.received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes sent so far.
+    pub fn sent(&self) -> u64 {
+        self.totals.sent.load(Ordering::Relaxed)
+    }
+
+    /// Wraps the request body, counting its data as received.
+    pub fn count_received<B>(&self, body: B) -> CountingBody<B> {
+        CountingBody {
+            inner: body,
+            totals: Arc::clone(&self.totals),
+            sent: false,
+        }
+    }
+
+    /// Wraps the response body, counting its data as sent.
+    pub fn count_sent<B>(&self, body: B) -> CountingBody<B> {
+        CountingBody {
+            inner: body,
+            totals: Arc::clone(&self.totals),
+            sent: true,
+        }
+    }
+
+    /// Wraps the I/O object, counting the read bytes as received and the written bytes as sent.
+    ///
+    /// Unlike the bodies, the I/O object also carries the protocol overhead, e.g. HTTP headers
+    /// and TLS records, so it can be used to account the bytes on the wire.
+    pub fn count_io<T>(&self, io: T) -> CountingIo<T> {
+        CountingIo {
+            inner: io,
+            totals: Arc::clone(&self.totals),
+        }
+    }
+}
+
+impl Drop for Totals {
+    fn drop(&mut self) {
+        let received = *self.received.get_mut();
+        let sent = *self.sent.get_mut();
+
+        #[cfg(feature = "metrics")]
+        {
+            super::foundations_http_server::request_size_bytes(self.server)
+                .observe(received as f64);
+            super::foundations_http_server::response_size_bytes(self.server).observe(sent as f64);
+        }
+
+        #[cfg(any(feature = "logging", feature = "tracing"))]
+        let _scope = self.ctx.scope();
+
+        #[cfg(feature = "tracing")]
+        crate::telemetry::tracing::add_span_tags!(
+            "request.size_bytes" => received as i64,
+            "response.size_bytes" => sent as i64
+        );
+
+        #[cfg(feature = "logging")]
+        crate::telemetry::log::add_fields!(
+            "request_size_bytes" => received,
+            "response_size_bytes" => sent
+        );
+
+        #[cfg(not(any(feature = "metrics", feature = "logging", feature = "tracing")))]
+        let _ = (received, sent);
+    }
+}
+
+/// A body that counts its data, see [`ByteAccounting`].
+pub struct CountingBody<B> {
+    inner: B,
+    totals: Arc<Totals>,
+    sent: bool,
+}
+
+impl<B> CountingBody<B> {
+    fn count(&self, bytes: usize) {
+        let total = if self.sent {
+            &self.totals.sent
+        } else {
+            &self.totals.received
+        };
+
+        total.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl<B> HttpBody for CountingBody<B>
+where
+    B: HttpBody + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let res = ready!(Pin::new(&mut self.inner).poll_data(cx));
+
+        if let Some(Ok(data)) = &res {
+            self.count(data.remaining());
+        }
+
+        Poll::Ready(res)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// An I/O object that counts the read and written bytes, see [`ByteAccounting`].
+pub struct CountingIo<T> {
+    inner: T,
+    totals: Arc<Totals>,
+}
+
+impl<T> AsyncRead for CountingIo<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+
+        let read = buf.filled().len() - filled;
+
+        self.totals
+            .received
+            .fetch_add(read as u64, Ordering::Relaxed);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> AsyncWrite for CountingIo<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+
+        self.totals
+            .sent
+            .fetch_add(written as u64, Ordering::Relaxed);
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write_vectored(cx, bufs))?;
+
+        self.totals
+            .sent
+            .fetch_add(written as u64, Ordering::Relaxed);
+
+        Poll::Ready(Ok(written))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Body;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn counts_body_and_io_bytes() {
+        let accounting = ByteAccounting::new("test_bytes");
+
+        let body = accounting.count_received(Body::from("hello"));
+
+        assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "hello");
+
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = accounting.count_io(client);
+        let mut server = server;
+
+        client.write_all(b"ping").await.unwrap();
+
+        let mut buf = [0; 4];
+
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(b"pong!").await.unwrap();
+        drop(server);
+
+        let mut buf = vec![];
+
+        client.read_to_end(&mut buf).await.unwrap();
+
+        assert_eq!(accounting.received(), 10);
+        assert_eq!(accounting.sent(), 4);
+    }
+
+    #[cfg(all(feature = "tracing", feature = "testing"))]
+    #[tokio::test]
+    async fn reports_totals_once_response_is_sent() {
+        use crate::telemetry::tracing::{self, test_trace, TestTraceOptions};
+
+        let ctx = TelemetryContext::test();
+
+        let response = {
+            let _scope = ctx.scope();
+            let _span = tracing::span("request");
+            let accounting = ByteAccounting::new("test_report");
+
+            hyper::body::to_bytes(accounting.count_received(Body::from("hello")))
+                .await
+                .unwrap();
+
+            accounting.count_sent(Body::from("hi"))
+        };
+
+        assert_eq!(hyper::body::to_bytes(response).await.unwrap(), "hi");
+
+        assert_eq!(
+            ctx.traces(TestTraceOptions {
+                include_tags: true,
+                ..Default::default()
+            }),
+            vec![test_trace! {
+                "request"; {
+                    tags: [("request.size_bytes", 5), ("response.size_bytes", 2)]
+                }
+            }]
+        );
+    }
+}
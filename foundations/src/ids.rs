@@ -0,0 +1,383 @@
+//! Generation of time-sortable identifiers.
+//!
+//! [`Ulid`] and [`UuidV7`] identifiers start with the Unix timestamp in milliseconds of their
+//! generation, so they sort by the generation time both as numbers and as strings, which keeps
+//! the database indices compact and makes the identifiers convenient to correlate with logs and
+//! traces of all the services of the fleet.
+//!
+//! The identifiers generated by the process are strictly monotonic: the random part of
+//! an identifier generated in the same millisecond as the previous one, or after the system clock
+//! went backwards, is the random part of the previous identifier incremented by one. Therefore,
+//! the identifiers are not suitable as secrets.
+//!
+//! Both identifiers are formatted as fixed-length strings of ASCII letters, digits and hyphens
+//! that don't need to be escaped in logs, span tags and metric labels. With the `logging` feature,
+//! they can be used directly as log fields.
+//!
+//! # Examples
+//! ```
+//! use foundations::ids::{Ulid, UuidV7};
+//!
+//! let first = Ulid::new();
+//! let second = Ulid::new();
+//!
+//! assert!(first < second);
+//! assert!(first.to_string() < second.to_string());
+//! assert_eq!(first.to_string().parse::<Ulid>().unwrap(), first);
+//!
+//! let id = UuidV7::new();
+//!
+//! assert_eq!(id.to_string().len(), 36);
+//! assert!(id.timestamp_ms() >= first.timestamp_ms());
+//! ```
+
+use crate::Result;
+use parking_lot::Mutex;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TIMESTAMP_BITS: u32 = 48;
+const ULID_RANDOM_BITS: u32 = 80;
+const UUID_RANDOM_BITS: u32 = 74;
+
+// NOTE: Crockford's base32 alphabet.
+const ULID_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+static ULID_GENERATOR: Mutex<Monotonic> = Mutex::new(Monotonic::new(ULID_RANDOM_BITS));
+static UUID_GENERATOR: Mutex<Monotonic> = Mutex::new(Monotonic::new(UUID_RANDOM_BITS));
+
+/// A [ULID] identifier: 48 bits of the Unix timestamp in milliseconds followed by 80 random bits.
+///
+/// Formatted as 26 uppercase characters of [Crockford's base32], e.g.
+/// `01ARZ3NDEKTSV4RRFFQ69G5FAV`. Parsing is case-insensitive.
+///
+/// [ULID]: https://github.com/ulid/spec
+/// [Crockford's base32]: https://www.crockford.com/base32.html
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    /// Generates a new identifier, that is greater than all the ones previously generated by
+    /// the process.
+    pub fn new() -> Self {
+        let (timestamp_ms, random) = ULID_GENERATOR.lock().next(unix_ms());
+
+        Self::from_parts(timestamp_ms, random)
+    }
+
+    /// Creates an identifier from the Unix timestamp in milliseconds and the random part.
+    ///
+    /// Only the lower 48 bits of the timestamp and the lower 80 bits of the random part are used.
+    pub const fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        Self(((timestamp_ms as u128) << ULID_RANDOM_BITS) | (random & mask(ULID_RANDOM_BITS)))
+    }
+
+    /// Returns the Unix timestamp in milliseconds of the identifier generation.
+    pub const fn timestamp_ms(&self) -> u64 {
+        (self.0 >> ULID_RANDOM_BITS) as u64
+    }
+
+    /// Returns the identifier as a number.
+    pub const fn to_u128(self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for Ulid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<u128> for Ulid {
+    fn from(value: u128) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = [0; 26];
+
+        for (i, c) in buf.iter_mut().enumerate() {
+            let shift = 5 * (25 - i);
+
+            *c = ULID_ALPHABET[((self.0 >> shift) & 0x1f) as usize];
+        }
+
+        // NOTE: the buffer only contains the characters of the alphabet.
+        f.write_str(std::str::from_utf8(&buf).unwrap())
+    }
+}
+
+impl fmt::Debug for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() != 26 {
+            return Err(format!("ULID should have 26 characters, got `{s}`").into());
+        }
+
+        let mut value = 0u128;
+
+        for (i, c) in s.bytes().enumerate() {
+            let digit = ULID_ALPHABET
+                .iter()
+                .position(|&d| d == c.to_ascii_uppercase())
+                .ok_or_else(|| format!("invalid character in ULID `{s}`"))?;
+
+            // NOTE: the first character only encodes 3 bits.
+            if i == 0 && digit > 7 {
+                return Err(format!("ULID `{s}` is out of range").into());
+            }
+
+            value = (value << 5) | digit as u128;
+        }
+
+        Ok(Self(value))
+    }
+}
+
+/// A version 7 [UUID]: 48 bits of the Unix timestamp in milliseconds followed by 74 random bits,
+/// interleaved with the version and variant bits.
+///
+/// Formatted as 36 lowercase hexadecimal digits and hyphens, e.g.
+/// `017f22e2-79b0-7cc3-98c4-dc0c0c07398f`. Parsing is case-insensitive and only accepts
+/// the hyphenated version 7 UUIDs.
+///
+/// [UUID]: https://www.rfc-editor.org/rfc/rfc9562
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UuidV7(u128);
+
+impl UuidV7 {
+    /// Generates a new identifier, that is greater than all the ones previously generated by
+    /// the process.
+    pub fn new() -> Self {
+        let (timestamp_ms, random) = UUID_GENERATOR.lock().next(unix_ms());
+
+        Self::from_parts(timestamp_ms, random)
+    }
+
+    /// Creates an identifier from the Unix timestamp in milliseconds and the random part.
+    ///
+    /// Only the lower 48 bits of the timestamp and the lower 74 bits of the random part are used.
+    pub const fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        let timestamp = (timestamp_ms as u128) & mask(TIMESTAMP_BITS);
+        let rand_a = (random >> 62) & mask(12);
+        let rand_b = random & mask(62);
+
+        Self((timestamp << 80) | (0x7 << 76) | (rand_a << 64) | (0b10 << 62) | rand_b)
+    }
+
+    /// Returns the Unix timestamp in milliseconds of the identifier generation.
+    pub const fn timestamp_ms(&self) -> u64 {
+        (self.0 >> 80) as u64
+    }
+
+    /// Returns the identifier as a number.
+    pub const fn to_u128(self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for UuidV7 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for UuidV7 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = self.0;
+
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            v >> 96,
+            (v >> 80) & 0xffff,
+            (v >> 64) & 0xffff,
+            (v >> 48) & 0xffff,
+            v & mask(48)
+        )
+    }
+}
+
+impl fmt::Debug for UuidV7 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for UuidV7 {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let groups: Vec<_> = s.split('-').collect();
+        let lens: Vec<_> = groups.iter().map(|g| g.len()).collect();
+
+        if lens != [8, 4, 4, 4, 12] {
+            return Err(format!("UUID should be in the hyphenated form, got `{s}`").into());
+        }
+
+        let hex = groups.concat();
+
+        if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid character in UUID `{s}`").into());
+        }
+
+        // NOTE: the string only contains 32 hexadecimal digits.
+        let value = u128::from_str_radix(&hex, 16)?;
+
+        if (value >> 76) & 0xf != 0x7 || (value >> 62) & 0b11 != 0b10 {
+            return Err(format!("`{s}` is not a version 7 UUID").into());
+        }
+
+        Ok(Self(value))
+    }
+}
+
+#[cfg(feature = "logging")]
+impl slog::Value for Ulid {
+    fn serialize(
+        &self,
+        _record: &slog::Record,
+        key: slog::Key,
+        serializer: &mut dyn slog::Serializer,
+    ) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{self}"))
+    }
+}
+
+#[cfg(feature = "logging")]
+impl slog::Value for UuidV7 {
+    fn serialize(
+        &self,
+        _record: &slog::Record,
+        key: slog::Key,
+        serializer: &mut dyn slog::Serializer,
+    ) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{self}"))
+    }
+}
+
+/// Generator of the timestamps and random parts of the monotonic identifiers.
+struct Monotonic {
+    random_bits: u32,
+    last_ms: u64,
+    last_random: u128,
+}
+
+impl Monotonic {
+    const fn new(random_bits: u32) -> Self {
+        Self {
+            random_bits,
+            last_ms: 0,
+            last_random: 0,
+        }
+    }
+
+    fn next(&mut self, now_ms: u64) -> (u64, u128) {
+        let max = mask(self.random_bits);
+
+        if now_ms > self.last_ms {
+            self.last_ms = now_ms;
+            self.last_random = rand::random::<u128>() & max;
+        } else if self.last_random < max {
+            self.last_random += 1;
+        } else {
+            // NOTE: the random part overflowed, so borrow the next millisecond.
+            self.last_ms += 1;
+            self.last_random = rand::random::<u128>() & max;
+        }
+
+        (self.last_ms, self.last_random)
+    }
+}
+
+const fn mask(bits: u32) -> u128 {
+    (1 << bits) - 1
+}
+
+fn unix_ms() -> u64 {
+    let ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    ms & mask(TIMESTAMP_BITS) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_and_parses_ulid() {
+        let id = Ulid::from_parts(1469918176385, 0x0100_0000_0000_0000_0001);
+
+        assert_eq!(id.to_string(), "01ARYZ6S410400000000000001");
+        assert_eq!(id.timestamp_ms(), 1469918176385);
+        assert_eq!("01aryz6s410400000000000001".parse::<Ulid>().unwrap(), id);
+
+        assert!("01ARYZ6S41040000000000000".parse::<Ulid>().is_err());
+        assert!("01ARYZ6S41040000000000000U".parse::<Ulid>().is_err());
+        assert!("81ARYZ6S410400000000000001".parse::<Ulid>().is_err());
+    }
+
+    #[test]
+    fn formats_and_parses_uuid() {
+        let id = UuidV7::from_parts(0x017f22e279b0, 0x0cc3 << 62 | 0x18c4_dc0c_0c07_398f);
+
+        assert_eq!(id.to_string(), "017f22e2-79b0-7cc3-98c4-dc0c0c07398f");
+        assert_eq!(id.timestamp_ms(), 0x017f22e279b0);
+        assert_eq!(
+            "017F22E2-79B0-7CC3-98C4-DC0C0C07398F"
+                .parse::<UuidV7>()
+                .unwrap(),
+            id
+        );
+
+        assert!("017f22e279b07cc398c4dc0c0c07398f"
+            .parse::<UuidV7>()
+            .is_err());
+        assert!("017f22e2-79b0-4cc3-98c4-dc0c0c07398f"
+            .parse::<UuidV7>()
+            .is_err());
+        assert!("017f22e2-79b0-7cc3-c8c4-dc0c0c07398f"
+            .parse::<UuidV7>()
+            .is_err());
+    }
+
+    #[test]
+    fn generates_monotonic_ids() {
+        let mut generator = Monotonic::new(4);
+
+        let (ms, _) = generator.next(100);
+
+        generator.last_random = 0x7;
+
+        // NOTE: the clock went backwards.
+        assert_eq!(generator.next(99), (100, 0x8));
+
+        generator.last_random = 0xf;
+
+        assert_eq!(generator.next(100).0, 101);
+        assert_eq!(generator.next(200).0, 200);
+        assert_eq!(ms, 100);
+
+        let ids: Vec<_> = (0..1000).map(|_| Ulid::new()).collect();
+
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+        let ids: Vec<_> = (0..1000).map(|_| UuidV7::new().to_string()).collect();
+
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,353 @@
+use crate::BootstrapError;
+use anyhow::{anyhow, bail};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+// NOTE: the schedules that don't match within this period, e.g. `0 0 30 2 *`, never match.
+const MAX_SEARCH_DAYS: i64 = 366 * 8;
+
+/// A schedule specified with a [cron expression], evaluated in UTC.
+///
+/// The expression consists of 5 fields: minute (0-59), hour (0-23), day of the month (1-31),
+/// month (1-12 or `JAN`-`DEC`) and day of the week (0-7 or `SUN`-`SAT`, both 0 and 7 are Sunday).
+/// Each field is either `*` or a comma-separated list of values, ranges like `1-5` and steps like
+/// `*/15` or `0-30/10`. If both the day of the month and the day of the week are restricted,
+/// the schedule matches the days that match either of them.
+///
+/// The `@yearly` (or `@annually`), `@monthly`, `@weekly`, `@daily` (or `@midnight`) and `@hourly`
+/// shortcuts are also supported.
+///
+/// # Examples
+/// ```
+/// use foundations::jobs::CronSchedule;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let schedule: CronSchedule = "*/15 9-17 * * MON-FRI".parse().unwrap();
+///
+/// // Thursday, 1 January 1970 00:00 UTC.
+/// let next = schedule.next_after(UNIX_EPOCH).unwrap();
+///
+/// assert_eq!(next, UNIX_EPOCH + Duration::from_secs(9 * 3600));
+/// ```
+///
+/// [cron expression]: https://man7.org/linux/man-pages/man5/crontab.5.html
+#[derive(Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    /// Returns the first time matching the schedule strictly after `time`, or `None` if
+    /// the schedule never matches.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+
+        let mut minute = secs.div_euclid(60) + 1;
+        let last_day = minute / (24 * 60) + MAX_SEARCH_DAYS;
+
+        loop {
+            let day = minute.div_euclid(24 * 60);
+
+            if day > last_day {
+                return None;
+            }
+
+            let (_, month, day_of_month) = civil_from_days(day);
+
+            if !has(self.months, month) {
+                minute = days_from_civil_next_month(day) * 24 * 60;
+                continue;
+            }
+
+            if !self.matches_day(day_of_month, weekday(day)) {
+                minute = (day + 1) * 24 * 60;
+                continue;
+            }
+
+            let minute_of_day = minute.rem_euclid(24 * 60);
+
+            if !has(self.hours, (minute_of_day / 60) as u32) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+
+            if !has(self.minutes, (minute_of_day % 60) as u32) {
+                minute += 1;
+                continue;
+            }
+
+            return Some(UNIX_EPOCH + Duration::from_secs(minute as u64 * 60));
+        }
+    }
+
+    fn matches_day(&self, day_of_month: u32, weekday: u32) -> bool {
+        let dom = has(self.days_of_month, day_of_month);
+        let dow = has(self.days_of_week, weekday);
+
+        match (self.days_of_month_restricted, self.days_of_week_restricted) {
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = BootstrapError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let expanded = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+
+        let fields: Vec<_> = expanded.split_whitespace().collect();
+
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            bail!(
+                "cron expression `{expr}` should have 5 fields, got {}",
+                fields.len()
+            );
+        };
+
+        let field = |name, value, min, max, names: &[&str]| {
+            parse_field(value, min, max, names)
+                .map_err(|e| anyhow!("invalid {name} field of cron expression `{expr}`: {e}"))
+        };
+
+        let mut schedule = Self {
+            expr: expr.trim().to_string(),
+            minutes: field("minute", minutes, 0, 59, &[])?,
+            hours: field("hour", hours, 0, 23, &[])?,
+            days_of_month: field("day of month", days_of_month, 1, 31, &[])?,
+            months: field("month", months, 1, 12, &MONTHS)?,
+            days_of_week: field("day of week", days_of_week, 0, 7, &WEEKDAYS)?,
+            days_of_month_restricted: !days_of_month.starts_with('*'),
+            days_of_week_restricted: !days_of_week.starts_with('*'),
+        };
+
+        // NOTE: both 0 and 7 are Sunday.
+        if has(schedule.days_of_week, 7) {
+            schedule.days_of_week |= 1;
+        }
+
+        Ok(schedule)
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr)
+    }
+}
+
+impl fmt::Debug for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CronSchedule").field(&self.expr).finish()
+    }
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses a field into a bit set of the matching values.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| {
+        let parsed = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            // NOTE: names of the months start from 1, names of the weekdays start from 0.
+            Some(i) => i as u32 + min,
+            None => s.parse().map_err(|_| format!("invalid value `{s}`"))?,
+        };
+
+        if parsed < min || parsed > max {
+            return Err(format!("value {parsed} is out of the {min}-{max} range"));
+        }
+
+        Ok(parsed)
+    };
+
+    let mut set = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step `{step}`"))?;
+
+                if step == 0 {
+                    return Err("step can't be 0".into());
+                }
+
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // NOTE: `a/n` is a shortcut for `a-<max>/n`.
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+
+                (value, value)
+            }
+        };
+
+        if start > end {
+            return Err(format!("range {start}-{end} is empty"));
+        }
+
+        for v in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << v;
+        }
+    }
+
+    Ok(set)
+}
+
+// NOTE: the conversions between days since the Unix epoch and the civil dates from
+// http://howardhinnant.github.io/date_algorithms.html.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+fn days_from_civil_next_month(days: i64) -> i64 {
+    let (year, month, _) = civil_from_days(days);
+
+    if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    }
+}
+
+/// Returns the day of the week, 0 is Sunday.
+fn weekday(days: i64) -> u32 {
+    // NOTE: 1 January 1970 was a Thursday.
+    (days + 4).rem_euclid(7) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i64, month: u32, day: u32, hour: u64, minute: u64) -> SystemTime {
+        let days = days_from_civil(year, month, day) as u64;
+
+        UNIX_EPOCH + Duration::from_secs(days * 86_400 + hour * 3600 + minute * 60)
+    }
+
+    fn next(expr: &str, after: SystemTime) -> Option<SystemTime> {
+        expr.parse::<CronSchedule>().unwrap().next_after(after)
+    }
+
+    #[test]
+    fn converts_civil_dates() {
+        for days in [-719_468, -1, 0, 59, 11_016, 19_782, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(weekday(19_782), 4);
+    }
+
+    #[test]
+    fn finds_next_matching_time() {
+        let now = at(2024, 2, 28, 23, 59) + Duration::from_secs(30);
+
+        assert_eq!(next("* * * * *", now), Some(at(2024, 2, 29, 0, 0)));
+        assert_eq!(
+            next("*/15 * * * *", at(2024, 2, 28, 10, 15)),
+            Some(at(2024, 2, 28, 10, 30))
+        );
+        assert_eq!(next("@daily", now), Some(at(2024, 2, 29, 0, 0)));
+        assert_eq!(next("@monthly", now), Some(at(2024, 3, 1, 0, 0)));
+        assert_eq!(next("0 12 29 2 *", now), Some(at(2024, 2, 29, 12, 0)));
+        assert_eq!(
+            next("0 12 29 2 *", at(2024, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 12, 0))
+        );
+        assert_eq!(next("30 9 * * mon-fri", now), Some(at(2024, 2, 29, 9, 30)));
+        assert_eq!(next("30 9 * * SAT,7", now), Some(at(2024, 3, 2, 9, 30)));
+        assert_eq!(next("0 0 30 2 *", now), None);
+    }
+
+    #[test]
+    fn matches_either_restricted_day() {
+        // NOTE: the 13th of the month or any Friday.
+        let schedule: CronSchedule = "0 0 13 * 5".parse().unwrap();
+
+        assert_eq!(
+            schedule.next_after(at(2024, 3, 1, 12, 0)),
+            Some(at(2024, 3, 8, 0, 0))
+        );
+        assert_eq!(
+            schedule.next_after(at(2024, 3, 8, 12, 0)),
+            Some(at(2024, 3, 13, 0, 0))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * FOO *",
+            "@often",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_err(), "{expr}");
+        }
+
+        assert_eq!(
+            "7 * * * x".parse::<CronSchedule>().unwrap_err().to_string(),
+            "invalid day of week field of cron expression `7 * * * x`: invalid value `x`"
+        );
+    }
+}
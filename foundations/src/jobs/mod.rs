@@ -0,0 +1,571 @@
+//! Jobs run on a schedule.
+//!
+//! [`JobScheduler`] runs named async jobs at the times matching the [cron expressions] from
+//! their [`JobSettings`], so the schedules of periodic maintenance tasks, e.g. cleanups or cache
+//! refreshes, can be tuned in the configuration of the service. The settings also specify what
+//! happens if a run is due while the previous one is still in progress, see [`OverlapPolicy`],
+//! and if the runs were missed, e.g. because the process was suspended or the wall clock jumped,
+//! see [`MissedRunPolicy`]. The runs missed while the service wasn't running are not tracked.
+//!
+//! With the `metrics` feature, the following metrics labeled with the job name are reported:
+//!
+//! - `<prefix>_foundations_jobs_runs_total` counter with the number of the finished runs,
+//!   additionally labeled with the `outcome` of the run: `success`, `failure` or `panic`;
+//! - `<prefix>_foundations_jobs_run_duration` histogram with the duration of the runs;
+//! - `<prefix>_foundations_jobs_running` gauge with the number of the runs in progress;
+//! - `<prefix>_foundations_jobs_skipped_runs_total` counter with the number of the runs skipped
+//!   because of the overlap with the previous run;
+//! - `<prefix>_foundations_jobs_missed_runs_total` counter with the number of the missed runs;
+//! - `<prefix>_foundations_jobs_last_success_timestamp_seconds` gauge with the Unix timestamp in
+//!   seconds of the last successful run.
+//!
+//! With the `logging` feature, the failed, skipped and missed runs are logged.
+//!
+//! # Examples
+//! ```
+//! use foundations::jobs::{JobScheduler, JobSettings};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> foundations::BootstrapResult<()> {
+//! let settings = JobSettings {
+//!     schedule: "*/10 * * * *".into(),
+//!     ..Default::default()
+//! };
+//!
+//! let mut scheduler = JobScheduler::new();
+//!
+//! scheduler.add("cleanup_sessions", &settings, || async {
+//!     // Remove the expired sessions...
+//!     Ok(())
+//! })?;
+//!
+//! let jobs = scheduler.start();
+//!
+//! // Run the service...
+//!
+//! jobs.stop();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [cron expressions]: CronSchedule
+
+mod cron;
+
+pub use self::cron::CronSchedule;
+
+use crate::{BootstrapResult, Result};
+use anyhow::Context;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, DurationHistogram, Gauge, HistogramBuilder};
+
+#[cfg(feature = "metrics")]
+use std::time::UNIX_EPOCH;
+
+// NOTE: cron schedules have a resolution of a minute, so the runs started within a minute of
+// their time are on time.
+const MISSED_RUN_TOLERANCE: Duration = Duration::from_secs(60);
+
+// NOTE: the wall clock can jump while the scheduler sleeps, so it's rechecked periodically.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+// NOTE: the missed runs are only counted up to this number, e.g. if the clock jumped years ahead.
+const MAX_COUNTED_RUNS: u64 = 100_000;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_jobs {
+    /// Number of the finished runs of the job, by outcome.
+    pub fn runs_total(job: &'static str, outcome: &'static str) -> Counter;
+
+    /// Duration of the runs of the job.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.01, 0.1, 1.0, 10.0, 60.0, 300.0, 900.0, 3600.0],
+    }]
+    pub fn run_duration(job: &'static str) -> DurationHistogram;
+
+    /// Number of the runs of the job in progress.
+    pub fn running(job: &'static str) -> Gauge;
+
+    /// Number of the runs skipped because the previous run was still in progress.
+    pub fn skipped_runs_total(job: &'static str) -> Counter;
+
+    /// Number of the runs missed, e.g. because the process was suspended.
+    pub fn missed_runs_total(job: &'static str) -> Counter;
+
+    /// Unix timestamp in seconds of the last successful run.
+    pub fn last_success_timestamp_seconds(job: &'static str) -> Gauge;
+}
+
+/// Settings of a job run by the [`JobScheduler`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct JobSettings {
+    /// Whether the job is run.
+    pub enabled: bool,
+
+    /// Cron expression with the schedule of the job in UTC, e.g. `*/15 * * * *` to run the job
+    /// every 15 minutes. See [`CronSchedule`] for the supported syntax.
+    pub schedule: String,
+
+    /// What to do if a run is due while the previous run is still in progress.
+    pub overlap_policy: OverlapPolicy,
+
+    /// What to do if runs were missed.
+    pub missed_run_policy: MissedRunPolicy,
+}
+
+impl Default for JobSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            schedule: "@hourly".into(),
+            overlap_policy: Default::default(),
+            missed_run_policy: Default::default(),
+        }
+    }
+}
+
+/// What to do if a run of a job is due while the previous run is still in progress.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[derive(Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Skip the run.
+    #[default]
+    Skip,
+    /// Start the run once the previous one finishes. At most one run is queued, the other
+    /// overlapping runs are skipped.
+    Queue,
+    /// Start the run concurrently with the previous one.
+    Concurrent,
+}
+
+/// What to do if runs of a job were missed, i.e. the scheduler couldn't start them within
+/// a minute of their time, e.g. because the process was suspended or the wall clock jumped
+/// ahead.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[derive(Copy, PartialEq, Eq)]
+pub enum MissedRunPolicy {
+    /// Skip the missed runs and wait for the next run.
+    #[default]
+    Skip,
+    /// Run the job once to catch up for all the missed runs.
+    RunOnce,
+}
+
+/// Runs the jobs on their schedule.
+///
+/// See the [module-level documentation] for more details.
+///
+/// [module-level documentation]: crate::jobs
+#[derive(Default)]
+pub struct JobScheduler {
+    jobs: Vec<Arc<Job>>,
+}
+
+impl JobScheduler {
+    /// Creates a new scheduler without jobs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a job with the given settings.
+    ///
+    /// `job` is called to start each run of the job. Returns an error if the schedule in
+    /// the settings is invalid, even if the job is disabled.
+    pub fn add<F, Fut>(
+        &mut self,
+        name: &'static str,
+        settings: &JobSettings,
+        job: F,
+    ) -> BootstrapResult<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let schedule = settings
+            .schedule
+            .parse()
+            .with_context(|| format!("invalid schedule of job `{name}`"))?;
+
+        if settings.enabled {
+            self.jobs.push(Arc::new(Job {
+                name,
+                schedule,
+                overlap_policy: settings.overlap_policy,
+                missed_run_policy: settings.missed_run_policy,
+                run: Box::new(move || job().boxed()),
+                lock: Default::default(),
+                queued: Default::default(),
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Starts running the jobs on their schedule.
+    ///
+    /// # Panics
+    /// If called outside of a Tokio runtime.
+    pub fn start(self) -> ScheduledJobs {
+        let tasks = self
+            .jobs
+            .into_iter()
+            .map(|job| tokio::spawn(job.run_schedule()))
+            .collect();
+
+        ScheduledJobs { tasks }
+    }
+}
+
+/// Jobs started with [`JobScheduler::start`].
+pub struct ScheduledJobs {
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl ScheduledJobs {
+    /// Stops starting new runs of the jobs. The runs in progress are not interrupted.
+    pub fn stop(self) {
+        for task in self.tasks {
+            task.abort();
+        }
+    }
+}
+
+type JobFn = Box<dyn Fn() -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+struct Job {
+    #[cfg_attr(not(any(feature = "logging", feature = "metrics")), allow(dead_code))]
+    name: &'static str,
+    schedule: CronSchedule,
+    overlap_policy: OverlapPolicy,
+    missed_run_policy: MissedRunPolicy,
+    run: JobFn,
+    // NOTE: held by the runs if the runs can't overlap.
+    lock: Arc<Mutex<()>>,
+    queued: AtomicBool,
+}
+
+impl Job {
+    async fn run_schedule(self: Arc<Self>) {
+        let mut next = self.schedule.next_after(SystemTime::now());
+
+        while let Some(at) = next {
+            sleep_until(at).await;
+
+            let now = SystemTime::now();
+            let due = due_runs(&self.schedule, at, now, self.missed_run_policy);
+
+            if due.missed > 0 {
+                self.report_missed(due.missed);
+            }
+
+            if due.run {
+                self.trigger();
+            }
+
+            next = self.schedule.next_after(now);
+        }
+    }
+
+    fn trigger(self: &Arc<Self>) {
+        let job = Arc::clone(self);
+
+        match self.overlap_policy {
+            OverlapPolicy::Concurrent => {
+                tokio::spawn(async move { job.run_once(None).await });
+            }
+            OverlapPolicy::Skip => match Arc::clone(&self.lock).try_lock_owned() {
+                Ok(guard) => {
+                    tokio::spawn(async move { job.run_once(Some(guard)).await });
+                }
+                Err(_) => self.report_skipped(),
+            },
+            OverlapPolicy::Queue => {
+                if self.queued.swap(true, Ordering::SeqCst) {
+                    self.report_skipped();
+                    return;
+                }
+
+                tokio::spawn(async move {
+                    let guard = Arc::clone(&job.lock).lock_owned().await;
+
+                    job.queued.store(false, Ordering::SeqCst);
+                    job.run_once(Some(guard)).await
+                });
+            }
+        }
+    }
+
+    async fn run_once(&self, _guard: Option<OwnedMutexGuard<()>>) -> RunOutcome {
+        #[cfg(feature = "metrics")]
+        foundations_jobs::running(self.name).inc();
+
+        let started_at = Instant::now();
+        let res = AssertUnwindSafe(async { (self.run)().await })
+            .catch_unwind()
+            .await;
+
+        let elapsed = started_at.elapsed();
+
+        let outcome = match res {
+            Ok(Ok(())) => RunOutcome::Success,
+            Ok(Err(e)) => {
+                #[cfg(feature = "logging")]
+                log::warn!("job run failed"; "job" => self.name, "error" => %e);
+
+                #[cfg(not(feature = "logging"))]
+                let _ = e;
+
+                RunOutcome::Failure
+            }
+            Err(_) => {
+                #[cfg(feature = "logging")]
+                log::error!("job run panicked"; "job" => self.name);
+
+                RunOutcome::Panic
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        {
+            foundations_jobs::running(self.name).dec();
+            foundations_jobs::runs_total(self.name, outcome.as_str()).inc();
+            foundations_jobs::run_duration(self.name).observe(elapsed);
+
+            if outcome == RunOutcome::Success {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+
+                foundations_jobs::last_success_timestamp_seconds(self.name).set(now.as_secs());
+            }
+        }
+
+        #[cfg(not(feature = "metrics"))]
+        let _ = elapsed;
+
+        outcome
+    }
+
+    fn report_skipped(&self) {
+        #[cfg(feature = "logging")]
+        log::warn!(
+            "job run skipped as the previous run is still in progress";
+            "job" => self.name
+        );
+
+        #[cfg(feature = "metrics")]
+        foundations_jobs::skipped_runs_total(self.name).inc();
+    }
+
+    fn report_missed(&self, missed: u64) {
+        #[cfg(feature = "logging")]
+        log::warn!(
+            "job runs missed";
+            "job" => self.name,
+            "missed" => missed,
+            "policy" => ?self.missed_run_policy
+        );
+
+        #[cfg(feature = "metrics")]
+        foundations_jobs::missed_runs_total(self.name).inc_by(missed);
+
+        #[cfg(not(any(feature = "logging", feature = "metrics")))]
+        let _ = missed;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RunOutcome {
+    Success,
+    Failure,
+    Panic,
+}
+
+impl RunOutcome {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn as_str(self) -> &'static str {
+        match self {
+            RunOutcome::Success => "success",
+            RunOutcome::Failure => "failure",
+            RunOutcome::Panic => "panic",
+        }
+    }
+}
+
+async fn sleep_until(at: SystemTime) {
+    while let Ok(remaining) = at.duration_since(SystemTime::now()) {
+        if remaining.is_zero() {
+            return;
+        }
+
+        tokio::time::sleep(remaining.min(MAX_SLEEP)).await;
+    }
+}
+
+/// Whether to start a run, and the number of the missed runs.
+#[derive(Debug, PartialEq, Eq)]
+struct DueRuns {
+    run: bool,
+    missed: u64,
+}
+
+/// Returns the runs due at `now`, starting with the run at `first`.
+fn due_runs(
+    schedule: &CronSchedule,
+    first: SystemTime,
+    now: SystemTime,
+    policy: MissedRunPolicy,
+) -> DueRuns {
+    let mut last = first;
+    let mut count = 1;
+
+    while count < MAX_COUNTED_RUNS {
+        match schedule.next_after(last) {
+            Some(next) if next <= now => {
+                last = next;
+                count += 1;
+            }
+            _ => break,
+        }
+    }
+
+    let on_time = now.duration_since(last).unwrap_or_default() <= MISSED_RUN_TOLERANCE;
+
+    if on_time {
+        DueRuns {
+            run: true,
+            missed: count - 1,
+        }
+    } else {
+        DueRuns {
+            run: policy == MissedRunPolicy::RunOnce,
+            missed: count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::UNIX_EPOCH;
+
+    fn job(overlap_policy: OverlapPolicy, runs: Arc<AtomicUsize>) -> Arc<Job> {
+        Arc::new(Job {
+            name: "test",
+            schedule: "* * * * *".parse().unwrap(),
+            overlap_policy,
+            missed_run_policy: MissedRunPolicy::Skip,
+            run: Box::new(move || {
+                let runs = Arc::clone(&runs);
+
+                async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+
+                    Ok(())
+                }
+                .boxed()
+            }),
+            lock: Default::default(),
+            queued: Default::default(),
+        })
+    }
+
+    #[test]
+    fn counts_missed_runs() {
+        let schedule: CronSchedule = "*/10 * * * *".parse().unwrap();
+        let first = UNIX_EPOCH + Duration::from_secs(600);
+        let mins = |m: u64| first + Duration::from_secs(m * 60);
+
+        assert_eq!(
+            due_runs(&schedule, first, mins(0), MissedRunPolicy::Skip),
+            DueRuns {
+                run: true,
+                missed: 0
+            }
+        );
+        assert_eq!(
+            due_runs(&schedule, first, mins(20), MissedRunPolicy::Skip),
+            DueRuns {
+                run: true,
+                missed: 2
+            }
+        );
+        assert_eq!(
+            due_runs(&schedule, first, mins(25), MissedRunPolicy::Skip),
+            DueRuns {
+                run: false,
+                missed: 3
+            }
+        );
+        assert_eq!(
+            due_runs(&schedule, first, mins(25), MissedRunPolicy::RunOnce),
+            DueRuns {
+                run: true,
+                missed: 3
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_overlap_policy() {
+        for (policy, expected_runs) in [
+            (OverlapPolicy::Skip, 1),
+            (OverlapPolicy::Queue, 2),
+            (OverlapPolicy::Concurrent, 3),
+        ] {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let job = job(policy, Arc::clone(&runs));
+
+            for _ in 0..3 {
+                job.trigger();
+                tokio::task::yield_now().await;
+            }
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            assert_eq!(runs.load(Ordering::SeqCst), expected_runs, "{policy:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_run_outcome() {
+        let job = Job {
+            run: Box::new(|| async { Err("disk full".into()) }.boxed()),
+            ..Arc::into_inner(job(OverlapPolicy::Skip, Default::default())).unwrap()
+        };
+
+        assert_eq!(job.run_once(None).await, RunOutcome::Failure);
+
+        let job = Job {
+            run: Box::new(|| async { panic!("boom") }.boxed()),
+            ..job
+        };
+
+        assert_eq!(job.run_once(None).await, RunOutcome::Panic);
+    }
+}
@@ -0,0 +1,652 @@
+//! A lightweight persistent key-value store for the operational state of a service.
+//!
+//! [`KvStore`] keeps small pieces of state that should survive restarts, such as rate limiting
+//! buckets, deduplication windows or restart counters, in a single file at the path configured
+//! with [`KvStoreSettings`]. The values can be of any type that is serializable with serde, and
+//! are stored as JSON.
+//!
+//! All the entries are kept in memory, so the reads don't touch the disk. Each modification is
+//! appended to the file as a JSON line, and is synced to the disk before the call returns if
+//! [`KvStoreSettings::sync_writes`] is set. Once the file is larger than
+//! [`KvStoreSettings::compaction_min_size`] and twice as large as its live entries, it's
+//! atomically replaced with a file containing only the live entries. The modifications don't fail
+//! if the compaction fails, the error is logged and the compaction is retried on the next
+//! modification. A record partially written on a crash is discarded when the store is opened.
+//!
+//! The store is not designed for large datasets or high write rates: all the modifications are
+//! serialized, and the whole file is read into memory when the store is opened.
+//!
+//! Unlike embedded databases such as [redb] or [sled], the store doesn't coordinate concurrent
+//! access to the file, so it can only be used by a single process at a time. [`KvStore::open`]
+//! takes an exclusive advisory lock (`flock` on Unix) on a `.lock` file next to the file of the
+//! store and returns an error if the store is already opened, including by the same process.
+//! The lock is released once the store and all its clones are dropped.
+//!
+//! [redb]: https://crates.io/crates/redb
+//! [sled]: https://crates.io/crates/sled
+//!
+//! With the `metrics` feature, the following metrics labeled with the store name are reported:
+//!
+//! - `<prefix>_foundations_kv_store_size_bytes` gauge with the size of the file;
+//! - `<prefix>_foundations_kv_store_keys` gauge with the number of the keys;
+//! - `<prefix>_foundations_kv_store_write_duration_seconds` histogram with the time it took to
+//!   write the modifications to the file;
+//! - `<prefix>_foundations_kv_store_compactions_total` counter with the number of the file
+//!   compactions.
+//!
+//! # Examples
+//! ```
+//! use foundations::kv_store::{KvStore, KvStoreSettings};
+//!
+//! # fn main() -> foundations::Result<()> {
+//! # let path = std::env::temp_dir().join(format!("kv-store-doc-{}", std::process::id()));
+//! # let _ = std::fs::remove_file(&path);
+//! let settings = KvStoreSettings {
+//!     path,
+//!     ..Default::default()
+//! };
+//!
+//! let store = KvStore::open("state", &settings)?;
+//! let restarts = store.update("restarts", |restarts: Option<u64>| restarts.unwrap_or(0) + 1)?;
+//!
+//! assert_eq!(restarts, 1);
+//! assert_eq!(store.get::<u64>("restarts")?, Some(1));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BootstrapResult, Result};
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, DurationHistogram, Gauge, HistogramBuilder};
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_kv_store {
+    /// Size of the file of the store.
+    pub fn size_bytes(store: &'static str) -> Gauge;
+
+    /// Number of the keys in the store.
+    pub fn keys(store: &'static str) -> Gauge;
+
+    /// Time it took to write the modifications to the file of the store.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0],
+    }]
+    pub fn write_duration(store: &'static str) -> DurationHistogram;
+
+    /// Number of the compactions of the file of the store.
+    pub fn compactions_total(store: &'static str) -> Counter;
+}
+
+/// Settings of a [`KvStore`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct KvStoreSettings {
+    /// Path of the file of the store. The file and its parent directories are created if they
+    /// don't exist.
+    pub path: PathBuf,
+
+    /// Sync each modification to the disk before returning, so it's not lost if the host
+    /// crashes. Otherwise, the modifications are only guaranteed to survive the crashes of
+    /// the process.
+    pub sync_writes: bool,
+
+    /// Minimum size of the file in bytes for it to be compacted.
+    pub compaction_min_size: u64,
+}
+
+impl Default for KvStoreSettings {
+    fn default() -> Self {
+        Self {
+            path: "/var/tmp/foundations/kv_store.jsonl".into(),
+            sync_writes: false,
+            compaction_min_size: 1024 * 1024,
+        }
+    }
+}
+
+// NOTE: the values along with the lengths of their records in the file.
+type Entries = HashMap<String, (Value, u64)>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Set { key: String, value: Value },
+    Remove { key: String },
+}
+
+/// A persistent key-value store.
+///
+/// See the [module-level documentation] for more details.
+///
+/// The store can be cloned to be shared, all the clones use the same file.
+///
+/// [module-level documentation]: crate::kv_store
+#[derive(Clone)]
+pub struct KvStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    path: PathBuf,
+    file: File,
+    // NOTE: the lock is held as long as the file is open.
+    _lock: File,
+    sync_writes: bool,
+    compaction_min_size: u64,
+    entries: Entries,
+    file_len: u64,
+    live_len: u64,
+}
+
+impl KvStore {
+    /// Opens the store with the given settings, reading its entries from the file.
+    ///
+    /// The `name` is used as a `store` label of the metrics. Returns an error if the file can't
+    /// be read, contains malformed records or the store is already opened, see the
+    /// [module-level documentation].
+    ///
+    /// [module-level documentation]: crate::kv_store
+    pub fn open(name: &'static str, settings: &KvStoreSettings) -> BootstrapResult<Self> {
+        let path = settings.path.clone();
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create the directory of the key-value store `{name}`")
+            })?;
+        }
+
+        let lock =
+            lock(&path).with_context(|| format!("failed to lock the key-value store `{name}`"))?;
+
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read the key-value store `{name}`"))
+            }
+        };
+
+        let (entries, valid_len, live_len) = read_records(&data)
+            .with_context(|| format!("failed to read the key-value store `{name}`"))?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open the key-value store `{name}`"))?;
+
+        // NOTE: the record is partially written if the process crashed while writing it.
+        if valid_len < data.len() as u64 {
+            file.set_len(valid_len)?;
+
+            #[cfg(feature = "logging")]
+            log::warn!(
+                "discarded a partially written record of the key-value store";
+                "store" => name,
+                "path" => path.display().to_string(),
+            );
+        }
+
+        let inner = Inner {
+            name,
+            path,
+            file,
+            _lock: lock,
+            sync_writes: settings.sync_writes,
+            compaction_min_size: settings.compaction_min_size,
+            entries,
+            file_len: valid_len,
+            live_len,
+        };
+
+        inner.report_metrics();
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    /// Returns the value of the key, or `None` if the key is not in the store.
+    ///
+    /// Returns an error if the stored value can't be deserialized as `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let inner = self.inner.lock();
+
+        match inner.entries.get(key) {
+            Some((value, _)) => Ok(Some(T::deserialize(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` if the key is in the store.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.lock().entries.contains_key(key)
+    }
+
+    /// Returns the keys of the store, in arbitrary order.
+    pub fn keys(&self) -> Vec<String> {
+        self.inner.lock().entries.keys().cloned().collect()
+    }
+
+    /// Returns the number of the keys in the store.
+    pub fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    /// Returns `true` if the store has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sets the value of the key.
+    ///
+    /// The store is not modified if the value can't be written to the file.
+    pub fn set<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+
+        self.inner.lock().set(key, value)
+    }
+
+    /// Atomically updates the value of the key with the value returned by `f`, and returns it.
+    ///
+    /// `f` is called with the current value of the key, or `None` if the key is not in the store.
+    /// The other modifications of the store wait for the update, so `f` should be fast.
+    pub fn update<T, F>(&self, key: &str, f: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> T,
+    {
+        let mut inner = self.inner.lock();
+
+        let current = match inner.entries.get(key) {
+            Some((value, _)) => Some(T::deserialize(value)?),
+            None => None,
+        };
+
+        let updated = f(current);
+
+        inner.set(key, serde_json::to_value(&updated)?)?;
+
+        Ok(updated)
+    }
+
+    /// Removes the key from the store, returning `true` if it was in the store.
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        let mut inner = self.inner.lock();
+
+        if !inner.entries.contains_key(key) {
+            return Ok(false);
+        }
+
+        inner.append(&Record::Remove { key: key.into() })?;
+
+        if let Some((_, len)) = inner.entries.remove(key) {
+            inner.live_len -= len;
+        }
+
+        inner.maybe_compact();
+        inner.report_metrics();
+
+        Ok(true)
+    }
+
+    /// Replaces the file of the store with a file containing only the live entries.
+    ///
+    /// The file is compacted automatically, so the method only needs to be called to reclaim
+    /// the disk space immediately, e.g. after removing many keys.
+    pub fn compact(&self) -> Result<()> {
+        let mut inner = self.inner.lock();
+
+        inner.compact()?;
+        inner.report_metrics();
+
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn set(&mut self, key: &str, value: Value) -> Result<()> {
+        let record = Record::Set {
+            key: key.into(),
+            value,
+        };
+
+        let len = self.append(&record)?;
+
+        let Record::Set { key, value } = record else {
+            unreachable!("the record should be a `set` record");
+        };
+
+        if let Some((_, prev_len)) = self.entries.insert(key, (value, len)) {
+            self.live_len -= prev_len;
+        }
+
+        self.live_len += len;
+
+        self.maybe_compact();
+        self.report_metrics();
+
+        Ok(())
+    }
+
+    // NOTE: returns the length of the written record.
+    fn append(&mut self, record: &Record) -> Result<u64> {
+        let mut line = serde_json::to_vec(record)?;
+
+        line.push(b'\n');
+
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        let res = self
+            .file
+            .write_all(&line)
+            .and_then(|_| match self.sync_writes {
+                true => self.file.sync_data(),
+                false => Ok(()),
+            });
+
+        #[cfg(feature = "metrics")]
+        foundations_kv_store::write_duration(self.name).observe(start.elapsed());
+
+        if let Err(e) = res {
+            // NOTE: discard the partially written record, so the next records are readable.
+            let _ = self.file.set_len(self.file_len);
+
+            return Err(e.into());
+        }
+
+        self.file_len += line.len() as u64;
+
+        Ok(line.len() as u64)
+    }
+
+    // NOTE: the modification is already written, so a failed compaction is only logged and
+    // retried on the next modification.
+    fn maybe_compact(&mut self) {
+        if self.file_len < self.compaction_min_size || self.file_len < self.live_len * 2 {
+            return;
+        }
+
+        let res = self.compact();
+
+        #[cfg(feature = "logging")]
+        if let Err(e) = res {
+            log::error!(
+                "failed to compact the key-value store";
+                "store" => self.name,
+                "path" => self.path.display().to_string(),
+                "error" => e.to_string(),
+            );
+        }
+
+        #[cfg(not(feature = "logging"))]
+        let _ = res;
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        let tmp_path = tmp_path(&self.path);
+        let mut data = vec![];
+
+        for (key, (value, len)) in self.entries.iter_mut() {
+            let start = data.len();
+
+            serde_json::to_writer(
+                &mut data,
+                &Record::Set {
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+            )?;
+
+            data.push(b'\n');
+            *len = (data.len() - start) as u64;
+        }
+
+        let mut tmp_file = File::create(&tmp_path)?;
+
+        tmp_file.write_all(&data)?;
+        tmp_file.sync_all()?;
+
+        fs::rename(&tmp_path, &self.path)?;
+
+        // NOTE: sync the directory, so the rename is not lost if the host crashes.
+        sync_parent_dir(&self.path)?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.file_len = data.len() as u64;
+        self.live_len = data.len() as u64;
+
+        #[cfg(feature = "metrics")]
+        foundations_kv_store::compactions_total(self.name).inc();
+
+        Ok(())
+    }
+
+    fn report_metrics(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            foundations_kv_store::size_bytes(self.name).set(self.file_len);
+            foundations_kv_store::keys(self.name).set(self.entries.len() as u64);
+        }
+    }
+}
+
+// NOTE: returns the entries with the lengths of their records, the length of the data without
+// the trailing partially written record, and the total length of the records of the entries.
+fn read_records(data: &[u8]) -> BootstrapResult<(Entries, u64, u64)> {
+    let mut entries = HashMap::new();
+    let mut valid_len = 0;
+    let mut live_len = 0;
+
+    for (i, line) in data.split_inclusive(|b| *b == b'\n').enumerate() {
+        if !line.ends_with(b"\n") {
+            break;
+        }
+
+        let record = serde_json::from_slice(line)
+            .with_context(|| format!("malformed record at line {}", i + 1))?;
+
+        let (key, entry) = match record {
+            Record::Set { key, value } => (key, Some((value, line.len() as u64))),
+            Record::Remove { key } => (key, None),
+        };
+
+        let prev = match entry {
+            Some(entry) => {
+                live_len += entry.1;
+                entries.insert(key, entry)
+            }
+            None => entries.remove(&key),
+        };
+
+        if let Some((_, prev_len)) = prev {
+            live_len -= prev_len;
+        }
+
+        valid_len += line.len() as u64;
+    }
+
+    Ok((entries, valid_len, live_len))
+}
+
+fn lock(path: &Path) -> BootstrapResult<File> {
+    let mut lock_path = path.as_os_str().to_owned();
+
+    lock_path.push(".lock");
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)?;
+
+    match lock.try_lock() {
+        Ok(()) => Ok(lock),
+        Err(TryLockError::WouldBlock) => Err(anyhow::anyhow!("the store is already opened")),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)?.sync_all()
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = path.as_os_str().to_owned();
+
+    tmp_path.push(".tmp");
+
+    tmp_path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(name: &str) -> KvStoreSettings {
+        let path = std::env::temp_dir().join(format!(
+            "foundations-kv-store-{name}-{}.jsonl",
+            std::process::id()
+        ));
+
+        let _ = fs::remove_file(&path);
+
+        KvStoreSettings {
+            path,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn restores_entries() {
+        let settings = settings("restore");
+        let store = KvStore::open("restore", &settings).unwrap();
+
+        store.set("bucket", &[1, 2, 3]).unwrap();
+        store.set("window", "5m").unwrap();
+        store.set("nothing", &()).unwrap();
+        store
+            .update("restarts", |n: Option<u64>| n.unwrap_or(0) + 1)
+            .unwrap();
+        store
+            .update("restarts", |n: Option<u64>| n.unwrap_or(0) + 1)
+            .unwrap();
+
+        assert!(store.remove("window").unwrap());
+        assert!(!store.remove("window").unwrap());
+
+        drop(store);
+
+        // NOTE: simulate a crash in the middle of a write.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&settings.path)
+            .unwrap();
+
+        file.write_all(br#"{"op":"set","key":"torn","#).unwrap();
+
+        let store = KvStore::open("restore", &settings).unwrap();
+
+        assert_eq!(
+            store.get::<Vec<u32>>("bucket").unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(store.get::<u64>("restarts").unwrap(), Some(2));
+        assert_eq!(store.get::<()>("nothing").unwrap(), Some(()));
+        assert_eq!(store.get::<String>("window").unwrap(), None);
+        assert!(!store.contains_key("torn"));
+        assert!(store.get::<String>("bucket").is_err());
+        assert_eq!(store.len(), 3);
+
+        store.set("after_crash", &true).unwrap();
+        drop(store);
+
+        let store = KvStore::open("restore", &settings).unwrap();
+
+        assert_eq!(store.get::<bool>("after_crash").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn locks_store() {
+        let settings = settings("lock");
+        let store = KvStore::open("lock", &settings).unwrap();
+        let clone = store.clone();
+
+        assert!(KvStore::open("lock", &settings).is_err());
+
+        drop(store);
+
+        assert!(KvStore::open("lock", &settings).is_err());
+
+        drop(clone);
+
+        assert!(KvStore::open("lock", &settings).is_ok());
+    }
+
+    #[test]
+    fn compacts_file() {
+        let settings = KvStoreSettings {
+            compaction_min_size: 256,
+            ..settings("compaction")
+        };
+
+        let store = KvStore::open("compaction", &settings).unwrap();
+
+        for i in 0..100 {
+            store.set("counter", &i).unwrap();
+        }
+
+        let file_len = fs::metadata(&settings.path).unwrap().len();
+
+        assert!(file_len < 256 * 2, "file is not compacted: {file_len}");
+
+        store.set("other", "value").unwrap();
+        store.remove("other").unwrap();
+        store.compact().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&settings.path).unwrap(),
+            "{\"op\":\"set\",\"key\":\"counter\",\"value\":99}\n"
+        );
+
+        drop(store);
+
+        let store = KvStore::open("compaction", &settings).unwrap();
+
+        assert_eq!(store.get::<u32>("counter").unwrap(), Some(99));
+        assert_eq!(store.keys(), ["counter"]);
+    }
+}
@@ -11,6 +11,10 @@
 //! * security features, such as [seccomp]-based syscall sandboxing
 //! * service configuration with documentation
 //! * CLI helper that takes care of the configuration loading
+//! * [tower] middleware bundle
+//! * graceful shutdown of HTTP servers and ordered shutdown of the service components
+//! * fault injection for resilience testing
+//! * dual-stack friendly connection establishment for clients
 //!
 //! then Foundations is a tool of choice for you.
 //!
@@ -20,9 +24,10 @@
 //! Foundations can take of all aspects of service bootstrapping, but also can be used as a component
 //! library in a modular fashion by enabling or disabling [Cargo features]:
 //!
-//! - **default**: All features are enabled by default.
+//! - **default**: The features of **platform-common-default** and **security**.
 //! - **platform-common-default**: The same as **default**, but excludes platform-specific features,
-//! such as **security**.
+//! such as **security**. Enables **settings**, **jemalloc**, **telemetry**, **cli** and
+//! **testing** features, the other features listed below are opt-in.
 //! - **server-client-common-default**: A subset of features that can be used both on server and client sides.
 //!   Useful for libraries that can be used either way.
 //! - **settings**: Enables serializable documented settings functionality.
@@ -40,10 +45,44 @@
 //!  **jemalloc** feature.
 //! - **cli**: Enables command line interface (CLI) functionality. Implicitly enabled **settings**
 //! feature.
+//! - **tower**: Enables [tower] middleware bundle.
+//! - **http-server**: Enables graceful shutdown helpers for HTTP servers.
+//! - **tls-telemetry**: Enables TLS connection telemetry helpers. Requires **logging** or
+//! **tracing** feature to be enabled.
+//! - **fault-injection**: Enables fault injection for resilience testing.
+//! - **test-allocator**: Replaces the global memory allocator with the one that allows testing of
+//! the allocation failures handling. Should be enabled only for tests.
+//! - **diagnostics**: Enables diagnostics bundles served by the telemetry server. Implicitly
+//! enables **telemetry-server** feature and requires **logging**, **metrics** or **tracing**
+//! feature to be enabled.
+//! - **trace-archive**: Enables the local archive of the finished tracing spans in
+//! zstd-compressed files. Implicitly enables **tracing** feature.
+//! - **otlp-metrics**: Enables the exporter of the metrics to an OpenTelemetry collector over
+//! OTLP. Implicitly enables **metrics** feature. TLS is not supported, so the collector must
+//! be reachable over plain-text `http://`, e.g. a collector agent running alongside the service.
+//! - **metrics-push**: Enables pushing of the metrics to a Prometheus Pushgateway for
+//! short-lived jobs. Implicitly enables **metrics** feature. TLS is not supported, so
+//! the Pushgateway must be reachable over plain-text `http://`.
+//! - **net**: Enables networking helpers for clients, such as Happy Eyeballs connection
+//! establishment.
+//! - **shutdown**: Enables ordered shutdown of the service components with per-stage timeouts.
+//! - **blocking**: Enables the bounded and instrumented use of the Tokio blocking thread pool.
+//! - **jobs**: Enables jobs run on cron schedules with overlap and missed run policies.
+//! - **workers**: Enables pools of async workers processing the items of a shared queue.
+//! - **ids**: Enables generation of time-sortable ULID and UUIDv7 identifiers.
+//! - **listeners**: Enables listeners declared in the settings, bound before the syscall
+//! sandboxing and instrumented with the accept telemetry.
+//! - **kv-store**: Enables the persistent key-value store for the operational state, such as
+//! rate limiting buckets and restart counters.
+//! - **compression**: Enables the gzip, deflate and zstd compression streams with metrics, that
+//! are shared by the telemetry components.
+//! - **cpu-affinity**: Enables pinning of the worker threads to CPU sets and NUMA nodes. Linux
+//! only.
 //!
 //! [Cargo features]: https://doc.rust-lang.org/stable/cargo/reference/features.html#the-features-section
 //! [seccomp]: https://en.wikipedia.org/wiki/Seccomp
 //! [jemalloc]: https://github.com/jemalloc/jemalloc
+//! [tower]: https://docs.rs/tower
 //! [examples]: https://github.com/cloudflare/foundations/tree/main/examples
 
 #![warn(missing_docs)]
@@ -65,6 +104,48 @@ pub mod settings;
 ))]
 pub mod telemetry;
 
+#[cfg(feature = "tower")]
+pub mod middleware;
+
+#[cfg(feature = "http-server")]
+pub mod http_server;
+
+#[cfg(feature = "fault-injection")]
+pub mod fault_injection;
+
+#[cfg(feature = "net")]
+pub mod net;
+
+#[cfg(feature = "shutdown")]
+pub mod shutdown;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+#[cfg(feature = "jobs")]
+pub mod jobs;
+
+#[cfg(feature = "workers")]
+pub mod workers;
+
+#[cfg(feature = "ids")]
+pub mod ids;
+
+#[cfg(feature = "listeners")]
+pub mod listeners;
+
+#[cfg(feature = "kv-store")]
+pub mod kv_store;
+
+#[cfg(feature = "compression")]
+pub mod compression;
+
+#[cfg(feature = "test-allocator")]
+pub mod test_allocator;
+
+#[cfg(all(feature = "cpu-affinity", target_os = "linux"))]
+pub mod cpu_affinity;
+
 #[cfg(all(
     feature = "security",
     target_os = "linux",
@@ -103,10 +184,22 @@ pub mod reexports_for_macros {
 /// be embedded in your binary.
 ///
 /// [jemalloc]: https://github.com/jemalloc/jemalloc
-#[cfg(feature = "jemalloc")]
+#[cfg(all(feature = "jemalloc", not(feature = "test-allocator")))]
 #[global_allocator]
 pub static JEMALLOC_MEMORY_ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+/// Global memory allocator used with the **test-allocator** feature.
+///
+/// The allocator is backed by [jemalloc] if **jemalloc** feature is enabled and by the system
+/// allocator otherwise. See [`test_allocator`] module for more details.
+///
+/// [jemalloc]: https://github.com/jemalloc/jemalloc
+#[cfg(feature = "test-allocator")]
+#[global_allocator]
+pub static TEST_MEMORY_ALLOCATOR: test_allocator::TestAllocator<
+    test_allocator::UnderlyingAllocator,
+> = test_allocator::TestAllocator::new(test_allocator::UNDERLYING_ALLOCATOR);
+
 /// Error that can be returned on a service initialisation.
 ///
 /// This is an alias for [`anyhow::Error`]. On service bootstrap all such errors can be
@@ -140,6 +233,13 @@ pub struct ServiceInfo {
     /// The version of the service.
     pub version: &'static str,
 
+    /// The git commit SHA the service is built from, or an empty string if unknown.
+    ///
+    /// [`service_info`] takes it from the `GIT_SHA` environment variable at compile time, which
+    /// can be set by the build script of the service with
+    /// `println!("cargo:rustc-env=GIT_SHA={sha}")`.
+    pub git_sha: &'static str,
+
     /// Service author.
     pub author: &'static str,
 
@@ -150,7 +250,8 @@ pub struct ServiceInfo {
 /// Creates [`ServiceInfo`] from the information in `Cargo.toml` manifest of the service.
 ///
 /// [`ServiceInfo::name_in_metrics`] is the same as the package name, with hypens (`-`) replaced
-/// by underscores (`_`).
+/// by underscores (`_`). [`ServiceInfo::git_sha`] is taken from the `GIT_SHA` environment variable,
+/// if it's set at compile time.
 #[macro_export]
 macro_rules! service_info {
     () => {
@@ -158,6 +259,10 @@ macro_rules! service_info {
             name: env!("CARGO_PKG_NAME"),
             name_in_metrics: env!("CARGO_PKG_NAME").replace("-", "_"),
             version: env!("CARGO_PKG_VERSION"),
+            git_sha: match option_env!("GIT_SHA") {
+                Some(sha) => sha,
+                None => "",
+            },
             author: env!("CARGO_PKG_AUTHORS"),
             description: env!("CARGO_PKG_DESCRIPTION"),
         }
@@ -0,0 +1,477 @@
+//! Listeners declared in the settings.
+//!
+//! [`ListenersSettings`] declares the named TCP listeners of the service, with their addresses
+//! and whether they expect TLS or [PROXY protocol] headers. [`Listeners::bind`] binds all of
+//! them at once and the service then [takes] the listeners by name, so the listening addresses
+//! can be changed in the configuration without touching the code.
+//!
+//! The listeners should be bound before the syscall sandboxing is enabled, as binding requires
+//! syscalls that the sandboxed services would rather not allow. For the same reason, the TLS
+//! certificates and keys are read when the listeners are bound. Foundations doesn't terminate
+//! TLS itself: the service passes the [`TlsCredentials`] of the listener to the TLS library of
+//! its choice.
+//!
+//! With the `metrics` feature, the following metrics labeled with the listener name are
+//! reported:
+//!
+//! - `<prefix>_foundations_listeners_accepted_total` counter with the number of the accepted
+//!   connections;
+//! - `<prefix>_foundations_listeners_accept_errors_total` counter with the number of the failed
+//!   accepts;
+//! - `<prefix>_foundations_listeners_proxy_protocol_errors_total` counter with the number of the
+//!   connections dropped because of the missing or invalid PROXY protocol header.
+//!
+//! # Examples
+//! ```
+//! use foundations::listeners::{ListenerSettings, Listeners, ListenersSettings};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> foundations::BootstrapResult<()> {
+//! let settings = ListenersSettings {
+//!     listeners: vec![ListenerSettings {
+//!         name: "public".into(),
+//!         ..Default::default()
+//!     }],
+//! };
+//!
+//! // Bind the listeners before enabling the syscall sandboxing...
+//! let mut listeners = Listeners::bind(&settings)?;
+//! let public = listeners.take("public")?;
+//!
+//! tokio::spawn(async move {
+//!     while let Ok(incoming) = public.accept().await {
+//!         tokio::spawn(async move {
+//!             let Ok(conn) = incoming.into_connection().await else {
+//!                 return;
+//!             };
+//!
+//!             // Serve the connection...
+//!         });
+//!     }
+//! });
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+//! [takes]: Listeners::take
+
+mod proxy_protocol;
+mod settings;
+
+pub use self::settings::{ListenerSettings, ListenerTlsSettings, ListenersSettings};
+
+use crate::BootstrapResult;
+use anyhow::{bail, Context};
+use once_cell::sync::OnceCell;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::Counter;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_listeners {
+    /// Number of the connections accepted by the listener.
+    pub fn accepted_total(listener: &Arc<String>) -> Counter;
+
+    /// Number of the failed accepts of the listener.
+    pub fn accept_errors_total(listener: &Arc<String>) -> Counter;
+
+    /// Number of the connections dropped because of the missing or invalid PROXY protocol
+    /// header.
+    pub fn proxy_protocol_errors_total(listener: &Arc<String>) -> Counter;
+}
+
+/// Listeners bound from the [`ListenersSettings`].
+#[derive(Debug)]
+pub struct Listeners {
+    listeners: Vec<(String, Option<Listener>)>,
+}
+
+impl Listeners {
+    /// Binds all the listeners declared in the settings.
+    ///
+    /// Fails if the names of the listeners are not unique, if any of them can't be bound or if
+    /// their TLS certificates or keys can't be read.
+    pub fn bind(settings: &ListenersSettings) -> BootstrapResult<Self> {
+        let mut names = HashSet::new();
+        let mut listeners = Vec::with_capacity(settings.listeners.len());
+
+        for listener in &settings.listeners {
+            if !names.insert(&listener.name) {
+                bail!("listener `{}` is declared more than once", listener.name);
+            }
+
+            let bound = Listener::bind(listener)
+                .with_context(|| format!("failed to bind listener `{}`", listener.name))?;
+
+            listeners.push((listener.name.clone(), Some(bound)));
+        }
+
+        Ok(Self { listeners })
+    }
+
+    /// Takes the listener with the given name.
+    ///
+    /// Fails if the listener is not declared in the settings or has already been taken.
+    pub fn take(&mut self, name: &str) -> BootstrapResult<Listener> {
+        let Some((_, listener)) = self.listeners.iter_mut().find(|(n, _)| n == name) else {
+            bail!("listener `{name}` is not declared in the settings");
+        };
+
+        listener
+            .take()
+            .with_context(|| format!("listener `{name}` has already been taken"))
+    }
+}
+
+/// A bound listener, see [`Listeners::take`].
+pub struct Listener {
+    name: Arc<String>,
+    std: std::net::TcpListener,
+    tokio: OnceCell<TcpListener>,
+    local_addr: SocketAddr,
+    tls: Option<TlsCredentials>,
+    proxy_protocol_timeout: Option<Duration>,
+}
+
+impl Listener {
+    fn bind(settings: &ListenerSettings) -> BootstrapResult<Self> {
+        let addr = settings.addr;
+
+        #[cfg(feature = "settings")]
+        let addr = SocketAddr::from(addr);
+
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(settings.backlog.try_into().unwrap_or(i32::MAX))?;
+
+        let std: std::net::TcpListener = socket.into();
+        let local_addr = std.local_addr()?;
+
+        let tls = settings
+            .tls
+            .as_ref()
+            .map(|tls| TlsCredentials::read(&tls.cert_path, &tls.key_path))
+            .transpose()?;
+
+        let proxy_protocol_timeout = settings
+            .proxy_protocol
+            .then(|| Duration::from_millis(settings.proxy_protocol_timeout_ms));
+
+        Ok(Self {
+            name: Arc::new(settings.name.clone()),
+            std,
+            tokio: OnceCell::new(),
+            local_addr,
+            tls,
+            proxy_protocol_timeout,
+        })
+    }
+
+    /// Name of the listener.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Address the listener is bound to, e.g. to find out the port if the listener is bound to
+    /// port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// TLS credentials of the listener, if it's configured with TLS.
+    pub fn tls(&self) -> Option<&TlsCredentials> {
+        self.tls.as_ref()
+    }
+
+    /// Accepts a new connection.
+    ///
+    /// The errors caused by the peer, e.g. if the connection is reset before it's accepted, are
+    /// counted and skipped. The other errors, e.g. if the process runs out of file descriptors,
+    /// are returned.
+    ///
+    /// Must be called in the context of a Tokio runtime.
+    pub async fn accept(&self) -> io::Result<Incoming> {
+        let listener = self
+            .tokio
+            .get_or_try_init(|| TcpListener::from_std(self.std.try_clone()?))?;
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer_addr)) => {
+                    #[cfg(feature = "metrics")]
+                    foundations_listeners::accepted_total(&self.name).inc();
+
+                    return Ok(Incoming {
+                        stream,
+                        peer_addr,
+                        local_addr: self.local_addr,
+                        listener: Arc::clone(&self.name),
+                        proxy_protocol_timeout: self.proxy_protocol_timeout,
+                    });
+                }
+                Err(e) => {
+                    #[cfg(feature = "metrics")]
+                    foundations_listeners::accept_errors_total(&self.name).inc();
+
+                    if !is_peer_error(&e) {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Listener")
+            .field("name", &self.name)
+            .field("local_addr", &self.local_addr)
+            .field("tls", &self.tls)
+            .field("proxy_protocol_timeout", &self.proxy_protocol_timeout)
+            .finish()
+    }
+}
+
+fn is_peer_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// PEM-encoded TLS certificate chain and private key of a listener, see
+/// [`ListenerSettings::tls`].
+#[derive(Clone)]
+pub struct TlsCredentials {
+    cert_chain: Vec<u8>,
+    private_key: Vec<u8>,
+}
+
+impl TlsCredentials {
+    fn read(cert_path: &Path, key_path: &Path) -> BootstrapResult<Self> {
+        let read = |path: &Path, what| -> BootstrapResult<Vec<u8>> {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("failed to read TLS {what} `{}`", path.display()))?;
+
+            if !pem.windows(11).any(|w| w == b"-----BEGIN ") {
+                bail!("TLS {what} `{}` is not PEM-encoded", path.display());
+            }
+
+            Ok(pem)
+        };
+
+        Ok(Self {
+            cert_chain: read(cert_path, "certificate chain")?,
+            private_key: read(key_path, "private key")?,
+        })
+    }
+
+    /// PEM-encoded certificate chain.
+    pub fn cert_chain_pem(&self) -> &[u8] {
+        &self.cert_chain
+    }
+
+    /// PEM-encoded private key.
+    pub fn private_key_pem(&self) -> &[u8] {
+        &self.private_key
+    }
+}
+
+impl fmt::Debug for TlsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsCredentials")
+            .field(
+                "cert_chain",
+                &format_args!("{} bytes", self.cert_chain.len()),
+            )
+            .field("private_key", &format_args!("<redacted>"))
+            .finish()
+    }
+}
+
+/// A connection accepted by a [`Listener`].
+///
+/// The PROXY protocol header, if the listener expects it, is only read by
+/// [`Incoming::into_connection`], so a slow client doesn't stall the accept loop.
+#[derive(Debug)]
+pub struct Incoming {
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    local_addr: SocketAddr,
+    listener: Arc<String>,
+    proxy_protocol_timeout: Option<Duration>,
+}
+
+impl Incoming {
+    /// Address of the peer of the TCP connection, which is the proxy if the listener expects the
+    /// PROXY protocol.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Reads the PROXY protocol header if the listener expects it and returns the established
+    /// connection.
+    ///
+    /// Fails if the header is missing or invalid, or isn't received within
+    /// [`ListenerSettings::proxy_protocol_timeout_ms`].
+    pub async fn into_connection(mut self) -> io::Result<Connection> {
+        let Some(timeout) = self.proxy_protocol_timeout else {
+            return Ok(Connection {
+                stream: self.stream,
+                peer_addr: self.peer_addr,
+                local_addr: self.local_addr,
+            });
+        };
+
+        let header = tokio::time::timeout(timeout, proxy_protocol::read_header(&mut self.stream))
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out reading PROXY protocol header",
+                ))
+            });
+
+        match header {
+            Ok(addrs) => {
+                let (peer_addr, local_addr) = addrs.unwrap_or((self.peer_addr, self.local_addr));
+
+                Ok(Connection {
+                    stream: self.stream,
+                    peer_addr,
+                    local_addr,
+                })
+            }
+            Err(e) => {
+                #[cfg(feature = "metrics")]
+                foundations_listeners::proxy_protocol_errors_total(&self.listener).inc();
+
+                #[cfg(not(feature = "metrics"))]
+                let _ = self.listener;
+
+                Err(e)
+            }
+        }
+    }
+}
+
+/// An established connection, see [`Incoming::into_connection`].
+#[derive(Debug)]
+pub struct Connection {
+    /// The TCP stream of the connection. If the listener expects the PROXY protocol, the header
+    /// has already been read from the stream.
+    pub stream: TcpStream,
+
+    /// Address of the client. If the listener expects the PROXY protocol, it's the source
+    /// address from the header.
+    pub peer_addr: SocketAddr,
+
+    /// Address the client connected to. If the listener expects the PROXY protocol, it's the
+    /// destination address from the header.
+    pub local_addr: SocketAddr,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn settings(name: &str, proxy_protocol: bool) -> ListenerSettings {
+        ListenerSettings {
+            name: name.into(),
+            proxy_protocol,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_proxied_connections() {
+        let mut listeners = Listeners::bind(&ListenersSettings {
+            listeners: vec![settings("proxied", true)],
+        })
+        .unwrap();
+
+        let listener = listeners.take("proxied").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr()).await.unwrap();
+
+        client
+            .write_all(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nhello")
+            .await
+            .unwrap();
+
+        let mut conn = listener
+            .accept()
+            .await
+            .unwrap()
+            .into_connection()
+            .await
+            .unwrap();
+        let mut buf = [0; 5];
+
+        assert_eq!(conn.peer_addr, "192.0.2.1:56324".parse().unwrap());
+        assert_eq!(conn.local_addr, "198.51.100.1:443".parse().unwrap());
+
+        conn.stream.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(&buf, b"hello");
+
+        let mut client = TcpStream::connect(listener.local_addr()).await.unwrap();
+
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+        assert!(listener
+            .accept()
+            .await
+            .unwrap()
+            .into_connection()
+            .await
+            .is_err());
+
+        #[cfg(feature = "metrics")]
+        {
+            assert_eq!(
+                foundations_listeners::accepted_total(&listener.name).get(),
+                2
+            );
+            assert_eq!(
+                foundations_listeners::proxy_protocol_errors_total(&listener.name).get(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn takes_declared_listeners_once() {
+        let duplicate = Listeners::bind(&ListenersSettings {
+            listeners: vec![settings("a", false), settings("a", false)],
+        });
+
+        assert!(duplicate.is_err());
+
+        let mut listeners = Listeners::bind(&ListenersSettings {
+            listeners: vec![settings("a", false), settings("b", false)],
+        })
+        .unwrap();
+
+        assert_eq!(listeners.take("b").unwrap().name(), "b");
+        assert!(listeners.take("b").is_err());
+        assert!(listeners.take("c").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Parsing of the [PROXY protocol] headers.
+//!
+//! [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+// NOTE: maximum length of a version 1 header, including the `\r\n`.
+const V1_MAX_LEN: usize = 107;
+
+/// Reads the header from the beginning of the stream.
+///
+/// Returns the source and destination addresses of the proxied connection, or `None` if
+/// the proxy doesn't provide them, e.g. for its own health checks.
+pub(super) async fn read_header(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> io::Result<Option<(SocketAddr, SocketAddr)>> {
+    let mut start = [0; 6];
+
+    stream.read_exact(&mut start).await?;
+
+    if &start == b"PROXY " {
+        return read_v1(stream).await;
+    }
+
+    if start[..] != V2_SIGNATURE[..6] {
+        return Err(invalid("missing PROXY protocol header"));
+    }
+
+    let mut rest = [0; 10];
+
+    stream.read_exact(&mut rest).await?;
+
+    if rest[..6] != V2_SIGNATURE[6..] {
+        return Err(invalid("invalid PROXY protocol v2 signature"));
+    }
+
+    let [version_command, family, len_hi, len_lo] = [rest[6], rest[7], rest[8], rest[9]];
+    let mut addrs = vec![0; u16::from_be_bytes([len_hi, len_lo]) as usize];
+
+    stream.read_exact(&mut addrs).await?;
+
+    match version_command {
+        // NOTE: the `LOCAL` command, the connection is established by the proxy itself.
+        0x20 => Ok(None),
+        0x21 => parse_v2_addrs(family, &addrs),
+        _ => Err(invalid("unsupported PROXY protocol v2 version or command")),
+    }
+}
+
+async fn read_v1(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> io::Result<Option<(SocketAddr, SocketAddr)>> {
+    let mut line = Vec::with_capacity(V1_MAX_LEN);
+
+    // NOTE: the header is read byte by byte, so the data that follows it stays in the stream.
+    while !line.ends_with(b"\r\n") {
+        if line.len() + "PROXY ".len() >= V1_MAX_LEN {
+            return Err(invalid("PROXY protocol v1 header is too long"));
+        }
+
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY protocol v1 header is not valid UTF-8"))?;
+
+    parse_v1_addrs(line)
+}
+
+fn parse_v1_addrs(line: &str) -> io::Result<Option<(SocketAddr, SocketAddr)>> {
+    let parts: Vec<_> = line.split(' ').collect();
+
+    match parts[..] {
+        ["UNKNOWN", ..] => Ok(None),
+        [proto @ ("TCP4" | "TCP6"), src, dst, src_port, dst_port] => {
+            let parse = |ip: &str, port: &str| -> io::Result<SocketAddr> {
+                let ip: IpAddr = ip
+                    .parse()
+                    .map_err(|_| invalid("invalid PROXY protocol v1 address"))?;
+                let port = port
+                    .parse()
+                    .map_err(|_| invalid("invalid PROXY protocol v1 port"))?;
+
+                if ip.is_ipv4() != (proto == "TCP4") {
+                    return Err(invalid("PROXY protocol v1 address doesn't match protocol"));
+                }
+
+                Ok(SocketAddr::new(ip, port))
+            };
+
+            Ok(Some((parse(src, src_port)?, parse(dst, dst_port)?)))
+        }
+        _ => Err(invalid("invalid PROXY protocol v1 header")),
+    }
+}
+
+fn parse_v2_addrs(family: u8, addrs: &[u8]) -> io::Result<Option<(SocketAddr, SocketAddr)>> {
+    let port = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+
+    match family {
+        // NOTE: TCP over IPv4.
+        0x11 if addrs.len() >= 12 => {
+            let src = Ipv4Addr::from(<[u8; 4]>::try_from(&addrs[0..4]).unwrap());
+            let dst = Ipv4Addr::from(<[u8; 4]>::try_from(&addrs[4..8]).unwrap());
+
+            Ok(Some((
+                SocketAddr::new(src.into(), port(&addrs[8..10])),
+                SocketAddr::new(dst.into(), port(&addrs[10..12])),
+            )))
+        }
+        // NOTE: TCP over IPv6.
+        0x21 if addrs.len() >= 36 => {
+            let src = Ipv6Addr::from(<[u8; 16]>::try_from(&addrs[0..16]).unwrap());
+            let dst = Ipv6Addr::from(<[u8; 16]>::try_from(&addrs[16..32]).unwrap());
+
+            Ok(Some((
+                SocketAddr::new(src.into(), port(&addrs[32..34])),
+                SocketAddr::new(dst.into(), port(&addrs[34..36])),
+            )))
+        }
+        0x11 | 0x21 => Err(invalid("truncated PROXY protocol v2 addresses")),
+        // NOTE: the addresses of the other families are not meaningful for a TCP listener.
+        _ => Ok(None),
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(mut data: &[u8]) -> (io::Result<Option<(SocketAddr, SocketAddr)>>, &[u8]) {
+        let res = read_header(&mut data).await;
+
+        (res, data)
+    }
+
+    #[tokio::test]
+    async fn reads_v1_header() {
+        let (res, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /").await;
+
+        assert_eq!(
+            res.unwrap(),
+            Some((
+                "192.0.2.1:56324".parse().unwrap(),
+                "198.51.100.1:443".parse().unwrap()
+            ))
+        );
+        assert_eq!(rest, b"GET /");
+
+        let (res, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").await;
+
+        assert_eq!(
+            res.unwrap(),
+            Some((
+                "[2001:db8::1]:56324".parse().unwrap(),
+                "[2001:db8::2]:443".parse().unwrap()
+            ))
+        );
+
+        assert_eq!(read(b"PROXY UNKNOWN\r\n").await.0.unwrap(), None);
+        assert!(read(b"PROXY TCP4 2001:db8::1 192.0.2.1 1 2\r\n")
+            .await
+            .0
+            .is_err());
+        assert!(read(&b"PROXY ".repeat(30)).await.0.is_err());
+        assert!(read(b"GET / HTTP/1.1\r\n").await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_v2_header() {
+        let header = [
+            &V2_SIGNATURE[..],
+            &[0x21, 0x11, 0x00, 0x0c],
+            &[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb],
+            b"GET /",
+        ]
+        .concat();
+
+        let (res, rest) = read(&header).await;
+
+        assert_eq!(
+            res.unwrap(),
+            Some((
+                "192.0.2.1:56324".parse().unwrap(),
+                "198.51.100.1:443".parse().unwrap()
+            ))
+        );
+        assert_eq!(rest, b"GET /");
+
+        let local = [&V2_SIGNATURE[..], &[0x20, 0x00, 0x00, 0x00]].concat();
+
+        assert_eq!(read(&local).await.0.unwrap(), None);
+
+        let truncated = [&V2_SIGNATURE[..], &[0x21, 0x11, 0x00, 0x04], &[0; 4]].concat();
+
+        assert!(read(&truncated).await.0.is_err());
+    }
+}
@@ -0,0 +1,78 @@
+#[cfg(feature = "settings")]
+use crate::settings::net::SocketAddr;
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+use std::net::Ipv4Addr;
+#[cfg(not(feature = "settings"))]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Settings of the listeners bound with [`Listeners::bind`].
+///
+/// [`Listeners::bind`]: super::Listeners::bind
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+pub struct ListenersSettings {
+    /// Listeners of the service.
+    pub listeners: Vec<ListenerSettings>,
+}
+
+/// Settings of a listener, see [`ListenersSettings`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct ListenerSettings {
+    /// Name of the listener, used to [take] it and as the `listener` label of the metrics.
+    ///
+    /// [take]: super::Listeners::take
+    pub name: String,
+
+    /// Address the listener is bound to.
+    pub addr: SocketAddr,
+
+    /// Maximum number of the connections waiting to be accepted.
+    pub backlog: u32,
+
+    /// TLS settings of the listener. The listener is plain-text if not specified.
+    pub tls: Option<ListenerTlsSettings>,
+
+    /// Whether the connections start with a [PROXY protocol] header, e.g. if the listener is
+    /// behind a load balancer. Both versions 1 and 2 of the protocol are accepted.
+    ///
+    /// [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+    pub proxy_protocol: bool,
+
+    /// Timeout in milliseconds of reading the PROXY protocol header of a connection.
+    pub proxy_protocol_timeout_ms: u64,
+}
+
+impl Default for ListenerSettings {
+    fn default() -> Self {
+        let addr: std::net::SocketAddr = (Ipv4Addr::LOCALHOST, 0).into();
+
+        #[cfg(feature = "settings")]
+        let addr = addr.into();
+
+        Self {
+            name: Default::default(),
+            addr,
+            backlog: 1024,
+            tls: None,
+            proxy_protocol: false,
+            proxy_protocol_timeout_ms: 5000,
+        }
+    }
+}
+
+/// TLS settings of a listener, see [`ListenerSettings::tls`].
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+pub struct ListenerTlsSettings {
+    /// Path to the PEM-encoded certificate chain.
+    pub cert_path: PathBuf,
+
+    /// Path to the PEM-encoded private key.
+    pub key_path: PathBuf,
+}
@@ -0,0 +1,696 @@
+//! Middleware bundle for [tower] service stacks.
+//!
+//! Middleware ordering in tower stacks is a frequent source of subtle bugs: e.g. a timeout
+//! applied beneath a retry layer limits each attempt instead of the whole request, and
+//! a concurrency limit applied above it holds a permit while the retry backs off. [`ServiceLayers`]
+//! combines the commonly used layers in a single [`Layer`] configured with
+//! [`ServiceLayersSettings`], applying them in the following order, from the outermost to
+//! the innermost:
+//!
+//! 1. **Telemetry**: the request is processed in its own tracing span and the request, error and
+//!    timeout counters are updated. The span of a failed request gets the `error` or `timeout`
+//!    status, and the span of a request whose future is dropped before completion gets
+//!    the `cancelled` status. If [`ServiceLayersSettings::request_ids`] is enabled, the request
+//!    also gets a time-sortable request ID.
+//! 2. **Timeout**: the request deadline that covers all the retry attempts.
+//! 3. **Retry**: failed requests are retried with a fixed backoff. Each attempt is processed in
+//!    a child `retry_attempt` span of the request span and with a forked log, both having
+//!    the `retry.attempt` field with the attempt number, so that the failures of the individual
+//!    attempts can be traced as a part of the same request.
+//! 4. **Concurrency limit**: each attempt occupies a concurrency slot.
+//!
+//! The duration of the connection phases of the requests to an upstream, e.g. DNS resolution,
+//! TCP connect, TLS handshake and waiting for the first byte of the response, can be recorded
+//! by wrapping the services implementing the phases with [`ServiceLayers::phase`] layers, so that
+//! a slow upstream can be attributed to the right phase.
+//!
+//! # Examples
+//! ```
+//! use foundations::middleware::{RetrySettings, ServiceLayers, ServiceLayersSettings};
+//! use tower::{service_fn, BoxError, ServiceBuilder, ServiceExt};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let settings = ServiceLayersSettings {
+//!     timeout_ms: Some(1000),
+//!     retry: RetrySettings {
+//!         enabled: true,
+//!         ..Default::default()
+//!     },
+//!     concurrency_limit: Some(64),
+//!     phase_histograms: false,
+//!     request_ids: true,
+//! };
+//!
+//! let service = ServiceBuilder::new()
+//!     .layer(ServiceLayers::new("echo", &settings))
+//!     .service(service_fn(|req: String| async move { Ok::<_, BoxError>(req) }));
+//!
+//! assert_eq!(service.oneshot("hello".to_string()).await.unwrap(), "hello");
+//! # }
+//! ```
+//!
+//! [tower]: https://docs.rs/tower
+
+mod phases;
+mod settings;
+
+pub use self::phases::{ConnectionPhase, PhaseLayer, PhaseTelemetry};
+pub use self::settings::{RetrySettings, ServiceLayersSettings};
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::limit::ConcurrencyLimit;
+use tower::retry::{Policy, Retry};
+use tower::timeout::Timeout;
+use tower::util::BoxCloneService;
+use tower::{BoxError, Layer, Service, ServiceExt};
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, DurationHistogram, HistogramBuilder};
+
+#[cfg(feature = "tracing")]
+use crate::telemetry::tracing::SpanStatus;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_service {
+    /// Number of requests processed by the service.
+    pub fn requests_total(service: &'static str) -> Counter;
+
+    /// Number of requests that failed, including the timed out ones.
+    pub fn errors_total(service: &'static str) -> Counter;
+
+    /// Number of requests that timed out.
+    pub fn timeouts_total(service: &'static str) -> Counter;
+
+    /// Duration of the connection phases recorded by the [`ServiceLayers::phase`] layers, if
+    /// [`ServiceLayersSettings::phase_histograms`] is enabled.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0],
+    }]
+    pub fn phase_duration(service: &'static str, phase: &'static str) -> DurationHistogram;
+}
+
+/// A [`Layer`] that applies timeout, retry, concurrency limit and telemetry middleware in
+/// the correct order.
+///
+/// All the errors of the resulting service are boxed. Timeouts are reported as
+/// [`tower::timeout::error::Elapsed`] errors.
+///
+/// Retries require requests to be cloneable, so `Req` should implement [`Clone`] even if
+/// retries are disabled.
+///
+/// See [module-level documentation] for more details.
+///
+/// [module-level documentation]: crate::middleware
+pub struct ServiceLayers<Req> {
+    name: &'static str,
+    settings: ServiceLayersSettings,
+    _req: PhantomData<fn(Req)>,
+}
+
+impl<Req> ServiceLayers<Req> {
+    /// Creates a new layer bundle.
+    ///
+    /// The `name` is used as a name of the request tracing span and a `service` label of
+    /// the metrics.
+    pub fn new(name: &'static str, settings: &ServiceLayersSettings) -> Self {
+        Self {
+            name,
+            settings: settings.clone(),
+            _req: PhantomData,
+        }
+    }
+
+    /// Returns a layer that records the duration of each call of the wrapped service as
+    /// the given connection phase of the requests processed by this layer bundle.
+    ///
+    /// The phase is recorded as a span log event on the current span, usually the request span,
+    /// with the `event` field containing the [phase name], the `duration_ms` and the `outcome`
+    /// (`ok` or `error`) fields. If [`ServiceLayersSettings::phase_histograms`] is enabled,
+    /// the duration is also reported in the phase duration histogram.
+    ///
+    /// The phase duration covers the whole call of the wrapped service, e.g. the connect phase
+    /// of a connector that resolves the upstream address includes the DNS resolution.
+    ///
+    /// # Examples
+    /// ```
+    /// use foundations::middleware::{ConnectionPhase, ServiceLayers};
+    /// use std::net::{IpAddr, Ipv4Addr};
+    /// use tower::{service_fn, BoxError, ServiceBuilder, ServiceExt};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let layers = ServiceLayers::<String>::new("upstream", &Default::default());
+    ///
+    /// let resolver = ServiceBuilder::new()
+    ///     .layer(layers.phase(ConnectionPhase::Dns))
+    ///     .service(service_fn(|_host: String| async {
+    ///         Ok::<_, BoxError>(IpAddr::from(Ipv4Addr::LOCALHOST))
+    ///     }));
+    ///
+    /// let addr = resolver.oneshot("example.com".to_string()).await.unwrap();
+    ///
+    /// assert!(addr.is_loopback());
+    /// # }
+    /// ```
+    ///
+    /// [phase name]: ConnectionPhase::as_str
+    pub fn phase(&self, phase: ConnectionPhase) -> PhaseLayer {
+        PhaseLayer {
+            name: self.name,
+            phase,
+            histograms: self.settings.phase_histograms,
+        }
+    }
+}
+
+impl<Req> Clone for ServiceLayers<Req> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            settings: self.settings.clone(),
+            _req: PhantomData,
+        }
+    }
+}
+
+impl<S, Req> Layer<S> for ServiceLayers<Req>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    Req: Clone + Send + 'static,
+{
+    type Service = BoxCloneService<Req, S::Response, BoxError>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let mut service = BoxCloneService::new(inner.map_err(Into::into));
+
+        if let Some(max) = self.settings.concurrency_limit {
+            service = BoxCloneService::new(ConcurrencyLimit::new(service, max));
+        }
+
+        if self.settings.retry.enabled {
+            let policy = RetryPolicy {
+                remaining: self.settings.retry.max_attempts.saturating_sub(1),
+                backoff: Duration::from_millis(self.settings.retry.backoff_ms),
+            };
+
+            let retry = Retry::new(policy, AttemptTelemetry { inner: service });
+
+            service = BoxCloneService::new(retry.map_request(|req| Attempt { req, number: 1 }));
+        }
+
+        if let Some(timeout_ms) = self.settings.timeout_ms {
+            service =
+                BoxCloneService::new(Timeout::new(service, Duration::from_millis(timeout_ms)));
+        }
+
+        BoxCloneService::new(Telemetry {
+            inner: service,
+            name: self.name,
+            request_ids: self.settings.request_ids,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct RetryPolicy {
+    remaining: usize,
+    backoff: Duration,
+}
+
+impl<Req, Res> Policy<Attempt<Req>, Res, BoxError> for RetryPolicy
+where
+    Req: Clone,
+{
+    type Future = tokio::time::Sleep;
+
+    fn retry(
+        &mut self,
+        req: &mut Attempt<Req>,
+        result: &mut Result<Res, BoxError>,
+    ) -> Option<Self::Future> {
+        if result.is_ok() || self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+        req.number += 1;
+
+        Some(tokio::time::sleep(self.backoff))
+    }
+
+    fn clone_request(&mut self, req: &Attempt<Req>) -> Option<Attempt<Req>> {
+        (self.remaining > 0).then(|| req.clone())
+    }
+}
+
+/// A request along with the number of the attempt to process it.
+#[derive(Clone)]
+struct Attempt<Req> {
+    req: Req,
+    number: usize,
+}
+
+/// Processes each retry attempt in its own span and with a forked log.
+#[derive(Clone)]
+struct AttemptTelemetry<S> {
+    inner: S,
+}
+
+impl<S, Req> Service<Attempt<Req>> for AttemptTelemetry<S>
+where
+    S: Service<Req, Error = BoxError> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Response: 'static,
+    Req: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, attempt: Attempt<Req>) -> Self::Future {
+        // NOTE: the inner service is called in the attempt's telemetry context, so take
+        // the service that has been driven to readiness.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let Attempt { req, number } = attempt;
+
+        let fut = async move {
+            #[cfg(feature = "logging")]
+            crate::telemetry::log::add_fields!("retry.attempt" => number);
+
+            #[cfg(feature = "tracing")]
+            crate::telemetry::tracing::add_span_tags!("retry.attempt" => number as i64);
+
+            #[cfg(not(any(feature = "logging", feature = "tracing")))]
+            let _ = number;
+
+            inner.call(req).await
+        };
+
+        // NOTE: the first attempt is called before the request span is entered, so the attempt's
+        // telemetry context is captured on the first poll.
+        Box::pin(async move {
+            #[cfg(any(feature = "logging", feature = "tracing"))]
+            let ctx = crate::telemetry::TelemetryContext::current();
+
+            #[cfg(feature = "logging")]
+            let ctx = ctx.with_forked_log();
+
+            #[cfg(feature = "tracing")]
+            let fut = ctx.apply_with_tracing_span("retry_attempt", fut);
+
+            #[cfg(all(feature = "logging", not(feature = "tracing")))]
+            let fut = ctx.apply(fut);
+
+            fut.await
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Telemetry<S> {
+    inner: S,
+    #[cfg_attr(not(any(feature = "metrics", feature = "tracing")), allow(dead_code))]
+    name: &'static str,
+    #[cfg_attr(not(any(feature = "logging", feature = "tracing")), allow(dead_code))]
+    request_ids: bool,
+}
+
+impl<S, Req> Service<Req> for Telemetry<S>
+where
+    S: Service<Req, Error = BoxError>,
+    S::Future: Send + 'static,
+    S::Response: 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let fut = self.inner.call(req);
+
+        #[cfg(feature = "metrics")]
+        let name = self.name;
+
+        #[cfg(feature = "metrics")]
+        foundations_service::requests_total(name).inc();
+
+        #[cfg(any(feature = "logging", feature = "tracing"))]
+        let request_id = self.request_ids.then(crate::ids::Ulid::new);
+
+        let fut = async move {
+            #[cfg(any(feature = "logging", feature = "tracing"))]
+            if let Some(request_id) = request_id {
+                #[cfg(feature = "logging")]
+                crate::telemetry::log::add_fields!("request.id" => request_id);
+
+                #[cfg(feature = "tracing")]
+                crate::telemetry::tracing::add_span_tags!("request.id" => request_id.to_string());
+            }
+
+            // NOTE: marks the request span as cancelled if the future is dropped before completion.
+            #[cfg(feature = "tracing")]
+            let status_guard = crate::telemetry::tracing::internal::SpanStatusGuard::new();
+
+            let res = fut.await;
+            let timed_out = matches!(&res, Err(err) if err.is::<tower::timeout::error::Elapsed>());
+
+            #[cfg(feature = "metrics")]
+            if res.is_err() {
+                if timed_out {
+                    foundations_service::timeouts_total(name).inc();
+                }
+
+                foundations_service::errors_total(name).inc();
+            }
+
+            #[cfg(feature = "tracing")]
+            match &res {
+                Ok(_) => status_guard.complete(),
+                Err(_) if timed_out => status_guard.fail(SpanStatus::Timeout),
+                Err(_) => status_guard.fail(SpanStatus::Error),
+            }
+
+            #[cfg(not(any(feature = "metrics", feature = "tracing")))]
+            let _ = timed_out;
+
+            res
+        };
+
+        #[cfg(any(feature = "logging", feature = "tracing"))]
+        let ctx = crate::telemetry::TelemetryContext::current();
+
+        // NOTE: the request ID is only added to the log of the request.
+        #[cfg(feature = "logging")]
+        let ctx = if request_id.is_some() {
+            ctx.with_forked_log()
+        } else {
+            ctx
+        };
+
+        #[cfg(feature = "tracing")]
+        let fut = ctx.apply_with_tracing_span(self.name, fut);
+
+        #[cfg(all(feature = "logging", not(feature = "tracing")))]
+        let fut = ctx.apply(fut);
+
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tower::{service_fn, ServiceBuilder};
+
+    fn flaky_service(
+        failures: usize,
+    ) -> (
+        impl Service<(), Response = usize, Error = BoxError, Future = impl Send> + Clone + Send,
+        Arc<AtomicUsize>,
+    ) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let service = service_fn({
+            let attempts = Arc::clone(&attempts);
+
+            move |_: ()| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+
+                async move {
+                    if attempt <= failures {
+                        Err(BoxError::from("flaky"))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            }
+        });
+
+        (service, attempts)
+    }
+
+    fn settings(max_attempts: usize, timeout_ms: Option<u64>) -> ServiceLayersSettings {
+        ServiceLayersSettings {
+            timeout_ms,
+            retry: RetrySettings {
+                enabled: true,
+                max_attempts,
+                backoff_ms: 10,
+            },
+            concurrency_limit: Some(1),
+            phase_histograms: false,
+            request_ids: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_failed_requests() {
+        let (service, attempts) = flaky_service(2);
+
+        let service = ServiceBuilder::new()
+            .layer(ServiceLayers::new("retries", &settings(3, None)))
+            .service(service);
+
+        assert_eq!(service.oneshot(()).await.unwrap(), 3);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (service, attempts) = flaky_service(5);
+
+        let service = ServiceBuilder::new()
+            .layer(ServiceLayers::new("gives_up", &settings(2, None)))
+            .service(service);
+
+        assert!(service.oneshot(()).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn timeout_covers_all_attempts() {
+        let (service, attempts) = flaky_service(usize::MAX);
+
+        let service = ServiceBuilder::new()
+            .layer(ServiceLayers::new("timeout", &settings(100, Some(35))))
+            .service(service);
+
+        let err = service.oneshot(()).await.unwrap_err();
+
+        assert!(err.is::<tower::timeout::error::Elapsed>());
+        assert!(attempts.load(Ordering::SeqCst) < 100);
+    }
+
+    #[cfg(all(feature = "logging", feature = "tracing", feature = "testing"))]
+    #[tokio::test]
+    async fn correlates_attempts_telemetry() {
+        use crate::telemetry::log::{self, TestLogRecord};
+        use crate::telemetry::settings::Level;
+        use crate::telemetry::tracing::{test_trace, TestTraceOptions};
+        use crate::telemetry::TelemetryContext;
+
+        let ctx = TelemetryContext::test();
+
+        {
+            let _scope = ctx.scope();
+            let (service, _) = flaky_service(1);
+
+            let service = ServiceBuilder::new()
+                .layer(ServiceLayers::new("correlated", &settings(3, None)))
+                .map_response(|attempt| {
+                    log::warn!("attempt succeeded");
+                    attempt
+                })
+                .service(service);
+
+            assert_eq!(service.oneshot(()).await.unwrap(), 2);
+        }
+
+        assert_eq!(
+            ctx.traces(TestTraceOptions {
+                include_tags: true,
+                ..Default::default()
+            }),
+            vec![test_trace! {
+                "correlated" => {
+                    "retry_attempt"; { tags: [("retry.attempt", 1_i64)] },
+                    "retry_attempt"; { tags: [("retry.attempt", 2_i64)] }
+                }
+            }]
+        );
+
+        assert_eq!(
+            *ctx.log_records(),
+            [TestLogRecord {
+                level: Level::Warning,
+                message: "attempt succeeded".into(),
+                fields: vec![("retry.attempt".into(), "2".into())],
+            }]
+        );
+    }
+
+    #[cfg(all(feature = "logging", feature = "tracing", feature = "testing"))]
+    #[tokio::test]
+    async fn assigns_request_ids() {
+        use crate::ids::Ulid;
+        use crate::telemetry::log;
+        use crate::telemetry::tracing::TestTraceOptions;
+        use crate::telemetry::TelemetryContext;
+
+        let ctx = TelemetryContext::test();
+
+        {
+            let _scope = ctx.scope();
+
+            let settings = ServiceLayersSettings {
+                request_ids: true,
+                ..Default::default()
+            };
+
+            let service = ServiceBuilder::new()
+                .layer(ServiceLayers::new("identified", &settings))
+                .service(service_fn(|_: ()| async {
+                    log::warn!("handled");
+                    Ok::<_, BoxError>(())
+                }));
+
+            service.oneshot(()).await.unwrap();
+            log::warn!("outside of the request");
+        }
+
+        let records = ctx.log_records();
+        let (key, request_id) = &records[0].fields[0];
+
+        assert_eq!(key, "request.id");
+        assert!(request_id.parse::<Ulid>().is_ok());
+        assert!(records[1].fields.is_empty());
+
+        let traces = ctx.traces(TestTraceOptions {
+            include_tags: true,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            traces[0].0.tags,
+            [("request.id".into(), request_id.clone().into())]
+        );
+    }
+
+    #[cfg(all(feature = "tracing", feature = "testing"))]
+    #[tokio::test]
+    async fn reports_span_statuses() {
+        use crate::telemetry::tracing::{test_trace, TestTraceOptions};
+        use crate::telemetry::TelemetryContext;
+
+        let ctx = TelemetryContext::test();
+
+        {
+            let _scope = ctx.scope();
+            let pending = service_fn(|_: ()| std::future::pending::<Result<(), BoxError>>());
+
+            let settings = ServiceLayersSettings {
+                timeout_ms: Some(10),
+                ..Default::default()
+            };
+
+            let service = ServiceBuilder::new()
+                .layer(ServiceLayers::new("timed_out", &settings))
+                .service(pending);
+
+            assert!(service.oneshot(()).await.is_err());
+
+            let service = ServiceBuilder::new()
+                .layer(ServiceLayers::new("cancelled", &Default::default()))
+                .service(pending);
+
+            let res = tokio::time::timeout(Duration::from_millis(10), service.oneshot(())).await;
+
+            assert!(res.is_err());
+        }
+
+        assert_eq!(
+            ctx.traces(TestTraceOptions {
+                include_tags: true,
+                ..Default::default()
+            }),
+            vec![
+                test_trace! {
+                    "timed_out"; { tags: [("status", "timeout"), ("error", true)] }
+                },
+                test_trace! {
+                    "cancelled"; { tags: [("status", "cancelled")] }
+                },
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "tracing", feature = "testing"))]
+    #[tokio::test]
+    async fn records_connection_phases() {
+        use crate::telemetry::tracing::TestTraceOptions;
+        use crate::telemetry::TelemetryContext;
+
+        let ctx = TelemetryContext::test();
+
+        {
+            let _scope = ctx.scope();
+            let layers = ServiceLayers::new("phases", &Default::default());
+
+            let connect = ServiceBuilder::new()
+                .layer(layers.phase(ConnectionPhase::Connect))
+                .service(service_fn(|_: ()| async {
+                    Err::<(), _>(BoxError::from("refused"))
+                }));
+
+            let service = ServiceBuilder::new()
+                .layer(layers.clone())
+                .layer(layers.phase(ConnectionPhase::FirstByte))
+                .service(connect);
+
+            assert!(service.oneshot(()).await.is_err());
+        }
+
+        let traces = ctx.traces(TestTraceOptions {
+            include_logs: true,
+            ..Default::default()
+        });
+
+        let events: Vec<_> = traces[0]
+            .0
+            .logs
+            .iter()
+            .filter(|(key, _)| key != "duration_ms")
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+            .collect();
+
+        assert_eq!(
+            events,
+            [
+                ("event", "connect"),
+                ("outcome", "error"),
+                ("event", "first_byte"),
+                ("outcome", "error"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+/// A phase of a request to an upstream, recorded by the [`PhaseLayer`] returned by
+/// [`ServiceLayers::phase`].
+///
+/// [`ServiceLayers::phase`]: super::ServiceLayers::phase
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionPhase {
+    /// Resolution of the upstream address, e.g. by a DNS resolver service.
+    Dns,
+
+    /// Establishing of a TCP connection, e.g. by a connector service.
+    Connect,
+
+    /// TLS handshake, e.g. by a TLS connector service.
+    TlsHandshake,
+
+    /// Waiting for the first byte of the response, e.g. for the response headers returned by
+    /// an HTTP client service.
+    FirstByte,
+}
+
+impl ConnectionPhase {
+    /// Returns the name of the phase used in the span events and the metric labels.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConnectionPhase::Dns => "dns",
+            ConnectionPhase::Connect => "connect",
+            ConnectionPhase::TlsHandshake => "tls_handshake",
+            ConnectionPhase::FirstByte => "first_byte",
+        }
+    }
+}
+
+impl fmt::Display for ConnectionPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A [`Layer`] that records the duration of each call of the wrapped service as a connection
+/// phase.
+///
+/// See [`ServiceLayers::phase`] for more details.
+///
+/// [`ServiceLayers::phase`]: super::ServiceLayers::phase
+#[derive(Clone, Debug)]
+pub struct PhaseLayer {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub(super) name: &'static str,
+    #[cfg_attr(not(any(feature = "metrics", feature = "tracing")), allow(dead_code))]
+    pub(super) phase: ConnectionPhase,
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub(super) histograms: bool,
+}
+
+impl<S> Layer<S> for PhaseLayer {
+    type Service = PhaseTelemetry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PhaseTelemetry {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service that records the duration of each call of the inner service as a connection phase,
+/// see [`PhaseLayer`].
+#[derive(Clone, Debug)]
+pub struct PhaseTelemetry<S> {
+    inner: S,
+    #[cfg_attr(not(any(feature = "metrics", feature = "tracing")), allow(dead_code))]
+    layer: PhaseLayer,
+}
+
+impl<S, Req> Service<Req> for PhaseTelemetry<S>
+where
+    S: Service<Req>,
+    S::Future: Send + 'static,
+    S::Response: 'static,
+    S::Error: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let fut = self.inner.call(req);
+
+        #[cfg(any(feature = "metrics", feature = "tracing"))]
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = fut.await;
+
+            #[cfg(any(feature = "metrics", feature = "tracing"))]
+            layer.record(start, res.is_ok());
+
+            #[cfg(not(any(feature = "metrics", feature = "tracing")))]
+            let _ = start;
+
+            res
+        })
+    }
+}
+
+impl PhaseLayer {
+    #[cfg(any(feature = "metrics", feature = "tracing"))]
+    fn record(&self, start: Instant, succeeded: bool) {
+        let duration = start.elapsed();
+
+        #[cfg(feature = "metrics")]
+        if self.histograms {
+            super::foundations_service::phase_duration(self.name, self.phase.as_str())
+                .observe(duration);
+        }
+
+        #[cfg(feature = "tracing")]
+        crate::telemetry::tracing::add_span_log_fields!(
+            "event" => self.phase.as_str(),
+            "duration_ms" => format!("{:.3}", duration.as_secs_f64() * 1000.0),
+            "outcome" => if succeeded { "ok" } else { "error" }
+        );
+
+        #[cfg(not(feature = "tracing"))]
+        let _ = succeeded;
+    }
+}
@@ -0,0 +1,61 @@
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+/// Settings of the [`ServiceLayers`] middleware bundle.
+///
+/// [`ServiceLayers`]: super::ServiceLayers
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+pub struct ServiceLayersSettings {
+    /// Deadline for a request in milliseconds, including all the retry attempts.
+    /// Requests are not limited in time if not specified.
+    pub timeout_ms: Option<u64>,
+
+    /// Retry settings.
+    pub retry: RetrySettings,
+
+    /// Maximum number of requests that can be processed concurrently.
+    /// Concurrency is not limited if not specified.
+    pub concurrency_limit: Option<usize>,
+
+    /// Reports the duration histograms of the connection phases recorded by
+    /// the [`ServiceLayers::phase`] layers.
+    ///
+    /// [`ServiceLayers::phase`]: super::ServiceLayers::phase
+    pub phase_histograms: bool,
+
+    /// Assigns a [`Ulid`] request ID to each request, added as the `request.id` field to
+    /// the request log and as the `request.id` tag to the request span.
+    ///
+    /// [`Ulid`]: crate::ids::Ulid
+    pub request_ids: bool,
+}
+
+/// Retry settings of the [`ServiceLayers`] middleware bundle.
+///
+/// [`ServiceLayers`]: super::ServiceLayers
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct RetrySettings {
+    /// Enables retries of failed requests.
+    pub enabled: bool,
+
+    /// Maximum number of attempts for a request, including the first one.
+    pub max_attempts: usize,
+
+    /// Delay between the attempts in milliseconds.
+    pub backoff_ms: u64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_attempts: 3,
+            backoff_ms: 100,
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Networking helpers for clients.
+//!
+//! In dual-stack environments, connecting to the addresses of a host one by one can stall for
+//! the whole connect timeout if the preferred address family is broken, e.g. if a v6-first host
+//! connects to a collector that is only reachable over IPv4. [`connect`] implements the
+//! [Happy Eyeballs] algorithm instead: connection attempts to the addresses of alternating
+//! families are started with a short delay without waiting for the previous attempts to fail,
+//! and the first established connection wins.
+//!
+//! With the `metrics` feature, the attempts are counted by the
+//! `<prefix>_foundations_net_connect_attempts_total` counter labeled with the address family
+//! (`ipv4` or `ipv6`) and the outcome (`ok`, `error` or `cancelled` for the attempts that lost
+//! the race), and the duration of the successful attempts is reported by the
+//! `<prefix>_foundations_net_connect_duration_seconds` histogram labeled with the family.
+//!
+//! # Examples
+//! ```
+//! use foundations::net::{self, HappyEyeballsSettings};
+//! use tokio::net::TcpListener;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! let listener = TcpListener::bind("127.0.0.1:0").await?;
+//! let port = listener.local_addr()?.port();
+//!
+//! let stream = net::connect("127.0.0.1", port, &HappyEyeballsSettings::default()).await?;
+//!
+//! assert_eq!(stream.peer_addr()?.port(), port);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [Happy Eyeballs]: https://www.rfc-editor.org/rfc/rfc8305
+
+use futures_util::future::{self, Either};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, DurationHistogram, HistogramBuilder};
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_net {
+    /// Number of TCP connection attempts, by the address family and the outcome.
+    pub fn connect_attempts_total(family: &'static str, outcome: &'static str) -> Counter;
+
+    /// Duration of the successful TCP connection attempts, by the address family.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0],
+    }]
+    pub fn connect_duration(family: &'static str) -> DurationHistogram;
+}
+
+/// Settings of the [Happy Eyeballs] connection establishment, see [`connect`].
+///
+/// [Happy Eyeballs]: https://www.rfc-editor.org/rfc/rfc8305
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct HappyEyeballsSettings {
+    /// Delay in milliseconds before the next connection attempt is started if the previous ones
+    /// haven't completed.
+    pub attempt_delay_ms: u64,
+
+    /// Timeout in milliseconds of the whole connection establishment, including the name
+    /// resolution.
+    ///
+    /// There is no timeout if not specified.
+    pub timeout_ms: Option<u64>,
+}
+
+impl Default for HappyEyeballsSettings {
+    fn default() -> Self {
+        Self {
+            // NOTE: the delay recommended by RFC 8305.
+            attempt_delay_ms: 250,
+            timeout_ms: None,
+        }
+    }
+}
+
+/// Resolves the host and connects to its addresses with the [Happy Eyeballs] algorithm.
+///
+/// The host can be either a domain name or an IP address. See the [module-level documentation]
+/// for more details.
+///
+/// [Happy Eyeballs]: https://www.rfc-editor.org/rfc/rfc8305
+/// [module-level documentation]: crate::net
+pub async fn connect(
+    host: &str,
+    port: u16,
+    settings: &HappyEyeballsSettings,
+) -> io::Result<TcpStream> {
+    with_timeout(settings, async {
+        let addrs = tokio::net::lookup_host((host, port)).await?;
+
+        connect_addrs_inner(addrs, settings).await
+    })
+    .await
+}
+
+/// Connects to the first reachable address with the [Happy Eyeballs] algorithm.
+///
+/// Addresses of the different families are interleaved, starting with the family of the first
+/// address, so the order of the addresses of the same family is preserved.
+///
+/// [Happy Eyeballs]: https://www.rfc-editor.org/rfc/rfc8305
+pub async fn connect_addrs(
+    addrs: impl IntoIterator<Item = SocketAddr>,
+    settings: &HappyEyeballsSettings,
+) -> io::Result<TcpStream> {
+    with_timeout(settings, connect_addrs_inner(addrs, settings)).await
+}
+
+async fn with_timeout(
+    settings: &HappyEyeballsSettings,
+    fut: impl std::future::Future<Output = io::Result<TcpStream>>,
+) -> io::Result<TcpStream> {
+    match settings.timeout_ms {
+        Some(timeout_ms) => tokio::time::timeout(Duration::from_millis(timeout_ms), fut)
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection establishment timed out",
+                ))
+            }),
+        None => fut.await,
+    }
+}
+
+async fn connect_addrs_inner(
+    addrs: impl IntoIterator<Item = SocketAddr>,
+    settings: &HappyEyeballsSettings,
+) -> io::Result<TcpStream> {
+    let attempt_delay = Duration::from_millis(settings.attempt_delay_ms);
+    let mut addrs = interleave_families(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    // NOTE: the next attempt is started either once the delay elapses or once an attempt fails.
+    loop {
+        if let Some(addr) = addrs.next() {
+            attempts.push(attempt(addr));
+        }
+
+        if attempts.is_empty() {
+            return Err(last_error.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+            }));
+        }
+
+        let delay = pin!(tokio::time::sleep(attempt_delay));
+
+        match future::select(attempts.next(), delay).await {
+            Either::Left((Some(Ok(stream)), _)) => return Ok(stream),
+            Either::Left((Some(Err(e)), _)) => last_error = Some(e),
+            Either::Left((None, _)) => unreachable!("there are pending attempts"),
+            Either::Right(_) => {}
+        }
+    }
+}
+
+fn interleave_families(addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let mut addrs = addrs.into_iter().peekable();
+    let first_is_ipv6 = addrs.peek().is_some_and(SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) =
+        addrs.partition(|addr| addr.is_ipv6() == first_is_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+async fn attempt(addr: SocketAddr) -> io::Result<TcpStream> {
+    #[cfg(feature = "metrics")]
+    let mut tracker = AttemptTracker {
+        family: if addr.is_ipv6() { "ipv6" } else { "ipv4" },
+        started_at: Instant::now(),
+        outcome: None,
+    };
+
+    let res = TcpStream::connect(addr).await;
+
+    #[cfg(feature = "metrics")]
+    {
+        tracker.outcome = Some(res.is_ok());
+    }
+
+    res
+}
+
+/// Reports the metrics of an attempt, including the attempts that are cancelled by dropping.
+#[cfg(feature = "metrics")]
+struct AttemptTracker {
+    family: &'static str,
+    started_at: Instant,
+    outcome: Option<bool>,
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for AttemptTracker {
+    fn drop(&mut self) {
+        let outcome = match self.outcome {
+            Some(true) => {
+                foundations_net::connect_duration(self.family).observe(self.started_at.elapsed());
+
+                "ok"
+            }
+            Some(false) => "error",
+            None => "cancelled",
+        };
+
+        foundations_net::connect_attempts_total(self.family, outcome).inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn interleaves_address_families() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1", "10.0.0.2:1"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+
+        let interleaved: Vec<_> = interleave_families(addrs)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            interleaved,
+            ["[::1]:1", "10.0.0.1:1", "[::2]:1", "10.0.0.2:1", "[::3]:1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_reachable_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // NOTE: bind and drop a listener to get a port that refuses connections.
+        let refused = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let stream = connect_addrs([refused, addr], &Default::default())
+            .await
+            .unwrap();
+
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+
+        let err = connect_addrs([refused], &Default::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
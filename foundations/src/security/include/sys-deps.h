@@ -1 +1,3 @@
+#include <sched.h>
+#include <sys/mount.h>
 #include <sys/prctl.h>
\ No newline at end of file
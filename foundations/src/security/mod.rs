@@ -16,9 +16,23 @@
 //!
 //! The crate provides a few [`common_syscall_allow_lists`] to simplify configuration.
 //!
+//! Sandboxes installed in the process can be inspected with [`installed_syscall_sandboxes`] or
+//! via the `/debug/sandbox` endpoint of the telemetry server. The kernel doesn't expose violation
+//! counts to the process, so they are only available for the sandboxes installed with
+//! [`ViolationAction::DenyAndCount`], see [`syscall_violation_counts`]: with
+//! [`ViolationAction::KillProcess`] the process is terminated on the first violation and with
+//! [`ViolationAction::AllowAndLog`] violations are recorded in the kernel audit log.
+//!
 //! Foundations compiles and statically links with [libseccomp], so it doesn't require the lib to be
 //! installed.
 //!
+//! # Filesystem isolation
+//!
+//! seccomp restricts which syscalls can be used, but not which files can be accessed. On kernels
+//! without [Landlock] support, [`isolate_filesystem`] can be used to restrict the filesystem
+//! visible to the process to a given root directory. See [`FilesystemIsolation`] for when each
+//! isolation method can be applied.
+//!
 //! # Simple case [Spectre] mitigation for x86_64 processors
 //!
 //! One of the simplest Spectre attack vectors it to use x86_64's [time stamp counter]. foundations
@@ -29,12 +43,14 @@
 //! [seccomp]: https://man7.org/linux/man-pages/man2/seccomp.2.html
 //! [arbitrary code execution]: https://en.wikipedia.org/wiki/Arbitrary_code_execution
 //! [libseccomp]: https://github.com/seccomp/libseccomp
+//! [Landlock]: https://docs.kernel.org/userspace-api/landlock.html
 //! [Spectre]: https://en.wikipedia.org/wiki/Spectre_(security_vulnerability)
 //! [time stamp counter]: https://en.wikipedia.org/wiki/Time_Stamp_Counter
 
 pub mod common_syscall_allow_lists;
 mod internal;
 mod syscalls;
+mod violations;
 
 #[allow(
     non_camel_case_types,
@@ -49,9 +65,16 @@ mod sys {
 
 use self::internal::RawRule;
 use crate::BootstrapResult;
-use anyhow::bail;
+use anyhow::{bail, Context};
+use std::ffi::c_ulong;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::ptr;
+use std::sync::Mutex;
 
 pub use self::syscalls::Syscall;
+pub use self::violations::syscall_violation_counts;
 
 /// A raw OS error code to be returned by [`Rule::ReturnError`].
 pub type RawOsErrorNum = u16;
@@ -77,6 +100,13 @@ pub enum ViolationAction {
     ///
     /// [sysctl]: https://man7.org/linux/man-pages/man8/sysctl.8.html
     AllowAndLog = sys::SCMP_ACT_LOG,
+
+    /// Fail the syscalls with `ENOSYS` error and count them, see [`syscall_violation_counts`].
+    ///
+    /// The violations are reported to the process with `SIGSYS` signal, so the process shouldn't
+    /// install its own `SIGSYS` handler. `rt_sigreturn` syscall used to return from the signal
+    /// handler is implicitly allowed.
+    DenyAndCount = sys::SCMP_ACT_TRAP,
 }
 
 /// A value to compare syscall arguments with in [comparators].
@@ -296,6 +326,79 @@ pub enum Rule {
     ReturnError(Syscall, RawOsErrorNum, Vec<ArgCmp>),
 }
 
+impl fmt::Display for ArgCmp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (arg_idx, op, value) = match self {
+            ArgCmp::NotEqual { arg_idx, value } => (arg_idx, "!=", value),
+            ArgCmp::LessThan { arg_idx, value } => (arg_idx, "<", value),
+            ArgCmp::LessThanOrEqual { arg_idx, value } => (arg_idx, "<=", value),
+            ArgCmp::Equal { arg_idx, value } => (arg_idx, "==", value),
+            ArgCmp::GreaterThanOrEqual { arg_idx, value } => (arg_idx, ">=", value),
+            ArgCmp::GreaterThan { arg_idx, value } => (arg_idx, ">", value),
+            ArgCmp::EqualMasked {
+                arg_idx,
+                mask,
+                value,
+            } => {
+                return write!(f, "arg{arg_idx} & {mask:#x} == {:#x}", value.0);
+            }
+        };
+
+        write!(f, "arg{arg_idx} {op} {:#x}", value.0)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (syscall, arg_cmps) = match self {
+            Rule::Allow(syscall, arg_cmps) => {
+                write!(f, "allow ")?;
+                (syscall, arg_cmps)
+            }
+            Rule::AllowAndLog(syscall, arg_cmps) => {
+                write!(f, "allow and log ")?;
+                (syscall, arg_cmps)
+            }
+            Rule::ReturnError(syscall, err_num, arg_cmps) => {
+                write!(f, "return error {err_num} for ")?;
+                (syscall, arg_cmps)
+            }
+        };
+
+        write!(f, "{syscall:?}")?;
+
+        for (i, arg_cmp) in arg_cmps.iter().enumerate() {
+            write!(f, "{} {arg_cmp}", if i == 0 { " if" } else { " and" })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Syscall sandbox installed with [`enable_syscall_sandboxing`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstalledSyscallSandbox {
+    /// Action performed on sandbox violation.
+    pub violation_action: ViolationAction,
+
+    /// Exception rules of the sandbox.
+    pub exception_rules: Vec<Rule>,
+}
+
+static INSTALLED_SANDBOXES: Mutex<Vec<InstalledSyscallSandbox>> = Mutex::new(Vec::new());
+
+/// Returns all the syscall sandboxes successfully installed with [`enable_syscall_sandboxing`]
+/// in the process, in the order of installation.
+///
+/// Note that sandboxes apply only to the threads they were installed from and the threads
+/// spawned by them afterwards.
+pub fn installed_syscall_sandboxes() -> Vec<InstalledSyscallSandbox> {
+    INSTALLED_SANDBOXES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
 /// Enables [seccomp]-based syscall sandboxing in the current thread and all the threads spawned
 /// by it.
 ///
@@ -349,13 +452,25 @@ pub fn enable_syscall_sandboxing(
     violation_action: ViolationAction,
     exception_rules: &Vec<Rule>,
 ) -> BootstrapResult<()> {
+    // NOTE: the `SIGSYS` handler returns with `rt_sigreturn`, which would be denied otherwise.
+    let sigreturn_rule = Rule::Allow(Syscall::rt_sigreturn, vec![]);
+    let mut implicit_rule = None;
+
+    if violation_action == ViolationAction::DenyAndCount {
+        violations::install_sigsys_handler()?;
+
+        if !exception_rules.contains(&sigreturn_rule) {
+            implicit_rule = Some(&sigreturn_rule);
+        }
+    }
+
     let ctx = unsafe { sys::seccomp_init(violation_action as u32) };
 
     if ctx.is_null() {
         bail!("failed to initialize seccomp context");
     }
 
-    for rule in exception_rules {
+    for rule in exception_rules.iter().chain(implicit_rule) {
         let RawRule {
             action,
             syscall,
@@ -387,6 +502,14 @@ pub fn enable_syscall_sandboxing(
         bail!("failed to load seccomp rules with error code {}", load_res);
     }
 
+    INSTALLED_SANDBOXES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(InstalledSyscallSandbox {
+            violation_action,
+            exception_rules: exception_rules.clone(),
+        });
+
     Ok(())
 }
 
@@ -428,6 +551,116 @@ pub fn forbid_x86_64_cpu_cycle_counter() {
     };
 }
 
+/// Filesystem isolation method used by [`isolate_filesystem`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilesystemIsolation {
+    /// Change the root directory of the process with [chroot].
+    ///
+    /// Requires `CAP_SYS_CHROOT` capability. The threads of the process share the root
+    /// directory, so the method can be used at any time, e.g. after the service is initialized,
+    /// and applies to all the existing and future threads.
+    ///
+    /// [chroot]: https://man7.org/linux/man-pages/man2/chroot.2.html
+    Chroot,
+
+    /// Enter a new [mount namespace] with all the mounts made private before changing the root
+    /// directory with [chroot], so mounts made by the process aren't propagated to the host
+    /// and vice versa.
+    ///
+    /// Requires `CAP_SYS_ADMIN` and `CAP_SYS_CHROOT` capabilities. Entering a mount namespace
+    /// also gives the calling thread its own copy of the root directory, so in a multi-threaded
+    /// process the other threads would keep both the host mounts and the host root. Therefore,
+    /// [`isolate_filesystem`] returns an error if the process has more than one thread, and this
+    /// method needs to be used before any threads are spawned (e.g. before the async runtime is
+    /// started and before the telemetry is initialized). The threads spawned afterwards inherit
+    /// the isolation.
+    ///
+    /// [mount namespace]: https://man7.org/linux/man-pages/man7/mount_namespaces.7.html
+    /// [chroot]: https://man7.org/linux/man-pages/man2/chroot.2.html
+    MountNamespaceAndChroot,
+}
+
+/// Restricts the filesystem visible to the process to the `root` directory.
+///
+/// After the call the current working directory of the process is set to the new root and all
+/// the paths are resolved relative to it. Files opened before the call remain accessible, so
+/// the files the service needs outside of the new root (e.g. the settings or the sockets) should
+/// be opened before the call.
+///
+/// With [`FilesystemIsolation::Chroot`] the function can be called at any time, including after
+/// the service initialization. With [`FilesystemIsolation::MountNamespaceAndChroot`] the
+/// function must be called while the process is single-threaded and returns an error otherwise.
+///
+/// Note that this function should be called before [`enable_syscall_sandboxing`], unless the
+/// sandbox allows the syscalls used by the function.
+///
+/// # Examples
+///
+/// ```no_run
+/// use foundations::security::{isolate_filesystem, FilesystemIsolation};
+///
+/// isolate_filesystem("/var/empty", FilesystemIsolation::Chroot).unwrap();
+///
+/// assert!(std::fs::read_dir("/").unwrap().next().is_none());
+/// ```
+pub fn isolate_filesystem(
+    root: impl AsRef<Path>,
+    isolation: FilesystemIsolation,
+) -> BootstrapResult<()> {
+    // NOTE: glibc defines mount flags as enum variants which are not picked up by bindgen, so
+    // we use the values from the kernel ABI instead.
+    const MS_REC: c_ulong = 0x4000;
+    const MS_PRIVATE: c_ulong = 0x40000;
+
+    let root = root.as_ref();
+
+    if isolation == FilesystemIsolation::MountNamespaceAndChroot {
+        // NOTE: `CLONE_NEWNS` implies `CLONE_FS`, so only the calling thread would be isolated.
+        let threads = std::fs::read_dir("/proc/self/task")
+            .context("failed to list the threads of the process")?
+            .count();
+
+        if threads > 1 {
+            bail!(
+                "the mount namespace can only be entered by a single-threaded process, \
+                but the process has {threads} threads"
+            );
+        }
+
+        if unsafe { sys::unshare(sys::CLONE_NEWNS.try_into().unwrap()) } != 0 {
+            bail!(
+                "failed to enter a new mount namespace: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        let mount_res = unsafe {
+            sys::mount(
+                ptr::null(),
+                c"/".as_ptr(),
+                ptr::null(),
+                MS_REC | MS_PRIVATE,
+                ptr::null(),
+            )
+        };
+
+        if mount_res != 0 {
+            bail!(
+                "failed to make mounts private in the new mount namespace: {}",
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    std::os::unix::fs::chroot(root)
+        .with_context(|| format!("failed to change root directory to {}", root.display()))?;
+
+    std::env::set_current_dir("/")
+        .context("failed to change working directory to the new root directory")?;
+
+    Ok(())
+}
+
 // NOTE: `#[doc(hidden)]` + `#[doc(inline)]` for `pub use` trick is used to prevent these macros
 // to show up in the crate's top level docs.
 
@@ -0,0 +1,113 @@
+use crate::BootstrapResult;
+use anyhow::bail;
+use std::ffi::{c_int, c_uint, c_void};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// NOTE: larger than the highest syscall number on both x86_64 and aarch64.
+const MAX_SYSCALLS: usize = 512;
+
+// NOTE: `si_code` of the signals sent by seccomp filters.
+const SYS_SECCOMP: c_int = 1;
+
+static VIOLATION_COUNTS: [AtomicU64; MAX_SYSCALLS] = [const { AtomicU64::new(0) }; MAX_SYSCALLS];
+
+// NOTE: the layout of `siginfo_t` for `SIGSYS` on 64-bit Linux, the `libc` crate doesn't expose
+// the syscall number.
+#[repr(C)]
+struct SigSysInfo {
+    _signo: c_int,
+    _errno: c_int,
+    code: c_int,
+    _pad: c_int,
+    _call_addr: *mut c_void,
+    syscall: c_int,
+    _arch: c_uint,
+}
+
+/// Returns the number of the syscalls denied by the sandboxes installed with
+/// [`ViolationAction::DenyAndCount`] in the process, per syscall number.
+///
+/// Only the syscalls that have been denied at least once are returned.
+///
+/// # Examples
+/// ```
+/// use foundations::security::common_syscall_allow_lists::SERVICE_BASICS;
+/// use foundations::security::{
+///     enable_syscall_sandboxing, syscall_violation_counts, Syscall, ViolationAction,
+/// };
+/// use std::net::TcpListener;
+///
+/// enable_syscall_sandboxing(ViolationAction::DenyAndCount, &SERVICE_BASICS).unwrap();
+///
+/// assert!(TcpListener::bind("127.0.0.1:0").is_err());
+/// assert!(syscall_violation_counts().contains(&(Syscall::socket as i32, 1)));
+/// ```
+///
+/// [`ViolationAction::DenyAndCount`]: super::ViolationAction::DenyAndCount
+pub fn syscall_violation_counts() -> Vec<(i32, u64)> {
+    VIOLATION_COUNTS
+        .iter()
+        .enumerate()
+        .map(|(syscall, count)| (syscall as i32, count.load(Ordering::Relaxed)))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+pub(super) fn install_sigsys_handler() -> BootstrapResult<()> {
+    let handler: extern "C" fn(c_int, *mut libc::siginfo_t, *mut c_void) = on_sigsys;
+
+    // SAFETY: `sigaction` is only given valid pointers to initialized structures.
+    let res = unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGSYS, &action, ptr::null_mut())
+    };
+
+    if res != 0 {
+        bail!(
+            "failed to install SIGSYS handler: {}",
+            io::Error::last_os_error()
+        );
+    }
+
+    Ok(())
+}
+
+// NOTE: async-signal-safe: only updates the atomic counters and the registers of the interrupted
+// thread.
+extern "C" fn on_sigsys(_signal: c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    let info = unsafe { &*(info as *const SigSysInfo) };
+
+    // NOTE: the signal is sent by something else than a sandbox, e.g. with `kill`.
+    if info.code != SYS_SECCOMP {
+        return;
+    }
+
+    if let Some(count) = usize::try_from(info.syscall)
+        .ok()
+        .and_then(|syscall| VIOLATION_COUNTS.get(syscall))
+    {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // NOTE: the syscall is skipped by the kernel, so its return value is the value of the return
+    // register once the handler returns.
+    let context = unsafe { &mut *(context as *mut libc::ucontext_t) };
+    let ret = -libc::ENOSYS as i64;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        context.uc_mcontext.gregs[libc::REG_RAX as usize] = ret;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        context.uc_mcontext.regs[0] = ret as u64;
+    }
+}
@@ -1,4 +1,4 @@
-use super::Settings;
+use super::{MergeStrategy, Settings};
 use indexmap::IndexSet;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -77,6 +77,21 @@ macro_rules! impl_for_ref {
             ) {
                 (**self).add_docs(parent_key, docs);
             }
+
+            #[inline]
+            fn add_merge_strategies(
+                parent_key: &[String],
+                strategies: &mut std::collections::HashMap<Vec<String>, MergeStrategy>,
+            ) {
+                T::add_merge_strategies(parent_key, strategies);
+            }
+
+            fn add_types(
+                parent_key: &[String],
+                types: &mut std::collections::HashMap<Vec<String>, &'static str>,
+            ) {
+                T::add_types(parent_key, types);
+            }
         }
     };
 }
@@ -102,6 +117,26 @@ macro_rules! impl_for_seq {
                     key.pop();
                 }
             }
+
+            fn add_merge_strategies(
+                parent_key: &[String],
+                strategies: &mut std::collections::HashMap<Vec<String>, MergeStrategy>,
+            ) {
+                let mut key = parent_key.to_vec();
+
+                key.push("*".into());
+                T::add_merge_strategies(&key, strategies);
+            }
+
+            fn add_types(
+                parent_key: &[String],
+                types: &mut std::collections::HashMap<Vec<String>, &'static str>,
+            ) {
+                let mut key = parent_key.to_vec();
+
+                key.push("*".into());
+                T::add_types(&key, types);
+            }
         }
     };
 }
@@ -135,4 +170,18 @@ impl<T: Settings> Settings for Option<T> {
             v.add_docs(parent_key, docs);
         }
     }
+
+    fn add_merge_strategies(
+        parent_key: &[String],
+        strategies: &mut std::collections::HashMap<Vec<String>, MergeStrategy>,
+    ) {
+        T::add_merge_strategies(parent_key, strategies);
+    }
+
+    fn add_types(
+        parent_key: &[String],
+        types: &mut std::collections::HashMap<Vec<String>, &'static str>,
+    ) {
+        T::add_types(parent_key, types);
+    }
 }
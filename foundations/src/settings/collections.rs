@@ -2,7 +2,7 @@
 //!
 //! [`Settings`]: super::Settings
 
-use super::Settings;
+use super::{MergeStrategy, Settings};
 use indexmap::map::{IntoIter, Iter, IterMut};
 use indexmap::IndexMap;
 use serde::de::DeserializeOwned;
@@ -150,4 +150,21 @@ where
             v.add_docs(&key, docs);
         }
     }
+
+    fn add_merge_strategies(
+        parent_key: &[String],
+        strategies: &mut HashMap<Vec<String>, MergeStrategy>,
+    ) {
+        let mut key = parent_key.to_vec();
+
+        key.push("*".into());
+        V::add_merge_strategies(&key, strategies);
+    }
+
+    fn add_types(parent_key: &[String], types: &mut HashMap<Vec<String>, &'static str>) {
+        let mut key = parent_key.to_vec();
+
+        key.push("*".into());
+        V::add_types(&key, types);
+    }
 }
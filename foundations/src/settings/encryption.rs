@@ -0,0 +1,141 @@
+//! Support for settings files encrypted with [sops].
+//!
+//! [sops]: https://github.com/getsops/sops
+
+use crate::BootstrapResult;
+use anyhow::{bail, Context};
+use serde_yaml::Value;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{PoisonError, RwLock};
+
+const DEFAULT_SOPS_PATH: &str = "/usr/bin/sops";
+
+static SOPS_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Sets the absolute path of the `sops` binary the settings files encrypted with [sops] are
+/// decrypted with, `/usr/bin/sops` by default.
+///
+/// The binary is never looked up in `PATH`, so it can't be substituted by changing
+/// the environment of the service. Applies to all the settings files read afterwards, see
+/// [`from_file`].
+///
+/// [sops]: https://github.com/getsops/sops
+/// [`from_file`]: super::from_file
+pub fn set_sops_path(path: impl Into<PathBuf>) {
+    *SOPS_PATH.write().unwrap_or_else(PoisonError::into_inner) = Some(path.into());
+}
+
+fn sops_path() -> BootstrapResult<PathBuf> {
+    let path = SOPS_PATH
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SOPS_PATH.into());
+
+    if !path.is_absolute() {
+        bail!(
+            "path of the `sops` binary used to decrypt the settings must be absolute, got {}",
+            path.display()
+        );
+    }
+
+    if !path.is_file() {
+        bail!(
+            "`sops` binary used to decrypt the settings doesn't exist at {}",
+            path.display()
+        );
+    }
+
+    Ok(path)
+}
+
+/// Checks whether the parsed YAML document was encrypted with sops.
+///
+/// sops stores its metadata, including the message authentication code for the encrypted data,
+/// under the top level `sops` key of the encrypted document.
+pub(super) fn is_sops_encrypted(value: &Value) -> bool {
+    let Value::Mapping(root) = value else {
+        return false;
+    };
+
+    matches!(
+        root.get(&Value::from("sops")),
+        Some(Value::Mapping(metadata)) if metadata.contains_key(&Value::from("mac"))
+    )
+}
+
+/// Decrypts the contents of the settings file with the sops binary, see [`set_sops_path`].
+///
+/// The contents are piped to sops, so the file is not read again after it has been read (and
+/// possibly verified) by the caller. The `path` is only used in the error messages.
+///
+/// The decryption keys are discovered by sops itself: age keys are taken from the
+/// `SOPS_AGE_KEY` or `SOPS_AGE_KEY_FILE` environment variables, and KMS keys are accessed with
+/// the credentials from the environment of the cloud provider.
+pub(super) fn decrypt_sops_data(path: &Path, data: &str) -> BootstrapResult<String> {
+    let sops = sops_path()?;
+
+    let mut child = Command::new(&sops)
+        .args(["--decrypt", "--input-type", "yaml", "--output-type", "yaml"])
+        .arg("/dev/stdin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| {
+            format!(
+                "failed to run `{}` to decrypt the encrypted settings file {}",
+                sops.display(),
+                path.display()
+            )
+        })?;
+
+    // NOTE: stdin is closed once written, so sops sees the end of the file.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        bail!(
+            "failed to decrypt settings file {} with `{}` ({}): {}",
+            path.display(),
+            sops.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_sops_encrypted() {
+        let encrypted = r#"
+foo: ENC[AES256_GCM,data:8Q==,iv:AAAA,tag:AAAA,type:int]
+sops:
+    age:
+        - recipient: age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p
+          enc: |
+            -----BEGIN AGE ENCRYPTED FILE-----
+            -----END AGE ENCRYPTED FILE-----
+    lastmodified: "2023-10-10T10:00:00Z"
+    mac: ENC[AES256_GCM,data:AAAA,iv:AAAA,tag:AAAA,type:str]
+    version: 3.8.1
+"#;
+
+        let is_sops_encrypted = |data| is_sops_encrypted(&serde_yaml::from_str(data).unwrap());
+
+        assert!(is_sops_encrypted(encrypted));
+        assert!(!is_sops_encrypted("foo: 42\n"));
+        assert!(!is_sops_encrypted("sops: 42\n"));
+        assert!(!is_sops_encrypted("- foo\n- bar\n"));
+    }
+}
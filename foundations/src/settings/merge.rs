@@ -0,0 +1,213 @@
+use serde_yaml::Value;
+use std::collections::HashMap;
+
+/// Strategy of merging a settings field of a profile overlay into the base settings.
+///
+/// Mappings are always merged key by key, the strategy determines how the lists are merged.
+/// Strategies are specified for the fields with the `#[settings(...)]` field attribute of the
+/// [`settings`] macro:
+///
+/// * `#[settings(merge = "replace")]` - [`MergeStrategy::Replace`] (the default).
+/// * `#[settings(merge = "append")]` - [`MergeStrategy::Append`].
+/// * `#[settings(merge_by_key = "name")]` - [`MergeStrategy::MergeByKey`] with the `name` key.
+///
+/// # Examples
+/// ```
+/// # use foundations::settings::{from_yaml_str_with_profile, settings};
+/// #
+/// #[settings]
+/// struct Upstream {
+///     /// Upstream name
+///     name: String,
+///
+///     /// Upstream address
+///     addr: String,
+///
+///     /// Upstream weight
+///     weight: u32,
+/// }
+///
+/// #[settings]
+/// struct ServiceSettings {
+///     /// Upstreams
+///     #[settings(merge_by_key = "name")]
+///     upstreams: Vec<Upstream>,
+///
+///     /// Allowed client networks
+///     #[settings(merge = "append")]
+///     allowed_networks: Vec<String>,
+/// }
+///
+/// let yaml = r#"
+/// upstreams:
+///   - name: primary
+///     addr: 10.0.0.1:8080
+///     weight: 1
+/// allowed_networks:
+///   - 10.0.0.0/8
+/// profiles:
+///   prod:
+///     upstreams:
+///       - name: primary
+///         weight: 10
+///       - name: backup
+///         addr: 10.0.0.2:8080
+///     allowed_networks:
+///       - 192.168.0.0/16
+/// "#;
+///
+/// let settings: ServiceSettings = from_yaml_str_with_profile(yaml, "prod").unwrap();
+///
+/// assert_eq!(settings.upstreams.len(), 2);
+/// assert_eq!(settings.upstreams[0].addr, "10.0.0.1:8080");
+/// assert_eq!(settings.upstreams[0].weight, 10);
+/// assert_eq!(settings.upstreams[1].name, "backup");
+/// assert_eq!(settings.allowed_networks, ["10.0.0.0/8", "192.168.0.0/16"]);
+/// ```
+///
+/// [`settings`]: crate::settings::settings
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The list of the overlay replaces the base list.
+    Replace,
+    /// The elements of the overlay list are appended to the base list.
+    Append,
+    /// The elements of the overlay list are merged with the elements of the base list that have
+    /// the same value of the given key, the rest of the elements are appended to the base list.
+    MergeByKey(&'static str),
+}
+
+/// Merge strategies of the settings fields by their key.
+///
+/// The `*` key segment matches any list index or map key.
+pub(super) type MergeStrategies = HashMap<Vec<String>, MergeStrategy>;
+
+pub(super) fn merge_overlay(base: &mut Value, overlay: Value, strategies: &MergeStrategies) {
+    merge_value(base, overlay, &mut vec![], strategies);
+}
+
+fn merge_value(
+    base: &mut Value,
+    overlay: Value,
+    key: &mut Vec<String>,
+    strategies: &MergeStrategies,
+) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (field, value) in overlay {
+                match base.get_mut(&field) {
+                    Some(base_value) => {
+                        key.push(key_segment(&field));
+                        merge_value(base_value, value, key, strategies);
+                        key.pop();
+                    }
+                    None => {
+                        base.insert(field, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(overlay)) => match strategy(key, strategies) {
+            Some(MergeStrategy::Append) => base.extend(overlay),
+            Some(MergeStrategy::MergeByKey(field)) => {
+                key.push("*".into());
+
+                for value in overlay {
+                    let existing = value
+                        .get(field)
+                        .and_then(|id| base.iter_mut().find(|base| base.get(field) == Some(id)));
+
+                    match existing {
+                        Some(base_value) => merge_value(base_value, value, key, strategies),
+                        None => base.push(value),
+                    }
+                }
+
+                key.pop();
+            }
+            Some(MergeStrategy::Replace) | None => *base = overlay,
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn key_segment(field: &Value) -> String {
+    match field {
+        Value::String(field) => field.clone(),
+        field => serde_yaml::to_string(field)
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn strategy<'s>(key: &[String], strategies: &'s MergeStrategies) -> Option<&'s MergeStrategy> {
+    strategies.get(key).or_else(|| {
+        strategies
+            .iter()
+            .find(|(pattern, _)| {
+                pattern.len() == key.len()
+                    && pattern
+                        .iter()
+                        .zip(key)
+                        .all(|(pattern, segment)| pattern == "*" || pattern == segment)
+            })
+            .map(|(_, strategy)| strategy)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn merges_nested_lists_by_key() {
+        let mut base = yaml(
+            r#"
+            pools:
+              - name: a
+                endpoints: [1]
+              - name: b
+                endpoints: [2]
+            "#,
+        );
+
+        let overlay = yaml(
+            r#"
+            pools:
+              - name: b
+                endpoints: [3]
+              - name: c
+                endpoints: [4]
+            "#,
+        );
+
+        let strategies = MergeStrategies::from([
+            (vec!["pools".into()], MergeStrategy::MergeByKey("name")),
+            (
+                vec!["pools".into(), "*".into(), "endpoints".into()],
+                MergeStrategy::Append,
+            ),
+        ]);
+
+        merge_overlay(&mut base, overlay, &strategies);
+
+        assert_eq!(
+            base,
+            yaml(
+                r#"
+                pools:
+                  - name: a
+                    endpoints: [1]
+                  - name: b
+                    endpoints: [2, 3]
+                  - name: c
+                    endpoints: [4]
+                "#
+            )
+        );
+    }
+}
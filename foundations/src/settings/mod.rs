@@ -137,15 +137,64 @@
 //! }
 //! ```
 //!
+//! # Profiles
+//!
+//! Settings for all the environments of a service can be kept in a single file with profiles.
+//! Profiles are named overlays specified in the top-level `profiles` key of the file. When
+//! settings are parsed with a profile, e.g. with [`from_file_with_profile`] or with the
+//! `--profile` command line option of [`Cli`], the overlay of the profile is merged on top of
+//! the rest of the file: nested mappings are merged key by key, while all the other values,
+//! including lists, are replaced by the values from the overlay. Lists can be extended by
+//! the overlay instead with the field's [`MergeStrategy`].
+//!
+//! ```
+//! # use foundations::settings::{from_yaml_str_with_profile, settings};
+//! #
+//! #[settings]
+//! struct ServiceSettings {
+//!     /// Upstream address
+//!     upstream: String,
+//!
+//!     /// Number of worker threads
+//!     workers: usize,
+//! }
+//!
+//! let yaml = r#"
+//! upstream: 127.0.0.1:8080
+//! workers: 1
+//! profiles:
+//!   prod:
+//!     upstream: 10.0.0.1:8080
+//! "#;
+//!
+//! let settings: ServiceSettings = from_yaml_str_with_profile(yaml, "prod").unwrap();
+//!
+//! assert_eq!(settings.upstream, "10.0.0.1:8080");
+//! assert_eq!(settings.workers, 1);
+//! ```
+//!
 //! [`Cli`]: crate::cli::Cli
 //! [`ipnetwork::Ipv4Network`]: https://docs.rs/ipnetwork/0.20.0/ipnetwork/struct.Ipv4Network.html
 
 mod basic_impls;
+mod encryption;
+mod merge;
+mod provenance;
+mod reference;
+mod signature;
 
 pub mod collections;
 pub mod net;
+pub mod schedule;
+
+pub use self::encryption::set_sops_path;
+pub use self::merge::MergeStrategy;
+pub use self::provenance::{SettingsProvenance, ValueSource};
+pub use self::reference::{SettingsField, SettingsReference, SettingsReferenceFormat};
+pub use self::signature::TrustBundle;
 
 use crate::BootstrapResult;
+use anyhow::anyhow;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::collections::HashMap;
@@ -397,6 +446,28 @@ pub trait Settings: Default + Clone + Serialize + DeserializeOwned + Debug + 'st
         _docs: &mut HashMap<Vec<String>, &'static [&'static str]>,
     ) {
     }
+
+    /// Add [merge strategies] of the settings fields.
+    ///
+    /// Similarly to [`Settings::add_docs`], strategies for each field need to be added to
+    /// the provided hashmap with the key consisting of the provided `parent_key` appended with
+    /// the field name, and implementors need to manually call the method for the fields that
+    /// also implement the trait. Elements of lists and maps are denoted by the `*` key segment.
+    ///
+    /// Fields without a strategy are merged with [`MergeStrategy::Replace`].
+    ///
+    /// [merge strategies]: MergeStrategy
+    fn add_merge_strategies(
+        _parent_key: &[String],
+        _strategies: &mut HashMap<Vec<String>, MergeStrategy>,
+    ) {
+    }
+
+    /// Add Rust types of the settings fields, as they are spelled in the settings structures.
+    ///
+    /// The types are added the same way as the strategies in [`Settings::add_merge_strategies`],
+    /// and are reported by [`SettingsReference`].
+    fn add_types(_parent_key: &[String], _types: &mut HashMap<Vec<String>, &'static str>) {}
 }
 
 /// Serialize documented settings as a YAML string.
@@ -467,21 +538,164 @@ pub fn to_yaml_file(settings: &impl Settings, path: impl AsRef<Path>) -> Bootstr
 ///
 /// [YAML key references]: https://yaml.org/type/merge.html
 pub fn from_yaml_str<T: Settings>(data: impl AsRef<str>) -> BootstrapResult<T> {
-    let de = serde_yaml::Deserializer::from_str(data.as_ref());
-    let value: serde_yaml::Value = serde_path_to_error::deserialize(de)?;
-    // NOTE: merge dict key refs: https://yaml.org/type/merge.html
-    let value = yaml_merge_keys::merge_keys_serde(value)?;
+    parse_yaml(data.as_ref(), None)
+}
 
-    Ok(serde_path_to_error::deserialize(value)?)
+/// Parse settings from YAML string, applying the overlay of the [profile] on top of the base
+/// settings.
+///
+/// Returns an error if the profile is not defined.
+///
+/// Note: [YAML key references] will be merged during parsing.
+///
+/// [profile]: crate::settings#profiles
+/// [YAML key references]: https://yaml.org/type/merge.html
+pub fn from_yaml_str_with_profile<T: Settings>(
+    data: impl AsRef<str>,
+    profile: &str,
+) -> BootstrapResult<T> {
+    parse_yaml(data.as_ref(), Some(profile))
 }
 
 /// Parse settings from YAML file.
 ///
 /// Note: [YAML key references] will be merged during parsing.
 ///
+/// Files encrypted with [sops] are detected automatically and decrypted with the `sops` binary
+/// at an absolute path, `/usr/bin/sops` by default, see [`set_sops_path`]. The decryption key is taken by sops from the environment,
+/// e.g. from the `SOPS_AGE_KEY_FILE` environment variable for [age] keys, or from the cloud
+/// provider's credentials for KMS keys. This allows complete configuration files to be stored
+/// in version control.
+///
 /// [YAML key references]: https://yaml.org/type/merge.html
+/// [sops]: https://github.com/getsops/sops
+/// [age]: https://age-encryption.org/
 pub fn from_file<T: Settings>(path: impl AsRef<Path>) -> BootstrapResult<T> {
-    let data = std::fs::read_to_string(path)?;
+    parse_value(read_file(path.as_ref(), None)?.1, None)
+}
 
-    from_yaml_str(data)
+/// Parse settings from YAML file, applying the overlay of the [profile] on top of the base
+/// settings.
+///
+/// Returns an error if the profile is not defined. See [`from_file`] for the details of
+/// the file parsing.
+///
+/// [profile]: crate::settings#profiles
+pub fn from_file_with_profile<T: Settings>(
+    path: impl AsRef<Path>,
+    profile: &str,
+) -> BootstrapResult<T> {
+    parse_value(read_file(path.as_ref(), None)?.1, Some(profile))
+}
+
+/// Parse settings from YAML file, verifying its detached signature before parsing.
+///
+/// The signature is read from `<path>.sig` and must be made by one of the public keys of
+/// the [`TrustBundle`], a file with one or more PEM-encoded ECDSA or RSA public keys. Files with
+/// a missing or invalid signature are rejected, so tampered settings files are not used even if
+/// the host is compromised. The signature is verified with the `openssl` binary at the absolute
+/// path specified by the trust bundle.
+///
+/// The signatures are compatible with the ones produced by [cosign] for blobs, or can be
+/// produced with openssl:
+///
+/// ```sh
+/// cosign sign-blob --key cosign.key --tlog-upload=false config.yaml > config.yaml.sig
+/// # or
+/// openssl dgst -sha256 -sign key.pem config.yaml | openssl base64 -A > config.yaml.sig
+/// ```
+///
+/// Only signatures made with a key pair are supported: keyless [sigstore] signatures, i.e.
+/// Fulcio certificates and Rekor transparency log entries, are not verified, so the files must
+/// be signed with `--tlog-upload=false` and the public keys must be distributed in the trust
+/// bundle.
+///
+/// Files encrypted with [sops] are verified in the encrypted form, and then the verified contents
+/// are decrypted without reading the file again. See [`from_file`] for the details of the file
+/// parsing.
+///
+/// [sigstore]: https://www.sigstore.dev/
+/// [cosign]: https://github.com/sigstore/cosign
+/// [sops]: https://github.com/getsops/sops
+pub fn from_signed_file<T: Settings>(
+    path: impl AsRef<Path>,
+    trust_bundle: &TrustBundle,
+) -> BootstrapResult<T> {
+    parse_value(read_file(path.as_ref(), Some(trust_bundle))?.1, None)
+}
+
+/// Parse settings from YAML file, verifying its detached signature before parsing and applying
+/// the overlay of the [profile] on top of the base settings.
+///
+/// See [`from_signed_file`] for the details of the signature verification.
+///
+/// [profile]: crate::settings#profiles
+pub fn from_signed_file_with_profile<T: Settings>(
+    path: impl AsRef<Path>,
+    profile: &str,
+    trust_bundle: &TrustBundle,
+) -> BootstrapResult<T> {
+    parse_value(
+        read_file(path.as_ref(), Some(trust_bundle))?.1,
+        Some(profile),
+    )
+}
+
+// NOTE: returns the decrypted contents of the file along with their parsed YAML value.
+pub(crate) fn read_file(
+    path: &Path,
+    trust_bundle: Option<&TrustBundle>,
+) -> BootstrapResult<(String, serde_yaml::Value)> {
+    let data = std::fs::read(path)?;
+
+    if let Some(trust_bundle) = trust_bundle {
+        signature::verify_signature(path, &data, trust_bundle)?;
+    }
+
+    let data = String::from_utf8(data)?;
+    let value = parse_yaml_value(&data)?;
+
+    if encryption::is_sops_encrypted(&value) {
+        let data = encryption::decrypt_sops_data(path, &data)?;
+        let value = parse_yaml_value(&data)?;
+
+        return Ok((data, value));
+    }
+
+    Ok((data, value))
+}
+
+fn parse_yaml<T: Settings>(data: &str, profile: Option<&str>) -> BootstrapResult<T> {
+    parse_value(parse_yaml_value(data)?, profile)
+}
+
+fn parse_yaml_value(data: &str) -> BootstrapResult<serde_yaml::Value> {
+    let de = serde_yaml::Deserializer::from_str(data);
+
+    Ok(serde_path_to_error::deserialize(de)?)
+}
+
+pub(crate) fn parse_value<T: Settings>(
+    value: serde_yaml::Value,
+    profile: Option<&str>,
+) -> BootstrapResult<T> {
+    const PROFILES_KEY: &str = "profiles";
+
+    // NOTE: merge dict key refs: https://yaml.org/type/merge.html
+    let mut value = yaml_merge_keys::merge_keys_serde(value)?;
+
+    if let Some(profile) = profile {
+        let overlay = value
+            .as_mapping_mut()
+            .and_then(|base| base.remove(&PROFILES_KEY.into()))
+            .and_then(|mut profiles| profiles.as_mapping_mut()?.remove(&profile.into()))
+            .ok_or_else(|| anyhow!("settings profile `{profile}` is not defined"))?;
+
+        let mut strategies = Default::default();
+
+        T::add_merge_strategies(&[], &mut strategies);
+        merge::merge_overlay(&mut value, overlay, &strategies);
+    }
+
+    Ok(serde_path_to_error::deserialize(value)?)
 }
@@ -0,0 +1,699 @@
+use crate::BootstrapResult;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+const PROFILES_KEY: &str = "profiles";
+const MERGE_KEY: &str = "<<";
+
+/// Sources of the effective values of the settings fields.
+///
+/// The provenance is computed from the same YAML the settings are parsed from, applying
+/// the [profile] and [YAML key references] the same way, so it reports the line each field
+/// is set at. It's used for the output of the `--check-config` command line option of the CLI.
+#[cfg_attr(
+    feature = "telemetry-server",
+    doc = "It can also be served by the telemetry server with \
+           [`SettingsProvenance::telemetry_server_route`]."
+)]
+///
+/// # Examples
+/// ```
+/// use foundations::settings::{SettingsProvenance, ValueSource};
+///
+/// let yaml = r#"
+/// upstream: 127.0.0.1:8080
+/// listener:
+///   addrs: [127.0.0.1:80]
+/// profiles:
+///   prod:
+///     upstream: 10.0.0.1:8080
+/// "#;
+///
+/// let provenance = SettingsProvenance::from_yaml_str(yaml, Some("prod")).unwrap();
+///
+/// assert_eq!(
+///     provenance.get("upstream"),
+///     ValueSource::Profile {
+///         profile: "prod".into(),
+///         line: 7
+///     }
+/// );
+/// assert_eq!(provenance.get("listener.addrs.0"), ValueSource::File { line: 4 });
+/// assert_eq!(provenance.get("workers"), ValueSource::Default);
+/// ```
+///
+/// [profile]: crate::settings#profiles
+/// [YAML key references]: https://yaml.org/type/merge.html
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SettingsProvenance {
+    file: Option<PathBuf>,
+    profile: Option<String>,
+    fields: BTreeMap<String, ValueSource>,
+}
+
+/// Source of the effective value of a settings field, see [`SettingsProvenance`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum ValueSource {
+    /// The field has its default value.
+    Default,
+
+    /// The field is set in the settings at the line.
+    File {
+        /// 1-based number of the line.
+        line: usize,
+    },
+
+    /// The field is set by the overlay of the profile at the line.
+    ///
+    /// The lists of the profile are reported as set by the profile as a whole, even if they are
+    /// merged with the base lists with [`MergeStrategy::Append`] or [`MergeStrategy::MergeByKey`].
+    ///
+    /// [`MergeStrategy::Append`]: super::MergeStrategy::Append
+    /// [`MergeStrategy::MergeByKey`]: super::MergeStrategy::MergeByKey
+    Profile {
+        /// Name of the profile.
+        profile: String,
+
+        /// 1-based number of the line.
+        line: usize,
+    },
+}
+
+impl SettingsProvenance {
+    /// Computes the provenance of the settings parsed from the YAML string, optionally with
+    /// the overlay of the [profile].
+    ///
+    /// Returns an error if the YAML is malformed or the profile is not defined.
+    ///
+    /// [profile]: crate::settings#profiles
+    pub fn from_yaml_str(data: impl AsRef<str>, profile: Option<&str>) -> BootstrapResult<Self> {
+        let mut walker = Walker::new(profile);
+        let mut parser = Parser::new(data.as_ref().chars());
+
+        parser.load(&mut walker, false)?;
+
+        if let Some(profile) = profile {
+            if !walker.profile_found {
+                return Err(anyhow!("settings profile `{profile}` is not defined"));
+            }
+        }
+
+        let [mut base, overlay, _] = walker.fields;
+
+        base.apply_overlay(overlay);
+
+        let fields = base
+            .0
+            .into_iter()
+            .filter(|(_, entry)| entry.leaf)
+            .map(|(path, entry)| {
+                let source = match entry.overlay {
+                    true => ValueSource::Profile {
+                        profile: profile.unwrap_or_default().to_string(),
+                        line: entry.line,
+                    },
+                    false => ValueSource::File { line: entry.line },
+                };
+
+                (path.join("."), source)
+            })
+            .collect();
+
+        Ok(Self {
+            file: None,
+            profile: profile.map(Into::into),
+            fields,
+        })
+    }
+
+    /// Computes the provenance of the settings parsed from the YAML file, optionally with
+    /// the overlay of the [profile].
+    ///
+    /// The lines of the files encrypted with [sops] are the lines of the decrypted YAML. See
+    /// [`SettingsProvenance::from_yaml_str`] for the details.
+    ///
+    /// [profile]: crate::settings#profiles
+    /// [sops]: https://github.com/getsops/sops
+    pub fn from_file(path: impl AsRef<Path>, profile: Option<&str>) -> BootstrapResult<Self> {
+        let path = path.as_ref();
+        let (data, _) = super::read_file(path, None)?;
+
+        Self::from_file_data(path, &data, profile)
+    }
+
+    // NOTE: `data` is the decrypted contents of the file, so the file is not read again if its
+    // contents are already read, e.g. by the CLI.
+    pub(crate) fn from_file_data(
+        path: &Path,
+        data: &str,
+        profile: Option<&str>,
+    ) -> BootstrapResult<Self> {
+        Ok(Self {
+            file: Some(path.to_path_buf()),
+            ..Self::from_yaml_str(data, profile)?
+        })
+    }
+
+    /// Returns the source of the value of the field.
+    ///
+    /// The field is specified by its path in the YAML with the parts joined by `.`, e.g.
+    /// `telemetry.logging.verbosity`, with the indices of the list items, e.g.
+    /// `listeners.listeners.0.addr`. The fields nested in a list or a value that is set as
+    /// a whole are reported with the source of that value.
+    pub fn get(&self, field: &str) -> ValueSource {
+        let mut path = field;
+
+        loop {
+            if let Some(source) = self.fields.get(path) {
+                return source.clone();
+            }
+
+            match path.rsplit_once('.') {
+                Some((parent, _)) => path = parent,
+                None => return ValueSource::Default,
+            }
+        }
+    }
+
+    /// Returns the paths and the sources of the fields that don't have the default values, in
+    /// alphabetical order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &ValueSource)> {
+        self.fields
+            .iter()
+            .map(|(field, source)| (field.as_str(), source))
+    }
+
+    /// Returns a telemetry server route that serves the provenance in JSON.
+    ///
+    /// The route should be passed to [`init_with_server`], usually with the `/debug/settings`
+    /// path.
+    ///
+    /// [`init_with_server`]: crate::telemetry::init_with_server
+    #[cfg(all(
+        feature = "telemetry-server",
+        any(feature = "logging", feature = "metrics", feature = "tracing")
+    ))]
+    pub fn telemetry_server_route(&self, path: &str) -> crate::telemetry::TelemetryServerRoute {
+        use futures_util::FutureExt;
+        use hyper::{header, Method, Response, StatusCode};
+
+        let provenance = self.clone();
+
+        crate::telemetry::TelemetryServerRoute {
+            path: path.into(),
+            methods: vec![Method::GET],
+            handler: Box::new(move |_, _| {
+                let res = match serde_json::to_string(&provenance) {
+                    Ok(json) => Response::builder()
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .body(json.into()),
+                    Err(err) => Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(err.to_string().into()),
+                };
+
+                async move { Ok(res.unwrap()) }.boxed()
+            }),
+        }
+    }
+}
+
+impl fmt::Display for SettingsProvenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = self
+            .file
+            .as_deref()
+            .map_or("<settings>".into(), Path::to_string_lossy);
+
+        for (field, source) in &self.fields {
+            match source {
+                ValueSource::Default => writeln!(f, "{field}: default")?,
+                ValueSource::File { line } => writeln!(f, "{field}: {file}:{line}")?,
+                ValueSource::Profile { profile, line } => {
+                    writeln!(f, "{field}: {file}:{line} (profile `{profile}`)")?
+                }
+            }
+        }
+
+        write!(f, "all the other fields have the default values")
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    line: usize,
+    // NOTE: scalars and lists, as opposed to mappings that are merged key by key.
+    leaf: bool,
+    merged: bool,
+    overlay: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Explicit,
+    // NOTE: merged with a `<<` key into the mapping at the path of the length.
+    Merged { base: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Base,
+    Overlay,
+    // NOTE: the overlays of the other profiles, only kept for their anchors.
+    Ignored,
+}
+
+#[derive(Default)]
+struct Fields(BTreeMap<Vec<String>, Entry>);
+
+impl Fields {
+    fn insert(&mut self, path: Vec<String>, line: usize, leaf: bool, origin: Origin) {
+        if path.is_empty() {
+            return;
+        }
+
+        let entry = Entry {
+            line,
+            leaf,
+            merged: origin != Origin::Explicit,
+            overlay: false,
+        };
+
+        match origin {
+            Origin::Explicit => {
+                // NOTE: explicit keys take precedence over the merged ones, and the scalars and
+                // lists replace everything nested in the field.
+                self.0
+                    .retain(|p, e| !(p.starts_with(&path) && (leaf || e.merged)));
+                self.0.insert(path, entry);
+            }
+            Origin::Merged { base } => {
+                // NOTE: the first merged value takes precedence, and the merges are shallow.
+                let shadowed = (base + 1..=path.len()).any(|len| self.0.contains_key(&path[..len]));
+
+                if !shadowed && leaf {
+                    self.0.insert(path, entry);
+                }
+            }
+        }
+    }
+
+    fn subtree(&self, path: &[String]) -> Vec<(Vec<String>, Entry)> {
+        self.0
+            .iter()
+            .filter(|(p, _)| p.starts_with(path))
+            .map(|(p, entry)| (p[path.len()..].to_vec(), *entry))
+            .collect()
+    }
+
+    fn apply_overlay(&mut self, overlay: Fields) {
+        // NOTE: the parents are visited before the nested fields.
+        for (path, mut entry) in overlay.0 {
+            match entry.leaf {
+                true => self.0.retain(|p, _| !p.starts_with(&path)),
+                false => {
+                    if self.0.get(&path).is_some_and(|e| e.leaf) {
+                        self.0.remove(&path);
+                    }
+
+                    // NOTE: the mappings of the overlay are merged with the base ones.
+                    if self.0.contains_key(&path) {
+                        continue;
+                    }
+                }
+            }
+
+            entry.overlay = true;
+            self.0.insert(path, entry);
+        }
+    }
+}
+
+enum Frame {
+    Mapping {
+        path: Vec<String>,
+        // NOTE: the key and its line, the values are reported at the lines of their keys.
+        key: Option<(String, usize)>,
+        origin: Origin,
+        target: Target,
+        profiles: bool,
+    },
+    // NOTE: a list of aliases under a `<<` key.
+    MergeList {
+        path: Vec<String>,
+        target: Target,
+    },
+    // NOTE: the items of a list or a complex key.
+    Skip {
+        key: bool,
+    },
+}
+
+struct Slot {
+    path: Vec<String>,
+    line: Option<usize>,
+    origin: Origin,
+    target: Target,
+    merge: bool,
+    profiles: bool,
+}
+
+struct AnchoredNode {
+    id: usize,
+    path: Vec<String>,
+    target: Target,
+    depth: usize,
+}
+
+/// Walks the events of the YAML parser, recording the lines of the fields.
+struct Walker<'a> {
+    profile: Option<&'a str>,
+    profile_found: bool,
+    stack: Vec<Frame>,
+    fields: [Fields; 3],
+    anchors: HashMap<usize, Vec<(Vec<String>, Entry)>>,
+    anchored: Vec<AnchoredNode>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(profile: Option<&'a str>) -> Self {
+        Self {
+            profile,
+            profile_found: false,
+            stack: vec![],
+            fields: Default::default(),
+            anchors: Default::default(),
+            anchored: vec![],
+        }
+    }
+
+    fn fields(&mut self, target: Target) -> &mut Fields {
+        &mut self.fields[target as usize]
+    }
+
+    fn slot(&self) -> Option<Slot> {
+        let Some(frame) = self.stack.last() else {
+            return Some(Slot {
+                path: vec![],
+                line: None,
+                origin: Origin::Explicit,
+                target: Target::Base,
+                merge: false,
+                profiles: false,
+            });
+        };
+
+        match frame {
+            Frame::Mapping {
+                path,
+                key: Some((key, line)),
+                origin,
+                target,
+                profiles,
+            } => {
+                let line = Some(*line);
+
+                let slot = if *profiles {
+                    let target = match self.profile == Some(key.as_str()) {
+                        true => Target::Overlay,
+                        false => Target::Ignored,
+                    };
+
+                    Slot {
+                        path: vec![],
+                        line,
+                        origin: Origin::Explicit,
+                        target,
+                        merge: false,
+                        profiles: false,
+                    }
+                } else if key == MERGE_KEY {
+                    Slot {
+                        path: path.clone(),
+                        line,
+                        origin: Origin::Merged { base: path.len() },
+                        target: *target,
+                        merge: true,
+                        profiles: false,
+                    }
+                } else {
+                    let is_root = self.stack.len() == 1 && *target == Target::Base;
+
+                    Slot {
+                        path: [&path[..], std::slice::from_ref(key)].concat(),
+                        line,
+                        origin: *origin,
+                        target: *target,
+                        merge: false,
+                        profiles: is_root && key == PROFILES_KEY,
+                    }
+                };
+
+                Some(slot)
+            }
+            Frame::MergeList { path, target } => Some(Slot {
+                path: path.clone(),
+                line: None,
+                origin: Origin::Merged { base: path.len() },
+                target: *target,
+                merge: true,
+                profiles: false,
+            }),
+            Frame::Mapping { key: None, .. } | Frame::Skip { .. } => None,
+        }
+    }
+
+    fn finish_value(&mut self) {
+        if let Some(Frame::Mapping { key, .. }) = self.stack.last_mut() {
+            *key = None;
+        }
+    }
+
+    fn end_node(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+
+        let depth = self.stack.len();
+
+        if let Some(node) = self.anchored.last().filter(|node| node.depth == depth) {
+            let subtree = self.fields[node.target as usize].subtree(&node.path);
+
+            self.anchors.insert(node.id, subtree);
+            self.anchored.pop();
+        }
+
+        if !matches!(frame, Frame::Skip { key: true }) {
+            self.finish_value();
+        }
+    }
+
+    fn on_key(&mut self, ev: Event, line: usize) {
+        match ev {
+            Event::Scalar(key, ..) => {
+                if let Some(Frame::Mapping { key: k, .. }) = self.stack.last_mut() {
+                    *k = Some((key, line));
+                }
+            }
+            Event::MappingStart(_) | Event::SequenceStart(_) => {
+                self.stack.push(Frame::Skip { key: true });
+            }
+            _ => {}
+        }
+    }
+
+    fn on_value(&mut self, ev: Event, mark: Marker) {
+        let Some(slot) = self.slot() else {
+            // NOTE: the items of the lists are reported with the list.
+            if matches!(ev, Event::MappingStart(_) | Event::SequenceStart(_)) {
+                self.stack.push(Frame::Skip { key: false });
+            }
+
+            return;
+        };
+
+        let line = slot.line.unwrap_or(mark.line());
+
+        match ev {
+            Event::Scalar(_, _, anchor, _) => {
+                if !slot.merge {
+                    self.fields(slot.target)
+                        .insert(slot.path, line, true, slot.origin);
+                }
+
+                self.add_leaf_anchor(anchor, line);
+                self.finish_value();
+            }
+            Event::Alias(id) => {
+                let subtree = self.anchors.get(&id).cloned().unwrap_or_default();
+                let fields = self.fields(slot.target);
+
+                for (rel, entry) in subtree {
+                    let path = [&slot.path[..], &rel[..]].concat();
+
+                    fields.insert(path, entry.line, entry.leaf, slot.origin);
+                }
+
+                self.finish_value();
+            }
+            Event::SequenceStart(anchor) => {
+                if slot.merge && !matches!(self.stack.last(), Some(Frame::MergeList { .. })) {
+                    self.stack.push(Frame::MergeList {
+                        path: slot.path,
+                        target: slot.target,
+                    });
+
+                    return;
+                }
+
+                if !slot.merge {
+                    self.fields(slot.target)
+                        .insert(slot.path, line, true, slot.origin);
+                }
+
+                self.add_leaf_anchor(anchor, line);
+                self.stack.push(Frame::Skip { key: false });
+            }
+            Event::MappingStart(anchor) => {
+                if slot.target == Target::Overlay && slot.path.is_empty() {
+                    self.profile_found = true;
+                }
+
+                if !slot.merge {
+                    self.fields(slot.target)
+                        .insert(slot.path.clone(), line, false, slot.origin);
+                }
+
+                if anchor > 0 {
+                    self.anchored.push(AnchoredNode {
+                        id: anchor,
+                        path: slot.path.clone(),
+                        target: slot.target,
+                        depth: self.stack.len(),
+                    });
+                }
+
+                self.stack.push(Frame::Mapping {
+                    path: slot.path,
+                    key: None,
+                    origin: slot.origin,
+                    target: slot.target,
+                    profiles: slot.profiles,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn add_leaf_anchor(&mut self, anchor: usize, line: usize) {
+        if anchor > 0 {
+            let entry = Entry {
+                line,
+                leaf: true,
+                merged: false,
+                overlay: false,
+            };
+
+            self.anchors.insert(anchor, vec![(vec![], entry)]);
+        }
+    }
+}
+
+impl MarkedEventReceiver for Walker<'_> {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::MappingEnd | Event::SequenceEnd => self.end_node(),
+            Event::Nothing
+            | Event::StreamStart
+            | Event::StreamEnd
+            | Event::DocumentStart
+            | Event::DocumentEnd => {}
+            _ if matches!(self.stack.last(), Some(Frame::Mapping { key: None, .. })) => {
+                self.on_key(ev, mark.line())
+            }
+            _ => self.on_value(ev, mark),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_lines_of_fields() {
+        let yaml = concat!(
+            "base: &base\n",             // 1
+            "  timeout: 10\n",           // 2
+            "  nested:\n",               // 3
+            "    a: 1\n",                // 4
+            "server:\n",                 // 5
+            "  <<: *base\n",             // 6
+            "  addr: 127.0.0.1:80\n",    // 7
+            "  nested:\n",               // 8
+            "    b: 2\n",                // 9
+            "  tags:\n",                 // 10
+            "    - a\n",                 // 11
+            "profiles:\n",               // 12
+            "  prod:\n",                 // 13
+            "    server:\n",             // 14
+            "      addr: 10.0.0.1:80\n", // 15
+            "  dev:\n",                  // 16
+            "    server:\n",             // 17
+            "      timeout: 1\n",        // 18
+        );
+
+        let provenance = SettingsProvenance::from_yaml_str(yaml, Some("prod")).unwrap();
+
+        assert_eq!(
+            provenance.get("server.timeout"),
+            ValueSource::File { line: 2 }
+        );
+        assert_eq!(
+            provenance.get("server.addr"),
+            ValueSource::Profile {
+                profile: "prod".into(),
+                line: 15
+            }
+        );
+
+        // NOTE: the merges are shallow, the nested mapping replaces the merged one.
+        assert_eq!(provenance.get("server.nested.a"), ValueSource::Default);
+        assert_eq!(
+            provenance.get("server.nested.b"),
+            ValueSource::File { line: 9 }
+        );
+        assert_eq!(
+            provenance.get("server.tags.0"),
+            ValueSource::File { line: 10 }
+        );
+        assert_eq!(provenance.get("server.port"), ValueSource::Default);
+
+        assert_eq!(
+            provenance
+                .fields()
+                .map(|(field, _)| field)
+                .collect::<Vec<_>>(),
+            [
+                "base.nested.a",
+                "base.timeout",
+                "server.addr",
+                "server.nested.b",
+                "server.tags",
+                "server.timeout",
+            ]
+        );
+
+        let provenance = SettingsProvenance::from_yaml_str(yaml, None).unwrap();
+
+        assert_eq!(provenance.get("server.addr"), ValueSource::File { line: 7 });
+
+        assert!(SettingsProvenance::from_yaml_str(yaml, Some("staging")).is_err());
+    }
+}
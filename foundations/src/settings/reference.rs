@@ -0,0 +1,373 @@
+use super::Settings;
+use crate::BootstrapResult;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A reference of the settings fields with their types, default values and documentation.
+///
+/// The reference is generated from the [`Settings`] implementation, so the tables of the settings
+/// in the runbooks can be regenerated whenever the settings change instead of being maintained by
+/// hand. It's used for the output of the `--settings-reference` command line option of the CLI.
+///
+/// # Examples
+/// ```
+/// use foundations::settings::{settings, SettingsReference, SettingsReferenceFormat};
+///
+/// #[settings]
+/// struct HttpServerSettings {
+///     /// Port of the server.
+///     #[serde(default = "HttpServerSettings::default_port")]
+///     port: u16,
+///
+///     /// Upstreams to forward the requests to.
+///     upstreams: Vec<String>,
+/// }
+///
+/// impl HttpServerSettings {
+///     fn default_port() -> u16 {
+///         8080
+///     }
+/// }
+///
+/// let reference = SettingsReference::new(&HttpServerSettings::default()).unwrap();
+///
+/// assert_eq!(
+///     reference.to_table(SettingsReferenceFormat::Markdown),
+///     concat!(
+///         "| Path | Type | Default | Description |\n",
+///         "| --- | --- | --- | --- |\n",
+///         "| `port` | `u16` | `8080` | Port of the server. |\n",
+///         "| `upstreams` | `Vec<String>` | `[]` | Upstreams to forward the requests to. |\n",
+///     )
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SettingsReference {
+    fields: Vec<SettingsField>,
+}
+
+/// A settings field in [`SettingsReference`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettingsField {
+    /// Path of the field in the YAML with the parts joined by `.`, e.g.
+    /// `telemetry.logging.verbosity`.
+    pub path: String,
+
+    /// Rust type of the field, see [`Settings::add_types`].
+    ///
+    /// `None` if the type is unknown, e.g. for the entries of the maps.
+    pub ty: Option<&'static str>,
+
+    /// Default value of the field in the YAML flow style, e.g. `[a, b]`.
+    ///
+    /// Empty for the nested settings structures, as their fields are listed separately.
+    pub default: String,
+
+    /// Doc comments of the field with the lines joined.
+    pub docs: String,
+}
+
+/// Format of the table of the [`SettingsReference`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsReferenceFormat {
+    /// A Markdown table with the `Path`, `Type`, `Default` and `Description` columns.
+    Markdown,
+
+    /// A CSV table with the `path`, `type`, `default` and `description` columns.
+    Csv,
+}
+
+impl SettingsReference {
+    /// Generates the reference of the settings fields, with their values in the `settings` as
+    /// the defaults.
+    ///
+    /// The fields are listed in the order they are serialized in.
+    pub fn new<S: Settings>(settings: &S) -> BootstrapResult<Self> {
+        let mut docs = HashMap::default();
+        let mut types = HashMap::default();
+        let mut reference = Self::default();
+
+        settings.add_docs(&[], &mut docs);
+        S::add_types(&[], &mut types);
+
+        if let Value::Mapping(mapping) = serde_yaml::to_value(settings)? {
+            let mut walker = Walker {
+                docs: &docs,
+                types: &types,
+                key: vec![],
+                type_key: vec![],
+                fields: &mut reference.fields,
+            };
+
+            walker.walk(&mapping)?;
+        }
+
+        Ok(reference)
+    }
+
+    /// Returns the fields of the settings.
+    pub fn fields(&self) -> &[SettingsField] {
+        &self.fields
+    }
+
+    /// Formats the reference as a table.
+    pub fn to_table(&self, format: SettingsReferenceFormat) -> String {
+        match format {
+            SettingsReferenceFormat::Markdown => self.to_markdown(),
+            SettingsReferenceFormat::Csv => self.to_csv(),
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        let code = |s: &str| match s {
+            "" => String::new(),
+            s => format!("`{}`", s.replace('|', "\\|")),
+        };
+
+        let mut table =
+            "| Path | Type | Default | Description |\n| --- | --- | --- | --- |\n".to_string();
+
+        for field in &self.fields {
+            let _ = writeln!(
+                table,
+                "| {} | {} | {} | {} |",
+                code(&field.path),
+                code(field.ty.unwrap_or_default()),
+                code(&field.default),
+                field.docs.replace('|', "\\|")
+            );
+        }
+
+        table
+    }
+
+    fn to_csv(&self) -> String {
+        let cell = |s: &str| match s.contains([',', '"', '\n', '\r']) {
+            true => format!("\"{}\"", s.replace('"', "\"\"")),
+            false => s.to_string(),
+        };
+
+        let mut table = "path,type,default,description\n".to_string();
+
+        for field in &self.fields {
+            let _ = writeln!(
+                table,
+                "{},{},{},{}",
+                cell(&field.path),
+                cell(field.ty.unwrap_or_default()),
+                cell(&field.default),
+                cell(&field.docs)
+            );
+        }
+
+        table
+    }
+}
+
+struct Walker<'a> {
+    docs: &'a HashMap<Vec<String>, &'static [&'static str]>,
+    types: &'a HashMap<Vec<String>, &'static str>,
+    key: Vec<String>,
+    // NOTE: same as `key`, but with the keys of the map entries replaced with `*`.
+    type_key: Vec<String>,
+    fields: &'a mut Vec<SettingsField>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, mapping: &Mapping) -> BootstrapResult<()> {
+        for (name, value) in mapping {
+            let name = match name {
+                Value::String(name) => name.clone(),
+                name => to_flow_yaml(name)?,
+            };
+
+            let is_map_entry = self.types.keys().any(|key| {
+                key.len() > self.type_key.len()
+                    && key.starts_with(&self.type_key)
+                    && key[self.type_key.len()] == "*"
+            });
+
+            self.key.push(name.clone());
+            self.type_key
+                .push(if is_map_entry { "*".into() } else { name });
+
+            let docs = self
+                .docs
+                .get(&self.key)
+                .map(|lines| {
+                    lines
+                        .iter()
+                        .map(|line| line.trim())
+                        // NOTE: the definitions of the rustdoc links are meaningless outside of
+                        // the code.
+                        .filter(|line| !line.is_empty() && !is_link_definition(line))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .unwrap_or_default();
+
+            let default = match value {
+                Value::Mapping(mapping) if !mapping.is_empty() => String::new(),
+                value => to_flow_yaml(value)?,
+            };
+
+            self.fields.push(SettingsField {
+                path: self.key.join("."),
+                ty: match is_map_entry {
+                    true => None,
+                    false => self.types.get(&self.type_key).copied(),
+                },
+                default,
+                docs,
+            });
+
+            if let Value::Mapping(mapping) = value {
+                self.walk(mapping)?;
+            }
+
+            self.key.pop();
+            self.type_key.pop();
+        }
+
+        Ok(())
+    }
+}
+
+fn is_link_definition(line: &str) -> bool {
+    line.starts_with('[') && line.contains("]: ")
+}
+
+fn to_flow_yaml(value: &Value) -> BootstrapResult<String> {
+    Ok(match value {
+        Value::Sequence(seq) => {
+            let items = seq
+                .iter()
+                .map(to_flow_yaml)
+                .collect::<BootstrapResult<Vec<_>>>()?;
+
+            format!("[{}]", items.join(", "))
+        }
+        Value::Mapping(mapping) => {
+            let entries = mapping
+                .iter()
+                .map(|(k, v)| Ok(format!("{}: {}", to_flow_yaml(k)?, to_flow_yaml(v)?)))
+                .collect::<BootstrapResult<Vec<_>>>()?;
+
+            format!("{{{}}}", entries.join(", "))
+        }
+        scalar => {
+            let yaml = serde_yaml::to_string(scalar)?;
+
+            // NOTE: the scalars are serialized as documents, so the document start is stripped.
+            yaml.trim_start_matches("---").trim().to_string()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::collections::Map;
+    use crate::settings::settings;
+
+    #[settings(crate_path = "crate")]
+    struct Upstream {
+        /// Address of the upstream.
+        addr: String,
+
+        /// Weight of the upstream.
+        #[serde(default = "Upstream::default_weight")]
+        weight: u32,
+    }
+
+    impl Upstream {
+        fn default_weight() -> u32 {
+            1
+        }
+    }
+
+    #[settings(crate_path = "crate")]
+    struct Proxy {
+        /// Upstreams of the proxy,
+        /// by [name].
+        ///
+        /// [name]: Upstream
+        #[serde(default = "Proxy::default_upstreams")]
+        upstreams: Map<String, Upstream>,
+
+        /// Hosts, e.g. "example.com", served by the proxy.
+        #[serde(default = "Proxy::default_hosts")]
+        hosts: Vec<String>,
+
+        timeout: Option<u64>,
+    }
+
+    impl Proxy {
+        fn default_upstreams() -> Map<String, Upstream> {
+            let mut upstreams = Map::default();
+
+            upstreams.insert(
+                "origin".into(),
+                Upstream {
+                    addr: "10.0.0.1:80".into(),
+                    weight: 1,
+                },
+            );
+
+            upstreams
+        }
+
+        fn default_hosts() -> Vec<String> {
+            vec!["example.com".into(), "a|b".into()]
+        }
+    }
+
+    #[test]
+    fn lists_fields() {
+        let reference = SettingsReference::new(&Proxy::default()).unwrap();
+
+        assert_eq!(
+            reference
+                .fields()
+                .iter()
+                .map(|f| (f.path.as_str(), f.ty, f.default.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("upstreams", Some("Map<String, Upstream>"), ""),
+                ("upstreams.origin", None, ""),
+                ("upstreams.origin.addr", Some("String"), "\"10.0.0.1:80\""),
+                ("upstreams.origin.weight", Some("u32"), "1"),
+                ("hosts", Some("Vec<String>"), "[example.com, a|b]"),
+                ("timeout", Some("Option<u64>"), "~"),
+            ]
+        );
+
+        assert_eq!(
+            reference.fields()[0].docs,
+            "Upstreams of the proxy, by [name]."
+        );
+        assert_eq!(reference.fields()[2].docs, "Address of the upstream.");
+        assert_eq!(reference.fields()[5].docs, "");
+    }
+
+    #[test]
+    fn formats_tables() {
+        let reference = SettingsReference::new(&Proxy::default()).unwrap();
+        let markdown = reference.to_table(SettingsReferenceFormat::Markdown);
+        let csv = reference.to_table(SettingsReferenceFormat::Csv);
+
+        assert!(markdown.contains(
+            "| `hosts` | `Vec<String>` | `[example.com, a\\|b]` | \
+                Hosts, e.g. \"example.com\", served by the proxy. |\n"
+        ));
+        assert!(markdown.contains("| `upstreams.origin` |  |  |  |\n"));
+
+        assert!(csv.starts_with("path,type,default,description\n"));
+        assert!(csv.contains(
+            "hosts,Vec<String>,\"[example.com, a|b]\",\
+                \"Hosts, e.g. \"\"example.com\"\", served by the proxy.\"\n"
+        ));
+        assert!(csv.contains("upstreams.origin.weight,u32,1,Weight of the upstream.\n"));
+    }
+}
@@ -0,0 +1,269 @@
+//! Scheduled settings changes.
+//!
+//! Staged rollouts often require changing the settings of a running service at a certain time,
+//! e.g. "at 14:00 UTC switch the tracing sampling ratio to 10%". [`ScheduledSettings`] holds
+//! the current settings of the service along with the changes scheduled for the future and
+//! switches to the scheduled settings atomically, once their time comes.
+
+use super::Settings;
+use crate::BootstrapResult;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Settings with the changes scheduled for the future.
+///
+/// The settings are a cheaply cloneable handle, all the clones share the same settings and
+/// schedule. Scheduled changes are applied lazily, on the first access to the settings at
+/// or after the time of the change, so the settings returned by [`ScheduledSettings::current`]
+/// are always up to date with the schedule.
+///
+/// # Examples
+/// ```
+/// use foundations::settings::schedule::ScheduledSettings;
+/// use foundations::settings::settings;
+/// use std::time::{Duration, SystemTime};
+///
+/// #[settings]
+/// struct SamplingSettings {
+///     /// Sampling ratio.
+///     ratio: f64,
+/// }
+///
+/// let settings = ScheduledSettings::new(SamplingSettings { ratio: 1.0 });
+/// let now = SystemTime::now();
+///
+/// settings.schedule(now + Duration::from_secs(3600), SamplingSettings { ratio: 0.1 });
+///
+/// assert_eq!(settings.current().ratio, 1.0);
+/// assert_eq!(settings.current_at(now + Duration::from_secs(3600)).ratio, 0.1);
+/// assert_eq!(settings.current().ratio, 0.1);
+/// ```
+pub struct ScheduledSettings<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Clone for ScheduledSettings<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+struct State<T> {
+    current: Arc<T>,
+    // NOTE: sorted by the time of the change.
+    scheduled: Vec<ScheduledChange<T>>,
+}
+
+/// A settings change scheduled with [`ScheduledSettings::schedule`].
+pub struct ScheduledChange<T> {
+    /// Time when the settings are applied.
+    pub at: SystemTime,
+
+    /// Settings applied at the scheduled time.
+    pub settings: Arc<T>,
+}
+
+impl<T> Clone for ScheduledChange<T> {
+    fn clone(&self) -> Self {
+        Self {
+            at: self.at,
+            settings: Arc::clone(&self.settings),
+        }
+    }
+}
+
+impl<T: Settings> ScheduledSettings<T> {
+    /// Creates new settings without scheduled changes.
+    pub fn new(settings: T) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                current: Arc::new(settings),
+                scheduled: vec![],
+            })),
+        }
+    }
+
+    /// Returns the current settings.
+    pub fn current(&self) -> Arc<T> {
+        self.current_at(SystemTime::now())
+    }
+
+    /// Returns the settings at the given time, applying all the changes scheduled before it.
+    ///
+    /// Changes are not reverted if the given time is in the past.
+    pub fn current_at(&self, now: SystemTime) -> Arc<T> {
+        Arc::clone(&self.state_at(now).current)
+    }
+
+    /// Replaces the current settings, e.g. after the settings have been reloaded from
+    /// the configuration file. The scheduled changes are preserved.
+    pub fn replace(&self, settings: T) {
+        self.lock().current = Arc::new(settings);
+    }
+
+    /// Schedules the settings to be applied at the given time.
+    ///
+    /// The settings are applied on the next access if the time is in the past.
+    pub fn schedule(&self, at: SystemTime, settings: T) {
+        let mut state = self.lock();
+        let pos = state.scheduled.partition_point(|change| change.at <= at);
+
+        state.scheduled.insert(
+            pos,
+            ScheduledChange {
+                at,
+                settings: Arc::new(settings),
+            },
+        );
+    }
+
+    /// Cancels all the scheduled changes.
+    pub fn cancel_scheduled(&self) {
+        self.lock().scheduled.clear();
+    }
+
+    /// Returns the changes that haven't been applied yet, in the order they are applied.
+    pub fn scheduled(&self) -> Vec<ScheduledChange<T>> {
+        self.state_at(SystemTime::now()).scheduled.clone()
+    }
+
+    /// Returns a YAML report with the current settings and the scheduled changes.
+    pub fn report(&self) -> BootstrapResult<String> {
+        #[derive(Serialize)]
+        struct Report<'a, T> {
+            current: &'a T,
+            scheduled: Vec<ChangeReport<'a, T>>,
+        }
+
+        #[derive(Serialize)]
+        struct ChangeReport<'a, T> {
+            /// Unix timestamp in seconds.
+            at: u64,
+            settings: &'a T,
+        }
+
+        let state = self.state_at(SystemTime::now());
+
+        let report = Report {
+            current: &*state.current,
+            scheduled: state
+                .scheduled
+                .iter()
+                .map(|change| ChangeReport {
+                    at: change
+                        .at
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                    settings: &*change.settings,
+                })
+                .collect(),
+        };
+
+        Ok(serde_yaml::to_string(&report)?)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // NOTE: the state is consistent even if a thread panicked while holding the lock.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn state_at(&self, now: SystemTime) -> MutexGuard<'_, State<T>> {
+        let mut state = self.lock();
+        let due = state.scheduled.partition_point(|change| change.at <= now);
+
+        let last = state.scheduled.drain(..due).next_back();
+
+        if let Some(last) = last {
+            state.current = last.settings;
+        }
+
+        state
+    }
+}
+
+#[cfg(all(
+    feature = "telemetry-server",
+    any(feature = "logging", feature = "metrics", feature = "tracing")
+))]
+impl<T: Settings + Send + Sync> ScheduledSettings<T> {
+    /// Returns a telemetry server route that serves the [report] of the settings.
+    ///
+    /// The route should be passed to [`init_with_server`], usually with the `/debug/settings`
+    /// path.
+    ///
+    /// [report]: ScheduledSettings::report
+    /// [`init_with_server`]: crate::telemetry::init_with_server
+    pub fn telemetry_server_route(&self, path: &str) -> crate::telemetry::TelemetryServerRoute {
+        use futures_util::FutureExt;
+        use hyper::{header, Method, Response, StatusCode};
+
+        let settings = self.clone();
+
+        crate::telemetry::TelemetryServerRoute {
+            path: path.into(),
+            methods: vec![Method::GET],
+            handler: Box::new(move |_, _| {
+                let res = match settings.report() {
+                    Ok(report) => Response::builder()
+                        .header(header::CONTENT_TYPE, "application/yaml")
+                        .body(report.into()),
+                    Err(err) => Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(err.to_string().into()),
+                };
+
+                async move { Ok(res.unwrap()) }.boxed()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::settings;
+    use std::time::Duration;
+
+    #[settings(crate_path = "crate")]
+    struct TestSettings {
+        /// Sampling ratio.
+        ratio: u32,
+    }
+
+    #[test]
+    fn applies_scheduled_changes_in_order() {
+        let settings = ScheduledSettings::new(TestSettings { ratio: 100 });
+        let t0 = UNIX_EPOCH + Duration::from_secs(1000);
+
+        settings.schedule(t0 + Duration::from_secs(20), TestSettings { ratio: 1 });
+        settings.schedule(t0 + Duration::from_secs(10), TestSettings { ratio: 10 });
+
+        assert_eq!(settings.current_at(t0).ratio, 100);
+        assert_eq!(settings.current_at(t0 + Duration::from_secs(15)).ratio, 10);
+
+        settings.replace(TestSettings { ratio: 50 });
+
+        assert_eq!(settings.current_at(t0 + Duration::from_secs(15)).ratio, 50);
+        assert_eq!(settings.current().ratio, 1);
+        assert!(settings.scheduled().is_empty());
+    }
+
+    #[test]
+    fn reports_schedule() {
+        let settings = ScheduledSettings::new(TestSettings { ratio: 100 });
+        let at = SystemTime::now() + Duration::from_secs(3600);
+
+        settings.schedule(at, TestSettings { ratio: 10 });
+
+        let at = at.duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        assert_eq!(
+            settings.report().unwrap(),
+            format!("---\ncurrent:\n  ratio: 100\nscheduled:\n  - at: {at}\n    settings:\n      ratio: 10\n")
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Verification of detached signatures of settings files.
+//!
+//! The signatures are compatible with the ones produced by [cosign] for blobs signed with a key
+//! pair, i.e. base64-encoded ECDSA or RSA signatures of the SHA-256 digest of the file. Keyless
+//! signing with Fulcio certificates and Rekor transparency log entries is not supported.
+
+use crate::BootstrapResult;
+use anyhow::{bail, Context};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempfile::NamedTempFile;
+
+const DEFAULT_OPENSSL_PATH: &str = "/usr/bin/openssl";
+const PEM_KEY_END: &str = "-----END PUBLIC KEY-----";
+
+/// Public keys the settings files must be signed with, see [`from_signed_file`].
+///
+/// The signatures are verified with the `openssl` binary at an absolute path, `/usr/bin/openssl`
+/// by default. The binary is never looked up in `PATH`, so it can't be substituted by changing
+/// the environment of the service.
+///
+/// [`from_signed_file`]: super::from_signed_file
+#[derive(Clone, Debug)]
+pub struct TrustBundle {
+    path: PathBuf,
+    openssl_path: PathBuf,
+}
+
+impl TrustBundle {
+    /// Creates a trust bundle from a file with one or more PEM-encoded ECDSA or RSA public keys.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            openssl_path: DEFAULT_OPENSSL_PATH.into(),
+        }
+    }
+
+    /// Sets the absolute path of the `openssl` binary the signatures are verified with.
+    pub fn with_openssl_path(mut self, openssl_path: impl Into<PathBuf>) -> Self {
+        self.openssl_path = openssl_path.into();
+        self
+    }
+
+    /// Returns the path of the file with the public keys.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the `openssl` binary the signatures are verified with.
+    pub fn openssl_path(&self) -> &Path {
+        &self.openssl_path
+    }
+}
+
+/// Returns the path of the detached signature of the settings file, i.e. `<path>.sig`.
+fn signature_path(path: &Path) -> PathBuf {
+    let mut sig_path = OsString::from(path.as_os_str());
+
+    sig_path.push(".sig");
+    sig_path.into()
+}
+
+/// Verifies that the contents of the settings file are signed by one of the public keys of
+/// the trust bundle.
+pub(super) fn verify_signature(
+    path: &Path,
+    data: &[u8],
+    trust_bundle: &TrustBundle,
+) -> BootstrapResult<()> {
+    let openssl = openssl_path(trust_bundle)?;
+    let keys = read_trust_bundle(&trust_bundle.path)?;
+    let sig_path = signature_path(path);
+
+    if !sig_path.exists() {
+        bail!(
+            "signature {} of the settings file {} doesn't exist",
+            sig_path.display(),
+            path.display()
+        );
+    }
+
+    // NOTE: openssl expects a raw signature, while cosign produces a base64-encoded one.
+    let raw_sig = NamedTempFile::new()?;
+
+    run_openssl(
+        Command::new(openssl)
+            .args(["base64", "-d", "-A", "-in"])
+            .arg(&sig_path)
+            .arg("-out")
+            .arg(raw_sig.path()),
+        None,
+    )
+    .with_context(|| format!("failed to decode the signature {}", sig_path.display()))?;
+
+    for key in &keys {
+        let mut key_file = NamedTempFile::new()?;
+
+        key_file.write_all(key.as_bytes())?;
+
+        let verified = run_openssl(
+            Command::new(openssl)
+                .args(["dgst", "-sha256", "-verify"])
+                .arg(key_file.path())
+                .arg("-signature")
+                .arg(raw_sig.path()),
+            Some(data),
+        )
+        .is_ok();
+
+        if verified {
+            return Ok(());
+        }
+    }
+
+    bail!(
+        "settings file {} is not signed by any of the {} key(s) of the trust bundle {}, \
+         it might have been tampered with",
+        path.display(),
+        keys.len(),
+        trust_bundle.path.display()
+    )
+}
+
+fn openssl_path(trust_bundle: &TrustBundle) -> BootstrapResult<&Path> {
+    let path = trust_bundle.openssl_path();
+
+    if !path.is_absolute() {
+        bail!(
+            "path of the `openssl` binary used to verify the settings signatures must be \
+             absolute, got {}",
+            path.display()
+        );
+    }
+
+    if !path.is_file() {
+        bail!(
+            "`openssl` binary used to verify the settings signatures doesn't exist at {}",
+            path.display()
+        );
+    }
+
+    Ok(path)
+}
+
+fn read_trust_bundle(path: &Path) -> BootstrapResult<Vec<String>> {
+    let bundle = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read the trust bundle {}", path.display()))?;
+
+    let keys: Vec<_> = bundle
+        .split_inclusive(PEM_KEY_END)
+        .filter(|block| block.contains(PEM_KEY_END))
+        .map(|block| format!("{}\n", block.trim()))
+        .collect();
+
+    if keys.is_empty() {
+        bail!(
+            "trust bundle {} doesn't contain any PEM-encoded public keys",
+            path.display()
+        );
+    }
+
+    Ok(keys)
+}
+
+fn run_openssl(cmd: &mut Command, stdin: Option<&[u8]>) -> BootstrapResult<()> {
+    let mut child = cmd
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run `{}`", cmd.get_program().to_string_lossy()))?;
+
+    if let (Some(data), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+        child_stdin.write_all(data)?;
+    }
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        bail!(
+            "`{}` failed ({}): {}",
+            cmd.get_program().to_string_lossy(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_trust_bundle() {
+        let mut bundle = NamedTempFile::new().unwrap();
+
+        write!(
+            bundle,
+            "# prod\n-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----\n\
+             # staging\n-----BEGIN PUBLIC KEY-----\nBBBB\n-----END PUBLIC KEY-----\n"
+        )
+        .unwrap();
+
+        let keys = read_trust_bundle(bundle.path()).unwrap();
+
+        assert_eq!(keys.len(), 2);
+        assert!(keys[1].starts_with("# staging\n-----BEGIN PUBLIC KEY-----\nBBBB\n"));
+        assert!(read_trust_bundle(Path::new("/nonexistent")).is_err());
+    }
+
+    #[test]
+    fn openssl_path_must_be_absolute_and_exist() {
+        let relative = TrustBundle::new("bundle.pem").with_openssl_path("openssl");
+
+        assert!(openssl_path(&relative)
+            .unwrap_err()
+            .to_string()
+            .contains("must be absolute"));
+
+        let missing = TrustBundle::new("bundle.pem").with_openssl_path("/nonexistent/openssl");
+
+        assert!(openssl_path(&missing)
+            .unwrap_err()
+            .to_string()
+            .contains("doesn't exist"));
+
+        assert_eq!(
+            TrustBundle::new("bundle.pem").openssl_path(),
+            Path::new(DEFAULT_OPENSSL_PATH)
+        );
+    }
+
+    #[test]
+    fn signature_path_appends_extension() {
+        assert_eq!(
+            signature_path(Path::new("/etc/svc/config.yaml")),
+            Path::new("/etc/svc/config.yaml.sig")
+        );
+    }
+}
@@ -0,0 +1,411 @@
+//! Ordered shutdown of the service components.
+//!
+//! Components of a service usually can't be shut down in an arbitrary order: listeners need to
+//! stop accepting new connections before the in-flight requests are drained, the telemetry needs
+//! to be flushed once the workers stop producing it, and the storage can only be closed once
+//! nothing writes to it anymore.
+//!
+//! [`ShutdownCoordinator`] runs the shutdown of the registered components in the order of
+//! the [`ShutdownStage`] they declare on registration. Components of the same stage are shut down
+//! concurrently, and each stage is limited in time with the timeouts from [`ShutdownSettings`],
+//! so a stuck component can't block the shutdown of the rest of the service. The outcome of
+//! the shutdown of each component is returned in the [`ShutdownReport`].
+//!
+//! # Examples
+//! ```
+//! use foundations::shutdown::{ShutdownCoordinator, ShutdownStage};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let mut coordinator = ShutdownCoordinator::new(&Default::default());
+//!
+//! coordinator.register("api_listener", ShutdownStage::StopAcceptors, async {
+//!     // Stop accepting new connections.
+//!     Ok(())
+//! });
+//!
+//! coordinator.register("database", ShutdownStage::CloseStorage, async {
+//!     Err("failed to flush the write-ahead log".into())
+//! });
+//!
+//! let report = coordinator.shutdown().await;
+//!
+//! assert!(!report.is_clean());
+//! assert_eq!(report.failed_components().collect::<Vec<_>>(), ["database"]);
+//! # }
+//! ```
+
+use crate::Result;
+use futures_util::future::{self, BoxFuture, Either};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::pin::pin;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+/// A stage of the shutdown, the stages are run in the order of the declaration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShutdownStage {
+    /// Listeners stop accepting new connections and requests.
+    StopAcceptors,
+    /// In-flight requests and background jobs are finished.
+    DrainWorkers,
+    /// Buffered telemetry is flushed to the outputs.
+    FlushTelemetry,
+    /// Storage and other resources used by the workers are closed.
+    CloseStorage,
+}
+
+impl ShutdownStage {
+    /// All the stages in the order they are run.
+    pub const ALL: [ShutdownStage; 4] = [
+        ShutdownStage::StopAcceptors,
+        ShutdownStage::DrainWorkers,
+        ShutdownStage::FlushTelemetry,
+        ShutdownStage::CloseStorage,
+    ];
+
+    fn timeout(self, settings: &ShutdownSettings) -> Duration {
+        Duration::from_millis(match self {
+            ShutdownStage::StopAcceptors => settings.stop_acceptors_timeout_ms,
+            ShutdownStage::DrainWorkers => settings.drain_workers_timeout_ms,
+            ShutdownStage::FlushTelemetry => settings.flush_telemetry_timeout_ms,
+            ShutdownStage::CloseStorage => settings.close_storage_timeout_ms,
+        })
+    }
+}
+
+/// Timeouts of the shutdown stages, see [`ShutdownCoordinator`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct ShutdownSettings {
+    /// Timeout in milliseconds of the [`ShutdownStage::StopAcceptors`] stage.
+    pub stop_acceptors_timeout_ms: u64,
+
+    /// Timeout in milliseconds of the [`ShutdownStage::DrainWorkers`] stage.
+    pub drain_workers_timeout_ms: u64,
+
+    /// Timeout in milliseconds of the [`ShutdownStage::FlushTelemetry`] stage.
+    pub flush_telemetry_timeout_ms: u64,
+
+    /// Timeout in milliseconds of the [`ShutdownStage::CloseStorage`] stage.
+    pub close_storage_timeout_ms: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self {
+            stop_acceptors_timeout_ms: 5_000,
+            drain_workers_timeout_ms: 30_000,
+            flush_telemetry_timeout_ms: 10_000,
+            close_storage_timeout_ms: 10_000,
+        }
+    }
+}
+
+struct Component {
+    name: &'static str,
+    stage: ShutdownStage,
+    shutdown: BoxFuture<'static, Result<()>>,
+}
+
+/// Shuts down the registered components stage by stage.
+///
+/// See the [module-level documentation] for more details.
+///
+/// [module-level documentation]: crate::shutdown
+pub struct ShutdownCoordinator {
+    settings: ShutdownSettings,
+    components: Vec<Component>,
+}
+
+impl ShutdownCoordinator {
+    /// Creates a new coordinator with the given stage timeouts.
+    pub fn new(settings: &ShutdownSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            components: vec![],
+        }
+    }
+
+    /// Registers a component to be shut down in the given `stage`.
+    ///
+    /// The `shutdown` future is only polled once the stage starts and is dropped if it doesn't
+    /// complete within the stage timeout.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        stage: ShutdownStage,
+        shutdown: impl Future<Output = Result<()>> + Send + 'static,
+    ) {
+        self.components.push(Component {
+            name,
+            stage,
+            shutdown: Box::pin(shutdown),
+        });
+    }
+
+    /// Runs the shutdown of all the registered components.
+    ///
+    /// Stages without components are skipped. A stage is started once all the components of
+    /// the previous stage have been shut down or the previous stage timed out.
+    pub async fn shutdown(self) -> ShutdownReport {
+        let started_at = Instant::now();
+        let mut components = self.components;
+        let mut stages = vec![];
+
+        for stage in ShutdownStage::ALL {
+            let (current, rest) = components.into_iter().partition(|c| c.stage == stage);
+
+            components = rest;
+
+            if !current.is_empty() {
+                stages.push(run_stage(stage, stage.timeout(&self.settings), current).await);
+            }
+        }
+
+        let report = ShutdownReport {
+            stages,
+            elapsed: started_at.elapsed(),
+        };
+
+        #[cfg(feature = "logging")]
+        report.log();
+
+        report
+    }
+}
+
+async fn run_stage(
+    stage: ShutdownStage,
+    timeout: Duration,
+    components: Vec<Component>,
+) -> StageReport {
+    let started_at = Instant::now();
+    let names: Vec<_> = components.iter().map(|c| c.name).collect();
+    let mut reports: Vec<Option<ComponentReport>> = vec![None; names.len()];
+
+    let mut pending: FuturesUnordered<_> = components
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| async move { (i, c.shutdown.await, started_at.elapsed()) })
+        .collect();
+
+    let mut deadline = pin!(tokio::time::sleep(timeout));
+
+    while let Either::Left((Some((i, res, elapsed)), _)) =
+        future::select(pending.next(), deadline.as_mut()).await
+    {
+        reports[i] = Some(ComponentReport {
+            name: names[i],
+            outcome: match res {
+                Ok(()) => ComponentOutcome::Completed,
+                Err(e) => ComponentOutcome::Failed(e.to_string()),
+            },
+            elapsed,
+        });
+    }
+
+    // NOTE: the components that haven't completed by the deadline are dropped with `pending`.
+    let components = reports
+        .into_iter()
+        .zip(names)
+        .map(|(report, name)| {
+            report.unwrap_or(ComponentReport {
+                name,
+                outcome: ComponentOutcome::TimedOut,
+                elapsed: timeout,
+            })
+        })
+        .collect();
+
+    StageReport {
+        stage,
+        components,
+        elapsed: started_at.elapsed(),
+    }
+}
+
+/// Outcome of the shutdown of a component.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComponentOutcome {
+    /// The component has been shut down successfully.
+    Completed,
+    /// The shutdown of the component failed with the error.
+    Failed(String),
+    /// The shutdown of the component didn't complete within the stage timeout.
+    TimedOut,
+}
+
+/// Report of the shutdown of a component.
+#[derive(Clone, Debug)]
+pub struct ComponentReport {
+    /// The name of the component.
+    pub name: &'static str,
+
+    /// Outcome of the shutdown.
+    pub outcome: ComponentOutcome,
+
+    /// Time it took to shut down the component.
+    pub elapsed: Duration,
+}
+
+/// Report of a shutdown stage.
+#[derive(Clone, Debug)]
+pub struct StageReport {
+    /// The stage.
+    pub stage: ShutdownStage,
+
+    /// Reports of the components of the stage, in the registration order.
+    pub components: Vec<ComponentReport>,
+
+    /// Time it took to run the stage.
+    pub elapsed: Duration,
+}
+
+/// Report of the shutdown returned by [`ShutdownCoordinator::shutdown`].
+#[derive(Clone, Debug)]
+pub struct ShutdownReport {
+    /// Reports of the stages that had components, in the order they were run.
+    pub stages: Vec<StageReport>,
+
+    /// Time it took to run the whole shutdown.
+    pub elapsed: Duration,
+}
+
+impl ShutdownReport {
+    /// Returns `true` if all the components have been shut down successfully.
+    pub fn is_clean(&self) -> bool {
+        self.components()
+            .all(|c| c.outcome == ComponentOutcome::Completed)
+    }
+
+    /// Returns the names of the components that failed or timed out.
+    pub fn failed_components(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.components()
+            .filter(|c| c.outcome != ComponentOutcome::Completed)
+            .map(|c| c.name)
+    }
+
+    fn components(&self) -> impl Iterator<Item = &ComponentReport> {
+        self.stages.iter().flat_map(|s| &s.components)
+    }
+
+    #[cfg(feature = "logging")]
+    fn log(&self) {
+        use crate::telemetry::log;
+
+        for stage in &self.stages {
+            for component in &stage.components {
+                match &component.outcome {
+                    ComponentOutcome::Completed => {}
+                    ComponentOutcome::Failed(e) => {
+                        log::warn!(
+                            "component shutdown failed";
+                            "component" => component.name,
+                            "stage" => ?stage.stage,
+                            "error" => e
+                        );
+                    }
+                    ComponentOutcome::TimedOut => {
+                        log::warn!(
+                            "component shutdown timed out";
+                            "component" => component.name,
+                            "stage" => ?stage.stage,
+                            "elapsed_ms" => component.elapsed.as_millis() as u64
+                        );
+                    }
+                }
+            }
+        }
+
+        log::info!(
+            "shutdown completed";
+            "clean" => self.is_clean(),
+            "elapsed_ms" => self.elapsed.as_millis() as u64
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn runs_stages_in_order() {
+        let order = Arc::new(Mutex::new(vec![]));
+        let mut coordinator = ShutdownCoordinator::new(&Default::default());
+
+        for (name, stage) in [
+            ("storage", ShutdownStage::CloseStorage),
+            ("listener", ShutdownStage::StopAcceptors),
+            ("telemetry", ShutdownStage::FlushTelemetry),
+            ("workers", ShutdownStage::DrainWorkers),
+        ] {
+            let order = Arc::clone(&order);
+
+            coordinator.register(name, stage, async move {
+                order.lock().unwrap().push(name);
+
+                Ok(())
+            });
+        }
+
+        let report = coordinator.shutdown().await;
+
+        assert!(report.is_clean());
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["listener", "workers", "telemetry", "storage"]
+        );
+        assert_eq!(
+            report.stages.iter().map(|s| s.stage).collect::<Vec<_>>(),
+            ShutdownStage::ALL
+        );
+    }
+
+    #[tokio::test]
+    async fn times_out_stuck_components() {
+        let mut coordinator = ShutdownCoordinator::new(&ShutdownSettings {
+            drain_workers_timeout_ms: 10,
+            ..Default::default()
+        });
+        let storage_closed = Arc::new(Mutex::new(false));
+
+        coordinator.register("stuck", ShutdownStage::DrainWorkers, future::pending());
+        coordinator.register("failing", ShutdownStage::DrainWorkers, async {
+            Err("boom".into())
+        });
+
+        {
+            let storage_closed = Arc::clone(&storage_closed);
+
+            coordinator.register("storage", ShutdownStage::CloseStorage, async move {
+                *storage_closed.lock().unwrap() = true;
+
+                Ok(())
+            });
+        }
+
+        let report = coordinator.shutdown().await;
+        let workers = &report.stages[0];
+
+        assert!(*storage_closed.lock().unwrap());
+        assert_eq!(workers.components[0].outcome, ComponentOutcome::TimedOut);
+        assert_eq!(
+            workers.components[1].outcome,
+            ComponentOutcome::Failed("boom".into())
+        );
+        assert!(workers.elapsed >= Duration::from_millis(10));
+        assert_eq!(
+            report.failed_components().collect::<Vec<_>>(),
+            ["stuck", "failing"]
+        );
+    }
+}
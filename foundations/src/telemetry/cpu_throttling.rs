@@ -0,0 +1,221 @@
+//! Monitor of the CPU throttling of the cgroup of the process, see
+//! [`CpuThrottlingMonitorSettings`].
+
+use super::metrics::Counter;
+use super::settings::CpuThrottlingMonitorSettings;
+use crate::BootstrapResult;
+use anyhow::bail;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "logging")]
+use super::log;
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_cpu {
+    /// Number of the elapsed CPU quota enforcement periods of the cgroup of the process.
+    pub fn periods_total() -> Counter;
+
+    /// Number of the CPU quota enforcement periods in which the cgroup of the process was
+    /// throttled.
+    pub fn throttled_periods_total() -> Counter;
+
+    /// Total time the cgroup of the process was throttled for.
+    pub fn throttled_seconds_total() -> Counter<f64, AtomicU64>;
+}
+
+/// Starts the monitor, if enabled in the settings.
+pub(super) fn start(settings: &CpuThrottlingMonitorSettings) -> BootstrapResult<()> {
+    if !settings.enabled || STARTED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    if !(0.0..).contains(&settings.warn_throttled_ratio) {
+        bail!("`warn_throttled_ratio` of the CPU throttling monitor should be non-negative");
+    }
+
+    let Some(mut prev) = cpu_stat() else {
+        #[cfg(feature = "logging")]
+        log::warn!("CPU throttling statistics of the cgroup are not available, the CPU throttling monitor is disabled");
+
+        return Ok(());
+    };
+
+    let interval = Duration::from_millis(settings.sample_interval_ms.max(1));
+    let _warn_throttled_ratio = settings.warn_throttled_ratio;
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        let Some(stat) = cpu_stat() else {
+            continue;
+        };
+
+        let delta = stat.saturating_sub(&prev);
+
+        prev = stat;
+
+        foundations_cpu::periods_total().inc_by(delta.periods);
+        foundations_cpu::throttled_periods_total().inc_by(delta.throttled_periods);
+        foundations_cpu::throttled_seconds_total().inc_by(delta.throttled_time.as_secs_f64());
+
+        #[cfg(feature = "logging")]
+        if delta.throttled_ratio() > _warn_throttled_ratio {
+            log::warn!(
+                "CPU of the cgroup was throttled, which can increase latency";
+                "throttled_periods" => delta.throttled_periods,
+                "periods" => delta.periods,
+                "throttled_ms" => delta.throttled_time.as_millis() as u64,
+                "interval_ms" => interval.as_millis() as u64
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// CPU throttling statistics of a cgroup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct CpuStat {
+    periods: u64,
+    throttled_periods: u64,
+    throttled_time: Duration,
+}
+
+impl CpuStat {
+    /// Parses the `cpu.stat` file of cgroup v1 or v2.
+    fn parse(contents: &str) -> Option<Self> {
+        let mut stat = Self::default();
+        let mut has_periods = false;
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+
+            let Ok(value) = value.trim().parse() else {
+                continue;
+            };
+
+            match key {
+                "nr_periods" => {
+                    stat.periods = value;
+                    has_periods = true;
+                }
+                "nr_throttled" => stat.throttled_periods = value,
+                // NOTE: cgroup v2.
+                "throttled_usec" => stat.throttled_time = Duration::from_micros(value),
+                // NOTE: cgroup v1.
+                "throttled_time" => stat.throttled_time = Duration::from_nanos(value),
+                _ => (),
+            }
+        }
+
+        has_periods.then_some(stat)
+    }
+
+    // NOTE: the statistics can decrease if the process is moved to another cgroup.
+    fn saturating_sub(&self, prev: &Self) -> Self {
+        Self {
+            periods: self.periods.saturating_sub(prev.periods),
+            throttled_periods: self
+                .throttled_periods
+                .saturating_sub(prev.throttled_periods),
+            throttled_time: self.throttled_time.saturating_sub(prev.throttled_time),
+        }
+    }
+
+    /// Returns the fraction of the periods in which the cgroup was throttled.
+    #[cfg_attr(not(feature = "logging"), allow(dead_code))]
+    fn throttled_ratio(&self) -> f64 {
+        match self.periods {
+            0 => 0.0,
+            periods => self.throttled_periods as f64 / periods as f64,
+        }
+    }
+}
+
+/// Returns the CPU throttling statistics of the cgroup of the process.
+fn cpu_stat() -> Option<CpuStat> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+
+    for line in cgroups.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(cgroup)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+
+        let paths = match controllers {
+            // NOTE: cgroup v2.
+            "" => vec![format!("/sys/fs/cgroup{cgroup}/cpu.stat")],
+            // NOTE: the `cpu` controller is commonly co-mounted with `cpuacct` in cgroup v1.
+            _ if controllers.split(',').any(|c| c == "cpu") => vec![
+                format!("/sys/fs/cgroup/{controllers}{cgroup}/cpu.stat"),
+                format!("/sys/fs/cgroup/cpu{cgroup}/cpu.stat"),
+            ],
+            _ => continue,
+        };
+
+        for path in paths {
+            if let Some(stat) = fs::read_to_string(path)
+                .ok()
+                .and_then(|contents| CpuStat::parse(&contents))
+            {
+                return Some(stat);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cpu_stat() {
+        let v2 = "usage_usec 1000\nnr_periods 200\nnr_throttled 50\nthrottled_usec 250000\n";
+        let v1 = "nr_periods 200\nnr_throttled 50\nthrottled_time 250000000\n";
+        let expected = CpuStat {
+            periods: 200,
+            throttled_periods: 50,
+            throttled_time: Duration::from_millis(250),
+        };
+
+        assert_eq!(CpuStat::parse(v2), Some(expected));
+        assert_eq!(CpuStat::parse(v1), Some(expected));
+
+        // NOTE: the `cpu` controller is not enabled for the cgroup.
+        assert_eq!(CpuStat::parse("usage_usec 1000\n"), None);
+    }
+
+    #[test]
+    fn computes_throttling_per_interval() {
+        let prev = CpuStat {
+            periods: 200,
+            throttled_periods: 50,
+            throttled_time: Duration::from_millis(250),
+        };
+
+        let current = CpuStat {
+            periods: 300,
+            throttled_periods: 75,
+            throttled_time: Duration::from_millis(400),
+        };
+
+        let delta = current.saturating_sub(&prev);
+
+        assert_eq!(delta.throttled_periods, 25);
+        assert_eq!(delta.throttled_time, Duration::from_millis(150));
+        assert_eq!(delta.throttled_ratio(), 0.25);
+
+        assert_eq!(prev.saturating_sub(&current), CpuStat::default());
+        assert_eq!(CpuStat::default().throttled_ratio(), 0.0);
+    }
+}
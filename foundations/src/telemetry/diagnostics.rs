@@ -0,0 +1,406 @@
+//! Diagnostics bundles for incident investigations.
+//!
+//! A diagnostics bundle is a `tar.gz` archive with the state of a service instance that is
+//! usually requested during incidents, so it can be gathered in one go and attached to
+//! the incident ticket. The bundle contains the following files:
+//!
+//! | File              | Contents                                                            |
+//! |-------------------|---------------------------------------------------------------------|
+//! | `build_info.json` | Service name and version, enabled features and the [startup report] |
+//! | `settings.json`   | Effective settings of the service with the secrets redacted         |
+//! | `logs.txt`        | The most recent log records                                         |
+//! | `metrics.txt`     | Snapshot of the metrics in the Prometheus text format               |
+//! | `threads.txt`     | Threads of the process with their states                            |
+//! | `sandbox.txt`     | Syscall sandboxing and privileges state of the process              |
+//!
+//! Recent log records are retained only if [`LoggingSettings::recent_records`] is set, metrics and
+//! logs are included only if the corresponding features are enabled, and the thread and sandbox
+//! states are only available on Linux.
+//!
+//! The bundle can be generated with [`DiagnosticsBundle::collect`] or served by the telemetry
+//! server with the [`telemetry_server_route`].
+//!
+//! # Examples
+//! ```
+//! use foundations::telemetry::diagnostics::DiagnosticsBundle;
+//! use foundations::telemetry::settings::TelemetrySettings;
+//!
+//! # fn main() -> foundations::BootstrapResult<()> {
+//! let settings = TelemetrySettings::default();
+//!
+//! let bundle = DiagnosticsBundle::collect(&foundations::service_info!(), &settings)
+//!     .with_settings(&serde_json::json!({ "upstream": "10.0.0.1", "api_token": "s3cr3t" }))?;
+//!
+//! assert!(bundle.file("settings.json").unwrap().contains("[REDACTED]"));
+//!
+//! let archive = bundle.to_tar_gz()?;
+//! # assert!(!archive.is_empty());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [startup report]: crate::telemetry::StartupReport
+//! [`LoggingSettings::recent_records`]: crate::telemetry::settings::LoggingSettings::recent_records
+
+use super::settings::TelemetrySettings;
+use super::startup_report::{sandbox_state, FEATURES};
+use super::StartupReport;
+use crate::compression::Encoder;
+use crate::{BootstrapResult, ServiceInfo};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Substrings of the settings keys whose values are redacted in the bundle.
+pub const REDACTED_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "private_key",
+    "credential",
+    "authorization",
+];
+
+const REDACTED: &str = "[REDACTED]";
+
+/// A diagnostics bundle. See [module-level documentation] for the details.
+///
+/// [module-level documentation]: crate::telemetry::diagnostics
+#[derive(Clone, Debug, Default)]
+pub struct DiagnosticsBundle {
+    files: Vec<(String, String)>,
+}
+
+impl DiagnosticsBundle {
+    /// Gathers the diagnostics of the current process.
+    ///
+    /// The settings of the service are not included in the bundle unless added with
+    /// [`DiagnosticsBundle::with_settings`].
+    pub fn collect(service_info: &ServiceInfo, _settings: &TelemetrySettings) -> Self {
+        let mut bundle = Self::default();
+
+        bundle.add_file("build_info.json", build_info(service_info));
+
+        #[cfg(feature = "logging")]
+        bundle.add_file("logs.txt", super::log::recent::recent_records().join("\n"));
+
+        #[cfg(feature = "metrics")]
+        bundle.add_file(
+            "metrics.txt",
+            super::metrics::collect_without_reset(&_settings.metrics)
+                .unwrap_or_else(|err| format!("failed to collect metrics: {err}")),
+        );
+
+        bundle.add_file("threads.txt", threads());
+        bundle.add_file("sandbox.txt", sandbox());
+
+        bundle
+    }
+
+    /// Adds the effective settings of the service to the bundle.
+    ///
+    /// Values of the keys that contain any of the [`REDACTED_KEYS`] are redacted.
+    pub fn with_settings(self, settings: &impl Serialize) -> BootstrapResult<Self> {
+        self.with_redacted_settings(settings, &[])
+    }
+
+    /// Adds the effective settings of the service to the bundle, additionally redacting values
+    /// of the keys that contain any of the `redact_keys`.
+    pub fn with_redacted_settings(
+        mut self,
+        settings: &impl Serialize,
+        redact_keys: &[&str],
+    ) -> BootstrapResult<Self> {
+        let mut settings = serde_json::to_value(settings)?;
+
+        redact(&mut settings, redact_keys);
+
+        self.add_file("settings.json", serde_json::to_string_pretty(&settings)?);
+
+        Ok(self)
+    }
+
+    /// Adds a custom file to the bundle, replacing the file with the same name if any.
+    pub fn with_file(mut self, name: impl Into<String>, contents: impl Into<String>) -> Self {
+        self.add_file(name, contents);
+        self
+    }
+
+    /// Returns the contents of the file of the bundle.
+    pub fn file(&self, name: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|(file_name, _)| file_name == name)
+            .map(|(_, contents)| contents.as_str())
+    }
+
+    /// Writes the bundle as a `tar.gz` archive.
+    ///
+    /// All the files are placed in a directory named after the time of the writing, e.g.
+    /// `diagnostics-1700000000/metrics.txt`.
+    pub fn write_tar_gz(&self, out: impl Write) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let encoder = Encoder::new(out, &Default::default(), "diagnostics")?;
+        let mut archive = tar::Builder::new(encoder);
+
+        for (name, contents) in &self.files {
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(now);
+
+            archive.append_data(
+                &mut header,
+                format!("diagnostics-{now}/{name}"),
+                contents.as_bytes(),
+            )?;
+        }
+
+        archive.into_inner()?.finish()?;
+
+        Ok(())
+    }
+
+    /// Returns the bundle as a `tar.gz` archive.
+    pub fn to_tar_gz(&self) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+
+        self.write_tar_gz(&mut out)?;
+
+        Ok(out)
+    }
+
+    fn add_file(&mut self, name: impl Into<String>, contents: impl Into<String>) {
+        let name = name.into();
+
+        self.files.retain(|(file_name, _)| *file_name != name);
+        self.files.push((name, contents.into()));
+    }
+}
+
+/// Returns a telemetry server route that serves the diagnostics bundle of the process on
+/// the `/debug/diagnostics` path.
+///
+/// The `settings` are the effective settings of the service, they are serialized and redacted
+/// once, when the route is created.
+///
+/// The route should be passed to [`init_with_server`].
+///
+/// [`init_with_server`]: crate::telemetry::init_with_server
+pub fn telemetry_server_route(
+    service_info: &ServiceInfo,
+    settings: &impl Serialize,
+) -> BootstrapResult<super::TelemetryServerRoute> {
+    use futures_util::FutureExt;
+    use hyper::{header, Method, Response, StatusCode};
+
+    let service_info = service_info.clone();
+    let settings = DiagnosticsBundle::default()
+        .with_settings(settings)?
+        .file("settings.json")
+        .unwrap_or_default()
+        .to_string();
+
+    Ok(super::TelemetryServerRoute {
+        path: "/debug/diagnostics".into(),
+        methods: vec![Method::GET],
+        handler: Box::new(move |_, telemetry_settings| {
+            let bundle = DiagnosticsBundle::collect(&service_info, &telemetry_settings)
+                .with_file("settings.json", settings.clone());
+
+            let res = match bundle.to_tar_gz() {
+                Ok(archive) => Response::builder()
+                    .header(header::CONTENT_TYPE, "application/gzip")
+                    .header(
+                        header::CONTENT_DISPOSITION,
+                        "attachment; filename=\"diagnostics.tar.gz\"",
+                    )
+                    .body(archive.into()),
+                Err(err) => Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(err.to_string().into()),
+            };
+
+            async move { Ok(res.unwrap()) }.boxed()
+        }),
+    })
+}
+
+fn build_info(service_info: &ServiceInfo) -> String {
+    #[derive(Serialize)]
+    struct BuildInfo<'a> {
+        service_name: &'static str,
+        service_version: &'static str,
+        foundations_version: &'static str,
+        features: &'static [&'static str],
+        startup_report: Option<&'a StartupReport>,
+    }
+
+    let info = BuildInfo {
+        service_name: service_info.name,
+        service_version: service_info.version,
+        foundations_version: env!("CARGO_PKG_VERSION"),
+        features: &FEATURES,
+        startup_report: StartupReport::get(),
+    };
+
+    serde_json::to_string_pretty(&info).unwrap_or_default()
+}
+
+fn redact(value: &mut Value, redact_keys: &[&str]) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                let redacted = REDACTED_KEYS
+                    .iter()
+                    .chain(redact_keys)
+                    .any(|redact_key| key.contains(&redact_key.to_lowercase()));
+
+                if redacted {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    redact(value, redact_keys);
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                redact(value, redact_keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn threads() -> String {
+    let mut out = String::from("TID\tSTATE\tNAME\n");
+
+    let Ok(tasks) = std::fs::read_dir("/proc/self/task") else {
+        return "thread information is unavailable".into();
+    };
+
+    let mut tasks: Vec<_> = tasks
+        .filter_map(|task| task.ok())
+        .filter_map(|task| task.file_name().to_str()?.parse::<u64>().ok())
+        .collect();
+
+    tasks.sort_unstable();
+
+    for tid in tasks {
+        let Ok(stat) = std::fs::read_to_string(format!("/proc/self/task/{tid}/stat")) else {
+            continue;
+        };
+
+        // NOTE: the thread name is enclosed in parentheses and can contain spaces and
+        // parentheses itself, so the state is the first field after the last parenthesis.
+        let (Some(name_start), Some(name_end)) = (stat.find('('), stat.rfind(')')) else {
+            continue;
+        };
+
+        let name = &stat[name_start + 1..name_end];
+        let state = stat[name_end + 1..]
+            .split_whitespace()
+            .next()
+            .unwrap_or("?");
+
+        out.push_str(&format!("{tid}\t{state}\t{name}\n"));
+    }
+
+    out
+}
+
+#[cfg(not(target_os = "linux"))]
+fn threads() -> String {
+    "thread information is only available on Linux".into()
+}
+
+fn sandbox() -> String {
+    let mut out = format!("seccomp: {}\n", sandbox_state());
+
+    #[cfg(target_os = "linux")]
+    if let Ok(status) = std::fs::read_to_string("/proc/self/status") {
+        const FIELDS: &[&str] = &[
+            "Seccomp_filters:",
+            "NoNewPrivs:",
+            "CapEff:",
+            "CapPrm:",
+            "CapBnd:",
+        ];
+
+        for line in status.lines() {
+            if FIELDS.iter().any(|field| line.starts_with(field)) {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{CompressionAlgorithm, Decoder};
+    use std::io::Read;
+
+    #[test]
+    fn redacts_secrets() {
+        let mut settings = serde_json::json!({
+            "upstream": { "addr": "10.0.0.1", "Auth_Token": "s3cr3t" },
+            "clients": [{ "name": "a", "password": "p" }],
+            "internal_id": 42,
+        });
+
+        redact(&mut settings, &["internal"]);
+
+        assert_eq!(
+            settings,
+            serde_json::json!({
+                "upstream": { "addr": "10.0.0.1", "Auth_Token": REDACTED },
+                "clients": [{ "name": "a", "password": REDACTED }],
+                "internal_id": REDACTED,
+            })
+        );
+    }
+
+    #[test]
+    fn writes_tar_gz_archive() {
+        let bundle = DiagnosticsBundle::collect(&Default::default(), &Default::default())
+            .with_file("custom.txt", "custom contents");
+
+        let archive = bundle.to_tar_gz().unwrap();
+        let mut archive = tar::Archive::new(Decoder::new(
+            &archive[..],
+            CompressionAlgorithm::Gzip,
+            "test",
+        ));
+        let mut files = vec![];
+
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut contents = String::new();
+
+            entry.read_to_string(&mut contents).unwrap();
+
+            files.push((path.split_once('/').unwrap().1.to_string(), contents));
+        }
+
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+
+        assert!(names.contains(&"build_info.json"));
+        assert!(names.contains(&"threads.txt"));
+        assert!(names.contains(&"sandbox.txt"));
+        assert!(files.contains(&("custom.txt".into(), "custom contents".into())));
+    }
+}
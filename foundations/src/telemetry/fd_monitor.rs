@@ -0,0 +1,179 @@
+//! Monitor of the open file descriptors of the process, see [`FdMonitorSettings`].
+
+use super::metrics::Gauge;
+use super::settings::FdMonitorSettings;
+use crate::BootstrapResult;
+use anyhow::bail;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "logging")]
+use super::log;
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+const KINDS: [&str; 5] = ["socket", "pipe", "file", "anon_inode", "other"];
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_fds {
+    /// Number of the open file descriptors of the process, by kind.
+    pub fn open(kind: &'static str) -> Gauge;
+}
+
+/// Starts the monitor, if enabled in the settings.
+pub(super) fn start(settings: &FdMonitorSettings) -> BootstrapResult<()> {
+    if !settings.enabled || STARTED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    if settings.leak_window < 2 {
+        bail!("`leak_window` of the file descriptor monitor should be at least 2");
+    }
+
+    let interval = Duration::from_millis(settings.sample_interval_ms.max(1));
+
+    let mut detectors: Vec<_> = KINDS
+        .iter()
+        .map(|_| LeakDetector::new(settings.leak_window, settings.min_leak_growth))
+        .collect();
+
+    thread::spawn(move || loop {
+        if let Some(counts) = count_fds() {
+            for ((kind, count), detector) in KINDS.iter().zip(counts).zip(&mut detectors) {
+                foundations_fds::open(kind).set(count);
+
+                if let Some(growth) = detector.observe(count) {
+                    #[cfg(feature = "logging")]
+                    log::warn!(
+                        "possible file descriptor leak, the number of open file descriptors kept growing";
+                        "kind" => kind,
+                        "open" => count,
+                        "growth" => growth,
+                        "window_ms" => interval.as_millis() as u64 * detector.window as u64
+                    );
+
+                    #[cfg(not(feature = "logging"))]
+                    let _ = growth;
+                }
+            }
+        }
+
+        thread::sleep(interval);
+    });
+
+    Ok(())
+}
+
+/// Returns the number of the open file descriptors of each of the [`KINDS`].
+fn count_fds() -> Option<[u64; KINDS.len()]> {
+    let mut counts = [0; KINDS.len()];
+
+    for entry in fs::read_dir("/proc/self/fd").ok()?.flatten() {
+        // NOTE: the file descriptor can be closed after the directory is listed.
+        let Ok(target) = fs::read_link(entry.path()) else {
+            continue;
+        };
+
+        counts[kind_index(&target.to_string_lossy())] += 1;
+    }
+
+    Some(counts)
+}
+
+// NOTE: returns the index of the kind in `KINDS`.
+fn kind_index(target: &str) -> usize {
+    if target.starts_with("socket:") {
+        0
+    } else if target.starts_with("pipe:") {
+        1
+    } else if target.starts_with('/') {
+        2
+    } else if target.starts_with("anon_inode:") {
+        3
+    } else {
+        4
+    }
+}
+
+/// Detects the numbers of the file descriptors that never decrease over a window of samples.
+struct LeakDetector {
+    window: usize,
+    min_growth: u64,
+    samples: VecDeque<u64>,
+}
+
+impl LeakDetector {
+    fn new(window: usize, min_growth: u64) -> Self {
+        Self {
+            window,
+            min_growth,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds a sample and returns the growth over the window if it looks like a leak.
+    fn observe(&mut self, count: u64) -> Option<u64> {
+        if self.samples.back().is_some_and(|last| count < *last) {
+            self.samples.clear();
+        }
+
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(count);
+
+        let first = *self.samples.front()?;
+        let growth = count - first;
+
+        if self.samples.len() < self.window || growth < self.min_growth.max(1) {
+            return None;
+        }
+
+        // NOTE: the leak is reported once per window.
+        self.samples.clear();
+        self.samples.push_back(count);
+
+        Some(growth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_fds() {
+        assert_eq!(KINDS[kind_index("socket:[123]")], "socket");
+        assert_eq!(KINDS[kind_index("pipe:[456]")], "pipe");
+        assert_eq!(KINDS[kind_index("/var/log/app.log")], "file");
+        assert_eq!(KINDS[kind_index("anon_inode:[eventfd]")], "anon_inode");
+        assert_eq!(KINDS[kind_index("net:[4026531840]")], "other");
+
+        let counts = count_fds().unwrap();
+
+        assert!(counts.iter().sum::<u64>() > 0);
+    }
+
+    #[test]
+    fn detects_monotonic_growth() {
+        let mut detector = LeakDetector::new(3, 10);
+
+        // NOTE: a decrease restarts the window.
+        assert_eq!(detector.observe(100), None);
+        assert_eq!(detector.observe(110), None);
+        assert_eq!(detector.observe(105), None);
+        assert_eq!(detector.observe(110), None);
+        assert_eq!(detector.observe(120), Some(15));
+
+        // NOTE: the growth is too small.
+        assert_eq!(detector.observe(121), None);
+        assert_eq!(detector.observe(122), None);
+        assert_eq!(detector.observe(123), None);
+
+        assert_eq!(detector.observe(140), Some(18));
+    }
+}
@@ -0,0 +1,119 @@
+use super::TelemetryContext;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+type HealthCheck = Box<dyn Fn() -> bool + Send + Sync>;
+
+static HEALTH_CHECKS: Lazy<RwLock<Vec<(&'static str, HealthCheck)>>> = Lazy::new(Default::default);
+
+/// A logical service hosted in the process alongside other services.
+///
+/// Foundations allows to consolidate several logical services, e.g. sidecars, in a single binary
+/// that shares a telemetry server, while keeping their telemetry apart:
+///
+/// - **Settings**: each service is expected to have its own section in the binary's settings
+///   structure.
+/// - **Logs**: records produced in the [telemetry context] of the hosted service carry the
+///   `service` field with the service name.
+/// - **Metrics**: metrics defined with `#[metrics(service = "<name>")]` are registered in the
+///   service's own registry and are prefixed with the service name instead of the process-wide
+///   prefix (or labeled with it, depending on `MetricsSettings::service_name_format`).
+/// - **Health checks**: health checks of all the hosted services are evaluated by the `/health`
+///   endpoint of the telemetry server, which responds with an error if any of them fail.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::settings::settings;
+/// use foundations::telemetry::metrics::{metrics, Counter};
+/// use foundations::telemetry::{log, HostedService};
+///
+/// #[settings]
+/// struct AuthSettings {
+///     /// Maximum number of sessions.
+///     max_sessions: u64,
+/// }
+///
+/// #[settings]
+/// struct BinarySettings {
+///     /// Settings of the auth service.
+///     auth: AuthSettings,
+/// }
+///
+/// // The metric below is reported as `auth_sessions_created_total`.
+/// #[metrics(service = "auth")]
+/// mod sessions {
+///     /// Number of created sessions
+///     pub fn created_total() -> Counter;
+/// }
+///
+/// fn run_auth_service(settings: &AuthSettings) {
+///     let service = HostedService::new("auth").with_health_check(|| true);
+///     let _scope = service.telemetry_context().scope();
+///
+///     log::info!("auth service started"; "max_sessions" => settings.max_sessions);
+///     sessions::created_total().inc();
+/// }
+/// # }
+/// ```
+///
+/// [telemetry context]: HostedService::telemetry_context
+pub struct HostedService {
+    name: &'static str,
+    ctx: TelemetryContext,
+}
+
+impl HostedService {
+    /// Creates a new hosted service with the given name.
+    ///
+    /// The name should match the `service` argument of the `metrics` macro used for the
+    /// service's metrics.
+    pub fn new(name: &'static str) -> Self {
+        let ctx = TelemetryContext::current();
+
+        #[cfg(feature = "logging")]
+        let ctx = {
+            let ctx = ctx.with_forked_log();
+            let _scope = ctx.scope();
+
+            super::log::add_fields!("service" => name);
+
+            ctx
+        };
+
+        Self { name, ctx }
+    }
+
+    /// Returns the name of the service.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Registers a health check of the service.
+    ///
+    /// The check should return `false` if the service is not healthy.
+    pub fn with_health_check(self, check: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        HEALTH_CHECKS.write().push((self.name, Box::new(check)));
+
+        self
+    }
+
+    /// Returns the telemetry context of the service.
+    ///
+    /// The context should be applied to the service code, so the produced telemetry can be
+    /// attributed to the service.
+    pub fn telemetry_context(&self) -> TelemetryContext {
+        self.ctx.clone()
+    }
+}
+
+/// Returns the names of the hosted services whose health checks fail.
+#[cfg(feature = "telemetry-server")]
+pub(super) fn unhealthy_services() -> Vec<&'static str> {
+    HEALTH_CHECKS
+        .read()
+        .iter()
+        .filter(|(_, check)| !check())
+        .map(|(name, _)| *name)
+        .collect()
+}
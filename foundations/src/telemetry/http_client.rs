@@ -0,0 +1,166 @@
+//! Minimal HTTP client of the telemetry exporters that push data to plain-text `http://`
+//! endpoints, optionally through the egress proxy.
+
+use crate::Result;
+use hyper::body::{Bytes, HttpBody};
+use hyper::client::conn;
+use hyper::header::{HeaderMap, HeaderValue, HOST};
+use hyper::{Body, Method, Request, StatusCode, Uri};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// A response of the endpoint.
+#[cfg_attr(not(feature = "otlp-metrics"), allow(dead_code))]
+pub(crate) struct Response {
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+    pub(crate) trailers: Option<HeaderMap>,
+}
+
+/// A `POST` request to an endpoint.
+pub(crate) struct Post<'a> {
+    pub(crate) url: &'a str,
+    pub(crate) proxy: Option<&'a str>,
+    pub(crate) http2: bool,
+    pub(crate) headers: &'a [(&'static str, &'static str)],
+    pub(crate) body: Vec<u8>,
+    pub(crate) timeout: Duration,
+}
+
+impl Post<'_> {
+    /// Sends the request on a new connection and reads the whole response.
+    pub(crate) async fn send(self) -> Result<Response> {
+        let timeout = self.timeout;
+
+        tokio::time::timeout(timeout, self.send_without_timeout())
+            .await
+            .map_err(|_| format!("request timed out after {timeout:?}"))?
+    }
+
+    async fn send_without_timeout(self) -> Result<Response> {
+        let uri: Uri = self.url.parse()?;
+
+        if uri.scheme_str() != Some("http") {
+            return Err(format!("only `http://` endpoints are supported, got `{uri}`").into());
+        }
+
+        let authority = uri
+            .authority()
+            .ok_or_else(|| format!("endpoint `{uri}` doesn't have a host"))?
+            .clone();
+
+        let port = authority.port_u16().unwrap_or(80);
+
+        let stream = match self.proxy {
+            Some(proxy) => {
+                let proxy: Uri = proxy.parse()?;
+                let proxy_host = proxy
+                    .host()
+                    .ok_or_else(|| format!("proxy `{proxy}` doesn't have a host"))?;
+
+                let mut stream =
+                    TcpStream::connect((unbracketed(proxy_host), proxy.port_u16().unwrap_or(80)))
+                        .await?;
+
+                // NOTE: HTTP/2 can't be sent to the proxy in the absolute form, so the connection
+                // is tunneled through the proxy instead.
+                if self.http2 {
+                    tunnel(&mut stream, authority.host(), port).await?;
+                }
+
+                stream
+            }
+            None => TcpStream::connect((unbracketed(authority.host()), port)).await?,
+        };
+
+        // NOTE: HTTP/2 requests need the full URI for the pseudo-headers, HTTP/1 requests are
+        // sent in the origin form, unless they are sent to the proxy.
+        let request_uri = if self.http2 || self.proxy.is_some() {
+            uri.clone()
+        } else {
+            uri.path_and_query()
+                .map_or("/", |path| path.as_str())
+                .parse()?
+        };
+
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(request_uri)
+            .body(Body::from(self.body))?;
+
+        if !self.http2 {
+            request
+                .headers_mut()
+                .insert(HOST, HeaderValue::from_str(authority.as_str())?);
+        }
+
+        for (name, value) in self.headers {
+            request
+                .headers_mut()
+                .insert(*name, HeaderValue::from_static(value));
+        }
+
+        let (mut sender, connection) = conn::Builder::new()
+            .http2_only(self.http2)
+            .handshake(stream)
+            .await?;
+
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+
+        let response = sender.send_request(request).await?;
+        let (parts, mut body) = response.into_parts();
+        let mut data = Vec::new();
+
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk?);
+        }
+
+        Ok(Response {
+            status: parts.status,
+            headers: parts.headers,
+            body: data.into(),
+            trailers: body.trailers().await?,
+        })
+    }
+}
+
+/// Strips the brackets of the IPv6 addresses in the URIs.
+fn unbracketed(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Establishes a tunnel to the endpoint through the proxy with the `CONNECT` method.
+async fn tunnel(stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+    const MAX_RESPONSE_HEAD_SIZE: usize = 8 * 1024;
+
+    stream
+        .write_all(
+            format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n").as_bytes(),
+        )
+        .await?;
+
+    let mut head = Vec::with_capacity(128);
+
+    // NOTE: the response head is read byte by byte, so none of the tunneled data is consumed.
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD_SIZE {
+            return Err("proxy response head is too large".into());
+        }
+
+        head.push(stream.read_u8().await?);
+    }
+
+    let status_line = head.split(|&b| b == b'\r').next().unwrap_or_default();
+    let status = String::from_utf8_lossy(status_line);
+
+    match status.split(' ').nth(1) {
+        Some("200") => Ok(()),
+        _ => Err(format!("proxy refused to establish a tunnel: {status}").into()),
+    }
+}
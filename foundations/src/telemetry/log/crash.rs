@@ -0,0 +1,256 @@
+//! Async-signal-safe logging of the last words of a crashing process.
+//!
+//! The regular log can't be used once the process is crashing: a signal handler can interrupt
+//! the thread that holds the lock of the logger, and a panic can happen while it's held, so
+//! logging from the crash handlers could deadlock instead of letting the process die. [`write`](fn@write)
+//! formats the message into a buffer on the stack, prepends the prefix formatted when the logging
+//! is initialized and writes the record to stderr with `write(2)`, without taking
+//! any locks or allocating.
+//!
+//! If [`LoggingSettings::log_crashes`] is enabled, the panics and the fatal signals, e.g.
+//! `SIGSEGV` or `SIGABRT`, are logged this way. The handlers of the fatal signals restore
+//! the previous handlers once the crash is logged, so the process still dumps core or reports
+//! the stack overflow as usual.
+//!
+//! [`LoggingSettings::log_crashes`]: crate::telemetry::settings::LoggingSettings::log_crashes
+
+use crate::ServiceInfo;
+use once_cell::sync::OnceCell;
+use std::fmt::{self, Write};
+use std::mem::MaybeUninit;
+use std::panic::PanicHookInfo;
+use std::ptr;
+
+// NOTE: messages longer than the buffer are truncated.
+const BUFFER_SIZE: usize = 1024;
+
+const FATAL_SIGNALS: [(libc::c_int, &str); 5] = [
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGABRT, "SIGABRT"),
+];
+
+static PREFIX: OnceCell<Box<str>> = OnceCell::new();
+
+// NOTE: written once before the handlers are installed, so the handlers only read it.
+static PREV_ACTIONS: OnceCell<Vec<(libc::c_int, libc::sigaction)>> = OnceCell::new();
+
+/// Writes a crash log record to stderr.
+///
+/// The function is async-signal-safe as long as formatting of the arguments doesn't allocate or
+/// take locks, which is the case for the strings, the numbers and the other primitive types.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::log::crash;
+///
+/// extern "C" fn on_sigterm(signal: libc::c_int) {
+///     crash::write(format_args!("received signal {signal}, exiting"));
+///
+///     unsafe { libc::_exit(1) };
+/// }
+/// ```
+pub fn write(args: fmt::Arguments<'_>) {
+    let mut buffer = StackBuffer::new();
+
+    // NOTE: errors only signal that the buffer is full, the message is written truncated.
+    let _ = buffer.write_str(PREFIX.get().map_or("CRIT ", |prefix| prefix));
+    let _ = buffer.write_fmt(args);
+
+    buffer.finish_line();
+    write_all(libc::STDERR_FILENO, buffer.as_bytes());
+}
+
+pub(super) fn init(service_info: &ServiceInfo, log_crashes: bool) {
+    let _ = PREFIX.set(format!("CRIT {}[{}]: ", service_info.name, std::process::id()).into());
+
+    if !log_crashes || PREV_ACTIONS.get().is_some() {
+        return;
+    }
+
+    let prev_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        write_panic(info);
+        prev_hook(info);
+    }));
+
+    install_signal_handlers();
+}
+
+fn write_panic(info: &PanicHookInfo<'_>) {
+    let payload = info.payload();
+
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>");
+
+    match info.location() {
+        Some(location) => write(format_args!("panicked at {location}: {message}")),
+        None => write(format_args!("panicked: {message}")),
+    }
+}
+
+fn install_signal_handlers() {
+    let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+        handle_fatal_signal;
+
+    // SAFETY: `sigaction` is only given valid pointers to initialized structures.
+    let prev_actions = FATAL_SIGNALS
+        .iter()
+        .filter_map(|&(signal, _)| unsafe {
+            let mut prev = MaybeUninit::<libc::sigaction>::zeroed();
+
+            (libc::sigaction(signal, ptr::null(), prev.as_mut_ptr()) == 0)
+                .then(|| (signal, prev.assume_init()))
+        })
+        .collect();
+
+    if PREV_ACTIONS.set(prev_actions).is_err() {
+        return;
+    }
+
+    for (signal, _) in PREV_ACTIONS.get().into_iter().flatten() {
+        // SAFETY: the structure is zero-initialized before the fields are set, and the handler
+        // only calls async-signal-safe functions.
+        unsafe {
+            let mut action = MaybeUninit::<libc::sigaction>::zeroed().assume_init();
+
+            action.sa_sigaction = handler as libc::sighandler_t;
+            // NOTE: run on the alternate stack set up by the standard library, so stack
+            // overflows are logged as well.
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(*signal, &action, ptr::null_mut());
+        }
+    }
+}
+
+extern "C" fn handle_fatal_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    let name = FATAL_SIGNALS
+        .iter()
+        .find_map(|&(s, name)| (s == signal).then_some(name))
+        .unwrap_or("unknown");
+
+    // SAFETY: the kernel passes a valid `siginfo_t` to the handlers installed with `SA_SIGINFO`.
+    let (code, addr) = unsafe { ((*info).si_code, (*info).si_addr() as usize) };
+
+    // NOTE: the address is only meaningful for the faults, not for the signals sent by
+    // the processes.
+    if code > 0 {
+        write(format_args!(
+            "received fatal signal {name} ({signal}), fault address {addr:#x}"
+        ));
+    } else {
+        write(format_args!("received fatal signal {name} ({signal})"));
+    }
+
+    let prev = PREV_ACTIONS
+        .get()
+        .into_iter()
+        .flatten()
+        .find_map(|(s, prev)| (*s == signal).then_some(prev));
+
+    // SAFETY: the previous action was returned by `sigaction`.
+    unsafe {
+        match prev {
+            Some(prev) => libc::sigaction(signal, prev, ptr::null_mut()),
+            None => libc::signal(signal, libc::SIG_DFL) as _,
+        };
+
+        // NOTE: the faults are raised again by the faulting instruction once the handler
+        // returns, while the signals sent by the processes, e.g. by `abort`, need to be re-raised.
+        if code <= 0 {
+            libc::raise(signal);
+        }
+    }
+}
+
+fn write_all(fd: libc::c_int, mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        // SAFETY: the pointer and the length come from a valid slice.
+        let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+
+        match written {
+            n if n > 0 => bytes = &bytes[n as usize..],
+            n if n < 0
+                && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => return,
+        }
+    }
+}
+
+/// A fixed-size buffer on the stack that truncates the formatted message if it's full.
+struct StackBuffer {
+    buffer: [u8; BUFFER_SIZE],
+    len: usize,
+}
+
+impl StackBuffer {
+    fn new() -> Self {
+        Self {
+            buffer: [0; BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    fn finish_line(&mut self) {
+        // NOTE: the last byte is reserved for the new line by `write_str`.
+        self.buffer[self.len] = b'\n';
+        self.len += 1;
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl Write for StackBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = BUFFER_SIZE - 1 - self.len;
+        let mut len = s.len().min(available);
+
+        // NOTE: don't split the multi-byte characters.
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.buffer[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+
+        if len < s.len() {
+            return Err(fmt::Error);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_long_messages() {
+        let mut buffer = StackBuffer::new();
+
+        assert!(buffer.write_str("crashed:").is_ok());
+        assert!(write!(buffer, "{}", "é".repeat(BUFFER_SIZE)).is_err());
+
+        buffer.finish_line();
+
+        let line = std::str::from_utf8(buffer.as_bytes()).unwrap();
+
+        assert_eq!(line.len(), BUFFER_SIZE - 1);
+        assert!(line.starts_with("crashed:éé"));
+        assert!(line.ends_with("é\n"));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::telemetry::settings::LoggingSettings;
+use slog::{Drain, Level, Never, OwnedKVList, Record, RecordLocation, RecordStatic};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+const FILTER_BITS: usize = 1 << 14;
+const FILTER_HASHES: u64 = 3;
+
+// NOTE: bounds memory used by the rollups if many distinct messages are repeated in a window.
+// Duplicates of the messages that don't fit are suppressed without a rollup.
+const MAX_ROLLUPS: usize = 1024;
+
+/// A fixed-size bloom filter of the message hashes.
+struct BloomFilter {
+    bits: Box<[u64]>,
+}
+
+impl BloomFilter {
+    fn new() -> Self {
+        Self {
+            bits: vec![0; FILTER_BITS / 64].into_boxed_slice(),
+        }
+    }
+
+    fn positions(hash: u64) -> impl Iterator<Item = usize> {
+        // NOTE: double hashing, see "Less Hashing, Same Performance: Building a Better Bloom
+        // Filter" by Kirsch and Mitzenmacher.
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+
+        (0..FILTER_HASHES)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) as usize) % FILTER_BITS)
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        Self::positions(hash).all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for pos in Self::positions(hash) {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+}
+
+/// Suppressed duplicates of a message.
+struct Rollup {
+    level: Level,
+    location: RecordLocation,
+    tag: String,
+    message: String,
+    values: OwnedKVList,
+    count: u64,
+}
+
+struct State {
+    current: BloomFilter,
+    previous: BloomFilter,
+    rotated_at: Instant,
+    rollups: HashMap<u64, Rollup>,
+}
+
+impl State {
+    /// Rotates the filters if the window has elapsed, returning the rollups of the window.
+    fn rotate(&mut self, window: Duration, now: Instant) -> Option<HashMap<u64, Rollup>> {
+        if now.saturating_duration_since(self.rotated_at) < window {
+            return None;
+        }
+
+        mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+        self.rotated_at = now;
+
+        Some(mem::take(&mut self.rollups))
+    }
+}
+
+/// A drain that suppresses repeated identical records, see [`LogDedupSettings`].
+///
+/// [`LogDedupSettings`]: crate::telemetry::settings::LogDedupSettings
+pub(crate) struct DedupDrain<D: Drain<Ok = (), Err = Never>> {
+    inner: D,
+    level: Level,
+    window: Duration,
+    state: Option<Mutex<State>>,
+}
+
+impl<D: Drain<Ok = (), Err = Never>> DedupDrain<D> {
+    pub(crate) fn new(inner: D, settings: &LoggingSettings) -> Self {
+        let state = settings.dedup.enabled.then(|| {
+            Mutex::new(State {
+                current: BloomFilter::new(),
+                previous: BloomFilter::new(),
+                rotated_at: Instant::now(),
+                rollups: Default::default(),
+            })
+        });
+
+        Self {
+            inner,
+            level: *settings.dedup.level,
+            window: Duration::from_millis(settings.dedup.window_ms),
+            state,
+        }
+    }
+
+    // NOTE: std mutex is used, since unlike the `parking_lot` one it is `RefUnwindSafe`, as
+    // required for the drains by `slog`.
+    fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+        state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn emit_rollups(&self, rollups: HashMap<u64, Rollup>) {
+        for rollup in rollups.into_values() {
+            let rstatic = RecordStatic {
+                location: &rollup.location,
+                tag: &rollup.tag,
+                level: rollup.level,
+            };
+
+            let _ = self.inner.log(
+                &Record::new(
+                    &rstatic,
+                    &format_args!("{}", rollup.message),
+                    slog::b!("suppressed_duplicates" => rollup.count),
+                ),
+                &rollup.values,
+            );
+        }
+    }
+}
+
+fn message_hash(record: &Record, message: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    record.level().as_usize().hash(&mut hasher);
+    record.module().hash(&mut hasher);
+    record.file().hash(&mut hasher);
+    record.line().hash(&mut hasher);
+    message.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+impl<D: Drain<Ok = (), Err = Never>> Drain for DedupDrain<D> {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let Some(state) = &self.state else {
+            return self.inner.log(record, values);
+        };
+
+        if !record.level().is_at_least(self.level) {
+            return self.inner.log(record, values);
+        }
+
+        let message = record.msg().to_string();
+        let hash = message_hash(record, &message);
+        let mut state = Self::lock(state);
+        let rollups = state.rotate(self.window, Instant::now());
+        let duplicate = state.current.contains(hash) || state.previous.contains(hash);
+
+        // NOTE: duplicates are also added to the current filter, so a message that keeps
+        // repeating is only reported with the rollups.
+        state.current.insert(hash);
+
+        if duplicate {
+            let has_capacity = state.rollups.len() < MAX_ROLLUPS;
+
+            if let Some(rollup) = state.rollups.get_mut(&hash) {
+                rollup.count += 1;
+            } else if has_capacity {
+                state.rollups.insert(
+                    hash,
+                    Rollup {
+                        level: record.level(),
+                        location: *record.location(),
+                        tag: record.tag().to_string(),
+                        message,
+                        values: values.clone(),
+                        count: 1,
+                    },
+                );
+            }
+        }
+
+        drop(state);
+
+        if let Some(rollups) = rollups {
+            self.emit_rollups(rollups);
+        }
+
+        if duplicate {
+            Ok(())
+        } else {
+            self.inner.log(record, values)
+        }
+    }
+}
+
+impl<D: Drain<Ok = (), Err = Never>> Drop for DedupDrain<D> {
+    fn drop(&mut self) {
+        if let Some(state) = &self.state {
+            let rollups = mem::take(&mut Self::lock(state).rollups);
+
+            self.emit_rollups(rollups);
+        }
+    }
+}
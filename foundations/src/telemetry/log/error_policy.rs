@@ -0,0 +1,196 @@
+use crate::telemetry::settings::LogDrainErrorPolicy;
+use slog::{Drain, Never, OwnedKVList, Record};
+use slog_term::{FullFormat as TextDrain, PlainDecorator};
+use std::fmt::Debug;
+use std::io::{self, Stderr};
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::Counter;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations {
+    /// Number of log records that the output drain failed to write.
+    pub fn log_drain_failures_total(drain: &'static str, policy: &'static str) -> Counter;
+}
+
+type StderrDrain = TextDrain<PlainDecorator<Stderr>>;
+
+/// A drain that isolates failures of the wrapped output drain from the rest of the
+/// logging pipeline, handling them according to the configured [`LogDrainErrorPolicy`].
+pub(crate) struct ErrorPolicyDrain<D, F = StderrDrain> {
+    inner: D,
+    name: &'static str,
+    policy: LogDrainErrorPolicy,
+    fallback: Option<F>,
+}
+
+impl<D> ErrorPolicyDrain<D> {
+    pub(crate) fn new(inner: D, name: &'static str, policy: LogDrainErrorPolicy) -> Self {
+        let fallback = TextDrain::new(PlainDecorator::new(io::stderr())).build();
+
+        Self::with_fallback(inner, name, policy, fallback)
+    }
+}
+
+impl<D, F> ErrorPolicyDrain<D, F> {
+    fn with_fallback(
+        inner: D,
+        name: &'static str,
+        policy: LogDrainErrorPolicy,
+        fallback: F,
+    ) -> Self {
+        Self {
+            inner,
+            name,
+            policy,
+            fallback: matches!(policy, LogDrainErrorPolicy::Fallback).then_some(fallback),
+        }
+    }
+}
+
+impl<D, F> Drain for ErrorPolicyDrain<D, F>
+where
+    D: Drain,
+    D::Err: Debug,
+    F: Drain,
+{
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let err = match self.inner.log(record, values) {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+
+        #[cfg(feature = "metrics")]
+        foundations::log_drain_failures_total(self.name, self.policy.as_str()).inc();
+
+        match self.policy {
+            LogDrainErrorPolicy::Ignore => {}
+            LogDrainErrorPolicy::Fallback => {
+                if let Some(fallback) = &self.fallback {
+                    // NOTE: there is nowhere else to report the error if stderr is also broken.
+                    let _ = fallback.log(record, values);
+                }
+            }
+            LogDrainErrorPolicy::Escalate => {
+                // NOTE: the drain runs on the background thread of the async drain, so a panic
+                // would only stop the logging instead of reaching the service.
+                eprintln!("`{}` log drain failed: {err:?}", self.name);
+                std::process::abort();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Logger};
+    use std::process::Command;
+    use std::sync::{Arc, Mutex};
+
+    struct FailingDrain;
+
+    impl Drain for FailingDrain {
+        type Ok = ();
+        type Err = io::Error;
+
+        fn log(&self, _record: &Record, _values: &OwnedKVList) -> Result<(), io::Error> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "sink is gone"))
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingDrain(Arc<Mutex<Vec<String>>>);
+
+    impl Drain for RecordingDrain {
+        type Ok = ();
+        type Err = Never;
+
+        fn log(&self, record: &Record, _values: &OwnedKVList) -> Result<(), Never> {
+            self.0.lock().unwrap().push(record.msg().to_string());
+
+            Ok(())
+        }
+    }
+
+    fn logger(name: &'static str, policy: LogDrainErrorPolicy, fallback: RecordingDrain) -> Logger {
+        let drain = ErrorPolicyDrain::with_fallback(FailingDrain, name, policy, fallback);
+
+        Logger::root(Mutex::new(drain).fuse(), o!())
+    }
+
+    #[test]
+    fn ignore_policy_swallows_errors() {
+        let fallback = RecordingDrain::default();
+        let log = logger("test_ignore", LogDrainErrorPolicy::Ignore, fallback.clone());
+
+        slog::info!(log, "first");
+        slog::info!(log, "second");
+
+        assert!(fallback.0.lock().unwrap().is_empty());
+
+        #[cfg(feature = "metrics")]
+        assert_eq!(
+            foundations::log_drain_failures_total("test_ignore", "ignore").get(),
+            2
+        );
+    }
+
+    #[test]
+    fn fallback_policy_writes_to_fallback() {
+        let fallback = RecordingDrain::default();
+        let log = logger(
+            "test_fallback",
+            LogDrainErrorPolicy::Fallback,
+            fallback.clone(),
+        );
+
+        slog::info!(log, "to fallback");
+
+        assert_eq!(*fallback.0.lock().unwrap(), ["to fallback"]);
+
+        #[cfg(feature = "metrics")]
+        assert_eq!(
+            foundations::log_drain_failures_total("test_fallback", "fallback").get(),
+            1
+        );
+    }
+
+    #[test]
+    fn escalate_policy_aborts() {
+        const CHILD_ENV: &str = "FOUNDATIONS_TEST_ESCALATE_POLICY_CHILD";
+
+        // NOTE: the process is aborted, so the policy is exercised in a child process.
+        if std::env::var_os(CHILD_ENV).is_some() {
+            let log = logger(
+                "test_escalate",
+                LogDrainErrorPolicy::Escalate,
+                Default::default(),
+            );
+
+            slog::info!(log, "boom");
+
+            return;
+        }
+
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "telemetry::log::error_policy::tests::escalate_policy_aborts",
+                "--nocapture",
+            ])
+            .env(CHILD_ENV, "1")
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("`test_escalate` log drain failed: Custom { kind: BrokenPipe"));
+    }
+}
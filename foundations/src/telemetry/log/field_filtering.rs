@@ -62,11 +62,20 @@ where
     }
 }
 
-struct FieldFilteringKV<K, F> {
+pub(crate) struct FieldFilteringKV<K, F> {
     inner: K,
     filter_factory: F,
 }
 
+impl<K, F> FieldFilteringKV<K, F> {
+    pub(crate) fn new(inner: K, filter_factory: F) -> Self {
+        Self {
+            inner,
+            filter_factory,
+        }
+    }
+}
+
 impl<K, F> KV for FieldFilteringKV<K, F>
 where
     K: KV,
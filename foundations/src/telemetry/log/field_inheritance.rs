@@ -0,0 +1,47 @@
+use super::field_filtering::{Filter, FilterFactory};
+use slog::Key;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Fields of the parent log inherited by a log forked with
+/// [`TelemetryContext::with_forked_log_fields`].
+///
+/// [`TelemetryContext::with_forked_log_fields`]: crate::telemetry::TelemetryContext::with_forked_log_fields
+#[derive(Clone, Debug)]
+pub struct InheritedLogFields {
+    keys: Arc<HashSet<String>>,
+    keep: bool,
+}
+
+impl InheritedLogFields {
+    /// Inherits only the fields with the given keys.
+    pub fn only(keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            keys: Arc::new(keys.into_iter().map(Into::into).collect()),
+            keep: true,
+        }
+    }
+
+    /// Inherits all the fields except the ones with the given keys.
+    pub fn except(keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            keys: Arc::new(keys.into_iter().map(Into::into).collect()),
+            keep: false,
+        }
+    }
+}
+
+impl FilterFactory for InheritedLogFields {
+    type Filter = Self;
+
+    fn create_filter(&self) -> Self::Filter {
+        self.clone()
+    }
+}
+
+impl Filter for InheritedLogFields {
+    #[inline]
+    fn filter(&mut self, key: &Key) -> bool {
+        self.keys.contains(*key) == self.keep
+    }
+}
@@ -1,61 +1,144 @@
+use super::dedup::DedupDrain;
+use super::error_policy::ErrorPolicyDrain;
 use super::field_dedup::FieldDedupFilterFactory;
 use super::field_filtering::FieldFilteringDrain;
 use super::field_redact::FieldRedactFilterFactory;
 use super::internal::SharedLog;
+use super::module_budget::ModuleBudgetDrain;
+use super::outputs::{
+    AdditionalOutput, AdditionalOutputsDrain, OutputsDrain, ReloadableOutputDrain, Verbosity,
+    VerbosityFilter,
+};
+use super::pre_init::{PreInitDrain, PRE_INIT_BUFFER_CAPACITY};
+use super::priority::PriorityDrain;
+use super::rotation::RotatingFileWriter;
 
 #[cfg(feature = "metrics")]
 use crate::telemetry::log::log_volume::LogVolumeMetricsDrain;
 
+#[cfg(feature = "diagnostics")]
+use super::recent::RecentRecordsDrain;
+
 use crate::telemetry::log::rate_limit::RateLimitingDrain;
 use crate::telemetry::scope::ScopeStack;
-use crate::telemetry::settings::{LogFormat, LogOutput, LoggingSettings};
+use crate::telemetry::settings::{LogFormat, LogOutput, LogOutputSettings, LoggingSettings};
 use crate::{BootstrapResult, ServiceInfo};
 use once_cell::sync::{Lazy, OnceCell};
 use slog::{
-    Discard, Drain, FnValue, Fuse, LevelFilter, Logger, Never, OwnedKV, SendSyncRefUnwindSafeDrain,
-    SendSyncRefUnwindSafeKV, SendSyncUnwindSafeDrain,
+    Drain, FnValue, Logger, Never, OwnedKV, SendSyncRefUnwindSafeDrain, SendSyncRefUnwindSafeKV,
+    SendSyncUnwindSafeDrain,
 };
-use slog_async::Async as AsyncDrain;
 use slog_json::{Json as JsonDrain, Json};
 use slog_term::{FullFormat as TextDrain, PlainDecorator, TermDecorator};
+use std::fmt::Debug;
 use std::fs::File;
 use std::io;
 use std::panic::RefUnwindSafe;
 use std::sync::Arc;
 
-type FilteredDrain<D> = LevelFilter<
-    FieldFilteringDrain<FieldRedactFilterFactory, FieldFilteringDrain<FieldDedupFilterFactory, D>>,
+type FilteredDrain<D> = VerbosityFilter<
+    FieldFilteringDrain<
+        FieldRedactFilterFactory,
+        FieldFilteringDrain<FieldDedupFilterFactory, OutputsDrain<D>>,
+    >,
 >;
 
 static HARNESS: OnceCell<LogHarness> = OnceCell::new();
 
+static PRE_INIT_DRAIN: Lazy<Arc<PreInitDrain>> =
+    Lazy::new(|| Arc::new(PreInitDrain::new(PRE_INIT_BUFFER_CAPACITY)));
+
 static NOOP_HARNESS: Lazy<LogHarness> = Lazy::new(|| {
-    let root_drain = Arc::new(Discard);
-    let noop_log = Logger::root(Arc::clone(&root_drain), slog::o!());
+    let noop_log = Logger::root(Arc::clone(&PRE_INIT_DRAIN), slog::o!());
 
     LogHarness {
-        root_drain,
         root_log: Arc::new(parking_lot::RwLock::new(noop_log)),
-        settings: Default::default(),
-        log_scope_stack: Default::default(),
+        verbosity: Verbosity::new(
+            *LoggingSettings::default().verbosity,
+            None,
+            &Default::default(),
+        ),
+        reloadable_output: None,
+        log_scope_stack: ScopeStack::new("log"),
     }
 });
 
 pub(crate) struct LogHarness {
     pub(crate) root_log: SharedLog,
-    pub(crate) root_drain: Arc<dyn SendSyncRefUnwindSafeDrain<Ok = (), Err = Never>>,
-    pub(crate) settings: LoggingSettings,
+    pub(crate) verbosity: Verbosity,
+    pub(crate) reloadable_output: Option<ReloadableOutput>,
     pub(crate) log_scope_stack: ScopeStack<SharedLog>,
 }
 
+/// The log outputs along with the settings they have been built with.
+pub(crate) struct ReloadableOutput {
+    drain: Arc<ReloadableOutputDrain>,
+    additional: Arc<AdditionalOutputsDrain>,
+    settings: parking_lot::Mutex<OutputSettings>,
+}
+
+#[derive(Clone, PartialEq)]
+struct OutputSettings {
+    output: LogOutput,
+    format: LogFormat,
+    additional_outputs: Vec<LogOutputSettings>,
+}
+
+impl OutputSettings {
+    fn new(settings: &LoggingSettings) -> Self {
+        Self {
+            output: settings.output.clone(),
+            format: settings.format,
+            additional_outputs: settings.additional_outputs.clone(),
+        }
+    }
+}
+
+/// The log outputs built with the reloaded settings, see [`prepare_reload`].
+pub(crate) struct OutputReload {
+    harness: &'static LogHarness,
+    output: &'static ReloadableOutput,
+    drain: Option<PriorityDrain>,
+    additional: Option<Vec<(slog::Level, AdditionalOutput)>>,
+    settings: OutputSettings,
+}
+
+impl OutputReload {
+    /// Replaces the changed log outputs of all the existing logs.
+    pub(crate) fn apply(self) {
+        if let Some(drain) = self.drain {
+            self.output.drain.replace(drain);
+        }
+
+        if let Some(additional) = self.additional {
+            self.output.additional.replace(additional);
+            self.harness
+                .verbosity
+                .set_additional(self.output.additional.max_level());
+        }
+
+        *self.output.settings.lock() = self.settings;
+    }
+}
+
 impl LogHarness {
     pub(crate) fn get() -> &'static Self {
         HARNESS.get().unwrap_or(&NOOP_HARNESS)
     }
 
+    /// Returns whether the logging has been initialized.
+    pub(crate) fn is_initialized() -> bool {
+        HARNESS.get().is_some()
+    }
+
     #[cfg(any(test, feature = "testing"))]
     pub(crate) fn override_for_testing(override_harness: Self) -> Result<(), ()> {
-        HARNESS.set(override_harness).map_err(|_| ())
+        HARNESS.set(override_harness).map_err(|_| ())?;
+
+        // NOTE: the records logged before the tests can't be attributed to any of them.
+        PRE_INIT_DRAIN.discard();
+
+        Ok(())
     }
 }
 
@@ -66,33 +149,25 @@ pub(crate) fn init(service_info: &ServiceInfo, settings: &LoggingSettings) -> Bo
         return Ok(());
     }
 
-    // NOTE: OXY-178, default is 128 (https://docs.rs/slog-async/2.7.0/src/slog_async/lib.rs.html#251)
-    const CHANNEL_SIZE: usize = 1024;
+    #[cfg(unix)]
+    super::crash::init(service_info, settings.log_crashes);
 
-    let base_drain = match (&settings.output, &settings.format) {
-        (LogOutput::Terminal, LogFormat::Text) => {
-            let drain = TextDrain::new(TermDecorator::new().stdout().build())
-                .build()
-                .fuse();
-            AsyncDrain::new(drain).chan_size(CHANNEL_SIZE).build()
-        }
-        (LogOutput::Terminal, LogFormat::Json) => {
-            let drain = build_json_log_drain(io::stdout());
-            AsyncDrain::new(drain).chan_size(CHANNEL_SIZE).build()
-        }
-        (LogOutput::File(file), LogFormat::Text) => {
-            let drain = TextDrain::new(PlainDecorator::new(File::create(file)?))
-                .build()
-                .fuse();
-            AsyncDrain::new(drain).chan_size(CHANNEL_SIZE).build()
-        }
-        (LogOutput::File(file), LogFormat::Json) => {
-            let drain = build_json_log_drain(File::create(file)?);
-            AsyncDrain::new(drain).chan_size(CHANNEL_SIZE).build()
-        }
-    };
+    let base_drain = build_output_drain(
+        &settings.output,
+        settings.format,
+        recent_records(settings),
+        settings,
+    )?;
+
+    let output = Arc::new(ReloadableOutputDrain::new(base_drain));
+    let root_drain = get_root_drain(settings, Arc::clone(&output) as _);
+
+    // NOTE: the additional outputs drain is created even if there are none, so they can be added
+    // when the settings are reloaded.
+    let additional_outputs = Arc::new(AdditionalOutputsDrain::new(build_additional_outputs(
+        settings,
+    )?));
 
-    let root_drain = get_root_drain(settings, Arc::new(base_drain.fuse()));
     let root_kv = slog::o!(
         "module" => FnValue(|record| {
             format!("{}:{}", record.module(), record.line())
@@ -101,19 +176,148 @@ pub(crate) fn init(service_info: &ServiceInfo, settings: &LoggingSettings) -> Bo
         "pid" => std::process::id(),
     );
 
-    let root_log = build_log_with_drain(settings, root_kv, Arc::clone(&root_drain));
-    let harness = LogHarness {
+    let verbosity = Verbosity::new(
+        *settings.verbosity,
+        Some(&additional_outputs),
+        &settings.filter,
+    );
+
+    let root_log = build_log_with_drain(
+        settings,
+        root_kv,
         root_drain,
+        Some(Arc::clone(&additional_outputs)),
+        verbosity.clone(),
+    );
+
+    let harness = LogHarness {
         root_log: Arc::new(parking_lot::RwLock::new(root_log)),
-        settings: settings.clone(),
-        log_scope_stack: Default::default(),
+        verbosity,
+        reloadable_output: Some(ReloadableOutput {
+            drain: output,
+            additional: additional_outputs,
+            settings: parking_lot::Mutex::new(OutputSettings::new(settings)),
+        }),
+        log_scope_stack: ScopeStack::new("log"),
     };
 
-    let _ = HARNESS.set(harness);
+    if HARNESS.set(harness).is_ok() {
+        PRE_INIT_DRAIN.replay(&HARNESS.get().unwrap().root_log.read());
+    }
 
     Ok(())
 }
 
+// NOTE: returns `None` if logging hasn't been initialized or the outputs haven't changed.
+pub(crate) fn prepare_reload(settings: &LoggingSettings) -> BootstrapResult<Option<OutputReload>> {
+    let Some(harness) = HARNESS.get() else {
+        return Ok(None);
+    };
+
+    let Some(output) = harness.reloadable_output.as_ref() else {
+        return Ok(None);
+    };
+
+    let new_settings = OutputSettings::new(settings);
+    let current_settings = output.settings.lock().clone();
+
+    if current_settings == new_settings {
+        return Ok(None);
+    }
+
+    // NOTE: only the changed outputs are rebuilt, so e.g. the files of the unchanged outputs are
+    // not truncated.
+    let drain = if (&current_settings.output, current_settings.format)
+        != (&new_settings.output, new_settings.format)
+    {
+        Some(build_output_drain(
+            &settings.output,
+            settings.format,
+            recent_records(settings),
+            settings,
+        )?)
+    } else {
+        None
+    };
+
+    let additional = if current_settings.additional_outputs != new_settings.additional_outputs {
+        Some(reload_additional_outputs(
+            settings,
+            &current_settings.additional_outputs,
+            output.additional.drains(),
+        )?)
+    } else {
+        None
+    };
+
+    Ok(Some(OutputReload {
+        harness,
+        output,
+        drain,
+        additional,
+        settings: new_settings,
+    }))
+}
+
+fn build_additional_outputs(
+    settings: &LoggingSettings,
+) -> BootstrapResult<Vec<(slog::Level, PriorityDrain)>> {
+    settings
+        .additional_outputs
+        .iter()
+        .map(|output| {
+            let drain = build_output_drain(&output.output, output.format, 0, settings)?;
+
+            Ok((*output.verbosity, drain))
+        })
+        .collect()
+}
+
+// NOTE: the drains of the current outputs with the same destination and format are kept, so
+// e.g. their files are not truncated.
+fn reload_additional_outputs(
+    settings: &LoggingSettings,
+    current_settings: &[LogOutputSettings],
+    current_drains: Vec<AdditionalOutput>,
+) -> BootstrapResult<Vec<(slog::Level, AdditionalOutput)>> {
+    let mut current: Vec<_> = current_settings
+        .iter()
+        .zip(current_drains.into_iter().map(Some))
+        .collect();
+
+    settings
+        .additional_outputs
+        .iter()
+        .map(|output| {
+            let kept = current
+                .iter_mut()
+                .find(|(current, drain)| {
+                    drain.is_some()
+                        && current.output == output.output
+                        && current.format == output.format
+                })
+                .and_then(|(_, drain)| drain.take());
+
+            let drain = match kept {
+                Some(drain) => drain,
+                None => {
+                    Arc::new(build_output_drain(&output.output, output.format, 0, settings)?.fuse())
+                }
+            };
+
+            Ok((*output.verbosity, drain))
+        })
+        .collect()
+}
+
+fn recent_records(_settings: &LoggingSettings) -> usize {
+    #[cfg(feature = "diagnostics")]
+    return _settings.recent_records;
+
+    #[cfg(not(feature = "diagnostics"))]
+    0
+}
+
 fn get_root_drain(
     _settings: &LoggingSettings,
     base_drain: Arc<dyn SendSyncRefUnwindSafeDrain<Err = Never, Ok = ()> + 'static>,
@@ -127,35 +331,46 @@ fn get_root_drain(
 
 pub(crate) fn apply_filters_to_drain<D>(
     drain: D,
+    additional_outputs: Option<Arc<AdditionalOutputsDrain>>,
+    verbosity: Verbosity,
     settings: &LoggingSettings,
-) -> RateLimitingDrain<FilteredDrain<D>>
+) -> DedupDrain<ModuleBudgetDrain<RateLimitingDrain<FilteredDrain<D>>>>
 where
     D: Drain<Ok = (), Err = Never> + 'static,
 {
+    let drain = OutputsDrain::new(drain, verbosity.clone(), additional_outputs);
     let drain = FieldFilteringDrain::new(drain, FieldDedupFilterFactory);
     let drain = FieldFilteringDrain::new(
         drain,
         FieldRedactFilterFactory::new(settings.redact_keys.clone()),
     );
-    let drain = drain.filter_level(*settings.verbosity);
+    let drain = VerbosityFilter::new(drain, verbosity.clone());
+
+    let drain = RateLimitingDrain::new(drain, settings);
+
+    // NOTE: budgets are enforced before the global rate limit, so suppressed records of a chatty
+    // module don't consume the global rate limit of the other modules.
+    let drain = ModuleBudgetDrain::new(drain, verbosity, settings);
 
-    RateLimitingDrain::new(drain, settings)
+    DedupDrain::new(drain, settings)
 }
 
 pub(crate) fn build_log_with_drain<D, K>(
     settings: &LoggingSettings,
     kv: OwnedKV<K>,
     drain: D,
+    additional_outputs: Option<Arc<AdditionalOutputsDrain>>,
+    verbosity: Verbosity,
 ) -> Logger
 where
     D: SendSyncUnwindSafeDrain<Ok = (), Err = Never> + RefUnwindSafe + 'static,
     K: SendSyncRefUnwindSafeKV + 'static,
 {
-    let drain = apply_filters_to_drain(drain, settings);
+    let drain = apply_filters_to_drain(drain, additional_outputs, verbosity, settings);
     Logger::root(drain, kv)
 }
 
-fn build_json_log_drain<O>(output: O) -> Fuse<Json<O>>
+fn build_json_log_drain<O>(output: O) -> Json<O>
 where
     O: io::Write + Send + 'static,
 {
@@ -163,5 +378,72 @@ where
         .add_default_keys()
         .set_pretty(false)
         .build()
-        .fuse()
+}
+
+fn build_output_drain(
+    output: &LogOutput,
+    format: LogFormat,
+    recent_records: usize,
+    settings: &LoggingSettings,
+) -> BootstrapResult<PriorityDrain> {
+    Ok(match (output, format) {
+        (LogOutput::Terminal, LogFormat::Text) => {
+            let drain = TextDrain::new(TermDecorator::new().stdout().build()).build();
+            build_async_drain(drain, "terminal", recent_records, settings)
+        }
+        (LogOutput::Terminal, LogFormat::Json) => {
+            let drain = build_json_log_drain(io::stdout());
+            build_async_drain(drain, "terminal", recent_records, settings)
+        }
+        (LogOutput::File(file), LogFormat::Text) => {
+            let drain = TextDrain::new(PlainDecorator::new(File::create(file)?)).build();
+            build_async_drain(drain, "file", recent_records, settings)
+        }
+        (LogOutput::File(file), LogFormat::Json) => {
+            let drain = build_json_log_drain(File::create(file)?);
+            build_async_drain(drain, "file", recent_records, settings)
+        }
+        (LogOutput::RotatingFile(output), LogFormat::Text) => {
+            let writer = RotatingFileWriter::new(output)?;
+            let drain = TextDrain::new(PlainDecorator::new(writer)).build();
+            build_async_drain(drain, "file", recent_records, settings)
+        }
+        (LogOutput::RotatingFile(output), LogFormat::Json) => {
+            // NOTE: the writer rotates the file on flushes, so it's flushed after each record.
+            let drain = JsonDrain::new(RotatingFileWriter::new(output)?)
+                .add_default_keys()
+                .set_pretty(false)
+                .set_flush(true)
+                .build();
+
+            build_async_drain(drain, "file", recent_records, settings)
+        }
+    })
+}
+
+// NOTE: the most recent records are retained only if `recent_records` is non-zero.
+fn build_async_drain<D>(
+    drain: D,
+    name: &'static str,
+    _recent_records: usize,
+    settings: &LoggingSettings,
+) -> PriorityDrain
+where
+    D: Drain + Send + 'static,
+    D::Err: Debug,
+{
+    // NOTE: OXY-178, default is 128 (https://docs.rs/slog-async/2.7.0/src/slog_async/lib.rs.html#251)
+    const CHANNEL_SIZE: usize = 1024;
+
+    let drain = ErrorPolicyDrain::new(drain, name, settings.error_policy);
+    let priority_level = *settings.overflow.priority_level;
+
+    #[cfg(feature = "diagnostics")]
+    if _recent_records > 0 {
+        let drain = RecentRecordsDrain::new(drain, _recent_records);
+
+        return PriorityDrain::new(drain, name, CHANNEL_SIZE, priority_level);
+    }
+
+    PriorityDrain::new(drain, name, CHANNEL_SIZE, priority_level)
 }
@@ -1,3 +1,5 @@
+use super::field_filtering::FieldFilteringKV;
+use super::field_inheritance::InheritedLogFields;
 use super::init::LogHarness;
 use crate::telemetry::scope::Scope;
 use slog::{Logger, OwnedKV, SendSyncRefUnwindSafeKV};
@@ -29,7 +31,10 @@ where
 
 pub fn current_log() -> SharedLog {
     let harness = LogHarness::get();
-    let log = harness.log_scope_stack.current();
+    let log = harness
+        .log_scope_stack
+        .current()
+        .or_else(|| crate::telemetry::with_thread_context(|ctx| Arc::clone(&ctx.log)));
 
     log.unwrap_or_else(|| Arc::clone(&harness.root_log))
 }
@@ -40,3 +45,14 @@ pub(crate) fn fork_log() -> SharedLog {
 
     Arc::new(parking_lot::RwLock::new(log))
 }
+
+// NOTE: the forked log can't be derived from the parent log, since it would inherit all of its
+// fields, so it's derived from the root log with a filtered copy of the parent log's fields.
+pub(crate) fn fork_log_with_fields(fields: InheritedLogFields) -> SharedLog {
+    let parent = current_log();
+    let kv = FieldFilteringKV::new(parent.read().list().clone(), fields);
+    let root = LogHarness::get().root_log.read().clone();
+    let log = Logger::root(root, OwnedKV(kv));
+
+    Arc::new(parking_lot::RwLock::new(log))
+}
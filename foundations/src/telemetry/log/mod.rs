@@ -1,12 +1,23 @@
 //! Logging-related functionality.
 
+mod dedup;
+mod error_policy;
 mod field_dedup;
 mod field_filtering;
+mod field_inheritance;
 mod field_redact;
+mod module_budget;
+mod outputs;
+mod pre_init;
+mod priority;
 mod rate_limit;
+mod rotation;
 
 pub(crate) mod init;
 
+#[cfg(unix)]
+pub mod crash;
+
 #[cfg(any(test, feature = "testing"))]
 pub(crate) mod testing;
 
@@ -16,33 +27,112 @@ pub mod internal;
 #[cfg(feature = "metrics")]
 pub mod log_volume;
 
+#[cfg(feature = "diagnostics")]
+pub(crate) mod recent;
+
 use self::init::LogHarness;
 use self::internal::current_log;
-use crate::telemetry::log::init::build_log_with_drain;
-use crate::telemetry::settings::LogVerbosity;
+use crate::telemetry::NotInitializedError;
 use crate::Result;
-use slog::{Level, Logger, OwnedKV};
+use slog::{Level, Logger};
 use std::sync::Arc;
 
+#[cfg(feature = "telemetry-server")]
+use crate::telemetry::TelemetryServerRoute;
+
 #[cfg(any(test, feature = "testing"))]
 pub use self::testing::TestLogRecord;
 
-/// Sets current log's verbosity, overriding the settings used in [`init`].
+pub use self::field_inheritance::InheritedLogFields;
+
+/// Sets the verbosity of the main log output, overriding the settings used in [`init`].
+///
+/// The verbosity is changed atomically for all the existing logs, including the forked ones, so
+/// e.g. debug logs can be enabled for a running service without a restart. The verbosity of
+/// the [additional outputs] is not affected.
+#[cfg_attr(
+    feature = "telemetry-server",
+    doc = "The verbosity can also be changed with the telemetry server, see \
+           [`telemetry_server_route`]."
+)]
+///
+/// Returns [`NotInitializedError`] if the logging hasn't been initialized yet, since the
+/// verbosity of the records emitted before the initialization is determined by the settings
+/// used in [`init`].
 ///
 /// [`init`]: crate::telemetry::init
+/// [additional outputs]: crate::telemetry::settings::LoggingSettings::additional_outputs
 pub fn set_verbosity(level: Level) -> Result<()> {
-    let harness = LogHarness::get();
+    if !LogHarness::is_initialized() {
+        return Err(NotInitializedError::new("logging").into());
+    }
 
-    let mut settings = harness.settings.clone();
-    settings.verbosity = LogVerbosity(level);
-
-    let kv = OwnedKV(current_log().read().list().clone());
-    let logger = build_log_with_drain(&settings, kv, Arc::clone(&harness.root_drain));
-    *current_log().write() = logger;
+    LogHarness::get().verbosity.set_main(level);
 
     Ok(())
 }
 
+/// Returns the current verbosity of the main log output, see [`set_verbosity`].
+pub fn verbosity() -> Level {
+    LogHarness::get().verbosity.main()
+}
+
+/// Returns a telemetry server route that changes the log verbosity at runtime.
+///
+/// The route should be passed to [`init_with_server`] and handles the following requests to
+/// `/loglevel` path:
+/// - `GET` returns the current verbosity of the main log output, e.g. `INFO`.
+/// - `PUT` with a level name body, e.g. `debug`, sets the verbosity with [`set_verbosity`].
+///
+/// Modifications are rejected with `403 Forbidden` if the telemetry server is in
+/// the [read-only mode].
+///
+/// [`init_with_server`]: crate::telemetry::init_with_server
+/// [read-only mode]: crate::telemetry::settings::TelemetryServerSettings::read_only
+#[cfg(feature = "telemetry-server")]
+pub fn telemetry_server_route() -> TelemetryServerRoute {
+    use futures_util::FutureExt;
+    use hyper::{Body, Method, Response, StatusCode};
+
+    fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .body(body.into())
+            .unwrap()
+    }
+
+    TelemetryServerRoute {
+        path: "/loglevel".into(),
+        methods: vec![Method::GET, Method::PUT],
+        handler: Box::new(|req, _| {
+            async move {
+                if req.method() == Method::GET {
+                    return Ok(response(StatusCode::OK, verbosity().as_str()));
+                }
+
+                let body = match hyper::body::to_bytes(req.into_body()).await {
+                    Ok(body) => body,
+                    Err(err) => return Ok(response(StatusCode::BAD_REQUEST, err.to_string())),
+                };
+
+                let Ok(level) = std::str::from_utf8(&body)
+                    .unwrap_or_default()
+                    .trim()
+                    .parse()
+                else {
+                    return Ok(response(StatusCode::BAD_REQUEST, "unknown log level"));
+                };
+
+                Ok(match set_verbosity(level) {
+                    Ok(()) => response(StatusCode::OK, level.as_str()),
+                    Err(err) => response(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+                })
+            }
+            .boxed()
+        }),
+    }
+}
+
 /// Returns current log as a raw [slog] crate's `Logger` used by Foundations internally.
 ///
 /// Can be used to propagate the logging context to libraries that don't use Foundations'
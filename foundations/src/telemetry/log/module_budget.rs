@@ -0,0 +1,138 @@
+use super::outputs::{matches_module, Verbosity};
+use crate::telemetry::settings::LoggingSettings;
+use governor::clock::DefaultClock;
+use governor::middleware::NoOpMiddleware;
+use governor::state::{InMemoryState, NotKeyed};
+use governor::{Quota, RateLimiter};
+use slog::{Drain, Never, OwnedKVList, Record};
+use std::sync::Arc;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::Counter;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations {
+    /// Number of log records suppressed because the module exceeded its log budget.
+    pub fn log_records_suppressed(module: &Arc<str>) -> Counter;
+}
+
+struct ModuleBudget {
+    module: Arc<str>,
+    rate_limiter: RateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>,
+}
+
+impl ModuleBudget {
+    fn matches(&self, module: &str) -> bool {
+        matches_module(&self.module, module)
+    }
+}
+
+/// A drain that enforces [per-module log budgets].
+///
+/// [per-module log budgets]: crate::telemetry::settings::LoggingSettings::module_budgets
+pub(crate) struct ModuleBudgetDrain<D: Drain<Err = Never>> {
+    inner: D,
+    verbosity: Verbosity,
+    // NOTE: sorted by the module path length in descending order, so the first matching budget
+    // is the one of the most specific module.
+    budgets: Vec<ModuleBudget>,
+}
+
+impl<D: Drain<Err = Never>> ModuleBudgetDrain<D> {
+    /// Creates a drain that enforces the budgets on records at or above the most verbose level of
+    /// the outputs, so the records that are filtered out by the inner drain don't consume
+    /// the budgets.
+    pub(crate) fn new(inner: D, verbosity: Verbosity, settings: &LoggingSettings) -> Self {
+        let mut budgets: Vec<_> = settings
+            .module_budgets
+            .iter()
+            .filter_map(|budget| {
+                Some(ModuleBudget {
+                    module: budget.module.as_str().into(),
+                    rate_limiter: RateLimiter::direct(Quota::per_second(
+                        budget.max_records_per_second.try_into().ok()?,
+                    )),
+                })
+            })
+            .collect();
+
+        budgets.sort_by_key(|budget| std::cmp::Reverse(budget.module.len()));
+
+        Self {
+            inner,
+            verbosity,
+            budgets,
+        }
+    }
+}
+
+impl<D: Drain<Err = Never>> Drain for ModuleBudgetDrain<D> {
+    type Ok = ();
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let budget = if record
+            .level()
+            .is_at_least(self.verbosity.max_for(record.module()))
+        {
+            self.budgets
+                .iter()
+                .find(|budget| budget.matches(record.module()))
+        } else {
+            None
+        };
+
+        match budget {
+            Some(budget) if budget.rate_limiter.check().is_err() => {
+                #[cfg(feature = "metrics")]
+                foundations::log_records_suppressed(&budget.module).inc();
+
+                Ok(())
+            }
+            _ => self.inner.log(record, values).map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::settings::LogModuleBudget;
+    use slog::Level;
+
+    #[test]
+    fn most_specific_budget_matches() {
+        let settings = LoggingSettings {
+            module_budgets: vec![
+                LogModuleBudget {
+                    module: "app".into(),
+                    max_records_per_second: 10,
+                },
+                LogModuleBudget {
+                    module: "app::db".into(),
+                    max_records_per_second: 1,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let drain = ModuleBudgetDrain::new(
+            slog::Discard,
+            Verbosity::new(Level::Info, None, &Default::default()),
+            &settings,
+        );
+        let matching = |module| {
+            drain
+                .budgets
+                .iter()
+                .find(|budget| budget.matches(module))
+                .map(|budget| &*budget.module)
+        };
+
+        assert_eq!(matching("app::db::pool"), Some("app::db"));
+        assert_eq!(matching("app::dbx"), Some("app"));
+        assert_eq!(matching("app"), Some("app"));
+        assert_eq!(matching("application"), None);
+    }
+}
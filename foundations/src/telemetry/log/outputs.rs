@@ -0,0 +1,368 @@
+use super::priority::PriorityDrain;
+use crate::telemetry::settings::LogFilter;
+use slog::{Drain, Fuse, Level, Never, OwnedKVList, Record};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// A drain of [`AdditionalOutputsDrain`], that can be kept when the outputs are replaced.
+pub(crate) type AdditionalOutput = Arc<Fuse<PriorityDrain>>;
+
+type Outputs = Arc<[(Level, AdditionalOutput)]>;
+
+/// Additional log outputs, each with its own verbosity level.
+///
+/// The outputs can be replaced at runtime, e.g. when the settings are reloaded.
+pub(crate) struct AdditionalOutputsDrain {
+    // NOTE: `std` lock is used, since the drain needs to be `RefUnwindSafe`.
+    outputs: RwLock<Outputs>,
+}
+
+impl AdditionalOutputsDrain {
+    pub(crate) fn new(outputs: Vec<(Level, PriorityDrain)>) -> Self {
+        let outputs = outputs
+            .into_iter()
+            .map(|(level, drain)| (level, Arc::new(drain.fuse())))
+            .collect();
+
+        Self {
+            outputs: RwLock::new(outputs),
+        }
+    }
+
+    /// Returns the drains of the outputs, in the order of the settings they were built with.
+    pub(crate) fn drains(&self) -> Vec<AdditionalOutput> {
+        self.outputs
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(_, drain)| Arc::clone(drain))
+            .collect()
+    }
+
+    /// Returns the most verbose level of the outputs.
+    pub(crate) fn max_level(&self) -> Option<Level> {
+        self.outputs
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(level, _)| *level)
+            .max()
+    }
+
+    /// Replaces the outputs.
+    ///
+    /// The drains of the current outputs can be passed again to keep them. The records that are
+    /// being written to the removed outputs are flushed once the last of them is written.
+    pub(crate) fn replace(&self, outputs: Vec<(Level, AdditionalOutput)>) {
+        let mut current = self.outputs.write().unwrap_or_else(PoisonError::into_inner);
+        let prev = std::mem::replace(&mut *current, outputs.into());
+
+        // NOTE: release the lock before the previous outputs are flushed on drop.
+        drop(current);
+        drop(prev);
+    }
+}
+
+impl Drain for AdditionalOutputsDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let outputs = Arc::clone(&self.outputs.read().unwrap_or_else(PoisonError::into_inner));
+
+        for (level, drain) in outputs.iter() {
+            if record.level().is_at_least(*level) {
+                drain.log(record, values)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `true` if `module` is the module at `path` or one of its submodules.
+pub(crate) fn matches_module(path: &str, module: &str) -> bool {
+    module
+        .strip_prefix(path)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// Verbosity of the log outputs.
+///
+/// The verbosity of the main output is shared by all the drains built with it, so it can be
+/// changed at runtime with [`set_verbosity`] for all the existing logs at once. The modules with
+/// a [filter directive] are logged to the main output with the verbosity of the directive
+/// instead.
+///
+/// [`set_verbosity`]: super::set_verbosity
+/// [filter directive]: crate::telemetry::settings::LoggingSettings::filter
+#[derive(Clone, Debug)]
+pub(crate) struct Verbosity {
+    main: Arc<AtomicUsize>,
+    // NOTE: `0` if there are no additional outputs, since it's not a valid level.
+    additional: Arc<AtomicUsize>,
+    // NOTE: sorted by the module path length in descending order, so the first matching
+    // directive is the one of the most specific module.
+    directives: Arc<[(Box<str>, Level)]>,
+}
+
+impl Verbosity {
+    pub(crate) fn new(
+        main: Level,
+        additional: Option<&AdditionalOutputsDrain>,
+        filter: &LogFilter,
+    ) -> Self {
+        let mut directives: Vec<_> = filter
+            .directives
+            .iter()
+            .map(|directive| (directive.module.as_str().into(), *directive.verbosity))
+            .collect();
+
+        // NOTE: the sort is stable, so the last of the directives of the same module wins.
+        directives.sort_by_key(|(module, _): &(Box<str>, _)| std::cmp::Reverse(module.len()));
+        directives.dedup_by(|next, prev| next.0 == prev.0);
+
+        Self {
+            main: Arc::new(AtomicUsize::new(main.as_usize())),
+            additional: Arc::new(AtomicUsize::new(
+                additional
+                    .and_then(AdditionalOutputsDrain::max_level)
+                    .map_or(0, |level| level.as_usize()),
+            )),
+            directives: directives.into(),
+        }
+    }
+
+    /// Returns the verbosity of the main output.
+    pub(crate) fn main(&self) -> Level {
+        // NOTE: only the valid levels are stored.
+        Level::from_usize(self.main.load(Ordering::Relaxed)).unwrap_or(Level::Info)
+    }
+
+    /// Sets the verbosity of the main output.
+    pub(crate) fn set_main(&self, level: Level) {
+        self.main.store(level.as_usize(), Ordering::Relaxed);
+    }
+
+    /// Returns the most verbose level of the additional outputs, if there are any.
+    pub(crate) fn additional(&self) -> Option<Level> {
+        Level::from_usize(self.additional.load(Ordering::Relaxed))
+    }
+
+    /// Sets the most verbose level of the additional outputs, e.g. once they are replaced.
+    pub(crate) fn set_additional(&self, level: Option<Level>) {
+        self.additional
+            .store(level.map_or(0, |level| level.as_usize()), Ordering::Relaxed);
+    }
+
+    /// Returns the verbosity of the main output for the records of the module.
+    pub(crate) fn main_for(&self, module: &str) -> Level {
+        self.directives
+            .iter()
+            .find(|(path, _)| matches_module(path, module))
+            .map_or_else(|| self.main(), |(_, level)| *level)
+    }
+
+    /// Returns the most verbose level of all the outputs for the records of the module.
+    pub(crate) fn max_for(&self, module: &str) -> Level {
+        let main = self.main_for(module);
+
+        self.additional().map_or(main, |level| level.max(main))
+    }
+
+    /// Returns the most verbose level of all the outputs for the records of any module.
+    pub(crate) fn max(&self) -> Level {
+        let main = self
+            .directives
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.main(), Level::max);
+
+        self.additional().map_or(main, |level| level.max(main))
+    }
+}
+
+/// A drain that drops the records that are more verbose than all the outputs, so they don't
+/// go through the rest of the drains.
+pub(crate) struct VerbosityFilter<D> {
+    inner: D,
+    verbosity: Verbosity,
+}
+
+impl<D> VerbosityFilter<D> {
+    pub(crate) fn new(inner: D, verbosity: Verbosity) -> Self {
+        Self { inner, verbosity }
+    }
+}
+
+impl<D: Drain> Drain for VerbosityFilter<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record
+            .level()
+            .is_at_least(self.verbosity.max_for(record.module()))
+        {
+            self.inner.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        level.is_at_least(self.verbosity.max()) && self.inner.is_enabled(level)
+    }
+}
+
+/// A drain that passes records at or above the log verbosity to the main output and the rest of
+/// the records to the additional outputs whose verbosity allows them.
+pub(crate) struct OutputsDrain<D> {
+    main: D,
+    verbosity: Verbosity,
+    additional: Option<Arc<AdditionalOutputsDrain>>,
+}
+
+impl<D> OutputsDrain<D> {
+    pub(crate) fn new(
+        main: D,
+        verbosity: Verbosity,
+        additional: Option<Arc<AdditionalOutputsDrain>>,
+    ) -> Self {
+        Self {
+            main,
+            verbosity,
+            additional,
+        }
+    }
+}
+
+impl<D> Drain for OutputsDrain<D>
+where
+    D: Drain<Ok = (), Err = Never>,
+{
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record
+            .level()
+            .is_at_least(self.verbosity.main_for(record.module()))
+        {
+            self.main.log(record, values)?;
+        }
+
+        // NOTE: the additional outputs are skipped without taking their lock if there are none.
+        if let Some(additional) = &self.additional {
+            if self.verbosity.additional().is_some() {
+                additional.log(record, values)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The main log output that can be replaced at runtime, e.g. when the settings are reloaded.
+pub(crate) struct ReloadableOutputDrain {
+    // NOTE: `std` lock is used, since the drain needs to be `RefUnwindSafe`.
+    drain: RwLock<Arc<Fuse<PriorityDrain>>>,
+}
+
+impl ReloadableOutputDrain {
+    pub(crate) fn new(drain: PriorityDrain) -> Self {
+        Self {
+            drain: RwLock::new(Arc::new(drain.fuse())),
+        }
+    }
+
+    /// Replaces the output.
+    ///
+    /// The records that are being written to the previous output are flushed once the last of
+    /// them is written.
+    pub(crate) fn replace(&self, drain: PriorityDrain) {
+        let mut current = self.drain.write().unwrap_or_else(PoisonError::into_inner);
+        let prev = std::mem::replace(&mut *current, Arc::new(drain.fuse()));
+
+        // NOTE: release the lock before the previous output is flushed on drop.
+        drop(current);
+        drop(prev);
+    }
+}
+
+impl Drain for ReloadableOutputDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let drain = Arc::clone(&self.drain.read().unwrap_or_else(PoisonError::into_inner));
+
+        drain.log(record, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Logger};
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct CollectingDrain(Arc<Mutex<Vec<String>>>);
+
+    impl Drain for CollectingDrain {
+        type Ok = ();
+        type Err = Never;
+
+        fn log(&self, record: &Record, _values: &OwnedKVList) -> Result<(), Never> {
+            self.0.lock().unwrap().push(record.msg().to_string());
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn filters_each_output_independently() {
+        let main = CollectingDrain::default();
+        let debug = CollectingDrain::default();
+        let warn = CollectingDrain::default();
+
+        let additional = AdditionalOutputsDrain::new(vec![
+            (
+                Level::Debug,
+                PriorityDrain::new(debug.clone(), "debug", 16, Level::Error),
+            ),
+            (
+                Level::Warning,
+                PriorityDrain::new(warn.clone(), "warn", 16, Level::Error),
+            ),
+        ]);
+
+        let additional = Arc::new(additional);
+        let verbosity = Verbosity::new(Level::Info, Some(&additional), &Default::default());
+        let drain = OutputsDrain::new(main.clone(), verbosity.clone(), Some(additional));
+
+        assert_eq!(verbosity.max(), Level::Debug);
+
+        let log = Logger::root(Mutex::new(drain).fuse(), o!());
+
+        slog::trace!(log, "trace");
+        slog::debug!(log, "debug");
+        slog::info!(log, "info");
+        slog::warn!(log, "warn");
+
+        verbosity.set_main(Level::Debug);
+
+        slog::debug!(log, "reloaded");
+
+        // NOTE: dropping the log flushes the asynchronous outputs.
+        drop(log);
+
+        assert_eq!(*main.0.lock().unwrap(), ["info", "warn", "reloaded"]);
+        assert_eq!(
+            *debug.0.lock().unwrap(),
+            ["debug", "info", "warn", "reloaded"]
+        );
+        assert_eq!(*warn.0.lock().unwrap(), ["warn"]);
+    }
+}
@@ -0,0 +1,278 @@
+use super::init::LogHarness;
+use slog::{
+    BorrowedKV, Drain, Key, Level, Logger, Never, OwnedKVList, Record, RecordLocation,
+    RecordStatic, Serializer, KV,
+};
+use std::collections::VecDeque;
+use std::fmt::Arguments;
+use std::mem;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Maximum number of records buffered before the logging is initialized, the oldest records are
+/// dropped once the limit is reached.
+pub(crate) const PRE_INIT_BUFFER_CAPACITY: usize = 1024;
+
+/// A field value of a buffered record.
+enum Value {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Str(String),
+    None,
+}
+
+impl Value {
+    fn emit(&self, key: Key, serializer: &mut dyn Serializer) -> slog::Result {
+        match self {
+            Value::Bool(v) => serializer.emit_bool(key, *v),
+            Value::I64(v) => serializer.emit_i64(key, *v),
+            Value::U64(v) => serializer.emit_u64(key, *v),
+            Value::F64(v) => serializer.emit_f64(key, *v),
+            Value::Str(v) => serializer.emit_str(key, v),
+            Value::None => serializer.emit_none(key),
+        }
+    }
+}
+
+/// Fields of a buffered record, including the fields of the logger it was emitted with.
+#[derive(Default)]
+struct Fields(Vec<(Key, Value)>);
+
+impl Serializer for Fields {
+    fn emit_bool(&mut self, key: Key, val: bool) -> slog::Result {
+        self.0.push((key, Value::Bool(val)));
+        Ok(())
+    }
+
+    fn emit_i64(&mut self, key: Key, val: i64) -> slog::Result {
+        self.0.push((key, Value::I64(val)));
+        Ok(())
+    }
+
+    fn emit_u64(&mut self, key: Key, val: u64) -> slog::Result {
+        self.0.push((key, Value::U64(val)));
+        Ok(())
+    }
+
+    fn emit_f64(&mut self, key: Key, val: f64) -> slog::Result {
+        self.0.push((key, Value::F64(val)));
+        Ok(())
+    }
+
+    fn emit_str(&mut self, key: Key, val: &str) -> slog::Result {
+        self.0.push((key, Value::Str(val.to_string())));
+        Ok(())
+    }
+
+    fn emit_none(&mut self, key: Key) -> slog::Result {
+        self.0.push((key, Value::None));
+        Ok(())
+    }
+
+    fn emit_arguments(&mut self, key: Key, val: &Arguments) -> slog::Result {
+        self.0.push((key, Value::Str(val.to_string())));
+        Ok(())
+    }
+}
+
+impl KV for Fields {
+    fn serialize(&self, _record: &Record, serializer: &mut dyn Serializer) -> slog::Result {
+        for (key, value) in &self.0 {
+            value.emit(key, serializer)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct BufferedRecord {
+    level: Level,
+    location: RecordLocation,
+    tag: String,
+    message: String,
+    fields: Fields,
+}
+
+enum State {
+    Buffering {
+        records: VecDeque<BufferedRecord>,
+        dropped: u64,
+    },
+    Initialized,
+}
+
+/// The drain of the logs used before the logging is initialized.
+///
+/// The records are buffered and replayed with the initialized log, see [`PreInitDrain::replay`].
+/// If the drain is still used afterwards, e.g. by a logger obtained before the initialization,
+/// the records are forwarded to the initialized log.
+pub(crate) struct PreInitDrain {
+    // NOTE: std mutex is used, since unlike the `parking_lot` one it is `RefUnwindSafe`, as
+    // required for the drains by `slog`.
+    state: Mutex<State>,
+    capacity: usize,
+}
+
+impl PreInitDrain {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(State::Buffering {
+                records: Default::default(),
+                dropped: 0,
+            }),
+            capacity,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Stops buffering and logs the buffered records with the initialized log.
+    ///
+    /// The records are tagged with the `pre_init` field, as their timestamps are the time of the
+    /// replay.
+    pub(crate) fn replay(&self, log: &Logger) {
+        let State::Buffering { records, dropped } =
+            mem::replace(&mut *self.lock(), State::Initialized)
+        else {
+            return;
+        };
+
+        for record in records {
+            let rstatic = RecordStatic {
+                location: &record.location,
+                tag: &record.tag,
+                level: record.level,
+            };
+
+            let fields = (slog::o!("pre_init" => true), &record.fields);
+
+            log.log(&Record::new(
+                &rstatic,
+                &format_args!("{}", record.message),
+                BorrowedKV(&fields),
+            ));
+        }
+
+        if dropped > 0 {
+            slog::warn!(
+                log,
+                "dropped log records emitted before the logging was initialized";
+                "count" => dropped,
+                "capacity" => self.capacity,
+            );
+        }
+    }
+
+    /// Stops buffering and discards the buffered records.
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) fn discard(&self) {
+        *self.lock() = State::Initialized;
+    }
+}
+
+impl Drain for PreInitDrain {
+    type Ok = ();
+    type Err = Never;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let mut state = self.lock();
+
+        let State::Buffering { records, dropped } = &mut *state else {
+            drop(state);
+
+            let rstatic = RecordStatic {
+                location: record.location(),
+                tag: record.tag(),
+                level: record.level(),
+            };
+
+            let kv = (record.kv(), values);
+
+            LogHarness::get().root_log.read().log(&Record::new(
+                &rstatic,
+                record.msg(),
+                BorrowedKV(&kv),
+            ));
+
+            return Ok(());
+        };
+
+        let mut fields = Fields::default();
+
+        // NOTE: the fields are serialized in the same order as by the output drains.
+        let _ = values.serialize(record, &mut fields);
+        let _ = record.kv().serialize(record, &mut fields);
+
+        if records.len() >= self.capacity {
+            records.pop_front();
+            *dropped += 1;
+        }
+
+        records.push_back(BufferedRecord {
+            level: record.level(),
+            location: *record.location(),
+            tag: record.tag().to_string(),
+            message: record.msg().to_string(),
+            fields,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::log::testing::create_test_log;
+    use crate::telemetry::log::TestLogRecord;
+
+    #[test]
+    fn replays_buffered_records() {
+        let drain = std::sync::Arc::new(PreInitDrain::new(2));
+        let pre_init_log = Logger::root(std::sync::Arc::clone(&drain), slog::o!("app" => "test"));
+
+        for i in 0..3 {
+            slog::info!(pre_init_log, "starting"; "step" => i);
+        }
+
+        let (log, records) = create_test_log(&Default::default());
+
+        drain.replay(&log);
+
+        assert_eq!(
+            *records.read().unwrap(),
+            vec![
+                TestLogRecord {
+                    level: Level::Info,
+                    message: "starting".into(),
+                    fields: vec![
+                        ("pre_init".into(), "true".into()),
+                        ("app".into(), "test".into()),
+                        ("step".into(), "1".into()),
+                    ]
+                },
+                TestLogRecord {
+                    level: Level::Info,
+                    message: "starting".into(),
+                    fields: vec![
+                        ("pre_init".into(), "true".into()),
+                        ("app".into(), "test".into()),
+                        ("step".into(), "2".into()),
+                    ]
+                },
+                TestLogRecord {
+                    level: Level::Warning,
+                    message: "dropped log records emitted before the logging was initialized"
+                        .into(),
+                    fields: vec![
+                        ("capacity".into(), "2".into()),
+                        ("count".into(), "1".into())
+                    ]
+                },
+            ]
+        );
+    }
+}
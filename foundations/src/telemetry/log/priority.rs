@@ -0,0 +1,181 @@
+use slog::{Drain, Level, Never, OwnedKVList, Record};
+use slog_async::{AsyncCore, AsyncError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::Counter;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations {
+    /// Number of log records dropped because the log output couldn't keep up.
+    pub fn log_records_dropped(drain: &'static str, level: &'static str) -> Counter;
+}
+
+/// An asynchronous drain that never drops records at or above the priority level.
+///
+/// Records are written to the output by two background threads: one for the priority records
+/// that blocks the logging thread if its queue is full, and one for the rest of the records that
+/// drops them on overflow. The number of dropped records is reported in a log record once
+/// the queue has room again.
+///
+/// Note that the priority records can be written out of order with the other records if
+/// the output can't keep up.
+pub(crate) struct PriorityDrain {
+    priority: AsyncCore,
+    regular: AsyncCore,
+    priority_level: Level,
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+    dropped: AtomicU64,
+}
+
+impl PriorityDrain {
+    pub(crate) fn new<D>(
+        drain: D,
+        name: &'static str,
+        chan_size: usize,
+        priority_level: Level,
+    ) -> Self
+    where
+        D: Drain<Ok = (), Err = Never> + Send + 'static,
+    {
+        let drain = Arc::new(Mutex::new(drain).fuse());
+
+        let priority = AsyncCore::custom(Arc::clone(&drain))
+            .chan_size(chan_size)
+            .blocking(true)
+            .thread_name(format!("foundations-log-{name}-priority"))
+            .build();
+
+        let regular = AsyncCore::custom(drain)
+            .chan_size(chan_size)
+            .thread_name(format!("foundations-log-{name}"))
+            .build();
+
+        Self {
+            priority,
+            regular,
+            priority_level,
+            name,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    fn report_dropped(&self, values: &OwnedKVList) -> Result<(), AsyncError> {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+
+        if dropped == 0 {
+            return Ok(());
+        }
+
+        let res = self.regular.log(
+            &slog::record!(
+                Level::Warning,
+                "",
+                &format_args!("log records dropped due to log queue overflow"),
+                slog::b!("count" => dropped)
+            ),
+            values,
+        );
+
+        match res {
+            Err(AsyncError::Full) => {
+                self.dropped.fetch_add(dropped, Ordering::Relaxed);
+
+                Ok(())
+            }
+            res => res,
+        }
+    }
+}
+
+impl Drain for PriorityDrain {
+    type Ok = ();
+    type Err = AsyncError;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record.level().is_at_least(self.priority_level) {
+            return self.priority.log(record, values);
+        }
+
+        self.report_dropped(values)?;
+
+        match self.regular.log(record, values) {
+            Err(AsyncError::Full) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+
+                #[cfg(feature = "metrics")]
+                foundations::log_records_dropped(self.name, record.level().as_str()).inc();
+
+                Ok(())
+            }
+            res => res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Logger};
+    use std::sync::mpsc;
+
+    struct GatedDrain {
+        gate: Mutex<mpsc::Receiver<()>>,
+        records: Arc<Mutex<Vec<(Level, String)>>>,
+    }
+
+    impl Drain for GatedDrain {
+        type Ok = ();
+        type Err = Never;
+
+        fn log(&self, record: &Record, _values: &OwnedKVList) -> Result<(), Never> {
+            let _ = self.gate.lock().unwrap().recv();
+
+            self.records
+                .lock()
+                .unwrap()
+                .push((record.level(), record.msg().to_string()));
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn never_drops_priority_records() {
+        let (open_gate, gate) = mpsc::channel();
+        let records = Arc::new(Mutex::new(vec![]));
+
+        let drain = GatedDrain {
+            gate: Mutex::new(gate),
+            records: Arc::clone(&records),
+        };
+
+        let drain = PriorityDrain::new(drain, "test", 1, Level::Error);
+        let log = Logger::root(drain.fuse(), o!());
+
+        // NOTE: the output is blocked until the gate is opened, so all the records, except
+        // the ones being written and queued, are dropped.
+        for i in 0..100 {
+            slog::info!(log, "info {i}");
+        }
+
+        drop(open_gate);
+
+        for i in 0..10 {
+            slog::error!(log, "error {i}");
+            slog::crit!(log, "crit {i}");
+        }
+
+        drop(log);
+
+        let records = records.lock().unwrap();
+        let count = |level| records.iter().filter(|(l, _)| *l == level).count();
+
+        assert_eq!(count(Level::Error), 10);
+        assert_eq!(count(Level::Critical), 10);
+        assert!(count(Level::Info) <= 2);
+    }
+}
@@ -0,0 +1,118 @@
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use slog::{Drain, Key, OwnedKVList, Record, Serializer, KV};
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static RECENT_RECORDS: OnceCell<RecentRecords> = OnceCell::new();
+
+struct RecentRecords {
+    capacity: usize,
+    records: Mutex<VecDeque<String>>,
+}
+
+/// Returns the most recent log records formatted as text lines, oldest first.
+///
+/// Empty if retention of the recent records is disabled in the settings.
+pub(crate) fn recent_records() -> Vec<String> {
+    RECENT_RECORDS
+        .get()
+        .map(|recent| recent.records.lock().iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// A drain that retains the most recent log records in a ring buffer, in addition to passing
+/// them to the wrapped drain.
+pub(crate) struct RecentRecordsDrain<D> {
+    inner: D,
+    recent: &'static RecentRecords,
+}
+
+impl<D> RecentRecordsDrain<D> {
+    pub(crate) fn new(inner: D, capacity: usize) -> Self {
+        let recent = RECENT_RECORDS.get_or_init(|| RecentRecords {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        });
+
+        Self { inner, recent }
+    }
+}
+
+impl<D: Drain> Drain for RecentRecordsDrain<D> {
+    type Ok = D::Ok;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let line = format_record(record, values);
+
+        {
+            let mut records = self.recent.records.lock();
+
+            if records.len() >= self.recent.capacity {
+                records.pop_front();
+            }
+
+            records.push_back(line);
+        }
+
+        self.inner.log(record, values)
+    }
+}
+
+fn format_record(record: &Record, values: &OwnedKVList) -> String {
+    struct Fields(String);
+
+    impl Serializer for Fields {
+        fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+            let _ = write!(self.0, ", {key}: {val}");
+
+            Ok(())
+        }
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut fields = Fields(format!(
+        "{}.{:03} {} {}",
+        timestamp.as_secs(),
+        timestamp.subsec_millis(),
+        record.level().as_short_str(),
+        record.msg()
+    ));
+
+    let _ = record.kv().serialize(record, &mut fields);
+    let _ = values.serialize(record, &mut fields);
+
+    fields.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Discard, Logger};
+
+    #[test]
+    fn retains_most_recent_records() {
+        let drain = RecentRecordsDrain::new(Discard, 2);
+        let log = Logger::root(std::sync::Mutex::new(drain).fuse(), o!("pid" => 42));
+
+        slog::info!(log, "first");
+        slog::warn!(log, "second"; "key" => "value");
+        slog::error!(log, "third");
+
+        let records = recent_records();
+        let records: Vec<_> = records
+            .iter()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+
+        assert_eq!(
+            records,
+            ["WARN second, key: value, pid: 42", "ERRO third, pid: 42"]
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use crate::telemetry::settings::RotatingFileLogOutput;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "compression")]
+use crate::compression::{CompressionAlgorithm, Encoder};
+
+// NOTE: a record larger than this is written in parts, so it can be split between the files.
+const MAX_BUFFERED_LEN: usize = 64 * 1024;
+
+// NOTE: the rotated files are suffixed with the rotation timestamp in milliseconds, zero-padded
+// to 13 digits, so they are ordered by their names.
+const TIMESTAMP_LEN: usize = 13;
+
+#[cfg(feature = "compression")]
+const COMPRESSED_EXTENSIONS: &[&str] = &[".gz", ".zz", ".zst"];
+
+#[cfg(not(feature = "compression"))]
+const COMPRESSED_EXTENSIONS: &[&str] = &[];
+
+/// A log file writer that rotates the file once it gets too large or too old, see
+/// [`RotatingFileLogOutput`].
+///
+/// The data is buffered until the writer is flushed, which the log drains do after each record,
+/// so the file is only rotated between the records.
+pub(crate) struct RotatingFileWriter {
+    path: PathBuf,
+    max_file_size: u64,
+    max_file_age: Option<Duration>,
+    max_files: usize,
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionAlgorithm>,
+    file: File,
+    size: u64,
+    opened_at: Instant,
+    buffer: Vec<u8>,
+    last_rotation_timestamp: u128,
+    cleanup: Option<Cleanup>,
+}
+
+// NOTE: the rotated files are compressed and the old ones are removed on a separate thread, so
+// the rotation doesn't block the logging.
+struct Cleanup {
+    rotated_tx: Sender<PathBuf>,
+    thread: JoinHandle<()>,
+}
+
+impl RotatingFileWriter {
+    pub(crate) fn new(settings: &RotatingFileLogOutput) -> io::Result<Self> {
+        let file = open(&settings.path)?;
+
+        Ok(Self {
+            path: settings.path.clone(),
+            max_file_size: settings.max_file_size,
+            max_file_age: Some(Duration::from_secs(settings.max_file_age_secs))
+                .filter(|age| !age.is_zero()),
+            max_files: settings.max_files,
+            #[cfg(feature = "compression")]
+            compression: settings.compression,
+            size: file.metadata()?.len(),
+            file,
+            opened_at: Instant::now(),
+            buffer: Vec::with_capacity(1024),
+            last_rotation_timestamp: 0,
+            cleanup: None,
+        })
+    }
+
+    fn should_rotate(&self, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+
+        let too_large = self.max_file_size > 0 && self.size + len as u64 > self.max_file_size;
+        let too_old = self
+            .max_file_age
+            .is_some_and(|age| self.opened_at.elapsed() >= age);
+
+        too_large || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // NOTE: the timestamps are kept increasing, so the rotated files are ordered by their
+        // names even if they are rotated within the same millisecond.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        self.last_rotation_timestamp = now.max(self.last_rotation_timestamp + 1);
+
+        let rotated = with_suffix(
+            &self.path,
+            &format!(".{:0TIMESTAMP_LEN$}", self.last_rotation_timestamp),
+        );
+
+        fs::rename(&self.path, &rotated)?;
+
+        self.file = open(&self.path)?;
+        self.size = 0;
+        self.opened_at = Instant::now();
+
+        if self.cleanup.is_none() {
+            self.cleanup = Some(Cleanup::start(self));
+        }
+
+        if let Some(cleanup) = &self.cleanup {
+            // NOTE: the cleanup thread only exits once the writer is dropped.
+            let _ = cleanup.rotated_tx.send(rotated);
+        }
+
+        Ok(())
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        // NOTE: the records are written to the current file if the rotation fails.
+        let rotation = match self.should_rotate(self.buffer.len()) {
+            true => self.rotate(),
+            false => Ok(()),
+        };
+
+        // NOTE: the buffer is discarded on errors, so the failed records are not written twice.
+        let res = self.file.write_all(&self.buffer);
+
+        self.size += self.buffer.len() as u64;
+        self.buffer.clear();
+
+        rotation.and(res)
+    }
+}
+
+impl Cleanup {
+    fn start(writer: &RotatingFileWriter) -> Self {
+        let (rotated_tx, rotated_rx) = mpsc::channel::<PathBuf>();
+        let path = writer.path.clone();
+        let max_files = writer.max_files;
+
+        #[cfg(feature = "compression")]
+        let compression = writer.compression;
+
+        let thread = thread::spawn(move || {
+            for rotated in rotated_rx {
+                #[cfg(feature = "compression")]
+                if let Some(algorithm) = compression {
+                    // NOTE: the uncompressed file is kept if the compression fails.
+                    let _ = compress(&rotated, algorithm);
+                }
+
+                #[cfg(not(feature = "compression"))]
+                let _ = rotated;
+
+                let _ = remove_old_files(&path, max_files);
+            }
+        });
+
+        Self { rotated_tx, thread }
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+
+        if self.buffer.len() >= MAX_BUFFERED_LEN {
+            self.write_buffer()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_buffer()?;
+        }
+
+        self.file.flush()
+    }
+}
+
+impl Drop for RotatingFileWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+
+        // NOTE: finish the queued cleanups, so the rotated files don't outlive the writer
+        // uncompressed.
+        if let Some(Cleanup { rotated_tx, thread }) = self.cleanup.take() {
+            drop(rotated_tx);
+
+            let _ = thread.join();
+        }
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+
+    path.push(suffix);
+
+    path.into()
+}
+
+#[cfg(feature = "compression")]
+fn compress(path: &Path, algorithm: CompressionAlgorithm) -> io::Result<()> {
+    let extension = match algorithm {
+        CompressionAlgorithm::Gzip => ".gz",
+        CompressionAlgorithm::Deflate => ".zz",
+        CompressionAlgorithm::Zstd => ".zst",
+    };
+
+    let compressed = with_suffix(path, extension);
+    let settings = crate::compression::CompressionSettings {
+        algorithm,
+        level: None,
+    };
+
+    let mut encoder = Encoder::new(File::create(&compressed)?, &settings, "log_rotation")?;
+
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+
+    fs::remove_file(path)
+}
+
+/// Removes the oldest rotated files of the log file above the limit.
+fn remove_old_files(path: &Path, max_files: usize) -> io::Result<()> {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(());
+    };
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let prefix = format!("{file_name}.");
+    let mut rotated = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+
+        let Some(timestamp) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(rotation_timestamp)
+        else {
+            continue;
+        };
+
+        rotated.push((timestamp, entry.path()));
+    }
+
+    rotated.sort();
+
+    // NOTE: both the compressed and the uncompressed versions of a file exist while it's
+    // being compressed, so the files are counted by their timestamps.
+    let mut timestamps: Vec<_> = rotated.iter().map(|(timestamp, _)| *timestamp).collect();
+
+    timestamps.dedup();
+
+    let removed = &timestamps[..timestamps.len().saturating_sub(max_files)];
+
+    for (timestamp, path) in rotated {
+        if removed.binary_search(&timestamp).is_ok() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the rotation timestamp of a rotated file from its name without the log file name,
+/// e.g. `1700000000000` or `1700000000000.gz`. Returns `None` for the other files.
+fn rotation_timestamp(suffix: &str) -> Option<u128> {
+    let (timestamp, extension) = suffix.split_at_checked(TIMESTAMP_LEN)?;
+
+    if !timestamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    if !extension.is_empty() && !COMPRESSED_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+
+    timestamp.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "foundations-log-rotation-{name}-{}",
+            std::process::id()
+        ));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn rotated_files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name != "app.log")
+            .collect();
+
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = temp_dir("size");
+        let path = dir.join("app.log");
+
+        fs::write(&path, "existing\n").unwrap();
+
+        let mut writer = RotatingFileWriter::new(&RotatingFileLogOutput {
+            path: path.clone(),
+            max_file_size: 20,
+            max_files: 2,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for i in 0..10 {
+            writer
+                .write_all(format!("record {i}\n").as_bytes())
+                .unwrap();
+            writer.flush().unwrap();
+        }
+
+        drop(writer);
+
+        // NOTE: the existing file is appended to, each file fits two records, and only the two
+        // most recent rotated files are kept.
+        assert_eq!(fs::read_to_string(&path).unwrap(), "record 9\n");
+
+        let rotated = rotated_files(&dir);
+
+        assert_eq!(rotated.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.join(&rotated[0])).unwrap(),
+            "record 5\nrecord 6\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join(&rotated[1])).unwrap(),
+            "record 7\nrecord 8\n"
+        );
+    }
+
+    #[test]
+    fn keeps_unrelated_files() {
+        let dir = temp_dir("unrelated");
+        let path = dir.join("app.log");
+        let unrelated = [
+            "app.log.1",
+            "app.log.old",
+            "app.log.17000000000001",
+            "app.log.bak.gz",
+        ];
+
+        for name in unrelated {
+            fs::write(dir.join(name), "unrelated\n").unwrap();
+        }
+
+        let mut writer = RotatingFileWriter::new(&RotatingFileLogOutput {
+            path: path.clone(),
+            max_file_size: 10,
+            max_files: 1,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for record in ["first\n", "second\n", "third\n"] {
+            writer.write_all(record.as_bytes()).unwrap();
+            writer.flush().unwrap();
+        }
+
+        drop(writer);
+
+        let rotated: Vec<_> = rotated_files(&dir)
+            .into_iter()
+            .filter(|name| !unrelated.contains(&name.as_str()))
+            .collect();
+
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join(&rotated[0])).unwrap(),
+            "second\n"
+        );
+
+        for name in unrelated {
+            assert!(dir.join(name).exists(), "{name} is removed");
+        }
+    }
+
+    #[test]
+    fn rotates_by_age() {
+        let dir = temp_dir("age");
+        let path = dir.join("app.log");
+
+        let mut writer = RotatingFileWriter::new(&RotatingFileLogOutput {
+            path: path.clone(),
+            max_file_size: 0,
+            max_files: 10,
+            ..Default::default()
+        })
+        .unwrap();
+
+        writer.max_file_age = Some(Duration::from_millis(50));
+
+        writer.write_all(b"first\n").unwrap();
+        writer.flush().unwrap();
+        writer.write_all(b"second\n").unwrap();
+        writer.flush().unwrap();
+
+        thread::sleep(Duration::from_millis(60));
+
+        // NOTE: the record is buffered until the flush, so it's not split between the files.
+        writer.write_all(b"third ").unwrap();
+        writer.write_all(b"record\n").unwrap();
+        writer.flush().unwrap();
+
+        drop(writer);
+
+        let rotated = rotated_files(&dir);
+
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join(&rotated[0])).unwrap(),
+            "first\nsecond\n"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third record\n");
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compresses_rotated_files() {
+        let dir = temp_dir("compression");
+        let path = dir.join("app.log");
+
+        let mut writer = RotatingFileWriter::new(&RotatingFileLogOutput {
+            path: path.clone(),
+            max_file_size: 10,
+            max_files: 1,
+            compression: Some(CompressionAlgorithm::Gzip),
+            ..Default::default()
+        })
+        .unwrap();
+
+        for record in ["first\n", "second\n", "third\n"] {
+            writer.write_all(record.as_bytes()).unwrap();
+            writer.flush().unwrap();
+        }
+
+        drop(writer);
+
+        let rotated = rotated_files(&dir);
+
+        assert_eq!(rotated.len(), 1);
+        assert!(rotated[0].ends_with(".gz"), "{rotated:?}");
+
+        let compressed = fs::read(dir.join(&rotated[0])).unwrap();
+        let decompressed =
+            crate::compression::decompress(&compressed, CompressionAlgorithm::Gzip, "test")
+                .unwrap();
+
+        assert_eq!(decompressed, b"second\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third\n");
+    }
+}
@@ -1,4 +1,6 @@
 use crate::telemetry::log::init::{apply_filters_to_drain, LogHarness};
+use crate::telemetry::log::outputs::Verbosity;
+use crate::telemetry::scope::ScopeStack;
 use crate::telemetry::settings::LoggingSettings;
 use parking_lot::RwLock as ParkingRwLock;
 use slog::{Drain, Key, Level, Logger, Never, OwnedKVList, Record, Serializer, KV};
@@ -66,13 +68,14 @@ pub(crate) fn create_test_log(settings: &LoggingSettings) -> (Logger, TestLogRec
         records: Arc::clone(&log_records),
     };
 
-    let drain = Arc::new(apply_filters_to_drain(drain, settings));
-    let log = Logger::root(Arc::clone(&drain), slog::o!());
+    let verbosity = Verbosity::new(*settings.verbosity, None, &settings.filter);
+    let drain = apply_filters_to_drain(drain, None, verbosity.clone(), settings);
+    let log = Logger::root(drain, slog::o!());
     let _ = LogHarness::override_for_testing(LogHarness {
         root_log: Arc::new(ParkingRwLock::new(log.clone())),
-        root_drain: drain,
-        settings: settings.clone(),
-        log_scope_stack: Default::default(),
+        verbosity,
+        reloadable_output: None,
+        log_scope_stack: ScopeStack::new("log"),
     });
 
     (log, log_records)
@@ -0,0 +1,160 @@
+use super::exposition::parse_sample;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// Cardinality of a metric family, returned by [`collect_cardinality`].
+///
+/// [`collect_cardinality`]: super::collect_cardinality
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct FamilyCardinality {
+    /// Name of the metric family.
+    pub name: String,
+
+    /// Number of the series of the family, e.g. each bucket of a histogram is a separate series.
+    pub series: usize,
+
+    /// Estimated number of bytes the family contributes to the scrape in the text format,
+    /// including its `# HELP` and `# TYPE` lines.
+    pub bytes: usize,
+
+    /// Labels of the family, by the number of their distinct values in descending order.
+    pub labels: Vec<LabelCardinality>,
+}
+
+/// Number of the distinct values of a label of a metric family, see [`FamilyCardinality`].
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct LabelCardinality {
+    /// Name of the label.
+    pub name: String,
+
+    /// Number of the distinct values of the label across the series of the family.
+    pub distinct_values: usize,
+}
+
+#[derive(Default)]
+struct Family {
+    series: usize,
+    bytes: usize,
+    labels: BTreeMap<String, HashSet<String>>,
+}
+
+/// Computes the cardinality of the families in the text format, by number of series in descending
+/// order.
+pub(super) fn analyze(text: &str) -> Vec<FamilyCardinality> {
+    let mut families: BTreeMap<String, Family> = BTreeMap::new();
+    // NOTE: the family declared with the last `# HELP` or `# TYPE` line.
+    let mut current: Option<String> = None;
+
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let declared = ["HELP ", "TYPE ", "UNIT "]
+                .iter()
+                .find_map(|keyword| comment.strip_prefix(keyword));
+
+            if let Some(rest) = declared {
+                let name = rest.split(' ').next().unwrap_or(rest);
+
+                families.entry(name.to_string()).or_default().bytes += line.len() + 1;
+                current = Some(name.to_string());
+            }
+
+            continue;
+        }
+
+        let Some((name, labels, _)) = parse_sample(line) else {
+            continue;
+        };
+
+        let name = match &current {
+            Some(family) if name.starts_with(family.as_str()) => family.as_str(),
+            _ => name,
+        };
+
+        let family = families.entry(name.to_string()).or_default();
+
+        family.series += 1;
+        family.bytes += line.len() + 1;
+
+        for (name, value) in labels {
+            family.labels.entry(name).or_default().insert(value);
+        }
+    }
+
+    let mut report: Vec<_> = families
+        .into_iter()
+        .filter(|(_, family)| family.series > 0)
+        .map(|(name, family)| {
+            let mut labels: Vec<_> = family
+                .labels
+                .into_iter()
+                .map(|(name, values)| LabelCardinality {
+                    name,
+                    distinct_values: values.len(),
+                })
+                .collect();
+
+            labels.sort_by_key(|label| std::cmp::Reverse(label.distinct_values));
+
+            FamilyCardinality {
+                name,
+                series: family.series,
+                bytes: family.bytes,
+                labels,
+            }
+        })
+        .collect();
+
+    report.sort_by_key(|family| std::cmp::Reverse(family.series));
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_series_and_label_values() {
+        let text = concat!(
+            "# HELP requests Number of requests.\n",
+            "# TYPE requests counter\n",
+            "requests_total{path=\"/a\",method=\"GET\"} 1\n",
+            "requests_total{path=\"/b\",method=\"GET\"} 2\n",
+            "requests_total{path=\"/c\",method=\"POST\"} 3\n",
+            "# TYPE latency histogram\n",
+            "latency_sum 1.0\n",
+            "latency_count 1\n",
+            "latency_bucket{le=\"1.0\"} 1\n",
+            "latency_bucket{le=\"+Inf\"} 1\n",
+            "# TYPE empty gauge\n",
+            "untyped_value 1\n",
+            "# EOF\n",
+        );
+
+        let report = analyze(text);
+
+        assert_eq!(
+            report
+                .iter()
+                .map(|f| (&*f.name, f.series))
+                .collect::<Vec<_>>(),
+            [("latency", 4), ("requests", 3), ("untyped_value", 1)]
+        );
+
+        assert_eq!(report[0].bytes, 112);
+
+        assert_eq!(
+            report[1].labels,
+            [
+                LabelCardinality {
+                    name: "path".into(),
+                    distinct_values: 3,
+                },
+                LabelCardinality {
+                    name: "method".into(),
+                    distinct_values: 2,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Bounded channels instrumented with metrics.
+//!
+//! The channels from this module are thin wrappers around [tokio's bounded channels] that
+//! standardize observability of the service's internal queues. Each channel has a name which is
+//! used as a `channel` label of the following metrics:
+//!
+//! - `<prefix>_foundations_channel_depth` - [`RangeGauge`] with the number of buffered messages;
+//! - `<prefix>_foundations_channel_send_failures_total` - number of messages that failed to be
+//!   sent because the channel was closed or full;
+//! - `<prefix>_foundations_channel_send_timeouts_total` - number of [`Sender::send_timeout`]
+//!   calls that timed out.
+//!
+//! With the `logging` feature the channel can also be configured to log a warning if it stays
+//! full for a certain amount of time, see the `saturation_warning_after` field of
+//! [`ChannelOptions`].
+//!
+//! # Examples
+//! ```
+//! use foundations::telemetry::metrics::channel;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let (tx, mut rx) = channel::bounded("jobs", 16);
+//!
+//! tokio::spawn(async move {
+//!     for job in 0..4 {
+//!         tx.send(job).await.unwrap();
+//!     }
+//! });
+//!
+//! while let Some(job) = rx.recv().await {
+//!     println!("processing job {job}");
+//! }
+//! # }
+//! ```
+//!
+//! [tokio's bounded channels]: tokio::sync::mpsc::channel
+
+use super::{Counter, RangeGauge};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error};
+
+#[cfg(feature = "logging")]
+use std::time::Instant;
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_channel {
+    /// Number of messages buffered in the channel.
+    pub fn depth(channel: &'static str) -> RangeGauge;
+
+    /// Number of messages that failed to be sent to the channel.
+    pub fn send_failures_total(channel: &'static str) -> Counter;
+
+    /// Number of sends to the channel that timed out.
+    pub fn send_timeouts_total(channel: &'static str) -> Counter;
+}
+
+/// Options of a channel created with [`bounded_with_options`].
+#[derive(Default, Debug, Clone)]
+pub struct ChannelOptions {
+    /// Logs a warning if the channel remains full for longer than the specified duration.
+    ///
+    /// The saturation is detected on send attempts and the warning is logged at most once per
+    /// the specified duration. Disabled if `None`.
+    #[cfg(feature = "logging")]
+    pub saturation_warning_after: Option<Duration>,
+}
+
+/// Creates a bounded channel with the given name and capacity.
+///
+/// Channels with the same name share the metrics.
+///
+/// # Panics
+/// Panics if `buffer` is `0`.
+pub fn bounded<T>(name: &'static str, buffer: usize) -> (Sender<T>, Receiver<T>) {
+    bounded_with_options(name, buffer, Default::default())
+}
+
+/// Creates a bounded channel with the given name, capacity and options.
+///
+/// Channels with the same name share the metrics.
+///
+/// # Panics
+/// Panics if `buffer` is `0`.
+pub fn bounded_with_options<T>(
+    name: &'static str,
+    buffer: usize,
+    options: ChannelOptions,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = mpsc::channel(buffer);
+
+    let shared = Arc::new(Shared {
+        name,
+        depth: foundations_channel::depth(name),
+
+        #[cfg(feature = "logging")]
+        saturation: options
+            .saturation_warning_after
+            .map(|after| parking_lot::Mutex::new(Saturation::new(after))),
+    });
+
+    #[cfg(not(feature = "logging"))]
+    let _ = options;
+
+    let tx = Sender {
+        inner: tx,
+        shared: Arc::clone(&shared),
+    };
+
+    let rx = Receiver { inner: rx, shared };
+
+    (tx, rx)
+}
+
+struct Shared {
+    name: &'static str,
+    depth: RangeGauge,
+
+    #[cfg(feature = "logging")]
+    saturation: Option<parking_lot::Mutex<Saturation>>,
+}
+
+#[cfg(feature = "logging")]
+struct Saturation {
+    warning_after: Duration,
+    since: Option<Instant>,
+    last_warning: Option<Instant>,
+}
+
+#[cfg(feature = "logging")]
+impl Saturation {
+    fn new(warning_after: Duration) -> Self {
+        Self {
+            warning_after,
+            since: None,
+            last_warning: None,
+        }
+    }
+
+    fn observe(&mut self, channel: &'static str, is_full: bool) {
+        if !is_full {
+            self.since = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let since = *self.since.get_or_insert(now);
+        let saturated_for = now.duration_since(since);
+
+        let warned_recently = self
+            .last_warning
+            .is_some_and(|last| now.duration_since(last) < self.warning_after);
+
+        if saturated_for >= self.warning_after && !warned_recently {
+            self.last_warning = Some(now);
+
+            crate::telemetry::log::warn!(
+                "channel is saturated";
+                "channel" => channel,
+                "saturated_for" => format!("{saturated_for:?}")
+            );
+        }
+    }
+}
+
+/// Sending half of a channel created with [`bounded`].
+///
+/// Provides the same functionality as [`tokio::sync::mpsc::Sender`], while also updating the
+/// channel metrics.
+pub struct Sender<T> {
+    inner: mpsc::Sender<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends a value, waiting until there is capacity.
+    ///
+    /// See [`tokio::sync::mpsc::Sender::send`]. The method is cancel safe: the metrics are updated
+    /// only once the capacity is reserved and the value is sent.
+    pub async fn send(&self, value: T) -> Result<(), error::SendError<T>> {
+        self.observe_saturation();
+
+        match self.inner.reserve().await {
+            Ok(permit) => {
+                self.send_with_permit(permit, value);
+                Ok(())
+            }
+            Err(_) => {
+                self.on_failure();
+                Err(error::SendError(value))
+            }
+        }
+    }
+
+    /// Sends a value, waiting until there is capacity, but only for a limited time.
+    ///
+    /// See [`tokio::sync::mpsc::Sender::send_timeout`]. The method is cancel safe, see
+    /// [`Sender::send`].
+    pub async fn send_timeout(
+        &self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), error::SendTimeoutError<T>> {
+        self.observe_saturation();
+
+        match tokio::time::timeout(timeout, self.inner.reserve()).await {
+            Ok(Ok(permit)) => {
+                self.send_with_permit(permit, value);
+                Ok(())
+            }
+            Ok(Err(_)) => {
+                self.on_failure();
+                Err(error::SendTimeoutError::Closed(value))
+            }
+            Err(_) => {
+                foundations_channel::send_timeouts_total(self.shared.name).inc();
+                Err(error::SendTimeoutError::Timeout(value))
+            }
+        }
+    }
+
+    /// Attempts to immediately send a value.
+    ///
+    /// See [`tokio::sync::mpsc::Sender::try_send`].
+    pub fn try_send(&self, value: T) -> Result<(), error::TrySendError<T>> {
+        self.observe_saturation();
+
+        match self.inner.try_reserve() {
+            Ok(permit) => {
+                self.send_with_permit(permit, value);
+                Ok(())
+            }
+            Err(err) => {
+                self.on_failure();
+
+                Err(match err {
+                    error::TrySendError::Full(()) => error::TrySendError::Full(value),
+                    error::TrySendError::Closed(()) => error::TrySendError::Closed(value),
+                })
+            }
+        }
+    }
+
+    /// Returns the current capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Returns the name of the channel.
+    pub fn name(&self) -> &'static str {
+        self.shared.name
+    }
+
+    /// Checks if the receiving half of the channel has been dropped or closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn observe_saturation(&self) {
+        #[cfg(feature = "logging")]
+        if let Some(saturation) = &self.shared.saturation {
+            saturation
+                .lock()
+                .observe(self.shared.name, self.inner.capacity() == 0);
+        }
+    }
+
+    fn send_with_permit(&self, permit: mpsc::Permit<'_, T>, value: T) {
+        // NOTE: the depth is increased before the value is sent, so the receiver never observes
+        // the depth dropping below zero.
+        self.shared.depth.inc();
+        permit.send(value);
+    }
+
+    fn on_failure(&self) {
+        foundations_channel::send_failures_total(self.shared.name).inc();
+    }
+}
+
+/// Receiving half of a channel created with [`bounded`].
+///
+/// Provides the same functionality as [`tokio::sync::mpsc::Receiver`], while also updating the
+/// channel metrics.
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<T>,
+    shared: Arc<Shared>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, waiting until one is available.
+    ///
+    /// See [`tokio::sync::mpsc::Receiver::recv`].
+    pub async fn recv(&mut self) -> Option<T> {
+        let value = self.inner.recv().await;
+
+        if value.is_some() {
+            self.shared.depth.dec();
+        }
+
+        value
+    }
+
+    /// Attempts to immediately receive the next value.
+    ///
+    /// See [`tokio::sync::mpsc::Receiver::try_recv`].
+    pub fn try_recv(&mut self) -> Result<T, error::TryRecvError> {
+        let res = self.inner.try_recv();
+
+        if res.is_ok() {
+            self.shared.depth.dec();
+        }
+
+        res
+    }
+
+    /// Closes the receiving half of the channel without dropping it.
+    ///
+    /// See [`tokio::sync::mpsc::Receiver::close`].
+    pub fn close(&mut self) {
+        self.inner.close();
+    }
+
+    /// Returns the name of the channel.
+    pub fn name(&self) -> &'static str {
+        self.shared.name
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // NOTE: drain the buffered messages, so they are not reported in the channel depth
+        // after the channel is gone.
+        self.inner.close();
+
+        while self.try_recv().is_ok() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_depth_and_failures() {
+        let (tx, mut rx) = bounded("test_tracks_depth_and_failures", 2);
+        let depth = foundations_channel::depth("test_tracks_depth_and_failures");
+        let failures = foundations_channel::send_failures_total("test_tracks_depth_and_failures");
+
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+
+        assert!(tx.try_send(3).is_err());
+        assert_eq!(depth.get(), 2);
+        assert_eq!(failures.get(), 1);
+
+        assert_eq!(rx.try_recv().unwrap(), 1);
+        assert_eq!(depth.get(), 1);
+
+        drop(rx);
+
+        assert_eq!(depth.get(), 0);
+        assert!(tx.try_send(4).is_err());
+        assert_eq!(depth.get(), 0);
+        assert_eq!(failures.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn cancelled_send_does_not_change_depth() {
+        let (tx, mut rx) = bounded("test_cancelled_send_does_not_change_depth", 1);
+        let depth = foundations_channel::depth("test_cancelled_send_does_not_change_depth");
+        let timeouts =
+            foundations_channel::send_timeouts_total("test_cancelled_send_does_not_change_depth");
+
+        tx.send(1).await.unwrap();
+
+        // NOTE: the send is blocked on the full channel and dropped by the timeout.
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), tx.send(2)).await;
+
+        assert!(cancelled.is_err());
+        assert_eq!(depth.get(), 1);
+
+        assert!(matches!(
+            tx.send_timeout(3, Duration::from_millis(10)).await,
+            Err(error::SendTimeoutError::Timeout(3))
+        ));
+        assert_eq!(depth.get(), 1);
+        assert_eq!(timeouts.get(), 1);
+
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(depth.get(), 0);
+    }
+}
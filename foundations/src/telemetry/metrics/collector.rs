@@ -0,0 +1,209 @@
+use super::internal::Registries;
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::MetricType;
+use std::io::{self, Write};
+
+/// A metric whose samples are produced on collection, e.g. when the metrics endpoint of
+/// the telemetry server is scraped.
+///
+/// Collectors are a good fit for the values that are cheap to compute on demand, but are costly
+/// to keep up to date, such as the number of open file descriptors or the size of a map
+/// protected by a lock, so they don't need to be maintained by background updaters.
+///
+/// The trait is implemented for closures, see [`register_collector`] for an example.
+pub trait Collector: Send + Sync + 'static {
+    /// Adds the current samples of the metric to `samples`.
+    fn collect(&self, samples: &mut Samples);
+}
+
+impl<F> Collector for F
+where
+    F: Fn(&mut Samples) + Send + Sync + 'static,
+{
+    fn collect(&self, samples: &mut Samples) {
+        self(samples)
+    }
+}
+
+/// Type of the metric reported by a [`Collector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollectorType {
+    /// The samples are reported as a counter, so they should never decrease.
+    Counter,
+    /// The samples are reported as a gauge.
+    Gauge,
+}
+
+/// Samples produced by a [`Collector`].
+#[derive(Debug, Default)]
+pub struct Samples {
+    samples: Vec<(LabelSet, f64)>,
+}
+
+impl Samples {
+    /// Adds a sample with the given labels.
+    ///
+    /// Samples without labels are added with an empty `labels` slice.
+    pub fn add(&mut self, labels: &[(&str, &str)], value: f64) {
+        let labels = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        self.samples.push((LabelSet(labels), value));
+    }
+}
+
+/// Registers a collector that reports a metric named `<prefix>_<subsystem>_<name>`, same as
+/// the metrics of a `#[metrics]` module named `subsystem`.
+///
+/// The collector is called each time the metrics are collected.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::metrics::{self, CollectorType, Samples};
+/// use foundations::telemetry::settings::MetricsSettings;
+/// use std::collections::HashMap;
+/// use std::sync::{Arc, Mutex};
+///
+/// let sessions = Arc::new(Mutex::new(HashMap::<u64, String>::new()));
+///
+/// metrics::register_collector(
+///     "my_app",
+///     "sessions",
+///     "Number of open sessions",
+///     CollectorType::Gauge,
+///     {
+///         let sessions = Arc::clone(&sessions);
+///
+///         move |samples: &mut Samples| samples.add(&[], sessions.lock().unwrap().len() as f64)
+///     },
+/// );
+///
+/// sessions.lock().unwrap().insert(1, "alice".into());
+///
+/// let metrics = metrics::collect(&MetricsSettings::default()).unwrap();
+///
+/// assert!(metrics.contains("my_app_sessions 1.0\n"));
+/// ```
+pub fn register_collector(
+    subsystem: &str,
+    name: &str,
+    help: &str,
+    collector_type: CollectorType,
+    collector: impl Collector,
+) {
+    Registries::get().main_subsystem(subsystem).register(
+        name,
+        help,
+        Box::new(CollectorMetric::new(collector_type, collector)),
+    );
+}
+
+pub(super) struct CollectorMetric {
+    collector_type: CollectorType,
+    collector: Box<dyn Collector>,
+}
+
+impl CollectorMetric {
+    pub(super) fn new(collector_type: CollectorType, collector: impl Collector) -> Self {
+        Self {
+            collector_type,
+            collector: Box::new(collector),
+        }
+    }
+}
+
+impl EncodeMetric for CollectorMetric {
+    fn encode(&self, mut encoder: Encoder) -> io::Result<()> {
+        let mut samples = Samples::default();
+
+        self.collector.collect(&mut samples);
+
+        for (labels, value) in &samples.samples {
+            // NOTE: the encoder opens the label set even if it's empty.
+            if labels.0.is_empty() {
+                encode_value(&mut encoder, *value)?;
+            } else {
+                encode_value(&mut encoder.with_label_set(labels), *value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        match self.collector_type {
+            CollectorType::Counter => MetricType::Counter,
+            CollectorType::Gauge => MetricType::Gauge,
+        }
+    }
+}
+
+fn encode_value(encoder: &mut Encoder, value: f64) -> io::Result<()> {
+    let mut bucket_encoder = encoder.no_suffix()?;
+    let mut value_encoder = bucket_encoder.no_bucket()?;
+
+    value_encoder.encode_value(value)?.no_exemplar()
+}
+
+#[derive(Debug)]
+struct LabelSet(Vec<(String, String)>);
+
+impl Encode for LabelSet {
+    fn encode(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+
+            write!(writer, "{name}=\"")?;
+
+            for c in value.chars() {
+                match c {
+                    '\\' => writer.write_all(b"\\\\")?,
+                    '"' => writer.write_all(b"\\\"")?,
+                    '\n' => writer.write_all(b"\\n")?,
+                    c => write!(writer, "{c}")?,
+                }
+            }
+
+            writer.write_all(b"\"")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::settings::ServiceNameFormat;
+
+    #[test]
+    fn encodes_collected_samples() {
+        let registries = Registries::new("test", &ServiceNameFormat::MetricPrefix);
+
+        registries.main_subsystem("collector").register(
+            "open_fds",
+            "Number of open file descriptors",
+            Box::new(CollectorMetric {
+                collector_type: CollectorType::Gauge,
+                collector: Box::new(|samples: &mut Samples| {
+                    samples.add(&[], 3.0);
+                    samples.add(&[("kind", "socket \"tcp\"")], 2.0);
+                }),
+            }),
+        );
+
+        let mut buffer = vec![];
+
+        registries.encode(&mut buffer, false).unwrap();
+
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(text.contains("# TYPE test_collector_open_fds gauge\n"));
+        assert!(text.contains("test_collector_open_fds 3.0\n"));
+        assert!(text.contains("test_collector_open_fds{kind=\"socket \\\"tcp\\\"\"} 2.0\n"));
+    }
+}
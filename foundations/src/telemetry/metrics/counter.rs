@@ -0,0 +1,200 @@
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A 4-byte event counter with saturating semantics.
+///
+/// The counter is intended for services that track events for a very large number of objects
+/// (e.g. connections or cache entries) where the memory footprint of a regular [`Counter`] for
+/// each object is prohibitive. Instead of wrapping around on overflow, the counter stays at
+/// [`u32::MAX`].
+///
+/// The counter can be used as a standalone metric, but usually per-object counters are obtained
+/// from an [`AggregatedCounter`] that sums them up on scrape.
+///
+/// [`Counter`]: super::Counter
+#[derive(Debug, Default)]
+pub struct CompactCounter(AtomicU32);
+
+impl CompactCounter {
+    /// Increases the counter by 1, returning the previous value.
+    #[inline]
+    pub fn inc(&self) -> u32 {
+        self.inc_by(1)
+    }
+
+    /// Increases the counter by `v`, saturating at [`u32::MAX`], returning the previous value.
+    #[inline]
+    pub fn inc_by(&self, v: u32) -> u32 {
+        // NOTE: `fetch_update` only fails if the closure returns `None`.
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |cur| {
+                Some(cur.saturating_add(v))
+            })
+            .unwrap_or_else(|cur| cur)
+    }
+
+    /// Returns the current value of the counter.
+    #[inline]
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn take(&self) -> u32 {
+        self.0.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl TypedMetric for CompactCounter {
+    const TYPE: MetricType = MetricType::Counter;
+}
+
+impl EncodeMetric for CompactCounter {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(self.get() as u64)?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// A counter that aggregates a set of [`CompactCounter`]s on scrape.
+///
+/// Per-object counters are created with [`AggregatedCounter::new_counter`] and only add
+/// a compact atomic to each object. On each scrape their values are moved into the aggregated
+/// 64-bit total, so per-object counters only need to accommodate the events that happened
+/// between two scrapes. Counters of dropped objects are collected on the next scrape, so
+/// no events are lost.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, AggregatedCounter, CompactCounter};
+/// use std::sync::Arc;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of read operations on cache entries.
+///     pub fn cache_entry_reads_total() -> AggregatedCounter;
+/// }
+///
+/// struct CacheEntry {
+///     reads: Arc<CompactCounter>,
+/// }
+///
+/// impl CacheEntry {
+///     fn new() -> Self {
+///         Self {
+///             reads: my_app_metrics::cache_entry_reads_total().new_counter(),
+///         }
+///     }
+///
+///     fn read(&self) {
+///         self.reads.inc();
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AggregatedCounter {
+    inner: Arc<AggregatedCounterInner>,
+}
+
+#[derive(Debug, Default)]
+struct AggregatedCounterInner {
+    total: AtomicU64,
+    counters: Mutex<Vec<Arc<CompactCounter>>>,
+}
+
+impl AggregatedCounter {
+    /// Creates a new per-object counter aggregated by this counter.
+    ///
+    /// Note that the value of the returned counter is reset on each scrape.
+    pub fn new_counter(&self) -> Arc<CompactCounter> {
+        let counter = Arc::new(CompactCounter::default());
+
+        self.inner.counters.lock().push(Arc::clone(&counter));
+
+        counter
+    }
+
+    /// Returns the aggregated value of all the per-object counters.
+    pub fn get(&self) -> u64 {
+        self.aggregate()
+    }
+
+    fn aggregate(&self) -> u64 {
+        let mut counters = self.inner.counters.lock();
+        let mut collected = 0;
+
+        counters.retain(|counter| {
+            collected += counter.take() as u64;
+
+            // NOTE: the object that owned the counter is gone if we hold the only reference.
+            Arc::strong_count(counter) > 1
+        });
+
+        self.inner.total.fetch_add(collected, Ordering::Relaxed) + collected
+    }
+}
+
+impl TypedMetric for AggregatedCounter {
+    const TYPE: MetricType = MetricType::Counter;
+}
+
+impl EncodeMetric for AggregatedCounter {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(self.aggregate())?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_counter_saturates() {
+        let counter = CompactCounter::default();
+
+        counter.inc_by(u32::MAX - 1);
+        counter.inc();
+        counter.inc();
+
+        assert_eq!(counter.get(), u32::MAX);
+    }
+
+    #[test]
+    fn aggregates_live_and_dropped_counters() {
+        let aggregated = AggregatedCounter::default();
+        let c1 = aggregated.new_counter();
+        let c2 = aggregated.new_counter();
+
+        c1.inc_by(3);
+        c2.inc();
+
+        assert_eq!(aggregated.get(), 4);
+        assert_eq!(c1.get(), 0);
+
+        c2.inc_by(u32::MAX);
+        drop(c2);
+        c1.inc();
+
+        assert_eq!(aggregated.get(), 5 + u32::MAX as u64);
+        assert_eq!(aggregated.inner.counters.lock().len(), 1);
+    }
+}
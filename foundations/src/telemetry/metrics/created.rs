@@ -0,0 +1,176 @@
+use super::exposition::{parse_sample, Labels};
+use super::ordering::series_key;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static TRACKER: Lazy<Mutex<CreatedTracker>> = Lazy::new(Default::default);
+
+#[derive(Default)]
+struct CreatedTracker {
+    series: HashMap<(String, Labels), f64>,
+}
+
+impl CreatedTracker {
+    fn add_created(&mut self, text: &str, now: SystemTime) -> String {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        // NOTE: the series that are not reported anymore are dropped.
+        let mut prev = std::mem::take(&mut self.series);
+        let mut out = String::with_capacity(text.len());
+        // NOTE: the family of the current samples, if it has created timestamps.
+        let mut family: Option<&str> = None;
+        // NOTE: the series whose created timestamp is emitted after its last sample.
+        let mut pending: Option<Labels> = None;
+
+        let mut flush = |out: &mut String, family: Option<&str>, pending: &mut Option<Labels>| {
+            let (Some(family), Some(labels)) = (family, pending.take()) else {
+                return;
+            };
+
+            let key = (family.to_string(), labels);
+            let created = prev.remove(&key).unwrap_or(now);
+
+            let _ = writeln!(
+                out,
+                "{family}_created{} {created:.3}",
+                encode_labels(&key.1)
+            );
+
+            self.series.insert(key, created);
+        };
+
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                flush(&mut out, family, &mut pending);
+
+                if let Some(rest) = comment.strip_prefix("TYPE ") {
+                    family = match rest.split_once(' ') {
+                        Some((name, "counter" | "histogram")) => Some(name),
+                        _ => None,
+                    };
+                }
+            } else if family.is_some() {
+                if let Some((_, labels, _)) = parse_sample(line) {
+                    let key = series_key(labels);
+
+                    if pending.as_ref() != Some(&key) {
+                        flush(&mut out, family, &mut pending);
+                        pending = Some(key);
+                    }
+                }
+            }
+
+            out.push_str(line);
+            out.push('\n');
+        }
+
+        flush(&mut out, family, &mut pending);
+
+        out
+    }
+}
+
+pub(super) fn encode_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("{");
+
+    for (i, (name, value)) in labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+
+        let _ = write!(out, "{name}=\"{value}\"");
+    }
+
+    out.push('}');
+    out
+}
+
+/// Adds `_created` samples with the creation timestamps to the counter and histogram series
+/// of the text exposition.
+pub(super) fn add_created(text: &str) -> String {
+    TRACKER.lock().add_created(text, SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn adds_created_samples() {
+        let mut tracker = CreatedTracker::default();
+        let t0 = UNIX_EPOCH + Duration::from_secs(100);
+        let t1 = t0 + Duration::from_secs(15);
+
+        let text = concat!(
+            "# HELP requests Number of requests.\n",
+            "# TYPE requests counter\n",
+            "requests_total{path=\"/a\"} 1\n",
+            "# HELP connections Number of connections.\n",
+            "# TYPE connections gauge\n",
+            "connections 3\n",
+            "# HELP latency Latency.\n",
+            "# TYPE latency histogram\n",
+            "latency_bucket{le=\"1.0\"} 1\n",
+            "latency_bucket{le=\"+Inf\"} 1\n",
+            "latency_sum 0.5\n",
+            "latency_count 1\n",
+            "# EOF\n",
+        );
+
+        assert_eq!(
+            tracker.add_created(text, t0),
+            concat!(
+                "# HELP requests Number of requests.\n",
+                "# TYPE requests counter\n",
+                "requests_total{path=\"/a\"} 1\n",
+                "requests_created{path=\"/a\"} 100.000\n",
+                "# HELP connections Number of connections.\n",
+                "# TYPE connections gauge\n",
+                "connections 3\n",
+                "# HELP latency Latency.\n",
+                "# TYPE latency histogram\n",
+                "latency_bucket{le=\"1.0\"} 1\n",
+                "latency_bucket{le=\"+Inf\"} 1\n",
+                "latency_sum 0.5\n",
+                "latency_count 1\n",
+                "latency_created 100.000\n",
+                "# EOF\n",
+            )
+        );
+
+        let text = concat!(
+            "# HELP requests Number of requests.\n",
+            "# TYPE requests counter\n",
+            "requests_total{path=\"/a\"} 2\n",
+            "requests_total{path=\"/b\\\"\"} 1\n",
+        );
+
+        assert_eq!(
+            tracker.add_created(text, t1),
+            concat!(
+                "# HELP requests Number of requests.\n",
+                "# TYPE requests counter\n",
+                "requests_total{path=\"/a\"} 2\n",
+                "requests_created{path=\"/a\"} 100.000\n",
+                "requests_total{path=\"/b\\\"\"} 1\n",
+                "requests_created{path=\"/b\\\"\"} 115.000\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use super::exposition::{parse_sample, parse_value, Labels};
+use super::label_filter::format_value;
+use crate::telemetry::settings::MetricsSettings;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+static PREVIOUS: Lazy<Mutex<HashMap<(String, Labels), f64>>> = Lazy::new(Default::default);
+
+/// Replaces the values of the counters reset on scrape with the deltas since the previous
+/// collection, see [`MetricsSettings::reset_counters_on_scrape`].
+pub(super) fn reset_counters(text: &str, settings: &MetricsSettings) -> String {
+    to_deltas(text, settings, &mut PREVIOUS.lock())
+}
+
+fn to_deltas(
+    text: &str,
+    settings: &MetricsSettings,
+    previous: &mut HashMap<(String, Labels), f64>,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    // NOTE: the series that are not reported anymore are dropped.
+    let mut current = HashMap::with_capacity(previous.len());
+    let mut reset = false;
+
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            if let Some(rest) = comment.strip_prefix("TYPE ") {
+                let mut parts = rest.split(' ');
+                let family = parts.next().unwrap_or_default();
+
+                reset = parts.next() == Some("counter") && is_reset(settings, family);
+            }
+
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        let parsed = parse_sample(line)
+            .filter(|_| reset)
+            .and_then(|(name, labels, value)| {
+                Some((name, labels, value, parse_value(value).ok()?))
+            });
+
+        let Some((name, labels, value, total)) = parsed else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+
+        let key = (name.to_string(), labels);
+
+        // NOTE: the counter was reset if it decreased, e.g. the series was removed and
+        // recreated, in which case its whole value is reported.
+        let delta = match previous.get(&key) {
+            Some(&prev) if prev <= total => total - prev,
+            _ => total,
+        };
+
+        // NOTE: the value is a subslice of the line, which also keeps the exemplar, if any.
+        let value_start = value.as_ptr() as usize - line.as_ptr() as usize;
+
+        out.push_str(&line[..value_start]);
+        out.push_str(&format_value(delta));
+        out.push_str(&line[value_start + value.len()..]);
+        out.push('\n');
+
+        current.insert(key, total);
+    }
+
+    *previous = current;
+
+    out
+}
+
+fn is_reset(settings: &MetricsSettings, family: &str) -> bool {
+    settings.reset_counters_on_scrape
+        || settings
+            .reset_on_scrape
+            .iter()
+            .any(|metric| metric == family || metric.strip_suffix("_total") == Some(family))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_deltas_of_reset_counters() {
+        let settings = MetricsSettings {
+            reset_on_scrape: vec!["app_requests_total".into()],
+            ..Default::default()
+        };
+
+        let text = |requests_a, requests_b, connections| {
+            format!(
+                concat!(
+                    "# HELP app_requests Number of requests.\n",
+                    "# TYPE app_requests counter\n",
+                    "app_requests_total{{path=\"/a\"}} {} # {{trace_id=\"abc\"}} 1.0\n",
+                    "app_requests_total{{path=\"/b\"}} {}\n",
+                    "# HELP app_errors Number of errors.\n",
+                    "# TYPE app_errors counter\n",
+                    "app_errors_total {}\n",
+                    "# EOF\n",
+                ),
+                requests_a, requests_b, connections
+            )
+        };
+
+        let mut previous = HashMap::new();
+
+        assert_eq!(
+            to_deltas(&text(3, 5, 7), &settings, &mut previous),
+            concat!(
+                "# HELP app_requests Number of requests.\n",
+                "# TYPE app_requests counter\n",
+                "app_requests_total{path=\"/a\"} 3.0 # {trace_id=\"abc\"} 1.0\n",
+                "app_requests_total{path=\"/b\"} 5.0\n",
+                "# HELP app_errors Number of errors.\n",
+                "# TYPE app_errors counter\n",
+                "app_errors_total 7\n",
+                "# EOF\n",
+            )
+        );
+
+        // NOTE: the counter of `/b` was reset.
+        assert_eq!(
+            to_deltas(&text(10, 2, 9), &settings, &mut previous),
+            concat!(
+                "# HELP app_requests Number of requests.\n",
+                "# TYPE app_requests counter\n",
+                "app_requests_total{path=\"/a\"} 7.0 # {trace_id=\"abc\"} 1.0\n",
+                "app_requests_total{path=\"/b\"} 2.0\n",
+                "# HELP app_errors Number of errors.\n",
+                "# TYPE app_errors counter\n",
+                "app_errors_total 9\n",
+                "# EOF\n",
+            )
+        );
+
+        let settings = MetricsSettings {
+            reset_counters_on_scrape: true,
+            ..Default::default()
+        };
+
+        assert!(to_deltas(&text(10, 2, 12), &settings, &mut previous)
+            .contains("app_errors_total 12.0\n"));
+
+        assert!(to_deltas(&text(10, 2, 12), &settings, &mut previous)
+            .contains("app_errors_total 0.0\n"));
+    }
+}
@@ -0,0 +1,247 @@
+use super::MetricConstructor;
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A builder for [`EwmaGauge`].
+///
+/// # Example
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, EwmaGauge, EwmaGaugeBuilder};
+/// use std::time::Duration;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests waiting in the queue.
+///     #[ctor = EwmaGaugeBuilder {
+///         half_life: Duration::from_secs(30),
+///     }]
+///     pub fn queue_depth() -> EwmaGauge;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct EwmaGaugeBuilder {
+    /// Time it takes for the smoothed value to move halfway towards the raw value, if the latter
+    /// doesn't change.
+    ///
+    /// The longer the half-life, the smoother the value: spikes shorter than the half-life
+    /// barely affect it.
+    pub half_life: Duration,
+}
+
+impl EwmaGaugeBuilder {
+    /// The default builder, with a half-life of one minute.
+    pub const DEFAULT: Self = Self {
+        half_life: Duration::from_secs(60),
+    };
+}
+
+impl Default for EwmaGaugeBuilder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MetricConstructor<EwmaGauge> for EwmaGaugeBuilder {
+    fn new_metric(&self) -> EwmaGauge {
+        EwmaGauge::new(self.half_life)
+    }
+}
+
+/// A gauge that, in addition to its raw value, tracks the exponentially weighted moving average
+/// (EWMA) of the value over time.
+///
+/// Instantaneous gauges, e.g. queue depths, can be too spiky to drive decisions like
+/// autoscaling. The smoothed value decays towards the raw value with the half-life configured
+/// with [`EwmaGaugeBuilder`], weighted by the time each raw value was held. The gauge reports two
+/// series:
+///
+/// - `<name>` with the raw value;
+/// - `<name>_ewma` with the smoothed value.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, EwmaGauge};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests waiting in the queue.
+///     pub fn queue_depth() -> EwmaGauge;
+/// }
+///
+/// fn on_queue_change(depth: usize) {
+///     my_app_metrics::queue_depth().set(depth as f64);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct EwmaGauge {
+    inner: Arc<EwmaGaugeInner>,
+}
+
+#[derive(Debug)]
+struct EwmaGaugeInner {
+    half_life: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    raw: f64,
+    smoothed: f64,
+    updated_at: Instant,
+}
+
+impl State {
+    fn decay(&mut self, half_life: Duration, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        let weight = if half_life.is_zero() {
+            1.0
+        } else {
+            1.0 - (-elapsed.as_secs_f64() / half_life.as_secs_f64()).exp2()
+        };
+
+        self.smoothed += (self.raw - self.smoothed) * weight;
+        self.updated_at = self.updated_at.max(now);
+    }
+}
+
+impl Default for EwmaGauge {
+    fn default() -> Self {
+        EwmaGaugeBuilder::DEFAULT.new_metric()
+    }
+}
+
+impl EwmaGauge {
+    fn new(half_life: Duration) -> Self {
+        Self {
+            inner: Arc::new(EwmaGaugeInner {
+                half_life,
+                state: Mutex::new(State {
+                    raw: 0.0,
+                    smoothed: 0.0,
+                    updated_at: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    /// Sets the raw value of the gauge to `v`, returning the previous value.
+    pub fn set(&self, v: f64) -> f64 {
+        self.update(Instant::now(), |_| v)
+    }
+
+    /// Increases the raw value of the gauge by `v`, returning the previous value.
+    pub fn inc_by(&self, v: f64) -> f64 {
+        self.update(Instant::now(), |raw| raw + v)
+    }
+
+    /// Decreases the raw value of the gauge by `v`, returning the previous value.
+    pub fn dec_by(&self, v: f64) -> f64 {
+        self.update(Instant::now(), |raw| raw - v)
+    }
+
+    /// Returns the raw value of the gauge.
+    pub fn get(&self) -> f64 {
+        self.inner.state.lock().raw
+    }
+
+    /// Returns the smoothed value of the gauge.
+    pub fn smoothed(&self) -> f64 {
+        self.smoothed_at(Instant::now())
+    }
+
+    fn update(&self, now: Instant, f: impl FnOnce(f64) -> f64) -> f64 {
+        let mut state = self.inner.state.lock();
+
+        state.decay(self.inner.half_life, now);
+
+        let prev = state.raw;
+
+        state.raw = f(prev);
+
+        prev
+    }
+
+    fn smoothed_at(&self, now: Instant) -> f64 {
+        let mut state = self.inner.state.lock();
+
+        state.decay(self.inner.half_life, now);
+
+        state.smoothed
+    }
+}
+
+impl TypedMetric for EwmaGauge {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for EwmaGauge {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let smoothed = self.smoothed();
+
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(self.get())?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("ewma")?
+            .no_bucket()?
+            .encode_value(smoothed)?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[test]
+    fn smoothed_value_decays_with_half_life() {
+        let gauge = EwmaGauge::new(Duration::from_secs(10));
+        let start = gauge.inner.state.lock().updated_at;
+
+        gauge.update(start, |_| 100.0);
+
+        assert_eq!(gauge.smoothed_at(start), 0.0);
+        assert_eq!(gauge.smoothed_at(start + Duration::from_secs(10)), 50.0);
+        assert_eq!(gauge.smoothed_at(start + Duration::from_secs(20)), 75.0);
+
+        gauge.update(start + Duration::from_secs(20), |_| 0.0);
+
+        assert_eq!(gauge.get(), 0.0);
+        assert_eq!(gauge.smoothed_at(start + Duration::from_secs(30)), 37.5);
+    }
+
+    #[test]
+    fn encodes_raw_and_smoothed_values() {
+        let gauge = EwmaGauge::new(Duration::ZERO);
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        gauge.set(4.0);
+        gauge.inc_by(1.5);
+
+        registry.register("depth", "Queue depth", Box::new(gauge.clone()));
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains("depth 5.5\n"));
+        assert!(encoded.contains("depth_ewma 5.5\n"));
+    }
+}
@@ -0,0 +1,301 @@
+use super::{HistogramBuilder, MetricConstructor};
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::exemplar::{CounterWithExemplar, HistogramWithExemplars};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::fmt;
+use std::io::{self, Write};
+
+/// A counter that attaches the trace ID of the current span to the increments as an [exemplar].
+///
+/// Exemplars allow dashboards to jump from a spike in the metric to a trace of one of
+/// the requests that caused it. The counter keeps the exemplar of the last increment made in
+/// the scope of a sampled span, and reports it as `trace_id` label of the exemplar. Increments made
+/// outside of a sampled span, or without the `tracing` feature enabled, don't replace
+/// the exemplar.
+///
+/// Exemplars are only supported by the [OpenMetrics] format, so they are reported only if
+/// [`MetricsSettings::exemplars`] is enabled. Prometheus also needs to be run with
+/// the `exemplar-storage` feature enabled to store them.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, ExemplarCounter};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of failed requests, with the trace ID of the last failed request.
+///     pub fn failed_requests_total() -> ExemplarCounter;
+/// }
+///
+/// fn usage() {
+///     my_app_metrics::failed_requests_total().inc();
+/// }
+/// # }
+/// ```
+///
+/// [exemplar]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#exemplars
+/// [OpenMetrics]: https://openmetrics.io/
+/// [`MetricsSettings::exemplars`]: crate::telemetry::settings::MetricsSettings::exemplars
+#[derive(Clone, Default)]
+pub struct ExemplarCounter(CounterWithExemplar<TraceIdLabel>);
+
+impl ExemplarCounter {
+    /// Increases the counter by 1, returning the previous value.
+    #[inline]
+    pub fn inc(&self) -> u64 {
+        self.inc_by(1)
+    }
+
+    /// Increases the counter by `v`, returning the previous value.
+    #[inline]
+    pub fn inc_by(&self, v: u64) -> u64 {
+        match TraceIdLabel::current() {
+            Some(label) => self.0.inc_by(v, Some(label)),
+            None => self
+                .0
+                .inner()
+                .fetch_add(v, std::sync::atomic::Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the current value of the counter.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.get().0
+    }
+}
+
+impl fmt::Debug for ExemplarCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ExemplarCounter").field(&self.get()).finish()
+    }
+}
+
+impl TypedMetric for ExemplarCounter {
+    const TYPE: MetricType = MetricType::Counter;
+}
+
+impl EncodeMetric for ExemplarCounter {
+    fn encode(&self, mut encoder: Encoder) -> io::Result<()> {
+        let (value, exemplar) = self.0.get();
+
+        // NOTE: unlike `CounterWithExemplar`, don't add the `_total` suffix, so the counter is
+        // named the same way as the other counters.
+        let mut bucket_encoder = encoder.no_suffix()?;
+        let mut value_encoder = bucket_encoder.no_bucket()?;
+        let mut exemplar_encoder = value_encoder.encode_value(value)?;
+
+        match &*exemplar {
+            Some(exemplar) => exemplar_encoder.encode_exemplar(exemplar),
+            None => exemplar_encoder.no_exemplar(),
+        }
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// A histogram that attaches the trace ID of the current span to the observations as
+/// an [exemplar].
+///
+/// The histogram keeps the exemplar of the last observation made in the scope of a sampled span
+/// for each bucket. See [`ExemplarCounter`] for the details on exemplars.
+///
+/// The histogram needs to be built with [`HistogramBuilder`].
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, ExemplarHistogram, HistogramBuilder};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Sizes of the responses, with the trace IDs of the requests in each bucket.
+///     #[ctor = HistogramBuilder { buckets: &[1024.0, 65536.0, 1048576.0] }]
+///     pub fn response_size() -> ExemplarHistogram;
+/// }
+///
+/// fn usage(body: &[u8]) {
+///     my_app_metrics::response_size().observe(body.len() as f64);
+/// }
+/// # }
+/// ```
+///
+/// [exemplar]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#exemplars
+#[derive(Clone, Debug)]
+pub struct ExemplarHistogram(HistogramWithExemplars<TraceIdLabel>);
+
+impl ExemplarHistogram {
+    /// Creates a new histogram with the given buckets.
+    pub fn new(buckets: impl Iterator<Item = f64>) -> Self {
+        Self(HistogramWithExemplars::new(buckets))
+    }
+
+    /// Observes the value.
+    #[inline]
+    pub fn observe(&self, v: f64) {
+        self.0.observe(v, TraceIdLabel::current());
+    }
+}
+
+impl MetricConstructor<ExemplarHistogram> for HistogramBuilder {
+    fn new_metric(&self) -> ExemplarHistogram {
+        ExemplarHistogram::new(self.buckets.iter().cloned())
+    }
+}
+
+impl TypedMetric for ExemplarHistogram {
+    const TYPE: MetricType = MetricType::Histogram;
+}
+
+impl EncodeMetric for ExemplarHistogram {
+    fn encode(&self, encoder: Encoder) -> io::Result<()> {
+        self.0.encode(encoder)
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// Label set of the exemplars.
+#[derive(Debug)]
+struct TraceIdLabel(String);
+
+impl TraceIdLabel {
+    #[cfg(feature = "tracing")]
+    fn current() -> Option<Self> {
+        crate::telemetry::tracing::trace_id().map(Self)
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn current() -> Option<Self> {
+        None
+    }
+}
+
+impl Encode for TraceIdLabel {
+    fn encode(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(b"trace_id=\"")?;
+        writer.write_all(self.0.as_bytes())?;
+        writer.write_all(b"\"")
+    }
+}
+
+/// Removes the exemplars from the samples in the text format.
+pub(super) fn strip_exemplars(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+
+    for line in text.split_inclusive('\n') {
+        match exemplar_start(line) {
+            Some(start) => {
+                stripped.push_str(&line[..start]);
+                stripped.push('\n');
+            }
+            None => stripped.push_str(line),
+        }
+    }
+
+    stripped
+}
+
+fn exemplar_start(line: &str) -> Option<usize> {
+    if line.starts_with('#') {
+        return None;
+    }
+
+    // NOTE: skip the label set, as the label values can contain ` # `.
+    let mut labels_end = 0;
+    let mut in_value = false;
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_value => {
+                chars.next();
+            }
+            '"' => in_value = !in_value,
+            ' ' if !in_value => break,
+            '}' if !in_value => {
+                labels_end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    line[labels_end..]
+        .find(" # ")
+        .map(|start| labels_end + start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "tracing", feature = "testing"))]
+    #[test]
+    fn records_trace_id_of_current_span() {
+        use crate::telemetry::{tracing, TelemetryContext};
+        use prometheus_client::encoding::text::encode;
+        use prometheus_client::registry::Registry;
+
+        let ctx = TelemetryContext::test();
+        let counter = ExemplarCounter::default();
+        let histogram = ExemplarHistogram::new([1.0, 10.0].into_iter());
+
+        counter.inc();
+        histogram.observe(0.5);
+
+        let trace_id = {
+            let _scope = ctx.scope();
+            let _span = tracing::span("request");
+
+            counter.inc_by(2);
+            histogram.observe(5.0);
+
+            tracing::trace_id().unwrap()
+        };
+
+        counter.inc();
+
+        let mut registry = Registry::<Box<dyn EncodeMetric>>::default();
+        let mut buffer = vec![];
+
+        registry.register("failures", "Failures", Box::new(counter));
+        registry.register("size", "Size", Box::new(histogram));
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains(&format!("failures 4 # {{trace_id=\"{trace_id}\"}} 2\n")));
+        assert!(encoded.contains(&format!(
+            "size_bucket{{le=\"10.0\"}} 2 # {{trace_id=\"{trace_id}\"}} 5.0\n"
+        )));
+        assert!(encoded.contains("size_bucket{le=\"1.0\"} 1\n"));
+    }
+
+    #[test]
+    fn strips_exemplars() {
+        let text = concat!(
+            "# HELP failures Failures.\n",
+            "# TYPE failures counter\n",
+            "failures{path=\"/a # {b}\"} 4 # {trace_id=\"42\"} 2\n",
+            "failures 1\n",
+            "# EOF\n",
+        );
+
+        assert_eq!(
+            strip_exemplars(text),
+            concat!(
+                "# HELP failures Failures.\n",
+                "# TYPE failures counter\n",
+                "failures{path=\"/a # {b}\"} 4\n",
+                "failures 1\n",
+                "# EOF\n",
+            )
+        );
+    }
+}
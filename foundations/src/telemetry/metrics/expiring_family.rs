@@ -0,0 +1,227 @@
+use super::LimitedFamily;
+use parking_lot::{MappedRwLockReadGuard, Mutex};
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+use prometheus_client::metrics::family::MetricConstructor;
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A [`LimitedFamily`] that removes the label sets that haven't been accessed within the TTL.
+///
+/// Long-running services can accumulate label sets that are no longer updated, e.g. labeled with
+/// the IDs of the closed connections or of the tenants that have gone away. The family tracks
+/// when each label set was last accessed with [`ExpiringFamily::get_or_create`] and removes the
+/// expired label sets on each scrape, before the metrics are encoded.
+///
+/// Metrics with labels declared with the [`metrics`] macro use this family if the TTL is
+/// specified with the `#[ttl]` attribute:
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Counter};
+/// use std::sync::Arc;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests, by the tenant. Tenants without requests are removed after an hour.
+///     #[ttl = std::time::Duration::from_secs(3600)]
+///     pub fn requests_per_tenant_total(tenant: &Arc<str>) -> Counter;
+/// }
+/// # }
+/// ```
+///
+/// Note that the label sets are only refreshed when accessed through the family, so the metrics
+/// obtained from the family shouldn't be retained for longer than the TTL.
+///
+/// [`metrics`]: crate::telemetry::metrics::metrics
+#[derive(Debug)]
+pub struct ExpiringFamily<S, M, C = fn() -> M> {
+    family: LimitedFamily<S, M, C>,
+    inner: Arc<ExpiringFamilyInner<S>>,
+}
+
+#[derive(Debug)]
+struct ExpiringFamilyInner<S> {
+    ttl: Duration,
+    accessed_at: Mutex<HashMap<S, Instant>>,
+}
+
+impl<S, M, C> ExpiringFamily<S, M, C>
+where
+    S: Clone + Eq + Hash,
+    C: Clone,
+{
+    /// Creates a family with the TTL of the label sets, using a custom constructor to construct
+    /// new metrics.
+    pub fn new_with_constructor(ttl: Duration, constructor: C) -> Self {
+        Self {
+            family: LimitedFamily::new_with_constructor(constructor),
+            inner: Arc::new(ExpiringFamilyInner {
+                ttl,
+                accessed_at: Default::default(),
+            }),
+        }
+    }
+}
+
+impl<S, M> ExpiringFamily<S, M>
+where
+    S: Clone + Eq + Hash,
+    M: Default,
+{
+    /// Creates a family with the TTL of the label sets.
+    pub fn new(ttl: Duration) -> Self {
+        Self::new_with_constructor(ttl, M::default)
+    }
+}
+
+impl<S, M, C> ExpiringFamily<S, M, C>
+where
+    S: Clone + Eq + Hash,
+    C: MetricConstructor<M>,
+{
+    /// Accesses a metric with the given label set, creating it if one does not yet exist, and
+    /// refreshes the label set.
+    ///
+    /// See [`LimitedFamily::get_or_create`] for the caveats of holding the returned reference.
+    pub fn get_or_create(&self, label_set: &S) -> MappedRwLockReadGuard<'_, M> {
+        // NOTE: the label sets collapsed into the overflow label set are not tracked, so they
+        // don't grow the map.
+        self.family
+            .get_or_create_with(label_set, || self.touch(label_set, Instant::now()))
+    }
+
+    /// Removes a label set from the family.
+    ///
+    /// Returns a bool indicating if the label set was present or not.
+    pub fn remove(&self, label_set: &S) -> bool {
+        let mut accessed_at = self.inner.accessed_at.lock();
+
+        accessed_at.remove(label_set);
+
+        self.family.remove(label_set)
+    }
+
+    /// Clears all label sets from the family.
+    pub fn clear(&self) {
+        let mut accessed_at = self.inner.accessed_at.lock();
+
+        accessed_at.clear();
+
+        self.family.clear();
+    }
+
+    fn touch(&self, label_set: &S, now: Instant) {
+        let mut accessed_at = self.inner.accessed_at.lock();
+
+        match accessed_at.get_mut(label_set) {
+            Some(at) => *at = (*at).max(now),
+            None => {
+                accessed_at.insert(label_set.clone(), now);
+            }
+        }
+    }
+
+    /// Removes the label sets that haven't been accessed within the TTL.
+    fn sweep(&self, now: Instant) {
+        let mut accessed_at = self.inner.accessed_at.lock();
+
+        // NOTE: the label sets are removed while holding the lock, so they can't be refreshed
+        // concurrently between the check and the removal.
+        accessed_at.retain(|label_set, at| {
+            let expired = now.saturating_duration_since(*at) >= self.inner.ttl;
+
+            if expired {
+                self.family.remove(label_set);
+            }
+
+            !expired
+        });
+    }
+}
+
+impl<S, M, C> EncodeMetric for ExpiringFamily<S, M, C>
+where
+    S: Clone + Eq + Hash + Serialize,
+    M: EncodeMetric + TypedMetric,
+    C: MetricConstructor<M>,
+{
+    fn encode(&self, encoder: Encoder) -> std::io::Result<()> {
+        self.sweep(Instant::now());
+
+        self.family.encode(encoder)
+    }
+
+    fn metric_type(&self) -> MetricType {
+        M::TYPE
+    }
+}
+
+impl<S, M, C> TypedMetric for ExpiringFamily<S, M, C>
+where
+    M: TypedMetric,
+{
+    const TYPE: MetricType = <M as TypedMetric>::TYPE;
+}
+
+impl<S, M, C> Clone for ExpiringFamily<S, M, C>
+where
+    C: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            family: self.family.clone(),
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::metrics::Counter;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[derive(Clone, Eq, Hash, PartialEq, Serialize)]
+    struct Labels {
+        tenant: &'static str,
+    }
+
+    fn encode_family(family: &ExpiringFamily<Labels, Counter>) -> String {
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        registry.register("requests", "Requests", Box::new(family.clone()));
+        encode(&mut buffer, &registry).unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn removes_expired_label_sets() {
+        let family = ExpiringFamily::<Labels, Counter>::new(Duration::from_secs(60));
+        let stale = Labels { tenant: "stale" };
+        let active = Labels { tenant: "active" };
+
+        family.get_or_create(&stale).inc();
+        family.get_or_create(&active).inc();
+
+        let start = Instant::now();
+
+        family.touch(&active, start + Duration::from_secs(30));
+        family.sweep(start + Duration::from_secs(60));
+
+        let encoded = encode_family(&family);
+
+        assert!(!encoded.contains("tenant=\"stale\""));
+        assert!(encoded.contains("requests{tenant=\"active\"} 1\n"));
+
+        assert!(family.remove(&active));
+        assert!(!family.remove(&active));
+        assert!(!encode_family(&family).contains("tenant=\"active\""));
+    }
+}
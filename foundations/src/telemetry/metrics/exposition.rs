@@ -0,0 +1,322 @@
+//! Parser of the metrics in the [text exposition format].
+//!
+//! Metric types from `prometheus_client` can only be encoded in the text format, so the metrics
+//! of the registries are encoded as text once and parsed into metric families by the collection
+//! paths that need the typed values, such as the [protobuf exposition format] encoder,
+//! the OTLP exporter and the snapshots.
+//!
+//! [text exposition format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+//! [protobuf exposition format]: https://github.com/prometheus/client_model/blob/master/io/prometheus/client/metrics.proto
+
+use super::native_histogram::NativeHistogramSnapshot;
+use crate::Result;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+    GaugeHistogram,
+    Info,
+    Untyped,
+}
+
+impl Kind {
+    fn parse(s: &str) -> Self {
+        match s {
+            "counter" => Kind::Counter,
+            "gauge" => Kind::Gauge,
+            "histogram" => Kind::Histogram,
+            "gaugehistogram" => Kind::GaugeHistogram,
+            "info" => Kind::Info,
+            _ => Kind::Untyped,
+        }
+    }
+}
+
+pub(super) type Labels = Vec<(String, String)>;
+
+pub(super) enum Value {
+    Scalar(f64),
+    Histogram(Box<Histogram>),
+}
+
+#[derive(Default)]
+pub(super) struct Histogram {
+    pub(super) data: NativeHistogramSnapshot,
+    pub(super) is_native: bool,
+}
+
+pub(super) struct Metric {
+    pub(super) labels: Labels,
+    pub(super) value: Value,
+}
+
+pub(super) struct Family {
+    pub(super) name: String,
+    pub(super) help: String,
+    pub(super) kind: Kind,
+    pub(super) metrics: Vec<Metric>,
+}
+
+impl Family {
+    fn new(name: &str, help: String, kind: Kind) -> Self {
+        Self {
+            name: name.to_string(),
+            help,
+            kind,
+            metrics: vec![],
+        }
+    }
+
+    fn histogram(&mut self, labels: Labels) -> &mut Histogram {
+        let pos = self.metrics.iter().position(|m| m.labels == labels);
+
+        let pos = pos.unwrap_or_else(|| {
+            self.metrics.push(Metric {
+                labels,
+                value: Value::Histogram(Default::default()),
+            });
+
+            self.metrics.len() - 1
+        });
+
+        match &mut self.metrics[pos].value {
+            Value::Histogram(histogram) => histogram,
+            Value::Scalar(_) => unreachable!("histogram families only contain histograms"),
+        }
+    }
+}
+
+/// Parses metrics in the text exposition format into metric families.
+pub(super) fn parse(text: &str) -> Result<Vec<Family>> {
+    let mut families: Vec<Family> = vec![];
+    // NOTE: the family declared with the last `# HELP` or `# TYPE` line,
+    // samples of other families are put in separate families.
+    let mut current: Option<usize> = None;
+    let mut help = String::new();
+
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            if let Some(rest) = comment.strip_prefix("HELP ") {
+                let (_, text) = rest.split_once(' ').unwrap_or((rest, ""));
+
+                help = text.replace("\\n", "\n").replace("\\\\", "\\");
+            } else if let Some(rest) = comment.strip_prefix("TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap_or((rest, ""));
+
+                families.push(Family::new(
+                    name,
+                    std::mem::take(&mut help),
+                    Kind::parse(kind),
+                ));
+                current = Some(families.len() - 1);
+            }
+
+            continue;
+        }
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (name, labels, value) =
+            parse_sample(line).ok_or_else(|| format!("malformed metrics sample: {line}"))?;
+
+        let family = current.map(|i| &mut families[i]).and_then(|f| {
+            let suffix = name.strip_prefix(f.name.as_str())?;
+
+            Some((f, suffix))
+        });
+
+        match family {
+            Some((family, suffix))
+                if matches!(family.kind, Kind::Histogram | Kind::GaugeHistogram) =>
+            {
+                add_histogram_sample(family, suffix, labels, value)?;
+            }
+            Some((family, "")) if family.kind != Kind::Info => family.metrics.push(Metric {
+                labels,
+                value: Value::Scalar(parse_value(value)?),
+            }),
+            Some((family, "_total")) if family.kind == Kind::Counter => {
+                // NOTE: use the name of the sample for the family, so the series names
+                // are the same regardless of the exposition format.
+                family.name = name.to_string();
+                family.metrics.push(Metric {
+                    labels,
+                    value: Value::Scalar(parse_value(value)?),
+                });
+            }
+            Some((_, "_created")) => {}
+            family => {
+                let (kind, help) = match family {
+                    Some((family, _)) if family.kind == Kind::Info => (Kind::Gauge, &family.help),
+                    Some((family, _)) => (family.kind, &family.help),
+                    None => (Kind::Untyped, &help),
+                };
+
+                let help = help.clone();
+                let metric = Metric {
+                    labels,
+                    value: Value::Scalar(parse_value(value)?),
+                };
+
+                match families.iter_mut().rev().find(|f| f.name == name) {
+                    Some(family) => family.metrics.push(metric),
+                    None => {
+                        let mut family = Family::new(name, help, kind);
+
+                        family.metrics.push(metric);
+                        families.push(family);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(families)
+}
+
+fn add_histogram_sample(
+    family: &mut Family,
+    suffix: &str,
+    mut labels: Labels,
+    value: &str,
+) -> Result<()> {
+    match suffix {
+        "_bucket" => {
+            let le = labels
+                .iter()
+                .position(|(name, _)| name == "le")
+                .map(|i| labels.remove(i).1)
+                .ok_or("histogram bucket without `le` label")?;
+
+            let upper_bound = if le == "+Inf" {
+                f64::INFINITY
+            } else {
+                le.parse()?
+            };
+
+            let count = parse_value(value)? as u64;
+
+            family
+                .histogram(labels)
+                .data
+                .classic
+                .push((upper_bound, count));
+        }
+        "_sum" | "_gsum" => family.histogram(labels).data.sum = parse_value(value)?,
+        "_count" | "_gcount" => family.histogram(labels).data.count = parse_value(value)? as u64,
+        "_native" => {
+            let histogram = family.histogram(labels);
+
+            histogram
+                .data
+                .parse_native_payload(value)
+                .ok_or("malformed native histogram payload")?;
+
+            histogram.is_native = true;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub(super) fn parse_sample(line: &str) -> Option<(&str, Labels, &str)> {
+    let name_end = line.find(['{', ' '])?;
+    let name = &line[..name_end];
+    let mut rest = &line[name_end..];
+    let mut labels = vec![];
+
+    if let Some(mut s) = rest.strip_prefix('{') {
+        loop {
+            s = s.trim_start_matches(',');
+
+            if let Some(after) = s.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let (label, after) = s.split_once("=\"")?;
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+
+            let end = loop {
+                match chars.next()? {
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        c => value.push(c),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+
+            labels.push((label.to_string(), value));
+            s = &after[end + 1..];
+        }
+    }
+
+    let value = rest.trim_start().split(' ').next()?;
+
+    Some((name, labels, value))
+}
+
+pub(super) fn parse_value(value: &str) -> Result<f64> {
+    Ok(match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        "NaN" => f64::NAN,
+        _ => value.parse()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_text_exposition() {
+        let text = concat!(
+            "# HELP requests Number of requests.\n",
+            "# TYPE requests counter\n",
+            "requests_total{method=\"GET\",path=\"/a\\\"b\"} 3\n",
+            "# HELP depth Queue depth.\n",
+            "# TYPE depth gauge\n",
+            "depth 2\n",
+            "depth_max 5\n",
+            "# HELP latency Latency.\n",
+            "# TYPE latency histogram\n",
+            "latency_sum 1.5\n",
+            "latency_count 2\n",
+            "latency_bucket{le=\"1.0\"} 1\n",
+            "latency_bucket{le=\"+Inf\"} 2\n",
+            "latency_native 3;0.001;0;1:1,2:1;;\n",
+        );
+
+        let families = parse(text).unwrap();
+        let names: Vec<_> = families.iter().map(|f| f.name.as_str()).collect();
+
+        assert_eq!(names, ["requests_total", "depth", "depth_max", "latency"]);
+        assert_eq!(
+            families[0].metrics[0].labels,
+            [
+                ("method".to_string(), "GET".to_string()),
+                ("path".to_string(), "/a\"b".to_string())
+            ]
+        );
+        assert_eq!(families[2].kind, Kind::Gauge);
+
+        let Value::Histogram(histogram) = &families[3].metrics[0].value else {
+            panic!("expected histogram");
+        };
+
+        assert!(histogram.is_native);
+        assert_eq!(histogram.data.count, 2);
+        assert_eq!(histogram.data.schema, 3);
+        assert_eq!(histogram.data.positive, [(1, 1), (2, 1)]);
+        assert_eq!(histogram.data.classic, [(1.0, 1), (f64::INFINITY, 2)]);
+    }
+}
@@ -0,0 +1,679 @@
+use super::scrape_windows::{self, RangeWindows};
+use super::Family;
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::io::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A gauge that, in addition to its current value, tracks the minimum and maximum values it had
+/// since the last scrape.
+///
+/// Regular gauges only report the value they have at the moment of a scrape, so short spikes
+/// between scrapes, e.g. a queue that briefly fills up, are invisible on dashboards. A range gauge
+/// reports three series:
+///
+/// - `<name>` with the current value;
+/// - `<name>_min` with the minimum value since the last scrape;
+/// - `<name>_max` with the maximum value since the last scrape.
+///
+/// The minimum and maximum are reset to the current value on each scrape. If the metrics have
+/// several scrapers, e.g. a pair of Prometheus servers, enable
+/// [`MetricsSettings::range_gauge_windows`], so each of them gets the range since its own previous
+/// scrape.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, RangeGauge};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests being processed.
+///     pub fn requests_in_flight() -> RangeGauge;
+/// }
+///
+/// fn handle_request() {
+///     my_app_metrics::requests_in_flight().inc();
+///
+///     // Handle the request...
+///
+///     my_app_metrics::requests_in_flight().dec();
+/// }
+/// # }
+/// ```
+///
+/// [`MetricsSettings::range_gauge_windows`]: crate::telemetry::settings::MetricsSettings::range_gauge_windows
+#[derive(Clone, Debug, Default)]
+pub struct RangeGauge {
+    inner: Arc<RangeGaugeInner>,
+}
+
+#[derive(Debug, Default)]
+struct RangeGaugeInner {
+    current: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+    windows: RangeWindows<u64>,
+}
+
+impl RangeGauge {
+    /// Increases the gauge by 1, returning the previous value.
+    pub fn inc(&self) -> u64 {
+        self.inc_by(1)
+    }
+
+    /// Increases the gauge by `v`, returning the previous value.
+    pub fn inc_by(&self, v: u64) -> u64 {
+        let prev = self.inner.current.fetch_add(v, Ordering::Relaxed);
+
+        self.inner
+            .max
+            .fetch_max(prev.wrapping_add(v), Ordering::Relaxed);
+
+        prev
+    }
+
+    /// Decreases the gauge by 1, returning the previous value.
+    pub fn dec(&self) -> u64 {
+        self.dec_by(1)
+    }
+
+    /// Decreases the gauge by `v`, returning the previous value.
+    pub fn dec_by(&self, v: u64) -> u64 {
+        let prev = self.inner.current.fetch_sub(v, Ordering::Relaxed);
+
+        self.inner
+            .min
+            .fetch_min(prev.wrapping_sub(v), Ordering::Relaxed);
+
+        prev
+    }
+
+    /// Sets the gauge to `v`, returning the previous value.
+    pub fn set(&self, v: u64) -> u64 {
+        let prev = self.inner.current.swap(v, Ordering::Relaxed);
+
+        self.inner.min.fetch_min(v, Ordering::Relaxed);
+        self.inner.max.fetch_max(v, Ordering::Relaxed);
+
+        prev
+    }
+
+    /// Returns the current value of the gauge.
+    pub fn get(&self) -> u64 {
+        self.inner.current.load(Ordering::Relaxed)
+    }
+
+    /// Returns the minimum and maximum values of the gauge since the last scrape.
+    pub fn range(&self) -> (u64, u64) {
+        (
+            self.inner.min.load(Ordering::Relaxed),
+            self.inner.max.load(Ordering::Relaxed),
+        )
+    }
+
+    fn collect(&self) -> (u64, u64, u64) {
+        let current = self.get();
+
+        if scrape_windows::is_peek() {
+            let (min, max) = self.range();
+
+            return (current, min.min(current), max.max(current));
+        }
+
+        // NOTE: the value can change between the loads and the resets, but this only affects
+        // the reported range in the same way as if the change happened right after the scrape.
+        let min = self.inner.min.swap(current, Ordering::Relaxed);
+        let max = self.inner.max.swap(current, Ordering::Relaxed);
+
+        let (min, max) = self.inner.windows.range(min.min(current), max.max(current));
+
+        (current, min, max)
+    }
+}
+
+impl TypedMetric for RangeGauge {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for RangeGauge {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let (current, min, max) = self.collect();
+
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(current)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("min")?
+            .no_bucket()?
+            .encode_value(min)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("max")?
+            .no_bucket()?
+            .encode_value(max)?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// A [`Family`] of [`RangeGauge`]s, one per label set.
+///
+/// Each label set tracks its own minimum and maximum, that are reset independently on each
+/// scrape. Metrics with labels declared with the [`metrics`] macro use this family for
+/// [`RangeGauge`]:
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, RangeGauge};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests being processed, by the endpoint.
+///     pub fn requests_in_flight(endpoint: &'static str) -> RangeGauge;
+/// }
+///
+/// fn handle_request(endpoint: &'static str) {
+///     my_app_metrics::requests_in_flight(endpoint).inc();
+///
+///     // Handle the request...
+///
+///     my_app_metrics::requests_in_flight(endpoint).dec();
+/// }
+/// # }
+/// ```
+///
+/// [`metrics`]: crate::telemetry::metrics::metrics
+pub type RangeGaugeFamily<L> = Family<L, RangeGauge>;
+
+/// A signed variant of [`RangeGauge`], for values that can be negative, e.g. clock skews or
+/// deltas.
+///
+/// Reports the same `<name>`, `<name>_min` and `<name>_max` series as [`RangeGauge`].
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, I64RangeGauge};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Clock skew relative to the upstream, in milliseconds.
+///     pub fn clock_skew_ms() -> I64RangeGauge;
+/// }
+///
+/// fn on_upstream_response(skew_ms: i64) {
+///     my_app_metrics::clock_skew_ms().set(skew_ms);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct I64RangeGauge {
+    inner: Arc<I64RangeGaugeInner>,
+}
+
+#[derive(Debug, Default)]
+struct I64RangeGaugeInner {
+    current: AtomicI64,
+    min: AtomicI64,
+    max: AtomicI64,
+    windows: RangeWindows<i64>,
+}
+
+impl I64RangeGauge {
+    /// Increases the gauge by 1, returning the previous value.
+    pub fn inc(&self) -> i64 {
+        self.inc_by(1)
+    }
+
+    /// Increases the gauge by `v`, returning the previous value.
+    ///
+    /// `v` can be negative, in which case the gauge is decreased.
+    pub fn inc_by(&self, v: i64) -> i64 {
+        let prev = self.inner.current.fetch_add(v, Ordering::Relaxed);
+
+        self.update_range(prev.wrapping_add(v));
+
+        prev
+    }
+
+    /// Decreases the gauge by 1, returning the previous value.
+    pub fn dec(&self) -> i64 {
+        self.dec_by(1)
+    }
+
+    /// Decreases the gauge by `v`, returning the previous value.
+    ///
+    /// `v` can be negative, in which case the gauge is increased.
+    pub fn dec_by(&self, v: i64) -> i64 {
+        let prev = self.inner.current.fetch_sub(v, Ordering::Relaxed);
+
+        self.update_range(prev.wrapping_sub(v));
+
+        prev
+    }
+
+    /// Sets the gauge to `v`, returning the previous value.
+    pub fn set(&self, v: i64) -> i64 {
+        let prev = self.inner.current.swap(v, Ordering::Relaxed);
+
+        self.update_range(v);
+
+        prev
+    }
+
+    /// Returns the current value of the gauge.
+    pub fn get(&self) -> i64 {
+        self.inner.current.load(Ordering::Relaxed)
+    }
+
+    /// Returns the minimum and maximum values of the gauge since the last scrape.
+    pub fn range(&self) -> (i64, i64) {
+        (
+            self.inner.min.load(Ordering::Relaxed),
+            self.inner.max.load(Ordering::Relaxed),
+        )
+    }
+
+    fn update_range(&self, v: i64) {
+        self.inner.min.fetch_min(v, Ordering::Relaxed);
+        self.inner.max.fetch_max(v, Ordering::Relaxed);
+    }
+
+    fn collect(&self) -> (i64, i64, i64) {
+        let current = self.get();
+
+        if scrape_windows::is_peek() {
+            let (min, max) = self.range();
+
+            return (current, min.min(current), max.max(current));
+        }
+
+        // NOTE: see `RangeGauge::collect`.
+        let min = self.inner.min.swap(current, Ordering::Relaxed);
+        let max = self.inner.max.swap(current, Ordering::Relaxed);
+
+        let (min, max) = self.inner.windows.range(min.min(current), max.max(current));
+
+        (current, min, max)
+    }
+}
+
+// NOTE: `prometheus_client` only encodes unsigned integers.
+struct SignedValue(i64);
+
+impl Encode for SignedValue {
+    fn encode(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+        write!(writer, "{}", self.0)
+    }
+}
+
+impl TypedMetric for I64RangeGauge {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for I64RangeGauge {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let (current, min, max) = self.collect();
+
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(SignedValue(current))?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("min")?
+            .no_bucket()?
+            .encode_value(SignedValue(min))?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("max")?
+            .no_bucket()?
+            .encode_value(SignedValue(max))?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// A floating-point variant of [`RangeGauge`], for fractional values, e.g. loads or ratios.
+///
+/// Reports the same `<name>`, `<name>_min` and `<name>_max` series as [`RangeGauge`].
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, F64RangeGauge};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Ratio of the busy worker threads.
+///     pub fn workers_busy_ratio() -> F64RangeGauge;
+/// }
+///
+/// fn on_worker_stats(busy: usize, total: usize) {
+///     my_app_metrics::workers_busy_ratio().set(busy as f64 / total as f64);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct F64RangeGauge {
+    inner: Arc<F64RangeGaugeInner>,
+}
+
+// NOTE: the values are stored as bits of `f64`, like in `prometheus_client`'s gauge. The
+// default of `0` bits is `0.0`.
+#[derive(Debug, Default)]
+struct F64RangeGaugeInner {
+    current: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+    windows: RangeWindows<f64>,
+}
+
+fn update_f64(atomic: &AtomicU64, f: impl Fn(f64) -> f64) -> f64 {
+    let prev = atomic
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some(f(f64::from_bits(bits)).to_bits())
+        })
+        // NOTE: the closure always returns `Some`.
+        .unwrap_or_else(|bits| bits);
+
+    f64::from_bits(prev)
+}
+
+impl F64RangeGauge {
+    /// Increases the gauge by `v`, returning the previous value.
+    pub fn inc_by(&self, v: f64) -> f64 {
+        let prev = update_f64(&self.inner.current, |current| current + v);
+
+        self.update_range(prev + v);
+
+        prev
+    }
+
+    /// Decreases the gauge by `v`, returning the previous value.
+    pub fn dec_by(&self, v: f64) -> f64 {
+        let prev = update_f64(&self.inner.current, |current| current - v);
+
+        self.update_range(prev - v);
+
+        prev
+    }
+
+    /// Sets the gauge to `v`, returning the previous value.
+    pub fn set(&self, v: f64) -> f64 {
+        let prev = f64::from_bits(self.inner.current.swap(v.to_bits(), Ordering::Relaxed));
+
+        self.update_range(v);
+
+        prev
+    }
+
+    /// Returns the current value of the gauge.
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.inner.current.load(Ordering::Relaxed))
+    }
+
+    /// Returns the minimum and maximum values of the gauge since the last scrape.
+    pub fn range(&self) -> (f64, f64) {
+        (
+            f64::from_bits(self.inner.min.load(Ordering::Relaxed)),
+            f64::from_bits(self.inner.max.load(Ordering::Relaxed)),
+        )
+    }
+
+    fn update_range(&self, v: f64) {
+        update_f64(&self.inner.min, |min| min.min(v));
+        update_f64(&self.inner.max, |max| max.max(v));
+    }
+
+    fn collect(&self) -> (f64, f64, f64) {
+        let current = self.get();
+
+        if scrape_windows::is_peek() {
+            let (min, max) = self.range();
+
+            return (current, min.min(current), max.max(current));
+        }
+
+        // NOTE: see `RangeGauge::collect`.
+        let min = f64::from_bits(self.inner.min.swap(current.to_bits(), Ordering::Relaxed));
+        let max = f64::from_bits(self.inner.max.swap(current.to_bits(), Ordering::Relaxed));
+
+        let (min, max) = self.inner.windows.range(min.min(current), max.max(current));
+
+        (current, min, max)
+    }
+}
+
+impl TypedMetric for F64RangeGauge {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for F64RangeGauge {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let (current, min, max) = self.collect();
+
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(current)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("min")?
+            .no_bucket()?
+            .encode_value(min)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("max")?
+            .no_bucket()?
+            .encode_value(max)?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::scrape_windows;
+    use super::*;
+    use crate::telemetry::metrics::with_scraper;
+    use crate::telemetry::settings::MetricsSettings;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    fn encode_gauge(gauge: &(impl EncodeMetric + Clone + Send + Sync + 'static)) -> String {
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        registry.register("depth", "Queue depth", Box::new(gauge.clone()));
+        encode(&mut buffer, &registry).unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn tracks_range_between_scrapes() {
+        let gauge = RangeGauge::default();
+
+        gauge.inc_by(10);
+        gauge.dec_by(7);
+
+        assert_eq!(gauge.get(), 3);
+        assert_eq!(gauge.range(), (0, 10));
+
+        let encoded = encode_gauge(&gauge);
+
+        assert!(encoded.contains("depth 3\n"));
+        assert!(encoded.contains("depth_min 0\n"));
+        assert!(encoded.contains("depth_max 10\n"));
+
+        assert_eq!(gauge.range(), (3, 3));
+
+        gauge.set(1);
+
+        let encoded = encode_gauge(&gauge);
+
+        assert!(encoded.contains("depth 1\n"));
+        assert!(encoded.contains("depth_min 1\n"));
+        assert!(encoded.contains("depth_max 3\n"));
+    }
+
+    #[test]
+    fn peek_keeps_range() {
+        let gauge = RangeGauge::default();
+
+        gauge.inc_by(10);
+        gauge.dec_by(7);
+
+        let encoded = scrape_windows::peek(|| encode_gauge(&gauge));
+
+        assert!(encoded.contains("depth 3\n"));
+        assert!(encoded.contains("depth_min 0\n"));
+        assert!(encoded.contains("depth_max 10\n"));
+
+        assert_eq!(gauge.range(), (0, 10));
+    }
+
+    #[test]
+    fn tracks_range_per_label_set() {
+        #[derive(Clone, Eq, Hash, PartialEq, serde::Serialize)]
+        struct Labels {
+            queue: &'static str,
+        }
+
+        let family = RangeGaugeFamily::<Labels>::default();
+        let fast = Labels { queue: "fast" };
+        let slow = Labels { queue: "slow" };
+
+        family.get_or_create(&fast).inc_by(2);
+        family.get_or_create(&slow).inc_by(10);
+        family.get_or_create(&slow).dec_by(4);
+
+        let encoded = encode_gauge(&family);
+
+        assert!(encoded.contains("depth{queue=\"fast\"} 2\n"));
+        assert!(encoded.contains("depth_max{queue=\"fast\"} 2\n"));
+        assert!(encoded.contains("depth{queue=\"slow\"} 6\n"));
+        assert!(encoded.contains("depth_min{queue=\"slow\"} 0\n"));
+        assert!(encoded.contains("depth_max{queue=\"slow\"} 10\n"));
+
+        assert_eq!(family.get_or_create(&fast).range(), (2, 2));
+        assert_eq!(family.get_or_create(&slow).range(), (6, 6));
+    }
+
+    #[test]
+    fn tracks_range_per_scraper() {
+        let settings = MetricsSettings {
+            range_gauge_windows: 4,
+            ..Default::default()
+        };
+
+        let gauge = RangeGauge::default();
+
+        let scrape = |scraper| {
+            with_scraper(scraper, || {
+                scrape_windows::scrape(&settings, || encode_gauge(&gauge))
+            })
+        };
+
+        gauge.set(10);
+        gauge.set(2);
+
+        let encoded = scrape("gauge_test_a");
+
+        assert!(encoded.contains("depth_min 0\n"));
+        assert!(encoded.contains("depth_max 10\n"));
+
+        gauge.set(7);
+        gauge.set(3);
+
+        // NOTE: the first scrape of a scraper covers all the retained windows.
+        let encoded = scrape("gauge_test_b");
+
+        assert!(encoded.contains("depth_min 0\n"));
+        assert!(encoded.contains("depth_max 10\n"));
+
+        let encoded = scrape("gauge_test_a");
+
+        assert!(encoded.contains("depth_min 2\n"));
+        assert!(encoded.contains("depth_max 7\n"));
+
+        let encoded = scrape("gauge_test_b");
+
+        assert!(encoded.contains("depth_min 3\n"));
+        assert!(encoded.contains("depth_max 3\n"));
+    }
+
+    #[test]
+    fn tracks_signed_range_between_scrapes() {
+        let gauge = I64RangeGauge::default();
+
+        gauge.dec_by(5);
+        gauge.inc_by(8);
+
+        assert_eq!(gauge.get(), 3);
+        assert_eq!(gauge.range(), (-5, 3));
+
+        let encoded = encode_gauge(&gauge);
+
+        assert!(encoded.contains("depth 3\n"));
+        assert!(encoded.contains("depth_min -5\n"));
+        assert!(encoded.contains("depth_max 3\n"));
+
+        assert_eq!(gauge.range(), (3, 3));
+
+        gauge.set(-2);
+
+        let encoded = encode_gauge(&gauge);
+
+        assert!(encoded.contains("depth -2\n"));
+        assert!(encoded.contains("depth_min -2\n"));
+        assert!(encoded.contains("depth_max 3\n"));
+    }
+
+    #[test]
+    fn tracks_fractional_range_between_scrapes() {
+        let gauge = F64RangeGauge::default();
+
+        gauge.inc_by(0.75);
+        gauge.dec_by(1.0);
+
+        assert_eq!(gauge.get(), -0.25);
+        assert_eq!(gauge.range(), (-0.25, 0.75));
+
+        let encoded = encode_gauge(&gauge);
+
+        assert!(encoded.contains("depth -0.25\n"));
+        assert!(encoded.contains("depth_min -0.25\n"));
+        assert!(encoded.contains("depth_max 0.75\n"));
+
+        assert_eq!(gauge.range(), (-0.25, -0.25));
+
+        gauge.set(0.5);
+
+        let encoded = encode_gauge(&gauge);
+
+        assert!(encoded.contains("depth 0.5\n"));
+        assert!(encoded.contains("depth_min -0.25\n"));
+        assert!(encoded.contains("depth_max 0.5\n"));
+    }
+}
@@ -0,0 +1,349 @@
+use super::exposition::parse_sample;
+use super::{HistogramBuilder, MetricConstructor};
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::collections::HashSet;
+use std::io;
+use std::sync::Arc;
+
+/// A histogram of the current state, e.g. of the ages of the requests in flight or of the sizes
+/// of the queued items.
+///
+/// Unlike a [`Histogram`], whose buckets count all the values observed since the start of
+/// the service, the buckets of a gauge histogram can go down: the values can be [removed] once
+/// they no longer describe the current state, or the whole distribution can be [replaced], e.g.
+/// right before the metrics are collected. The following series are reported:
+///
+/// - `<name>_bucket{le="<upper bound>"}` with the number of the current values less than or equal
+///   to the upper bound;
+/// - `<name>_gsum` with the sum of the current values;
+/// - `<name>_gcount` with the number of the current values.
+///
+/// The family is declared with the [OpenMetrics] `gaugehistogram` type if
+/// [`MetricsSettings::exemplars`] is enabled, and as `GAUGE_HISTOGRAM` in the protobuf format.
+/// As the Prometheus text format has no type for the gauge histograms, it's declared as
+/// a `histogram` with `_sum` and `_count` series there.
+///
+/// The histogram needs to be built with [`HistogramBuilder`].
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, GaugeHistogram, HistogramBuilder};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Sizes of the queued jobs.
+///     #[ctor = HistogramBuilder { buckets: &[1024.0, 65536.0, 1048576.0] }]
+///     pub fn queued_job_size() -> GaugeHistogram;
+/// }
+///
+/// fn enqueue(job: &[u8]) {
+///     my_app_metrics::queued_job_size().observe(job.len() as f64);
+/// }
+///
+/// fn dequeue(job: &[u8]) {
+///     my_app_metrics::queued_job_size().remove(job.len() as f64);
+/// }
+/// # }
+/// ```
+///
+/// [`Histogram`]: super::Histogram
+/// [removed]: GaugeHistogram::remove
+/// [replaced]: GaugeHistogram::set
+/// [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#gaugehistogram
+/// [`MetricsSettings::exemplars`]: crate::telemetry::settings::MetricsSettings::exemplars
+#[derive(Clone, Debug)]
+pub struct GaugeHistogram {
+    inner: Arc<GaugeHistogramInner>,
+}
+
+#[derive(Debug)]
+struct GaugeHistogramInner {
+    upper_bounds: Vec<f64>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    // NOTE: non-cumulative counts, the last one is the `+Inf` bucket.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl GaugeHistogram {
+    /// Creates a new gauge histogram with the given buckets.
+    pub fn new(buckets: impl Iterator<Item = f64>) -> Self {
+        let mut upper_bounds: Vec<_> = buckets.filter(|b| !b.is_nan()).collect();
+
+        upper_bounds.sort_by(f64::total_cmp);
+        upper_bounds.dedup();
+
+        let counts = vec![0; upper_bounds.len() + 1];
+
+        Self {
+            inner: Arc::new(GaugeHistogramInner {
+                upper_bounds,
+                state: Mutex::new(State { counts, sum: 0.0 }),
+            }),
+        }
+    }
+
+    /// Adds a value to the current state. `NaN` values are ignored.
+    pub fn observe(&self, v: f64) {
+        if let Some(bucket) = self.bucket(v) {
+            let mut state = self.inner.state.lock();
+
+            state.counts[bucket] += 1;
+            state.sum += v;
+        }
+    }
+
+    /// Removes a previously [observed] value from the current state.
+    ///
+    /// [observed]: GaugeHistogram::observe
+    pub fn remove(&self, v: f64) {
+        if let Some(bucket) = self.bucket(v) {
+            let mut state = self.inner.state.lock();
+
+            if state.counts[bucket] > 0 {
+                state.counts[bucket] -= 1;
+                state.sum -= v;
+            }
+        }
+    }
+
+    /// Replaces the current state with the given values.
+    pub fn set(&self, values: impl IntoIterator<Item = f64>) {
+        let mut counts = vec![0; self.inner.upper_bounds.len() + 1];
+        let mut sum = 0.0;
+
+        for v in values {
+            if let Some(bucket) = self.bucket(v) {
+                counts[bucket] += 1;
+                sum += v;
+            }
+        }
+
+        *self.inner.state.lock() = State { counts, sum };
+    }
+
+    /// Returns the sum of the current values.
+    pub fn sum(&self) -> f64 {
+        self.inner.state.lock().sum
+    }
+
+    /// Returns the number of the current values.
+    pub fn count(&self) -> u64 {
+        self.inner.state.lock().counts.iter().sum()
+    }
+
+    fn bucket(&self, v: f64) -> Option<usize> {
+        if v.is_nan() {
+            return None;
+        }
+
+        Some(self.inner.upper_bounds.partition_point(|b| *b < v))
+    }
+}
+
+impl MetricConstructor<GaugeHistogram> for HistogramBuilder {
+    fn new_metric(&self) -> GaugeHistogram {
+        GaugeHistogram::new(self.buckets.iter().cloned())
+    }
+}
+
+impl TypedMetric for GaugeHistogram {
+    // NOTE: `prometheus_client` doesn't support gauge histograms, the type is fixed up by
+    // `declare_types`.
+    const TYPE: MetricType = MetricType::Histogram;
+}
+
+impl EncodeMetric for GaugeHistogram {
+    fn encode(&self, mut encoder: Encoder) -> io::Result<()> {
+        let (counts, sum) = {
+            let state = self.inner.state.lock();
+
+            (state.counts.clone(), state.sum)
+        };
+
+        encoder
+            .encode_suffix("gsum")?
+            .no_bucket()?
+            .encode_value(sum)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("gcount")?
+            .no_bucket()?
+            .encode_value(counts.iter().sum::<u64>())?
+            .no_exemplar()?;
+
+        let upper_bounds = self.inner.upper_bounds.iter().copied();
+        let mut cumulative = 0;
+
+        // NOTE: `f64::MAX` is encoded as `+Inf` by `prometheus_client`.
+        for (upper_bound, count) in upper_bounds.chain([f64::MAX]).zip(counts) {
+            cumulative += count;
+
+            encoder
+                .encode_suffix("bucket")?
+                .encode_bucket(upper_bound)?
+                .encode_value(cumulative)?
+                .no_exemplar()?;
+        }
+
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// Declares the families with `_gcount` samples with the `gaugehistogram` type in the text
+/// format.
+pub(super) fn declare_types(text: &str) -> String {
+    if !text.contains("_gcount") {
+        return text.to_string();
+    }
+
+    let families: HashSet<_> = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| parse_sample(line)?.0.strip_suffix("_gcount"))
+        .collect();
+
+    let mut out = String::with_capacity(text.len() + families.len() * 5);
+
+    for line in text.split_inclusive('\n') {
+        let family = line
+            .strip_prefix("# TYPE ")
+            .and_then(|rest| rest.trim_end().strip_suffix(" histogram"));
+
+        match family {
+            Some(family) if families.contains(family) => {
+                out.push_str("# TYPE ");
+                out.push_str(family);
+                out.push_str(" gaugehistogram\n");
+            }
+            _ => out.push_str(line),
+        }
+    }
+
+    out
+}
+
+/// Declares the gauge histograms as histograms with `_sum` and `_count` samples, as
+/// the Prometheus text format doesn't support gauge histograms.
+pub(super) fn to_prometheus_text(text: &str) -> String {
+    if !text.contains(" gaugehistogram") {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut family: Option<&str> = None;
+
+    for line in text.split_inclusive('\n') {
+        if let Some(rest) = line.strip_prefix("# TYPE ") {
+            family = rest.trim_end().strip_suffix(" gaugehistogram");
+
+            match family {
+                Some(family) => {
+                    out.push_str("# TYPE ");
+                    out.push_str(family);
+                    out.push_str(" histogram\n");
+                }
+                None => out.push_str(line),
+            }
+
+            continue;
+        }
+
+        let renamed = family.and_then(|family| {
+            let rest = line.strip_prefix(family)?;
+
+            [("_gsum", "_sum"), ("_gcount", "_count")]
+                .into_iter()
+                .find_map(|(from, to)| {
+                    let rest = rest.strip_prefix(from)?;
+
+                    rest.starts_with(['{', ' ']).then_some((to, rest))
+                })
+        });
+
+        match (family, renamed) {
+            (Some(family), Some((suffix, rest))) => {
+                out.push_str(family);
+                out.push_str(suffix);
+                out.push_str(rest);
+            }
+            _ => out.push_str(line),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::registry::Registry;
+
+    fn encode(histogram: &GaugeHistogram) -> String {
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        registry.register("queue", "Queue", Box::new(histogram.clone()));
+        prometheus_client::encoding::text::encode(&mut buffer, &registry).unwrap();
+
+        declare_types(&String::from_utf8(buffer).unwrap())
+    }
+
+    #[test]
+    fn reports_current_state() {
+        let histogram = GaugeHistogram::new([1.0, 10.0].into_iter());
+
+        histogram.observe(0.5);
+        histogram.observe(5.0);
+        histogram.observe(50.0);
+        histogram.remove(0.5);
+        histogram.remove(1.0);
+
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.sum(), 55.0);
+
+        let text = encode(&histogram);
+
+        assert_eq!(
+            text,
+            concat!(
+                "# HELP queue Queue.\n",
+                "# TYPE queue gaugehistogram\n",
+                "queue_gsum 55.0\n",
+                "queue_gcount 2\n",
+                "queue_bucket{le=\"1.0\"} 0\n",
+                "queue_bucket{le=\"10.0\"} 1\n",
+                "queue_bucket{le=\"+Inf\"} 2\n",
+                "# EOF\n",
+            )
+        );
+
+        histogram.set([1.0, 2.0]);
+
+        assert_eq!(
+            to_prometheus_text(&encode(&histogram)),
+            concat!(
+                "# HELP queue Queue.\n",
+                "# TYPE queue histogram\n",
+                "queue_sum 3.0\n",
+                "queue_count 2\n",
+                "queue_bucket{le=\"1.0\"} 1\n",
+                "queue_bucket{le=\"10.0\"} 2\n",
+                "queue_bucket{le=\"+Inf\"} 2\n",
+                "# EOF\n",
+            )
+        );
+    }
+}
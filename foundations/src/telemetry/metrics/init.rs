@@ -1,5 +1,7 @@
-use super::internal::{BuildInfo, Registries, RuntimeInfo};
-use super::report_info;
+use super::internal::{
+    set_histogram_buckets, set_optional_flags, BuildInfo, Registries, RuntimeInfo,
+};
+use super::{limited_family, report_info, runtime};
 use crate::telemetry::settings::MetricsSettings;
 use crate::ServiceInfo;
 
@@ -9,9 +11,30 @@ use crate::ServiceInfo;
 /// by the `metrics` proc macro attribute.
 pub(crate) fn init(service_info: &ServiceInfo, settings: &MetricsSettings) {
     Registries::init(service_info, settings);
+    limited_family::set_max_label_sets(settings.max_label_sets);
+    set_histogram_buckets(&settings.histogram_buckets);
+    set_optional_flags(&settings.optional_flags);
+
+    #[cfg(any(feature = "logging", feature = "tracing"))]
+    crate::telemetry::scope::register_collector();
+
+    if settings.runtime_metrics {
+        runtime::register_collectors(Registries::get());
+
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            runtime::register_runtime("main", &handle);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if settings.process_metrics {
+        super::process::register(Registries::get());
+    }
 
     report_info(BuildInfo {
         version: service_info.version,
+        git_sha: service_info.git_sha,
+        rustc_version: env!("FOUNDATIONS_RUSTC_VERSION"),
     });
 
     report_info(RuntimeInfo {
@@ -1,56 +1,140 @@
-use super::{info_metric, InfoMetric};
-use crate::telemetry::settings::{MetricsSettings, ServiceNameFormat};
+use super::{info_metric, HistogramBuilder, InfoMetric};
+use crate::telemetry::settings::{HistogramBuckets, MetricsSettings, ServiceNameFormat};
 use crate::{Result, ServiceInfo};
 use once_cell::sync::OnceCell;
-use parking_lot::{RwLock, RwLockWriteGuard};
+use parking_lot::{MappedRwLockWriteGuard, RwLock, RwLockWriteGuard};
 use prometheus_client::encoding::text::{encode, EncodeMetric};
 use prometheus_client::registry::Registry;
 use prometools::serde::InfoGauge;
 use std::any::TypeId;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::DerefMut;
 
 static REGISTRIES: OnceCell<Registries> = OnceCell::new();
 
+static HISTOGRAM_BUCKETS: OnceCell<HashMap<String, &'static [f64]>> = OnceCell::new();
+
+static OPTIONAL_FLAGS: OnceCell<HashSet<String>> = OnceCell::new();
+
 #[doc(hidden)]
 pub struct Registries {
     main: RwLock<Registry>,
     opt: RwLock<Registry>,
+    // NOTE: registry of the metrics reported without the service prefix and labels.
+    pub(super) unprefixed: RwLock<Registry>,
     pub(super) info: RwLock<HashMap<TypeId, Box<dyn ErasedInfoMetric>>>,
+    // NOTE: registries of the services hosted in the process alongside the main one, keyed
+    // by the service name.
+    service_main: RwLock<BTreeMap<String, Registry>>,
+    service_opt: RwLock<BTreeMap<String, Registry>>,
+    // NOTE: registries reported on their own endpoints, keyed by the registry name.
+    named_main: RwLock<BTreeMap<String, Registry>>,
+    named_opt: RwLock<BTreeMap<String, Registry>>,
+    service_name_in_metrics: String,
+    service_name_format: ServiceNameFormat,
     extra_label: Option<(String, String)>,
 }
 
 impl Registries {
     pub(super) fn init(service_info: &ServiceInfo, settings: &MetricsSettings) {
-        let extra_label = match &settings.service_name_format {
+        REGISTRIES.get_or_init(|| {
+            Registries::new(&service_info.name_in_metrics, &settings.service_name_format)
+        });
+    }
+
+    pub(super) fn new(
+        service_name_in_metrics: &str,
+        service_name_format: &ServiceNameFormat,
+    ) -> Self {
+        let extra_label = match service_name_format {
             ServiceNameFormat::MetricPrefix => None,
             ServiceNameFormat::LabelWithName(name) => {
-                Some((name.clone(), service_info.name_in_metrics.clone()))
+                Some((name.clone(), service_name_in_metrics.to_string()))
             }
         };
 
-        REGISTRIES.get_or_init(|| Registries {
-            main: new_registry(&service_info.name_in_metrics, &settings.service_name_format),
-            opt: new_registry(&service_info.name_in_metrics, &settings.service_name_format),
+        Registries {
+            main: new_registry(service_name_in_metrics, service_name_format),
+            opt: new_registry(service_name_in_metrics, service_name_format),
+            unprefixed: Default::default(),
             info: Default::default(),
+            service_main: Default::default(),
+            service_opt: Default::default(),
+            named_main: Default::default(),
+            named_opt: Default::default(),
+            service_name_in_metrics: service_name_in_metrics.to_string(),
+            service_name_format: service_name_format.clone(),
             extra_label,
-        });
+        }
     }
 
     pub(super) fn collect(buffer: &mut Vec<u8>, collect_optional: bool) -> Result<()> {
-        let registries = Self::get();
+        Self::get().encode(buffer, collect_optional)
+    }
+
+    pub(super) fn encode(&self, buffer: &mut Vec<u8>, collect_optional: bool) -> Result<()> {
+        self.collect_info_metrics(buffer)?;
+
+        encode_registry(buffer, &self.unprefixed.read())?;
+
+        encode_registry(buffer, &self.main.read())?;
+
+        if collect_optional {
+            encode_registry(buffer, &self.opt.read())?;
+        }
+
+        for registry in self.service_main.read().values() {
+            encode_registry(buffer, registry)?;
+        }
+
+        if collect_optional {
+            for registry in self.service_opt.read().values() {
+                encode_registry(buffer, registry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn collect_named(
+        buffer: &mut Vec<u8>,
+        name: &str,
+        collect_optional: bool,
+    ) -> Result<()> {
+        Self::get().encode_named(buffer, name, collect_optional)
+    }
 
-        registries.collect_info_metrics(buffer)?;
+    pub(super) fn encode_named(
+        &self,
+        buffer: &mut Vec<u8>,
+        name: &str,
+        collect_optional: bool,
+    ) -> Result<()> {
+        let main = self.named_main.read();
+        let opt = self.named_opt.read();
 
-        encode_registry(buffer, &registries.main.read())?;
+        if !main.contains_key(name) && !opt.contains_key(name) {
+            return Err(format!("metrics registry `{name}` is not registered").into());
+        }
+
+        if let Some(registry) = main.get(name) {
+            encode_registry(buffer, registry)?;
+        }
 
         if collect_optional {
-            encode_registry(buffer, &registries.opt.read())?;
+            if let Some(registry) = opt.get(name) {
+                encode_registry(buffer, registry)?;
+            }
         }
 
         Ok(())
     }
 
+    #[cfg(feature = "telemetry-server")]
+    pub(super) fn has_named(&self, name: &str) -> bool {
+        self.named_main.read().contains_key(name) || self.named_opt.read().contains_key(name)
+    }
+
     fn collect_info_metrics(&self, buffer: &mut Vec<u8>) -> Result<()> {
         let info_registry = self.info.read();
         let mut registry = Registry::default();
@@ -64,36 +148,180 @@ impl Registries {
         encode_registry(buffer, &registry)
     }
 
-    pub fn get_main_subsystem(subsystem: &str) -> impl DerefMut<Target = Registry> + '_ {
-        let registries = Self::get();
-
+    pub fn main_subsystem<'a>(&'a self, subsystem: &str) -> impl DerefMut<Target = Registry> + 'a {
         get_subsystem(
-            Self::get().main.write(),
+            RwLockWriteGuard::map(self.main.write(), |registry| registry),
             subsystem,
-            registries.extra_label.clone(),
+            self.extra_label.clone(),
         )
     }
 
-    pub fn get_opt_subsystem(subsystem: &str) -> impl DerefMut<Target = Registry> + '_ {
-        let registries = Self::get();
-
+    pub fn opt_subsystem<'a>(&'a self, subsystem: &str) -> impl DerefMut<Target = Registry> + 'a {
         get_subsystem(
-            Self::get().opt.write(),
+            RwLockWriteGuard::map(self.opt.write(), |registry| registry),
             subsystem,
-            registries.extra_label.clone(),
+            self.extra_label.clone(),
         )
     }
 
+    pub fn service_main_subsystem<'a>(
+        &'a self,
+        service: &str,
+        subsystem: &str,
+    ) -> impl DerefMut<Target = Registry> + 'a {
+        self.service_subsystem(&self.service_main, service, subsystem)
+    }
+
+    pub fn service_opt_subsystem<'a>(
+        &'a self,
+        service: &str,
+        subsystem: &str,
+    ) -> impl DerefMut<Target = Registry> + 'a {
+        self.service_subsystem(&self.service_opt, service, subsystem)
+    }
+
+    pub fn named_main_subsystem<'a>(
+        &'a self,
+        registry: &str,
+        subsystem: &str,
+    ) -> impl DerefMut<Target = Registry> + 'a {
+        self.named_subsystem(&self.named_main, registry, subsystem)
+    }
+
+    pub fn named_opt_subsystem<'a>(
+        &'a self,
+        registry: &str,
+        subsystem: &str,
+    ) -> impl DerefMut<Target = Registry> + 'a {
+        self.named_subsystem(&self.named_opt, registry, subsystem)
+    }
+
+    fn named_subsystem<'a>(
+        &'a self,
+        named_registries: &'a RwLock<BTreeMap<String, Registry>>,
+        registry: &str,
+        subsystem: &str,
+    ) -> impl DerefMut<Target = Registry> + 'a {
+        let registry = RwLockWriteGuard::map(named_registries.write(), |registries| {
+            registries.entry(registry.to_string()).or_insert_with(|| {
+                new_registry(&self.service_name_in_metrics, &self.service_name_format).into_inner()
+            })
+        });
+
+        get_subsystem(registry, subsystem, self.extra_label.clone())
+    }
+
+    fn service_subsystem<'a>(
+        &'a self,
+        service_registries: &'a RwLock<BTreeMap<String, Registry>>,
+        service: &str,
+        subsystem: &str,
+    ) -> impl DerefMut<Target = Registry> + 'a {
+        let extra_label = match &self.service_name_format {
+            ServiceNameFormat::MetricPrefix => None,
+            ServiceNameFormat::LabelWithName(name) => Some((name.clone(), service.to_string())),
+        };
+
+        let registry = RwLockWriteGuard::map(service_registries.write(), |registries| {
+            registries
+                .entry(service.to_string())
+                .or_insert_with(|| new_registry(service, &self.service_name_format).into_inner())
+        });
+
+        get_subsystem(registry, subsystem, extra_label)
+    }
+
     pub(super) fn get() -> &'static Registries {
-        REGISTRIES.get_or_init(|| Registries {
-            main: new_registry("undefined", &ServiceNameFormat::MetricPrefix),
-            opt: new_registry("undefined", &ServiceNameFormat::MetricPrefix),
-            info: Default::default(),
-            extra_label: None,
-        })
+        REGISTRIES.get_or_init(|| Registries::new("undefined", &ServiceNameFormat::MetricPrefix))
     }
 }
 
+/// Metrics of a module annotated with the `#[metrics]` macro.
+///
+/// The metrics are registered in the global registries on the first access. With the `testing`
+/// feature, the metrics accessed in the scope of a [test telemetry context] are instead registered
+/// in the registries of the context, a separate instance of the metrics per context, so the tests
+/// don't observe each other's metrics. Until a test telemetry context is entered for the first
+/// time, checking for it costs a single atomic load per access.
+///
+/// [test telemetry context]: crate::telemetry::TestTelemetryContext
+#[doc(hidden)]
+pub struct ModuleMetrics<T: 'static> {
+    global: OnceCell<T>,
+    init: fn(&Registries) -> T,
+}
+
+impl<T> ModuleMetrics<T>
+where
+    T: Send + Sync + 'static,
+{
+    pub const fn new(init: fn(&Registries) -> T) -> Self {
+        Self {
+            global: OnceCell::new(),
+            init,
+        }
+    }
+
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        #[cfg(feature = "testing")]
+        if let Some(test_metrics) = super::testing::current_test_metrics() {
+            return f(&test_metrics.module_metrics(self));
+        }
+
+        f(self.global.get_or_init(|| (self.init)(Registries::get())))
+    }
+
+    #[cfg(feature = "testing")]
+    pub(super) fn init(&self, registries: &Registries) -> T {
+        (self.init)(registries)
+    }
+}
+
+/// Returns `builder` with the buckets overridden by [`MetricsSettings::histogram_buckets`] for
+/// the metric, if any.
+///
+/// `metric` is the name of the metric without the service prefix, i.e. `<module>_<metric>`.
+#[doc(hidden)]
+pub fn histogram_builder(metric: &str, builder: HistogramBuilder) -> HistogramBuilder {
+    match HISTOGRAM_BUCKETS
+        .get()
+        .and_then(|buckets| buckets.get(metric))
+    {
+        Some(buckets) => HistogramBuilder { buckets },
+        None => builder,
+    }
+}
+
+pub(super) fn set_histogram_buckets(settings: &[HistogramBuckets]) {
+    HISTOGRAM_BUCKETS.get_or_init(|| {
+        settings
+            .iter()
+            .map(|HistogramBuckets { metric, buckets }| {
+                let mut buckets = buckets.clone();
+
+                buckets.sort_by(f64::total_cmp);
+                buckets.dedup();
+
+                // NOTE: the overrides are set once per process, so leaking them is bounded.
+                (metric.clone(), &*Vec::leak(buckets))
+            })
+            .collect()
+    });
+}
+
+/// Returns `true` if the optional metrics with the flag are enabled by
+/// [`MetricsSettings::optional_flags`].
+#[doc(hidden)]
+pub fn is_optional_flag_enabled(flag: &str) -> bool {
+    OPTIONAL_FLAGS
+        .get()
+        .is_some_and(|flags| flags.contains(flag))
+}
+
+pub(super) fn set_optional_flags(flags: &[String]) {
+    OPTIONAL_FLAGS.get_or_init(|| flags.iter().cloned().collect());
+}
+
 fn new_registry(
     service_name_in_metrics: &str,
     service_name_format: &ServiceNameFormat,
@@ -108,11 +336,11 @@ fn new_registry(
 }
 
 fn get_subsystem<'a>(
-    registry: RwLockWriteGuard<'a, Registry>,
+    registry: MappedRwLockWriteGuard<'a, Registry>,
     subsystem: &str,
     extra_label: Option<(String, String)>,
 ) -> impl DerefMut<Target = Registry> + 'a {
-    RwLockWriteGuard::map(registry, move |mut registry| {
+    MappedRwLockWriteGuard::map(registry, move |mut registry| {
         if let Some((name, value)) = extra_label {
             registry = registry.sub_registry_with_label((name.into(), value.into()));
         }
@@ -125,6 +353,8 @@ fn get_subsystem<'a>(
 #[info_metric(crate_path = "crate")]
 pub(super) struct BuildInfo {
     pub(super) version: &'static str,
+    pub(super) git_sha: &'static str,
+    pub(super) rustc_version: &'static str,
 }
 
 /// Information about the process runtime
@@ -166,3 +396,27 @@ pub(super) fn encode_registry(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_histogram_buckets() {
+        set_histogram_buckets(&[HistogramBuckets {
+            metric: "internal_test_latency".into(),
+            buckets: vec![0.5, 0.1, 1.0, 0.5],
+        }]);
+
+        let code_buckets = HistogramBuilder { buckets: &[1.0] };
+
+        assert_eq!(
+            histogram_builder("internal_test_latency", code_buckets.clone()).buckets,
+            &[0.1, 0.5, 1.0]
+        );
+        assert_eq!(
+            histogram_builder("internal_test_size", code_buckets).buckets,
+            &[1.0]
+        );
+    }
+}
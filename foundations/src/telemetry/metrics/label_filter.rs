@@ -0,0 +1,189 @@
+use super::created::encode_labels;
+use super::exposition::{parse_sample, parse_value, Labels};
+use crate::telemetry::settings::LabelFilter;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Removes the labels of the metric families matched by the filters from the text exposition.
+///
+/// The samples that end up with the same name and labels are merged by summing their values.
+pub(super) fn filter_labels(text: &str, filters: &[LabelFilter]) -> String {
+    let mut out = String::with_capacity(text.len());
+    // NOTE: the filter of the current family and its samples, in the order of their first
+    // occurrence.
+    let mut filter: Option<&LabelFilter> = None;
+    let mut samples: Vec<Sample> = vec![];
+    let mut index: HashMap<(String, Labels), usize> = HashMap::new();
+
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            flush(&mut out, &mut samples, &mut index);
+
+            if let Some(rest) = comment.strip_prefix("TYPE ") {
+                let family = rest.split(' ').next().unwrap_or_default();
+
+                filter = filters.iter().find(|filter| {
+                    filter.metric == family || filter.metric.strip_suffix("_total") == Some(family)
+                });
+            }
+
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        let (Some(filter), Some((name, mut labels, value))) = (filter, parse_sample(line)) else {
+            flush(&mut out, &mut samples, &mut index);
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+
+        labels.retain(|(label, _)| is_kept(filter, label));
+
+        let sum = parse_value(value).ok();
+        let key = (name.to_string(), labels);
+
+        match index.get(&key) {
+            Some(&i) => {
+                let sample = &mut samples[i];
+
+                // NOTE: the values that can't be summed, e.g. the payloads of the native
+                // histograms, are kept from the first sample.
+                sample.sum = sample.sum.zip(sum).map(|(a, b)| a + b);
+                sample.merged = true;
+            }
+            None => {
+                index.insert(key.clone(), samples.len());
+                samples.push(Sample {
+                    name: key.0,
+                    labels: key.1,
+                    value: value.to_string(),
+                    sum,
+                    merged: false,
+                });
+            }
+        }
+    }
+
+    flush(&mut out, &mut samples, &mut index);
+
+    out
+}
+
+fn flush(
+    out: &mut String,
+    samples: &mut Vec<Sample>,
+    index: &mut HashMap<(String, Labels), usize>,
+) {
+    for sample in samples.drain(..) {
+        let value = match sample.sum {
+            Some(sum) if sample.merged => format_value(sum),
+            _ => sample.value,
+        };
+
+        let _ = writeln!(
+            out,
+            "{}{} {value}",
+            sample.name,
+            encode_labels(&sample.labels)
+        );
+    }
+
+    index.clear();
+}
+
+struct Sample {
+    name: String,
+    labels: Labels,
+    value: String,
+    sum: Option<f64>,
+    merged: bool,
+}
+
+// NOTE: bucket and quantile labels identify samples of a series, so they're never removed.
+fn is_kept(filter: &LabelFilter, label: &str) -> bool {
+    if label == "le" || label == "quantile" {
+        return true;
+    }
+
+    let allowed = match &filter.allow {
+        Some(allow) => allow.iter().any(|allowed| allowed == label),
+        None => true,
+    };
+
+    allowed && !filter.deny.iter().any(|denied| denied == label)
+}
+
+pub(super) fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.into()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_labels_and_merges_series() {
+        let text = concat!(
+            "# HELP app_requests Number of requests.\n",
+            "# TYPE app_requests counter\n",
+            "app_requests_total{client_ip=\"10.0.0.1\",path=\"/a\"} 1\n",
+            "app_requests_total{client_ip=\"10.0.0.2\",path=\"/a\"} 2\n",
+            "app_requests_total{client_ip=\"10.0.0.1\",path=\"/b\"} 3\n",
+            "# HELP app_latency Latency.\n",
+            "# TYPE app_latency histogram\n",
+            "app_latency_bucket{client_ip=\"10.0.0.1\",path=\"/a\",le=\"1.0\"} 1\n",
+            "app_latency_bucket{client_ip=\"10.0.0.1\",path=\"/a\",le=\"+Inf\"} 1\n",
+            "app_latency_sum{client_ip=\"10.0.0.1\",path=\"/a\"} 0.5\n",
+            "app_latency_count{client_ip=\"10.0.0.1\",path=\"/a\"} 1\n",
+            "app_latency_bucket{client_ip=\"10.0.0.2\",path=\"/b\",le=\"1.0\"} 0\n",
+            "app_latency_bucket{client_ip=\"10.0.0.2\",path=\"/b\",le=\"+Inf\"} 2\n",
+            "app_latency_sum{client_ip=\"10.0.0.2\",path=\"/b\"} 3.5\n",
+            "app_latency_count{client_ip=\"10.0.0.2\",path=\"/b\"} 2\n",
+            "# HELP app_connections Number of connections.\n",
+            "# TYPE app_connections gauge\n",
+            "app_connections{client_ip=\"10.0.0.1\"} 4\n",
+            "# EOF\n",
+        );
+
+        let filters = [
+            LabelFilter {
+                metric: "app_requests_total".into(),
+                allow: None,
+                deny: vec!["client_ip".into()],
+            },
+            LabelFilter {
+                metric: "app_latency".into(),
+                allow: Some(vec![]),
+                deny: vec![],
+            },
+        ];
+
+        assert_eq!(
+            filter_labels(text, &filters),
+            concat!(
+                "# HELP app_requests Number of requests.\n",
+                "# TYPE app_requests counter\n",
+                "app_requests_total{path=\"/a\"} 3.0\n",
+                "app_requests_total{path=\"/b\"} 3\n",
+                "# HELP app_latency Latency.\n",
+                "# TYPE app_latency histogram\n",
+                "app_latency_bucket{le=\"1.0\"} 1.0\n",
+                "app_latency_bucket{le=\"+Inf\"} 3.0\n",
+                "app_latency_sum 4.0\n",
+                "app_latency_count 3.0\n",
+                "# HELP app_connections Number of connections.\n",
+                "# TYPE app_connections gauge\n",
+                "app_connections{client_ip=\"10.0.0.1\"} 4\n",
+                "# EOF\n",
+            )
+        );
+    }
+}
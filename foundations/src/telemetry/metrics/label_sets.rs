@@ -0,0 +1,177 @@
+use super::exposition::{self, Labels, Value};
+use crate::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static TRACKER: Lazy<Mutex<LabelSetTracker>> = Lazy::new(Default::default);
+
+/// The time of the last update of a label set of a metric, returned by
+/// [`collect_label_set_updates`].
+///
+/// [`collect_label_set_updates`]: super::collect_label_set_updates
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct LabelSetUpdate {
+    /// Labels of the series.
+    pub labels: BTreeMap<String, String>,
+
+    /// Unix timestamp in seconds of the last update of the series.
+    ///
+    /// Note that updates are detected by comparing values of the series between collections of
+    /// the metrics, so the timestamp is precise up to the scrape interval. For the series that
+    /// haven't changed since the tracking was enabled, it's the time the series was first
+    /// collected.
+    pub last_update: u64,
+
+    /// Number of seconds elapsed since the last update of the series.
+    pub secs_since_update: u64,
+}
+
+#[derive(Default)]
+struct LabelSetTracker {
+    families: BTreeMap<String, BTreeMap<Labels, Series>>,
+}
+
+struct Series {
+    fingerprint: u64,
+    last_update: SystemTime,
+}
+
+impl LabelSetTracker {
+    fn observe(&mut self, text: &str, now: SystemTime) -> Result<()> {
+        let mut families = BTreeMap::new();
+
+        for family in exposition::parse(text)? {
+            let mut prev = self.families.remove(&family.name).unwrap_or_default();
+            let series = families.entry(family.name).or_insert_with(BTreeMap::new);
+
+            for metric in family.metrics {
+                let fingerprint = fingerprint(&metric.value);
+
+                let last_update = match prev.remove(&metric.labels) {
+                    Some(prev) if prev.fingerprint == fingerprint => prev.last_update,
+                    _ => now,
+                };
+
+                series.insert(
+                    metric.labels,
+                    Series {
+                        fingerprint,
+                        last_update,
+                    },
+                );
+            }
+        }
+
+        // NOTE: the series that are not reported anymore are dropped.
+        self.families = families;
+
+        Ok(())
+    }
+
+    fn report(&self, now: SystemTime) -> BTreeMap<String, Vec<LabelSetUpdate>> {
+        let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        self.families
+            .iter()
+            .map(|(name, series)| {
+                let updates = series
+                    .iter()
+                    .map(|(labels, series)| LabelSetUpdate {
+                        labels: labels.iter().cloned().collect(),
+                        last_update: secs(series.last_update),
+                        secs_since_update: now
+                            .duration_since(series.last_update)
+                            .unwrap_or(Duration::ZERO)
+                            .as_secs(),
+                    })
+                    .collect();
+
+                (name.clone(), updates)
+            })
+            .collect()
+    }
+}
+
+fn fingerprint(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    match value {
+        Value::Scalar(v) => v.to_bits().hash(&mut hasher),
+        Value::Histogram(histogram) => {
+            histogram.data.count.hash(&mut hasher);
+            histogram.data.sum.to_bits().hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+/// Records the updates of the label sets in the collected metrics.
+pub(super) fn observe(text: &str) -> Result<()> {
+    TRACKER.lock().observe(text, SystemTime::now())
+}
+
+pub(super) fn report() -> BTreeMap<String, Vec<LabelSetUpdate>> {
+    TRACKER.lock().report(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_last_update_of_label_sets() {
+        let mut tracker = LabelSetTracker::default();
+        let t0 = UNIX_EPOCH + Duration::from_secs(100);
+        let t1 = t0 + Duration::from_secs(15);
+
+        tracker
+            .observe(
+                concat!(
+                    "# HELP requests Number of requests.\n",
+                    "# TYPE requests counter\n",
+                    "requests_total{path=\"/a\"} 1\n",
+                    "requests_total{path=\"/b\"} 1\n",
+                    "requests_total{path=\"/c\"} 1\n",
+                ),
+                t0,
+            )
+            .unwrap();
+
+        tracker
+            .observe(
+                concat!(
+                    "# HELP requests Number of requests.\n",
+                    "# TYPE requests counter\n",
+                    "requests_total{path=\"/a\"} 1\n",
+                    "requests_total{path=\"/b\"} 2\n",
+                ),
+                t1,
+            )
+            .unwrap();
+
+        let report = tracker.report(t1 + Duration::from_secs(5));
+        let labels = |path: &str| BTreeMap::from([("path".to_string(), path.to_string())]);
+
+        assert_eq!(
+            report["requests_total"],
+            [
+                LabelSetUpdate {
+                    labels: labels("/a"),
+                    last_update: 100,
+                    secs_since_update: 20,
+                },
+                LabelSetUpdate {
+                    labels: labels("/b"),
+                    last_update: 115,
+                    secs_since_update: 5,
+                },
+            ]
+        );
+    }
+}
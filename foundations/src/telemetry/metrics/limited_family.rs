@@ -0,0 +1,261 @@
+use super::{Counter, Family};
+use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::family::MetricConstructor;
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// NOTE: `usize::MAX` stands for no limit, so the label sets are not tracked by default.
+static MAX_LABEL_SETS: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_metrics {
+    /// Number of accesses to the metrics with new label sets that were reported in the overflow
+    /// label set, because the metrics reached the maximum number of label sets.
+    pub fn cardinality_overflow_total() -> Counter;
+}
+
+/// Sets the maximum number of label sets of each [`LimitedFamily`].
+pub(super) fn set_max_label_sets(max_label_sets: Option<usize>) {
+    MAX_LABEL_SETS.store(max_label_sets.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// A [`Family`] with a limit on the number of label sets.
+///
+/// A label with unbounded values, e.g. the client IP address, makes the family grow until the
+/// service runs out of memory. Once the family reaches the limit specified by
+/// [`MetricsSettings::max_label_sets`], metrics with new label sets are collapsed into a single
+/// series labeled with `overflow="true"`, and the accesses are counted by the
+/// `<prefix>_foundations_metrics_cardinality_overflow_total` counter. The label sets created
+/// before the limit was reached keep being reported as usual, and the removed label sets free up
+/// the room for the new ones.
+///
+/// Metrics with labels declared with the [`metrics`] macro use this family, unless
+/// the `#[ttl]` attribute is specified, in which case the [`ExpiringFamily`] with the same limit is
+/// used.
+///
+/// [`MetricsSettings::max_label_sets`]: crate::telemetry::settings::MetricsSettings::max_label_sets
+/// [`metrics`]: crate::telemetry::metrics::metrics
+/// [`ExpiringFamily`]: crate::telemetry::metrics::ExpiringFamily
+#[derive(Debug)]
+pub struct LimitedFamily<S, M, C = fn() -> M> {
+    family: Family<S, M, C>,
+    constructor: C,
+    max_label_sets: Option<usize>,
+    inner: Arc<LimitedFamilyInner<S, M>>,
+}
+
+#[derive(Debug)]
+struct LimitedFamilyInner<S, M> {
+    label_sets: RwLock<HashSet<S>>,
+    overflow: RwLock<Option<M>>,
+}
+
+impl<S, M, C> LimitedFamily<S, M, C>
+where
+    S: Clone + Eq + Hash,
+    C: Clone,
+{
+    /// Creates a family using a custom constructor to construct new metrics.
+    pub fn new_with_constructor(constructor: C) -> Self {
+        Self {
+            family: Family::new_with_constructor(constructor.clone()),
+            constructor,
+            max_label_sets: None,
+            inner: Arc::new(LimitedFamilyInner {
+                label_sets: Default::default(),
+                overflow: Default::default(),
+            }),
+        }
+    }
+
+    /// Overrides the maximum number of label sets specified in the settings for this family.
+    pub fn with_max_label_sets(mut self, max_label_sets: usize) -> Self {
+        self.max_label_sets = Some(max_label_sets);
+        self
+    }
+}
+
+impl<S, M> Default for LimitedFamily<S, M>
+where
+    S: Clone + Eq + Hash,
+    M: Default,
+{
+    fn default() -> Self {
+        Self::new_with_constructor(M::default)
+    }
+}
+
+impl<S, M, C> LimitedFamily<S, M, C>
+where
+    S: Clone + Eq + Hash,
+    C: MetricConstructor<M>,
+{
+    /// Accesses a metric with the given label set, creating it if one does not yet exist.
+    ///
+    /// Returns the overflow metric if the label set is new and the family has reached the limit.
+    /// See [`Family::get_or_create`] for the caveats of holding the returned reference.
+    pub fn get_or_create(&self, label_set: &S) -> MappedRwLockReadGuard<'_, M> {
+        self.get_or_create_with(label_set, || {})
+    }
+
+    /// Same as [`LimitedFamily::get_or_create`], but calls `on_admit` before accessing
+    /// the metric, unless the overflow metric is returned.
+    pub(super) fn get_or_create_with(
+        &self,
+        label_set: &S,
+        on_admit: impl FnOnce(),
+    ) -> MappedRwLockReadGuard<'_, M> {
+        if !self.admit(label_set) {
+            foundations_metrics::cardinality_overflow_total().inc();
+
+            return self.overflow();
+        }
+
+        on_admit();
+
+        self.family.get_or_create(label_set)
+    }
+
+    /// Removes a label set from the family.
+    ///
+    /// Returns a bool indicating if the label set was present or not.
+    pub fn remove(&self, label_set: &S) -> bool {
+        self.inner.label_sets.write().remove(label_set);
+
+        self.family.remove(label_set)
+    }
+
+    /// Clears all label sets from the family, including the overflow label set.
+    pub fn clear(&self) {
+        self.inner.label_sets.write().clear();
+        *self.inner.overflow.write() = None;
+
+        self.family.clear();
+    }
+
+    /// Returns whether the label set is within the limit, tracking it if it's new.
+    fn admit(&self, label_set: &S) -> bool {
+        let max_label_sets = self
+            .max_label_sets
+            .unwrap_or_else(|| MAX_LABEL_SETS.load(Ordering::Relaxed));
+
+        if max_label_sets == usize::MAX || self.inner.label_sets.read().contains(label_set) {
+            return true;
+        }
+
+        let mut label_sets = self.inner.label_sets.write();
+
+        // NOTE: the label set could have been added concurrently while the lock was released.
+        if label_sets.len() >= max_label_sets {
+            return label_sets.contains(label_set);
+        }
+
+        label_sets.insert(label_set.clone());
+
+        true
+    }
+
+    fn overflow(&self) -> MappedRwLockReadGuard<'_, M> {
+        if let Ok(overflow) = RwLockReadGuard::try_map(self.inner.overflow.read(), Option::as_ref) {
+            return overflow;
+        }
+
+        let mut overflow = self.inner.overflow.write();
+
+        overflow.get_or_insert_with(|| self.constructor.new_metric());
+
+        RwLockReadGuard::map(RwLockWriteGuard::downgrade(overflow), |overflow| {
+            overflow
+                .as_ref()
+                .expect("overflow metric should exist after creating it")
+        })
+    }
+}
+
+impl<S, M, C> EncodeMetric for LimitedFamily<S, M, C>
+where
+    S: Clone + Eq + Hash + Serialize,
+    M: EncodeMetric + TypedMetric,
+    C: MetricConstructor<M>,
+{
+    fn encode(&self, mut encoder: Encoder) -> io::Result<()> {
+        if let Some(overflow) = &*self.inner.overflow.read() {
+            overflow.encode(encoder.with_label_set(&OverflowLabel))?;
+        }
+
+        self.family.encode(encoder)
+    }
+
+    fn metric_type(&self) -> MetricType {
+        M::TYPE
+    }
+}
+
+impl<S, M, C> TypedMetric for LimitedFamily<S, M, C>
+where
+    M: TypedMetric,
+{
+    const TYPE: MetricType = <M as TypedMetric>::TYPE;
+}
+
+impl<S, M, C> Clone for LimitedFamily<S, M, C>
+where
+    C: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            family: self.family.clone(),
+            constructor: self.constructor.clone(),
+            max_label_sets: self.max_label_sets,
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+struct OverflowLabel;
+
+impl Encode for OverflowLabel {
+    fn encode(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(b"overflow=\"true\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[derive(Clone, Eq, Hash, PartialEq, Serialize)]
+    struct Labels {
+        client: &'static str,
+    }
+
+    #[test]
+    fn collapses_label_sets_above_limit() {
+        let family = LimitedFamily::<Labels, Counter>::default().with_max_label_sets(2);
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        registry.register("requests", "Requests", Box::new(family.clone()));
+
+        for client in ["a", "b", "c", "d", "a"] {
+            family.get_or_create(&Labels { client }).inc();
+        }
+
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains("requests{overflow=\"true\"} 2\n"));
+        assert!(encoded.contains("requests{client=\"a\"} 2\n"));
+        assert!(encoded.contains("requests{client=\"b\"} 1\n"));
+        assert!(!encoded.contains("client=\"c\""));
+    }
+}
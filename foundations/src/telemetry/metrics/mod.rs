@@ -4,10 +4,25 @@
 //! - Use [`metrics`] macro to define regular metrics.
 //! - Use [`report_info`] function to register service information metrics (metrics, whose value is
 //! persistent during the service lifetime, e.g. software version).
+//! The `build_info` metric with the version and the git commit SHA from the [`ServiceInfo`] and
+//! the version of the compiler is reported automatically.
+//! - Use [`register_collector`] function to register metrics whose samples are computed on
+//!   collection.
 //! - Use [`collect`] method to obtain metrics report programmatically.
+//! - Use [`collect_snapshot`] method to obtain typed values of the metrics, e.g. for assertions
+//!   in tests.
+//! - Use [`collect_protobuf`] method to obtain metrics report in the protobuf format that supports
+//!   [native histograms](NativeHistogram).
+//! - Use [`channel`] module to create bounded channels instrumented with metrics.
 //! - Use [telemetry server] to expose a metrics endpoint.
+//! - Enable the OTLP exporter in `MetricsSettings::otlp` to push metrics to an OpenTelemetry
+//!   collector (requires **otlp-metrics** feature). TLS is not supported, the collector must
+//!   have a plain-text `http://` endpoint.
+//! - Use `push` module to push metrics of short-lived jobs to a Prometheus Pushgateway
+//!   (requires **metrics-push** feature).
 //!
 //! [Prometheus]: https://prometheus.io/
+//! [`ServiceInfo`]: crate::ServiceInfo
 //! [telemetry server]: crate::telemetry::init_with_server
 
 use super::settings::MetricsSettings;
@@ -15,14 +30,70 @@ use crate::Result;
 use prometheus::{Encoder, TextEncoder};
 use serde::Serialize;
 use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+mod cardinality;
+mod collector;
+mod counter;
+mod created;
+mod delta;
+mod ewma;
+mod exemplar;
+mod expiring_family;
+mod exposition;
+mod gauge;
+mod gauge_histogram;
 pub(super) mod init;
+mod label_filter;
+mod label_sets;
+mod limited_family;
+mod namespace;
+mod native_histogram;
+mod ordering;
+#[cfg(feature = "otlp-metrics")]
+pub(crate) mod otlp;
+#[cfg(target_os = "linux")]
+mod process;
+mod protobuf;
+#[cfg(feature = "metrics-push")]
+pub mod push;
+mod rate;
+mod runtime;
+mod scrape_windows;
+mod snapshot;
+mod summary;
+mod top_k;
+mod units;
+
+#[cfg(feature = "testing")]
+pub(crate) mod testing;
+
+pub mod channel;
 
 #[doc(hidden)]
 pub mod internal;
 
 use internal::{ErasedInfoMetric, Registries};
 
+pub use self::cardinality::{FamilyCardinality, LabelCardinality};
+pub use self::collector::{register_collector, Collector, CollectorType, Samples};
+pub use self::counter::{AggregatedCounter, CompactCounter};
+pub use self::ewma::{EwmaGauge, EwmaGaugeBuilder};
+pub use self::exemplar::{ExemplarCounter, ExemplarHistogram};
+pub use self::expiring_family::ExpiringFamily;
+pub use self::gauge::{F64RangeGauge, I64RangeGauge, RangeGauge, RangeGaugeFamily};
+pub use self::gauge_histogram::GaugeHistogram;
+pub use self::label_sets::LabelSetUpdate;
+pub use self::limited_family::LimitedFamily;
+pub use self::native_histogram::{NativeHistogram, NativeHistogramBuilder};
+pub use self::rate::{RateGauge, RateGaugeBuilder};
+pub use self::runtime::register_runtime;
+pub use self::scrape_windows::with_scraper;
+pub use self::snapshot::{HistogramValue, MetricValue, MetricsSnapshot};
+pub use self::summary::{Summary, SummaryBuilder};
+pub use self::top_k::{TopK, TopKBuilder};
+pub use self::units::{ByteCounter, DurationHistogram};
 pub use prometheus_client::metrics::family::MetricConstructor;
 pub use prometheus_client::metrics::gauge::Gauge;
 pub use prometheus_client::metrics::histogram::Histogram;
@@ -30,18 +101,265 @@ pub use prometools::histogram::{HistogramTimer, TimeHistogram};
 pub use prometools::nonstandard::NonstandardUnsuffixedCounter as Counter;
 pub use prometools::serde::Family;
 
+static STALE: AtomicBool = AtomicBool::new(false);
+
 /// Collects all metrics in [Prometheus text format].
 ///
+/// The exemplars of [`ExemplarCounter`] and [`ExemplarHistogram`] are only reported if
+/// [`MetricsSettings::exemplars`] is enabled. The counters [reset on scrape] are reported as
+/// the deltas since the previous collection.
+///
+/// [reset on scrape]: MetricsSettings::reset_counters_on_scrape
 /// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
 pub fn collect(settings: &MetricsSettings) -> Result<String> {
+    collect_text(settings, None, true)
+}
+
+/// Collects all metrics in Prometheus text format for the internal use, e.g. for the diagnostics.
+///
+/// Unlike [`collect`], the collection is not a scrape: the counters reset on scrape are reported
+/// as totals and the ranges of the range gauges are not reset, so it doesn't affect the values
+/// reported to the scrapers of the metrics.
+pub(crate) fn collect_without_reset(settings: &MetricsSettings) -> Result<String> {
+    scrape_windows::peek(|| collect_text(settings, None, false))
+}
+
+/// Collects the metrics of the named registry in [Prometheus text format].
+///
+/// The metrics are defined in the registry with the `registry` argument of the [`metrics`] macro
+/// and are not reported by [`collect`]. The telemetry server reports them on
+/// the `/metrics/<registry>` endpoint, e.g. to expose the internal metrics separately from
+/// the public ones. Returns an error if no metrics were registered in the registry.
+///
+/// The metrics are post-processed according to the settings the same way as by [`collect`], except
+/// that the label set updates are not tracked and the counters are not reset on scrape.
+///
+/// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+pub fn collect_registry(registry: &str, settings: &MetricsSettings) -> Result<String> {
+    collect_text(settings, Some(registry), false)
+}
+
+/// Same as [`collect_registry`], but in [Prometheus protobuf format], see [`collect_protobuf`].
+///
+/// [Prometheus protobuf format]: https://prometheus.io/docs/instrumenting/exposition_formats/#protobuf-format
+pub fn collect_registry_protobuf(registry: &str, settings: &MetricsSettings) -> Result<Vec<u8>> {
+    let text = collect_with_native_histograms(settings, Some(registry))?;
+    let mut buffer = Vec::with_capacity(text.len());
+
+    protobuf::encode(&exposition::parse(&text)?, &mut buffer);
+
+    Ok(buffer)
+}
+
+/// Returns whether any metrics were registered in the named registry, see [`collect_registry`].
+#[cfg(feature = "telemetry-server")]
+pub(crate) fn has_registry(registry: &str) -> bool {
+    Registries::get().has_named(registry)
+}
+
+fn collect_text(
+    settings: &MetricsSettings,
+    registry: Option<&str>,
+    reset_counters: bool,
+) -> Result<String> {
+    if STALE.load(Ordering::Relaxed) {
+        return Ok("# EOF\n".into());
+    }
+
     let mut buffer = Vec::with_capacity(128);
 
-    Registries::collect(&mut buffer, settings.report_optional)?;
-    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    scrape_windows::scrape(settings, || match registry {
+        Some(registry) => {
+            Registries::collect_named(&mut buffer, registry, settings.report_optional)
+        }
+        None => Registries::collect(&mut buffer, settings.report_optional),
+    })?;
+
+    if registry.is_none() {
+        TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    }
 
     buffer.extend_from_slice(b"# EOF\n");
 
-    Ok(String::from_utf8(buffer)?)
+    let mut text = gauge_histogram::declare_types(&String::from_utf8(buffer)?);
+
+    if !settings.exemplars {
+        text = exemplar::strip_exemplars(&text);
+    }
+
+    if settings.metric_prefix.is_some() || !settings.const_labels.is_empty() {
+        text = namespace::apply_namespace(
+            &text,
+            settings.metric_prefix.as_deref().unwrap_or_default(),
+            &settings.const_labels,
+        );
+    }
+
+    if !settings.label_filters.is_empty() {
+        text = label_filter::filter_labels(&text, &settings.label_filters);
+    }
+
+    if settings.stable_ordering {
+        text = ordering::sort_exposition(&text);
+    }
+
+    if registry.is_none() && settings.track_label_set_updates {
+        label_sets::observe(&text)?;
+    }
+
+    if reset_counters && (settings.reset_counters_on_scrape || !settings.reset_on_scrape.is_empty())
+    {
+        text = delta::reset_counters(&text, settings);
+    }
+
+    if settings.created_timestamps {
+        text = created::add_created(&text);
+    }
+
+    // NOTE: only the OpenMetrics format, used with the exemplars, supports gauge histograms.
+    if !settings.exemplars {
+        text = gauge_histogram::to_prometheus_text(&text);
+    }
+
+    Ok(text)
+}
+
+/// Content type of the metrics collected with [`collect`] if [`MetricsSettings::exemplars`] is
+/// enabled.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Content type of the metrics collected with [`collect_protobuf`].
+pub const PROTOBUF_CONTENT_TYPE: &str =
+    "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+
+/// Collects all metrics in [Prometheus protobuf format].
+///
+/// Unlike the text format, the protobuf format includes the exponential buckets of
+/// [`NativeHistogram`]s.
+///
+/// [Prometheus protobuf format]: https://prometheus.io/docs/instrumenting/exposition_formats/#protobuf-format
+pub fn collect_protobuf(settings: &MetricsSettings) -> Result<Vec<u8>> {
+    let mut text = collect_with_native_histograms(settings, None)?;
+
+    if settings.track_label_set_updates {
+        label_sets::observe(&text)?;
+    }
+
+    if settings.reset_counters_on_scrape || !settings.reset_on_scrape.is_empty() {
+        text = delta::reset_counters(&text, settings);
+    }
+
+    let mut buffer = Vec::with_capacity(text.len());
+
+    protobuf::encode(&exposition::parse(&text)?, &mut buffer);
+
+    Ok(buffer)
+}
+
+/// Collects all metrics in the text format, including the `_native` samples of
+/// the [`NativeHistogram`]s, that are only understood by
+/// the parser of the metric families for the protobuf and OTLP encoding.
+fn collect_with_native_histograms(
+    settings: &MetricsSettings,
+    registry: Option<&str>,
+) -> Result<String> {
+    if STALE.load(Ordering::Relaxed) {
+        return Ok(String::new());
+    }
+
+    let mut text = Vec::with_capacity(128);
+
+    scrape_windows::scrape(settings, || {
+        native_histogram::with_native_encoding(|| match registry {
+            Some(registry) => {
+                Registries::collect_named(&mut text, registry, settings.report_optional)
+            }
+            None => Registries::collect(&mut text, settings.report_optional),
+        })
+    })?;
+
+    if registry.is_none() {
+        TextEncoder::new().encode(&prometheus::gather(), &mut text)?;
+    }
+
+    let mut text = gauge_histogram::declare_types(&String::from_utf8(text)?);
+
+    if settings.metric_prefix.is_some() || !settings.const_labels.is_empty() {
+        text = namespace::apply_namespace(
+            &text,
+            settings.metric_prefix.as_deref().unwrap_or_default(),
+            &settings.const_labels,
+        );
+    }
+
+    if !settings.label_filters.is_empty() {
+        text = label_filter::filter_labels(&text, &settings.label_filters);
+    }
+
+    if settings.stable_ordering {
+        text = ordering::sort_exposition(&text);
+    }
+
+    Ok(text)
+}
+
+/// Stops reporting all the metrics, so Prometheus marks their series as stale.
+///
+/// After the call, [`collect`] and [`collect_protobuf`] return no samples. Prometheus then writes
+/// [staleness markers] for all the series of the service on the next successful scrape, instead
+/// of showing their last values on the dashboards for a few minutes after the service is gone.
+///
+/// This is meant to be called on the graceful shutdown of the service, with the telemetry server
+/// still serving the scrapes for at least a scrape interval, see
+/// `TelemetryServerSettings::stale_metrics_period_ms`. The call can't be undone.
+///
+/// [staleness markers]: https://prometheus.io/docs/prometheus/latest/querying/basics/#staleness
+pub fn mark_stale() {
+    STALE.store(true, Ordering::Relaxed);
+}
+
+/// Collects all metrics and returns the label sets of each metric along with the time of their
+/// last update, keyed by the metric name.
+///
+/// Updates are detected by comparing the values of the series between collections of the metrics,
+/// so [`MetricsSettings::track_label_set_updates`] should be enabled for the metrics scrapes to
+/// be taken into account. Returns an error if the tracking is disabled.
+///
+/// The report helps diagnosing stale series, that are not updated anymore but are still reported.
+pub fn collect_label_set_updates(
+    settings: &MetricsSettings,
+) -> Result<BTreeMap<String, Vec<LabelSetUpdate>>> {
+    if !settings.track_label_set_updates {
+        return Err("label set update tracking is disabled in the metrics settings".into());
+    }
+
+    collect_without_reset(settings)?;
+
+    Ok(label_sets::report())
+}
+
+/// Collects all metrics and returns the number of series of each metric family, the number of
+/// distinct values of their labels and their estimated contribution to the size of the scrape,
+/// by number of series in descending order.
+///
+/// The report helps finding the families with too many series, e.g. because of a label with
+/// unbounded values, before they overload Prometheus.
+pub fn collect_cardinality(settings: &MetricsSettings) -> Result<Vec<FamilyCardinality>> {
+    let text = collect_without_reset(settings)?;
+
+    Ok(cardinality::analyze(&text))
+}
+
+/// Collects all metrics and returns their typed values keyed by the metric name and labels.
+///
+/// The metrics are post-processed according to the settings the same way as by [`collect`], except
+/// that the counters and the ranges of the range gauges are not reset on scrape. In tests, use
+/// `TestTelemetryContext::metrics_snapshot` to get the metrics of the test context.
+pub fn collect_snapshot(settings: &MetricsSettings) -> Result<MetricsSnapshot> {
+    let text = collect_without_reset(settings)?;
+
+    MetricsSnapshot::parse(&text)
 }
 
 /// A macro that allows to define Prometheus metrics.
@@ -54,13 +372,58 @@ pub fn collect(settings: &MetricsSettings) -> Result<String> {
 /// # Labels
 /// Arguments of the bodyless functions become labels for that metric.
 ///
+/// Labels of `Option<T>` type are omitted if the value is `None`. Alternatively, such labels can
+/// declare a default value with the `#[default = <expr>]` attribute, that is converted with
+/// [`Into`] to `T` and used if the value is `None`:
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{self, metrics, Counter};
+/// use foundations::telemetry::settings::MetricsSettings;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of handled requests
+///     pub fn handled_requests_total(
+///         colo: Option<&'static str>,
+///         #[default = "unknown"]
+///         method: Option<String>,
+///     ) -> Counter;
+/// }
+///
+/// # pub fn main() {
+/// my_app_metrics::handled_requests_total(None, None).inc();
+///
+/// let metrics = metrics::collect(&MetricsSettings::default()).unwrap();
+///
+/// assert!(metrics.contains(r#"my_app_metrics_handled_requests_total{method="unknown"} 1"#));
+/// # }
+/// # }
+/// # rustdoc_workaround::main();
+/// ```
+///
 /// The metric types must implement [`prometheus_client::metrics::MetricType`], they
 /// are reexported from this module for convenience:
 ///
 /// * [`Counter`]
+/// * [`CompactCounter`]
+/// * [`AggregatedCounter`]
+/// * [`ExemplarCounter`]
 /// * [`Gauge`]
+/// * [`RangeGauge`]
+/// * [`I64RangeGauge`]
+/// * [`F64RangeGauge`]
+/// * [`EwmaGauge`]
+/// * [`RateGauge`]
 /// * [`Histogram`]
 /// * [`TimeHistogram`]
+/// * [`DurationHistogram`]
+/// * [`ExemplarHistogram`]
+/// * [`ByteCounter`]
+/// * [`NativeHistogram`]
+/// * [`GaugeHistogram`]
+/// * [`TopK`]
+/// * [`Summary`]
 ///
 /// The metrics associated with the functions are automatically registered in a global
 /// registry, and they can be collected with the [`collect`] function.
@@ -84,6 +447,38 @@ pub fn collect(settings: &MetricsSettings) -> Result<String> {
 /// Can be used for heavy-weight metrics (e.g. with high cardinality) that don't need to be reported
 /// on a regular basis.
 ///
+/// `#[optional = "<flag>"]` additionally assigns a flag to the metric: the metric is reported as
+/// a regular one if the flag is listed in [`MetricsSettings::optional_flags`] on the telemetry
+/// initialization, so the expensive metrics can be enabled in the configuration of the service.
+///
+/// ## `#[unit]`
+///
+/// Metrics marked with `#[unit = "<unit>"]` are reported with the unit suffix appended to their
+/// name, e.g. `_seconds`, unless the name already has it, and with the unit in
+/// the [OpenMetrics] `# UNIT` metadata. The supported units are `amperes`, `bytes`, `celsius`,
+/// `grams`, `joules`, `meters`, `ratios`, `seconds` and `volts`.
+///
+/// The names of the metrics with a unit can't have the `_total` suffix. [`DurationHistogram`] and
+/// [`ByteCounter`] have the `seconds` and `bytes` units respectively without the attribute.
+///
+/// ## `#[ttl]`
+///
+/// Label sets of the metrics with labels marked with `#[ttl = <Duration>]` are removed once they
+/// haven't been accessed within the TTL, see [`ExpiringFamily`].
+///
+/// ## `#[removable]`
+///
+/// For the metrics with labels marked with `#[removable]`, the macro also generates
+/// a `remove_<metric name>` function with the same arguments, that removes the label set from
+/// the metric, e.g. once the connection the label set refers to is closed.
+///
+///
+/// # Cardinality limit
+///
+/// The number of label sets of each metric can be limited with
+/// [`MetricsSettings::max_label_sets`]. Once a metric reaches the limit, new label sets are
+/// collapsed into a single `overflow="true"` label set, see [`LimitedFamily`].
+///
 /// # Example
 ///
 /// ```
@@ -141,6 +536,7 @@ pub fn collect(settings: &MetricsSettings) -> Result<String> {
 /// #[metrics]
 /// pub mod my_app_metrics {
 ///     /// Number of active client connections
+///     #[removable]
 ///     pub fn client_connections_active(
 ///         // Labels with an anonymous reference type will get cloned.
 ///         endpoint: &Arc<String>,
@@ -187,6 +583,13 @@ pub fn collect(settings: &MetricsSettings) -> Result<String> {
 ///     /// Number of Proxy-Status serialization errors
 ///     // Metrics with no labels are also obviously supported.
 ///     pub fn proxy_status_serialization_error_count() -> Counter;
+///
+///     /// Size of the request headers
+///     // Reported as `request_headers_size_bytes` if `request_details` is listed in
+///     // `MetricsSettings::optional_flags`.
+///     #[unit = "bytes"]
+///     #[optional = "request_details"]
+///     pub fn request_headers_size(endpoint: &Arc<String>) -> Gauge;
 /// }
 ///
 /// fn usage() {
@@ -212,6 +615,164 @@ pub fn collect(settings: &MetricsSettings) -> Result<String> {
 ///     my_app_metrics::proxy_status_serialization_error_count().inc();
 ///
 ///     client_connections_active.dec();
+///
+///     my_app_metrics::remove_client_connections_active(
+///         &endpoint,
+///         l4_protocol,
+///         labels::IpVersion::V4,
+///         ingress_ip,
+///     );
+/// }
+/// # }
+/// ```
+///
+/// # Timing
+///
+/// [`TimeHistogram`] and [`DurationHistogram`] provide a `start_timer` method that returns a
+/// [`HistogramTimer`] guard, recording the elapsed time into the histogram when dropped. Unlike
+/// a manual `Instant::now()` and `observe` pair, the duration is recorded on all the code paths,
+/// including early returns. The timer can also be stopped explicitly with
+/// [`HistogramTimer::stop_and_record`] or [`HistogramTimer::stop_and_discard`].
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, HistogramBuilder, TimeHistogram};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Time spent parsing requests
+///     #[ctor = HistogramBuilder { buckets: &[0.001, 0.01, 0.1] }]
+///     pub fn parse_duration() -> TimeHistogram;
+/// }
+///
+/// fn parse(body: &str) -> Result<u64, std::num::ParseIntError> {
+///     let _timer = my_app_metrics::parse_duration().start_timer();
+///
+///     // The duration is recorded even if parsing fails.
+///     let value = body.trim().parse()?;
+///
+///     Ok(value)
+/// }
+/// # }
+/// ```
+///
+/// # Duration unit policy
+///
+/// To avoid mixing units across duration metrics, a module can declare the unit all its duration
+/// metrics are reported in with the `duration_unit` argument, which accepts either `"seconds"`
+/// or `"milliseconds"`.
+///
+/// Compilation fails if a metric name ends with a suffix of the other unit (e.g. `_ms` or
+/// `_seconds`, optionally followed by `_total`). [`TimeHistogram`] and [`DurationHistogram`]
+/// always report seconds, so they can't be used in modules with `duration_unit = "milliseconds"`.
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Histogram, HistogramBuilder, TimeHistogram};
+///
+/// #[metrics(duration_unit = "seconds")]
+/// pub mod my_app_metrics {
+///     /// Time spent handling requests
+///     #[ctor = HistogramBuilder { buckets: &[0.01, 0.1, 1.0] }]
+///     pub fn request_duration_seconds() -> TimeHistogram;
+///
+///     // Uncommenting this causes a compilation error:
+///     // /// Time spent waiting for upstream
+///     // pub fn upstream_wait_ms() -> Histogram;
+/// }
+/// # }
+/// ```
+///
+/// # Unit suffixes
+///
+/// Metrics of the unit-typed [`DurationHistogram`] and [`ByteCounter`] types are registered
+/// with their unit, so their names get the `_seconds` and `_bytes` suffixes respectively
+/// (unless the function name already ends with the suffix) and the unit is reported in the
+/// `# UNIT` line of the exposition.
+///
+/// In the modules with the `duration_unit` argument (see [the duration unit policy]), compilation
+/// also fails if a metric name ends with a unit suffix (optionally followed by `_total`) that
+/// contradicts the unit the metric is reported in: [`TimeHistogram`] and [`DurationHistogram`]
+/// report seconds, [`ByteCounter`] reports bytes and the other metrics report the unit of their
+/// `#[unit]` attribute, if any.
+///
+/// ```compile_fail
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, TimeHistogram};
+///
+/// #[metrics(duration_unit = "seconds")]
+/// pub mod my_app_metrics {
+///     /// Time spent handling requests
+///     pub fn request_duration_ms() -> TimeHistogram;
+/// }
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, ByteCounter};
+///
+/// #[metrics(duration_unit = "seconds")]
+/// pub mod my_app_metrics {
+///     /// Bytes sent to the clients
+///     pub fn sent_seconds_total() -> ByteCounter;
+/// }
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Gauge};
+///
+/// #[metrics(duration_unit = "seconds")]
+/// pub mod my_app_metrics {
+///     /// Size of the cache
+///     #[unit = "bytes"]
+///     pub fn cache_size_seconds() -> Gauge;
+/// }
+/// # }
+/// ```
+///
+/// [the duration unit policy]: #duration-unit-policy
+///
+/// # Hosted services
+///
+/// When several logical services are hosted in a single process, the `service` argument
+/// registers the metrics in the registry of the given service, so they are reported with the
+/// service name instead of the process-wide service name. See [`HostedService`] for more details.
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Counter};
+///
+/// // Reported as `auth_sessions_created_total`.
+/// #[metrics(service = "auth")]
+/// pub mod sessions {
+///     /// Number of created sessions
+///     pub fn created_total() -> Counter;
+/// }
+/// # }
+/// ```
+///
+/// [`HostedService`]: crate::telemetry::HostedService
+///
+/// # Named registries
+///
+/// The `registry` argument registers the metrics in the named registry, that is reported
+/// separately from the other metrics on the `/metrics/<registry>` endpoint of the telemetry
+/// server, e.g. to expose the internal metrics to the operators of the service only. The metrics
+/// of the named registries can also be collected with [`collect_registry`]. The argument can't
+/// be used together with the `service` argument.
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Counter};
+///
+/// // Reported on the `/metrics/internal` endpoint.
+/// #[metrics(registry = "internal")]
+/// pub mod cache_internals {
+///     /// Number of evicted cache entries
+///     pub fn evictions_total() -> Counter;
 /// }
 /// # }
 /// ```
@@ -239,6 +800,8 @@ pub fn collect(settings: &MetricsSettings) -> Result<String> {
 ///
 /// [telemetry server]: crate::telemetry::init_with_server
 /// [`MetricsSettings::report_optional`]: crate::telemetry::settings::MetricsSettings::report_optional
+/// [`MetricsSettings::optional_flags`]: crate::telemetry::settings::MetricsSettings::optional_flags
+/// [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md#unit
 pub use foundations_macros::metrics;
 
 /// A macro that allows to define a Prometheus info metric.
@@ -334,7 +897,10 @@ where
     );
 }
 
-/// A builder suitable for [`Histogram`] and [`TimeHistogram`].
+/// A builder suitable for [`Histogram`], [`TimeHistogram`] and [`DurationHistogram`].
+///
+/// The buckets specified with the `ctor` attribute of the [`metrics`] macro can be overridden
+/// per metric in [`MetricsSettings::histogram_buckets`].
 ///
 /// # Example
 ///
@@ -0,0 +1,119 @@
+use super::created::encode_labels;
+use super::exposition::parse_sample;
+use crate::telemetry::settings::ConstLabel;
+
+/// Adds the prefix to the names of all the metric families of the text exposition and
+/// the constant labels to all of their samples.
+///
+/// The constant labels are not added to the samples that already have a label with the same
+/// name.
+pub(super) fn apply_namespace(text: &str, prefix: &str, const_labels: &[ConstLabel]) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix("# ") {
+            let declaration = ["HELP ", "TYPE ", "UNIT "]
+                .iter()
+                .find(|keyword| comment.starts_with(*keyword));
+
+            match declaration {
+                Some(keyword) => {
+                    out.push_str("# ");
+                    out.push_str(keyword);
+                    out.push_str(prefix);
+                    out.push_str(&comment[keyword.len()..]);
+                }
+                None => out.push_str(line),
+            }
+
+            out.push('\n');
+            continue;
+        }
+
+        let (Some(name_end), Some((_, labels, _))) = (line.find(['{', ' ']), parse_sample(line))
+        else {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        };
+
+        let added: Vec<_> = const_labels
+            .iter()
+            .filter(|label| !labels.iter().any(|(name, _)| *name == label.name))
+            .map(|label| (label.name.clone(), label.value.clone()))
+            .collect();
+
+        out.push_str(prefix);
+        out.push_str(&line[..name_end]);
+
+        let rest = &line[name_end..];
+
+        if added.is_empty() {
+            out.push_str(rest);
+        } else {
+            let added = encode_labels(&added);
+
+            match rest.strip_prefix('{') {
+                Some(rest) if rest.starts_with('}') => {
+                    out.push_str(&added);
+                    out.push_str(&rest[1..]);
+                }
+                Some(rest) => {
+                    out.push_str(&added[..added.len() - 1]);
+                    out.push(',');
+                    out.push_str(rest);
+                }
+                None => {
+                    out.push_str(&added);
+                    out.push_str(rest);
+                }
+            }
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_prefix_and_const_labels() {
+        let text = concat!(
+            "# HELP app_requests Number of requests.\n",
+            "# TYPE app_requests counter\n",
+            "app_requests_total{path=\"/a\"} 1 # {trace_id=\"abc\"} 1.0\n",
+            "app_requests_total{region=\"eu\"} 2\n",
+            "# TYPE app_connections gauge\n",
+            "app_connections 3\n",
+            "# EOF\n",
+        );
+
+        let const_labels = [
+            ConstLabel {
+                name: "region".into(),
+                value: "us \"east\"".into(),
+            },
+            ConstLabel {
+                name: "instance".into(),
+                value: "host1".into(),
+            },
+        ];
+
+        assert_eq!(
+            apply_namespace(text, "team_", &const_labels),
+            concat!(
+                "# HELP team_app_requests Number of requests.\n",
+                "# TYPE team_app_requests counter\n",
+                "team_app_requests_total{region=\"us \\\"east\\\"\",instance=\"host1\",path=\"/a\"} 1 # {trace_id=\"abc\"} 1.0\n",
+                "team_app_requests_total{instance=\"host1\",region=\"eu\"} 2\n",
+                "# TYPE team_app_connections gauge\n",
+                "team_app_connections{region=\"us \\\"east\\\"\",instance=\"host1\"} 3\n",
+                "# EOF\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use super::MetricConstructor;
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+const MIN_SCHEMA: i32 = -4;
+const MAX_SCHEMA: i32 = 8;
+
+thread_local! {
+    static ENCODE_NATIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes native histograms encoded in `f` additionally emit their exponential buckets
+/// in the `<name>_native` sample, so they can be converted to the protobuf exposition format.
+pub(super) fn with_native_encoding<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            ENCODE_NATIVE.with(|native| native.set(false));
+        }
+    }
+
+    ENCODE_NATIVE.with(|native| native.set(true));
+
+    let _reset = Reset;
+
+    f()
+}
+
+/// A builder for [`NativeHistogram`].
+///
+/// # Example
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, NativeHistogram, NativeHistogramBuilder};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Size of the responses in bytes.
+///     #[ctor = NativeHistogramBuilder {
+///         schema: 2,
+///         buckets: &[256.0, 1024.0, 4096.0, 16384.0],
+///         ..NativeHistogramBuilder::DEFAULT
+///     }]
+///     pub fn response_size_bytes() -> NativeHistogram;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct NativeHistogramBuilder {
+    /// Resolution of the exponential buckets, from `-4` to `8`.
+    ///
+    /// Bucket boundaries grow by a factor of `2^(2^-schema)`, e.g. with schema `3` each bucket is
+    /// about 9% wider than the previous one. Values outside of the range are clamped.
+    pub schema: i32,
+
+    /// Observations with the absolute value not exceeding the threshold are counted in the zero
+    /// bucket.
+    pub zero_threshold: f64,
+
+    /// Classic buckets reported in the text exposition format.
+    pub buckets: &'static [f64],
+}
+
+impl NativeHistogramBuilder {
+    /// The default builder, that matches defaults of the official Prometheus clients: schema `3`,
+    /// the minimal zero threshold and the default classic buckets.
+    pub const DEFAULT: Self = Self {
+        schema: 3,
+        zero_threshold: 2.938735877055719e-39,
+        buckets: &[
+            0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+        ],
+    };
+}
+
+impl Default for NativeHistogramBuilder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MetricConstructor<NativeHistogram> for NativeHistogramBuilder {
+    fn new_metric(&self) -> NativeHistogram {
+        NativeHistogram::new(self)
+    }
+}
+
+/// A [Prometheus native histogram].
+///
+/// Native histograms have high-resolution exponential buckets that don't need to be
+/// configured upfront: only the buckets that received observations are stored and exposed.
+///
+/// Native histograms are only supported by the protobuf exposition format, that Prometheus
+/// negotiates with the telemetry server if native histograms are enabled in Prometheus. When
+/// the text format is used, the histogram falls back to the classic buckets configured with
+/// [`NativeHistogramBuilder::buckets`].
+///
+/// [Prometheus native histogram]: https://prometheus.io/docs/specs/native_histograms/
+#[derive(Clone, Debug)]
+pub struct NativeHistogram {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    schema: i32,
+    zero_threshold: f64,
+    classic_bounds: Vec<f64>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    count: u64,
+    sum: f64,
+    classic: Vec<u64>,
+    zero_count: u64,
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+}
+
+impl Default for NativeHistogram {
+    fn default() -> Self {
+        Self::new(&NativeHistogramBuilder::DEFAULT)
+    }
+}
+
+impl NativeHistogram {
+    /// Creates a new native histogram.
+    pub fn new(builder: &NativeHistogramBuilder) -> Self {
+        let mut classic_bounds = builder.buckets.to_vec();
+
+        classic_bounds.sort_by(f64::total_cmp);
+
+        Self {
+            inner: Arc::new(Inner {
+                schema: builder.schema.clamp(MIN_SCHEMA, MAX_SCHEMA),
+                zero_threshold: builder.zero_threshold.abs(),
+                state: Mutex::new(State {
+                    classic: vec![0; classic_bounds.len() + 1],
+                    ..Default::default()
+                }),
+                classic_bounds,
+            }),
+        }
+    }
+
+    /// Observes a value.
+    pub fn observe(&self, v: f64) {
+        if v.is_nan() {
+            return;
+        }
+
+        let classic_idx = self
+            .inner
+            .classic_bounds
+            .partition_point(|bound| *bound < v);
+        let mut state = self.inner.state.lock();
+
+        state.count += 1;
+        state.sum += v;
+        state.classic[classic_idx] += 1;
+
+        if v.abs() <= self.inner.zero_threshold {
+            state.zero_count += 1;
+        } else {
+            let buckets = if v > 0.0 {
+                &mut state.positive
+            } else {
+                &mut state.negative
+            };
+
+            *buckets.entry(self.bucket_index(v.abs())).or_default() += 1;
+        }
+    }
+
+    /// Returns the index of the exponential bucket for the positive value, so
+    /// `base^(index - 1) < v <= base^index`, where `base = 2^(2^-schema)`.
+    fn bucket_index(&self, v: f64) -> i32 {
+        let scale = 2f64.powi(self.inner.schema);
+
+        (v.log2() * scale).ceil() as i32
+    }
+
+    pub(super) fn snapshot(&self) -> NativeHistogramSnapshot {
+        let state = self.inner.state.lock();
+
+        NativeHistogramSnapshot {
+            schema: self.inner.schema,
+            zero_threshold: self.inner.zero_threshold,
+            zero_count: state.zero_count,
+            count: state.count,
+            sum: state.sum,
+            classic: self
+                .inner
+                .classic_bounds
+                .iter()
+                .copied()
+                .chain([f64::MAX])
+                .zip(state.classic.iter().scan(0, |acc, count| {
+                    *acc += count;
+                    Some(*acc)
+                }))
+                .collect(),
+            positive: state.positive.iter().map(|(i, c)| (*i, *c)).collect(),
+            negative: state.negative.iter().map(|(i, c)| (*i, *c)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct NativeHistogramSnapshot {
+    pub(super) schema: i32,
+    pub(super) zero_threshold: f64,
+    pub(super) zero_count: u64,
+    pub(super) count: u64,
+    pub(super) sum: f64,
+    pub(super) classic: Vec<(f64, u64)>,
+    pub(super) positive: Vec<(i32, u64)>,
+    pub(super) negative: Vec<(i32, u64)>,
+}
+
+impl NativeHistogramSnapshot {
+    /// Parses the payload of the `<name>_native` sample: `<schema>;<zero threshold>;
+    /// <zero count>;<positive buckets>;<negative buckets>`, where buckets are encoded as
+    /// comma-separated `<index>:<count>` pairs.
+    pub(super) fn parse_native_payload(&mut self, payload: &str) -> Option<()> {
+        let mut parts = payload.split(';');
+
+        self.schema = parts.next()?.parse().ok()?;
+        self.zero_threshold = parts.next()?.parse().ok()?;
+        self.zero_count = parts.next()?.parse().ok()?;
+        self.positive = parse_buckets(parts.next()?)?;
+        self.negative = parse_buckets(parts.next()?)?;
+
+        Some(())
+    }
+}
+
+fn parse_buckets(s: &str) -> Option<Vec<(i32, u64)>> {
+    s.split(',')
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            let (idx, count) = b.split_once(':')?;
+
+            Some((idx.parse().ok()?, count.parse().ok()?))
+        })
+        .collect()
+}
+
+struct NativePayload<'a>(&'a NativeHistogramSnapshot);
+
+impl Encode for NativePayload<'_> {
+    fn encode(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+        let snapshot = self.0;
+
+        write!(
+            writer,
+            "{};{:?};{};",
+            snapshot.schema, snapshot.zero_threshold, snapshot.zero_count
+        )?;
+
+        for buckets in [&snapshot.positive, &snapshot.negative] {
+            for (i, (idx, count)) in buckets.iter().enumerate() {
+                if i > 0 {
+                    writer.write_all(b",")?;
+                }
+
+                write!(writer, "{idx}:{count}")?;
+            }
+
+            writer.write_all(b";")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TypedMetric for NativeHistogram {
+    const TYPE: MetricType = MetricType::Histogram;
+}
+
+impl EncodeMetric for NativeHistogram {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let snapshot = self.snapshot();
+
+        encoder
+            .encode_suffix("sum")?
+            .no_bucket()?
+            .encode_value(snapshot.sum)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("count")?
+            .no_bucket()?
+            .encode_value(snapshot.count)?
+            .no_exemplar()?;
+
+        for (upper_bound, count) in &snapshot.classic {
+            encoder
+                .encode_suffix("bucket")?
+                .encode_bucket(*upper_bound)?
+                .encode_value(*count)?
+                .no_exemplar()?;
+        }
+
+        if ENCODE_NATIVE.with(Cell::get) {
+            encoder
+                .encode_suffix("native")?
+                .no_bucket()?
+                .encode_value(NativePayload(&snapshot))?
+                .no_exemplar()?;
+        }
+
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observes_into_exponential_buckets() {
+        let histogram = NativeHistogram::new(&NativeHistogramBuilder {
+            schema: 0,
+            zero_threshold: 0.001,
+            buckets: &[1.0],
+        });
+
+        for v in [0.0, 0.0005, 1.0, 1.5, 2.0, 3.0, 4.0, -3.0] {
+            histogram.observe(v);
+        }
+
+        let snapshot = histogram.snapshot();
+
+        assert_eq!(snapshot.count, 8);
+        assert!((snapshot.sum - 8.5005).abs() < 1e-9);
+        assert_eq!(snapshot.zero_count, 2);
+        // With schema 0 bucket boundaries are powers of 2: (0.5, 1], (1, 2], (2, 4].
+        assert_eq!(snapshot.positive, vec![(0, 1), (1, 2), (2, 2)]);
+        assert_eq!(snapshot.negative, vec![(2, 1)]);
+        assert_eq!(snapshot.classic, vec![(1.0, 4), (f64::MAX, 8)]);
+    }
+
+    #[test]
+    fn native_payload_roundtrip() {
+        let histogram = NativeHistogram::default();
+
+        for v in [0.0, 0.1, 0.2, 5.0, -1.0] {
+            histogram.observe(v);
+        }
+
+        let snapshot = histogram.snapshot();
+        let mut payload = vec![];
+
+        NativePayload(&snapshot).encode(&mut payload).unwrap();
+
+        let mut parsed = NativeHistogramSnapshot {
+            count: snapshot.count,
+            sum: snapshot.sum,
+            classic: snapshot.classic.clone(),
+            ..Default::default()
+        };
+
+        parsed
+            .parse_native_payload(std::str::from_utf8(&payload).unwrap())
+            .unwrap();
+
+        assert_eq!(parsed, snapshot);
+    }
+}
@@ -0,0 +1,153 @@
+use super::exposition::{parse_sample, Labels};
+
+/// Sorts metric families of the text exposition by name and series of each family by labels.
+///
+/// Samples of the same series, e.g. buckets, sum and count of a histogram, retain their relative
+/// order. Comment lines that don't declare a family and the `# EOF` marker keep their position
+/// at the end of the exposition.
+pub(super) fn sort_exposition(text: &str) -> String {
+    let mut families: Vec<Family> = vec![];
+    let mut trailer = vec![];
+
+    for line in text.lines() {
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix("# ") {
+            let declared = ["HELP ", "TYPE ", "UNIT "]
+                .iter()
+                .find_map(|prefix| comment.strip_prefix(prefix))
+                .and_then(|rest| rest.split(' ').next());
+
+            let Some(name) = declared else {
+                trailer.push(line);
+                continue;
+            };
+
+            match families.last_mut() {
+                Some(family) if family.name == name && family.samples.is_empty() => {
+                    family.header.push(line);
+                }
+                _ => families.push(Family::new(name, vec![line])),
+            }
+
+            continue;
+        }
+
+        let Some((name, labels, _)) = parse_sample(line) else {
+            trailer.push(line);
+            continue;
+        };
+
+        let key = series_key(labels);
+
+        match families.last_mut() {
+            Some(family) if name.starts_with(family.name) => {
+                family.samples.push((key, line));
+            }
+            _ => {
+                let mut family = Family::new(name, vec![]);
+
+                family.samples.push((key, line));
+                families.push(family);
+            }
+        }
+    }
+
+    // NOTE: the sorts are stable, so families with the same name and samples of the same series
+    // retain their relative order.
+    families.sort_by(|f1, f2| f1.name.cmp(f2.name));
+
+    let mut out = String::with_capacity(text.len());
+
+    for mut family in families {
+        family.samples.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+
+        for line in family.header {
+            out.push_str(line);
+            out.push('\n');
+        }
+
+        for (_, line) in family.samples {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    for line in trailer {
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    out
+}
+
+struct Family<'a> {
+    name: &'a str,
+    header: Vec<&'a str>,
+    samples: Vec<(Labels, &'a str)>,
+}
+
+impl<'a> Family<'a> {
+    fn new(name: &'a str, header: Vec<&'a str>) -> Self {
+        Self {
+            name,
+            header,
+            samples: vec![],
+        }
+    }
+}
+
+// NOTE: bucket and quantile labels identify samples of a series rather than the series itself.
+pub(super) fn series_key(mut labels: Labels) -> Labels {
+    labels.retain(|(name, _)| name != "le" && name != "quantile");
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_families_and_series() {
+        let text = "\
+# HELP b_total B.
+# TYPE b_total counter
+b_total{kind=\"y\"} 2
+b_total{kind=\"x\"} 1
+# HELP a_seconds A.
+# TYPE a_seconds histogram
+a_seconds_bucket{op=\"write\",le=\"0.5\"} 1
+a_seconds_bucket{op=\"write\",le=\"+Inf\"} 1
+a_seconds_sum{op=\"write\"} 0.1
+a_seconds_count{op=\"write\"} 1
+a_seconds_bucket{op=\"read\",le=\"0.5\"} 2
+a_seconds_bucket{op=\"read\",le=\"+Inf\"} 3
+a_seconds_sum{op=\"read\"} 1.5
+a_seconds_count{op=\"read\"} 3
+# EOF
+";
+
+        assert_eq!(
+            sort_exposition(text),
+            "\
+# HELP a_seconds A.
+# TYPE a_seconds histogram
+a_seconds_bucket{op=\"read\",le=\"0.5\"} 2
+a_seconds_bucket{op=\"read\",le=\"+Inf\"} 3
+a_seconds_sum{op=\"read\"} 1.5
+a_seconds_count{op=\"read\"} 3
+a_seconds_bucket{op=\"write\",le=\"0.5\"} 1
+a_seconds_bucket{op=\"write\",le=\"+Inf\"} 1
+a_seconds_sum{op=\"write\"} 0.1
+a_seconds_count{op=\"write\"} 1
+# HELP b_total B.
+# TYPE b_total counter
+b_total{kind=\"x\"} 1
+b_total{kind=\"y\"} 2
+# EOF
+"
+        );
+    }
+}
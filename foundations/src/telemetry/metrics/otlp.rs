@@ -0,0 +1,520 @@
+//! Exporter of the metrics to an OpenTelemetry collector over [OTLP].
+//!
+//! The metrics are collected in the text format, same as for the telemetry server, parsed
+//! into metric families and encoded as `opentelemetry.proto.collector.metrics.v1.
+//! ExportMetricsServiceRequest` messages.
+//!
+//! TLS is not supported: only plain-text `http://` endpoints are accepted and the exporter fails
+//! to start with an `https://` endpoint. Collectors that only accept TLS connections should be
+//! reached through a collector agent running alongside the service.
+//!
+//! [OTLP]: https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/metrics/v1/metrics.proto
+
+use super::exposition::{self, Family, Histogram, Kind, Labels, Metric, Value};
+use super::protobuf::{varint, Message};
+use super::scrape_windows;
+use super::Counter;
+use crate::telemetry::http_client::Post;
+use crate::telemetry::settings::{MetricsSettings, OtlpProtocol, ProxySettings};
+use crate::{BootstrapResult, Result, ServiceInfo};
+use anyhow::bail;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::MissedTickBehavior;
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+const GRPC_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+const HTTP_PATH: &str = "/v1/metrics";
+
+// NOTE: value of the `AGGREGATION_TEMPORALITY_CUMULATIVE` variant of
+// the `opentelemetry.proto.metrics.v1.AggregationTemporality` enum.
+const AGGREGATION_TEMPORALITY_CUMULATIVE: u64 = 2;
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_otlp {
+    /// Number of the failed exports of the metrics to the OTLP collector.
+    pub fn export_failures_total() -> Counter;
+}
+
+/// Starts the exporter, if enabled in the settings.
+pub(crate) fn start(
+    service_info: &ServiceInfo,
+    settings: &MetricsSettings,
+    proxy: &ProxySettings,
+) -> BootstrapResult<()> {
+    if !settings.otlp.enabled {
+        return Ok(());
+    }
+
+    let exporter = OtlpExporter::new(service_info, settings, proxy)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    thread::spawn(move || runtime.block_on(exporter.run()));
+
+    Ok(())
+}
+
+struct OtlpExporter {
+    settings: MetricsSettings,
+    url: String,
+    proxy: Option<String>,
+    resource: Vec<(&'static str, String)>,
+    start_time: u64,
+}
+
+impl OtlpExporter {
+    fn new(
+        service_info: &ServiceInfo,
+        settings: &MetricsSettings,
+        proxy: &ProxySettings,
+    ) -> BootstrapResult<Self> {
+        let endpoint = settings.otlp.endpoint.trim_end_matches('/');
+
+        if !endpoint.starts_with("http://") {
+            bail!("OTLP metrics exporter only supports `http://` endpoints, got `{endpoint}`");
+        }
+
+        let url = match settings.otlp.protocol {
+            OtlpProtocol::Http => format!("{endpoint}{HTTP_PATH}"),
+            OtlpProtocol::Grpc => format!("{endpoint}{GRPC_PATH}"),
+        };
+
+        Ok(Self {
+            settings: settings.clone(),
+            proxy: proxy.proxy_for(&url),
+            url,
+            resource: vec![
+                ("service.name", service_info.name.to_string()),
+                ("service.version", service_info.version.to_string()),
+            ],
+            start_time: unix_nanos(),
+        })
+    }
+
+    async fn run(self) {
+        let mut interval = tokio::time::interval(Duration::from_millis(
+            self.settings.otlp.export_interval_ms.max(1),
+        ));
+
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.export().await {
+                foundations_otlp::export_failures_total().inc();
+
+                #[cfg(feature = "logging")]
+                log::warn!("failed to export metrics to the OTLP collector"; "error" => %e);
+
+                #[cfg(not(feature = "logging"))]
+                let _ = e;
+            }
+        }
+    }
+
+    async fn export(&self) -> Result<()> {
+        // NOTE: the exports shouldn't reset the ranges reported to the scrapers of the service.
+        let text =
+            scrape_windows::peek(|| super::collect_with_native_histograms(&self.settings, None))?;
+        let families = exposition::parse(&text)?;
+        let request = encode_request(&self.resource, &families, self.start_time, unix_nanos());
+        let grpc = matches!(self.settings.otlp.protocol, OtlpProtocol::Grpc);
+
+        let (body, headers): (_, &[_]) = if grpc {
+            (
+                grpc_frame(request),
+                &[("content-type", "application/grpc"), ("te", "trailers")],
+            )
+        } else {
+            (request, &[("content-type", "application/x-protobuf")])
+        };
+
+        let response = Post {
+            url: &self.url,
+            proxy: self.proxy.as_deref(),
+            http2: grpc,
+            headers,
+            body,
+            timeout: Duration::from_millis(self.settings.otlp.timeout_ms),
+        }
+        .send()
+        .await?;
+
+        if !response.status.is_success() {
+            return Err(format!(
+                "collector responded with {}: {}",
+                response.status,
+                String::from_utf8_lossy(&response.body)
+            )
+            .into());
+        }
+
+        if grpc {
+            // NOTE: the status is sent in the headers if the response doesn't have a body.
+            let trailers = response.trailers.as_ref().unwrap_or(&response.headers);
+            let header = |name| trailers.get(name).and_then(|v| v.to_str().ok());
+
+            match header("grpc-status") {
+                Some("0") => {}
+                Some(status) => {
+                    return Err(format!(
+                        "collector responded with gRPC status {status}: {}",
+                        header("grpc-message").unwrap_or_default()
+                    )
+                    .into())
+                }
+                None => return Err("collector response doesn't have a gRPC status".into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+/// Prefixes the message with the uncompressed gRPC message header.
+fn grpc_frame(msg: Vec<u8>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(msg.len() + 5);
+
+    frame.push(0);
+    frame.extend((msg.len() as u32).to_be_bytes());
+    frame.extend(msg);
+
+    frame
+}
+
+fn encode_request(
+    resource: &[(&'static str, String)],
+    families: &[Family],
+    start_time: u64,
+    time: u64,
+) -> Vec<u8> {
+    let mut msg = Message::default();
+
+    // NOTE: `ResourceMetrics`.
+    msg.message(1, |msg| {
+        msg.message(1, |msg| {
+            for (key, value) in resource {
+                msg.message(1, |msg| encode_attribute(msg, key, value));
+            }
+        });
+
+        // NOTE: `ScopeMetrics`.
+        msg.message(2, |msg| {
+            msg.message(1, |msg| {
+                msg.string(1, "foundations");
+                msg.string(2, env!("CARGO_PKG_VERSION"));
+            });
+
+            for family in families.iter().filter(|f| !f.metrics.is_empty()) {
+                msg.message(2, |msg| encode_metric(msg, family, start_time, time));
+            }
+        });
+    });
+
+    msg.0
+}
+
+fn encode_attribute(msg: &mut Message, key: &str, value: &str) {
+    msg.string(1, key);
+    msg.message(2, |msg| msg.string(1, value));
+}
+
+fn encode_attributes(msg: &mut Message, field: u32, labels: &Labels) {
+    for (name, value) in labels {
+        msg.message(field, |msg| encode_attribute(msg, name, value));
+    }
+}
+
+fn encode_metric(msg: &mut Message, family: &Family, start_time: u64, time: u64) {
+    msg.string(1, &family.name);
+
+    if !family.help.is_empty() {
+        msg.string(2, &family.help);
+    }
+
+    let scalars = || {
+        family
+            .metrics
+            .iter()
+            .filter_map(|metric| match metric.value {
+                Value::Scalar(value) => Some((metric, value)),
+                Value::Histogram(_) => None,
+            })
+    };
+
+    let histograms = || {
+        family
+            .metrics
+            .iter()
+            .filter_map(|metric| match &metric.value {
+                Value::Histogram(histogram) => Some((metric, &**histogram)),
+                Value::Scalar(_) => None,
+            })
+    };
+
+    match family.kind {
+        Kind::Counter => msg.message(7, |msg| {
+            for (metric, value) in scalars() {
+                msg.message(1, |msg| {
+                    encode_number_point(msg, metric, value, Some(start_time), time)
+                });
+            }
+
+            msg.uint64(2, AGGREGATION_TEMPORALITY_CUMULATIVE);
+            msg.uint64(3, 1);
+        }),
+        Kind::Histogram if histograms().any(|(_, h)| h.is_native) => msg.message(10, |msg| {
+            for (metric, histogram) in histograms() {
+                msg.message(1, |msg| {
+                    encode_exponential_point(msg, metric, histogram, start_time, time)
+                });
+            }
+
+            msg.uint64(2, AGGREGATION_TEMPORALITY_CUMULATIVE);
+        }),
+        // NOTE: OTLP has no gauge histograms, they are reported as cumulative histograms.
+        Kind::Histogram | Kind::GaugeHistogram => msg.message(9, |msg| {
+            for (metric, histogram) in histograms() {
+                msg.message(1, |msg| {
+                    encode_histogram_point(msg, metric, histogram, start_time, time)
+                });
+            }
+
+            msg.uint64(2, AGGREGATION_TEMPORALITY_CUMULATIVE);
+        }),
+        Kind::Gauge | Kind::Info | Kind::Untyped => msg.message(5, |msg| {
+            for (metric, value) in scalars() {
+                msg.message(1, |msg| encode_number_point(msg, metric, value, None, time));
+            }
+        }),
+    }
+}
+
+fn encode_number_point(
+    msg: &mut Message,
+    metric: &Metric,
+    value: f64,
+    start_time: Option<u64>,
+    time: u64,
+) {
+    if let Some(start_time) = start_time {
+        msg.fixed64(2, start_time);
+    }
+
+    msg.fixed64(3, time);
+    msg.double(4, value);
+    encode_attributes(msg, 7, &metric.labels);
+}
+
+fn encode_histogram_point(
+    msg: &mut Message,
+    metric: &Metric,
+    histogram: &Histogram,
+    start_time: u64,
+    time: u64,
+) {
+    let data = &histogram.data;
+    let (bounds, counts) = explicit_buckets(&data.classic, data.count);
+
+    msg.fixed64(2, start_time);
+    msg.fixed64(3, time);
+    msg.fixed64(4, data.count);
+    msg.double(5, data.sum);
+    msg.bytes(
+        6,
+        &counts
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>(),
+    );
+
+    if !bounds.is_empty() {
+        msg.bytes(
+            7,
+            &bounds
+                .iter()
+                .flat_map(|b| b.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    encode_attributes(msg, 9, &metric.labels);
+}
+
+fn encode_exponential_point(
+    msg: &mut Message,
+    metric: &Metric,
+    histogram: &Histogram,
+    start_time: u64,
+    time: u64,
+) {
+    let data = &histogram.data;
+
+    encode_attributes(msg, 1, &metric.labels);
+    msg.fixed64(2, start_time);
+    msg.fixed64(3, time);
+    msg.fixed64(4, data.count);
+    msg.double(5, data.sum);
+    msg.sint64(6, data.schema as i64);
+    msg.fixed64(7, data.zero_count);
+
+    for (field, buckets) in [(8, &data.positive), (9, &data.negative)] {
+        if let Some((offset, counts)) = dense_buckets(buckets) {
+            msg.message(field, |msg| {
+                msg.sint64(1, offset as i64);
+                msg.bytes(2, &counts.into_iter().flat_map(varint).collect::<Vec<_>>());
+            });
+        }
+    }
+
+    msg.double(14, data.zero_threshold);
+}
+
+/// Converts the cumulative classic buckets to the explicit bounds and the counts of
+/// the buckets, including the implicit `+Inf` bucket.
+fn explicit_buckets(classic: &[(f64, u64)], count: u64) -> (Vec<f64>, Vec<u64>) {
+    let mut bounds = vec![];
+    let mut counts = vec![];
+    let mut prev = 0;
+
+    for &(upper_bound, cumulative) in classic {
+        // NOTE: the `+Inf` bucket is implicit, same as in the protobuf exposition format.
+        if upper_bound.is_finite() && upper_bound != f64::MAX {
+            bounds.push(upper_bound);
+            counts.push(cumulative.saturating_sub(prev));
+            prev = cumulative;
+        }
+    }
+
+    counts.push(count.saturating_sub(prev));
+
+    (bounds, counts)
+}
+
+/// Converts the sparse native histogram buckets to the offset and the counts of consecutive
+/// exponential histogram buckets.
+///
+/// Native histogram bucket `i` covers `(base^(i-1), base^i]`, while the exponential histogram
+/// bucket `i` covers `(base^i, base^(i+1)]`, so the indices are shifted by one.
+fn dense_buckets(buckets: &[(i32, u64)]) -> Option<(i32, Vec<u64>)> {
+    let first = buckets.first()?.0;
+    let last = buckets.last()?.0;
+    let mut counts = vec![0; (last - first + 1) as usize];
+
+    for &(idx, count) in buckets {
+        counts[(idx - first) as usize] = count;
+    }
+
+    Some((first - 1, counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn converts_classic_buckets() {
+        assert_eq!(
+            explicit_buckets(&[(1.0, 1), (5.0, 3), (f64::INFINITY, 4)], 4),
+            (vec![1.0, 5.0], vec![1, 2, 1])
+        );
+    }
+
+    #[test]
+    fn converts_native_buckets() {
+        assert_eq!(dense_buckets(&[]), None);
+        assert_eq!(
+            dense_buckets(&[(-1, 2), (0, 3), (3, 1)]),
+            Some((-2, vec![2, 3, 0, 0, 1]))
+        );
+    }
+
+    #[tokio::test]
+    async fn pushes_metrics_to_collector() {
+        let (request_tx, mut request_rx) = mpsc::unbounded_channel();
+
+        let make_service = make_service_fn(move |_| {
+            let request_tx = request_tx.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let request_tx = request_tx.clone();
+
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let content_type = req.headers()["content-type"].clone();
+                        let body = hyper::body::to_bytes(req.into_body()).await?;
+
+                        let _ = request_tx.send((path, content_type, body));
+
+                        Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+
+        let mut settings = MetricsSettings::default();
+
+        settings.otlp.endpoint = format!("http://{addr}/");
+
+        let proxy = ProxySettings {
+            from_env: false,
+            ..Default::default()
+        };
+
+        let service_info = crate::service_info!();
+        let exporter = OtlpExporter::new(&service_info, &settings, &proxy).unwrap();
+
+        exporter.export().await.unwrap();
+
+        let (path, content_type, body) = request_rx.recv().await.unwrap();
+
+        assert_eq!(path, "/v1/metrics");
+        assert_eq!(content_type, "application/x-protobuf");
+
+        // NOTE: the resource attributes are encoded as is at the start of the request.
+        let mut resource = Message::default();
+
+        resource.message(1, |msg| {
+            encode_attribute(msg, "service.name", service_info.name)
+        });
+
+        assert!(body
+            .windows(resource.0.len())
+            .any(|window| window == resource.0));
+    }
+
+    #[test]
+    fn rejects_https_endpoints() {
+        let mut settings = MetricsSettings::default();
+
+        settings.otlp.endpoint = "https://collector.example.com".into();
+
+        assert!(
+            OtlpExporter::new(&crate::service_info!(), &settings, &Default::default()).is_err()
+        );
+    }
+}
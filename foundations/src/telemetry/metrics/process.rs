@@ -0,0 +1,218 @@
+//! Standard [process metrics] of the current process, collected from procfs.
+//!
+//! [process metrics]: https://prometheus.io/docs/instrumenting/writing_clientlibs/#process-metrics
+
+use super::collector::{CollectorMetric, CollectorType, Samples};
+use super::internal::Registries;
+use std::fs;
+
+// NOTE: the metric names are the same as the ones of the other Prometheus client libraries, so
+// they're reported without the service prefix to be compatible with the existing dashboards.
+pub(super) fn register(registries: &Registries) {
+    let mut registry = registries.unprefixed.write();
+
+    let mut register = |name, help, collector_type, collect: fn() -> Option<f64>| {
+        registry.register(
+            name,
+            help,
+            Box::new(CollectorMetric::new(
+                collector_type,
+                move |samples: &mut Samples| {
+                    if let Some(value) = collect() {
+                        samples.add(&[], value);
+                    }
+                },
+            )),
+        );
+    };
+
+    register(
+        "process_cpu_seconds_total",
+        "Total user and system CPU time spent in seconds",
+        CollectorType::Counter,
+        || {
+            let stat = read_stat()?;
+
+            Some((stat.utime + stat.stime) as f64 / clock_ticks()?)
+        },
+    );
+
+    register(
+        "process_resident_memory_bytes",
+        "Resident memory size in bytes",
+        CollectorType::Gauge,
+        || Some(read_stat()?.rss as f64 * page_size()?),
+    );
+
+    register(
+        "process_virtual_memory_bytes",
+        "Virtual memory size in bytes",
+        CollectorType::Gauge,
+        || Some(read_stat()?.vsize as f64),
+    );
+
+    register(
+        "process_open_fds",
+        "Number of open file descriptors",
+        CollectorType::Gauge,
+        || Some(fs::read_dir("/proc/self/fd").ok()?.count() as f64),
+    );
+
+    register(
+        "process_max_fds",
+        "Maximum number of open file descriptors",
+        CollectorType::Gauge,
+        || parse_max_fds(&fs::read_to_string("/proc/self/limits").ok()?),
+    );
+
+    register(
+        "process_threads",
+        "Number of OS threads in the process",
+        CollectorType::Gauge,
+        || Some(read_stat()?.num_threads as f64),
+    );
+
+    register(
+        "process_start_time_seconds",
+        "Start time of the process since Unix epoch in seconds",
+        CollectorType::Gauge,
+        || {
+            let boot_time = parse_boot_time(&fs::read_to_string("/proc/stat").ok()?)?;
+
+            Some(boot_time as f64 + read_stat()?.starttime as f64 / clock_ticks()?)
+        },
+    );
+}
+
+/// The fields of `/proc/self/stat` used by the metrics, see [proc(5)].
+///
+/// [proc(5)]: https://man7.org/linux/man-pages/man5/proc.5.html
+#[derive(Debug, PartialEq, Eq)]
+struct Stat {
+    utime: u64,
+    stime: u64,
+    num_threads: u64,
+    starttime: u64,
+    vsize: u64,
+    rss: u64,
+}
+
+fn read_stat() -> Option<Stat> {
+    parse_stat(&fs::read_to_string("/proc/self/stat").ok()?)
+}
+
+fn parse_stat(stat: &str) -> Option<Stat> {
+    // NOTE: the process name is enclosed in parentheses and can contain spaces and parentheses
+    // itself, so the fields are counted from the last parenthesis, starting with the state,
+    // which is the third field.
+    let fields: Vec<_> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3)?.parse().ok();
+
+    Some(Stat {
+        utime: field(14)?,
+        stime: field(15)?,
+        num_threads: field(20)?,
+        starttime: field(22)?,
+        vsize: field(23)?,
+        rss: field(24)?,
+    })
+}
+
+fn parse_max_fds(limits: &str) -> Option<f64> {
+    let line = limits
+        .lines()
+        .find(|line| line.starts_with("Max open files"))?;
+
+    // NOTE: the soft limit is the first value after the name of the limit.
+    line["Max open files".len()..]
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn parse_boot_time(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn clock_ticks() -> Option<f64> {
+    // SAFETY: `sysconf` has no preconditions.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+
+    (ticks > 0).then_some(ticks as f64)
+}
+
+fn page_size() -> Option<f64> {
+    // SAFETY: `sysconf` has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    (size > 0).then_some(size as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::settings::ServiceNameFormat;
+
+    #[test]
+    fn parses_procfs() {
+        let stat = "4242 (my (app) 1) S 1 4242 4242 0 -1 4194560 1210 0 0 0 37 12 0 0 20 0 \
+                    9 0 170035 1099386880 3072 18446744073709551615 1 1 0 0 0 0 0 4096 0 0 0 \
+                    0 17 3 0 0 0 0 0";
+
+        assert_eq!(
+            parse_stat(stat),
+            Some(Stat {
+                utime: 37,
+                stime: 12,
+                num_threads: 9,
+                starttime: 170035,
+                vsize: 1099386880,
+                rss: 3072,
+            })
+        );
+        assert_eq!(parse_stat("4242 (my app) S 1"), None);
+
+        let limits = "Limit                     Soft Limit           Hard Limit           Units\n\
+                      Max processes             127431               127431               processes\n\
+                      Max open files            1024                 524288               files\n";
+
+        assert_eq!(parse_max_fds(limits), Some(1024.0));
+        assert_eq!(
+            parse_boot_time("cpu  1 2 3\nbtime 1700000000\nprocesses 42\n"),
+            Some(1700000000)
+        );
+    }
+
+    #[test]
+    fn reports_process_metrics() {
+        let registries = Registries::new("test", &ServiceNameFormat::MetricPrefix);
+
+        register(&registries);
+
+        let mut buffer = vec![];
+
+        registries.encode(&mut buffer, false).unwrap();
+
+        let text = String::from_utf8(buffer).unwrap();
+
+        for name in [
+            "process_cpu_seconds_total",
+            "process_resident_memory_bytes",
+            "process_virtual_memory_bytes",
+            "process_open_fds",
+            "process_max_fds",
+            "process_threads",
+            "process_start_time_seconds",
+        ] {
+            assert!(
+                text.contains(&format!("\n{name} ")),
+                "{name} is missing:\n{text}"
+            );
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Encoder of the metric families in the [protobuf exposition format].
+//!
+//! The families are written as length-delimited `io.prometheus.client.MetricFamily` messages.
+//!
+//! [protobuf exposition format]: https://github.com/prometheus/client_model/blob/master/io/prometheus/client/metrics.proto
+
+use super::exposition::{Family, Histogram, Kind, Metric, Value};
+
+// NOTE: values of the `io.prometheus.client.MetricType` enum.
+const TYPE_COUNTER: u64 = 0;
+const TYPE_GAUGE: u64 = 1;
+const TYPE_UNTYPED: u64 = 3;
+const TYPE_HISTOGRAM: u64 = 4;
+const TYPE_GAUGE_HISTOGRAM: u64 = 5;
+
+/// Encodes metric families as length-delimited protobuf messages.
+pub(super) fn encode(families: &[Family], out: &mut Vec<u8>) {
+    for family in families {
+        let mut msg = Message::default();
+
+        encode_family(&mut msg, family);
+        out.extend(varint(msg.0.len() as u64));
+        out.extend(msg.0);
+    }
+}
+
+fn encode_family(msg: &mut Message, family: &Family) {
+    msg.string(1, &family.name);
+
+    if !family.help.is_empty() {
+        msg.string(2, &family.help);
+    }
+
+    msg.uint64(
+        3,
+        match family.kind {
+            Kind::Counter => TYPE_COUNTER,
+            Kind::Gauge | Kind::Info => TYPE_GAUGE,
+            Kind::Histogram => TYPE_HISTOGRAM,
+            Kind::GaugeHistogram => TYPE_GAUGE_HISTOGRAM,
+            Kind::Untyped => TYPE_UNTYPED,
+        },
+    );
+
+    for metric in &family.metrics {
+        msg.message(4, |msg| encode_metric(msg, family.kind, metric));
+    }
+}
+
+fn encode_metric(msg: &mut Message, kind: Kind, metric: &Metric) {
+    for (name, value) in &metric.labels {
+        msg.message(1, |msg| {
+            msg.string(1, name);
+            msg.string(2, value);
+        });
+    }
+
+    let field = match kind {
+        Kind::Gauge | Kind::Info => 2,
+        Kind::Counter => 3,
+        Kind::Untyped => 5,
+        Kind::Histogram | Kind::GaugeHistogram => 7,
+    };
+
+    match &metric.value {
+        Value::Scalar(value) => msg.message(field, |msg| msg.double(1, *value)),
+        Value::Histogram(histogram) => msg.message(field, |msg| encode_histogram(msg, histogram)),
+    }
+}
+
+fn encode_histogram(msg: &mut Message, histogram: &Histogram) {
+    let data = &histogram.data;
+
+    msg.uint64(1, data.count);
+    msg.double(2, data.sum);
+
+    for (upper_bound, count) in &data.classic {
+        // NOTE: the `+Inf` bucket is implicit in the protobuf format.
+        if upper_bound.is_finite() && *upper_bound != f64::MAX {
+            msg.message(3, |msg| {
+                msg.uint64(1, *count);
+                msg.double(2, *upper_bound);
+            });
+        }
+    }
+
+    if !histogram.is_native {
+        return;
+    }
+
+    msg.sint64(5, data.schema as i64);
+    msg.double(6, data.zero_threshold);
+    msg.uint64(7, data.zero_count);
+
+    encode_buckets(msg, 9, 10, &data.negative);
+    encode_buckets(msg, 12, 13, &data.positive);
+
+    // NOTE: a no-op span distinguishes native histograms without observations
+    // from the classic ones.
+    if data.positive.is_empty() && data.negative.is_empty() {
+        msg.message(12, |msg| {
+            msg.sint64(1, 0);
+            msg.uint64(2, 0);
+        });
+    }
+}
+
+fn encode_buckets(msg: &mut Message, span_field: u32, delta_field: u32, buckets: &[(i32, u64)]) {
+    let mut prev_idx = None;
+
+    for (idx, _) in buckets {
+        match prev_idx {
+            Some(prev) if idx - prev == 1 => {}
+            _ => {
+                let offset = prev_idx.map_or(*idx, |prev| idx - prev - 1);
+                let length = buckets
+                    .iter()
+                    .skip_while(|(i, _)| i < idx)
+                    .zip(*idx..)
+                    .take_while(|((i, _), expected)| i == expected)
+                    .count();
+
+                msg.message(span_field, |msg| {
+                    msg.sint64(1, offset as i64);
+                    msg.uint64(2, length as u64);
+                });
+            }
+        }
+
+        prev_idx = Some(*idx);
+    }
+
+    let mut deltas = Message::default();
+    let mut prev_count = 0;
+
+    for (_, count) in buckets {
+        deltas.raw_sint64(*count as i64 - prev_count);
+        prev_count = *count as i64;
+    }
+
+    if !deltas.0.is_empty() {
+        msg.bytes(delta_field, &deltas.0);
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Message(pub(super) Vec<u8>);
+
+impl Message {
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.0
+            .extend(varint(((field as u64) << 3) | wire_type as u64));
+    }
+
+    pub(super) fn uint64(&mut self, field: u32, v: u64) {
+        self.key(field, 0);
+        self.0.extend(varint(v));
+    }
+
+    pub(super) fn sint64(&mut self, field: u32, v: i64) {
+        self.key(field, 0);
+        self.raw_sint64(v);
+    }
+
+    pub(super) fn raw_sint64(&mut self, v: i64) {
+        self.0.extend(varint(((v << 1) ^ (v >> 63)) as u64));
+    }
+
+    pub(super) fn double(&mut self, field: u32, v: f64) {
+        self.key(field, 1);
+        self.0.extend(v.to_le_bytes());
+    }
+
+    #[cfg(feature = "otlp-metrics")]
+    pub(super) fn fixed64(&mut self, field: u32, v: u64) {
+        self.key(field, 1);
+        self.0.extend(v.to_le_bytes());
+    }
+
+    pub(super) fn bytes(&mut self, field: u32, v: &[u8]) {
+        self.key(field, 2);
+        self.0.extend(varint(v.len() as u64));
+        self.0.extend(v);
+    }
+
+    pub(super) fn string(&mut self, field: u32, v: &str) {
+        self.bytes(field, v.as_bytes());
+    }
+
+    pub(super) fn message(&mut self, field: u32, encode: impl FnOnce(&mut Message)) {
+        let mut msg = Message::default();
+
+        encode(&mut msg);
+        self.bytes(field, &msg.0);
+    }
+}
+
+pub(super) fn varint(mut v: u64) -> impl Iterator<Item = u8> {
+    let mut done = false;
+
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+
+        let byte = (v & 0x7f) as u8;
+
+        v >>= 7;
+        done = v == 0;
+
+        Some(if done { byte } else { byte | 0x80 })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_varints() {
+        assert_eq!(varint(0).collect::<Vec<_>>(), [0]);
+        assert_eq!(varint(1).collect::<Vec<_>>(), [1]);
+        assert_eq!(varint(300).collect::<Vec<_>>(), [0xac, 0x02]);
+    }
+
+    #[test]
+    fn encodes_bucket_spans_and_deltas() {
+        let mut msg = Message::default();
+
+        encode_buckets(&mut msg, 12, 13, &[(-1, 2), (0, 3), (3, 1)]);
+
+        let mut expected = Message::default();
+
+        expected.message(12, |msg| {
+            msg.sint64(1, -1);
+            msg.uint64(2, 2);
+        });
+
+        expected.message(12, |msg| {
+            msg.sint64(1, 2);
+            msg.uint64(2, 1);
+        });
+
+        let mut deltas = Message::default();
+
+        deltas.raw_sint64(2);
+        deltas.raw_sint64(1);
+        deltas.raw_sint64(-2);
+        expected.bytes(13, &deltas.0);
+
+        assert_eq!(msg.0, expected.0);
+    }
+}
@@ -0,0 +1,347 @@
+//! Pushing of the metrics to a Prometheus [Pushgateway].
+//!
+//! Batch jobs and other short-lived processes can exit before Prometheus scrapes them, so their
+//! metrics need to be pushed instead. If [`MetricsSettings::push`] is enabled, the metrics are
+//! pushed periodically by a background thread started on [telemetry initialization], and can be
+//! pushed on demand with [`push`] or [`push_blocking`], e.g. right before the job exits or in
+//! the `FlushTelemetry` stage of the shutdown coordinator of the `shutdown` module.
+//!
+//! The metrics are pushed in the text format to the group identified by the job name and
+//! the grouping labels from the settings. Remote-write endpoints are not supported.
+//!
+//! # Examples
+//! ```no_run
+//! use foundations::telemetry::metrics::push;
+//! use foundations::telemetry::settings::TelemetrySettings;
+//!
+//! # fn main() -> foundations::Result<()> {
+//! let service_info = foundations::service_info!();
+//! let mut settings = TelemetrySettings::default();
+//!
+//! settings.metrics.push.enabled = true;
+//! foundations::telemetry::init(&service_info, &settings)?;
+//!
+//! // Run the job...
+//!
+//! push::push_blocking()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [Pushgateway]: https://github.com/prometheus/pushgateway
+//! [`MetricsSettings::push`]: crate::telemetry::settings::MetricsSettings::push
+//! [telemetry initialization]: crate::telemetry::init
+
+use super::Counter;
+use crate::telemetry::http_client::Post;
+use crate::telemetry::settings::{MetricsSettings, ProxySettings};
+use crate::{BootstrapResult, Result, ServiceInfo};
+use anyhow::bail;
+use once_cell::sync::OnceCell;
+use std::thread;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+static PUSHER: OnceCell<Pusher> = OnceCell::new();
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_push {
+    /// Number of the failed pushes of the metrics to the Pushgateway.
+    pub fn failures_total() -> Counter;
+}
+
+/// Pushes the metrics to the Pushgateway.
+///
+/// Returns an error if [`MetricsSettings::push`] is not enabled or the telemetry is not
+/// initialized.
+///
+/// [`MetricsSettings::push`]: crate::telemetry::settings::MetricsSettings::push
+pub async fn push() -> Result<()> {
+    let pusher = PUSHER
+        .get()
+        .ok_or("pushing of the metrics is not enabled")?;
+
+    pusher.push_and_count_failures().await
+}
+
+/// Pushes the metrics to the Pushgateway, blocking the current thread.
+///
+/// Should not be called from an async context, use [`push`] instead.
+pub fn push_blocking() -> Result<()> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(push())
+}
+
+/// Starts pushing the metrics, if enabled in the settings.
+pub(crate) fn start(
+    service_info: &ServiceInfo,
+    settings: &MetricsSettings,
+    proxy: &ProxySettings,
+) -> BootstrapResult<()> {
+    if !settings.push.enabled || PUSHER.get().is_some() {
+        return Ok(());
+    }
+
+    let pusher = PUSHER.get_or_try_init(|| Pusher::new(service_info, settings, proxy))?;
+
+    let Some(interval_ms) = settings.push.push_interval_ms else {
+        return Ok(());
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    thread::spawn(move || runtime.block_on(pusher.run(Duration::from_millis(interval_ms.max(1)))));
+
+    Ok(())
+}
+
+struct Pusher {
+    settings: MetricsSettings,
+    url: String,
+    proxy: Option<String>,
+}
+
+impl Pusher {
+    fn new(
+        service_info: &ServiceInfo,
+        settings: &MetricsSettings,
+        proxy: &ProxySettings,
+    ) -> BootstrapResult<Self> {
+        let endpoint = settings.push.endpoint.trim_end_matches('/');
+
+        if !endpoint.starts_with("http://") {
+            bail!("metrics can only be pushed to `http://` endpoints, got `{endpoint}`");
+        }
+
+        let job = settings.push.job.as_deref().unwrap_or(service_info.name);
+        let mut url = format!("{endpoint}/metrics{}", path_segment("job", job));
+
+        for label in &settings.push.grouping_labels {
+            url.push_str(&path_segment(&label.name, &label.value));
+        }
+
+        let mut settings = settings.clone();
+
+        // NOTE: exemplars are not supported by the text format of the Pushgateway.
+        settings.exemplars = false;
+
+        Ok(Self {
+            proxy: proxy.proxy_for(&url),
+            url,
+            settings,
+        })
+    }
+
+    async fn run(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.push_and_count_failures().await {
+                #[cfg(feature = "logging")]
+                log::warn!("failed to push metrics to the Pushgateway"; "error" => %e);
+
+                #[cfg(not(feature = "logging"))]
+                let _ = e;
+            }
+        }
+    }
+
+    async fn push_and_count_failures(&self) -> Result<()> {
+        let res = self.push().await;
+
+        if res.is_err() {
+            foundations_push::failures_total().inc();
+        }
+
+        res
+    }
+
+    async fn push(&self) -> Result<()> {
+        // NOTE: the Pushgateway expects the totals of the counters, and the pushes shouldn't reset
+        // the ranges reported to the scrapers of the service.
+        let text = super::collect_without_reset(&self.settings)?;
+
+        let response = Post {
+            url: &self.url,
+            proxy: self.proxy.as_deref(),
+            http2: false,
+            headers: &[("content-type", "text/plain; version=0.0.4")],
+            body: text.into_bytes(),
+            timeout: Duration::from_millis(self.settings.push.timeout_ms),
+        }
+        .send()
+        .await?;
+
+        if !response.status.is_success() {
+            return Err(format!(
+                "Pushgateway responded with {}: {}",
+                response.status,
+                String::from_utf8_lossy(&response.body)
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes a grouping label as a path segment of the push URL.
+///
+/// Values that are empty or contain characters other than the unreserved ones are encoded with
+/// the URL-safe base64 encoding, as supported by the Pushgateway.
+fn path_segment(name: &str, value: &str) -> String {
+    let is_unreserved = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~');
+
+    if !value.is_empty() && value.bytes().all(is_unreserved) {
+        format!("/{name}/{value}")
+    } else {
+        format!("/{name}@base64/{}", base64_url(value.as_bytes()))
+    }
+}
+
+fn base64_url(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    // NOTE: the Pushgateway requires at least one character, so the empty value is encoded as
+    // padding.
+    if data.is_empty() {
+        return "=".into();
+    }
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::settings::PushGroupingLabel;
+    use hyper::body::Bytes;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, Uri};
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn encodes_grouping_labels() {
+        assert_eq!(path_segment("job", "backup"), "/job/backup");
+        assert_eq!(
+            path_segment("path", "/var/tmp"),
+            "/path@base64/L3Zhci90bXA="
+        );
+        assert_eq!(path_segment("instance", ""), "/instance@base64/=");
+        assert_eq!(base64_url(b"\xfb\xff"), "-_8=");
+    }
+
+    // NOTE: the server receives the URI of the requests, that is absolute if the server is used
+    // as a proxy.
+    fn serve() -> (SocketAddr, mpsc::UnboundedReceiver<(Uri, Bytes)>) {
+        let (request_tx, request_rx) = mpsc::unbounded_channel();
+
+        let make_service = make_service_fn(move |_| {
+            let request_tx = request_tx.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let request_tx = request_tx.clone();
+
+                    async move {
+                        let uri = req.uri().clone();
+                        let body = hyper::body::to_bytes(req.into_body()).await?;
+                        let _ = request_tx.send((uri, body));
+
+                        Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+
+        (addr, request_rx)
+    }
+
+    #[tokio::test]
+    async fn pushes_metrics_to_group() {
+        let (addr, mut request_rx) = serve();
+        let mut settings = MetricsSettings::default();
+
+        settings.push.endpoint = format!("http://{addr}/");
+        settings.push.grouping_labels = vec![PushGroupingLabel {
+            name: "instance".into(),
+            value: "host-1".into(),
+        }];
+
+        let proxy = ProxySettings {
+            from_env: false,
+            ..Default::default()
+        };
+
+        let _ = foundations_push::failures_total();
+
+        let pusher = Pusher::new(&crate::service_info!(), &settings, &proxy).unwrap();
+
+        pusher.push().await.unwrap();
+
+        let (uri, body) = request_rx.recv().await.unwrap();
+
+        assert_eq!(uri.path(), "/metrics/job/foundations/instance/host-1");
+        assert!(String::from_utf8_lossy(&body).contains("_foundations_push_failures_total 0\n"));
+    }
+
+    #[tokio::test]
+    async fn pushes_metrics_through_proxy() {
+        let (addr, mut request_rx) = serve();
+        let mut settings = MetricsSettings::default();
+
+        settings.push.endpoint = "http://pushgateway.invalid:9091".into();
+
+        let proxy = ProxySettings {
+            http_proxy: Some(format!("http://{addr}")),
+            no_proxy: vec![],
+            from_env: false,
+        };
+
+        let pusher = Pusher::new(&crate::service_info!(), &settings, &proxy).unwrap();
+
+        pusher.push().await.unwrap();
+
+        let (uri, _) = request_rx.recv().await.unwrap();
+
+        assert_eq!(
+            uri.to_string(),
+            "http://pushgateway.invalid:9091/metrics/job/foundations"
+        );
+    }
+}
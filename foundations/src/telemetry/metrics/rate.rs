@@ -0,0 +1,268 @@
+use super::MetricConstructor;
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// NOTE: the window is split into this number of slots, so the window slides in the steps of
+// a slot.
+const SLOTS: usize = 60;
+
+/// A builder for [`RateGauge`].
+///
+/// # Example
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, RateGauge, RateGaugeBuilder};
+/// use std::time::Duration;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests per second over the last 5 minutes.
+///     #[ctor = RateGaugeBuilder {
+///         window: Duration::from_secs(300),
+///     }]
+///     pub fn requests_rate() -> RateGauge;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RateGaugeBuilder {
+    /// Period of time the rate is computed over.
+    ///
+    /// The longer the window, the smoother the rate, and the longer it takes for the rate to
+    /// reflect a change.
+    pub window: Duration,
+}
+
+impl RateGaugeBuilder {
+    /// The default builder, with a window of one minute.
+    pub const DEFAULT: Self = Self {
+        window: Duration::from_secs(60),
+    };
+}
+
+impl Default for RateGaugeBuilder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MetricConstructor<RateGauge> for RateGaugeBuilder {
+    fn new_metric(&self) -> RateGauge {
+        RateGauge::new(self.window)
+    }
+}
+
+/// A counter that is reported as a gauge with its per-second rate over a sliding window.
+///
+/// Rates of the counters are usually computed by the Prometheus queries, e.g. with `irate()`,
+/// which produce artifacts when the counters reset on restarts or when the scrapes are missed.
+/// The gauge counts the events in the slots of the window configured with [`RateGaugeBuilder`],
+/// and reports the number of the events in the window divided by its duration in seconds, so
+/// the rate can be used by the alerts as is.
+///
+/// For the first window after the gauge is created, the rate is computed over the time since its
+/// creation.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, RateGauge};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests per second over the last minute.
+///     pub fn requests_rate() -> RateGauge;
+/// }
+///
+/// fn on_request() {
+///     my_app_metrics::requests_rate().inc();
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RateGauge {
+    inner: Arc<RateGaugeInner>,
+}
+
+#[derive(Debug)]
+struct RateGaugeInner {
+    slot_duration: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    created_at: Instant,
+    total: u64,
+    // NOTE: number of the slot `slots[current % SLOTS]` since the creation of the gauge.
+    current: u64,
+    slots: [u64; SLOTS],
+}
+
+impl State {
+    fn slot_at(&self, slot_duration: Duration, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.created_at);
+
+        (elapsed.as_nanos() / slot_duration.as_nanos()) as u64
+    }
+
+    fn advance(&mut self, slot_duration: Duration, now: Instant) {
+        let slot = self.slot_at(slot_duration, now);
+
+        if slot <= self.current {
+            return;
+        }
+
+        for expired in (self.current + 1..=slot).take(SLOTS) {
+            self.slots[(expired % SLOTS as u64) as usize] = 0;
+        }
+
+        self.current = slot;
+    }
+}
+
+impl Default for RateGauge {
+    fn default() -> Self {
+        RateGaugeBuilder::DEFAULT.new_metric()
+    }
+}
+
+impl RateGauge {
+    fn new(window: Duration) -> Self {
+        Self {
+            inner: Arc::new(RateGaugeInner {
+                slot_duration: (window / SLOTS as u32).max(Duration::from_nanos(1)),
+                state: Mutex::new(State {
+                    created_at: Instant::now(),
+                    total: 0,
+                    current: 0,
+                    slots: [0; SLOTS],
+                }),
+            }),
+        }
+    }
+
+    /// Counts an event.
+    pub fn inc(&self) {
+        self.inc_by(1)
+    }
+
+    /// Counts `v` events.
+    pub fn inc_by(&self, v: u64) {
+        self.inc_by_at(Instant::now(), v)
+    }
+
+    /// Returns the total number of the counted events.
+    pub fn total(&self) -> u64 {
+        self.inner.state.lock().total
+    }
+
+    /// Returns the per-second rate of the events over the window.
+    pub fn rate(&self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    fn inc_by_at(&self, now: Instant, v: u64) {
+        let mut state = self.inner.state.lock();
+
+        state.advance(self.inner.slot_duration, now);
+        state.total = state.total.wrapping_add(v);
+
+        let current = (state.current % SLOTS as u64) as usize;
+
+        state.slots[current] = state.slots[current].saturating_add(v);
+    }
+
+    fn rate_at(&self, now: Instant) -> f64 {
+        let slot_duration = self.inner.slot_duration;
+        let mut state = self.inner.state.lock();
+
+        state.advance(slot_duration, now);
+
+        // NOTE: the window covers the previous slots and the elapsed part of the current one.
+        let elapsed = now.saturating_duration_since(state.created_at);
+        let current_elapsed =
+            Duration::from_nanos((elapsed.as_nanos() % slot_duration.as_nanos()) as u64);
+        let covered = elapsed.min(slot_duration * (SLOTS as u32 - 1) + current_elapsed);
+
+        if covered.is_zero() {
+            return 0.0;
+        }
+
+        state.slots.iter().sum::<u64>() as f64 / covered.as_secs_f64()
+    }
+}
+
+impl TypedMetric for RateGauge {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for RateGauge {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        encoder
+            .no_suffix()?
+            .no_bucket()?
+            .encode_value(self.rate())?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[test]
+    fn computes_rate_over_sliding_window() {
+        let gauge = RateGauge::new(Duration::from_secs(60));
+        let start = gauge.inner.state.lock().created_at;
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(gauge.rate_at(start), 0.0);
+
+        gauge.inc_by_at(start, 10);
+
+        // NOTE: the rate is computed over the time since the creation in the first window.
+        assert_eq!(gauge.rate_at(at(10)), 1.0);
+
+        gauge.inc_by_at(at(45), 50);
+
+        assert_eq!(gauge.rate_at(at(59)), 60.0 / 59.0);
+
+        // NOTE: the events of the first slot slide out of the window.
+        assert_eq!(gauge.rate_at(at(90)), 50.0 / 59.0);
+        assert_eq!(gauge.rate_at(at(120)), 0.0);
+        assert_eq!(gauge.total(), 60);
+
+        gauge.inc_by_at(at(3600), 119);
+
+        assert_eq!(
+            gauge.rate_at(at(3600) + Duration::from_millis(500)),
+            119.0 / 59.5
+        );
+    }
+
+    #[test]
+    fn encodes_rate() {
+        let gauge = RateGauge::new(Duration::from_secs(60));
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        registry.register("requests_rate", "Request rate", Box::new(gauge.clone()));
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains("# TYPE requests_rate gauge\n"));
+        assert!(encoded.contains("requests_rate 0.0\n"));
+    }
+}
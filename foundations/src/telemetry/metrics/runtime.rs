@@ -0,0 +1,160 @@
+use super::collector::{CollectorMetric, CollectorType, Samples};
+use super::internal::Registries;
+use parking_lot::RwLock;
+use tokio::runtime::{Handle, RuntimeMetrics};
+
+static RUNTIMES: RwLock<Vec<(String, Handle)>> = RwLock::new(Vec::new());
+
+/// Registers a Tokio runtime whose metrics are reported if
+/// [`MetricsSettings::runtime_metrics`] is enabled.
+///
+/// The `name` is used as a `runtime` label of the metrics. Registering a runtime with the name
+/// of an already registered one replaces it. The runtime the telemetry is [initialized] in is
+/// registered automatically with the `main` name.
+///
+/// The following metrics are reported:
+///
+/// - `<prefix>_foundations_tokio_runtime_workers` gauge with the number of the worker threads;
+/// - `<prefix>_foundations_tokio_runtime_alive_tasks` gauge with the number of the alive tasks;
+/// - `<prefix>_foundations_tokio_runtime_global_queue_depth` gauge with the number of the tasks
+///   in the global queue;
+/// - `<prefix>_foundations_tokio_runtime_worker_busy_seconds_total` counter with the time each
+///   worker thread has been busy, additionally labeled with the `worker` index;
+/// - `<prefix>_foundations_tokio_runtime_worker_parks_total` counter with the number of times
+///   each worker thread has parked, additionally labeled with the `worker` index.
+///
+/// The blocking threads are not reported as the corresponding Tokio metrics are unstable, the
+/// pools of the `blocking` module can be used to instrument the blocking tasks instead.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::metrics::register_runtime;
+///
+/// let runtime = tokio::runtime::Builder::new_multi_thread()
+///     .worker_threads(2)
+///     .build()
+///     .unwrap();
+///
+/// register_runtime("io", runtime.handle());
+/// ```
+///
+/// [`MetricsSettings::runtime_metrics`]: crate::telemetry::settings::MetricsSettings::runtime_metrics
+/// [initialized]: crate::telemetry::init
+pub fn register_runtime(name: &str, handle: &Handle) {
+    let mut runtimes = RUNTIMES.write();
+
+    runtimes.retain(|(registered, _)| registered != name);
+    runtimes.push((name.to_string(), handle.clone()));
+}
+
+/// Registers the collectors of the metrics of the registered runtimes.
+pub(super) fn register_collectors(registries: &Registries) {
+    let mut registry = registries.main_subsystem("foundations_tokio_runtime");
+
+    let mut register =
+        |name, help, collector_type, collect: fn(&str, &RuntimeMetrics, &mut Samples)| {
+            registry.register(
+                name,
+                help,
+                Box::new(CollectorMetric::new(
+                    collector_type,
+                    move |samples: &mut Samples| {
+                        for (runtime, handle) in RUNTIMES.read().iter() {
+                            collect(runtime, &handle.metrics(), samples);
+                        }
+                    },
+                )),
+            );
+        };
+
+    register(
+        "workers",
+        "Number of the worker threads of the runtime",
+        CollectorType::Gauge,
+        |runtime, metrics, samples| {
+            samples.add(&[("runtime", runtime)], metrics.num_workers() as f64)
+        },
+    );
+
+    register(
+        "alive_tasks",
+        "Number of the alive tasks of the runtime",
+        CollectorType::Gauge,
+        |runtime, metrics, samples| {
+            samples.add(&[("runtime", runtime)], metrics.num_alive_tasks() as f64)
+        },
+    );
+
+    register(
+        "global_queue_depth",
+        "Number of the tasks in the global queue of the runtime",
+        CollectorType::Gauge,
+        |runtime, metrics, samples| {
+            samples.add(&[("runtime", runtime)], metrics.global_queue_depth() as f64)
+        },
+    );
+
+    #[cfg(target_has_atomic = "64")]
+    register(
+        "worker_busy_seconds_total",
+        "Time the worker thread of the runtime has been busy",
+        CollectorType::Counter,
+        |runtime, metrics, samples| {
+            for worker in 0..metrics.num_workers() {
+                samples.add(
+                    &[("runtime", runtime), ("worker", &worker.to_string())],
+                    metrics.worker_total_busy_duration(worker).as_secs_f64(),
+                );
+            }
+        },
+    );
+
+    #[cfg(target_has_atomic = "64")]
+    register(
+        "worker_parks_total",
+        "Number of times the worker thread of the runtime has parked",
+        CollectorType::Counter,
+        |runtime, metrics, samples| {
+            for worker in 0..metrics.num_workers() {
+                samples.add(
+                    &[("runtime", runtime), ("worker", &worker.to_string())],
+                    metrics.worker_park_count(worker) as f64,
+                );
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::settings::ServiceNameFormat;
+
+    #[test]
+    fn reports_runtime_metrics() {
+        let registries = Registries::new("test", &ServiceNameFormat::MetricPrefix);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap();
+
+        register_collectors(&registries);
+        register_runtime("test_runtime", runtime.handle());
+
+        let mut buffer = vec![];
+
+        registries.encode(&mut buffer, false).unwrap();
+
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(
+            text.contains("test_foundations_tokio_runtime_workers{runtime=\"test_runtime\"} 2.0\n")
+        );
+        assert!(text.contains(
+            "test_foundations_tokio_runtime_alive_tasks{runtime=\"test_runtime\"} 0.0\n"
+        ));
+        assert!(text.contains(
+            "test_foundations_tokio_runtime_worker_parks_total{runtime=\"test_runtime\",worker=\"1\"} "
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+//! Per-scraper ranges of the range gauges, see [`MetricsSettings::range_gauge_windows`].
+//!
+//! Each collection of the metrics is a scrape with a sequential epoch. On each scrape a range
+//! gauge closes its current window, tagged with the epoch, and keeps the recent windows, so
+//! the range reported to a scraper is merged from the windows closed since its previous scrape.
+//!
+//! The internal collections of the metrics, e.g. for the diagnostics, are not scrapes: they are
+//! made with [`peek`] and report the ranges without closing the windows or resetting the ranges.
+
+use crate::telemetry::settings::MetricsSettings;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// NOTE: the scrapers are keyed by their IP addresses by default, so the number of the tracked
+// scrapers is capped regardless of the number of the retained windows.
+const MAX_SCRAPERS: usize = 1024;
+
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+// NOTE: epochs of the previous scrapes, keyed by the identity of the scraper.
+static SCRAPERS: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(Default::default);
+
+thread_local! {
+    static SCRAPER: RefCell<Option<String>> = const { RefCell::new(None) };
+    static CURRENT: Cell<Option<Scrape>> = const { Cell::new(None) };
+    static PEEK: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Scrape {
+    epoch: u64,
+    // NOTE: epoch of the previous scrape of the same scraper, `0` if unknown.
+    since: u64,
+    retained: u64,
+}
+
+/// Makes the collections of the metrics in `f` report the ranges of the range gauges since
+/// the previous collection by the same scraper.
+///
+/// The ranges are only tracked per scraper if [`MetricsSettings::range_gauge_windows`] is set,
+/// otherwise the minimum and maximum of the range gauges are reset by every collection regardless
+/// of the scraper. The collections outside of this function are made by an anonymous scraper.
+///
+/// The telemetry server identifies the scrapers by the `scraper` query parameter of the metrics
+/// endpoints, e.g. `/metrics?scraper=prometheus-a`, or by their IP addresses otherwise.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::metrics;
+/// use foundations::telemetry::settings::MetricsSettings;
+///
+/// let settings = MetricsSettings {
+///     range_gauge_windows: 16,
+///     ..Default::default()
+/// };
+///
+/// let metrics = metrics::with_scraper("prometheus-a", || metrics::collect(&settings)).unwrap();
+/// ```
+///
+/// [`MetricsSettings::range_gauge_windows`]: crate::telemetry::settings::MetricsSettings::range_gauge_windows
+pub fn with_scraper<R>(scraper: &str, f: impl FnOnce() -> R) -> R {
+    let prev = SCRAPER.with(|current| current.replace(Some(scraper.to_string())));
+
+    struct Reset(Option<String>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SCRAPER.with(|current| *current.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _reset = Reset(prev);
+
+    f()
+}
+
+/// Makes the collections of the metrics in `f` report the ranges of the range gauges since
+/// the previous scrape without resetting them, so the collections don't affect the scrapes.
+pub(super) fn peek<R>(f: impl FnOnce() -> R) -> R {
+    let prev = PEEK.with(|peek| peek.replace(true));
+
+    struct Reset(bool);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            PEEK.with(|peek| peek.set(self.0));
+        }
+    }
+
+    let _reset = Reset(prev);
+
+    f()
+}
+
+/// Returns `true` if the metrics are collected with [`peek`].
+pub(super) fn is_peek() -> bool {
+    PEEK.with(Cell::get)
+}
+
+/// Starts a scrape of the current scraper for the encoding of the metrics in `f`.
+pub(super) fn scrape<R>(settings: &MetricsSettings, f: impl FnOnce() -> R) -> R {
+    if settings.range_gauge_windows == 0 || is_peek() {
+        return f();
+    }
+
+    let retained = settings.range_gauge_windows as u64;
+    let scraper = SCRAPER.with(|scraper| scraper.borrow().clone().unwrap_or_default());
+    let (epoch, since) = start_scrape(scraper, retained);
+
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.set(None));
+        }
+    }
+
+    CURRENT.with(|current| {
+        current.set(Some(Scrape {
+            epoch,
+            since,
+            retained,
+        }))
+    });
+
+    let _reset = Reset;
+
+    f()
+}
+
+/// Returns the epoch of a new scrape by `scraper` and the epoch of its previous scrape.
+///
+/// The lock is only held to update the epochs of the scrapers: the scrapes themselves run
+/// concurrently, see [`RangeWindows::close`].
+fn start_scrape(scraper: String, retained: u64) -> (u64, u64) {
+    let mut scrapers = SCRAPERS.lock();
+
+    // NOTE: the epoch is taken under the lock, so it's newer than the epochs of all the scrapers.
+    let epoch = EPOCH.fetch_add(1, Ordering::Relaxed) + 1;
+    let since = track_scraper(&mut scrapers, scraper, epoch, retained);
+
+    (epoch, since)
+}
+
+/// Records the scrape of `scraper` at `epoch` and returns the epoch of its previous scrape.
+fn track_scraper(
+    scrapers: &mut HashMap<String, u64>,
+    scraper: String,
+    epoch: u64,
+    retained: u64,
+) -> u64 {
+    let since = scrapers.insert(scraper, epoch).unwrap_or(0);
+
+    // NOTE: the scrapers that didn't scrape in a while get all the retained windows anyway.
+    scrapers.retain(|_, last| epoch - *last < retained);
+
+    if scrapers.len() > MAX_SCRAPERS {
+        let oldest = scrapers
+            .iter()
+            .min_by_key(|(_, last)| **last)
+            .map(|(scraper, _)| scraper.clone());
+
+        if let Some(oldest) = oldest {
+            scrapers.remove(&oldest);
+        }
+    }
+
+    since
+}
+
+/// Recent windows of a range gauge.
+#[derive(Debug, Default)]
+pub(super) struct RangeWindows<T> {
+    windows: Mutex<VecDeque<(u64, T, T)>>,
+}
+
+impl<T: Copy + PartialOrd> RangeWindows<T> {
+    /// Returns the range to report for the range since the previous scrape by any scraper.
+    ///
+    /// If the ranges are tracked per scraper, the range is merged with the ranges of the windows
+    /// closed since the previous scrape by the current scraper.
+    pub(super) fn range(&self, min: T, max: T) -> (T, T) {
+        match CURRENT.with(Cell::get) {
+            Some(scrape) => self.close(scrape, min, max),
+            None => (min, max),
+        }
+    }
+
+    /// Closes the current window with the range since the previous scrape by any scraper, and
+    /// returns the range since the previous scrape by the scraper of `scrape`.
+    ///
+    /// The scrapes run concurrently, so a newer scrape can close the window first. The window
+    /// closed by the older scrape then covers the values observed after the newer scrape, so
+    /// it's tagged with a fresh epoch to be reported to the scrapers that scraped in between,
+    /// at the cost of reporting it again to the scraper of `scrape` on its next scrape.
+    fn close(&self, scrape: Scrape, window_min: T, window_max: T) -> (T, T) {
+        let mut windows = self.windows.lock();
+        let (mut min, mut max) = (window_min, window_max);
+
+        let window_epoch = match windows.back() {
+            Some(&(last, ..)) if last > scrape.epoch => EPOCH.fetch_add(1, Ordering::Relaxed) + 1,
+            _ => scrape.epoch,
+        };
+
+        while windows
+            .front()
+            .is_some_and(|(epoch, ..)| epoch + scrape.retained <= scrape.epoch)
+        {
+            windows.pop_front();
+        }
+
+        for &(_, prev_min, prev_max) in windows.iter().filter(|(e, ..)| *e > scrape.since) {
+            if prev_min < min {
+                min = prev_min;
+            }
+
+            if prev_max > max {
+                max = prev_max;
+            }
+        }
+
+        windows.push_back((window_epoch, window_min, window_max));
+
+        (min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_tracked_scrapers() {
+        let mut scrapers = HashMap::new();
+
+        for epoch in 1..=MAX_SCRAPERS as u64 + 1 {
+            track_scraper(&mut scrapers, format!("scraper-{epoch}"), epoch, u64::MAX);
+        }
+
+        // NOTE: the least recently seen scraper is evicted.
+        assert_eq!(scrapers.len(), MAX_SCRAPERS);
+        assert!(!scrapers.contains_key("scraper-1"));
+        assert!(scrapers.contains_key("scraper-2"));
+    }
+
+    #[test]
+    fn reports_windows_closed_out_of_order() {
+        let windows = RangeWindows::default();
+        let scrape = |scraper: &str| {
+            let (epoch, since) = start_scrape(scraper.to_string(), 16);
+
+            Scrape {
+                epoch,
+                since,
+                retained: 16,
+            }
+        };
+
+        let (older, newer) = (scrape("older"), scrape("newer"));
+
+        // NOTE: the newer scrape closes the window before the older one.
+        assert_eq!(windows.close(newer, 0, 10), (0, 10));
+        assert_eq!(windows.close(older, 20, 30), (0, 30));
+
+        // NOTE: the values closed by the older scrape are reported to the newer scraper.
+        assert_eq!(windows.close(scrape("newer"), 5, 5), (5, 30));
+    }
+}
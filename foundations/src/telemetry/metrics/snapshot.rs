@@ -0,0 +1,264 @@
+use super::exposition::{self, Kind, Value};
+use crate::Result;
+use std::collections::BTreeMap;
+
+/// A snapshot of the values of the metrics, returned by [`collect_snapshot`] and
+/// `TestTelemetryContext::metrics_snapshot` (requires **testing** feature).
+///
+/// All the metrics are collected at once, so the snapshot can be used to assert on the values of
+/// several metrics without parsing the text format:
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Counter, Gauge};
+///
+/// #[metrics]
+/// pub mod my_lib {
+///     /// Number of processed jobs.
+///     pub fn jobs_total(queue: &'static str) -> Counter;
+///
+///     /// Number of the jobs in flight.
+///     pub fn jobs_in_flight() -> Gauge;
+/// }
+/// # }
+/// # use rustdoc_workaround::my_lib;
+/// use foundations::telemetry::TelemetryContext;
+///
+/// let ctx = TelemetryContext::test();
+///
+/// {
+///     let _scope = ctx.scope();
+///
+///     my_lib::jobs_total("emails").inc();
+///     my_lib::jobs_in_flight().set(3);
+/// }
+///
+/// let snapshot = ctx.metrics_snapshot();
+///
+/// assert_eq!(
+///     snapshot.value("undefined_my_lib_jobs_total", &[("queue", "emails")]),
+///     Some(1.0)
+/// );
+/// assert_eq!(snapshot.value("undefined_my_lib_jobs_in_flight", &[]), Some(3.0));
+/// assert_eq!(snapshot.value("undefined_my_lib_jobs_total", &[("queue", "sms")]), None);
+/// ```
+///
+/// The series are keyed by the names they are reported with, including the prefix of the metrics
+/// and the `_total` suffix of the counters.
+///
+/// [`collect_snapshot`]: super::collect_snapshot
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    series: BTreeMap<String, Vec<Series>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Series {
+    // NOTE: sorted by name, so the lookups don't depend on the order of the labels.
+    labels: Vec<(String, String)>,
+    value: MetricValue,
+}
+
+/// A value of a series in a [`MetricsSnapshot`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetricValue {
+    /// A value of a counter.
+    Counter(f64),
+
+    /// A value of a gauge, including the info metrics.
+    Gauge(f64),
+
+    /// A value of a series with no declared type, e.g. a quantile of a summary.
+    Untyped(f64),
+
+    /// A histogram.
+    Histogram(HistogramValue),
+
+    /// A [gauge histogram](super::GaugeHistogram).
+    GaugeHistogram(HistogramValue),
+}
+
+impl MetricValue {
+    /// Returns the value of a counter, a gauge or an untyped series, or `None` for the histograms.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MetricValue::Counter(v) | MetricValue::Gauge(v) | MetricValue::Untyped(v) => Some(*v),
+            MetricValue::Histogram(_) | MetricValue::GaugeHistogram(_) => None,
+        }
+    }
+
+    /// Returns the histogram, or `None` for the scalar values.
+    pub fn as_histogram(&self) -> Option<&HistogramValue> {
+        match self {
+            MetricValue::Histogram(h) | MetricValue::GaugeHistogram(h) => Some(h),
+            _ => None,
+        }
+    }
+}
+
+/// A histogram in a [`MetricsSnapshot`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistogramValue {
+    /// Number of the observed values.
+    pub count: u64,
+
+    /// Sum of the observed values.
+    pub sum: f64,
+
+    /// Upper bounds of the buckets with the cumulative number of the values less than or equal
+    /// to them, the last one being `f64::INFINITY`.
+    ///
+    /// Empty for the [native histograms](super::NativeHistogram).
+    pub buckets: Vec<(f64, u64)>,
+}
+
+impl MetricsSnapshot {
+    /// Returns the value of the series with the name and exactly the given labels, in any order.
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<&MetricValue> {
+        let mut labels = labels.to_vec();
+
+        labels.sort_unstable();
+
+        self.series
+            .get(name)?
+            .iter()
+            .find(|series| {
+                series.labels.len() == labels.len()
+                    && series
+                        .labels
+                        .iter()
+                        .zip(&labels)
+                        .all(|((n1, v1), (n2, v2))| n1 == n2 && v1 == v2)
+            })
+            .map(|series| &series.value)
+    }
+
+    /// Returns the value of the counter, the gauge or the untyped series with the name and
+    /// the labels, see [`MetricsSnapshot::get`].
+    pub fn value(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        self.get(name, labels)?.as_f64()
+    }
+
+    /// Returns the histogram with the name and the labels, see [`MetricsSnapshot::get`].
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)]) -> Option<&HistogramValue> {
+        self.get(name, labels)?.as_histogram()
+    }
+
+    /// Returns the labels and the values of all the series with the name.
+    pub fn series<'a>(
+        &'a self,
+        name: &str,
+    ) -> impl Iterator<Item = (&'a [(String, String)], &'a MetricValue)> + 'a {
+        self.series
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|series| (&series.labels[..], &series.value))
+    }
+
+    /// Returns the names of the series in the snapshot, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.series.keys().map(String::as_str)
+    }
+
+    /// Parses the snapshot from the metrics in the text format.
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        let mut snapshot = Self::default();
+
+        for family in exposition::parse(text)? {
+            let series = snapshot.series.entry(family.name).or_default();
+
+            for metric in family.metrics {
+                let mut labels = metric.labels;
+
+                labels.sort_unstable();
+
+                let value = match metric.value {
+                    Value::Scalar(v) => match family.kind {
+                        Kind::Counter => MetricValue::Counter(v),
+                        Kind::Gauge | Kind::Info => MetricValue::Gauge(v),
+                        _ => MetricValue::Untyped(v),
+                    },
+                    Value::Histogram(histogram) => {
+                        let histogram = HistogramValue {
+                            count: histogram.data.count,
+                            sum: histogram.data.sum,
+                            buckets: histogram.data.classic,
+                        };
+
+                        match family.kind {
+                            Kind::GaugeHistogram => MetricValue::GaugeHistogram(histogram),
+                            _ => MetricValue::Histogram(histogram),
+                        }
+                    }
+                };
+
+                series.push(Series { labels, value });
+            }
+        }
+
+        snapshot.series.retain(|_, series| !series.is_empty());
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_typed_values() {
+        let text = concat!(
+            "# HELP requests Number of requests.\n",
+            "# TYPE requests counter\n",
+            "requests_total{path=\"/a\",method=\"GET\"} 1\n",
+            "requests_total{path=\"/b\",method=\"GET\"} 2\n",
+            "# TYPE connections gauge\n",
+            "connections 3\n",
+            "# TYPE latency histogram\n",
+            "latency_sum{host=\"a\"} 1.5\n",
+            "latency_count{host=\"a\"} 2\n",
+            "latency_bucket{host=\"a\",le=\"1.0\"} 1\n",
+            "latency_bucket{host=\"a\",le=\"+Inf\"} 2\n",
+            "# TYPE queue gaugehistogram\n",
+            "queue_gsum 5.0\n",
+            "queue_gcount 1\n",
+            "queue_bucket{le=\"+Inf\"} 1\n",
+            "# EOF\n",
+        );
+
+        let snapshot = MetricsSnapshot::parse(text).unwrap();
+
+        assert_eq!(
+            snapshot.names().collect::<Vec<_>>(),
+            ["connections", "latency", "queue", "requests_total"]
+        );
+
+        assert_eq!(
+            snapshot.get("requests_total", &[("method", "GET"), ("path", "/b")]),
+            Some(&MetricValue::Counter(2.0))
+        );
+        assert_eq!(snapshot.value("requests_total", &[("path", "/b")]), None);
+        assert_eq!(snapshot.series("requests_total").count(), 2);
+        assert_eq!(
+            snapshot.get("connections", &[]),
+            Some(&MetricValue::Gauge(3.0))
+        );
+
+        assert_eq!(
+            snapshot.histogram("latency", &[("host", "a")]),
+            Some(&HistogramValue {
+                count: 2,
+                sum: 1.5,
+                buckets: vec![(1.0, 1), (f64::INFINITY, 2)],
+            })
+        );
+        assert_eq!(snapshot.value("latency", &[("host", "a")]), None);
+
+        assert!(matches!(
+            snapshot.get("queue", &[]),
+            Some(MetricValue::GaugeHistogram(HistogramValue { count: 1, .. }))
+        ));
+    }
+}
@@ -0,0 +1,443 @@
+use super::MetricConstructor;
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// NOTE: the quantiles are estimated within 1% of the observed values.
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// A builder for [`Summary`].
+///
+/// # Example
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Summary, SummaryBuilder};
+/// use std::time::Duration;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Size of the request bodies.
+///     #[ctor = SummaryBuilder {
+///         quantiles: &[0.5, 0.99, 0.999],
+///         max_age: Duration::from_secs(60),
+///         age_buckets: 6,
+///     }]
+///     pub fn request_body_size() -> Summary;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SummaryBuilder {
+    /// The reported quantiles, between 0 and 1.
+    pub quantiles: &'static [f64],
+
+    /// Duration of the sliding time window the quantiles are computed over.
+    pub max_age: Duration,
+
+    /// Number of the buckets the time window is split into. The observations of the oldest
+    /// bucket are discarded all at once when it leaves the window, so more buckets make
+    /// the window slide more smoothly at the cost of memory.
+    pub age_buckets: usize,
+}
+
+impl SummaryBuilder {
+    /// The default builder, reporting the median, the 90th and the 99th percentiles over the last
+    /// 10 minutes, in 5 buckets.
+    pub const DEFAULT: Self = Self {
+        quantiles: &[0.5, 0.9, 0.99],
+        max_age: Duration::from_secs(600),
+        age_buckets: 5,
+    };
+}
+
+impl Default for SummaryBuilder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MetricConstructor<Summary> for SummaryBuilder {
+    fn new_metric(&self) -> Summary {
+        Summary::new(self)
+    }
+}
+
+/// A metric that reports the quantiles of the observed values over a sliding time window.
+///
+/// Histograms need many buckets, and so series, to have a good resolution over a wide range of
+/// values, while range gauges only report the extremes. The summary estimates the quantiles
+/// configured with [`SummaryBuilder`] within 1% of the observed values, with memory bounded by
+/// the range of the values rather than their number. It reports the following series:
+///
+/// - `<name>{quantile="<quantile>"}` with the quantiles of the values observed in the time
+///   window, `NaN` if there are none;
+/// - `<name>_sum` with the sum of all the observed values;
+/// - `<name>_count` with the number of all the observed values.
+///
+/// Unlike histogram buckets, the quantiles can't be aggregated across instances. As the text
+/// format of `prometheus_client` doesn't support summaries, the series are reported as gauges.
+///
+/// The metric can't be used in metrics with labels, since it reports its own label.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, Summary};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Size of the request bodies.
+///     pub fn request_body_size() -> Summary;
+/// }
+///
+/// fn handle_request(body: &[u8]) {
+///     my_app_metrics::request_body_size().observe(body.len() as f64);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Summary {
+    inner: Arc<SummaryInner>,
+}
+
+#[derive(Debug)]
+struct SummaryInner {
+    quantiles: &'static [f64],
+    bucket_duration: Duration,
+    created_at: Instant,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    // NOTE: the buckets are indexed by their number since the creation of the summary modulo
+    // the number of buckets, the stale ones are reset on the first observation.
+    buckets: Vec<AgeBucket>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Default)]
+struct AgeBucket {
+    number: u64,
+    sketch: Sketch,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        SummaryBuilder::DEFAULT.new_metric()
+    }
+}
+
+impl Summary {
+    fn new(builder: &SummaryBuilder) -> Self {
+        let age_buckets = builder.age_buckets.max(1);
+
+        Self {
+            inner: Arc::new(SummaryInner {
+                quantiles: builder.quantiles,
+                bucket_duration: (builder.max_age / age_buckets as u32)
+                    .max(Duration::from_nanos(1)),
+                created_at: Instant::now(),
+                state: Mutex::new(State {
+                    buckets: (0..age_buckets).map(|_| Default::default()).collect(),
+                    sum: 0.0,
+                    count: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Observes a value. `NaN` values are ignored.
+    pub fn observe(&self, v: f64) {
+        self.observe_at(Instant::now(), v)
+    }
+
+    /// Returns the estimated quantile of the values observed in the time window, or `NaN` if
+    /// there are none.
+    pub fn quantile(&self, q: f64) -> f64 {
+        self.window_at(Instant::now()).quantile(q)
+    }
+
+    /// Returns the sum of all the observed values.
+    pub fn sum(&self) -> f64 {
+        self.inner.state.lock().sum
+    }
+
+    /// Returns the number of all the observed values.
+    pub fn count(&self) -> u64 {
+        self.inner.state.lock().count
+    }
+
+    fn bucket_number(&self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.inner.created_at);
+
+        (elapsed.as_nanos() / self.inner.bucket_duration.as_nanos()) as u64
+    }
+
+    fn observe_at(&self, now: Instant, v: f64) {
+        if v.is_nan() {
+            return;
+        }
+
+        let number = self.bucket_number(now);
+        let mut state = self.inner.state.lock();
+        let len = state.buckets.len() as u64;
+        let bucket = &mut state.buckets[(number % len) as usize];
+
+        if bucket.number != number {
+            *bucket = AgeBucket {
+                number,
+                sketch: Default::default(),
+            };
+        }
+
+        bucket.sketch.add(v);
+        state.sum += v;
+        state.count += 1;
+    }
+
+    fn window_at(&self, now: Instant) -> Sketch {
+        let number = self.bucket_number(now);
+        let state = self.inner.state.lock();
+        let len = state.buckets.len() as u64;
+        let mut window = Sketch::default();
+
+        for bucket in &state.buckets {
+            if number - bucket.number.min(number) < len {
+                window.merge(&bucket.sketch);
+            }
+        }
+
+        window
+    }
+}
+
+impl TypedMetric for Summary {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for Summary {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let window = self.window_at(Instant::now());
+        let (sum, count) = {
+            let state = self.inner.state.lock();
+
+            (state.sum, state.count)
+        };
+
+        for &quantile in self.inner.quantiles {
+            encoder
+                .with_label_set(&QuantileLabel(quantile))
+                .no_suffix()?
+                .no_bucket()?
+                .encode_value(window.quantile(quantile))?
+                .no_exemplar()?;
+        }
+
+        encoder
+            .encode_suffix("sum")?
+            .no_bucket()?
+            .encode_value(sum)?
+            .no_exemplar()?;
+
+        encoder
+            .encode_suffix("count")?
+            .no_bucket()?
+            .encode_value(count)?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+struct QuantileLabel(f64);
+
+impl Encode for QuantileLabel {
+    fn encode(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(b"quantile=\"")?;
+        self.0.encode(writer)?;
+        writer.write_all(b"\"")
+    }
+}
+
+/// Counts of the observed values in logarithmically sized buckets, so the value of each bucket is
+/// within the relative accuracy of the values counted in it.
+#[derive(Debug, Default)]
+struct Sketch {
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero: u64,
+    count: u64,
+}
+
+impl Sketch {
+    fn gamma() -> f64 {
+        (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+    }
+
+    fn index(v: f64) -> i32 {
+        (v.ln() / Self::gamma().ln()).ceil() as i32
+    }
+
+    fn value(index: i32) -> f64 {
+        let gamma = Self::gamma();
+
+        2.0 * gamma.powi(index) / (gamma + 1.0)
+    }
+
+    fn add(&mut self, v: f64) {
+        if v >= f64::MIN_POSITIVE {
+            *self.positive.entry(Self::index(v)).or_default() += 1;
+        } else if v <= -f64::MIN_POSITIVE {
+            *self.negative.entry(Self::index(-v)).or_default() += 1;
+        } else {
+            self.zero += 1;
+        }
+
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Sketch) {
+        for (&index, &count) in &other.positive {
+            *self.positive.entry(index).or_default() += count;
+        }
+
+        for (&index, &count) in &other.negative {
+            *self.negative.entry(index).or_default() += count;
+        }
+
+        self.zero += other.zero;
+        self.count += other.count;
+    }
+
+    fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return f64::NAN;
+        }
+
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).round() as u64;
+        let mut seen = 0;
+
+        for (&index, &count) in self.negative.iter().rev() {
+            seen += count;
+
+            if seen > rank {
+                return -Self::value(index);
+            }
+        }
+
+        seen += self.zero;
+
+        if seen > rank {
+            return 0.0;
+        }
+
+        for (&index, &count) in &self.positive {
+            seen += count;
+
+            if seen > rank {
+                return Self::value(index);
+            }
+        }
+
+        unreachable!("rank is lower than the number of the values")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    fn summary(max_age: Duration, age_buckets: usize) -> Summary {
+        Summary::new(&SummaryBuilder {
+            quantiles: &[0.5, 0.9, 0.99],
+            max_age,
+            age_buckets,
+        })
+    }
+
+    #[test]
+    fn estimates_quantiles_within_relative_accuracy() {
+        let summary = summary(Duration::from_secs(60), 3);
+
+        for v in 1..=1000 {
+            summary.observe(v as f64);
+            summary.observe(-(v as f64));
+        }
+
+        summary.observe(0.0);
+        summary.observe(f64::NAN);
+
+        for (q, expected) in [(0.5, 0.0), (0.75, 500.0), (0.99, 980.0), (0.01, -980.0)] {
+            let estimated = summary.quantile(q);
+
+            assert!(
+                (estimated - expected).abs() <= expected.abs() * RELATIVE_ACCURACY,
+                "quantile {q} estimated as {estimated}, expected {expected}"
+            );
+        }
+
+        assert_eq!(summary.count(), 2001);
+        assert_eq!(summary.sum(), 0.0);
+    }
+
+    #[test]
+    fn discards_observations_outside_time_window() {
+        let summary = summary(Duration::from_secs(30), 3);
+        let start = summary.inner.created_at;
+
+        summary.observe_at(start, 100.0);
+        summary.observe_at(start + Duration::from_secs(15), 10.0);
+
+        assert_eq!(summary.window_at(start + Duration::from_secs(25)).count, 2);
+
+        // NOTE: the bucket of the first observation leaves the window.
+        let window = summary.window_at(start + Duration::from_secs(30));
+
+        assert_eq!(window.count, 1);
+        assert!((window.quantile(0.99) - 10.0).abs() <= 10.0 * RELATIVE_ACCURACY);
+
+        summary.observe_at(start + Duration::from_secs(60), 1.0);
+
+        assert_eq!(summary.window_at(start + Duration::from_secs(60)).count, 1);
+        assert_eq!(summary.count(), 3);
+    }
+
+    #[test]
+    fn encodes_quantiles_sum_and_count() {
+        let summary = summary(Duration::from_secs(60), 1);
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        registry.register("latency", "Latency", Box::new(summary.clone()));
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains("latency{quantile=\"0.5\"} NaN\n"));
+
+        summary.observe(2.0);
+        summary.observe(2.0);
+
+        let mut buffer = vec![];
+
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains("# TYPE latency gauge\n"));
+        assert!(encoded.contains("latency{quantile=\"0.99\"} 1.99"));
+        assert!(encoded.contains("latency_sum 4.0\n"));
+        assert!(encoded.contains("latency_count 2\n"));
+    }
+}
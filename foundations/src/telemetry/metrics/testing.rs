@@ -0,0 +1,112 @@
+use super::internal::{ModuleMetrics, Registries};
+use crate::telemetry::scope::{Scope, ScopeStack};
+use crate::telemetry::settings::ServiceNameFormat;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// NOTE: checked before the scope stack lookup, so the metrics accesses don't pay for it in
+// the services that never create test telemetry contexts.
+static TEST_METRICS_SCOPE_ENTERED: AtomicBool = AtomicBool::new(false);
+
+static TEST_METRICS_SCOPE_STACK: Lazy<ScopeStack<TestMetrics>> =
+    Lazy::new(|| ScopeStack::new("test_metrics"));
+
+/// Registries of the metrics accessed in the scope of a test telemetry context.
+#[derive(Clone)]
+pub(crate) struct TestMetrics {
+    inner: Arc<TestMetricsInner>,
+}
+
+struct TestMetricsInner {
+    registries: Registries,
+
+    // NOTE: instances of the metrics of the `#[metrics]` modules, keyed by the address of
+    // the module static.
+    modules: Mutex<HashMap<usize, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl TestMetrics {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::new(TestMetricsInner {
+                // NOTE: use the same prefix as the global registries that are not initialized.
+                registries: Registries::new("undefined", &ServiceNameFormat::MetricPrefix),
+                modules: Default::default(),
+            }),
+        }
+    }
+
+    /// Returns the metrics of the module, registering them in the test registries on the first
+    /// access.
+    pub(super) fn module_metrics<T>(&self, module: &'static ModuleMetrics<T>) -> Arc<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        let key = module as *const ModuleMetrics<T> as usize;
+        let mut modules = self.inner.modules.lock();
+
+        let metrics = modules
+            .entry(key)
+            .or_insert_with(|| Arc::new(module.init(&self.inner.registries)));
+
+        Arc::clone(metrics)
+            .downcast()
+            .expect("metrics should have the type of the module")
+    }
+
+    /// Collects the metrics in the Prometheus text format.
+    pub(crate) fn collect(&self) -> String {
+        let mut buffer = Vec::with_capacity(128);
+
+        self.inner
+            .registries
+            .encode(&mut buffer, true)
+            .expect("should encode metrics to a buffer");
+
+        buffer.extend_from_slice(b"# EOF\n");
+
+        String::from_utf8(buffer).expect("metrics should be encoded as UTF-8")
+    }
+}
+
+impl fmt::Debug for TestMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestMetrics").finish_non_exhaustive()
+    }
+}
+
+#[must_use]
+pub(crate) struct TestMetricsScope {
+    _scope: Scope<TestMetrics>,
+}
+
+impl TestMetricsScope {
+    #[inline]
+    pub(crate) fn new(test_metrics: TestMetrics) -> Self {
+        TEST_METRICS_SCOPE_ENTERED.store(true, Ordering::Relaxed);
+
+        Self {
+            _scope: Scope::new(&TEST_METRICS_SCOPE_STACK, test_metrics),
+        }
+    }
+}
+
+pub(crate) fn current_test_metrics() -> Option<TestMetrics> {
+    // NOTE: the scope is entered on the same thread before the lookup, so the relaxed ordering
+    // is sufficient for the flag to be observed.
+    if !TEST_METRICS_SCOPE_ENTERED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    TEST_METRICS_SCOPE_STACK.current()
+}
+
+/// Returns the number of the test metrics scopes entered on the current thread.
+pub(crate) fn scope_depth() -> usize {
+    TEST_METRICS_SCOPE_STACK.depth()
+}
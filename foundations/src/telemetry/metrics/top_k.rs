@@ -0,0 +1,310 @@
+use super::MetricConstructor;
+use parking_lot::Mutex;
+use prometheus_client::encoding::text::{Encode, EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+/// A builder for [`TopK`].
+///
+/// # Example
+///
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, TopK, TopKBuilder};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests of the most requested tenants.
+///     #[ctor = TopKBuilder {
+///         k: 5,
+///         label: "tenant",
+///         ..TopKBuilder::DEFAULT
+///     }]
+///     pub fn top_tenant_requests() -> TopK;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TopKBuilder {
+    /// Number of the label values with the highest counts to report.
+    pub k: usize,
+
+    /// Name of the label the tracked values are reported with.
+    pub label: &'static str,
+
+    /// Number of the label values tracked in memory, at least `k`.
+    ///
+    /// The more values are tracked, the more accurate the counts are, but the more memory is
+    /// used and the slower new values are observed once the capacity is reached.
+    pub capacity: usize,
+}
+
+impl TopKBuilder {
+    /// The default builder, that reports the top 10 values of the `value` label, tracking
+    /// 100 values.
+    pub const DEFAULT: Self = Self {
+        k: 10,
+        label: "value",
+        capacity: 100,
+    };
+}
+
+impl Default for TopKBuilder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MetricConstructor<TopK> for TopKBuilder {
+    fn new_metric(&self) -> TopK {
+        TopK::new(self)
+    }
+}
+
+/// A metric that tracks the label values with the highest counts, e.g. the most requested URLs
+/// or the busiest tenants.
+///
+/// Using such values as labels of regular metrics leads to unbounded cardinality. Instead, the
+/// metric keeps a bounded number of counters with the [space-saving algorithm] and reports two
+/// series:
+///
+/// - `<name>{<label>="<value>"}` with the count of each of the top-K values, configured with
+///   [`TopKBuilder`];
+/// - `<name>_other` with the count of all the other values.
+///
+/// The counts are approximate once more distinct values are observed than the builder's capacity:
+/// a value that replaces the least frequent tracked one inherits its count, so the counts can be
+/// overestimated, but heavy hitters are always reported. For the same reason, and as values can
+/// leave the top-K, the series are reported as gauges.
+///
+/// The metric can't be used in metrics with labels, since it reports its own label.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, TopK};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of requests of the most requested URLs.
+///     pub fn top_url_requests() -> TopK;
+/// }
+///
+/// fn handle_request(url: &str) {
+///     my_app_metrics::top_url_requests().observe(url);
+/// }
+/// # }
+/// ```
+///
+/// [space-saving algorithm]: https://www.cs.ucsb.edu/sites/default/files/documents/2005-23.pdf
+#[derive(Clone, Debug)]
+pub struct TopK {
+    inner: Arc<TopKInner>,
+}
+
+#[derive(Debug)]
+struct TopKInner {
+    k: usize,
+    label: &'static str,
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    counts: HashMap<Box<str>, u64>,
+    total: u64,
+}
+
+impl Default for TopK {
+    fn default() -> Self {
+        TopKBuilder::DEFAULT.new_metric()
+    }
+}
+
+impl TopK {
+    fn new(builder: &TopKBuilder) -> Self {
+        Self {
+            inner: Arc::new(TopKInner {
+                k: builder.k,
+                label: builder.label,
+                capacity: builder.capacity.max(builder.k).max(1),
+                state: Default::default(),
+            }),
+        }
+    }
+
+    /// Counts an occurrence of the label value.
+    pub fn observe(&self, value: &str) {
+        self.observe_by(value, 1)
+    }
+
+    /// Counts `n` occurrences of the label value.
+    pub fn observe_by(&self, value: &str, n: u64) {
+        let mut state = self.inner.state.lock();
+
+        state.total += n;
+
+        if let Some(count) = state.counts.get_mut(value) {
+            *count += n;
+            return;
+        }
+
+        if state.counts.len() < self.inner.capacity {
+            state.counts.insert(value.into(), n);
+            return;
+        }
+
+        // NOTE: replace the least frequent value, inheriting its count as an upper bound of the
+        // occurrences of the new value that haven't been counted.
+        let min = state
+            .counts
+            .iter()
+            .min_by_key(|(_, count)| **count)
+            .map(|(value, count)| (value.clone(), *count));
+
+        if let Some((min_value, min_count)) = min {
+            state.counts.remove(&min_value);
+            state.counts.insert(value.into(), min_count + n);
+        }
+    }
+
+    /// Returns the top-K label values along with their counts, in descending order of the counts.
+    pub fn top(&self) -> Vec<(String, u64)> {
+        self.collect().0
+    }
+
+    fn collect(&self) -> (Vec<(String, u64)>, u64) {
+        let state = self.inner.state.lock();
+
+        let mut top: Vec<_> = state
+            .counts
+            .iter()
+            .map(|(value, count)| (value.to_string(), *count))
+            .collect();
+
+        top.sort_unstable_by(|(a_value, a_count), (b_value, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_value.cmp(b_value))
+        });
+
+        top.truncate(self.inner.k);
+
+        let reported: u64 = top.iter().map(|(_, count)| count).sum();
+
+        (top, state.total.saturating_sub(reported))
+    }
+}
+
+struct Label<'a> {
+    key: &'static str,
+    value: &'a str,
+}
+
+impl Encode for Label<'_> {
+    fn encode(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+        write!(writer, "{}=\"", self.key)?;
+
+        for c in self.value.chars() {
+            match c {
+                '\\' => writer.write_all(b"\\\\")?,
+                '"' => writer.write_all(b"\\\"")?,
+                '\n' => writer.write_all(b"\\n")?,
+                c => write!(writer, "{c}")?,
+            }
+        }
+
+        writer.write_all(b"\"")
+    }
+}
+
+impl TypedMetric for TopK {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+impl EncodeMetric for TopK {
+    fn encode(&self, mut encoder: Encoder) -> Result<(), std::io::Error> {
+        let (top, other) = self.collect();
+
+        for (value, count) in &top {
+            let label = Label {
+                key: self.inner.label,
+                value,
+            };
+
+            encoder
+                .with_label_set(&label)
+                .no_suffix()?
+                .no_bucket()?
+                .encode_value(*count)?
+                .no_exemplar()?;
+        }
+
+        encoder
+            .encode_suffix("other")?
+            .no_bucket()?
+            .encode_value(other)?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    fn top_k(k: usize, capacity: usize) -> TopK {
+        TopKBuilder {
+            k,
+            label: "url",
+            capacity,
+        }
+        .new_metric()
+    }
+
+    #[test]
+    fn tracks_heavy_hitters() {
+        let metric = top_k(2, 3);
+
+        for _ in 0..10 {
+            metric.observe("/a");
+        }
+
+        metric.observe_by("/b", 5);
+
+        // NOTE: the rare values replace each other in the only remaining slot, inheriting the
+        // counts.
+        for i in 0..4 {
+            metric.observe(&format!("/rare/{i}"));
+        }
+
+        assert_eq!(
+            metric.collect(),
+            (vec![("/a".to_string(), 10), ("/b".to_string(), 5)], 4)
+        );
+    }
+
+    #[test]
+    fn encodes_top_values_and_other() {
+        let metric = top_k(1, 10);
+        let mut registry = <Registry>::default();
+        let mut buffer = vec![];
+
+        metric.observe_by("/a\"b", 3);
+        metric.observe("/c");
+
+        registry.register("requests", "Requests", Box::new(metric.clone()));
+        encode(&mut buffer, &registry).unwrap();
+
+        let encoded = String::from_utf8(buffer).unwrap();
+
+        assert!(encoded.contains("requests{url=\"/a\\\"b\"} 3\n"));
+        assert!(encoded.contains("requests_other 1\n"));
+    }
+}
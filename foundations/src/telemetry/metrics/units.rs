@@ -0,0 +1,201 @@
+use super::{Counter, HistogramBuilder, HistogramTimer, MetricConstructor, TimeHistogram};
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+use prometheus_client::metrics::{MetricType, TypedMetric};
+use std::fmt;
+use std::time::Duration;
+
+/// A histogram of durations reported in seconds.
+///
+/// Unlike [`TimeHistogram`], whose `observe` method takes a raw number of nanoseconds, the
+/// histogram only accepts [`Duration`]s, so the observed values can't be reported in a wrong
+/// unit. When defined with the [`metrics`] macro, the metric name gets the `_seconds` suffix,
+/// unless it already has one.
+///
+/// The histogram needs to be built with [`HistogramBuilder`] with the buckets specified in
+/// seconds.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, DurationHistogram, HistogramBuilder};
+/// use std::time::Duration;
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Duration of the request handling, reported with the `_seconds` suffix.
+///     #[ctor = HistogramBuilder { buckets: &[0.01, 0.1, 1.0] }]
+///     pub fn request_duration() -> DurationHistogram;
+/// }
+///
+/// fn usage() {
+///     my_app_metrics::request_duration().observe(Duration::from_millis(42));
+///
+///     let _timer = my_app_metrics::request_duration().start_timer();
+/// }
+/// # }
+/// ```
+///
+/// [`metrics`]: super::metrics
+#[derive(Clone, Debug)]
+pub struct DurationHistogram(TimeHistogram);
+
+impl DurationHistogram {
+    /// Creates a new histogram with the buckets specified in seconds.
+    pub fn new(buckets: impl Iterator<Item = f64>) -> Self {
+        Self(TimeHistogram::new(buckets))
+    }
+
+    /// Observes the duration.
+    #[inline]
+    pub fn observe(&self, duration: Duration) {
+        self.0
+            .observe(duration.as_nanos().try_into().unwrap_or(u64::MAX));
+    }
+
+    /// Starts a timer that observes the elapsed time when stopped or dropped.
+    #[inline]
+    pub fn start_timer(&self) -> HistogramTimer {
+        self.0.start_timer()
+    }
+}
+
+impl MetricConstructor<DurationHistogram> for HistogramBuilder {
+    fn new_metric(&self) -> DurationHistogram {
+        DurationHistogram::new(self.buckets.iter().cloned())
+    }
+}
+
+impl TypedMetric for DurationHistogram {
+    const TYPE: MetricType = MetricType::Histogram;
+}
+
+impl EncodeMetric for DurationHistogram {
+    fn encode(&self, encoder: Encoder) -> Result<(), std::io::Error> {
+        self.0.encode(encoder)
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+/// A counter of bytes.
+///
+/// The counter only exposes byte-oriented methods, so sizes can't be confused with the number of
+/// events at call sites. When defined with the [`metrics`] macro, the metric name gets the
+/// `_bytes` suffix, unless it already has one.
+///
+/// # Examples
+/// ```
+/// # mod rustdoc_workaround {
+/// use foundations::telemetry::metrics::{metrics, ByteCounter};
+///
+/// #[metrics]
+/// pub mod my_app_metrics {
+///     /// Number of bytes received from the clients, reported with the `_bytes` suffix.
+///     pub fn received() -> ByteCounter;
+/// }
+///
+/// fn usage(body: &[u8]) {
+///     my_app_metrics::received().inc_bytes(body.len());
+/// }
+/// # }
+/// ```
+///
+/// [`metrics`]: super::metrics
+#[derive(Clone, Default)]
+pub struct ByteCounter(Counter);
+
+impl ByteCounter {
+    /// Increases the counter by the number of bytes, returning the previous value.
+    #[inline]
+    pub fn inc_bytes(&self, bytes: usize) -> u64 {
+        self.0.inc_by(bytes as u64)
+    }
+
+    /// Returns the current number of bytes.
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+impl fmt::Debug for ByteCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ByteCounter").field(&self.get()).finish()
+    }
+}
+
+impl TypedMetric for ByteCounter {
+    const TYPE: MetricType = MetricType::Counter;
+}
+
+impl EncodeMetric for ByteCounter {
+    fn encode(&self, encoder: Encoder) -> Result<(), std::io::Error> {
+        self.0.encode(encoder)
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus_client::registry::{Registry, Unit};
+
+    #[test]
+    fn encodes_with_unit_suffixes() {
+        let histogram = DurationHistogram::new([0.1, 1.0].into_iter());
+        let counter = ByteCounter::default();
+
+        histogram.observe(Duration::from_millis(500));
+        counter.inc_bytes(1024);
+
+        let mut registry = Registry::<Box<dyn EncodeMetric>>::default();
+
+        registry.register_with_unit("latency", "Latency", Unit::Seconds, Box::new(histogram));
+        registry.register_with_unit("received", "Received", Unit::Bytes, Box::new(counter));
+
+        let mut buffer = vec![];
+
+        prometheus_client::encoding::text::encode(&mut buffer, &registry).unwrap();
+
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(text.contains("latency_seconds_sum 0.5\n"));
+        assert!(text.contains("latency_seconds_bucket{le=\"1.0\"} 1\n"));
+        assert!(text.contains("received_bytes 1024\n"));
+    }
+
+    #[test]
+    fn timer_records_on_early_return() {
+        let histogram = DurationHistogram::new([0.1, 1.0].into_iter());
+
+        let parse = |body: &str| -> Result<u64, std::num::ParseIntError> {
+            let _timer = histogram.start_timer();
+
+            body.parse()
+        };
+
+        assert!(parse("42").is_ok());
+        assert!(parse("not a number").is_err());
+
+        let timer = histogram.start_timer();
+
+        timer.stop_and_discard();
+
+        let mut registry = Registry::<Box<dyn EncodeMetric>>::default();
+
+        registry.register("parse", "Parse", Box::new(histogram));
+
+        let mut buffer = vec![];
+
+        prometheus_client::encoding::text::encode(&mut buffer, &registry).unwrap();
+
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .contains("parse_count 2\n"));
+    }
+}
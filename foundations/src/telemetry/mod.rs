@@ -58,6 +58,15 @@ pub mod metrics;
 #[cfg(feature = "tracing")]
 pub mod tracing;
 
+#[cfg(all(
+    feature = "tls-telemetry",
+    any(feature = "logging", feature = "tracing")
+))]
+pub mod tls;
+
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+
 #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
 mod memory_profiler;
 
@@ -66,15 +75,32 @@ pub mod settings;
 #[cfg(feature = "telemetry-server")]
 mod server;
 
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+mod fd_monitor;
+
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+mod cpu_throttling;
+
+#[cfg(any(feature = "otlp-metrics", feature = "metrics-push"))]
+mod http_client;
+
+mod hosted_service;
+mod process_state;
+mod selfcheck;
+mod startup_report;
+
 use self::settings::TelemetrySettings;
 use crate::utils::feature_use;
 use crate::{BootstrapResult, ServiceInfo};
+use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
 use std::task::{Context, Poll};
 
 feature_use!(cfg(feature = "logging"), {
-    use self::log::internal::{current_log, fork_log, LogScope, SharedLog};
+    use self::log::internal::{current_log, fork_log, fork_log_with_fields, LogScope, SharedLog};
+    use self::log::InheritedLogFields;
     use std::sync::Arc;
 });
 
@@ -89,8 +115,35 @@ feature_use!(cfg(feature = "tracing"), {
     });
 });
 
+#[cfg(all(feature = "metrics", feature = "testing"))]
+use self::metrics::testing::{current_test_metrics, TestMetrics, TestMetricsScope};
+
 #[cfg(feature = "testing")]
-pub use self::testing::TestTelemetryContext;
+pub use self::testing::{ScopeLeakDetector, TestTelemetryContext};
+
+pub use self::hosted_service::HostedService;
+pub use self::process_state::{ExitReason, ProcessState};
+pub use self::selfcheck::{SelfCheck, SelfCheckReport};
+pub use self::startup_report::StartupReport;
+
+// NOTE: unlike scope stacks, which are empty by the time a thread exits, the thread context
+// outlives all the scopes of the thread, so we use std's thread local storage for it to be
+// dropped on thread exit and not be inherited by a new thread that reuses the thread id.
+#[cfg(any(feature = "logging", feature = "tracing"))]
+thread_local! {
+    static THREAD_CONTEXT: std::cell::RefCell<Option<TelemetryContext>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Calls the provided function with the context installed with
+/// [`TelemetryContext::install_on_current_thread`], if any.
+#[cfg(any(feature = "logging", feature = "tracing"))]
+pub(crate) fn with_thread_context<R>(f: impl FnOnce(&TelemetryContext) -> R) -> Option<R> {
+    THREAD_CONTEXT
+        .try_with(|ctx| ctx.borrow().as_ref().map(f))
+        .ok()
+        .flatten()
+}
 
 #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
 pub use self::memory_profiler::MemoryProfiler;
@@ -102,6 +155,8 @@ pub use self::server::{
 
 /// A macro that enables telemetry testing in `#[test]` and `#[tokio::test]`.
 ///
+/// The test fails if it leaks telemetry scopes on the test thread, see [`ScopeLeakDetector`].
+///
 /// # Wrapping `#[test]`
 /// ```
 /// use foundations::telemetry::tracing::{self, test_trace};
@@ -220,6 +275,11 @@ pub struct TelemetryScope {
     // the harness.
     #[cfg(all(feature = "tracing", feature = "testing"))]
     _test_tracer_scope: Option<TestTracerScope>,
+
+    // NOTE: the metrics of the `metrics` macro are static, so we need to scope the test metrics
+    // for them to be registered in the test registries instead of the global ones.
+    #[cfg(all(feature = "metrics", feature = "testing"))]
+    _test_metrics_scope: Option<TestMetricsScope>,
 }
 
 /// Implicit context for logging and tracing.
@@ -240,6 +300,9 @@ pub struct TelemetryContext {
 
     #[cfg(all(feature = "tracing", feature = "testing"))]
     test_tracer: Option<Tracer>,
+
+    #[cfg(all(feature = "metrics", feature = "testing"))]
+    test_metrics: Option<TestMetrics>,
 }
 
 impl TelemetryContext {
@@ -254,6 +317,9 @@ impl TelemetryContext {
 
             #[cfg(all(feature = "tracing", feature = "testing"))]
             test_tracer: current_test_tracer(),
+
+            #[cfg(all(feature = "metrics", feature = "testing"))]
+            test_metrics: current_test_metrics(),
         }
     }
 
@@ -314,9 +380,68 @@ impl TelemetryContext {
 
             #[cfg(all(feature = "tracing", feature = "testing"))]
             _test_tracer_scope: self.test_tracer.as_ref().cloned().map(TestTracerScope::new),
+
+            #[cfg(all(feature = "metrics", feature = "testing"))]
+            _test_metrics_scope: self.test_metrics.clone().map(TestMetricsScope::new),
         }
     }
 
+    /// Installs the telemetry context as the base context of the current thread.
+    ///
+    /// Unlike [`TelemetryContext::scope`], the installed context is not bound to a lexical
+    /// scope: it is used on the thread whenever there is no other active scope, until the thread
+    /// exits or the context is removed with [`TelemetryContext::uninstall_from_current_thread`].
+    /// Installing a context replaces the previously installed one.
+    ///
+    /// This is useful for threads that are not managed by the service code, e.g. threads of
+    /// third-party libraries that invoke FFI callbacks, or dedicated OS threads with a complex
+    /// control flow. Without any context installed, telemetry produced on such threads uses the
+    /// root logger and starts new traces.
+    ///
+    /// # Examples
+    /// ```
+    /// use foundations::telemetry::TelemetryContext;
+    /// use foundations::telemetry::log::{self, TestLogRecord};
+    /// use foundations::telemetry::settings::Level;
+    ///
+    /// // Test context is used for demonstration purposes to show the resulting log records.
+    /// let ctx = TelemetryContext::test();
+    ///
+    /// {
+    ///     let _scope = ctx.scope();
+    ///     let thread_ctx = TelemetryContext::current();
+    ///
+    ///     std::thread::spawn(move || {
+    ///         thread_ctx.install_on_current_thread();
+    ///
+    ///         log::add_fields!("thread" => "worker");
+    ///         log::warn!("callback invoked");
+    ///     })
+    ///     .join()
+    ///     .unwrap();
+    /// }
+    ///
+    /// assert_eq!(
+    ///     *ctx.log_records(),
+    ///     &[TestLogRecord {
+    ///         level: Level::Warning,
+    ///         message: "callback invoked".into(),
+    ///         fields: vec![("thread".into(), "worker".into())]
+    ///     }]
+    /// );
+    /// ```
+    pub fn install_on_current_thread(&self) {
+        #[cfg(any(feature = "logging", feature = "tracing"))]
+        THREAD_CONTEXT.with(|ctx| *ctx.borrow_mut() = Some(self.clone()));
+    }
+
+    /// Removes the telemetry context installed on the current thread with
+    /// [`TelemetryContext::install_on_current_thread`].
+    pub fn uninstall_from_current_thread() {
+        #[cfg(any(feature = "logging", feature = "tracing"))]
+        THREAD_CONTEXT.with(|ctx| *ctx.borrow_mut() = None);
+    }
+
     /// Creates a test telemetry context.
     ///
     /// Returned context has the same API as standard context, but also exposes API to obtain the
@@ -502,6 +627,9 @@ impl TelemetryContext {
 
             #[cfg(feature = "testing")]
             test_tracer: self.test_tracer.clone(),
+
+            #[cfg(all(feature = "metrics", feature = "testing"))]
+            test_metrics: self.test_metrics.clone(),
         }
     }
 
@@ -637,6 +765,63 @@ impl TelemetryContext {
 
             #[cfg(all(feature = "tracing", feature = "testing"))]
             test_tracer: self.test_tracer.clone(),
+
+            #[cfg(all(feature = "metrics", feature = "testing"))]
+            test_metrics: self.test_metrics.clone(),
+        }
+    }
+
+    /// Creates a telemetry context with log that is detached from the current context's log and
+    /// inherits only the specified fields of it.
+    ///
+    /// Unlike [`TelemetryContext::with_forked_log`], can be used for the long-lived background
+    /// tasks spawned from the request scopes, so they don't carry the stale request fields.
+    /// The fields of the root log, e.g. the service version, are always inherited.
+    ///
+    /// # Examples
+    /// ```
+    /// use foundations::telemetry::TelemetryContext;
+    /// use foundations::telemetry::log::{self, InheritedLogFields, TestLogRecord};
+    /// use foundations::telemetry::settings::Level;
+    ///
+    /// // Test context is used for demonstration purposes to show the resulting log records.
+    /// let ctx = TelemetryContext::test();
+    /// let _scope = ctx.scope();
+    ///
+    /// log::add_fields!("conn_id" => 42, "req_id" => 1);
+    ///
+    /// {
+    ///     let _scope = TelemetryContext::current()
+    ///         .with_forked_log_fields(InheritedLogFields::except(["req_id"]))
+    ///         .scope();
+    ///
+    ///     log::add_fields!("task" => "cache_refresh");
+    ///     log::warn!("Hello from background task");
+    /// }
+    ///
+    /// assert_eq!(*ctx.log_records(), &[
+    ///     TestLogRecord {
+    ///         level: Level::Warning,
+    ///         message: "Hello from background task".into(),
+    ///         fields: vec![
+    ///             ("task".into(), "cache_refresh".into()),
+    ///             ("conn_id".into(), "42".into()),
+    ///         ]
+    ///     },
+    /// ]);
+    /// ```
+    pub fn with_forked_log_fields(&self, fields: InheritedLogFields) -> Self {
+        Self {
+            log: fork_log_with_fields(fields),
+
+            #[cfg(feature = "tracing")]
+            span: self.span.clone(),
+
+            #[cfg(all(feature = "tracing", feature = "testing"))]
+            test_tracer: self.test_tracer.clone(),
+
+            #[cfg(all(feature = "metrics", feature = "testing"))]
+            test_metrics: self.test_metrics.clone(),
         }
     }
 }
@@ -649,6 +834,19 @@ impl TelemetryContext {
 ///
 /// The function should be called once on service initialization. Consequent calls to the function
 /// don't have any effect.
+///
+/// # Usage before initialization
+///
+/// Telemetry can be used before the function is called, e.g. by libraries during the service
+/// bootstrap:
+/// - log records are buffered in memory and logged with the initialized log, tagged with the
+///   `pre_init` field. Only the 1024 most recent records are buffered, the number of the dropped
+///   ones is reported in a warning once the logging is initialized.
+/// - metrics are registered and updated as usual, since they don't depend on the settings.
+/// - spans are not recorded, as the sampling can't be decided without the settings.
+///
+/// APIs that can't be meaningfully used before the initialization, such as
+/// `log::set_verbosity`, return [`NotInitializedError`].
 pub fn init(service_info: &ServiceInfo, settings: &TelemetrySettings) -> BootstrapResult<()> {
     #[cfg(all(
         not(feature = "metrics"),
@@ -657,9 +855,6 @@ pub fn init(service_info: &ServiceInfo, settings: &TelemetrySettings) -> Bootstr
     ))]
     let _ = service_info;
 
-    #[cfg(all(not(feature = "logging"), not(feature = "tracing"),))]
-    let _ = settings;
-
     #[cfg(feature = "logging")]
     self::log::init::init(service_info, &settings.logging)?;
 
@@ -669,20 +864,149 @@ pub fn init(service_info: &ServiceInfo, settings: &TelemetrySettings) -> Bootstr
     #[cfg(feature = "metrics")]
     self::metrics::init::init(service_info, &settings.metrics);
 
+    #[cfg(feature = "otlp-metrics")]
+    self::metrics::otlp::start(service_info, &settings.metrics, &settings.proxy)?;
+
+    #[cfg(feature = "metrics-push")]
+    self::metrics::push::start(service_info, &settings.metrics, &settings.proxy)?;
+
+    #[cfg(all(target_os = "linux", feature = "metrics"))]
+    self::fd_monitor::start(&settings.fd_monitor)?;
+
+    #[cfg(all(target_os = "linux", feature = "metrics"))]
+    self::cpu_throttling::start(&settings.cpu_throttling_monitor)?;
+
+    self::process_state::init(settings.state_file.as_deref())?;
+
+    Ok(())
+}
+
+/// An error returned by the telemetry APIs that require the telemetry to be initialized with
+/// [`init`] when they are called before the initialization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotInitializedError {
+    component: &'static str,
+}
+
+impl NotInitializedError {
+    #[cfg_attr(not(feature = "logging"), allow(dead_code))]
+    pub(crate) fn new(component: &'static str) -> Self {
+        Self { component }
+    }
+
+    /// Returns the telemetry component that hasn't been initialized, e.g. `logging`.
+    pub fn component(&self) -> &'static str {
+        self.component
+    }
+}
+
+impl fmt::Display for NotInitializedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has not been initialized with `telemetry::init`",
+            self.component
+        )
+    }
+}
+
+impl std::error::Error for NotInitializedError {}
+
+/// Applies the changed telemetry settings, e.g. after the settings have been reloaded from
+/// the configuration file, without restarting the process.
+///
+/// The telemetry pipeline components affected by the changes are rebuilt:
+/// - the main log output, if `LoggingSettings::output` or `LoggingSettings::format` have
+///   changed. The records that are already being written to the previous output are flushed.
+/// - the additional log outputs, if any of them have changed. The records that are already
+///   being written to the previous outputs are flushed.
+/// - the tracer and the traces reporter, if any of the `TracingSettings` have changed, e.g.
+///   the sampling ratio or the traces output. The spans of the traces started before the reload
+///   are reported to the previous output.
+/// - the span duration SLOs, with the **metrics** feature enabled. The spans started before
+///   the reload are measured against the previous SLOs.
+///
+/// All the affected components are built before any of them are replaced, so if the function
+/// returns an error the telemetry pipeline remains intact. Other telemetry settings as well as
+/// `TracingSettings::trace_id_generation` are not reloaded.
+///
+/// The function has no effect on the components that haven't been initialized with [`init`],
+/// except for tracing which is initialized if it gets enabled by the reloaded settings.
+pub fn reload(service_info: &ServiceInfo, settings: &TelemetrySettings) -> BootstrapResult<()> {
+    // NOTE: prevents concurrent reloads from interleaving their changes.
+    static RELOAD_LOCK: Mutex<()> = Mutex::new(());
+
+    let _guard = RELOAD_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    #[cfg(not(feature = "tracing"))]
+    let _ = service_info;
+
+    #[cfg(all(not(feature = "logging"), not(feature = "tracing")))]
+    let _ = settings;
+
+    #[cfg(feature = "logging")]
+    let log_reload = self::log::init::prepare_reload(&settings.logging)?;
+
+    #[cfg(feature = "tracing")]
+    let tracer_reload = self::tracing::init::prepare_reload(service_info, &settings.tracing)?;
+
+    #[cfg(feature = "logging")]
+    if let Some(log_reload) = log_reload {
+        log_reload.apply();
+    }
+
+    #[cfg(feature = "tracing")]
+    if let Some(tracer_reload) = tracer_reload {
+        tracer_reload.apply();
+    }
+
+    #[cfg(all(feature = "tracing", feature = "metrics"))]
+    self::tracing::slo::set_slos(&settings.tracing.slos);
+
     Ok(())
 }
 
 /// Initializes service telemetry and returns a HTTP server to be driven by the caller.
 ///
 /// The server exposes the following URL paths:
-/// - `/health` - telemetry server healtcheck endpoint, returns `200 OK` response if server is functional.
+/// - `/health` - telemetry server healtcheck endpoint, returns `200 OK` response if server is
+///   functional and health checks of all the [`HostedService`]s pass.
 /// - `/metrics` - returns service metrics in [Prometheus text format] (requires **metrics** feature).
+///   Metrics are returned in the [protobuf format] if it's preferred by the `Accept` request header,
+///   which is required to scrape [native histograms].
+/// - `/metrics/<registry>` - returns the metrics of the [named registry] in the same formats as
+///   `/metrics` (requires **metrics** feature).
 /// - `/pprof/heap` - returns [jemalloc] heap profile (requires **memory-profiling** feature).
 /// - `/pprof/heap_stats` returns [jemalloc] heap stats (requires **memory-profiling** feature).
+/// - `/info` - returns the [`StartupReport`] emitted by the service as JSON.
+/// - `/debug/cardinality` - returns the number of series of each metric family, the number of
+///   distinct values of their labels and their estimated contribution to the scrape size as
+///   JSON, see [`collect_cardinality`] (requires **metrics** feature).
+/// - `/debug/metrics/label_sets` - returns label sets of each metric along with the time of their
+///   last update as JSON, if [`MetricsSettings::track_label_set_updates`] is enabled (requires
+///   **metrics** feature).
+/// - `/debug/sandbox` - returns the seccomp enforcement mode, the syscall sandboxes installed
+///   in the process and the number of the denied syscalls, if any of the sandboxes counts them
+///   (requires **security** feature).
 ///
 /// Additional custom routes can be added via `custom_routes` parameter.
 ///
+/// If [`TelemetryServerSettings::read_only`] is enabled, the requests to the routes that could
+/// mutate the service state are rejected, e.g. fault injection changes, and `/pprof/heap` is
+/// not exposed.
+///
+/// If the **metrics** feature is enabled, the number of requests and their duration are reported
+/// for each route as `<service>_foundations_telemetry_server_requests_total` and
+/// `<service>_foundations_telemetry_server_request_duration_seconds`, e.g. to alert on slow
+/// metrics scrapes or failing health checks.
+///
 /// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+/// [protobuf format]: https://prometheus.io/docs/instrumenting/exposition_formats/#protobuf-format
+/// [native histograms]: crate::telemetry::metrics::NativeHistogram
+/// [named registry]: crate::telemetry::metrics::collect_registry
+/// [`MetricsSettings::track_label_set_updates`]: crate::telemetry::settings::MetricsSettings::track_label_set_updates
+/// [`collect_cardinality`]: crate::telemetry::metrics::collect_cardinality
+/// [`TelemetryServerSettings::read_only`]: crate::telemetry::settings::TelemetryServerSettings::read_only
 /// [jemalloc]: https://github.com/jemalloc/jemalloc
 #[cfg(feature = "telemetry-server")]
 pub fn init_with_server(
@@ -0,0 +1,405 @@
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "metrics")]
+use super::metrics::Gauge;
+#[cfg(feature = "metrics")]
+use prometheus_client::encoding::text::{EncodeMetric, Encoder};
+#[cfg(feature = "metrics")]
+use prometheus_client::metrics::{MetricType, TypedMetric};
+
+static STATE: OnceCell<(PathBuf, ProcessState)> = OnceCell::new();
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_process {
+    /// Number of times the service was restarted, according to the process state file.
+    pub fn restart_count() -> Gauge;
+
+    /// Reason of the previous exit of the service, the value is `1` for the reason label.
+    pub fn last_exit_reason(reason: &'static str) -> Gauge;
+
+    /// Time in seconds since the telemetry was initialized.
+    pub fn uptime_seconds() -> UptimeGauge;
+}
+
+/// Reason of the previous exit of the service, see [`ProcessState`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "telemetry-server", derive(serde::Serialize))]
+#[cfg_attr(feature = "telemetry-server", serde(rename_all = "snake_case"))]
+pub enum ExitReason {
+    /// The state file didn't exist, so this is the first start of the service.
+    FirstStart,
+    /// The service exited after calling [`ProcessState::record_clean_exit`].
+    Clean,
+    /// The service panicked.
+    Panic,
+    /// The service was killed by the OOM killer, inferred from the `oom_kill` counter of its
+    /// memory cgroup, if the cgroup outlives the process (Linux only).
+    OomKill,
+    /// The service was terminated without recording the exit reason, e.g. killed with `SIGKILL`
+    /// or crashed with a segfault.
+    Unknown,
+}
+
+impl ExitReason {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn as_str(self) -> &'static str {
+        match self {
+            ExitReason::FirstStart => "first_start",
+            ExitReason::Clean => "clean",
+            ExitReason::Panic => "panic",
+            ExitReason::OomKill => "oom_kill",
+            ExitReason::Unknown => "unknown",
+        }
+    }
+}
+
+/// Restart count and the previous exit reason of the service tracked in the state file specified
+/// by [`TelemetrySettings::state_file`].
+///
+/// The file is updated on start up, on panics and on [`ProcessState::record_clean_exit`], so
+/// the reason of the previous exit can be inferred on the next start. The state is reported by
+/// the `<app_name>_foundations_process_restart_count` and
+/// `<app_name>_foundations_process_last_exit_reason` metrics and served on the `/info` endpoint
+/// of the telemetry server, so crash loops are visible from the scrape data alone.
+///
+/// [`TelemetrySettings::state_file`]: crate::telemetry::settings::TelemetrySettings::state_file
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "telemetry-server", derive(serde::Serialize))]
+pub struct ProcessState {
+    /// Number of times the service was restarted since the state file was created.
+    pub restart_count: u64,
+
+    /// Reason of the previous exit of the service.
+    pub last_exit_reason: ExitReason,
+
+    /// Unix timestamp in seconds of the current start of the service.
+    pub started_at: u64,
+}
+
+impl ProcessState {
+    /// Returns the state of the process, if the state file is specified in the settings.
+    pub fn get() -> Option<&'static Self> {
+        STATE.get().map(|(_, state)| state)
+    }
+
+    /// Records the clean exit of the service in the state file.
+    ///
+    /// Should be called once the service has shut down, right before the process exits.
+    pub fn record_clean_exit() {
+        record_status(Status::Clean);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Running,
+    Clean,
+    Panic,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Running => "running",
+            Status::Clean => "clean",
+            Status::Panic => "panic",
+        }
+    }
+}
+
+/// Contents of the state file.
+#[derive(Debug, PartialEq)]
+struct StateFile {
+    status: Status,
+    restart_count: u64,
+    oom_kill_count: Option<u64>,
+    started_at: u64,
+}
+
+impl StateFile {
+    fn parse(contents: &str) -> Option<Self> {
+        let mut status = None;
+        let mut restart_count = None;
+        let mut oom_kill_count = None;
+        let mut started_at = None;
+
+        for line in contents.lines() {
+            match line.split_once('=') {
+                Some(("status", "running")) => status = Some(Status::Running),
+                Some(("status", "clean")) => status = Some(Status::Clean),
+                Some(("status", "panic")) => status = Some(Status::Panic),
+                Some(("restart_count", v)) => restart_count = v.parse().ok(),
+                Some(("oom_kill_count", v)) => oom_kill_count = v.parse().ok(),
+                Some(("started_at", v)) => started_at = v.parse().ok(),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            status: status?,
+            restart_count: restart_count?,
+            oom_kill_count,
+            started_at: started_at?,
+        })
+    }
+
+    fn serialize(&self) -> String {
+        let mut contents = format!(
+            "status={}\nrestart_count={}\nstarted_at={}\n",
+            self.status.as_str(),
+            self.restart_count,
+            self.started_at
+        );
+
+        if let Some(count) = self.oom_kill_count {
+            contents.push_str(&format!("oom_kill_count={count}\n"));
+        }
+
+        contents
+    }
+
+    /// Infers the reason of the exit of the process that wrote the file.
+    fn exit_reason(&self, oom_kill_count: Option<u64>) -> ExitReason {
+        match self.status {
+            Status::Clean => ExitReason::Clean,
+            Status::Panic => ExitReason::Panic,
+            Status::Running => match (self.oom_kill_count, oom_kill_count) {
+                (Some(prev), Some(current)) if current > prev => ExitReason::OomKill,
+                _ => ExitReason::Unknown,
+            },
+        }
+    }
+}
+
+/// Reads the state left by the previous process and records the start of the current one.
+pub(super) fn init(path: Option<&Path>) -> crate::BootstrapResult<()> {
+    // NOTE: register the uptime gauge right away, so it's measured from the initialization.
+    #[cfg(feature = "metrics")]
+    let _ = foundations_process::uptime_seconds();
+
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    if STATE.get().is_some() {
+        return Ok(());
+    }
+
+    let prev = match fs::read_to_string(path) {
+        Ok(contents) => StateFile::parse(&contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!("failed to read the process state file {}", path.display())
+            })
+        }
+    };
+
+    let oom_kill_count = oom_kill_count();
+
+    let state = ProcessState {
+        restart_count: prev.as_ref().map_or(0, |prev| prev.restart_count + 1),
+        last_exit_reason: prev.as_ref().map_or(ExitReason::FirstStart, |prev| {
+            prev.exit_reason(oom_kill_count)
+        }),
+        started_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+
+    write(
+        path,
+        &StateFile {
+            status: Status::Running,
+            restart_count: state.restart_count,
+            oom_kill_count,
+            started_at: state.started_at,
+        },
+    )
+    .with_context(|| format!("failed to write the process state file {}", path.display()))?;
+
+    #[cfg(feature = "metrics")]
+    {
+        foundations_process::restart_count().set(state.restart_count);
+        foundations_process::last_exit_reason(state.last_exit_reason.as_str()).set(1);
+    }
+
+    let _ = STATE.set((path.to_path_buf(), state));
+
+    let prev_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        record_status(Status::Panic);
+        prev_hook(info);
+    }));
+
+    Ok(())
+}
+
+fn record_status(status: Status) {
+    // NOTE: only the first exit reason is recorded, e.g. a clean exit after a panic of one of
+    // the threads is reported as a panic.
+    static RECORDED: AtomicBool = AtomicBool::new(false);
+
+    let Some((path, state)) = STATE.get() else {
+        return;
+    };
+
+    if RECORDED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let _ = write(
+        path,
+        &StateFile {
+            status,
+            restart_count: state.restart_count,
+            oom_kill_count: oom_kill_count(),
+            started_at: state.started_at,
+        },
+    );
+}
+
+// NOTE: the file is replaced atomically, so it's never left partially written.
+fn write(path: &Path, state: &StateFile) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, state.serialize())?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Returns the number of the processes killed by the OOM killer in the memory cgroup of
+/// the process.
+#[cfg(target_os = "linux")]
+fn oom_kill_count() -> Option<u64> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+
+    let find_oom_kill = |contents: String| {
+        contents.lines().find_map(|line| {
+            line.strip_prefix("oom_kill ")
+                .and_then(|count| count.trim().parse().ok())
+        })
+    };
+
+    for line in cgroups.lines() {
+        let mut parts = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(cgroup)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+
+        let path = match controllers {
+            // NOTE: cgroup v2.
+            "" => format!("/sys/fs/cgroup{cgroup}/memory.events"),
+            _ if controllers.split(',').any(|c| c == "memory") => {
+                format!("/sys/fs/cgroup/memory{cgroup}/memory.oom_control")
+            }
+            _ => continue,
+        };
+
+        if let Some(count) = fs::read_to_string(path).ok().and_then(find_oom_kill) {
+            return Some(count);
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn oom_kill_count() -> Option<u64> {
+    None
+}
+
+/// A gauge reporting the time since the telemetry was initialized.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Debug)]
+pub(crate) struct UptimeGauge {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "metrics")]
+impl Default for UptimeGauge {
+    fn default() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl TypedMetric for UptimeGauge {
+    const TYPE: MetricType = MetricType::Gauge;
+}
+
+#[cfg(feature = "metrics")]
+impl EncodeMetric for UptimeGauge {
+    fn encode(&self, mut encoder: Encoder) -> std::io::Result<()> {
+        let mut bucket_encoder = encoder.no_suffix()?;
+        let mut value_encoder = bucket_encoder.no_bucket()?;
+
+        value_encoder
+            .encode_value(self.start.elapsed().as_secs_f64())?
+            .no_exemplar()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        Self::TYPE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_exit_reason() {
+        let state = |status, oom_kill_count| StateFile {
+            status,
+            restart_count: 3,
+            oom_kill_count,
+            started_at: 1_700_000_000,
+        };
+
+        assert_eq!(
+            state(Status::Clean, None).exit_reason(None),
+            ExitReason::Clean
+        );
+        assert_eq!(
+            state(Status::Panic, Some(0)).exit_reason(Some(1)),
+            ExitReason::Panic
+        );
+        assert_eq!(
+            state(Status::Running, Some(0)).exit_reason(Some(1)),
+            ExitReason::OomKill
+        );
+        assert_eq!(
+            state(Status::Running, Some(1)).exit_reason(Some(1)),
+            ExitReason::Unknown
+        );
+        assert_eq!(
+            state(Status::Running, None).exit_reason(Some(1)),
+            ExitReason::Unknown
+        );
+    }
+
+    #[test]
+    fn roundtrips_state_file() {
+        let state = StateFile {
+            status: Status::Running,
+            restart_count: 2,
+            oom_kill_count: Some(5),
+            started_at: 1_700_000_000,
+        };
+
+        assert_eq!(StateFile::parse(&state.serialize()), Some(state));
+        assert_eq!(StateFile::parse("status=running\n"), None);
+    }
+}
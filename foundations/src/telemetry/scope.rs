@@ -2,6 +2,21 @@ use std::cell::RefCell;
 use std::marker::PhantomData;
 use thread_local::ThreadLocal;
 
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{self, CollectorType, Samples};
+
+#[cfg(feature = "metrics")]
+use once_cell::sync::OnceCell;
+
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// NOTE: the stacks are registered on the first entered scope, so the collector can sum up their
+// per-thread depths at the scrape time.
+#[cfg(feature = "metrics")]
+static SCOPE_STACKS: parking_lot::Mutex<Vec<&'static dyn ScopeStackDepth>> =
+    parking_lot::Mutex::new(Vec::new());
+
 // NOTE: this prevents scope handles to be held between `await` points by making scope
 // handles `!Send`. Negative trait bounds are not available in Rust yet, so instead we
 // use a raw pointer here which is `!Send`. We introduce a zero-sized structure behind
@@ -9,25 +24,104 @@ use thread_local::ThreadLocal;
 struct DontHoldScopeHandlesAcrossAwaitPoints;
 type AwaitPointGuard = PhantomData<*mut DontHoldScopeHandlesAcrossAwaitPoints>;
 
-pub(crate) struct ScopeStack<T>(ThreadLocal<RefCell<Vec<T>>>)
-where
-    T: Send + Clone + 'static;
+/// Registers the collector of the `<prefix>_foundations_telemetry_scopes` gauge with the number
+/// of the telemetry scopes currently entered on all the threads, by the scope stack.
+#[cfg(feature = "metrics")]
+pub(crate) fn register_collector() {
+    static REGISTERED: std::sync::Once = std::sync::Once::new();
+
+    REGISTERED.call_once(|| {
+        metrics::register_collector(
+            "foundations",
+            "telemetry_scopes",
+            "Number of telemetry scopes currently entered on all the threads, by the scope stack",
+            CollectorType::Gauge,
+            |samples: &mut Samples| {
+                for stack in SCOPE_STACKS.lock().iter() {
+                    samples.add(&[("stack", stack.name())], stack.total_depth() as f64);
+                }
+            },
+        );
+    });
+}
+
+#[cfg(feature = "metrics")]
+trait ScopeStackDepth: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn total_depth(&self) -> usize;
+}
 
-impl<T> Default for ScopeStack<T>
+pub(crate) struct ScopeStack<T>
 where
     T: Send + Clone + 'static,
 {
-    fn default() -> Self {
-        Self(Default::default())
-    }
+    stack: ThreadLocal<RefCell<Vec<T>>>,
+
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    name: &'static str,
+
+    // NOTE: scopes are entered on each poll of the futures with telemetry context, so the depths
+    // are tracked per thread to not contend on a shared counter.
+    #[cfg(feature = "metrics")]
+    depths: ThreadLocal<AtomicUsize>,
+
+    #[cfg(feature = "metrics")]
+    registered: OnceCell<()>,
 }
 
 impl<T> ScopeStack<T>
 where
     T: Send + Clone + 'static,
 {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self {
+            stack: Default::default(),
+            name,
+
+            #[cfg(feature = "metrics")]
+            depths: Default::default(),
+
+            #[cfg(feature = "metrics")]
+            registered: OnceCell::new(),
+        }
+    }
+
     pub(crate) fn current(&self) -> Option<T> {
-        self.0.get_or_default().borrow().last().map(Clone::clone)
+        self.stack.get_or_default().borrow().last().cloned()
+    }
+
+    /// Returns the number of the scopes entered on the current thread.
+    #[cfg(feature = "testing")]
+    pub(crate) fn depth(&self) -> usize {
+        self.stack.get_or_default().borrow().len()
+    }
+
+    #[cfg(feature = "metrics")]
+    fn update_depth(&'static self, depth: usize) {
+        self.registered
+            .get_or_init(|| SCOPE_STACKS.lock().push(self));
+
+        // NOTE: the counter is only written by the owning thread, so it doesn't need an atomic
+        // read-modify-write.
+        self.depths.get_or_default().store(depth, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<T> ScopeStackDepth for ScopeStack<T>
+where
+    T: Send + Clone + 'static,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn total_depth(&self) -> usize {
+        self.depths
+            .iter()
+            .map(|depth| depth.load(Ordering::Relaxed))
+            .sum()
     }
 }
 
@@ -44,7 +138,14 @@ where
     T: Send + Clone + 'static,
 {
     pub(crate) fn new(scope_stack: &'static ScopeStack<T>, item: T) -> Self {
-        scope_stack.0.get_or_default().borrow_mut().push(item);
+        let mut stack = scope_stack.stack.get_or_default().borrow_mut();
+
+        stack.push(item);
+
+        #[cfg(feature = "metrics")]
+        scope_stack.update_depth(stack.len());
+
+        drop(stack);
 
         Self {
             scope_stack,
@@ -58,6 +159,36 @@ where
     T: Send + Clone + 'static,
 {
     fn drop(&mut self) {
-        self.scope_stack.0.get_or_default().borrow_mut().pop();
+        let mut stack = self.scope_stack.stack.get_or_default().borrow_mut();
+
+        stack.pop();
+
+        #[cfg(feature = "metrics")]
+        self.scope_stack.update_depth(stack.len());
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use once_cell::sync::Lazy;
+
+    #[test]
+    fn sums_depths_of_all_threads() {
+        static STACK: Lazy<ScopeStack<u32>> = Lazy::new(|| ScopeStack::new("test_depths"));
+
+        let _outer = Scope::new(&STACK, 1);
+        let _inner = Scope::new(&STACK, 2);
+
+        std::thread::spawn(|| {
+            let _scope = Scope::new(&STACK, 3);
+
+            assert_eq!(STACK.total_depth(), 3);
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(STACK.total_depth(), 2);
+        assert_eq!(STACK.current(), Some(2));
     }
 }
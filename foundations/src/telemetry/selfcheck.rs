@@ -0,0 +1,225 @@
+use super::settings::TelemetrySettings;
+use std::fmt;
+
+#[cfg(feature = "logging")]
+use super::settings::{LogOutput, RotatingFileLogOutput};
+
+#[cfg(feature = "tracing")]
+use super::settings::TracesOutput;
+
+#[cfg(feature = "metrics")]
+use super::metrics::Counter;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_telemetry_selfcheck {
+    /// Number of telemetry self-checks run by the service.
+    pub fn checks_total() -> Counter;
+}
+
+/// A report of the telemetry self-check that emits a test log record, a test span and a test
+/// metric and verifies that the configured telemetry outputs are reachable.
+///
+/// The self-check is meant to be run by the services started with the `--telemetry-selfcheck`
+/// command line option, e.g. in bootstrap scripts, after telemetry is [initialized].
+///
+/// # Examples
+/// ```no_run
+/// use foundations::cli::Cli;
+/// use foundations::telemetry::settings::TelemetrySettings;
+/// use foundations::telemetry::{self, SelfCheckReport};
+///
+/// # fn main() -> foundations::BootstrapResult<()> {
+/// let service_info = foundations::service_info!();
+/// let cli = Cli::<TelemetrySettings>::new(&service_info, vec![])?;
+///
+/// telemetry::init(&service_info, &cli.settings)?;
+///
+/// if cli.telemetry_selfcheck() {
+///     let report = SelfCheckReport::run(&cli.settings);
+///
+///     println!("{report}");
+///     std::process::exit(if report.passed() { 0 } else { 1 });
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [initialized]: crate::telemetry::init
+#[derive(Clone, Debug, Default)]
+pub struct SelfCheckReport {
+    /// Results of the checks of the individual telemetry components.
+    pub checks: Vec<SelfCheck>,
+}
+
+/// Result of the check of a telemetry component in [`SelfCheckReport`].
+#[derive(Clone, Debug)]
+pub struct SelfCheck {
+    /// The checked telemetry component, e.g. `logs`, `traces` or `metrics`.
+    pub component: &'static str,
+
+    /// Whether the check has passed.
+    pub passed: bool,
+
+    /// Human-readable outcome of the check.
+    pub details: String,
+}
+
+impl SelfCheckReport {
+    /// Runs the self-check of the telemetry components enabled by the settings.
+    pub fn run(_settings: &TelemetrySettings) -> Self {
+        Self {
+            checks: vec![
+                #[cfg(feature = "logging")]
+                check_logs(_settings),
+                #[cfg(feature = "tracing")]
+                check_traces(_settings),
+                #[cfg(feature = "metrics")]
+                check_metrics(_settings),
+            ],
+        }
+    }
+
+    /// Returns `true` if all the checks have passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+impl fmt::Display for SelfCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let status = if check.passed { "PASS" } else { "FAIL" };
+
+            writeln!(f, "[{status}] {}: {}", check.component, check.details)?;
+        }
+
+        let outcome = if self.passed() { "passed" } else { "failed" };
+
+        write!(f, "telemetry self-check {outcome}")
+    }
+}
+
+impl SelfCheck {
+    #[cfg_attr(
+        not(any(feature = "logging", feature = "tracing", feature = "metrics", test)),
+        allow(dead_code)
+    )]
+    fn new(component: &'static str, result: Result<String, String>) -> Self {
+        let (passed, details) = match result {
+            Ok(details) => (true, details),
+            Err(details) => (false, details),
+        };
+
+        Self {
+            component,
+            passed,
+            details,
+        }
+    }
+}
+
+#[cfg(feature = "logging")]
+fn check_logs(settings: &TelemetrySettings) -> SelfCheck {
+    crate::telemetry::log::info!("telemetry self-check"; "component" => "logs");
+
+    let result = match &settings.logging.output {
+        LogOutput::Terminal => Ok("test record emitted to the terminal".to_string()),
+        LogOutput::File(path) | LogOutput::RotatingFile(RotatingFileLogOutput { path, .. }) => {
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map(|_| format!("test record emitted to `{}`", path.display()))
+                .map_err(|e| format!("log file `{}` is not writable: {e}", path.display()))
+        }
+    };
+
+    SelfCheck::new("logs", result)
+}
+
+#[cfg(feature = "tracing")]
+fn check_traces(settings: &TelemetrySettings) -> SelfCheck {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+    let settings = &settings.tracing;
+
+    if !settings.enabled {
+        return SelfCheck::new("traces", Ok("tracing is disabled".to_string()));
+    }
+
+    drop(crate::telemetry::tracing::span("telemetry self-check"));
+
+    let result = match &settings.output {
+        TracesOutput::JaegerThriftUdp => {
+            let agent_addr: SocketAddr = settings.jaeger_tracing_server_addr.into();
+
+            let bind_addr: SocketAddr = match settings.jaeger_reporter_bind_addr {
+                Some(addr) => addr.into(),
+                None if agent_addr.is_ipv6() => (Ipv6Addr::UNSPECIFIED, 0).into(),
+                None => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            };
+
+            // NOTE: UDP is connectionless, so only check that the agent address is routable.
+            UdpSocket::bind(bind_addr)
+                .and_then(|socket| socket.connect(agent_addr))
+                .map(|_| format!("test span emitted to the Jaeger agent at {agent_addr}"))
+                .map_err(|e| format!("Jaeger agent at {agent_addr} is not reachable: {e}"))
+        }
+
+        #[cfg(unix)]
+        TracesOutput::UnixSocket(output) => std::os::unix::net::UnixStream::connect(&output.path)
+            .map(|_| format!("test span emitted to `{}`", output.path.display()))
+            .map_err(|e| {
+                format!(
+                    "traces agent socket `{}` is not reachable: {e}",
+                    output.path.display()
+                )
+            }),
+
+        #[cfg(not(unix))]
+        TracesOutput::UnixSocket(_) => {
+            Err("Unix socket traces output is only supported on Unix platforms".to_string())
+        }
+    };
+
+    SelfCheck::new("traces", result)
+}
+
+#[cfg(feature = "metrics")]
+fn check_metrics(settings: &TelemetrySettings) -> SelfCheck {
+    foundations_telemetry_selfcheck::checks_total().inc();
+
+    let result = match crate::telemetry::metrics::collect_without_reset(&settings.metrics) {
+        Ok(text) if text.contains("telemetry_selfcheck_checks_total") => {
+            Ok("test metric collected".to_string())
+        }
+        Ok(_) => Err("test metric is missing in the collected metrics".to_string()),
+        Err(e) => Err(format!("failed to collect metrics: {e}")),
+    };
+
+    SelfCheck::new("metrics", result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_failed_checks() {
+        let report = SelfCheckReport {
+            checks: vec![
+                SelfCheck::new("logs", Ok("test record emitted".to_string())),
+                SelfCheck::new("traces", Err("agent is not reachable".to_string())),
+            ],
+        };
+
+        assert!(!report.passed());
+
+        assert_eq!(
+            report.to_string(),
+            "[PASS] logs: test record emitted\n\
+             [FAIL] traces: agent is not reachable\n\
+             telemetry self-check failed"
+        );
+    }
+}
@@ -1,6 +1,9 @@
 #[cfg(feature = "metrics")]
-use super::metrics;
+use super::metrics::{self, Counter, DurationHistogram, HistogramBuilder};
+#[cfg(feature = "metrics")]
+use super::settings::MetricsSettings;
 use super::settings::TelemetrySettings;
+use super::StartupReport;
 use crate::{BootstrapResult, Result};
 use anyhow::anyhow;
 use futures_util::future::BoxFuture;
@@ -9,6 +12,9 @@ use futures_util::FutureExt;
 use hyper::server::conn::AddrIncoming;
 use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
 use routerify::{Router, RouterService};
+
+#[cfg(feature = "metrics")]
+use routerify::ext::RequestExt;
 use socket2::{Domain, SockAddr, Socket, Type};
 use std::convert::Infallible;
 use std::future::Future;
@@ -17,6 +23,27 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+// NOTE: smaller responses, e.g. of the stale metrics, are not worth compressing.
+#[cfg(all(feature = "metrics", feature = "compression"))]
+const MIN_COMPRESSED_LEN: usize = 1024;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_telemetry_server {
+    /// Number of requests handled by the telemetry server.
+    pub fn requests_total(route: &Arc<String>, status: u16) -> Counter;
+
+    /// Duration of the requests handled by the telemetry server, e.g. of the metrics scrapes
+    /// and health checks.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0],
+    }]
+    pub fn request_duration(route: &Arc<String>) -> DurationHistogram;
+}
+
 /// Telemetry server future returned by [`crate::telemetry::init_with_server`].
 ///
 /// This future drives a HTTP server as configured by [`TelemetryServerSettings`].
@@ -24,6 +51,8 @@ use std::task::{Context, Poll};
 /// [`TelemetryServerSettings`]: `crate::telemetry::settings::TelemetryServerSettings`
 pub struct TelemetryServerFuture {
     pub(super) inner: Option<Server<AddrIncoming, RouterService<Body, Infallible>>>,
+    #[cfg(feature = "metrics")]
+    pub(super) stale_metrics_period: Duration,
 }
 
 /// Transformation of [`TelemetryServerFuture`] when that server is instructed to perform a
@@ -45,10 +74,31 @@ impl TelemetryServerFuture {
     ///
     /// The yielded future should be polled to drive the telemetry server forward.
     /// If telemetry is disabled, the given signal is still awaited for in the yielded future.
+    ///
+    /// If [`TelemetryServerSettings::stale_metrics_period_ms`] is set, the metrics are
+    /// [marked as stale] once the signal completes and the server keeps serving them for that
+    /// period before shutting down.
+    ///
+    /// [`TelemetryServerSettings::stale_metrics_period_ms`]: crate::telemetry::settings::TelemetryServerSettings::stale_metrics_period_ms
+    /// [marked as stale]: crate::telemetry::metrics::mark_stale
     pub fn with_graceful_shutdown(
         self,
         signal: impl Future<Output = ()> + Send + Sync + 'static,
     ) -> TelemetryServerFutureWithGracefulShutdown {
+        #[cfg(feature = "metrics")]
+        let signal = {
+            let period = self.stale_metrics_period;
+
+            async move {
+                signal.await;
+
+                if !period.is_zero() {
+                    metrics::mark_stale();
+                    tokio::time::sleep(period).await;
+                }
+            }
+        };
+
         async move {
             match self.inner {
                 Some(server) => Ok(server.with_graceful_shutdown(signal).await?),
@@ -104,7 +154,11 @@ pub(super) fn init(
     custom_routes: Vec<TelemetryServerRoute>,
 ) -> BootstrapResult<TelemetryServerFuture> {
     if !settings.server.enabled {
-        return Ok(TelemetryServerFuture { inner: None });
+        return Ok(TelemetryServerFuture {
+            inner: None,
+            #[cfg(feature = "metrics")]
+            stale_metrics_period: Duration::ZERO,
+        });
     }
 
     let settings = Arc::new(settings);
@@ -120,6 +174,8 @@ pub(super) fn init(
 
     Ok(TelemetryServerFuture {
         inner: Some(builder.serve(service)),
+        #[cfg(feature = "metrics")]
+        stale_metrics_period: Duration::from_millis(settings.server.stale_metrics_period_ms),
     })
 }
 
@@ -151,27 +207,81 @@ fn create_router(
 
     macro_rules! route {
         ($path:expr, $content_type:expr, $f:expr) => {
-            router = router.get($path, {
-                let settings = Arc::clone(&settings);
-                move |_| {
-                    let res = $f(Arc::clone(&settings));
-                    async move { Ok(into_response($content_type, res.await)) }
-                }
-            })
+            router = router.get(
+                $path,
+                instrument($path, {
+                    let settings = Arc::clone(&settings);
+                    move |_| {
+                        let res = $f(Arc::clone(&settings));
+                        async move { Ok(into_response($content_type, res.await)) }.boxed()
+                    }
+                }),
+            )
         };
     }
 
     route!("/health", "text/plain", health);
 
+    route!("/info", "application/json", info);
+
+    #[cfg(feature = "metrics")]
+    {
+        router = router.get(
+            "/metrics",
+            instrument("/metrics", {
+                let settings = Arc::clone(settings);
+                move |req| {
+                    let settings = Arc::clone(&settings);
+                    async move { Ok(metrics(&req, &settings, None)) }.boxed()
+                }
+            }),
+        );
+
+        router = router.get(
+            "/metrics/:registry",
+            instrument("/metrics/:registry", {
+                let settings = Arc::clone(settings);
+                move |req| {
+                    let settings = Arc::clone(&settings);
+
+                    async move {
+                        let registry = req.param("registry").cloned().unwrap_or_default();
+
+                        if !metrics::has_registry(&registry) {
+                            return Ok(Response::builder()
+                                .status(StatusCode::NOT_FOUND)
+                                .body(format!("unknown metrics registry `{registry}`").into())
+                                .unwrap());
+                        }
+
+                        Ok(metrics(&req, &settings, Some(&registry)))
+                    }
+                    .boxed()
+                }
+            }),
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    route!("/debug/cardinality", "application/json", cardinality);
+
     #[cfg(feature = "metrics")]
-    route!("/metrics", "text/plain; version=0.0.4", metrics);
+    if settings.metrics.track_label_set_updates {
+        route!(
+            "/debug/metrics/label_sets",
+            "application/json",
+            label_set_updates
+        );
+    }
 
     #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
-    route!(
-        "/pprof/heap",
-        "application/x-gperftools-profile",
-        memory_profiling::heap_profile
-    );
+    if !settings.server.read_only {
+        route!(
+            "/pprof/heap",
+            "application/x-gperftools-profile",
+            memory_profiling::heap_profile
+        );
+    }
 
     #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
     route!(
@@ -180,6 +290,17 @@ fn create_router(
         memory_profiling::heap_stats
     );
 
+    #[cfg(all(
+        feature = "security",
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    route!(
+        "/debug/sandbox",
+        "text/plain; charset=utf-8",
+        sandbox::sandbox_state
+    );
+
     for route in custom_routes {
         let TelemetryServerRoute {
             path,
@@ -187,15 +308,70 @@ fn create_router(
             handler,
         } = route;
 
-        router = router.add(path, methods, {
+        let handler = instrument(&path, {
             let settings = Arc::clone(settings);
-            move |req| handler(req, Arc::clone(&settings))
+
+            move |req| {
+                if settings.server.read_only && !req.method().is_safe() {
+                    return async {
+                        Ok(Response::builder()
+                            .status(StatusCode::FORBIDDEN)
+                            .body("telemetry server is in read-only mode".into())
+                            .unwrap())
+                    }
+                    .boxed();
+                }
+
+                handler(req, Arc::clone(&settings))
+            }
         });
+
+        router = router.add(path, methods, handler);
     }
 
     router.build().map_err(|err| anyhow!(err))
 }
 
+/// Instruments the route handler with the request metrics of the telemetry server.
+///
+/// The metrics are prefixed with the service name like the other metrics of the service, e.g.
+/// `<service>_foundations_telemetry_server_requests_total`, so operators can alert on the
+/// scraping and health checking problems.
+#[cfg(feature = "metrics")]
+fn instrument(
+    route: &str,
+    handler: impl Fn(Request<Body>) -> TelemetryRouteHandlerFuture + Send + Sync + 'static,
+) -> impl Fn(Request<Body>) -> TelemetryRouteHandlerFuture + Send + Sync + 'static {
+    let route = Arc::new(route.to_string());
+
+    move |req| {
+        let route = Arc::clone(&route);
+        let start = Instant::now();
+        let res = handler(req);
+
+        async move {
+            let res = res.await;
+
+            foundations_telemetry_server::request_duration(&route).observe(start.elapsed());
+
+            if let Ok(res) = &res {
+                foundations_telemetry_server::requests_total(&route, res.status().as_u16()).inc();
+            }
+
+            res
+        }
+        .boxed()
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+fn instrument(
+    _route: &str,
+    handler: impl Fn(Request<Body>) -> TelemetryRouteHandlerFuture + Send + Sync + 'static,
+) -> impl Fn(Request<Body>) -> TelemetryRouteHandlerFuture + Send + Sync + 'static {
+    handler
+}
+
 fn into_response(content_type: &str, res: crate::Result<impl Into<Body>>) -> Response<Body> {
     match res {
         Ok(data) => Response::builder()
@@ -210,12 +386,188 @@ fn into_response(content_type: &str, res: crate::Result<impl Into<Body>>) -> Res
 }
 
 async fn health(_settings: Arc<TelemetrySettings>) -> Result<&'static str> {
+    let unhealthy = super::hosted_service::unhealthy_services();
+
+    if !unhealthy.is_empty() {
+        return Err(format!("unhealthy services: {}", unhealthy.join(", ")).into());
+    }
+
     Ok("")
 }
 
+async fn info(_settings: Arc<TelemetrySettings>) -> Result<String> {
+    Ok(serde_json::to_string(&StartupReport::get())?)
+}
+
 #[cfg(feature = "metrics")]
-async fn metrics(settings: Arc<TelemetrySettings>) -> Result<String> {
-    metrics::collect(&settings.metrics)
+fn metrics(
+    req: &Request<Body>,
+    settings: &TelemetrySettings,
+    registry: Option<&str>,
+) -> Response<Body> {
+    let scraper = scraper(req);
+
+    let (content_type, res) = metrics::with_scraper(&scraper, || {
+        collect_metrics(req, &settings.metrics, registry)
+    });
+
+    match res {
+        #[cfg(feature = "compression")]
+        Ok(body)
+            if settings.server.compress_metrics
+                && body.len() >= MIN_COMPRESSED_LEN
+                && accepts_gzip(req) =>
+        {
+            match gzip(&body) {
+                Ok(compressed) => Response::builder()
+                    .header(header::CONTENT_TYPE, content_type)
+                    .header(header::CONTENT_ENCODING, "gzip")
+                    .body(compressed.into())
+                    .unwrap(),
+                Err(err) => into_response(content_type, Err::<Vec<u8>, _>(err.into())),
+            }
+        }
+        res => into_response(content_type, res),
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn collect_metrics(
+    req: &Request<Body>,
+    settings: &MetricsSettings,
+    registry: Option<&str>,
+) -> (&'static str, Result<Vec<u8>>) {
+    if accepts_protobuf(req) {
+        let res = match registry {
+            Some(registry) => metrics::collect_registry_protobuf(registry, settings),
+            None => metrics::collect_protobuf(settings),
+        };
+
+        return (metrics::PROTOBUF_CONTENT_TYPE, res);
+    }
+
+    let res = match registry {
+        Some(registry) => metrics::collect_registry(registry, settings),
+        None => metrics::collect(settings),
+    };
+
+    let content_type = if settings.exemplars {
+        metrics::OPENMETRICS_CONTENT_TYPE
+    } else {
+        "text/plain; version=0.0.4"
+    };
+
+    (content_type, res.map(String::into_bytes))
+}
+
+#[cfg(all(feature = "metrics", feature = "compression"))]
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    use crate::compression::{self, CompressionAlgorithm, CompressionSettings};
+
+    // NOTE: the fast compression still shrinks the text format several times, while taking
+    // a fraction of the CPU time of the default level.
+    let settings = CompressionSettings {
+        algorithm: CompressionAlgorithm::Gzip,
+        level: Some(1),
+    };
+
+    compression::compress(data, &settings, "telemetry_server")
+}
+
+#[cfg(feature = "metrics")]
+async fn label_set_updates(settings: Arc<TelemetrySettings>) -> Result<String> {
+    let updates = metrics::collect_label_set_updates(&settings.metrics)?;
+
+    Ok(serde_json::to_string(&updates)?)
+}
+
+#[cfg(feature = "metrics")]
+async fn cardinality(settings: Arc<TelemetrySettings>) -> Result<String> {
+    let report = metrics::collect_cardinality(&settings.metrics)?;
+
+    Ok(serde_json::to_string(&report)?)
+}
+
+/// Returns the identity of the scraper of the metrics, see [`metrics::with_scraper`].
+#[cfg(feature = "metrics")]
+fn scraper(req: &Request<Body>) -> String {
+    let param = req.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("scraper="))
+    });
+
+    match param {
+        Some(scraper) => scraper.to_string(),
+        None => req.remote_addr().ip().to_string(),
+    }
+}
+
+/// Checks if the client accepts gzip-compressed responses, e.g. Prometheus sends
+/// `Accept-Encoding: gzip`.
+#[cfg(all(feature = "metrics", feature = "compression"))]
+fn accepts_gzip(req: &Request<Body>) -> bool {
+    let Some(accept) = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+
+    accept.split(',').any(|coding| {
+        let mut params = coding.split(';').map(str::trim);
+        let coding = params.next().unwrap_or_default();
+
+        let q = params
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(1.0, |q| q.parse().unwrap_or(0.0));
+
+        matches!(coding, "gzip" | "*") && q > 0.0
+    })
+}
+
+/// Checks if the protobuf format is preferred over the text one, e.g. Prometheus with native
+/// histograms enabled sends `Accept: application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,text/plain;version=0.0.4;q=0.3`.
+#[cfg(feature = "metrics")]
+fn accepts_protobuf(req: &Request<Body>) -> bool {
+    let Some(accept) = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mut protobuf_q = None;
+    let mut text_q = None;
+
+    for media_range in accept.split(',') {
+        let mut params = media_range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or_default();
+        let mut q = 1.0;
+        let mut is_metric_family = false;
+
+        for param in params {
+            match param.split_once('=') {
+                Some(("q", v)) => q = v.parse().unwrap_or(0.0),
+                Some(("proto", "io.prometheus.client.MetricFamily")) => is_metric_family = true,
+                _ => {}
+            }
+        }
+
+        match media_type {
+            "application/vnd.google.protobuf" if is_metric_family => protobuf_q = Some(q),
+            "text/plain" | "*/*" if text_q.is_none() => text_q = Some(q),
+            _ => {}
+        }
+    }
+
+    match (protobuf_q, text_q) {
+        (Some(protobuf_q), Some(text_q)) => protobuf_q > 0.0 && protobuf_q >= text_q,
+        (Some(protobuf_q), None) => protobuf_q > 0.0,
+        _ => false,
+    }
 }
 
 #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
@@ -237,3 +589,51 @@ mod memory_profiling {
         profiler(settings)?.heap_stats()
     }
 }
+
+#[cfg(all(
+    feature = "security",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod sandbox {
+    use super::*;
+    use crate::security::{installed_syscall_sandboxes, syscall_violation_counts};
+    use std::fmt::Write;
+
+    pub(super) async fn sandbox_state(_settings: Arc<TelemetrySettings>) -> Result<String> {
+        let mut state = String::new();
+
+        // NOTE: the kernel reports the enforcement mode and the number of installed filters
+        // for the thread reading the file.
+        let status = std::fs::read_to_string("/proc/self/status")?;
+
+        for line in status.lines() {
+            if line.starts_with("Seccomp:") || line.starts_with("Seccomp_filters:") {
+                writeln!(state, "{line}")?;
+            }
+        }
+
+        for (i, sandbox) in installed_syscall_sandboxes().iter().enumerate() {
+            writeln!(state)?;
+            writeln!(state, "Sandbox #{i}")?;
+            writeln!(state, "Violation action: {:?}", sandbox.violation_action)?;
+
+            for rule in &sandbox.exception_rules {
+                writeln!(state, "  {rule}")?;
+            }
+        }
+
+        let violation_counts = syscall_violation_counts();
+
+        if !violation_counts.is_empty() {
+            writeln!(state)?;
+            writeln!(state, "Denied syscalls (number: count)")?;
+
+            for (syscall, count) in violation_counts {
+                writeln!(state, "  {syscall}: {count}")?;
+            }
+        }
+
+        Ok(state)
+    }
+}
@@ -0,0 +1,37 @@
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+/// Settings of the monitor of the CPU throttling of the cgroup of the process.
+///
+/// The monitor periodically reports the CPU throttling statistics of the cgroup in the
+/// `<prefix>_foundations_cpu_periods_total`, `<prefix>_foundations_cpu_throttled_periods_total`
+/// and `<prefix>_foundations_cpu_throttled_seconds_total` counters and, with the `logging`
+/// feature, warns if the cgroup was throttled in too many of the CPU quota enforcement periods
+/// of a sample interval, as the throttling commonly shows up as an unexplained latency.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct CpuThrottlingMonitorSettings {
+    /// Enables the monitor.
+    pub enabled: bool,
+
+    /// Interval between the samples of the throttling statistics, in milliseconds.
+    pub sample_interval_ms: u64,
+
+    /// Fraction of the CPU quota enforcement periods of a sample interval in which the cgroup
+    /// was throttled, above which a warning is logged. The warnings are disabled if set to `1.0`
+    /// or more.
+    pub warn_throttled_ratio: f64,
+}
+
+impl Default for CpuThrottlingMonitorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_interval_ms: 10_000,
+            warn_throttled_ratio: 0.1,
+        }
+    }
+}
@@ -0,0 +1,40 @@
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+/// Settings of the monitor of the open file descriptors of the process.
+///
+/// The monitor periodically reports the number of the open file descriptors by kind, e.g.
+/// sockets or pipes, in the `<prefix>_foundations_fds_open` metric and, with the `logging`
+/// feature, warns about a possible leak if the number of the file descriptors of a kind grows
+/// during the whole leak detection window.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct FdMonitorSettings {
+    /// Enables the monitor.
+    pub enabled: bool,
+
+    /// Interval between the samples of the open file descriptors, in milliseconds.
+    pub sample_interval_ms: u64,
+
+    /// Number of the consecutive samples in which the number of the file descriptors of a kind
+    /// should never decrease to be considered a leak.
+    pub leak_window: usize,
+
+    /// Minimum growth of the number of the file descriptors of a kind over the leak detection
+    /// window to be considered a leak.
+    pub min_leak_growth: u64,
+}
+
+impl Default for FdMonitorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_interval_ms: 10_000,
+            leak_window: 30,
+            min_leak_growth: 100,
+        }
+    }
+}
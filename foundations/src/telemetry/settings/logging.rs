@@ -1,13 +1,19 @@
 use crate::telemetry::settings::rate_limit::RateLimitingSettings;
 use crate::utils::feature_use;
 
+use crate::BootstrapError;
+use anyhow::{anyhow, bail};
+use std::fmt;
 use std::ops::Deref;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+#[cfg(feature = "compression")]
+use crate::compression::CompressionAlgorithm;
 
 feature_use!(cfg(feature = "settings"), {
     use crate::settings::{settings, Settings};
     use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
-    use std::str::FromStr;
 });
 
 // NOTE: we technically don't need a feature gate here, but if we don't add it then docs don't
@@ -28,6 +34,25 @@ pub struct LoggingSettings {
     /// Set the logging verbosity level.
     pub verbosity: LogVerbosity,
 
+    /// Per-module overrides of the verbosity of the main output, as comma-separated
+    /// `RUST_LOG`-style `<module>=<level>` directives, e.g. `my_service::db=debug,hyper=warn`.
+    ///
+    /// The directive of the most specific module applies to a record, a directive also applies
+    /// to the submodules. So noisy dependencies can be silenced while keeping the debug records
+    /// of the service, or the other way around. The records of the modules without a directive
+    /// are filtered by the [verbosity]. The additional outputs are not affected by the
+    /// directives.
+    ///
+    /// [verbosity]: LoggingSettings::verbosity
+    pub filter: LogFilter,
+
+    /// Additional log outputs, each with its own format and verbosity level.
+    ///
+    /// Log records are written to all the additional outputs whose verbosity allows them, in
+    /// addition to the main output. Unlike the main output verbosity, verbosity of the additional
+    /// outputs can't be changed at runtime.
+    pub additional_outputs: Vec<LogOutputSettings>,
+
     /// A list of field keys to redact when emitting logs.
     ///
     /// This might be useful to hide certain fields in production logs as they may
@@ -37,13 +62,75 @@ pub struct LoggingSettings {
     /// Settings for rate limiting emission of log events
     pub rate_limit: RateLimitingSettings,
 
+    /// Per-module log budgets.
+    ///
+    /// Log records of a module that exceed its budget are suppressed, so one chatty module
+    /// can't drown out the others, even if they log at the same level. The budget of the most
+    /// specific module applies to a record, records of the modules without a budget are not
+    /// limited.
+    ///
+    /// With the `metrics` feature, suppressed records are counted by the
+    /// `<app_name>_foundations_log_records_suppressed` counter, tagged with the module.
+    pub module_budgets: Vec<LogModuleBudget>,
+
     /// Configure log volume metrics.
     pub log_volume_metrics: LogVolumeMetricSettings,
+
+    /// Specifies how failures to write to the log output are handled.
+    pub error_policy: LogDrainErrorPolicy,
+
+    /// Specifies which log records can be dropped if the log output can't keep up.
+    pub overflow: LogOverflowSettings,
+
+    /// Specifies how repeated identical log records are suppressed.
+    pub dedup: LogDedupSettings,
+
+    /// Whether the panics and the fatal signals, e.g. `SIGSEGV`, are logged to stderr with
+    /// the async-signal-safe [crash log], that doesn't deadlock if the process crashes while
+    /// the logger is in use. Unix only.
+    ///
+    /// [crash log]: crate::telemetry::log::crash
+    pub log_crashes: bool,
+
+    /// Number of the most recent log records retained in memory for [diagnostics bundles].
+    ///
+    /// Retention is disabled if set to `0`.
+    ///
+    /// [diagnostics bundles]: crate::telemetry::diagnostics
+    #[cfg(feature = "diagnostics")]
+    pub recent_records: usize,
+}
+
+/// Log budget of a module, see [`LoggingSettings::module_budgets`].
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+pub struct LogModuleBudget {
+    /// Path of the module, e.g. `my_service::db`. The budget also applies to the submodules.
+    pub module: String,
+
+    /// Maximum number of records the module can emit per second.
+    pub max_records_per_second: u32,
+}
+
+/// Settings of an additional log output.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[derive(PartialEq)]
+pub struct LogOutputSettings {
+    /// Specifies log output.
+    pub output: LogOutput,
+
+    /// The format to use for log messages.
+    pub format: LogFormat,
+
+    /// Set the logging verbosity level of the output.
+    pub verbosity: LogVerbosity,
 }
 
 /// Log output destination.
 #[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
 #[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+#[derive(PartialEq)]
 pub enum LogOutput {
     /// Write log to terminal.
     #[default]
@@ -52,12 +139,64 @@ pub enum LogOutput {
     ///
     /// File will be created if it doesn't exist and overwritten otherwise.
     File(PathBuf),
+    /// Write log to file that is rotated once it gets too large or too old.
+    RotatingFile(RotatingFileLogOutput),
+}
+
+/// Log file output with rotation, see [`LogOutput::RotatingFile`].
+///
+/// The file is appended to if it exists. Once the file reaches the maximum size or age, it's
+/// renamed to `<path>.<unix timestamp in milliseconds, 13 digits>` and a new file is started. Only
+/// the files with such names are counted and removed as the rotated files. The rotation
+/// happens between the log records, so a record is never split between the files.
+///
+/// The files are written by the background thread of the log output, and the rotated files are
+/// compressed by a separate thread, so neither of them blocks the logging.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
+pub struct RotatingFileLogOutput {
+    /// Path of the log file. The directory of the file should exist.
+    pub path: PathBuf,
+
+    /// Maximum size in bytes of the log file, it's rotated once exceeded. Size-based rotation is
+    /// disabled if set to `0`.
+    pub max_file_size: u64,
+
+    /// Maximum age in seconds of the log file, it's rotated once exceeded. Time-based rotation is
+    /// disabled if set to `0`.
+    pub max_file_age_secs: u64,
+
+    /// Maximum number of the rotated files kept next to the log file, the oldest ones are
+    /// removed.
+    pub max_files: usize,
+
+    /// Compression of the rotated files, that get the extension of the algorithm, e.g.
+    /// `.gz`. The rotated files are not compressed if not specified.
+    #[cfg(feature = "compression")]
+    pub compression: Option<CompressionAlgorithm>,
+}
+
+impl Default for RotatingFileLogOutput {
+    fn default() -> Self {
+        Self {
+            path: "/var/log/service.log".into(),
+            max_file_size: 100 * 1024 * 1024,
+            max_file_age_secs: 0,
+            max_files: 5,
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
 }
 
 /// Format of the log output.
 #[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
 #[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
-#[derive(Copy)]
+#[derive(Copy, PartialEq)]
 pub enum LogFormat {
     /// Plain text
     #[default]
@@ -66,8 +205,102 @@ pub enum LogFormat {
     Json,
 }
 
+/// Policy for handling failures of a log output drain.
+///
+/// If metrics are enabled, each failure is counted in the
+/// `<app_name>_foundations_log_drain_failures_total` counter, tagged with the drain and the policy.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[derive(Copy)]
+pub enum LogDrainErrorPolicy {
+    /// Drop the log record that failed to be written.
+    #[default]
+    Ignore,
+    /// Write the log record that failed to be written to stderr instead.
+    Fallback,
+    /// Print the error to stderr and abort the process, so the service can be restarted with
+    /// a working log output.
+    Escalate,
+}
+
+impl LogDrainErrorPolicy {
+    /// Returns the name of the policy as it appears in the settings.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogDrainErrorPolicy::Ignore => "ignore",
+            LogDrainErrorPolicy::Fallback => "fallback",
+            LogDrainErrorPolicy::Escalate => "escalate",
+        }
+    }
+}
+
+/// Settings of the log records queue overflow handling.
+///
+/// Log records are written to the output by a background thread. If the output can't keep up
+/// with the logging rate and the queue of the pending records is full, records less severe than
+/// the [priority level] are dropped, while the more severe ones block the logging thread until
+/// there is room in the queue, so they are never lost.
+///
+/// If metrics are enabled, each dropped record is counted in the
+/// `<app_name>_foundations_log_records_dropped` counter, tagged with the drain and the level.
+///
+/// [priority level]: LogOverflowSettings::priority_level
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct LogOverflowSettings {
+    /// The least severe level of the records that are never dropped.
+    pub priority_level: LogVerbosity,
+}
+
+impl Default for LogOverflowSettings {
+    fn default() -> Self {
+        Self {
+            priority_level: LogVerbosity(Level::Error),
+        }
+    }
+}
+
+/// Settings of the suppression of repeated identical log records.
+///
+/// If enabled, a record with the same level, location and message as a record emitted within
+/// the window is suppressed, regardless of its fields. Instead, once per window, a rollup record
+/// is emitted for each suppressed message, with the number of the suppressed records in the
+/// `suppressed_duplicates` field.
+///
+/// Messages are tracked with rotating bloom filters, so memory usage doesn't depend on the
+/// number of distinct messages. A record that is mistakenly taken for a duplicate is still
+/// emitted with the rollups.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct LogDedupSettings {
+    /// Whether to suppress repeated log records.
+    pub enabled: bool,
+
+    /// Duration of the window, in milliseconds.
+    pub window_ms: u64,
+
+    /// The least severe level of the records that are suppressed.
+    pub level: LogVerbosity,
+}
+
+impl Default for LogDedupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window_ms: 10_000,
+            level: LogVerbosity(Level::Error),
+        }
+    }
+}
+
 /// Verbosity level of the log.
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq)]
 pub struct LogVerbosity(pub Level);
 
 impl Default for LogVerbosity {
@@ -84,6 +317,77 @@ impl Deref for LogVerbosity {
     }
 }
 
+/// Per-module overrides of the log verbosity, see [`LoggingSettings::filter`].
+///
+/// Can be parsed from a string of comma-separated `<module>=<level>` directives, e.g.
+/// `my_service::db=debug,hyper=warn`.
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    /// Directives of the filter.
+    pub directives: Vec<LogFilterDirective>,
+}
+
+/// A directive of [`LogFilter`].
+#[derive(Clone, Debug)]
+pub struct LogFilterDirective {
+    /// Path of the module, e.g. `my_service::db`. The directive also applies to the submodules.
+    pub module: String,
+
+    /// Verbosity level of the module.
+    pub verbosity: LogVerbosity,
+}
+
+impl FromStr for LogFilter {
+    type Err = BootstrapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut directives = vec![];
+
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let Some((module, level)) = directive.split_once('=') else {
+                bail!(
+                    "log filter directive `{directive}` should be in the `<module>=<level>` format"
+                );
+            };
+
+            let module = module.trim();
+
+            if module.is_empty() {
+                bail!("log filter directive `{directive}` should have a module");
+            }
+
+            let level = Level::from_str(level.trim())
+                .map_err(|_| anyhow!("log filter directive `{directive}` has invalid level"))?;
+
+            directives.push(LogFilterDirective {
+                module: module.to_string(),
+                verbosity: LogVerbosity(level),
+            });
+        }
+
+        Ok(Self { directives })
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, directive) in self.directives.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+
+            write!(
+                f,
+                "{}={}",
+                directive.module,
+                directive.verbosity.as_str().to_ascii_lowercase()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Log volume metrics settings
 ///
 /// If enabled, a counter metric will be exposed as <app_name>_foundations_log_record_count
@@ -120,6 +424,28 @@ mod with_settings_feature {
     }
 
     impl Settings for LogVerbosity {}
+
+    impl<'de> Deserialize<'de> for LogFilter {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(de::Error::custom)
+        }
+    }
+
+    impl Serialize for LogFilter {
+        fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            s.collect_str(self)
+        }
+    }
+
+    impl Settings for LogFilter {}
 }
 
 fn _assert_traits_implemented_for_all_features() {
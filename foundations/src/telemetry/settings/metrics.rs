@@ -2,15 +2,346 @@
 use crate::settings::settings;
 
 /// Metrics settings.
-#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
-#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
 pub struct MetricsSettings {
     /// How the metrics service identifier defined in `ServiceInfo` is used
     /// for this service.
     pub service_name_format: ServiceNameFormat,
 
+    /// Prefix added to the names of all the reported metrics, e.g. `myservice_`, so
+    /// the services can share dashboards.
+    ///
+    /// The prefix is added in front of the service identifier if the service name format is
+    /// [`ServiceNameFormat::MetricPrefix`].
+    pub metric_prefix: Option<String>,
+
+    /// Labels added to all the reported metrics, e.g. the region or the instance of
+    /// the service.
+    ///
+    /// The label is not added to the series that already have a label with the same name.
+    pub const_labels: Vec<ConstLabel>,
+
     /// Whether to report optional metrics in the telemetry server.
     pub report_optional: bool,
+
+    /// Flags of the optional metrics to report even if `report_optional` is disabled.
+    ///
+    /// The optional metrics are assigned a flag with `#[optional = "<flag>"]` in
+    /// the [`metrics`] macro, so several expensive metrics can be enabled at once.
+    ///
+    /// [`metrics`]: crate::telemetry::metrics::metrics
+    pub optional_flags: Vec<String>,
+
+    /// Whether to report the [standard process metrics], such as `process_cpu_seconds_total`
+    /// and `process_resident_memory_bytes`.
+    ///
+    /// The metrics are named the same as the ones of the other Prometheus client libraries, so
+    /// they're reported without the service prefix. Enabled by default, only supported on Linux.
+    ///
+    /// [standard process metrics]: https://prometheus.io/docs/instrumenting/writing_clientlibs/#process-metrics
+    pub process_metrics: bool,
+
+    /// Whether to report the metrics of the Tokio runtimes, such as the number of the alive tasks
+    /// and the busy time of the worker threads, see [`register_runtime`].
+    ///
+    /// [`register_runtime`]: crate::telemetry::metrics::register_runtime
+    pub runtime_metrics: bool,
+
+    /// Whether to track the time of the last update of each label set of the metrics.
+    ///
+    /// The label sets and their last update times are exposed on the `/debug/metrics/label_sets`
+    /// endpoint of the telemetry server, which helps diagnosing stale series.
+    pub track_label_set_updates: bool,
+
+    /// Whether to sort the reported metric families by name and the series of each family by
+    /// labels.
+    ///
+    /// Makes the output independent of the metrics registration order, so golden-file tests and
+    /// diffs of the scrapes don't churn when the code is refactored.
+    pub stable_ordering: bool,
+
+    /// Whether to report the [OpenMetrics] `_created` samples with creation timestamps of
+    /// the counter and histogram series in the text format.
+    ///
+    /// Backends use created timestamps to detect counter resets. The timestamp of a series is
+    /// the time it was first collected, so it's precise up to the scrape interval.
+    ///
+    /// [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+    pub created_timestamps: bool,
+
+    /// Maximum number of label sets of each metric with labels.
+    ///
+    /// Once a metric reaches the limit, the new label sets are reported as a single series
+    /// labeled with `overflow="true"`, which protects the service from running out of memory if
+    /// a label has unbounded values. There is no limit if not specified.
+    pub max_label_sets: Option<usize>,
+
+    /// Bucket boundaries of the histograms overriding the ones specified in the code with
+    /// the `ctor` attribute of the [`metrics`] macro.
+    ///
+    /// Only applies to the metrics first accessed after the telemetry initialization.
+    ///
+    /// [`metrics`]: crate::telemetry::metrics::metrics
+    pub histogram_buckets: Vec<HistogramBuckets>,
+
+    /// Filters of the labels of the reported metrics, e.g. to drop a high-cardinality
+    /// `client_ip` label in a specific deployment without code changes.
+    ///
+    /// The series of a metric that only differ in the removed labels are merged by summing their
+    /// values.
+    pub label_filters: Vec<LabelFilter>,
+
+    /// Whether to report all the counters as the deltas since the previous scrape instead of
+    /// the cumulative values, for the pipelines that consume deltas.
+    ///
+    /// The delta of a series is computed against its value reported by the previous collection
+    /// of the metrics in the text or protobuf format, so the metrics should have a single
+    /// scraper. The counters exported over OTLP or pushed to a Pushgateway, as well as the ones
+    /// in the diagnostics, stay cumulative.
+    pub reset_counters_on_scrape: bool,
+
+    /// Names of the counters reported as the deltas since the previous scrape, see
+    /// [`MetricsSettings::reset_counters_on_scrape`].
+    ///
+    /// The names are as reported, including the service prefix and
+    /// [`MetricsSettings::metric_prefix`], e.g. `my_app_http_requests_total`.
+    pub reset_on_scrape: Vec<String>,
+
+    /// Number of the recent scrapes whose windows are retained by the [range gauges], so each
+    /// scraper gets the minimum and maximum since its own previous scrape, e.g. if the metrics are
+    /// scraped by several Prometheus servers. Disabled if `0`, in which case the minimum and
+    /// maximum are reset by every scrape.
+    ///
+    /// The scrapers are identified as described in [`with_scraper`]. The range reported to
+    /// a scraper that didn't scrape in the last `range_gauge_windows` scrapes only covers
+    /// the retained windows. The exports over OTLP, the pushes to a Pushgateway and
+    /// the diagnostics are not scrapes, they report the range since the previous scrape without
+    /// resetting it.
+    ///
+    /// [range gauges]: crate::telemetry::metrics::RangeGauge
+    /// [`with_scraper`]: crate::telemetry::metrics::with_scraper
+    pub range_gauge_windows: usize,
+
+    /// Whether to report the exemplars of the [`ExemplarCounter`] and [`ExemplarHistogram`]
+    /// metrics in the text format.
+    ///
+    /// Exemplars are only supported by the [OpenMetrics] text format, so the telemetry server
+    /// reports the metrics with the OpenMetrics content type if enabled.
+    ///
+    /// [`ExemplarCounter`]: crate::telemetry::metrics::ExemplarCounter
+    /// [`ExemplarHistogram`]: crate::telemetry::metrics::ExemplarHistogram
+    /// [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+    pub exemplars: bool,
+
+    /// Settings of the [OTLP] exporter that pushes the metrics to an OpenTelemetry collector.
+    ///
+    /// [OTLP]: https://opentelemetry.io/docs/specs/otlp/
+    #[cfg(feature = "otlp-metrics")]
+    pub otlp: OtlpExporterSettings,
+
+    /// Settings of pushing the metrics to a Prometheus [Pushgateway], see [`push`].
+    ///
+    /// [Pushgateway]: https://github.com/prometheus/pushgateway
+    /// [`push`]: crate::telemetry::metrics::push
+    #[cfg(feature = "metrics-push")]
+    pub push: PushSettings,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self {
+            service_name_format: Default::default(),
+            metric_prefix: None,
+            const_labels: vec![],
+            report_optional: false,
+            optional_flags: vec![],
+            process_metrics: true,
+            runtime_metrics: false,
+            track_label_set_updates: false,
+            stable_ordering: false,
+            created_timestamps: false,
+            max_label_sets: None,
+            histogram_buckets: vec![],
+            label_filters: vec![],
+            reset_counters_on_scrape: false,
+            reset_on_scrape: vec![],
+            range_gauge_windows: 0,
+            exemplars: false,
+
+            #[cfg(feature = "otlp-metrics")]
+            otlp: Default::default(),
+
+            #[cfg(feature = "metrics-push")]
+            push: Default::default(),
+        }
+    }
+}
+
+/// Bucket boundaries of a histogram, see [`MetricsSettings::histogram_buckets`].
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+pub struct HistogramBuckets {
+    /// Name of the metric without the service prefix, i.e. `<module>_<metric>` for a metric
+    /// defined by the [`metrics`] macro.
+    ///
+    /// [`metrics`]: crate::telemetry::metrics::metrics
+    pub metric: String,
+
+    /// Upper bounds of the buckets.
+    pub buckets: Vec<f64>,
+}
+
+/// A label added to all the reported metrics, see [`MetricsSettings::const_labels`].
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+pub struct ConstLabel {
+    /// Name of the label.
+    pub name: String,
+
+    /// Value of the label.
+    pub value: String,
+}
+
+/// Filter of the labels of a metric, see [`MetricsSettings::label_filters`].
+///
+/// The `le` and `quantile` labels of the histogram and summary samples are never removed.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+pub struct LabelFilter {
+    /// Name of the metric as reported, including the service prefix and
+    /// [`MetricsSettings::metric_prefix`], e.g. `my_app_http_requests_total`.
+    pub metric: String,
+
+    /// Labels to keep. All the labels are kept if not specified.
+    pub allow: Option<Vec<String>>,
+
+    /// Labels to remove.
+    pub deny: Vec<String>,
+}
+
+/// Settings of pushing the metrics to a Prometheus [Pushgateway].
+///
+/// Only plain-text `http://` endpoints are supported.
+///
+/// [Pushgateway]: https://github.com/prometheus/pushgateway
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[cfg(feature = "metrics-push")]
+pub struct PushSettings {
+    /// Enables pushing of the metrics.
+    pub enabled: bool,
+
+    /// URL of the Pushgateway, e.g. `http://127.0.0.1:9091`.
+    pub endpoint: String,
+
+    /// Name of the job the metrics are grouped by. The service name is used if not specified.
+    pub job: Option<String>,
+
+    /// Additional labels the metrics are grouped by, e.g. `instance`.
+    pub grouping_labels: Vec<PushGroupingLabel>,
+
+    /// Interval in milliseconds between the periodic pushes. The metrics are only pushed on
+    /// demand if not specified.
+    pub push_interval_ms: Option<u64>,
+
+    /// Timeout in milliseconds of a push.
+    pub timeout_ms: u64,
+}
+
+#[cfg(feature = "metrics-push")]
+impl Default for PushSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://127.0.0.1:9091".into(),
+            job: None,
+            grouping_labels: vec![],
+            push_interval_ms: Some(15_000),
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// A grouping label of the metrics pushed to a Pushgateway, see [`PushSettings`].
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+#[cfg(feature = "metrics-push")]
+pub struct PushGroupingLabel {
+    /// Name of the label.
+    pub name: String,
+
+    /// Value of the label.
+    pub value: String,
+}
+
+/// Settings of the [OTLP] metrics exporter.
+///
+/// The exporter periodically pushes the same metrics that are reported in the text format,
+/// including the optional ones if [`MetricsSettings::report_optional`] is enabled. Counters are
+/// exported as cumulative monotonic sums, native histograms as exponential histograms and
+/// the other metrics as gauges. Only plain-text `http://` endpoints are supported, e.g.
+/// a collector running alongside the service.
+///
+/// [OTLP]: https://opentelemetry.io/docs/specs/otlp/
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[cfg(feature = "otlp-metrics")]
+pub struct OtlpExporterSettings {
+    /// Enables the exporter.
+    pub enabled: bool,
+
+    /// Protocol of the collector endpoint.
+    pub protocol: OtlpProtocol,
+
+    /// URL of the collector endpoint, e.g. `http://127.0.0.1:4318` for the HTTP protocol or
+    /// `http://127.0.0.1:4317` for gRPC. TLS is not supported, so `https://` endpoints are
+    /// rejected.
+    ///
+    /// With the HTTP protocol, the metrics are sent to the `/v1/metrics` path of the endpoint.
+    pub endpoint: String,
+
+    /// Interval in milliseconds between the exports.
+    pub export_interval_ms: u64,
+
+    /// Timeout in milliseconds of an export.
+    pub timeout_ms: u64,
+}
+
+#[cfg(feature = "otlp-metrics")]
+impl Default for OtlpExporterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: Default::default(),
+            endpoint: "http://127.0.0.1:4318".into(),
+            export_interval_ms: 15_000,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Protocol of the [OTLP] metrics exporter.
+///
+/// [OTLP]: https://opentelemetry.io/docs/specs/otlp/
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+#[cfg(feature = "otlp-metrics")]
+pub enum OtlpProtocol {
+    /// OTLP/HTTP with binary protobuf payloads.
+    #[default]
+    Http,
+    /// OTLP/gRPC, over HTTP/2 without TLS.
+    Grpc,
 }
 
 /// Service name format.
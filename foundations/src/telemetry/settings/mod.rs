@@ -12,6 +12,15 @@ mod metrics;
 #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
 mod memory_profiler;
 
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+mod fd_monitor;
+
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+mod cpu_throttling;
+
+#[cfg(any(feature = "otlp-metrics", feature = "metrics-push"))]
+mod proxy;
+
 mod rate_limit;
 
 #[cfg(feature = "telemetry-server")]
@@ -29,6 +38,15 @@ pub use self::metrics::*;
 #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
 pub use self::memory_profiler::*;
 
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+pub use self::fd_monitor::*;
+
+#[cfg(all(target_os = "linux", feature = "metrics"))]
+pub use self::cpu_throttling::*;
+
+#[cfg(any(feature = "otlp-metrics", feature = "metrics-push"))]
+pub use self::proxy::ProxySettings;
+
 pub use self::rate_limit::RateLimitingSettings;
 
 #[cfg(feature = "telemetry-server")]
@@ -57,9 +75,31 @@ pub struct TelemetrySettings {
     #[cfg(all(target_os = "linux", feature = "memory-profiling"))]
     pub memory_profiler: MemoryProfilerSettings,
 
+    /// Settings of the monitor of the open file descriptors.
+    #[cfg(all(target_os = "linux", feature = "metrics"))]
+    pub fd_monitor: FdMonitorSettings,
+
+    /// Settings of the monitor of the CPU throttling of the cgroup.
+    #[cfg(all(target_os = "linux", feature = "metrics"))]
+    pub cpu_throttling_monitor: CpuThrottlingMonitorSettings,
+
     /// Server settings.
     #[cfg(feature = "telemetry-server")]
     pub server: TelemetryServerSettings,
+
+    /// Egress proxy settings of the OTLP metrics exporter and of the pushes of the metrics to
+    /// a Pushgateway.
+    #[cfg(any(feature = "otlp-metrics", feature = "metrics-push"))]
+    pub proxy: ProxySettings,
+
+    /// Path of the state file tracking the restarts and the exit reasons of the service, see
+    /// [`ProcessState`]. The tracking is disabled if not specified.
+    ///
+    /// The file should be stored in a location that persists across the restarts of
+    /// the service, but not across reboots of the host, e.g. in `/run`.
+    ///
+    /// [`ProcessState`]: crate::telemetry::ProcessState
+    pub state_file: Option<std::path::PathBuf>,
 }
 
 fn _assert_traits_implemented_for_all_features() {
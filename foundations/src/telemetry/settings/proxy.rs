@@ -0,0 +1,168 @@
+use crate::utils::feature_use;
+use std::net::IpAddr;
+
+feature_use!(cfg(feature = "settings"), {
+    use crate::settings::settings;
+});
+
+/// Egress proxy settings of the OTLP metrics exporter and of the pushes of the metrics to
+/// a Pushgateway.
+///
+/// The settings are equivalent to the conventional `HTTP_PROXY` and `NO_PROXY` environment
+/// variables, that are used as a fallback if [`ProxySettings::from_env`] is enabled. The exporters
+/// only support plain-text `http://` endpoints, so there is no proxy for `https://` endpoints.
+///
+/// The HTTP/1 requests are sent to the proxy with the absolute URL of the endpoint, the HTTP/2
+/// connections, e.g. of the OTLP exporter with the gRPC protocol, are tunneled through the proxy
+/// with `CONNECT`.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct ProxySettings {
+    /// URL of the proxy for `http://` endpoints, e.g. `http://proxy.internal:3128`.
+    pub http_proxy: Option<String>,
+
+    /// Hosts that are reached without a proxy.
+    ///
+    /// Each entry is either `*`, that disables the proxy for all the hosts, an IP address or
+    /// a domain name, that also matches its subdomains, e.g. `example.com` and `.example.com`
+    /// both match `api.example.com`.
+    pub no_proxy: Vec<String>,
+
+    /// Whether to use the `HTTP_PROXY` and `NO_PROXY` environment variables
+    /// (or their lowercase variants) for the settings that are not specified.
+    pub from_env: bool,
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        Self {
+            http_proxy: None,
+            no_proxy: vec![],
+            from_env: true,
+        }
+    }
+}
+
+impl ProxySettings {
+    /// Returns the URL of the proxy that should be used to reach the endpoint `url`, or `None`
+    /// if the endpoint should be reached directly.
+    ///
+    /// # Examples
+    /// ```
+    /// use foundations::telemetry::settings::ProxySettings;
+    ///
+    /// let settings = ProxySettings {
+    ///     http_proxy: Some("http://proxy.internal:3128".into()),
+    ///     no_proxy: vec![".internal".into()],
+    ///     from_env: false,
+    /// };
+    ///
+    /// assert_eq!(
+    ///     settings.proxy_for("http://otel.example.com:4318/v1/metrics").as_deref(),
+    ///     Some("http://proxy.internal:3128")
+    /// );
+    ///
+    /// assert_eq!(settings.proxy_for("http://otel.internal:4318/v1/metrics"), None);
+    /// assert_eq!(settings.proxy_for("https://otel.example.com/v1/metrics"), None);
+    /// ```
+    pub fn proxy_for(&self, url: &str) -> Option<String> {
+        let (scheme, rest) = url.split_once("://")?;
+        let host = host_of(rest);
+
+        let no_proxy = if self.no_proxy.is_empty() && self.from_env {
+            env_var("NO_PROXY")
+                .map(|list| list.split(',').map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default()
+        } else {
+            self.no_proxy.clone()
+        };
+
+        if no_proxy.iter().any(|entry| no_proxy_matches(entry, host)) {
+            return None;
+        }
+
+        if !scheme.eq_ignore_ascii_case("http") {
+            return None;
+        }
+
+        match &self.http_proxy {
+            Some(proxy) => Some(proxy.clone()),
+            None if self.from_env => env_var("HTTP_PROXY").filter(|proxy| !proxy.is_empty()),
+            None => None,
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .or_else(|_| std::env::var(name.to_ascii_lowercase()))
+        .ok()
+}
+
+/// Extracts the host from the part of the URL after the scheme.
+fn host_of(authority_and_path: &str) -> &str {
+    let authority = authority_and_path
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+
+    let host_and_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    if let Some(ipv6) = host_and_port.strip_prefix('[') {
+        return ipv6.split(']').next().unwrap_or_default();
+    }
+
+    host_and_port.split(':').next().unwrap_or_default()
+}
+
+fn no_proxy_matches(entry: &str, host: &str) -> bool {
+    let entry = entry.trim();
+
+    if entry.is_empty() {
+        return false;
+    }
+
+    if entry == "*" {
+        return true;
+    }
+
+    if let (Ok(entry), Ok(host)) = (entry.parse::<IpAddr>(), host.parse::<IpAddr>()) {
+        return entry == host;
+    }
+
+    let entry = entry.trim_start_matches('.');
+
+    host.eq_ignore_ascii_case(entry)
+        || host.len() > entry.len()
+            && host.as_bytes()[host.len() - entry.len() - 1] == b'.'
+            && host
+                .get(host.len() - entry.len()..)
+                .is_some_and(|suffix| suffix.eq_ignore_ascii_case(entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_hosts() {
+        assert_eq!(host_of("example.com/path"), "example.com");
+        assert_eq!(host_of("user:pass@example.com:8080?q"), "example.com");
+        assert_eq!(host_of("[::1]:4318/v1/metrics"), "::1");
+    }
+
+    #[test]
+    fn matches_no_proxy_entries() {
+        assert!(no_proxy_matches("*", "example.com"));
+        assert!(no_proxy_matches("example.com", "api.Example.com"));
+        assert!(no_proxy_matches(".example.com", "example.com"));
+        assert!(!no_proxy_matches("example.com", "badexample.com"));
+        assert!(no_proxy_matches("::1", "::1"));
+        assert!(!no_proxy_matches("10.0.0.1", "10.0.0.2"));
+    }
+}
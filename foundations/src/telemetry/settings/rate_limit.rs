@@ -7,6 +7,7 @@ feature_use!(cfg(feature = "settings"), {
 /// Rate limiting settings for events
 #[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
 #[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+#[derive(PartialEq)]
 pub struct RateLimitingSettings {
     /// Whether to enable rate limiting of events
     pub enabled: bool,
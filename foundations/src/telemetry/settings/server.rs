@@ -18,6 +18,28 @@ pub struct TelemetryServerSettings {
 
     /// Telemetry server address.
     pub addr: SocketAddr,
+
+    /// Disables all the endpoints that mutate the service state.
+    ///
+    /// In the read-only mode, requests with methods other than `GET`, `HEAD`, `OPTIONS` and `TRACE` are
+    /// rejected with `403 Forbidden` and the endpoints that trigger profiling are not exposed.
+    pub read_only: bool,
+
+    /// Period in milliseconds during which the server keeps serving the metrics scrapes with no
+    /// samples on the graceful shutdown, so Prometheus marks the series of the service as stale.
+    ///
+    /// Should be at least the scrape interval. The metrics are not marked as stale if set to `0`,
+    /// see [`metrics::mark_stale`] for details.
+    ///
+    /// [`metrics::mark_stale`]: crate::telemetry::metrics::mark_stale
+    #[cfg(feature = "metrics")]
+    pub stale_metrics_period_ms: u64,
+
+    /// Compresses the responses of the metrics endpoints with gzip if the scraper accepts it
+    /// with the `Accept-Encoding` header, as Prometheus does. Requires the `compression`
+    /// feature, the responses are never compressed without it.
+    #[cfg(feature = "metrics")]
+    pub compress_metrics: bool,
 }
 
 impl Default for TelemetryServerSettings {
@@ -30,6 +52,11 @@ impl Default for TelemetryServerSettings {
         Self {
             enabled: true,
             addr,
+            read_only: false,
+            #[cfg(feature = "metrics")]
+            stale_metrics_period_ms: 0,
+            #[cfg(feature = "metrics")]
+            compress_metrics: true,
         }
     }
 }
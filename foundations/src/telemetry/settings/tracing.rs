@@ -1,6 +1,7 @@
 use crate::telemetry::settings::rate_limit::RateLimitingSettings;
 use crate::utils::feature_use;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 
 feature_use!(cfg(feature = "settings"), {
     use crate::settings::net::SocketAddr;
@@ -16,10 +17,14 @@ use std::net::SocketAddr;
     settings(crate_path = "crate", impl_default = false)
 )]
 #[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
 pub struct TracingSettings {
     /// Enables tracing.
     pub enabled: bool,
 
+    /// Output of the traces.
+    pub output: TracesOutput,
+
     /// The address of the Jaeger Thrift (UDP) agent.
     pub jaeger_tracing_server_addr: SocketAddr,
 
@@ -38,6 +43,276 @@ pub struct TracingSettings {
 
     /// Settings for rate limiting emission of traces
     pub rate_limit: RateLimitingSettings,
+
+    /// Settings for adaptive sampling of traces.
+    pub adaptive_sampling: AdaptiveSamplingSettings,
+
+    /// Strategy of the trace ID generation.
+    ///
+    /// Ignored if a custom generator is set with [`set_id_generator`].
+    ///
+    /// [`set_id_generator`]: crate::telemetry::tracing::set_id_generator
+    pub trace_id_generation: TraceIdGeneration,
+
+    /// Settings of the circuit breaker of the traces output.
+    pub circuit_breaker: ExportCircuitBreakerSettings,
+
+    /// Settings of the local archive of the finished spans.
+    #[cfg(feature = "trace-archive")]
+    pub archive: TraceArchiveSettings,
+
+    /// Span duration SLOs.
+    ///
+    /// For each span with an SLO, the spans that finished within the SLO duration threshold are
+    /// counted by the `<app_name>_foundations_tracing_span_slo_good_total` counter and the rest
+    /// by the `<app_name>_foundations_tracing_span_slo_bad_total` counter, both tagged with
+    /// the span name. The objective is reported by
+    /// the `<app_name>_foundations_tracing_span_slo_objective` gauge, so a single burn-rate alert
+    /// can cover all the SLOs:
+    ///
+    /// ```text
+    /// rate(span_slo_bad_total[1h]) / (rate(span_slo_good_total[1h]) + rate(span_slo_bad_total[1h]))
+    ///   / on(span) (1 - span_slo_objective)
+    /// ```
+    ///
+    /// All spans are measured, including the ones that are not sampled, and even if tracing is
+    /// disabled.
+    #[cfg(feature = "metrics")]
+    pub slos: Vec<SpanSlo>,
+}
+
+/// Duration SLO of a span, see [`TracingSettings::slos`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
+#[cfg(feature = "metrics")]
+pub struct SpanSlo {
+    /// Name of the span.
+    pub span_name: String,
+
+    /// Maximum duration in milliseconds of a good span.
+    pub threshold_ms: u64,
+
+    /// Target ratio of the good spans, e.g. `0.999`.
+    pub objective: f64,
+}
+
+#[cfg(feature = "metrics")]
+impl Default for SpanSlo {
+    fn default() -> Self {
+        Self {
+            span_name: String::new(),
+            threshold_ms: 1000,
+            objective: 0.999,
+        }
+    }
+}
+
+/// Strategy of the trace ID generation.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+#[derive(Copy, PartialEq)]
+pub enum TraceIdGeneration {
+    /// Random 128-bit trace IDs.
+    #[default]
+    Random,
+    /// Trace IDs prefixed with the Unix timestamp in milliseconds, so they are sortable by
+    /// the trace start time.
+    TimePrefixed,
+}
+
+/// Output of the traces.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug, Default))]
+#[derive(PartialEq)]
+pub enum TracesOutput {
+    /// Report spans to the Jaeger Thrift (UDP) agent at
+    /// [`TracingSettings::jaeger_tracing_server_addr`].
+    #[default]
+    JaegerThriftUdp,
+    /// Write batches of spans to the Unix socket of a local agent (Unix only).
+    UnixSocket(UnixSocketTracesOutput),
+}
+
+/// Settings of the Unix socket traces output.
+///
+/// The spans are written in frames, each containing the big-endian `u32` length of the payload
+/// followed by the payload: the Jaeger agent `emitBatch` message with a batch of spans encoded
+/// in the Thrift compact protocol, same as the one sent to the Jaeger UDP agent.
+///
+/// If the agent is not available, the batches are kept in memory and written once the connection
+/// is re-established.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
+pub struct UnixSocketTracesOutput {
+    /// Path of the agent's Unix socket.
+    pub path: PathBuf,
+
+    /// Maximum number of spans in a batch.
+    pub max_batch_size: usize,
+
+    /// Maximum time in milliseconds a span can wait to be written in a batch.
+    pub flush_interval_ms: u64,
+
+    /// Maximum number of batches kept while the agent is not available, the oldest batches are
+    /// dropped once the limit is reached.
+    pub max_backfill_batches: usize,
+}
+
+impl Default for UnixSocketTracesOutput {
+    fn default() -> Self {
+        Self {
+            path: "/run/traces.sock".into(),
+            max_batch_size: 100,
+            flush_interval_ms: 1000,
+            max_backfill_batches: 100,
+        }
+    }
+}
+
+/// Settings of the local trace archive.
+///
+/// The archive is a "black box recorder": in addition to being reported with
+/// [`TracingSettings::output`], the finished spans are written to a bounded ring of
+/// zstd-compressed files, so the traces can be recovered after an incident even if the exporter
+/// or the agent were down.
+///
+/// The files are named `traces-<unix timestamp in milliseconds>.bin.zst` and contain the frames
+/// of the same format as [`UnixSocketTracesOutput`]. The compressed data is flushed with each
+/// batch, so the spans are readable even if the service is terminated before the file is
+/// complete. Once the directory contains [`TraceArchiveSettings::max_files`] files, the oldest file
+/// is removed before a new one is created.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
+#[cfg(feature = "trace-archive")]
+pub struct TraceArchiveSettings {
+    /// Enables the archive.
+    pub enabled: bool,
+
+    /// Directory of the archive files, created if it doesn't exist.
+    pub directory: PathBuf,
+
+    /// Archive only the spans that have the `error` tag set, e.g. with the failure
+    /// [`SpanStatus`].
+    ///
+    /// [`SpanStatus`]: crate::telemetry::tracing::SpanStatus
+    pub errors_only: bool,
+
+    /// Maximum size in bytes of a compressed archive file, a new file is started once exceeded.
+    pub max_file_size: u64,
+
+    /// Maximum number of archive files kept in the directory.
+    pub max_files: usize,
+
+    /// Zstd compression level, from `1` (fastest) to `22` (smallest).
+    pub compression_level: i32,
+
+    /// Maximum number of spans in a batch.
+    pub max_batch_size: usize,
+
+    /// Maximum time in milliseconds a span can wait to be written in a batch.
+    pub flush_interval_ms: u64,
+}
+
+#[cfg(feature = "trace-archive")]
+impl Default for TraceArchiveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "/var/tmp/traces".into(),
+            errors_only: false,
+            max_file_size: 16 * 1024 * 1024,
+            max_files: 8,
+            compression_level: 3,
+            max_batch_size: 100,
+            flush_interval_ms: 1000,
+        }
+    }
+}
+
+/// Settings of the circuit breaker of the traces output.
+///
+/// After [`ExportCircuitBreakerSettings::failure_threshold`] consecutive failures to export
+/// the spans, the breaker opens: the spans are dropped without being serialized and the failures
+/// are not logged anymore. Once [`ExportCircuitBreakerSettings::open_duration_ms`] elapses,
+/// an export is attempted as a probe, which closes the breaker on success and reopens it on
+/// failure.
+///
+/// The dropped spans are counted by the `<app_name>_foundations_tracing_export_dropped_spans_total`
+/// counter and the state of the breaker is reported by
+/// the `<app_name>_foundations_tracing_export_circuit_open` gauge.
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
+pub struct ExportCircuitBreakerSettings {
+    /// Enables the circuit breaker.
+    pub enabled: bool,
+
+    /// Number of the consecutive export failures that open the breaker.
+    pub failure_threshold: u32,
+
+    /// Time in milliseconds the breaker stays open before probing the output.
+    pub open_duration_ms: u64,
+
+    /// Archive all the spans while the breaker is open, even if
+    /// [`TraceArchiveSettings::errors_only`] is set. Requires the archive to be enabled.
+    #[cfg(feature = "trace-archive")]
+    pub divert_to_archive: bool,
+}
+
+impl Default for ExportCircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: 5,
+            open_duration_ms: 10_000,
+
+            #[cfg(feature = "trace-archive")]
+            divert_to_archive: false,
+        }
+    }
+}
+
+/// Adaptive sampling settings.
+///
+/// Adaptive sampling adjusts the sampling ratio every second, so the number of spans reported by
+/// the service instance stays close to the target, smoothing the traffic spikes. The ratio never
+/// exceeds [`TracingSettings::sampling_ratio`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+#[derive(PartialEq)]
+pub struct AdaptiveSamplingSettings {
+    /// Enables adaptive sampling.
+    pub enabled: bool,
+
+    /// Target number of spans reported per second.
+    pub target_spans_per_second: u32,
+}
+
+impl Default for AdaptiveSamplingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_spans_per_second: 100,
+        }
+    }
 }
 
 impl Default for TracingSettings {
@@ -51,10 +326,20 @@ impl Default for TracingSettings {
 
         Self {
             enabled: true,
+            output: Default::default(),
             jaeger_tracing_server_addr,
             jaeger_reporter_bind_addr: None,
             sampling_ratio: 1.0,
             rate_limit: Default::default(),
+            adaptive_sampling: Default::default(),
+            trace_id_generation: Default::default(),
+            circuit_breaker: Default::default(),
+
+            #[cfg(feature = "trace-archive")]
+            archive: Default::default(),
+
+            #[cfg(feature = "metrics")]
+            slos: Default::default(),
         }
     }
 }
@@ -63,4 +348,15 @@ fn _assert_traits_implemented_for_all_features() {
     fn assert<S: std::fmt::Debug + Clone + Default>() {}
 
     assert::<TracingSettings>();
+    assert::<TracesOutput>();
+    assert::<UnixSocketTracesOutput>();
+
+    #[cfg(feature = "trace-archive")]
+    assert::<TraceArchiveSettings>();
+    assert::<AdaptiveSamplingSettings>();
+    assert::<ExportCircuitBreakerSettings>();
+    assert::<TraceIdGeneration>();
+
+    #[cfg(feature = "metrics")]
+    assert::<SpanSlo>();
 }
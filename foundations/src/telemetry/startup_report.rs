@@ -0,0 +1,243 @@
+use super::settings::TelemetrySettings;
+use super::ProcessState;
+use crate::ServiceInfo;
+use once_cell::sync::{Lazy, OnceCell};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[cfg(feature = "logging")]
+use super::settings::{LogFormat, LogOutput};
+
+#[cfg(feature = "tracing")]
+use super::settings::TracesOutput;
+
+#[cfg(feature = "otlp-metrics")]
+use super::settings::OtlpProtocol;
+
+static REPORT: OnceCell<StartupReport> = OnceCell::new();
+
+// NOTE: the list is generated by the build script from the features the crate is built with.
+pub(super) static FEATURES: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    env!("FOUNDATIONS_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect()
+});
+
+/// A summary of the service configuration resolved on start up.
+///
+/// The report is emitted with [`StartupReport::emit`] as a single "service started" log record
+/// and is also served as JSON on the `/info` endpoint of the telemetry server, so the startup
+/// configuration of every service instance in the fleet can be audited in a uniform way.
+///
+/// # Examples
+/// ```no_run
+/// use foundations::telemetry::settings::TelemetrySettings;
+/// use foundations::telemetry::{self, StartupReport};
+///
+/// # fn main() -> foundations::BootstrapResult<()> {
+/// let service_info = foundations::service_info!();
+/// let settings = TelemetrySettings::default();
+/// let server = telemetry::init_with_server(&service_info, &settings, vec![])?;
+///
+/// let mut report = StartupReport::new(&service_info, &settings)
+///     .with_settings_file("/etc/my-service/config.yaml");
+///
+/// if let Some(addr) = server.server_addr() {
+///     report = report.with_bind_addr("telemetry", addr);
+/// }
+///
+/// report.emit();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "telemetry-server", derive(serde::Serialize))]
+pub struct StartupReport {
+    /// The name of the service.
+    pub service_name: &'static str,
+
+    /// The version of the service.
+    pub service_version: &'static str,
+
+    /// Addresses the service listens on, keyed by the listener name.
+    pub bind_addrs: BTreeMap<String, SocketAddr>,
+
+    /// Enabled telemetry outputs, e.g. `logs:terminal:text` or `traces:jaeger:127.0.0.1:6831`.
+    pub telemetry_outputs: Vec<String>,
+
+    /// The seccomp mode of the process as reported by the kernel: `disabled`, `strict`, `filter`
+    /// or `unknown` if the mode can't be determined.
+    pub sandbox: &'static str,
+
+    /// Enabled Foundations features.
+    pub features: Vec<&'static str>,
+
+    /// Files the service settings were loaded from. Empty if default settings are used.
+    pub settings_files: Vec<PathBuf>,
+
+    /// Restart count and the previous exit reason of the service, if
+    /// [`TelemetrySettings::state_file`] is specified.
+    pub process: Option<ProcessState>,
+}
+
+impl StartupReport {
+    /// Creates a new report populated with the service information, telemetry outputs,
+    /// enabled features and the current sandbox state.
+    ///
+    /// The report should be created after syscall sandboxing is set up for the sandbox state
+    /// to be reflected correctly.
+    pub fn new(service_info: &ServiceInfo, settings: &TelemetrySettings) -> Self {
+        Self {
+            service_name: service_info.name,
+            service_version: service_info.version,
+            bind_addrs: Default::default(),
+            telemetry_outputs: telemetry_outputs(settings),
+            sandbox: sandbox_state(),
+            features: FEATURES.clone(),
+            settings_files: vec![],
+            process: ProcessState::get().cloned(),
+        }
+    }
+
+    /// Adds a named address the service listens on.
+    pub fn with_bind_addr(mut self, name: impl Into<String>, addr: SocketAddr) -> Self {
+        self.bind_addrs.insert(name.into(), addr);
+        self
+    }
+
+    /// Adds a file the service settings were loaded from.
+    pub fn with_settings_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.settings_files.push(path.into());
+        self
+    }
+
+    /// Returns the report emitted with [`StartupReport::emit`], if any.
+    pub fn get() -> Option<&'static Self> {
+        REPORT.get()
+    }
+
+    /// Logs the report as a single "service started" record and makes it available via
+    /// [`StartupReport::get`] and the telemetry server's `/info` endpoint.
+    ///
+    /// Only the first emitted report is retained, consequent calls only produce a log record.
+    pub fn emit(self) {
+        #[cfg(feature = "logging")]
+        crate::telemetry::log::info!(
+            "service started";
+            "service" => self.service_name,
+            "version" => self.service_version,
+            "bind_addrs" => join(self.bind_addrs.iter().map(|(name, addr)| format!("{name}={addr}"))),
+            "telemetry_outputs" => join(self.telemetry_outputs.iter()),
+            "sandbox" => self.sandbox,
+            "features" => join(self.features.iter()),
+            "settings_files" => join(self.settings_files.iter().map(|p| p.display()))
+        );
+
+        let _ = REPORT.set(self);
+    }
+}
+
+#[cfg(feature = "logging")]
+fn join(items: impl Iterator<Item = impl std::fmt::Display>) -> String {
+    items.map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn telemetry_outputs(_settings: &TelemetrySettings) -> Vec<String> {
+    #[allow(unused_mut)]
+    let mut outputs = vec![];
+
+    #[cfg(feature = "logging")]
+    {
+        let logging = &_settings.logging;
+        let additional = logging
+            .additional_outputs
+            .iter()
+            .map(|output| (&output.output, output.format));
+
+        for (output, format) in std::iter::once((&logging.output, logging.format)).chain(additional)
+        {
+            let format = match format {
+                LogFormat::Text => "text",
+                LogFormat::Json => "json",
+            };
+
+            outputs.push(match output {
+                LogOutput::Terminal => format!("logs:terminal:{format}"),
+                LogOutput::File(path) => format!("logs:file:{}:{format}", path.display()),
+                LogOutput::RotatingFile(output) => {
+                    format!("logs:rotating_file:{}:{format}", output.path.display())
+                }
+            });
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    if _settings.tracing.enabled {
+        outputs.push(match &_settings.tracing.output {
+            TracesOutput::JaegerThriftUdp => format!(
+                "traces:jaeger:{}",
+                _settings.tracing.jaeger_tracing_server_addr
+            ),
+            TracesOutput::UnixSocket(output) => {
+                format!("traces:unix_socket:{}", output.path.display())
+            }
+        });
+
+        #[cfg(feature = "trace-archive")]
+        if _settings.tracing.archive.enabled {
+            outputs.push(format!(
+                "traces:archive:{}",
+                _settings.tracing.archive.directory.display()
+            ));
+        }
+    }
+
+    #[cfg(all(feature = "metrics", feature = "telemetry-server"))]
+    if _settings.server.enabled {
+        outputs.push("metrics:prometheus".to_string());
+    }
+
+    #[cfg(feature = "otlp-metrics")]
+    if _settings.metrics.otlp.enabled {
+        let protocol = match _settings.metrics.otlp.protocol {
+            OtlpProtocol::Http => "http",
+            OtlpProtocol::Grpc => "grpc",
+        };
+
+        outputs.push(format!(
+            "metrics:otlp:{protocol}:{}",
+            _settings.metrics.otlp.endpoint
+        ));
+    }
+
+    #[cfg(feature = "metrics-push")]
+    if _settings.metrics.push.enabled {
+        outputs.push(format!(
+            "metrics:pushgateway:{}",
+            _settings.metrics.push.endpoint
+        ));
+    }
+
+    outputs
+}
+
+pub(super) fn sandbox_state() -> &'static str {
+    #[cfg(target_os = "linux")]
+    if let Ok(status) = std::fs::read_to_string("/proc/self/status") {
+        let mode = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Seccomp:"))
+            .map(str::trim);
+
+        return match mode {
+            Some("0") => "disabled",
+            Some("1") => "strict",
+            Some("2") => "filter",
+            _ => "unknown",
+        };
+    }
+
+    "unknown"
+}
@@ -3,6 +3,7 @@ use crate::utils::feature_use;
 use std::ops::Deref;
 
 feature_use!(cfg(feature = "logging"), {
+    use super::log::init::LogHarness;
     use super::log::testing::{create_test_log, TestLogRecord, TestLogRecords};
     use super::settings::LogVerbosity;
     use super::settings::LoggingSettings;
@@ -11,8 +12,14 @@ feature_use!(cfg(feature = "logging"), {
     use std::sync::RwLockReadGuard;
 });
 
+feature_use!(cfg(feature = "metrics"), {
+    use super::metrics::testing::TestMetrics;
+    use super::metrics::MetricsSnapshot;
+});
+
 feature_use!(cfg(feature = "tracing"), {
     use super::settings::TracingSettings;
+    use super::tracing::init::TracingHarness;
     use super::tracing::testing::{
         create_test_tracer, TestTrace, TestTraceOptions, TestTracesSink,
     };
@@ -68,6 +75,9 @@ impl TestTelemetryContext {
 
                 #[cfg(feature = "tracing")]
                 test_tracer: Some(tracer),
+
+                #[cfg(feature = "metrics")]
+                test_metrics: Some(TestMetrics::new()),
             },
 
             #[cfg(feature = "tracing")]
@@ -107,6 +117,60 @@ impl TestTelemetryContext {
     pub fn traces(&self, options: TestTraceOptions) -> Vec<TestTrace> {
         self.traces_sink.traces(options)
     }
+
+    /// Returns the metrics reported in the test context, in the [Prometheus text format].
+    ///
+    /// Metrics defined with the [`metrics`] macro that are accessed in the scope of the test
+    /// context are registered in the registries of the context, instead of the global ones, so
+    /// the tests running in parallel don't observe each other's metrics. The returned metrics
+    /// include only those metrics, including the optional ones.
+    ///
+    /// # Examples
+    /// ```
+    /// # mod rustdoc_workaround {
+    /// use foundations::telemetry::metrics::{metrics, Counter};
+    ///
+    /// #[metrics]
+    /// pub mod my_lib {
+    ///     /// Number of processed jobs.
+    ///     pub fn jobs_total() -> Counter;
+    /// }
+    /// # }
+    /// # use rustdoc_workaround::my_lib;
+    /// use foundations::telemetry::TelemetryContext;
+    ///
+    /// let ctx = TelemetryContext::test();
+    ///
+    /// {
+    ///     let _scope = ctx.scope();
+    ///
+    ///     my_lib::jobs_total().inc();
+    /// }
+    ///
+    /// // The metric outside of the test context is not affected.
+    /// assert_eq!(my_lib::jobs_total().get(), 0);
+    /// assert!(ctx.metrics().contains("undefined_my_lib_jobs_total 1\n"));
+    /// ```
+    ///
+    /// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+    /// [`metrics`]: crate::telemetry::metrics::metrics
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> String {
+        self.inner
+            .test_metrics
+            .as_ref()
+            .map(TestMetrics::collect)
+            .unwrap_or_default()
+    }
+
+    /// Returns the typed values of the metrics accessed in the scope of the context, see
+    /// [`MetricsSnapshot`].
+    ///
+    /// [`MetricsSnapshot`]: crate::telemetry::metrics::MetricsSnapshot
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot::parse(&self.metrics()).expect("test metrics should be well-formed")
+    }
 }
 
 impl Deref for TestTelemetryContext {
@@ -116,3 +180,74 @@ impl Deref for TestTelemetryContext {
         &self.inner
     }
 }
+
+/// A detector of the leaked telemetry scopes.
+///
+/// A scope leaks if its handle, e.g. the one returned by [`TelemetryContext::scope`], is not
+/// dropped, which leaves the telemetry context of the scope active on the thread forever, e.g. if
+/// the handle is [forgotten] or moved to a long-lived structure.
+///
+/// [`with_test_telemetry`] macro automatically fails the tests that leak scopes.
+///
+/// # Examples
+/// ```should_panic
+/// use foundations::telemetry::{ScopeLeakDetector, TelemetryContext};
+///
+/// let detector = ScopeLeakDetector::new();
+/// let ctx = TelemetryContext::test();
+///
+/// std::mem::forget(ctx.scope());
+///
+/// detector.assert_no_leaks();
+/// ```
+///
+/// [`TelemetryContext::scope`]: super::TelemetryContext::scope
+/// [forgotten]: std::mem::forget
+/// [`with_test_telemetry`]: super::with_test_telemetry
+#[derive(Debug)]
+pub struct ScopeLeakDetector {
+    depths: Vec<(&'static str, usize)>,
+}
+
+impl ScopeLeakDetector {
+    /// Creates a detector that records the number of the scopes entered on the current thread.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            depths: scope_depths(),
+        }
+    }
+
+    /// Panics if any of the scopes entered on the current thread since the detector creation
+    /// is still active.
+    pub fn assert_no_leaks(&self) {
+        for ((stack, before), (_, after)) in self.depths.iter().zip(scope_depths()) {
+            assert!(
+                after <= *before,
+                "{} telemetry scope(s) of {stack} leaked on the current thread",
+                after - before
+            );
+        }
+    }
+}
+
+fn scope_depths() -> Vec<(&'static str, usize)> {
+    #[allow(unused_mut)]
+    let mut depths = vec![];
+
+    #[cfg(feature = "logging")]
+    depths.push(("log", LogHarness::get().log_scope_stack.depth()));
+
+    #[cfg(feature = "tracing")]
+    {
+        let harness = TracingHarness::get();
+
+        depths.push(("span", harness.span_scope_stack.depth()));
+        depths.push(("test_tracer", harness.test_tracer_scope_stack.depth()));
+    }
+
+    #[cfg(feature = "metrics")]
+    depths.push(("test_metrics", super::metrics::testing::scope_depth()));
+
+    depths
+}
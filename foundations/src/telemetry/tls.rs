@@ -0,0 +1,268 @@
+//! TLS connection telemetry.
+//!
+//! Services that terminate TLS often need to correlate the requests with the parameters of the
+//! TLS connection they came from, e.g. to debug handshake failures or to identify the clients
+//! that use obsolete protocol versions. [`TlsConnectionInfo`] captures the negotiated connection
+//! parameters and records them as log fields and tags of the current span.
+//!
+//! The helpers are agnostic of the TLS library: the connection info is filled in from the
+//! session of the library used by the service (e.g. [rustls] or [BoringSSL]).
+//!
+//! As the server name and the client fingerprint can be used to identify users, they are
+//! recorded only if enabled in [`TlsTelemetrySettings`].
+//!
+//! # Examples
+//! ```
+//! use foundations::telemetry::log::{self, TestLogRecord};
+//! use foundations::telemetry::settings::Level;
+//! use foundations::telemetry::tls::{TlsConnectionInfo, TlsTelemetrySettings};
+//! use foundations::telemetry::TelemetryContext;
+//!
+//! // Test context is used for demonstration purposes to show the resulting log records.
+//! let ctx = TelemetryContext::test();
+//! let _scope = ctx.scope();
+//!
+//! let info = TlsConnectionInfo {
+//!     protocol: Some("TLSv1.3".into()),
+//!     cipher: Some("TLS13_AES_128_GCM_SHA256".into()),
+//!     alpn: Some("h2".into()),
+//!     server_name: Some("example.com".into()),
+//!     ..Default::default()
+//! };
+//!
+//! info.record(&TlsTelemetrySettings::default());
+//!
+//! log::warn!("Handshake completed");
+//!
+//! assert_eq!(*ctx.log_records(), &[
+//!     TestLogRecord {
+//!         level: Level::Warning,
+//!         message: "Handshake completed".into(),
+//!         fields: vec![
+//!             ("tls.alpn".into(), "h2".into()),
+//!             ("tls.cipher".into(), "TLS13_AES_128_GCM_SHA256".into()),
+//!             ("tls.protocol".into(), "TLSv1.3".into()),
+//!         ]
+//!     }
+//! ]);
+//! ```
+//!
+//! [rustls]: https://docs.rs/rustls
+//! [BoringSSL]: https://docs.rs/boring
+
+use md5::{Digest, Md5};
+use std::fmt::Write;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+/// Settings of the TLS connection telemetry.
+#[cfg_attr(feature = "settings", settings(crate_path = "crate"))]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Default, Debug))]
+pub struct TlsTelemetrySettings {
+    /// Records the server name (SNI) requested by the client.
+    pub record_server_name: bool,
+
+    /// Records the [JA3] fingerprint of the client.
+    ///
+    /// [JA3]: https://github.com/salesforce/ja3
+    pub record_fingerprint: bool,
+}
+
+/// Parameters of a TLS connection.
+///
+/// Parameters are recorded as the following log fields and span tags, if specified:
+///
+/// | Field            | Parameter                                     |
+/// |------------------|-----------------------------------------------|
+/// | `tls.protocol`   | [`TlsConnectionInfo::protocol`]               |
+/// | `tls.cipher`     | [`TlsConnectionInfo::cipher`]                 |
+/// | `tls.alpn`       | [`TlsConnectionInfo::alpn`]                   |
+/// | `tls.sni`        | [`TlsConnectionInfo::server_name`]            |
+/// | `tls.ja3`        | Hash of [`TlsConnectionInfo::client_hello`]   |
+#[derive(Clone, Debug, Default)]
+pub struct TlsConnectionInfo {
+    /// Negotiated protocol version, e.g. `TLSv1.3`.
+    pub protocol: Option<String>,
+
+    /// Negotiated cipher suite, e.g. `TLS13_AES_128_GCM_SHA256`.
+    pub cipher: Option<String>,
+
+    /// Negotiated application protocol (ALPN), e.g. `h2`.
+    pub alpn: Option<String>,
+
+    /// Server name (SNI) requested by the client.
+    ///
+    /// Recorded only if [`TlsTelemetrySettings::record_server_name`] is enabled.
+    pub server_name: Option<String>,
+
+    /// Parameters of the client hello message used to fingerprint the client.
+    ///
+    /// Recorded only if [`TlsTelemetrySettings::record_fingerprint`] is enabled.
+    pub client_hello: Option<ClientHello>,
+}
+
+impl TlsConnectionInfo {
+    /// Records the connection parameters as log fields of the current log and tags of
+    /// the current span.
+    ///
+    /// Usually, the parameters are recorded in the telemetry context of the connection
+    /// once the handshake is completed, so they are inherited by the request logs and spans.
+    pub fn record(&self, settings: &TlsTelemetrySettings) {
+        for (name, value) in self.fields(settings) {
+            #[cfg(feature = "logging")]
+            crate::telemetry::log::add_fields!(name => value.clone());
+
+            #[cfg(feature = "tracing")]
+            crate::telemetry::tracing::add_span_tags!(name => value);
+        }
+    }
+
+    fn fields(&self, settings: &TlsTelemetrySettings) -> Vec<(&'static str, String)> {
+        let mut fields = vec![];
+
+        let params = [
+            ("tls.protocol", &self.protocol),
+            ("tls.cipher", &self.cipher),
+            ("tls.alpn", &self.alpn),
+        ];
+
+        for (name, value) in params {
+            if let Some(value) = value {
+                fields.push((name, value.clone()));
+            }
+        }
+
+        if let Some(server_name) = self.server_name.as_ref() {
+            if settings.record_server_name {
+                fields.push(("tls.sni", server_name.clone()));
+            }
+        }
+
+        if let Some(client_hello) = self.client_hello.as_ref() {
+            if settings.record_fingerprint {
+                fields.push(("tls.ja3", client_hello.ja3_hash()));
+            }
+        }
+
+        fields
+    }
+}
+
+/// Parameters of the TLS client hello message that constitute the [JA3] fingerprint
+/// of the client.
+///
+/// [GREASE] values are ignored.
+///
+/// [JA3]: https://github.com/salesforce/ja3
+/// [GREASE]: https://www.rfc-editor.org/rfc/rfc8701
+#[derive(Clone, Debug, Default)]
+pub struct ClientHello {
+    /// Legacy protocol version of the message, e.g. `0x0303` for TLS 1.2.
+    pub version: u16,
+
+    /// Cipher suites offered by the client.
+    pub cipher_suites: Vec<u16>,
+
+    /// Types of the extensions in the order they appear in the message.
+    pub extensions: Vec<u16>,
+
+    /// Supported groups (elliptic curves) offered by the client.
+    pub supported_groups: Vec<u16>,
+
+    /// Supported elliptic curve point formats.
+    pub ec_point_formats: Vec<u8>,
+}
+
+impl ClientHello {
+    /// Returns the JA3 string of the client hello, e.g.
+    /// `771,4865-4866-4867,0-11-10-35-16,29-23-24,0`.
+    pub fn ja3(&self) -> String {
+        fn join(out: &mut String, values: impl Iterator<Item = u16>) {
+            for (i, value) in values.filter(|v| !is_grease(*v)).enumerate() {
+                if i > 0 {
+                    out.push('-');
+                }
+
+                let _ = write!(out, "{value}");
+            }
+        }
+
+        let mut out = format!("{},", self.version);
+
+        join(&mut out, self.cipher_suites.iter().copied());
+        out.push(',');
+        join(&mut out, self.extensions.iter().copied());
+        out.push(',');
+        join(&mut out, self.supported_groups.iter().copied());
+        out.push(',');
+        join(&mut out, self.ec_point_formats.iter().map(|f| *f as u16));
+
+        out
+    }
+
+    /// Returns the JA3 fingerprint: the hex-encoded MD5 hash of the [JA3 string].
+    ///
+    /// [JA3 string]: ClientHello::ja3
+    pub fn ja3_hash(&self) -> String {
+        let hash = Md5::digest(self.ja3().as_bytes());
+
+        hash.iter().fold(String::with_capacity(32), |mut out, b| {
+            let _ = write!(out, "{b:02x}");
+            out
+        })
+    }
+}
+
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ja3_fingerprint() {
+        let client_hello = ClientHello {
+            version: 0x0303,
+            cipher_suites: vec![0x0a0a, 4865, 4866, 4867],
+            extensions: vec![0x1a1a, 0, 11, 10, 35, 16],
+            supported_groups: vec![0x2a2a, 29, 23, 24],
+            ec_point_formats: vec![0],
+        };
+
+        assert_eq!(
+            client_hello.ja3(),
+            "771,4865-4866-4867,0-11-10-35-16,29-23-24,0"
+        );
+        assert_eq!(client_hello.ja3_hash(), "bf5b0207209693157bd583fb4e78f433");
+    }
+
+    #[test]
+    fn records_sensitive_fields_only_if_enabled() {
+        let info = TlsConnectionInfo {
+            protocol: Some("TLSv1.3".into()),
+            server_name: Some("example.com".into()),
+            client_hello: Some(Default::default()),
+            ..Default::default()
+        };
+
+        let names = |settings| {
+            info.fields(&settings)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(TlsTelemetrySettings::default()), ["tls.protocol"]);
+
+        assert_eq!(
+            names(TlsTelemetrySettings {
+                record_server_name: true,
+                record_fingerprint: true,
+            }),
+            ["tls.protocol", "tls.sni", "tls.ja3"]
+        );
+    }
+}
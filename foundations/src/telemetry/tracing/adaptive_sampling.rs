@@ -0,0 +1,140 @@
+use crate::telemetry::settings::TracingSettings;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const ADJUSTMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+// NOTE: the ratio never drops to zero, so the span rate can still be estimated.
+const MIN_RATIO: f64 = 1e-6;
+
+// NOTE: weight of the latest interval in the estimated span rate, lower values make
+// the ratio more resistant to short traffic spikes.
+const SMOOTHING_FACTOR: f64 = 0.3;
+
+/// Sampling ratio that is adjusted to keep the number of reported spans close to the target.
+///
+/// The total rate of the spans, as if all of them were sampled, is estimated from the number of
+/// the spans reported in each interval and the ratio they were sampled with, the ratio is then
+/// set to the fraction of the estimated rate that matches the target.
+#[derive(Debug)]
+pub(crate) struct AdaptiveSamplingRatio {
+    target_spans_per_second: f64,
+    max_ratio: f64,
+    ratio: AtomicU64,
+    interval: Mutex<Interval>,
+}
+
+#[derive(Debug)]
+struct Interval {
+    start: Instant,
+    spans: u64,
+    estimated_rate: Option<f64>,
+}
+
+impl AdaptiveSamplingRatio {
+    pub(crate) fn new(settings: &TracingSettings) -> Self {
+        let max_ratio = settings.sampling_ratio;
+
+        Self {
+            target_spans_per_second: settings.adaptive_sampling.target_spans_per_second as f64,
+            max_ratio,
+            ratio: AtomicU64::new(max_ratio.to_bits()),
+            interval: Mutex::new(Interval {
+                start: Instant::now(),
+                spans: 0,
+                estimated_rate: None,
+            }),
+        }
+    }
+
+    /// Returns the current sampling ratio.
+    pub(crate) fn ratio(&self) -> f64 {
+        f64::from_bits(self.ratio.load(Ordering::Relaxed))
+    }
+
+    /// Records the reported spans, adjusting the ratio if the interval has elapsed.
+    pub(crate) fn record_spans(&self, spans: u64) {
+        self.record_spans_at(spans, Instant::now());
+    }
+
+    fn record_spans_at(&self, spans: u64, now: Instant) {
+        let mut interval = self.interval.lock();
+
+        interval.spans += spans;
+
+        let elapsed = now.saturating_duration_since(interval.start);
+
+        if elapsed < ADJUSTMENT_INTERVAL {
+            return;
+        }
+
+        let ratio = self.ratio().max(MIN_RATIO);
+        let rate = interval.spans as f64 / elapsed.as_secs_f64() / ratio;
+
+        let estimated_rate = match interval.estimated_rate {
+            Some(estimated) => SMOOTHING_FACTOR * rate + (1.0 - SMOOTHING_FACTOR) * estimated,
+            None => rate,
+        };
+
+        let new_ratio = if estimated_rate > 0.0 {
+            (self.target_spans_per_second / estimated_rate).clamp(MIN_RATIO, self.max_ratio)
+        } else {
+            self.max_ratio
+        };
+
+        self.ratio.store(new_ratio.to_bits(), Ordering::Relaxed);
+
+        *interval = Interval {
+            start: now,
+            spans: 0,
+            estimated_rate: Some(estimated_rate),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::settings::AdaptiveSamplingSettings;
+
+    fn sampling_ratio(target_spans_per_second: u32) -> AdaptiveSamplingRatio {
+        AdaptiveSamplingRatio::new(&TracingSettings {
+            adaptive_sampling: AdaptiveSamplingSettings {
+                enabled: true,
+                target_spans_per_second,
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn adjusts_ratio_to_target_rate() {
+        let ratio = sampling_ratio(100);
+        let mut now = Instant::now();
+
+        assert_eq!(ratio.ratio(), 1.0);
+
+        // NOTE: steady traffic of 1000 spans per second if all of them are sampled.
+        for _ in 0..20 {
+            now += ADJUSTMENT_INTERVAL;
+            ratio.record_spans_at((1000.0 * ratio.ratio()) as u64, now);
+        }
+
+        assert!((ratio.ratio() - 0.1).abs() < 0.01, "{}", ratio.ratio());
+
+        // NOTE: a short spike doesn't drop the ratio proportionally.
+        now += ADJUSTMENT_INTERVAL;
+        ratio.record_spans_at((10_000.0 * ratio.ratio()) as u64, now);
+
+        assert!(ratio.ratio() > 0.02, "{}", ratio.ratio());
+
+        // NOTE: the ratio recovers up to the maximum once the traffic stops.
+        for _ in 0..50 {
+            now += ADJUSTMENT_INTERVAL;
+            ratio.record_spans_at(0, now);
+        }
+
+        assert_eq!(ratio.ratio(), 1.0);
+    }
+}
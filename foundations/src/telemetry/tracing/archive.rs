@@ -0,0 +1,345 @@
+use super::circuit_breaker::CircuitBreaker;
+use super::frame;
+use super::internal::FinishedSpan;
+use crate::compression::{CompressionAlgorithm, CompressionSettings, Encoder};
+use crate::telemetry::settings::TraceArchiveSettings;
+use crate::{BootstrapResult, ServiceInfo};
+use anyhow::Context;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TrySendError};
+use rustracing::tag::TagValue;
+use rustracing_jaeger::thrift::jaeger;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+const FILE_PREFIX: &str = "traces-";
+const FILE_SUFFIX: &str = ".bin.zst";
+
+/// Starts archiving the spans received from the tracer, if enabled in the settings.
+///
+/// Returns the receiver of the spans for the exporter. The spans are forwarded to the exporter
+/// without blocking, so a stalled exporter doesn't stop the archiving. If the circuit breaker of
+/// the exporter is specified, all the spans are archived while it's open.
+pub(super) fn start(
+    service_info: &ServiceInfo,
+    settings: &TraceArchiveSettings,
+    diverting_breaker: Option<Arc<CircuitBreaker>>,
+    span_rx: Receiver<FinishedSpan>,
+) -> BootstrapResult<Receiver<FinishedSpan>> {
+    if !settings.enabled {
+        return Ok(span_rx);
+    }
+
+    let mut archive = TraceArchive::new(service_info, settings)?;
+
+    archive.diverting_breaker = diverting_breaker;
+
+    let (exporter_tx, exporter_rx) = crossbeam_channel::bounded(span_rx.capacity().unwrap_or(30));
+
+    thread::spawn(move || archive.run(span_rx, exporter_tx));
+
+    Ok(exporter_rx)
+}
+
+/// Writer of the span batches to a bounded ring of zstd-compressed files.
+struct TraceArchive {
+    settings: TraceArchiveSettings,
+    process: jaeger::Process,
+    file: Option<Encoder<BufWriter<File>>>,
+    last_file_timestamp: u128,
+    diverting_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl TraceArchive {
+    fn new(service_info: &ServiceInfo, settings: &TraceArchiveSettings) -> BootstrapResult<Self> {
+        fs::create_dir_all(&settings.directory).with_context(|| {
+            format!(
+                "failed to create the trace archive directory {}",
+                settings.directory.display()
+            )
+        })?;
+
+        Ok(Self {
+            settings: settings.clone(),
+            process: frame::process(service_info),
+            file: None,
+            last_file_timestamp: 0,
+            diverting_breaker: None,
+        })
+    }
+
+    /// Archives the received spans until the channel is closed.
+    fn run(mut self, span_rx: Receiver<FinishedSpan>, exporter_tx: Sender<FinishedSpan>) {
+        let flush_interval = Duration::from_millis(self.settings.flush_interval_ms);
+        let max_batch_size = self.settings.max_batch_size.max(1);
+        let mut batch = Vec::with_capacity(max_batch_size);
+        let mut exporter_tx = Some(exporter_tx);
+
+        loop {
+            let deadline = Instant::now() + flush_interval;
+            let mut disconnected = false;
+
+            while batch.len() < max_batch_size {
+                let span = match span_rx.recv_deadline(deadline) {
+                    Ok(span) => span,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                };
+
+                if self.should_archive(&span) {
+                    batch.push(jaeger::Span::from(&span));
+                }
+
+                // NOTE: the span is dropped if the exporter can't keep up, same as if it was
+                // sent to the exporter by the tracer directly.
+                if let Some(tx) = &exporter_tx {
+                    if let Err(TrySendError::Disconnected(_)) = tx.try_send(span) {
+                        exporter_tx = None;
+                    }
+                }
+            }
+
+            if !batch.is_empty() {
+                if let Err(e) = self.write(std::mem::take(&mut batch)) {
+                    #[cfg(feature = "logging")]
+                    log::warn!(
+                        "failed to write tracing spans to the archive";
+                        "directory" => %self.settings.directory.display(),
+                        "error" => %e
+                    );
+
+                    #[cfg(not(feature = "logging"))]
+                    drop(e);
+                }
+            }
+
+            if disconnected {
+                if let Some(file) = self.file.take() {
+                    let _ = file.finish().and_then(|mut writer| writer.flush());
+                }
+
+                return;
+            }
+        }
+    }
+
+    fn should_archive(&self, span: &FinishedSpan) -> bool {
+        !self.settings.errors_only
+            || self
+                .diverting_breaker
+                .as_ref()
+                .is_some_and(|breaker| breaker.is_open())
+            || span
+                .tags()
+                .iter()
+                .any(|tag| tag.name() == "error" && matches!(tag.value(), TagValue::Boolean(true)))
+    }
+
+    fn write(&mut self, spans: Vec<jaeger::Span>) -> io::Result<()> {
+        let frame = frame::encode(&self.process, spans)?;
+
+        // NOTE: the file is closed on errors, so the next batch is written to a new one.
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => self.open()?,
+        };
+
+        file.write_all(&frame)?;
+        file.flush()?;
+
+        if file.compressed_len() >= self.settings.max_file_size {
+            file.finish()?.flush()?;
+        } else {
+            self.file = Some(file);
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new archive file, removing the oldest files above the limit.
+    fn open(&mut self) -> io::Result<Encoder<BufWriter<File>>> {
+        let mut files = self.files()?;
+        let max_files = self.settings.max_files.max(1);
+
+        if files.len() >= max_files {
+            for path in files.drain(..=files.len() - max_files) {
+                fs::remove_file(path)?;
+            }
+        }
+
+        // NOTE: the timestamps are kept increasing, so the files are ordered by their names even
+        // if they are created within the same millisecond.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        self.last_file_timestamp = now.max(self.last_file_timestamp + 1);
+
+        let path = self.settings.directory.join(format!(
+            "{FILE_PREFIX}{:013}{FILE_SUFFIX}",
+            self.last_file_timestamp
+        ));
+
+        let settings = CompressionSettings {
+            algorithm: CompressionAlgorithm::Zstd,
+            level: Some(self.settings.compression_level),
+        };
+
+        Encoder::new(
+            BufWriter::new(File::create(path)?),
+            &settings,
+            "trace_archive",
+        )
+    }
+
+    /// Returns the paths of the archive files, from the oldest to the newest.
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = vec![];
+
+        for entry in fs::read_dir(&self.settings.directory)? {
+            let path = entry?.path();
+
+            let is_archive = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX));
+
+            if is_archive {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::tracing::internal::Tracer;
+    use crate::telemetry::tracing::rate_limit::RateLimitingProbabilisticSampler;
+    use rustracing::tag::Tag;
+    use thrift_codec::message::Message;
+    use thrift_codec::CompactDecode;
+
+    fn archive(settings: TraceArchiveSettings) -> (Tracer, thread::JoinHandle<()>) {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let sampler = RateLimitingProbabilisticSampler::new(&Default::default()).unwrap();
+        let tracer = Tracer::with_sender(sampler, span_tx);
+        let archive = TraceArchive::new(&crate::service_info!(), &settings).unwrap();
+        let (exporter_tx, _) = crossbeam_channel::bounded(1);
+
+        (
+            tracer,
+            thread::spawn(move || archive.run(span_rx, exporter_tx)),
+        )
+    }
+
+    /// Returns the given span names found in each archive file.
+    fn archived_spans<'a>(settings: &TraceArchiveSettings, names: &[&'a str]) -> Vec<Vec<&'a str>> {
+        let archive = TraceArchive::new(&crate::service_info!(), settings).unwrap();
+        let mut files = vec![];
+
+        for path in archive.files().unwrap() {
+            let data = fs::read(path).unwrap();
+            let data =
+                crate::compression::decompress(&data, CompressionAlgorithm::Zstd, "test").unwrap();
+            let mut data = &data[..];
+            let mut found = vec![];
+
+            while !data.is_empty() {
+                let len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                let payload = &data[4..4 + len];
+                let message = Message::compact_decode(&mut &payload[..]).unwrap();
+
+                assert_eq!(message.method_name(), "emitBatch");
+
+                // NOTE: strings are encoded as is in the Thrift compact protocol.
+                found.extend(names.iter().copied().filter(|name| {
+                    payload
+                        .windows(name.len())
+                        .any(|window| window == name.as_bytes())
+                }));
+
+                data = &data[4 + len..];
+            }
+
+            files.push(found);
+        }
+
+        files
+    }
+
+    fn settings(name: &str) -> TraceArchiveSettings {
+        let directory = std::env::temp_dir().join(format!(
+            "foundations-trace-archive-{name}-{}",
+            std::process::id()
+        ));
+
+        let _ = fs::remove_dir_all(&directory);
+
+        TraceArchiveSettings {
+            enabled: true,
+            directory,
+            max_batch_size: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rotates_files() {
+        let settings = TraceArchiveSettings {
+            max_file_size: 1,
+            max_files: 2,
+            ..settings("rotation")
+        };
+
+        let (tracer, handle) = archive(settings.clone());
+
+        for name in ["first", "second", "third"] {
+            drop(tracer.span(name).start());
+        }
+
+        drop(tracer);
+        handle.join().unwrap();
+
+        assert_eq!(
+            archived_spans(&settings, &["first", "second", "third"]),
+            [["second"], ["third"]]
+        );
+
+        fs::remove_dir_all(&settings.directory).unwrap();
+    }
+
+    #[test]
+    fn archives_only_errors() {
+        let settings = TraceArchiveSettings {
+            errors_only: true,
+            ..settings("errors")
+        };
+
+        let (tracer, handle) = archive(settings.clone());
+
+        drop(tracer.span("ok").start());
+        drop(tracer.span("failed").tag(Tag::new("error", true)).start());
+
+        drop(tracer);
+        handle.join().unwrap();
+
+        assert_eq!(archived_spans(&settings, &["ok", "failed"]), [["failed"]]);
+
+        fs::remove_dir_all(&settings.directory).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use super::internal::current_span;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rustracing::span::BaggageItem;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+type Hooks = Vec<(Cow<'static, str>, Arc<dyn Fn(&str) + Send + Sync>)>;
+
+static HOOKS: Lazy<RwLock<Hooks>> = Lazy::new(Default::default);
+
+/// Returns the value of the baggage item of the current trace.
+///
+/// Baggage items are key-value pairs that are propagated to all the descendant spans of
+/// the span they are set on, and to the traces of other services that are started with
+/// the baggage obtained with [`baggage`].
+///
+/// Note that baggage is only maintained for the sampled traces.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::TelemetryContext;
+/// use foundations::telemetry::tracing;
+///
+/// // Test context is used for demonstration purposes.
+/// let ctx = TelemetryContext::test();
+/// let _scope = ctx.scope();
+///
+/// let _root = tracing::span("root");
+///
+/// tracing::set_baggage_item("route", "canary");
+///
+/// let _child = tracing::span("child");
+///
+/// assert_eq!(tracing::baggage_item("route").as_deref(), Some("canary"));
+/// ```
+pub fn baggage_item(name: &str) -> Option<String> {
+    current_span()?
+        .inner
+        .read()
+        .context()?
+        .baggage_items()
+        .iter()
+        .find(|item| item.name() == name)
+        .map(|item| item.value().to_string())
+}
+
+/// Returns all the baggage items of the current trace, sorted by name.
+///
+/// The baggage can be passed to other services along with the [trace stitching state] and
+/// provided to [`start_trace`] in [`StartTraceOptions::baggage`] to propagate it to their traces.
+///
+/// [trace stitching state]: super::state_for_trace_stitching
+/// [`start_trace`]: super::start_trace
+/// [`StartTraceOptions::baggage`]: super::StartTraceOptions::baggage
+pub fn baggage() -> Vec<(String, String)> {
+    let Some(span) = current_span() else {
+        return vec![];
+    };
+
+    let span = span.inner.read();
+
+    span.context()
+        .map(|ctx| {
+            ctx.baggage_items()
+                .iter()
+                .map(|item| (item.name().to_string(), item.value().to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Sets the baggage item on the current span, so it's propagated to all its descendant spans.
+///
+/// Runs the [baggage hooks] registered for the item.
+///
+/// [baggage hooks]: register_baggage_hook
+pub fn set_baggage_item(name: impl Into<String>, value: impl Into<String>) {
+    let (name, value) = (name.into(), value.into());
+
+    let Some(span) = current_span() else {
+        return;
+    };
+
+    span.inner
+        .write()
+        .set_baggage_item(|| BaggageItem::new(&name, &value));
+
+    run_hooks(&name, &value);
+}
+
+/// Registers a hook that is run whenever the baggage item with the given name is set on a span
+/// with [`set_baggage_item`] or received from upstream with [`StartTraceOptions::baggage`].
+///
+/// The hook receives the value of the item and runs in the telemetry context of the span, so it
+/// can adjust the behavior of the request processing, e.g. add log fields or span tags. Multiple
+/// hooks can be registered for the same item, they are run in the registration order.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::TelemetryContext;
+/// use foundations::telemetry::tracing::{self, test_trace, TestTraceOptions};
+///
+/// tracing::register_baggage_hook("route", |route| {
+///     tracing::add_span_tags!("route" => route.to_string());
+/// });
+///
+/// // Test context is used for demonstration purposes to show the resulting traces.
+/// let ctx = TelemetryContext::test();
+///
+/// {
+///     let _scope = ctx.scope();
+///     let _root = tracing::span("root");
+///
+///     tracing::set_baggage_item("route", "canary");
+/// }
+///
+/// assert_eq!(
+///     ctx.traces(TestTraceOptions {
+///         include_tags: true,
+///         ..Default::default()
+///     }),
+///     vec![test_trace! {
+///         "root"; { tags: [("route", "canary")] }
+///     }]
+/// );
+/// ```
+///
+/// [`StartTraceOptions::baggage`]: super::StartTraceOptions::baggage
+pub fn register_baggage_hook(
+    name: impl Into<Cow<'static, str>>,
+    hook: impl Fn(&str) + Send + Sync + 'static,
+) {
+    HOOKS.write().push((name.into(), Arc::new(hook)));
+}
+
+fn run_hooks(name: &str, value: &str) {
+    // NOTE: clone the hooks, so they can register other hooks or set baggage items themselves.
+    let hooks: Vec<_> = HOOKS
+        .read()
+        .iter()
+        .filter(|(hook_name, _)| hook_name == name)
+        .map(|(_, hook)| Arc::clone(hook))
+        .collect();
+
+    for hook in hooks {
+        hook(value);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::telemetry::settings::ExportCircuitBreakerSettings;
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, Gauge};
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_tracing_export {
+    /// Number of the spans dropped because the circuit breaker of the traces output is open.
+    pub fn dropped_spans_total() -> Counter;
+
+    /// Whether the circuit breaker of the traces output is open.
+    pub fn circuit_open() -> Gauge;
+}
+
+/// Circuit breaker of the traces output, see [`ExportCircuitBreakerSettings`].
+pub(super) struct CircuitBreaker {
+    enabled: bool,
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // NOTE: an export is attempted as a probe of the output.
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(settings: &ExportCircuitBreakerSettings) -> Self {
+        Self {
+            enabled: settings.enabled,
+            failure_threshold: settings.failure_threshold.max(1),
+            open_duration: Duration::from_millis(settings.open_duration_ms),
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns whether the spans should be exported. Otherwise, the spans are counted as dropped.
+    pub(super) fn allow(&self, spans: usize) -> bool {
+        let mut state = self.state.lock();
+
+        match *state {
+            State::Open { until } if Instant::now() < until => {
+                #[cfg(feature = "metrics")]
+                foundations_tracing_export::dropped_spans_total().inc_by(spans as u64);
+
+                #[cfg(not(feature = "metrics"))]
+                let _ = spans;
+
+                false
+            }
+            State::Open { .. } => {
+                *state = State::HalfOpen;
+
+                true
+            }
+            State::Closed { .. } | State::HalfOpen => true,
+        }
+    }
+
+    /// Returns whether the breaker is open, i.e. the spans are not exported.
+    #[cfg_attr(not(feature = "trace-archive"), allow(dead_code))]
+    pub(super) fn is_open(&self) -> bool {
+        !matches!(*self.state.lock(), State::Closed { .. })
+    }
+
+    pub(super) fn record_success(&self) {
+        let mut state = self.state.lock();
+        let was_open = !matches!(*state, State::Closed { .. });
+
+        *state = State::Closed { failures: 0 };
+
+        if !was_open {
+            return;
+        }
+
+        #[cfg(feature = "logging")]
+        log::info!("traces output recovered, closing the circuit breaker");
+
+        #[cfg(feature = "metrics")]
+        foundations_tracing_export::circuit_open().set(0);
+    }
+
+    /// Records an export failure and returns whether the breaker is open, in which case
+    /// the failure shouldn't be logged.
+    pub(super) fn record_failure(&self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let mut state = self.state.lock();
+        let until = Instant::now() + self.open_duration;
+
+        match *state {
+            State::Closed { failures } if failures + 1 < self.failure_threshold => {
+                *state = State::Closed {
+                    failures: failures + 1,
+                };
+
+                false
+            }
+            State::Closed { .. } => {
+                *state = State::Open { until };
+
+                #[cfg(feature = "logging")]
+                log::warn!(
+                    "traces output is failing, opening the circuit breaker";
+                    "failures" => self.failure_threshold,
+                    "open_duration_ms" => self.open_duration.as_millis() as u64
+                );
+
+                #[cfg(feature = "metrics")]
+                foundations_tracing_export::circuit_open().set(1);
+
+                true
+            }
+            State::Open { .. } | State::HalfOpen => {
+                *state = State::Open { until };
+
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(&ExportCircuitBreakerSettings {
+            failure_threshold: 3,
+            open_duration_ms: 50,
+            ..Default::default()
+        });
+
+        assert!(!breaker.record_failure());
+        breaker.record_success();
+        assert!(!breaker.record_failure());
+        assert!(!breaker.record_failure());
+        assert!(breaker.record_failure());
+
+        assert!(breaker.is_open());
+        assert!(!breaker.allow(1));
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        // NOTE: the failed probe reopens the breaker.
+        assert!(breaker.allow(1));
+        assert!(breaker.record_failure());
+        assert!(!breaker.allow(1));
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        assert!(breaker.allow(1));
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow(1));
+    }
+
+    #[test]
+    fn never_opens_if_disabled() {
+        let breaker = CircuitBreaker::new(&ExportCircuitBreakerSettings {
+            enabled: false,
+            failure_threshold: 1,
+            ..Default::default()
+        });
+
+        for _ in 0..10 {
+            assert!(!breaker.record_failure());
+            assert!(breaker.allow(1));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use super::internal::FinishedSpan;
+use crate::ServiceInfo;
+use rustracing::tag::Tag;
+use rustracing_jaeger::thrift::{agent, jaeger};
+use std::io;
+use thrift_codec::message::Message;
+use thrift_codec::CompactEncode;
+
+/// Returns the Jaeger process describing the service.
+pub(super) fn process(service_info: &ServiceInfo) -> jaeger::Process {
+    jaeger::Process {
+        service_name: service_info.name.to_string(),
+        tags: vec![(&Tag::new("app.version", service_info.version)).into()],
+    }
+}
+
+/// Encodes a frame with the big-endian `u32` length of the payload followed by the payload: the
+/// Jaeger agent `emitBatch` message with the batch of spans encoded in the Thrift compact
+/// protocol.
+pub(super) fn encode(process: &jaeger::Process, spans: Vec<jaeger::Span>) -> io::Result<Vec<u8>> {
+    let batch = jaeger::Batch {
+        process: process.clone(),
+        spans,
+    };
+
+    let message = Message::from(agent::EmitBatchNotification { batch });
+
+    // NOTE: reserve the length prefix.
+    let mut frame = vec![0; 4];
+
+    message
+        .compact_encode(&mut frame)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let len = u32::try_from(frame.len() - 4)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "span batch is too large"))?;
+
+    frame[..4].copy_from_slice(&len.to_be_bytes());
+
+    Ok(frame)
+}
+
+/// Encodes the finished spans in a frame, see [`encode`].
+pub(super) fn encode_spans(
+    process: &jaeger::Process,
+    spans: &[FinishedSpan],
+) -> io::Result<Vec<u8>> {
+    encode(process, spans.iter().map(From::from).collect())
+}
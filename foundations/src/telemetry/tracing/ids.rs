@@ -0,0 +1,111 @@
+use crate::telemetry::settings::TraceIdGeneration;
+use once_cell::sync::OnceCell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use rustracing_jaeger::span::TraceId;
+
+static CUSTOM_GENERATOR: OnceCell<Box<dyn IdGenerator>> = OnceCell::new();
+
+/// A generator of the trace and span IDs.
+///
+/// Can be used to implement an organization-specific ID scheme, see [`set_id_generator`].
+pub trait IdGenerator: Send + Sync + 'static {
+    /// Generates an ID for a new trace.
+    fn trace_id(&self) -> TraceId;
+
+    /// Generates an ID for a new span.
+    fn span_id(&self) -> u64 {
+        rand::random()
+    }
+}
+
+/// A generator of random 128-bit trace IDs.
+#[derive(Debug, Default)]
+pub struct RandomIdGenerator;
+
+impl IdGenerator for RandomIdGenerator {
+    fn trace_id(&self) -> TraceId {
+        TraceId::new()
+    }
+}
+
+/// A generator of trace IDs that are sortable by the trace start time.
+///
+/// The upper 48 bits of the trace ID contain the Unix timestamp in milliseconds, the rest of
+/// the bits are random.
+#[derive(Debug, Default)]
+pub struct TimePrefixedIdGenerator;
+
+impl IdGenerator for TimePrefixedIdGenerator {
+    fn trace_id(&self) -> TraceId {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        TraceId {
+            high: (millis << 16) | rand::random::<u16>() as u64,
+            low: rand::random(),
+        }
+    }
+}
+
+/// Sets a custom generator of the trace and span IDs.
+///
+/// The custom generator takes precedence over the [`trace_id_generation`] setting. Returns
+/// an error if a custom generator has already been set.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::tracing::{self, IdGenerator, TraceId};
+///
+/// struct DatacenterIdGenerator {
+///     datacenter_id: u64,
+/// }
+///
+/// impl IdGenerator for DatacenterIdGenerator {
+///     fn trace_id(&self) -> TraceId {
+///         TraceId {
+///             high: self.datacenter_id,
+///             low: rand::random(),
+///         }
+///     }
+/// }
+///
+/// tracing::set_id_generator(DatacenterIdGenerator { datacenter_id: 42 }).unwrap();
+/// ```
+///
+/// [`trace_id_generation`]: crate::telemetry::settings::TracingSettings::trace_id_generation
+pub fn set_id_generator(generator: impl IdGenerator) -> crate::Result<()> {
+    CUSTOM_GENERATOR
+        .set(Box::new(generator))
+        .map_err(|_| "custom trace ID generator has already been set".into())
+}
+
+pub(crate) fn generator(generation: TraceIdGeneration) -> Box<dyn IdGenerator> {
+    match generation {
+        TraceIdGeneration::Random => Box::new(RandomIdGenerator),
+        TraceIdGeneration::TimePrefixed => Box::new(TimePrefixedIdGenerator),
+    }
+}
+
+pub(crate) fn custom_generator() -> Option<&'static dyn IdGenerator> {
+    CUSTOM_GENERATOR.get().map(AsRef::as_ref)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_prefixed_ids_are_sortable() {
+        let first = TimePrefixedIdGenerator.trace_id();
+
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        let second = TimePrefixedIdGenerator.trace_id();
+
+        assert!(first.high >> 16 < second.high >> 16);
+        assert!(first.to_string() < second.to_string());
+    }
+}
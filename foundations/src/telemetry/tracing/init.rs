@@ -1,20 +1,27 @@
+use super::adaptive_sampling::AdaptiveSamplingRatio;
+use super::circuit_breaker::CircuitBreaker;
+use super::ids::{self, IdGenerator};
 use super::internal::{FinishedSpan, SharedSpan, Tracer};
+#[cfg(unix)]
+use super::unix_socket::UnixSocketExporter;
 use crate::telemetry::scope::ScopeStack;
-use crate::telemetry::settings::TracingSettings;
+use crate::telemetry::settings::{TracesOutput, TracingSettings};
 use crate::{BootstrapResult, ServiceInfo};
 use anyhow::bail;
 use crossbeam_channel::Receiver;
 use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::{Mutex, RwLock};
+use thread_local::ThreadLocal;
 
 use rustracing::tag::Tag;
 use rustracing_jaeger::reporter::JaegerCompactReporter;
+use std::cell::RefCell;
 use std::net::Ipv6Addr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-#[cfg(feature = "testing")]
-use std::borrow::Cow;
-
 #[cfg(feature = "logging")]
 use crate::telemetry::log;
 use crate::telemetry::tracing::rate_limit::RateLimitingProbabilisticSampler;
@@ -24,17 +31,20 @@ static HARNESS: OnceCell<TracingHarness> = OnceCell::new();
 static NOOP_HARNESS: Lazy<TracingHarness> = Lazy::new(|| {
     let (noop_tracer, _) = Tracer::new(Default::default());
 
-    TracingHarness {
-        tracer: noop_tracer,
-        span_scope_stack: Default::default(),
-
-        #[cfg(feature = "testing")]
-        test_tracer_scope_stack: Default::default(),
-    }
+    TracingHarness::new(noop_tracer, &Default::default())
 });
 
 pub(crate) struct TracingHarness {
-    tracer: Tracer,
+    tracer: RwLock<Arc<Tracer>>,
+
+    // NOTE: the tracer is cached per thread along with the generation it's been taken at, so
+    // starting traces doesn't take the lock, which is only taken once the tracer is replaced.
+    tracer_generation: AtomicU64,
+    cached_tracers: ThreadLocal<RefCell<(u64, Arc<Tracer>)>>,
+
+    settings: Mutex<TracingSettings>,
+
+    id_generator: Box<dyn IdGenerator>,
 
     pub(crate) span_scope_stack: ScopeStack<SharedSpan>,
 
@@ -47,24 +57,56 @@ impl TracingHarness {
         HARNESS.get().unwrap_or(&NOOP_HARNESS)
     }
 
-    #[cfg(feature = "testing")]
-    pub(crate) fn tracer(&'static self) -> Cow<'static, Tracer> {
-        self.test_tracer_scope_stack
-            .current()
-            .map(Cow::Owned)
-            .unwrap_or_else(|| Cow::Borrowed(&self.tracer))
+    pub(crate) fn with_tracer<R>(&'static self, f: impl FnOnce(&Tracer) -> R) -> R {
+        #[cfg(feature = "testing")]
+        if let Some(tracer) = self.test_tracer_scope_stack.current() {
+            return f(&tracer);
+        }
+
+        let generation = self.tracer_generation.load(Ordering::Acquire);
+        let cached = self
+            .cached_tracers
+            .get_or(|| RefCell::new((generation, Arc::clone(&self.tracer.read()))));
+
+        // NOTE: the cache is only borrowed here if the tracer is used re-entrantly, in which case
+        // the previous tracer is used until the next call.
+        if cached.borrow().0 != generation {
+            if let Ok(mut cached) = cached.try_borrow_mut() {
+                *cached = (generation, Arc::clone(&self.tracer.read()));
+            }
+        }
+
+        f(&cached.borrow().1)
     }
 
-    #[cfg(not(feature = "testing"))]
-    pub(crate) fn tracer(&'static self) -> &Tracer {
-        &self.tracer
+    fn replace_tracer(&self, tracer: Tracer) {
+        *self.tracer.write() = Arc::new(tracer);
+        self.tracer_generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn new(tracer: Tracer, settings: &TracingSettings) -> Self {
+        Self {
+            tracer: RwLock::new(Arc::new(tracer)),
+            tracer_generation: AtomicU64::new(0),
+            cached_tracers: ThreadLocal::new(),
+            settings: Mutex::new(settings.clone()),
+            id_generator: ids::generator(settings.trace_id_generation),
+            span_scope_stack: ScopeStack::new("span"),
+
+            #[cfg(feature = "testing")]
+            test_tracer_scope_stack: ScopeStack::new("test_tracer"),
+        }
+    }
+
+    pub(crate) fn id_generator(&'static self) -> &'static dyn IdGenerator {
+        ids::custom_generator().unwrap_or(&*self.id_generator)
     }
 }
 
 pub(crate) fn create_tracer_and_span_rx(
-    settings: &TracingSettings,
+    sampler: RateLimitingProbabilisticSampler,
     with_unbounded_chan: bool,
-) -> BootstrapResult<(Tracer, Receiver<FinishedSpan>)> {
+) -> (Tracer, Receiver<FinishedSpan>) {
     const SPAN_CHANNEL_CAPACITY: usize = 30;
 
     let (span_tx, span_rx) = if with_unbounded_chan {
@@ -73,39 +115,125 @@ pub(crate) fn create_tracer_and_span_rx(
         crossbeam_channel::bounded(SPAN_CHANNEL_CAPACITY)
     };
 
-    let tracer = Tracer::with_sender(RateLimitingProbabilisticSampler::new(settings)?, span_tx);
+    let tracer = Tracer::with_sender(sampler, span_tx);
 
-    Ok((tracer, span_rx))
+    (tracer, span_rx)
 }
 
 // NOTE: does nothing if tracing has already been initialized in this process.
 pub(crate) fn init(service_info: &ServiceInfo, settings: &TracingSettings) -> BootstrapResult<()> {
+    #[cfg(feature = "metrics")]
+    super::slo::set_slos(&settings.slos);
+
     if settings.enabled {
-        let (tracer, span_rx) = create_tracer_and_span_rx(settings, false)?;
+        let tracer = start_tracer(service_info, settings)?;
 
-        start_reporter(service_info, settings, span_rx)?;
+        let _ = HARNESS.set(TracingHarness::new(tracer, settings));
+    }
 
-        let harness = TracingHarness {
-            tracer,
-            span_scope_stack: Default::default(),
+    Ok(())
+}
 
-            #[cfg(feature = "testing")]
-            test_tracer_scope_stack: Default::default(),
-        };
+/// The tracer built with the reloaded settings, see [`prepare_reload`].
+pub(crate) struct TracerReload {
+    tracer: Tracer,
+    settings: TracingSettings,
+}
 
-        let _ = HARNESS.set(harness);
+impl TracerReload {
+    /// Replaces the tracer that starts new traces.
+    ///
+    /// The spans of the traces started by the previous tracer are still reported by its
+    /// reporter, which stops once all of them are finished and all the threads have started
+    /// a trace with the new tracer.
+    pub(crate) fn apply(self) {
+        match HARNESS.get() {
+            Some(harness) => {
+                harness.replace_tracer(self.tracer);
+                *harness.settings.lock() = self.settings;
+            }
+            None => {
+                let _ = HARNESS.set(TracingHarness::new(self.tracer, &self.settings));
+            }
+        }
     }
+}
 
-    Ok(())
+// NOTE: returns `None` if the settings haven't changed or tracing stays disabled.
+pub(crate) fn prepare_reload(
+    service_info: &ServiceInfo,
+    settings: &TracingSettings,
+) -> BootstrapResult<Option<TracerReload>> {
+    let unchanged = match HARNESS.get() {
+        Some(harness) => *harness.settings.lock() == *settings,
+        None => !settings.enabled,
+    };
+
+    if unchanged {
+        return Ok(None);
+    }
+
+    let tracer = if settings.enabled {
+        start_tracer(service_info, settings)?
+    } else {
+        Tracer::new(Default::default()).0
+    };
+
+    Ok(Some(TracerReload {
+        tracer,
+        settings: settings.clone(),
+    }))
+}
+
+fn start_tracer(service_info: &ServiceInfo, settings: &TracingSettings) -> BootstrapResult<Tracer> {
+    let sampler = RateLimitingProbabilisticSampler::new(settings)?;
+    let adaptive_ratio = sampler.adaptive_ratio();
+    let (tracer, span_rx) = create_tracer_and_span_rx(sampler, false);
+    let breaker = Arc::new(CircuitBreaker::new(&settings.circuit_breaker));
+
+    #[cfg(feature = "trace-archive")]
+    let span_rx = super::archive::start(
+        service_info,
+        &settings.archive,
+        settings
+            .circuit_breaker
+            .divert_to_archive
+            .then(|| Arc::clone(&breaker)),
+        span_rx,
+    )?;
+
+    start_reporter(service_info, settings, span_rx, adaptive_ratio, breaker)?;
+
+    Ok(tracer)
 }
 
 fn start_reporter(
     service_info: &ServiceInfo,
     settings: &TracingSettings,
     span_rx: Receiver<FinishedSpan>,
+    adaptive_ratio: Option<Arc<AdaptiveSamplingRatio>>,
+    breaker: Arc<CircuitBreaker>,
 ) -> BootstrapResult<()> {
     const REPORTER_COOLDOWN_PERIOD: Duration = Duration::from_secs(2);
 
+    match &settings.output {
+        TracesOutput::JaegerThriftUdp => {}
+
+        #[cfg(unix)]
+        TracesOutput::UnixSocket(output) => {
+            let exporter = UnixSocketExporter::new(service_info, output, breaker);
+
+            thread::spawn(move || exporter.run(span_rx, adaptive_ratio));
+
+            return Ok(());
+        }
+
+        #[cfg(not(unix))]
+        TracesOutput::UnixSocket(_) => {
+            bail!("Unix socket traces output is only supported on Unix platforms")
+        }
+    }
+
     let mut reporter = JaegerCompactReporter::new(service_info.name)?;
 
     reporter.add_service_tag(Tag::new("app.version", service_info.version));
@@ -132,14 +260,27 @@ fn start_reporter(
 
     thread::spawn(move || {
         while let Ok(span) = span_rx.recv() {
-            if let Err(e) = reporter.report(&[span][..]) {
-                #[cfg(feature = "logging")]
-                log::warn!("failed to send a tracing span to the agent"; "error" => %e);
+            if let Some(adaptive_ratio) = &adaptive_ratio {
+                adaptive_ratio.record_spans(1);
+            }
+
+            if !breaker.allow(1) {
+                continue;
+            }
+
+            match reporter.report(&[span][..]) {
+                Ok(()) => breaker.record_success(),
+                Err(e) => {
+                    if !breaker.record_failure() {
+                        #[cfg(feature = "logging")]
+                        log::warn!("failed to send a tracing span to the agent"; "error" => %e);
+                    }
 
-                #[cfg(not(feature = "logging"))]
-                drop(e);
+                    #[cfg(not(feature = "logging"))]
+                    drop(e);
 
-                thread::sleep(REPORTER_COOLDOWN_PERIOD);
+                    thread::sleep(REPORTER_COOLDOWN_PERIOD);
+                }
             }
         }
     });
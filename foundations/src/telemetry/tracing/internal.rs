@@ -1,10 +1,13 @@
+use super::ids::TraceId;
 use super::init::TracingHarness;
-use super::StartTraceOptions;
+#[cfg(feature = "metrics")]
+use super::slo::SloTimer;
+use super::{SpanStatus, StartTraceOptions};
 use rand::{self, Rng};
 
 use crate::telemetry::tracing::rate_limit::RateLimitingProbabilisticSampler;
 use rustracing::tag::Tag;
-use rustracing_jaeger::span::{SpanContext, SpanContextState};
+use rustracing_jaeger::span::{SpanContext, SpanContextState, SpanContextStateBuilder};
 use std::borrow::Cow;
 use std::sync::Arc;
 
@@ -20,6 +23,23 @@ pub(crate) struct SharedSpan {
     // NOTE: store sampling flag separately, so we don't need to acquire lock
     // every time we need to check the flag.
     is_sampled: bool,
+    // NOTE: measures the span regardless of the sampling, so it's not reported with the span.
+    #[cfg(feature = "metrics")]
+    _slo_timer: Option<Arc<SloTimer>>,
+}
+
+impl SharedSpan {
+    /// Starts measuring the span duration if the span has an SLO.
+    pub(crate) fn with_slo(self, name: &str) -> Self {
+        #[cfg(not(feature = "metrics"))]
+        let _ = name;
+
+        Self {
+            #[cfg(feature = "metrics")]
+            _slo_timer: SloTimer::start(name).map(Arc::new),
+            ..self
+        }
+    }
 }
 
 impl From<Span> for SharedSpan {
@@ -29,6 +49,8 @@ impl From<Span> for SharedSpan {
         Self {
             inner: Arc::new(parking_lot::RwLock::new(inner)),
             is_sampled,
+            #[cfg(feature = "metrics")]
+            _slo_timer: None,
         }
     }
 }
@@ -41,16 +63,88 @@ pub fn write_current_span(write_fn: impl FnOnce(&mut Span)) {
     }
 }
 
-pub(crate) fn create_span(name: impl Into<Cow<'static, str>>) -> SharedSpan {
-    match current_span() {
-        Some(parent) => parent.inner.read().child(name, |o| o.start()),
-        None => start_trace(name, Default::default()),
+pub(crate) fn set_status(span: &SharedSpan, status: SpanStatus) {
+    if !span.is_sampled {
+        return;
+    }
+
+    let mut span = span.inner.write();
+
+    span.set_tag(|| Tag::new("status", status.as_str()));
+
+    if status.is_error() {
+        span.set_tag(|| Tag::new("error", true));
+    }
+}
+
+/// Sets the [`SpanStatus::Cancelled`] status of the span on drop, unless the operation has
+/// completed with [`SpanStatusGuard::complete`] or [`SpanStatusGuard::fail`].
+#[cfg(feature = "tower")]
+pub(crate) struct SpanStatusGuard(Option<SharedSpan>);
+
+#[cfg(feature = "tower")]
+impl SpanStatusGuard {
+    /// Creates a guard for the current span.
+    pub(crate) fn new() -> Self {
+        Self(current_span())
+    }
+
+    /// Completes the operation successfully, keeping the status set by the application, if any.
+    pub(crate) fn complete(mut self) {
+        self.0 = None;
+    }
+
+    /// Completes the operation with the failure status.
+    pub(crate) fn fail(mut self, status: SpanStatus) {
+        if let Some(span) = self.0.take() {
+            set_status(&span, status);
+        }
+    }
+}
+
+#[cfg(feature = "tower")]
+impl Drop for SpanStatusGuard {
+    fn drop(&mut self) {
+        if let Some(span) = self.0.take() {
+            set_status(&span, SpanStatus::Cancelled);
+        }
     }
-    .into()
+}
+
+pub(crate) fn create_span(name: impl Into<Cow<'static, str>>) -> SharedSpan {
+    let name = name.into();
+
+    SharedSpan::from(match current_span() {
+        Some(parent) => create_child_span(&parent.inner.read(), name.clone()),
+        None => start_trace(name.clone(), Default::default()),
+    })
+    .with_slo(&name)
+}
+
+fn create_child_span(parent: &Span, name: impl Into<Cow<'static, str>>) -> Span {
+    parent.child(name, |o| {
+        let trace_id = parent.context().map(|ctx| ctx.state().trace_id());
+
+        o.start_with_state(new_span_state(trace_id))
+    })
+}
+
+// NOTE: the IDs are generated by the configured generator instead of the tracer, which
+// always generates random IDs.
+fn new_span_state(trace_id: Option<TraceId>) -> SpanContextState {
+    let generator = TracingHarness::get().id_generator();
+
+    SpanContextStateBuilder::new()
+        .trace_id(trace_id.unwrap_or_else(|| generator.trace_id()))
+        .span_id(generator.span_id())
+        .finish()
 }
 
 pub(crate) fn current_span() -> Option<SharedSpan> {
-    TracingHarness::get().span_scope_stack.current()
+    TracingHarness::get()
+        .span_scope_stack
+        .current()
+        .or_else(|| crate::telemetry::with_thread_context(|ctx| ctx.span.clone()).flatten())
 }
 
 pub(crate) fn span_trace_id(span: &Span) -> Option<String> {
@@ -61,11 +155,22 @@ pub(crate) fn start_trace(
     root_span_name: impl Into<Cow<'static, str>>,
     options: StartTraceOptions,
 ) -> Span {
-    let tracer = TracingHarness::get().tracer();
+    TracingHarness::get()
+        .with_tracer(|tracer| start_trace_with_tracer(tracer, root_span_name, options))
+}
+
+fn start_trace_with_tracer(
+    tracer: &Tracer,
+    root_span_name: impl Into<Cow<'static, str>>,
+    options: StartTraceOptions,
+) -> Span {
     let root_span_name = root_span_name.into();
     let mut span_builder = tracer.span(root_span_name.clone());
+    let mut trace_id = None;
 
     if let Some(state) = options.stitch_with_trace {
+        trace_id = Some(state.trace_id());
+
         let ctx = SpanContext::new(state, vec![]);
 
         span_builder = span_builder.child_of(&ctx);
@@ -79,12 +184,12 @@ pub(crate) fn start_trace(
     }
     let mut current_span = match current_span() {
         Some(current_span) if current_span.is_sampled => current_span,
-        _ => return span_builder.start(),
+        _ => return span_builder.start_with_state(new_span_state(trace_id)),
     };
 
     // if a prior trace was ongoing (e.g. during stitching, forking), we want to
     // link the new trace with the existing one
-    let mut new_trace_root_span = span_builder.start();
+    let mut new_trace_root_span = span_builder.start_with_state(new_span_state(trace_id));
 
     link_new_trace_with_current(&mut current_span, &root_span_name, &mut new_trace_root_span);
 
@@ -148,7 +253,7 @@ fn create_fork_ref_span(
 ) -> Span {
     let fork_ref_span_name = format!("[{fork_name} ref]");
 
-    current_span_lock.child(fork_ref_span_name, |o| o.start())
+    create_child_span(current_span_lock, fork_ref_span_name)
 }
 
 fn should_sample(sampling_ratio: f64) -> bool {
@@ -6,8 +6,20 @@ pub mod internal;
 #[cfg(any(test, feature = "testing"))]
 pub(crate) mod testing;
 
+mod adaptive_sampling;
+#[cfg(feature = "trace-archive")]
+mod archive;
+mod baggage;
+mod circuit_breaker;
+#[cfg(any(unix, feature = "trace-archive"))]
+mod frame;
+mod ids;
 pub(crate) mod init;
 mod rate_limit;
+#[cfg(feature = "metrics")]
+pub(crate) mod slo;
+#[cfg(unix)]
+mod unix_socket;
 
 use self::init::TracingHarness;
 use self::internal::{create_span, current_span, span_trace_id, SharedSpan, Span};
@@ -18,6 +30,10 @@ use std::sync::Arc;
 #[cfg(any(test, feature = "testing"))]
 pub use self::testing::{TestSpan, TestTrace, TestTraceIterator, TestTraceOptions};
 
+pub use self::baggage::{baggage, baggage_item, register_baggage_hook, set_baggage_item};
+pub use self::ids::{
+    set_id_generator, IdGenerator, RandomIdGenerator, TimePrefixedIdGenerator, TraceId,
+};
 pub use rustracing_jaeger::span::SpanContextState as SerializableTraceState;
 
 /// A macro that wraps function body with a tracing span that is active as long as the function
@@ -146,6 +162,86 @@ pub struct StartTraceOptions {
     /// [sampling ratio]: crate::telemetry::settings::TracingSettings::sampling_ratio
     /// [tracing initializaion]: crate::telemetry::init
     pub override_sampling_ratio: Option<f64>,
+
+    /// Baggage items to set on the root span of the new trace, usually received from the upstream
+    /// service along with the stitching state.
+    ///
+    /// The [baggage hooks] registered for the items are run in the context of the root span.
+    ///
+    /// [baggage hooks]: register_baggage_hook
+    pub baggage: Vec<(String, String)>,
+}
+
+/// Status of the operation traced by a span.
+///
+/// The status is reported in the `status` tag of the span. Spans with [`SpanStatus::Error`] and
+/// [`SpanStatus::Timeout`] statuses are also tagged with the `error` tag, so they are
+/// highlighted as failed in Jaeger.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SpanStatus {
+    /// The operation has completed successfully.
+    Ok,
+    /// The operation has failed.
+    Error,
+    /// The operation has been cancelled before completion, e.g. its future has been dropped.
+    Cancelled,
+    /// The operation has timed out.
+    Timeout,
+}
+
+impl SpanStatus {
+    /// Returns the status as it's reported in the `status` tag.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpanStatus::Ok => "ok",
+            SpanStatus::Error => "error",
+            SpanStatus::Cancelled => "cancelled",
+            SpanStatus::Timeout => "timeout",
+        }
+    }
+
+    pub(crate) fn is_error(&self) -> bool {
+        matches!(self, SpanStatus::Error | SpanStatus::Timeout)
+    }
+}
+
+/// Sets the status of the current span, overriding the previously set status.
+///
+/// Spans of the failed requests processed by the middleware bundle of the `tower` feature get
+/// the `error` or `timeout` status automatically, and the `cancelled` status if the request
+/// future is dropped before completion.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::TelemetryContext;
+/// use foundations::telemetry::tracing::{self, test_trace, SpanStatus, TestTraceOptions};
+///
+/// // Test context is used for demonstration purposes to show the resulting traces.
+/// let ctx = TelemetryContext::test();
+///
+/// {
+///     let _scope = ctx.scope();
+///     let _root = tracing::span("root");
+///
+///     tracing::set_span_status(SpanStatus::Cancelled);
+/// }
+///
+/// let traces = ctx.traces(TestTraceOptions {
+///     include_tags: true,
+///     ..Default::default()
+/// });
+///
+/// assert_eq!(
+///     traces,
+///     vec![test_trace! {
+///         "root"; { tags: [("status", "cancelled")] }
+///     }]
+/// );
+/// ```
+pub fn set_span_status(status: SpanStatus) {
+    if let Some(span) = current_span() {
+        internal::set_status(&span, status);
+    }
 }
 
 /// Returns a trace ID of the current span.
@@ -303,9 +399,18 @@ pub fn span(name: impl Into<Cow<'static, str>>) -> SpanScope {
 /// ```
 pub fn start_trace(
     root_span_name: impl Into<Cow<'static, str>>,
-    options: StartTraceOptions,
+    mut options: StartTraceOptions,
 ) -> SpanScope {
-    SpanScope::new(internal::start_trace(root_span_name, options).into())
+    let baggage = std::mem::take(&mut options.baggage);
+    let root_span_name = root_span_name.into();
+    let span = SharedSpan::from(internal::start_trace(root_span_name.clone(), options));
+    let scope = SpanScope::new(span.with_slo(&root_span_name));
+
+    for (name, value) in baggage {
+        set_baggage_item(name, value);
+    }
+
+    scope
 }
 
 /// Returns the current span as a raw [rustracing] crate's `Span` that is used by Foundations internally.
@@ -407,6 +512,81 @@ macro_rules! __add_span_tags {
     };
 }
 
+/// Adds a tag to the current tracing span, computing its value with the closure only if the span
+/// is sampled.
+///
+/// Can be used for the tag values that are expensive to compute, e.g. serialized request
+/// payloads, so their computation is not wasted on the spans that are never reported. Tag values
+/// can be of the same types as with [`add_span_tags`].
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::TelemetryContext;
+/// use foundations::telemetry::tracing::{self, test_trace, StartTraceOptions, TestTraceOptions};
+/// use std::cell::Cell;
+///
+/// // Test context is used for demonstration purposes to show the resulting traces.
+/// let ctx = TelemetryContext::test();
+/// let computed = Cell::new(0);
+///
+/// let serialize_payload = || {
+///     computed.set(computed.get() + 1);
+///
+///     r#"{"user":"alice"}"#
+/// };
+///
+/// {
+///     let _scope = ctx.scope();
+///
+///     {
+///         let _root = tracing::span("sampled");
+///
+///         tracing::span_attr_lazy!("payload", serialize_payload);
+///     }
+///
+///     {
+///         let _root = tracing::start_trace(
+///             "not sampled",
+///             StartTraceOptions {
+///                 override_sampling_ratio: Some(0.0),
+///                 ..Default::default()
+///             },
+///         );
+///
+///         tracing::span_attr_lazy!("payload", serialize_payload);
+///     }
+/// }
+///
+/// assert_eq!(computed.get(), 1);
+///
+/// let traces = ctx.traces(TestTraceOptions {
+///     include_tags: true,
+///     ..Default::default()
+/// });
+///
+/// assert_eq!(
+///     traces,
+///     vec![test_trace! {
+///         "sampled"; {
+///             tags: [("payload", r#"{"user":"alice"}"#)]
+///         }
+///     }]
+/// );
+/// ```
+///
+/// [`add_span_tags`]: crate::telemetry::tracing::add_span_tags
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __span_attr_lazy {
+    ( $name:expr, $value_fn:expr $(,)? ) => {
+        $crate::telemetry::tracing::internal::write_current_span(|span| {
+            span.set_tag(|| {
+                $crate::reexports_for_macros::rustracing::tag::Tag::new($name, ($value_fn)())
+            });
+        });
+    };
+}
+
 /// Adds log fields to the current span.
 ///
 /// Log entries need to be provided as comma-separated `"field" => "value"` pairs there. Fields and
@@ -734,10 +914,140 @@ macro_rules! __test_trace {
     ( @children ) => { vec![] };
 }
 
+/// Declares the tags of a span, so they can be added with [`add_span_schema_tags`] with the tag
+/// keys and value types validated at compile time.
+///
+/// The macro generates a module with the `NAME` constant containing the span name, which is
+/// the module name unless specified explicitly, and a function for each of the declared tags.
+/// Tag value types need to be convertible to the tag value, i.e. be one of `&'static str`,
+/// `String`, `Cow<'static, str>`, `bool`, `i64` or `f64`.
+///
+/// # Examples
+/// ```
+/// use foundations::telemetry::TelemetryContext;
+/// use foundations::telemetry::tracing::{self, test_trace, TestTraceOptions};
+///
+/// tracing::span_schema! {
+///     /// Tags of the span that handles an HTTP request.
+///     pub http_request = "http request" {
+///         /// The HTTP method of the request.
+///         method: &'static str,
+///         /// The status code of the response.
+///         status: i64,
+///     }
+/// }
+///
+/// // Test context is used for demonstration purposes to show the resulting traces.
+/// let ctx = TelemetryContext::test();
+///
+/// {
+///     let _scope = ctx.scope();
+///     let _span = tracing::span(http_request::NAME);
+///
+///     tracing::add_span_schema_tags!(http_request {
+///         method: "GET",
+///         status: 200
+///     });
+/// }
+///
+/// let traces = ctx.traces(TestTraceOptions {
+///     include_tags: true,
+///     ..Default::default()
+/// });
+///
+/// assert_eq!(
+///     traces,
+///     vec![test_trace! {
+///         "http request"; {
+///             tags: [
+///                 ("method", "GET"),
+///                 ("status", 200)
+///             ]
+///         }
+///     }]
+/// );
+/// ```
+///
+/// A misspelled tag key doesn't compile:
+/// ```compile_fail
+/// use foundations::telemetry::tracing;
+///
+/// tracing::span_schema! {
+///     http_request {
+///         status: i64,
+///     }
+/// }
+///
+/// tracing::add_span_schema_tags!(http_request { statsu: 200 });
+/// ```
+///
+/// [`add_span_schema_tags`]: crate::telemetry::tracing::add_span_schema_tags
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __span_schema {
+    (
+        $(#[$attr:meta])*
+        $vis:vis $schema:ident $( = $span_name:literal )? {
+            $(
+                $(#[$tag_attr:meta])*
+                $tag:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[allow(dead_code)]
+        $vis mod $schema {
+            #[allow(unused_imports)]
+            use super::*;
+
+            /// The name of the span.
+            pub const NAME: &str = $crate::__span_schema!(@name $schema $( $span_name )?);
+
+            $(
+                $(#[$tag_attr])*
+                pub fn $tag(
+                    value: $ty,
+                ) -> $crate::reexports_for_macros::rustracing::tag::Tag {
+                    $crate::reexports_for_macros::rustracing::tag::Tag::new(
+                        stringify!($tag),
+                        value,
+                    )
+                }
+            )*
+        }
+    };
+
+    ( @name $schema:ident ) => { stringify!($schema) };
+    ( @name $schema:ident $span_name:literal ) => { $span_name };
+}
+
+/// Adds tags declared with [`span_schema`] to the current span.
+///
+/// Tags are provided in a form of comma-separated `key: value` pairs in braces after the path
+/// to the schema. Keys that are not declared in the schema and values of the wrong type are
+/// compile-time errors.
+///
+/// See [`span_schema`] for the examples.
+///
+/// [`span_schema`]: crate::telemetry::tracing::span_schema
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __add_span_schema_tags {
+    ( $( $schema:ident )::+ { $( $tag:ident : $val:expr ),+ $(,)? } ) => {
+        $crate::telemetry::tracing::internal::write_current_span(|span| {
+            use $( $schema )::+ as __schema;
+
+            span.set_tags(|| vec![ $( __schema::$tag($val) ),+ ]);
+        });
+    };
+}
+
 #[doc(inline)]
 pub use {
-    __add_span_log_fields as add_span_log_fields, __add_span_tags as add_span_tags,
-    __set_span_finish_time as set_span_finish_time, __set_span_start_time as set_span_start_time,
+    __add_span_log_fields as add_span_log_fields, __add_span_schema_tags as add_span_schema_tags,
+    __add_span_tags as add_span_tags, __set_span_finish_time as set_span_finish_time,
+    __set_span_start_time as set_span_start_time, __span_attr_lazy as span_attr_lazy,
+    __span_schema as span_schema,
 };
 
 #[cfg(feature = "testing")]
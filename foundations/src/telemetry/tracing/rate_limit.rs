@@ -1,3 +1,4 @@
+use super::adaptive_sampling::AdaptiveSamplingRatio;
 use crate::telemetry::settings::TracingSettings;
 use governor::clock::DefaultClock;
 use governor::middleware::NoOpMiddleware;
@@ -6,10 +7,12 @@ use governor::{Quota, RateLimiter};
 use rustracing::sampler::Sampler;
 use rustracing::span::CandidateSpan;
 use rustracing::{sampler::ProbabilisticSampler, Result};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub(crate) struct RateLimitingProbabilisticSampler {
     inner: ProbabilisticSampler,
+    adaptive_ratio: Option<Arc<AdaptiveSamplingRatio>>,
     rate_limiter: Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>>,
 }
 
@@ -17,12 +20,14 @@ impl Default for RateLimitingProbabilisticSampler {
     fn default() -> Self {
         Self {
             inner: ProbabilisticSampler::new(0.0).unwrap(),
+            adaptive_ratio: None,
             rate_limiter: None,
         }
     }
 }
 
-/// A tracing sampler which also optionally rate limits the number of spans emitted
+/// A tracing sampler which also optionally adapts the sampling ratio to the number of reported
+/// spans and rate limits the number of spans emitted
 impl RateLimitingProbabilisticSampler {
     /// If `sampling_rate` is not in the range `0.0...1.0`,
     /// it will return an error with the kind `ErrorKind::InvalidInput`.
@@ -38,16 +43,33 @@ impl RateLimitingProbabilisticSampler {
             None
         };
 
+        let adaptive_ratio = settings
+            .adaptive_sampling
+            .enabled
+            .then(|| Arc::new(AdaptiveSamplingRatio::new(settings)));
+
         Ok(Self {
             inner: ProbabilisticSampler::new(settings.sampling_ratio)?,
+            adaptive_ratio,
             rate_limiter,
         })
     }
+
+    /// Returns the adaptive sampling ratio that should be fed with the reported spans, if
+    /// adaptive sampling is enabled.
+    pub(crate) fn adaptive_ratio(&self) -> Option<Arc<AdaptiveSamplingRatio>> {
+        self.adaptive_ratio.clone()
+    }
 }
 
 impl<T> Sampler<T> for RateLimitingProbabilisticSampler {
     fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
-        if !self.inner.is_sampled(span) {
+        let is_sampled = match &self.adaptive_ratio {
+            Some(adaptive_ratio) => rand::random::<f64>() < adaptive_ratio.ratio(),
+            None => self.inner.is_sampled(span),
+        };
+
+        if !is_sampled {
             return false;
         }
 
@@ -0,0 +1,126 @@
+use crate::telemetry::metrics::{Counter, Gauge};
+use crate::telemetry::settings::SpanSlo;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// NOTE: duration thresholds of the SLOs, keyed by the span name.
+type Slos = HashMap<Arc<str>, Duration>;
+
+static SLOS: Lazy<RwLock<Arc<Slos>>> = Lazy::new(Default::default);
+
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_tracing {
+    /// Number of the spans that finished within the duration threshold of their SLO.
+    pub fn span_slo_good_total(span: &Arc<str>) -> Counter;
+
+    /// Number of the spans that exceeded the duration threshold of their SLO.
+    pub fn span_slo_bad_total(span: &Arc<str>) -> Counter;
+
+    /// Target ratio of the good spans of the SLO.
+    #[removable]
+    pub fn span_slo_objective(span: &Arc<str>) -> Gauge<f64, AtomicU64>;
+}
+
+/// Replaces the span duration SLOs, see [`TracingSettings::slos`].
+///
+/// [`TracingSettings::slos`]: crate::telemetry::settings::TracingSettings::slos
+pub(crate) fn set_slos(settings: &[SpanSlo]) {
+    let slos: Slos = settings
+        .iter()
+        .map(|slo| {
+            let span_name: Arc<str> = slo.span_name.as_str().into();
+
+            foundations_tracing::span_slo_objective(&span_name).set(slo.objective);
+
+            (span_name, Duration::from_millis(slo.threshold_ms))
+        })
+        .collect();
+
+    let prev = std::mem::replace(&mut *SLOS.write(), Arc::new(slos));
+
+    for span_name in prev.keys() {
+        if !settings.iter().any(|slo| *slo.span_name == **span_name) {
+            foundations_tracing::remove_span_slo_objective(span_name);
+        }
+    }
+}
+
+/// Measures the duration of a span with an SLO, reporting whether it met the SLO on drop.
+#[derive(Debug)]
+pub(crate) struct SloTimer {
+    span_name: Arc<str>,
+    threshold: Duration,
+    start: Instant,
+}
+
+impl SloTimer {
+    /// Starts the timer if the span has an SLO.
+    pub(crate) fn start(span_name: &str) -> Option<Self> {
+        let slos = Arc::clone(&SLOS.read());
+        let (span_name, threshold) = slos.get_key_value(span_name)?;
+
+        Some(Self {
+            span_name: Arc::clone(span_name),
+            threshold: *threshold,
+            start: Instant::now(),
+        })
+    }
+}
+
+impl Drop for SloTimer {
+    fn drop(&mut self) {
+        if self.start.elapsed() <= self.threshold {
+            foundations_tracing::span_slo_good_total(&self.span_name).inc();
+        } else {
+            foundations_tracing::span_slo_bad_total(&self.span_name).inc();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::telemetry::tracing;
+    use crate::telemetry::TelemetryContext;
+
+    #[test]
+    fn counts_spans_by_slo_threshold() {
+        let ctx = TelemetryContext::test();
+
+        set_slos(&[
+            SpanSlo {
+                span_name: "slo_fast".into(),
+                threshold_ms: 60_000,
+                objective: 0.99,
+            },
+            SpanSlo {
+                span_name: "slo_slow".into(),
+                threshold_ms: 0,
+                objective: 0.9,
+            },
+        ]);
+
+        {
+            let _scope = ctx.scope();
+            let _root = tracing::span("slo_fast");
+
+            {
+                let _child = tracing::span("slo_slow");
+
+                std::thread::sleep(Duration::from_millis(1));
+            }
+
+            let _other = tracing::span("without_slo");
+        }
+
+        let metrics = ctx.metrics();
+
+        assert!(metrics.contains("span_slo_good_total{span=\"slo_fast\"} 1\n"));
+        assert!(metrics.contains("span_slo_bad_total{span=\"slo_slow\"} 1\n"));
+        assert!(!metrics.contains("without_slo"));
+    }
+}
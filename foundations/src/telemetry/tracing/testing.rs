@@ -1,5 +1,6 @@
 use super::init::{create_tracer_and_span_rx, TracingHarness};
 use super::internal::{FinishedSpan, Tracer};
+use super::rate_limit::RateLimitingProbabilisticSampler;
 use crate::telemetry::scope::Scope;
 use crate::telemetry::settings::TracingSettings;
 use crossbeam_channel::Receiver;
@@ -252,12 +253,17 @@ fn span_tags(raw_span: &FinishedSpan) -> Vec<(String, TagValue)> {
 }
 
 pub(crate) fn current_test_tracer() -> Option<Tracer> {
-    TracingHarness::get().test_tracer_scope_stack.current()
+    TracingHarness::get()
+        .test_tracer_scope_stack
+        .current()
+        .or_else(|| crate::telemetry::with_thread_context(|ctx| ctx.test_tracer.clone()).flatten())
 }
 
 pub(crate) fn create_test_tracer(settings: &TracingSettings) -> (Tracer, TestTracesSink) {
-    let (tracer, span_rx) = create_tracer_and_span_rx(settings, true)
-        .expect("should create tracer with default settings");
+    let sampler = RateLimitingProbabilisticSampler::new(settings)
+        .expect("should create sampler with default settings");
+
+    let (tracer, span_rx) = create_tracer_and_span_rx(sampler, true);
 
     let sink = TestTracesSink {
         span_rx,
@@ -0,0 +1,253 @@
+use super::adaptive_sampling::AdaptiveSamplingRatio;
+use super::circuit_breaker::CircuitBreaker;
+use super::frame;
+use super::internal::FinishedSpan;
+use crate::telemetry::settings::UnixSocketTracesOutput;
+use crate::ServiceInfo;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use rustracing_jaeger::thrift::jaeger;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "logging")]
+use crate::telemetry::log;
+
+const RECONNECT_COOLDOWN: Duration = Duration::from_secs(2);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Exporter of the span batches to the Unix socket of a local agent.
+pub(super) struct UnixSocketExporter {
+    settings: UnixSocketTracesOutput,
+    process: jaeger::Process,
+    stream: Option<UnixStream>,
+    backlog: VecDeque<Vec<u8>>,
+    last_connect_attempt: Option<Instant>,
+    reconnect_cooldown: Duration,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl UnixSocketExporter {
+    pub(super) fn new(
+        service_info: &ServiceInfo,
+        settings: &UnixSocketTracesOutput,
+        breaker: Arc<CircuitBreaker>,
+    ) -> Self {
+        Self {
+            settings: settings.clone(),
+            process: frame::process(service_info),
+            stream: None,
+            backlog: Default::default(),
+            last_connect_attempt: None,
+            reconnect_cooldown: RECONNECT_COOLDOWN,
+            breaker,
+        }
+    }
+
+    /// Exports the received spans until the channel is closed.
+    pub(super) fn run(
+        mut self,
+        span_rx: Receiver<FinishedSpan>,
+        adaptive_ratio: Option<Arc<AdaptiveSamplingRatio>>,
+    ) {
+        let flush_interval = Duration::from_millis(self.settings.flush_interval_ms);
+        let max_batch_size = self.settings.max_batch_size.max(1);
+        let mut batch = Vec::with_capacity(max_batch_size);
+
+        loop {
+            let deadline = Instant::now() + flush_interval;
+            let mut disconnected = false;
+
+            while batch.len() < max_batch_size {
+                match span_rx.recv_deadline(deadline) {
+                    Ok(span) => batch.push(span),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                }
+            }
+
+            if let Some(adaptive_ratio) = &adaptive_ratio {
+                adaptive_ratio.record_spans(batch.len() as u64);
+            }
+
+            self.export(&batch);
+            batch.clear();
+
+            if disconnected {
+                return;
+            }
+        }
+    }
+
+    /// Queues the batch of spans and writes all the queued batches to the socket.
+    fn export(&mut self, spans: &[FinishedSpan]) {
+        if spans.is_empty() && self.backlog.is_empty() {
+            return;
+        }
+
+        // NOTE: the spans are not encoded while the breaker is open.
+        if !self.breaker.allow(spans.len()) {
+            return;
+        }
+
+        if !spans.is_empty() {
+            match frame::encode_spans(&self.process, spans) {
+                Ok(frame) => {
+                    if self.backlog.len() >= self.settings.max_backfill_batches.max(1) {
+                        self.backlog.pop_front();
+                    }
+
+                    self.backlog.push_back(frame);
+                }
+                Err(e) => {
+                    #[cfg(feature = "logging")]
+                    log::warn!("failed to encode a batch of tracing spans"; "error" => %e);
+
+                    #[cfg(not(feature = "logging"))]
+                    drop(e);
+                }
+            }
+        }
+
+        while !self.backlog.is_empty() && self.connect() {
+            let (Some(stream), Some(frame)) = (&mut self.stream, self.backlog.front()) else {
+                return;
+            };
+
+            if let Err(e) = stream.write_all(frame) {
+                if !self.breaker.record_failure() {
+                    #[cfg(feature = "logging")]
+                    log::warn!("failed to write tracing spans to the agent socket"; "error" => %e);
+                }
+
+                #[cfg(not(feature = "logging"))]
+                drop(e);
+
+                self.stream = None;
+
+                return;
+            }
+
+            self.backlog.pop_front();
+
+            if self.backlog.is_empty() {
+                self.breaker.record_success();
+            }
+        }
+    }
+
+    /// Connects to the agent socket, unless connected already, and returns whether connected.
+    fn connect(&mut self) -> bool {
+        let cooling_down = matches!(
+            self.last_connect_attempt,
+            Some(last) if last.elapsed() < self.reconnect_cooldown
+        );
+
+        let should_connect = self.stream.is_none() && !cooling_down;
+
+        if should_connect {
+            self.last_connect_attempt = Some(Instant::now());
+
+            match UnixStream::connect(&self.settings.path).and_then(|stream| {
+                stream
+                    .set_write_timeout(Some(WRITE_TIMEOUT))
+                    .map(|_| stream)
+            }) {
+                Ok(stream) => self.stream = Some(stream),
+                Err(e) => {
+                    if !self.breaker.record_failure() {
+                        #[cfg(feature = "logging")]
+                        log::warn!(
+                            "failed to connect to the tracing agent socket";
+                            "path" => %self.settings.path.display(),
+                            "error" => %e
+                        );
+                    }
+
+                    #[cfg(not(feature = "logging"))]
+                    drop(e);
+                }
+            }
+        }
+
+        self.stream.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::tracing::internal::Tracer;
+    use crate::telemetry::tracing::rate_limit::RateLimitingProbabilisticSampler;
+    use std::io::Read;
+    use std::os::unix::net::UnixListener;
+    use thrift_codec::message::Message;
+    use thrift_codec::CompactDecode;
+
+    fn finished_spans(names: &[&'static str]) -> Vec<FinishedSpan> {
+        let (span_tx, span_rx) = crossbeam_channel::unbounded();
+        let sampler = RateLimitingProbabilisticSampler::new(&Default::default()).unwrap();
+        let tracer = Tracer::with_sender(sampler, span_tx);
+
+        for name in names {
+            drop(tracer.span(*name).start());
+        }
+
+        span_rx.try_iter().collect()
+    }
+
+    fn read_frame(stream: &mut UnixStream) -> Message {
+        let mut len = [0; 4];
+
+        stream.read_exact(&mut len).unwrap();
+
+        let mut payload = vec![0; u32::from_be_bytes(len) as usize];
+
+        stream.read_exact(&mut payload).unwrap();
+
+        Message::compact_decode(&mut &payload[..]).unwrap()
+    }
+
+    #[test]
+    fn backfills_batches_on_reconnect() {
+        let path =
+            std::env::temp_dir().join(format!("foundations-traces-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut exporter = UnixSocketExporter::new(
+            &crate::service_info!(),
+            &UnixSocketTracesOutput {
+                path: path.clone(),
+                ..Default::default()
+            },
+            Arc::new(CircuitBreaker::new(&Default::default())),
+        );
+
+        exporter.reconnect_cooldown = Duration::ZERO;
+
+        // NOTE: the agent is not listening yet, so the batch is queued.
+        exporter.export(&finished_spans(&["first"]));
+
+        assert_eq!(exporter.backlog.len(), 1);
+
+        let listener = UnixListener::bind(&path).unwrap();
+
+        exporter.export(&finished_spans(&["second", "third"]));
+
+        assert!(exporter.backlog.is_empty());
+
+        let (mut stream, _) = listener.accept().unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(read_frame(&mut stream).method_name(), "emitBatch");
+        }
+
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+//! Memory allocator for testing of the out-of-memory handling.
+//!
+//! With the **test-allocator** feature, Foundations installs [`TestAllocator`] as the global
+//! memory allocator. The allocator forwards all the allocations to the underlying allocator
+//! ([jemalloc] if the **jemalloc** feature is enabled and the system allocator otherwise), but
+//! allows code regions to be executed with a memory budget ([`with_memory_limit`]) or with
+//! deterministic allocation failures ([`fail_allocations_after`]).
+//!
+//! Regions are specific to the current thread, so tests running in parallel don't affect
+//! each other and allocations of the other threads are never failed.
+//!
+//! Note that a failed allocation aborts the process unless the code uses the fallible allocation
+//! APIs, like [`Vec::try_reserve`], so only the code paths that handle allocation failures
+//! should be executed in the regions.
+//!
+//! The feature is intended to be enabled only for tests, e.g. with a dev-dependency:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! foundations = { version = "3", features = ["test-allocator"] }
+//! ```
+//!
+//! # Examples
+//! ```
+//! use foundations::test_allocator::{fail_allocations_after, with_memory_limit};
+//!
+//! fn try_copy(data: &[u8]) -> Option<Vec<u8>> {
+//!     let mut copy = Vec::new();
+//!
+//!     copy.try_reserve_exact(data.len()).ok()?;
+//!     copy.extend_from_slice(data);
+//!
+//!     Some(copy)
+//! }
+//!
+//! let data = vec![42; 4096];
+//!
+//! assert!(with_memory_limit(1024, || try_copy(&data)).is_none());
+//! assert!(with_memory_limit(8192, || try_copy(&data)).is_some());
+//! assert!(fail_allocations_after(0, || try_copy(&data)).is_none());
+//! ```
+//!
+//! [jemalloc]: https://github.com/jemalloc/jemalloc
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+
+#[cfg(feature = "jemalloc")]
+#[doc(hidden)]
+pub type UnderlyingAllocator = tikv_jemallocator::Jemalloc;
+
+#[cfg(feature = "jemalloc")]
+#[doc(hidden)]
+pub const UNDERLYING_ALLOCATOR: UnderlyingAllocator = tikv_jemallocator::Jemalloc;
+
+#[cfg(not(feature = "jemalloc"))]
+#[doc(hidden)]
+pub type UnderlyingAllocator = std::alloc::System;
+
+#[cfg(not(feature = "jemalloc"))]
+#[doc(hidden)]
+pub const UNDERLYING_ALLOCATOR: UnderlyingAllocator = std::alloc::System;
+
+thread_local! {
+    static REGION: Cell<Region> = const { Cell::new(Region::NONE) };
+}
+
+#[derive(Clone, Copy)]
+struct Region {
+    active: bool,
+    limit: usize,
+    allocated: usize,
+    remaining_allocations: usize,
+}
+
+impl Region {
+    const NONE: Self = Self {
+        active: false,
+        limit: usize::MAX,
+        allocated: 0,
+        remaining_allocations: usize::MAX,
+    };
+}
+
+/// Global allocator that can fail allocations in the [regions] of the code.
+///
+/// [regions]: crate::test_allocator
+pub struct TestAllocator<A> {
+    inner: A,
+}
+
+impl<A> TestAllocator<A> {
+    /// Creates a new test allocator that forwards allocations to the `inner` allocator.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+/// Accounts for an allocation of `size` bytes in the current region and returns `false`
+/// if the allocation should fail.
+fn try_account(size: usize) -> bool {
+    // NOTE: the thread local storage can be inaccessible on thread teardown, allocations
+    // are never failed in that case.
+    REGION
+        .try_with(|region| {
+            let mut current = region.get();
+
+            if !current.active {
+                return true;
+            }
+
+            if current.remaining_allocations == 0 {
+                return false;
+            }
+
+            let allocated = match current.allocated.checked_add(size) {
+                Some(allocated) if allocated <= current.limit => allocated,
+                _ => return false,
+            };
+
+            current.remaining_allocations -= 1;
+            current.allocated = allocated;
+            region.set(current);
+
+            true
+        })
+        .unwrap_or(true)
+}
+
+fn account_dealloc(size: usize) {
+    let _ = REGION.try_with(|region| {
+        let mut current = region.get();
+
+        if current.active {
+            current.allocated = current.allocated.saturating_sub(size);
+            region.set(current);
+        }
+    });
+}
+
+// SAFETY: the allocator either forwards the calls to the inner allocator, preserving its safety
+// guarantees, or returns null pointers which signal allocation failures.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TestAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !try_account(layout.size()) {
+            return std::ptr::null_mut();
+        }
+
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if !try_account(layout.size()) {
+            return std::ptr::null_mut();
+        }
+
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        account_dealloc(layout.size());
+
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            if !try_account(new_size - layout.size()) {
+                return std::ptr::null_mut();
+            }
+        } else {
+            account_dealloc(layout.size() - new_size);
+        }
+
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
+
+fn with_region<R>(region: Region, f: impl FnOnce() -> R) -> R {
+    struct Restore(Region);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REGION.with(|region| region.set(self.0));
+        }
+    }
+
+    let _restore = Restore(REGION.with(|current| current.replace(region)));
+
+    f()
+}
+
+/// Executes `f` with a memory budget of `limit` bytes on the current thread.
+///
+/// Allocations fail once the memory allocated by the thread within the region, excluding
+/// the memory deallocated by it, exceeds the limit.
+pub fn with_memory_limit<R>(limit: usize, f: impl FnOnce() -> R) -> R {
+    with_region(
+        Region {
+            active: true,
+            limit,
+            ..Region::NONE
+        },
+        f,
+    )
+}
+
+/// Executes `f` failing all the allocations of the current thread after the first `n` ones
+/// succeed.
+///
+/// Running the code under test with increasing `n` exercises handling of the allocation failure
+/// at each of the allocation sites of the code, one by one.
+pub fn fail_allocations_after<R>(n: usize, f: impl FnOnce() -> R) -> R {
+    with_region(
+        Region {
+            active: true,
+            remaining_allocations: n,
+            ..Region::NONE
+        },
+        f,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Barrier};
+
+    fn try_alloc(size: usize) -> Option<Vec<u8>> {
+        let mut vec = Vec::new();
+
+        vec.try_reserve_exact(size).ok()?;
+
+        Some(vec)
+    }
+
+    #[test]
+    fn enforces_memory_limit() {
+        with_memory_limit(100, || {
+            let first = try_alloc(60).unwrap();
+
+            assert!(try_alloc(60).is_none());
+
+            drop(first);
+
+            assert!(try_alloc(60).is_some());
+        });
+
+        assert!(try_alloc(1000).is_some());
+    }
+
+    #[test]
+    fn fails_allocations_after_n() {
+        let allocated = fail_allocations_after(2, || {
+            [
+                try_alloc(8).is_some(),
+                try_alloc(8).is_some(),
+                try_alloc(8).is_some(),
+            ]
+        });
+
+        assert_eq!(allocated, [true, true, false]);
+    }
+
+    #[test]
+    fn regions_are_thread_local() {
+        let barrier = Arc::new(Barrier::new(2));
+
+        let other = std::thread::spawn({
+            let barrier = Arc::clone(&barrier);
+
+            move || {
+                barrier.wait();
+
+                let allocated = try_alloc(1024).is_some();
+
+                barrier.wait();
+
+                allocated
+            }
+        });
+
+        with_memory_limit(0, || {
+            barrier.wait();
+            barrier.wait();
+        });
+
+        assert!(other.join().unwrap());
+    }
+}
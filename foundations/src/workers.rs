@@ -0,0 +1,461 @@
+//! Pools of async workers processing the items of a shared queue.
+//!
+//! [`WorkerPool`] spawns a fixed number of Tokio tasks, the workers, that take the items
+//! submitted to the pool from a bounded queue and process them with the handler of the pool.
+//! The processing of a stream of items, e.g. outgoing notifications, is thus limited in
+//! concurrency, and the producers wait for the workers once the queue is full.
+//!
+//! With the `logging` feature, each worker runs with its own forked log that has the `pool` and
+//! `worker_id` fields, so the records produced by the handler can be attributed to the worker.
+//!
+//! With the `metrics` feature, the following metrics labeled with the pool name are reported:
+//!
+//! - `<prefix>_foundations_workers_queue_depth` range gauge with the number of the items waiting
+//!   in the queue;
+//! - `<prefix>_foundations_workers_queue_latency_seconds` histogram with the time the items
+//!   waited in the queue before a worker took them;
+//! - `<prefix>_foundations_workers_busy_seconds_total` counter with the time each worker has been
+//!   processing the items, additionally labeled with the `worker_id`;
+//! - `<prefix>_foundations_workers_processed_total` counter with the number of the items processed
+//!   by each worker, additionally labeled with the `worker_id`.
+//!
+//! [`WorkerPool::shutdown`] stops accepting new items and waits for the workers to process
+//! the queued ones. The returned future can be registered with the [`ShutdownCoordinator`] in
+//! the [`ShutdownStage::DrainWorkers`] stage, so the queue is drained within the stage timeout.
+//!
+//! # Examples
+//! ```
+//! use foundations::workers::{WorkerPool, WorkerPoolSettings};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> foundations::Result<()> {
+//! let pool = WorkerPool::new(
+//!     "notifications",
+//!     &WorkerPoolSettings::default(),
+//!     |email: String| async move {
+//!         // Send the notification...
+//!         # let _ = email;
+//!     },
+//! );
+//!
+//! let queue = pool.queue();
+//!
+//! queue
+//!     .submit("user@example.com".to_string())
+//!     .await
+//!     .expect("pool is not shut down");
+//!
+//! pool.shutdown().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ShutdownCoordinator`]: crate::shutdown::ShutdownCoordinator
+//! [`ShutdownStage::DrainWorkers`]: crate::shutdown::ShutdownStage::DrainWorkers
+
+use crate::Result;
+use anyhow::Context;
+use futures_util::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "settings")]
+use crate::settings::settings;
+
+#[cfg(feature = "logging")]
+use crate::telemetry::{log, TelemetryContext};
+
+#[cfg(feature = "metrics")]
+use crate::telemetry::metrics::{Counter, DurationHistogram, HistogramBuilder, RangeGauge};
+
+#[cfg(feature = "metrics")]
+use std::sync::atomic::AtomicU64;
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+#[crate::telemetry::metrics::metrics(crate_path = "crate")]
+mod foundations_workers {
+    /// Number of the items waiting in the queue of the pool.
+    pub fn queue_depth(pool: &'static str) -> RangeGauge;
+
+    /// Time the items waited in the queue before a worker took them.
+    #[ctor = HistogramBuilder {
+        buckets: &[0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0],
+    }]
+    pub fn queue_latency(pool: &'static str) -> DurationHistogram;
+
+    /// Time the worker has been processing the items.
+    pub fn busy_seconds_total(pool: &'static str, worker_id: usize) -> Counter<f64, AtomicU64>;
+
+    /// Number of the items processed by the worker.
+    pub fn processed_total(pool: &'static str, worker_id: usize) -> Counter;
+}
+
+/// Settings of a [`WorkerPool`].
+#[cfg_attr(
+    feature = "settings",
+    settings(crate_path = "crate", impl_default = false)
+)]
+#[cfg_attr(not(feature = "settings"), derive(Clone, Debug))]
+pub struct WorkerPoolSettings {
+    /// Number of the workers of the pool.
+    pub workers: usize,
+
+    /// Maximum number of the items waiting in the queue, the submission of the other items waits
+    /// for the workers to take the queued ones.
+    pub queue_capacity: usize,
+}
+
+impl Default for WorkerPoolSettings {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            queue_capacity: 1024,
+        }
+    }
+}
+
+/// A fixed number of workers processing the items of a shared queue.
+///
+/// See the [module-level documentation] for more details.
+///
+/// Dropping the pool stops accepting new items, same as [`WorkerPool::shutdown`], but doesn't
+/// wait for the workers to process the queued ones.
+///
+/// [module-level documentation]: crate::workers
+pub struct WorkerPool<T> {
+    queue: WorkQueue<T>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Creates a new pool with the given settings and spawns its workers.
+    ///
+    /// The `name` is used as a `pool` label of the metrics and a `pool` field of the log of
+    /// the workers. `handler` is called by a worker to process each item.
+    ///
+    /// # Panics
+    /// If called outside of a Tokio runtime.
+    pub fn new<F, Fut>(name: &'static str, settings: &WorkerPoolSettings, handler: F) -> Self
+    where
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(settings.queue_capacity.max(1));
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let workers = (0..settings.workers.max(1))
+            .map(|worker_id| {
+                let worker = Worker {
+                    pool: name,
+                    id: worker_id,
+                    receiver: Arc::clone(&receiver),
+                    handler: Arc::clone(&handler),
+                };
+
+                let run = worker.run();
+
+                #[cfg(feature = "logging")]
+                let run = {
+                    let ctx = TelemetryContext::current().with_forked_log();
+                    let _scope = ctx.scope();
+
+                    log::add_fields!("pool" => name, "worker_id" => worker_id);
+
+                    ctx.apply(run)
+                };
+
+                tokio::spawn(run)
+            })
+            .collect();
+
+        Self {
+            queue: WorkQueue {
+                name,
+                sender: Arc::new(Mutex::new(Some(sender))),
+            },
+            workers,
+        }
+    }
+}
+
+impl<T> WorkerPool<T> {
+    /// Returns a handle to submit the items to the pool.
+    pub fn queue(&self) -> WorkQueue<T> {
+        self.queue.clone()
+    }
+
+    /// Stops accepting new items and waits for the workers to process the queued ones.
+    ///
+    /// The submissions waiting for the space in the queue when the pool is shut down are still
+    /// processed.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.queue.close();
+
+        for worker in std::mem::take(&mut self.workers) {
+            worker
+                .await
+                .with_context(|| format!("worker of pool `{}` failed", self.queue.name))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+/// A cheaply cloneable handle to submit the items to a [`WorkerPool`], returned by
+/// [`WorkerPool::queue`].
+pub struct WorkQueue<T> {
+    name: &'static str,
+    // NOTE: `None` once the pool is shut down, so the clones of the handle don't keep the queue
+    // open.
+    sender: Arc<Mutex<Option<mpsc::Sender<Queued<T>>>>>,
+}
+
+impl<T> WorkQueue<T> {
+    /// Submits the item to the pool, waiting for the space in the queue if it's full.
+    ///
+    /// Returns the item back if the pool is shut down.
+    pub async fn submit(&self, item: T) -> std::result::Result<(), T> {
+        let Some(sender) = self.sender() else {
+            return Err(item);
+        };
+
+        sender
+            .send(Queued::new(self.name, item))
+            .await
+            .map_err(|e| e.0.into_item())
+    }
+
+    /// Submits the item to the pool if there is space in the queue.
+    ///
+    /// Returns the item back if the queue is full or the pool is shut down.
+    pub fn try_submit(&self, item: T) -> std::result::Result<(), T> {
+        let Some(sender) = self.sender() else {
+            return Err(item);
+        };
+
+        sender
+            .try_send(Queued::new(self.name, item))
+            .map_err(|e| e.into_inner().into_item())
+    }
+
+    /// Returns `true` if the pool doesn't accept new items anymore.
+    pub fn is_closed(&self) -> bool {
+        self.sender().is_none()
+    }
+
+    fn sender(&self) -> Option<mpsc::Sender<Queued<T>>> {
+        self.sender
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn close(&self) {
+        self.sender.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+}
+
+impl<T> Clone for WorkQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            sender: Arc::clone(&self.sender),
+        }
+    }
+}
+
+/// An item in the queue of the pool.
+struct Queued<T> {
+    item: Option<T>,
+    #[cfg(feature = "metrics")]
+    pool: &'static str,
+    #[cfg(feature = "metrics")]
+    queued_at: Instant,
+}
+
+impl<T> Queued<T> {
+    fn new(
+        #[cfg_attr(not(feature = "metrics"), allow(unused))] pool: &'static str,
+        item: T,
+    ) -> Self {
+        #[cfg(feature = "metrics")]
+        foundations_workers::queue_depth(pool).inc();
+
+        Self {
+            item: Some(item),
+            #[cfg(feature = "metrics")]
+            pool,
+            #[cfg(feature = "metrics")]
+            queued_at: Instant::now(),
+        }
+    }
+
+    /// Takes the item out of the queue to be processed by a worker.
+    fn take(self) -> T {
+        #[cfg(feature = "metrics")]
+        foundations_workers::queue_latency(self.pool).observe(self.queued_at.elapsed());
+
+        self.into_item()
+    }
+
+    /// Takes the item out of the queue, e.g. if it couldn't be submitted.
+    fn into_item(mut self) -> T {
+        self.item.take().expect("item is only taken once")
+    }
+}
+
+impl<T> Drop for Queued<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        foundations_workers::queue_depth(self.pool).dec();
+    }
+}
+
+struct Worker<T, F> {
+    #[cfg_attr(not(any(feature = "logging", feature = "metrics")), allow(dead_code))]
+    pool: &'static str,
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    id: usize,
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<Queued<T>>>>,
+    handler: Arc<F>,
+}
+
+impl<T, F, Fut> Worker<T, F>
+where
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    async fn run(self) {
+        // NOTE: the workers take the receiver in turns, so only one of them waits for an item.
+        while let Some(queued) = self.receiver.lock().await.recv().await {
+            let item = queued.take();
+
+            #[cfg(feature = "metrics")]
+            let started_at = Instant::now();
+
+            let res = AssertUnwindSafe(async { (self.handler)(item).await })
+                .catch_unwind()
+                .await;
+
+            if res.is_err() {
+                #[cfg(feature = "logging")]
+                log::error!("worker pool handler panicked"; "pool" => self.pool);
+            }
+
+            #[cfg(feature = "metrics")]
+            {
+                foundations_workers::busy_seconds_total(self.pool, self.id)
+                    .inc_by(started_at.elapsed().as_secs_f64());
+                foundations_workers::processed_total(self.pool, self.id).inc();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn drains_queue_on_shutdown() {
+        let processed = Arc::new(AtomicUsize::new(0));
+
+        let pool = WorkerPool::new("test_drains_queue_on_shutdown", &Default::default(), {
+            let processed = Arc::clone(&processed);
+
+            move |n: usize| {
+                let processed = Arc::clone(&processed);
+
+                async move {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    processed.fetch_add(n, Ordering::SeqCst);
+                }
+            }
+        });
+
+        let queue = pool.queue();
+
+        for n in 1..=10 {
+            queue.submit(n).await.unwrap();
+        }
+
+        pool.shutdown().await.unwrap();
+
+        assert_eq!(processed.load(Ordering::SeqCst), 55);
+        assert!(queue.is_closed());
+        assert_eq!(queue.try_submit(1), Err(1));
+    }
+
+    #[cfg(all(feature = "logging", feature = "metrics", feature = "testing"))]
+    #[tokio::test]
+    async fn reports_per_worker_telemetry() {
+        use crate::telemetry::settings::Level;
+
+        let ctx = TelemetryContext::test();
+
+        ctx.apply(async {
+            let settings = WorkerPoolSettings {
+                workers: 2,
+                queue_capacity: 4,
+            };
+
+            let pool = WorkerPool::new("test_pool", &settings, |n: usize| async move {
+                log::warn!("processed"; "n" => n);
+            });
+
+            pool.queue().submit(1).await.unwrap();
+            pool.shutdown().await.unwrap();
+        })
+        .await;
+
+        let records = ctx.log_records();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].level, Level::Warning);
+        assert_eq!(records[0].message, "processed");
+
+        let fields = &records[0].fields;
+        let worker_id = fields
+            .iter()
+            .find_map(|(k, v)| (k == "worker_id").then_some(v.as_str()))
+            .unwrap();
+
+        assert!(fields.contains(&("pool".into(), "test_pool".into())));
+        assert!(fields.contains(&("n".into(), "1".into())));
+
+        let snapshot = ctx.metrics_snapshot();
+        let labels = [("pool", "test_pool"), ("worker_id", worker_id)];
+
+        assert_eq!(
+            snapshot.value("undefined_foundations_workers_processed_total", &labels),
+            Some(1.0)
+        );
+        assert!(snapshot
+            .value("undefined_foundations_workers_busy_seconds_total", &labels)
+            .is_some());
+        assert_eq!(
+            snapshot
+                .histogram(
+                    "undefined_foundations_workers_queue_latency_seconds",
+                    &[("pool", "test_pool")]
+                )
+                .map(|h| h.count),
+            Some(1)
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use foundations::telemetry::log::warn;
-use foundations::telemetry::settings::{LoggingSettings, RateLimitingSettings};
+use foundations::telemetry::log::{debug, error, warn};
+use foundations::telemetry::settings::{
+    LogDedupSettings, LogFilter, LogModuleBudget, LoggingSettings, RateLimitingSettings,
+};
 use foundations::telemetry::TestTelemetryContext;
 use foundations_macros::with_test_telemetry;
+use std::time::Duration;
 
 #[with_test_telemetry(test)]
 fn test_rate_limiter(mut ctx: TestTelemetryContext) {
@@ -25,3 +28,113 @@ fn test_rate_limiter(mut ctx: TestTelemetryContext) {
 
     assert!(ctx.log_records().len() < 32);
 }
+
+mod chatty {
+    use foundations::telemetry::log::{debug, warn};
+
+    pub(super) fn log(i: usize) {
+        warn!("chatty {}", i);
+    }
+
+    pub(super) mod db {
+        use super::*;
+
+        pub(crate) fn log() {
+            warn!("chatty db warn");
+            debug!("chatty db debug");
+        }
+    }
+}
+
+#[with_test_telemetry(test)]
+fn test_module_budgets(mut ctx: TestTelemetryContext) {
+    ctx.set_logging_settings(LoggingSettings {
+        module_budgets: vec![LogModuleBudget {
+            module: "logging::chatty".into(),
+            max_records_per_second: 5,
+        }],
+        ..Default::default()
+    });
+
+    for i in 0..16 {
+        chatty::log(i);
+        warn!("{}", i);
+    }
+
+    let records = ctx.log_records();
+    let chatty = records
+        .iter()
+        .filter(|record| record.message.starts_with("chatty"))
+        .count();
+
+    assert!(chatty < 16);
+    assert_eq!(records.len() - chatty, 16);
+}
+
+#[with_test_telemetry(test)]
+fn test_dedup(mut ctx: TestTelemetryContext) {
+    ctx.set_logging_settings(LoggingSettings {
+        dedup: LogDedupSettings {
+            enabled: true,
+            window_ms: 100,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    for _ in 0..5 {
+        error!("connection refused"; "attempt" => "retry");
+    }
+
+    warn!("not deduplicated");
+    warn!("not deduplicated");
+
+    assert_eq!(ctx.log_records().len(), 3);
+
+    std::thread::sleep(Duration::from_millis(150));
+
+    error!("window elapsed");
+
+    let records = ctx.log_records();
+
+    assert_eq!(records.len(), 5);
+    assert_eq!(records[3].message, "connection refused");
+    assert!(records[3]
+        .fields
+        .contains(&("suppressed_duplicates".to_string(), "4".to_string())));
+    assert_eq!(records[4].message, "window elapsed");
+}
+
+#[with_test_telemetry(test)]
+fn test_filter(mut ctx: TestTelemetryContext) {
+    let filter: LogFilter = "logging::chatty=error, logging::chatty::db=debug"
+        .parse()
+        .unwrap();
+
+    assert_eq!(
+        filter.to_string(),
+        "logging::chatty=error,logging::chatty::db=debug"
+    );
+
+    ctx.set_logging_settings(LoggingSettings {
+        filter,
+        ..Default::default()
+    });
+
+    chatty::log(0);
+    chatty::db::log();
+    warn!("warn");
+    debug!("debug");
+
+    let messages: Vec<_> = ctx
+        .log_records()
+        .iter()
+        .map(|record| record.message.clone())
+        .collect();
+
+    assert_eq!(messages, ["chatty db warn", "chatty db debug", "warn"]);
+
+    assert!("logging::chatty".parse::<LogFilter>().is_err());
+    assert!("logging::chatty=loud".parse::<LogFilter>().is_err());
+    assert!("=debug".parse::<LogFilter>().is_err());
+}
@@ -0,0 +1,41 @@
+use foundations::telemetry::metrics::{self, metrics, Counter};
+use foundations::telemetry::settings::MetricsSettings;
+
+#[metrics]
+mod deltas {
+    /// Number of processed jobs.
+    pub fn jobs_total() -> Counter;
+}
+
+fn scraped_jobs(settings: &MetricsSettings) -> f64 {
+    let text = metrics::collect(settings).unwrap();
+
+    text.lines()
+        .find_map(|line| line.strip_prefix("undefined_deltas_jobs_total "))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn snapshots_dont_reset_counters() {
+    let settings = MetricsSettings {
+        reset_counters_on_scrape: true,
+        ..Default::default()
+    };
+
+    deltas::jobs_total().inc_by(3);
+
+    assert_eq!(scraped_jobs(&settings), 3.0);
+
+    deltas::jobs_total().inc_by(2);
+
+    let snapshot = metrics::collect_snapshot(&settings).unwrap();
+
+    // NOTE: the snapshot reports the totals and doesn't move the baseline of the deltas.
+    assert_eq!(
+        snapshot.value("undefined_deltas_jobs_total", &[]),
+        Some(5.0)
+    );
+    assert_eq!(scraped_jobs(&settings), 2.0);
+}
@@ -1,6 +1,9 @@
 use foundations::settings::collections::Map;
 use foundations::settings::net::SocketAddr;
-use foundations::settings::{settings, to_yaml_string};
+use foundations::settings::{
+    from_file, from_file_with_profile, from_signed_file, from_signed_file_with_profile,
+    set_sops_path, settings, to_yaml_string, TrustBundle,
+};
 
 #[settings]
 struct NestedStruct {
@@ -256,3 +259,228 @@ fn vec() {
 
     assert_ser_eq!(s, "data/with_vec.yaml");
 }
+
+#[test]
+fn profiles() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/with_profiles.yaml");
+
+    let base: SimpleStruct = from_file(path).unwrap();
+
+    assert_eq!(base.x, 1);
+    assert_eq!(base.inner.a, 1);
+
+    let dev: SimpleStruct = from_file_with_profile(path, "dev").unwrap();
+
+    assert_eq!(dev.x, 2);
+    assert_eq!(dev.inner.a, 1);
+
+    let prod: SimpleStruct = from_file_with_profile(path, "prod").unwrap();
+
+    assert_eq!(prod.x, 1);
+    assert_eq!(prod.inner.a, 10);
+    assert_eq!(prod.inner.b, 2);
+
+    assert!(from_file_with_profile::<SimpleStruct>(path, "staging").is_err());
+}
+
+// NOTE: openssl is not necessarily installed at the default path in the test environments.
+fn trust_bundle(path: impl Into<std::path::PathBuf>) -> TrustBundle {
+    let output = std::process::Command::new("sh")
+        .args(["-c", "command -v openssl"])
+        .output()
+        .unwrap();
+
+    TrustBundle::new(path).with_openssl_path(String::from_utf8(output.stdout).unwrap().trim())
+}
+
+#[test]
+fn signed_file() {
+    let data_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+    let path = format!("{data_dir}/signed.yaml");
+    let trust_bundle = trust_bundle(format!("{data_dir}/trust_bundle.pem"));
+
+    let settings: SimpleStruct = from_signed_file(&path, &trust_bundle).unwrap();
+
+    assert_eq!(settings.x, 1);
+
+    let prod: SimpleStruct = from_signed_file_with_profile(&path, "prod", &trust_bundle).unwrap();
+
+    assert_eq!(prod.inner.a, 10);
+
+    let untrusted = from_signed_file::<SimpleStruct>(
+        &path,
+        &self::trust_bundle(format!("{data_dir}/untrusted_bundle.pem")),
+    );
+
+    assert!(untrusted
+        .unwrap_err()
+        .to_string()
+        .contains("is not signed by any of the 1 key(s)"));
+
+    let tampered =
+        from_signed_file::<SimpleStruct>(format!("{data_dir}/tampered.yaml"), &trust_bundle);
+
+    assert!(tampered
+        .unwrap_err()
+        .to_string()
+        .contains("might have been tampered with"));
+
+    let unsigned =
+        from_signed_file::<SimpleStruct>(format!("{data_dir}/with_vec.yaml"), &trust_bundle);
+
+    assert!(unsigned.unwrap_err().to_string().contains("doesn't exist"));
+
+    let openssl_from_path = from_signed_file::<SimpleStruct>(
+        &path,
+        &TrustBundle::new(format!("{data_dir}/trust_bundle.pem")).with_openssl_path("openssl"),
+    );
+
+    assert!(openssl_from_path
+        .unwrap_err()
+        .to_string()
+        .contains("must be absolute"));
+}
+
+#[test]
+fn signed_sops_file_is_not_read_again() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    let dir = std::env::temp_dir().join(format!("foundations-signed-sops-{}", std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("config.yaml");
+    let encrypted =
+        "x: 1\ninner:\n  a: 1\n  b: 2\n  c: 3\nsops:\n  mac: ENC[AES256_GCM,data:AAAA]\n";
+    let sh = |cmd: String| {
+        assert!(Command::new("sh")
+            .args(["-c", &cmd])
+            .status()
+            .unwrap()
+            .success())
+    };
+
+    std::fs::write(&path, encrypted).unwrap();
+
+    sh(format!(
+        "cd {dir} && openssl ecparam -genkey -name prime256v1 -noout -out key.pem && \
+         openssl ec -in key.pem -pubout -out bundle.pem && \
+         openssl dgst -sha256 -sign key.pem config.yaml | openssl base64 -A > config.yaml.sig",
+        dir = dir.display()
+    ));
+
+    // NOTE: the fake sops swaps the file on disk before "decrypting" the file it's given, the
+    // same way an attacker would between the signature verification and the decryption.
+    let bin_dir = dir.join("bin");
+    let sops = bin_dir.join("sops");
+
+    std::fs::create_dir_all(&bin_dir).unwrap();
+    std::fs::write(
+        &sops,
+        format!(
+            "#!/bin/sh\nfor last; do :; done\nprintf 'x: 666\\n' > '{}'\nsed '/^sops:/,$d' \"$last\"\n",
+            path.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&sops, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    set_sops_path(&sops);
+
+    let settings: SimpleStruct =
+        from_signed_file(&path, &trust_bundle(dir.join("bundle.pem"))).unwrap();
+
+    assert_eq!(settings.x, 1);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "x: 666\n");
+
+    // NOTE: sops is never looked up in `PATH`.
+    std::fs::write(&path, encrypted).unwrap();
+    set_sops_path("sops");
+
+    assert!(from_file::<SimpleStruct>(&path)
+        .unwrap_err()
+        .to_string()
+        .contains("must be absolute"));
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn cli() {
+    use foundations::cli::Cli;
+    use foundations::settings::ValueSource;
+
+    let service_info = foundations::service_info!();
+    let path = std::env::temp_dir().join(format!("foundations-cli-{}.yaml", std::process::id()));
+
+    std::fs::write(&path, "x: 5\ninner:\n  a: 1\n  b: 2\n  c: 3\n").unwrap();
+
+    let new_cli = |args: &[&str]| {
+        let os_args = ["service"].iter().chain(args).map(|arg| arg.to_string());
+
+        Cli::<SimpleStruct>::new_from_os_args(&service_info, vec![], os_args).unwrap()
+    };
+
+    let cli = new_cli(&["-c", path.to_str().unwrap()]);
+
+    assert_eq!(cli.settings.x, 5);
+
+    // NOTE: the provenance is computed from the contents the settings are parsed from.
+    std::fs::write(&path, "inner:\n  a: 1\n  b: 2\n  c: 3\nx: 6\n").unwrap();
+
+    assert_eq!(
+        cli.settings_provenance().unwrap().get("x"),
+        ValueSource::File { line: 1 }
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn cli_check_config_exits() {
+    use foundations::cli::Cli;
+    use std::process::Command;
+
+    const CHILD_ENV: &str = "FOUNDATIONS_TEST_CHECK_CONFIG_CHILD";
+
+    // NOTE: the process exits once the config is checked, so the CLI is run in a child process.
+    if let Some(path) = std::env::var_os(CHILD_ENV) {
+        let os_args = ["service".into(), "-c".into(), path, "--check-config".into()];
+
+        Cli::<SimpleStruct>::new_from_os_args(&foundations::service_info!(), vec![], os_args)
+            .unwrap();
+
+        unreachable!("the process should exit");
+    }
+
+    let check_config = |contents: &str| {
+        let path = std::env::temp_dir().join(format!(
+            "foundations-check-config-{}.yaml",
+            std::process::id()
+        ));
+
+        std::fs::write(&path, contents).unwrap();
+
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "cli_check_config_exits", "--nocapture"])
+            .env(CHILD_ENV, &path)
+            .output()
+            .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        output
+    };
+
+    let output = check_config("x: 5\n");
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(".yaml:1\n"));
+
+    let output = check_config("x: five\n");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid config"));
+}
@@ -0,0 +1,63 @@
+use foundations::telemetry::metrics::{metrics, Counter};
+use foundations::telemetry::settings::{TelemetryServerSettings, TelemetrySettings};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+#[metrics(service = "stale")]
+mod stale_metrics {
+    /// Number of requests handled by the service
+    pub fn requests_total() -> Counter;
+}
+
+async fn scrape(server_addr: SocketAddr) -> String {
+    reqwest::get(format!("http://{server_addr}/metrics"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn metrics_are_marked_stale_on_shutdown() {
+    let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 1339));
+
+    let settings = TelemetrySettings {
+        server: TelemetryServerSettings {
+            enabled: true,
+            addr: server_addr.into(),
+            stale_metrics_period_ms: 500,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    let server = tokio::spawn(
+        foundations::telemetry::init_with_server(&foundations::service_info!(), &settings, vec![])
+            .unwrap()
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.await;
+            }),
+    );
+
+    stale_metrics::requests_total().inc();
+
+    assert!(scrape(server_addr)
+        .await
+        .contains("stale_stale_metrics_requests_total 1\n"));
+
+    shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(scrape(server_addr).await, "# EOF\n");
+    assert!(!server.is_finished());
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
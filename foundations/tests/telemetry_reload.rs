@@ -0,0 +1,143 @@
+#![cfg(unix)]
+
+use foundations::telemetry::settings::{
+    Level, LogOutput, LogOutputSettings, LogVerbosity, TelemetrySettings, TracesOutput,
+    UnixSocketTracesOutput,
+};
+use foundations::telemetry::{self, log, tracing};
+use std::io::Read;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn temp_path(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("foundations-reload-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    path
+}
+
+fn settings(log_path: &Path, traces_path: &Path) -> TelemetrySettings {
+    let mut settings = TelemetrySettings::default();
+
+    settings.logging.output = LogOutput::File(log_path.to_path_buf());
+    settings.tracing.output = TracesOutput::UnixSocket(UnixSocketTracesOutput {
+        path: traces_path.to_path_buf(),
+        flush_interval_ms: 10,
+        ..Default::default()
+    });
+
+    settings
+}
+
+fn read_frame(listener: &UnixListener) -> Vec<u8> {
+    let (mut stream, _): (UnixStream, _) = listener.accept().unwrap();
+    let mut len = [0; 4];
+
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+
+    stream.read_exact(&mut len).unwrap();
+
+    let mut payload = vec![0; u32::from_be_bytes(len) as usize];
+
+    stream.read_exact(&mut payload).unwrap();
+
+    payload
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+#[test]
+fn reload_telemetry_pipeline() {
+    let service_info = foundations::service_info!();
+    let log_paths = [
+        temp_path("1.log"),
+        temp_path("2.log"),
+        temp_path("3.log"),
+        temp_path("4.log"),
+    ];
+    let traces_paths = [temp_path("1.sock"), temp_path("2.sock")];
+    let listeners: Vec<_> = traces_paths
+        .iter()
+        .map(|path| UnixListener::bind(path).unwrap())
+        .collect();
+
+    telemetry::init(&service_info, &settings(&log_paths[0], &traces_paths[0])).unwrap();
+
+    log::warn!("before reload");
+
+    let in_flight_span = tracing::span("in flight");
+
+    telemetry::reload(&service_info, &settings(&log_paths[1], &traces_paths[1])).unwrap();
+
+    log::warn!("after reload");
+
+    // NOTE: the span started before the reload is reported to the previous output.
+    drop(in_flight_span);
+    drop(tracing::span("after reload"));
+
+    assert!(contains(&read_frame(&listeners[0]), "in flight"));
+    assert!(contains(&read_frame(&listeners[1]), "after reload"));
+
+    // NOTE: the main output is unchanged, so only the additional output is built.
+    let mut additional_settings = settings(&log_paths[1], &traces_paths[1]);
+
+    additional_settings
+        .logging
+        .additional_outputs
+        .push(LogOutputSettings {
+            output: LogOutput::File(log_paths[2].clone()),
+            verbosity: LogVerbosity(Level::Debug),
+            ..Default::default()
+        });
+
+    telemetry::reload(&service_info, &additional_settings).unwrap();
+
+    log::debug!("additional only");
+
+    // NOTE: the unchanged additional output keeps writing to its file without truncating it.
+    additional_settings
+        .logging
+        .additional_outputs
+        .push(LogOutputSettings {
+            output: LogOutput::File(log_paths[3].clone()),
+            verbosity: LogVerbosity(Level::Debug),
+            ..Default::default()
+        });
+
+    telemetry::reload(&service_info, &additional_settings).unwrap();
+
+    log::debug!("second additional");
+
+    // NOTE: reload the logs back to the terminal to flush the log files.
+    let mut terminal_settings = settings(&log_paths[1], &traces_paths[1]);
+
+    terminal_settings.logging.output = LogOutput::Terminal;
+
+    telemetry::reload(&service_info, &terminal_settings).unwrap();
+
+    let logs: Vec<_> = log_paths
+        .iter()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect();
+
+    assert!(logs[0].contains("before reload"));
+    assert!(!logs[0].contains("after reload"));
+    assert!(logs[1].contains("after reload"));
+    assert!(!logs[1].contains("additional only"));
+    assert!(logs[2].contains("additional only"));
+    assert!(logs[2].contains("second additional"));
+    assert!(!logs[3].contains("additional only"));
+    assert!(logs[3].contains("second additional"));
+
+    for path in log_paths.iter().chain(&traces_paths) {
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,8 +1,12 @@
-use foundations::telemetry::settings::{TelemetryServerSettings, TelemetrySettings};
-use foundations::telemetry::TelemetryServerRoute;
+use foundations::telemetry::log;
+use foundations::telemetry::metrics::{self, metrics, Counter};
+use foundations::telemetry::settings::{Level, TelemetryServerSettings, TelemetrySettings};
+use foundations::telemetry::{HostedService, StartupReport, TelemetryServerRoute};
 use futures_util::FutureExt;
 use hyper::{Method, Response};
 use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[cfg(target_os = "linux")]
 use foundations::telemetry::settings::MemoryProfilerSettings;
@@ -10,6 +14,24 @@ use foundations::telemetry::settings::MemoryProfilerSettings;
 #[cfg(target_os = "linux")]
 use foundations::telemetry::MemoryProfiler;
 
+#[cfg(feature = "fault-injection")]
+use foundations::fault_injection;
+
+#[cfg(feature = "diagnostics")]
+use foundations::telemetry::diagnostics;
+
+#[metrics(service = "sidecar")]
+mod sidecar_metrics {
+    /// Number of requests handled by the sidecar
+    pub fn requests_total() -> Counter;
+}
+
+#[metrics(registry = "internal")]
+mod internal_metrics {
+    /// Number of evicted cache entries
+    pub fn evictions_total() -> Counter;
+}
+
 #[tokio::test]
 async fn telemetry_server() {
     let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 1337));
@@ -18,6 +40,8 @@ async fn telemetry_server() {
         server: TelemetryServerSettings {
             enabled: true,
             addr: server_addr.into(),
+            read_only: false,
+            ..Default::default()
         },
         #[cfg(target_os = "linux")]
         memory_profiler: MemoryProfilerSettings {
@@ -35,19 +59,29 @@ async fn telemetry_server() {
         "memory profiling should be enabled for tests via `_RJEM_MALLOC_CONF=prof:true` env var"
     );
 
+    #[allow(unused_mut)]
+    let mut routes = vec![
+        TelemetryServerRoute {
+            path: "/custom-route".into(),
+            methods: vec![Method::GET],
+            handler: Box::new(|_, _| {
+                async { Ok(Response::builder().body("Hello".into()).unwrap()) }.boxed()
+            }),
+        },
+        log::telemetry_server_route(),
+    ];
+
+    #[cfg(feature = "fault-injection")]
+    routes.push(fault_injection::telemetry_server_route());
+
+    #[cfg(feature = "diagnostics")]
+    routes.push(
+        diagnostics::telemetry_server_route(&foundations::service_info!(), &settings).unwrap(),
+    );
+
     tokio::spawn(
-        foundations::telemetry::init_with_server(
-            &foundations::service_info!(),
-            &settings,
-            vec![TelemetryServerRoute {
-                path: "/custom-route".into(),
-                methods: vec![Method::GET],
-                handler: Box::new(|_, _| {
-                    async { Ok(Response::builder().body("Hello".into()).unwrap()) }.boxed()
-                }),
-            }],
-        )
-        .unwrap(),
+        foundations::telemetry::init_with_server(&foundations::service_info!(), &settings, routes)
+            .unwrap(),
     );
 
     assert_eq!(
@@ -58,6 +92,59 @@ async fn telemetry_server() {
         200
     );
 
+    let sidecar_healthy = Arc::new(AtomicBool::new(false));
+
+    let _sidecar = HostedService::new("sidecar").with_health_check({
+        let sidecar_healthy = Arc::clone(&sidecar_healthy);
+
+        move || sidecar_healthy.load(Ordering::SeqCst)
+    });
+
+    let health_res = reqwest::get(format!("http://{server_addr}/health"))
+        .await
+        .unwrap();
+
+    assert_eq!(health_res.status(), 500);
+    assert!(health_res.text().await.unwrap().contains("sidecar"));
+
+    sidecar_healthy.store(true, Ordering::SeqCst);
+
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/health"))
+            .await
+            .unwrap()
+            .status(),
+        200
+    );
+
+    sidecar_metrics::requests_total().inc();
+    internal_metrics::evictions_total().inc();
+
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/info"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap(),
+        "null"
+    );
+
+    StartupReport::new(&foundations::service_info!(), &settings)
+        .with_bind_addr("telemetry", server_addr)
+        .emit();
+
+    let info_res = reqwest::get(format!("http://{server_addr}/info"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(info_res.contains(r#""service_name":"foundations""#));
+    assert!(info_res.contains(r#""bind_addrs":{"telemetry":"127.0.0.1:1337"}"#));
+    assert!(info_res.contains(r#""telemetry-server""#));
+
     assert_eq!(
         reqwest::get(format!("http://{server_addr}/custom-route"))
             .await
@@ -68,6 +155,81 @@ async fn telemetry_server() {
         "Hello"
     );
 
+    #[cfg(feature = "fault-injection")]
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/fault_injection"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap(),
+        "[]"
+    );
+
+    #[cfg(feature = "fault-injection")]
+    assert_eq!(
+        reqwest::Client::new()
+            .put(format!("http://{server_addr}/fault_injection"))
+            .body(r#"{"point":"upstream","error_probability":1.0}"#)
+            .send()
+            .await
+            .unwrap()
+            .status(),
+        403
+    );
+
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/loglevel"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap(),
+        "INFO"
+    );
+
+    let loglevel_res = reqwest::Client::new()
+        .put(format!("http://{server_addr}/loglevel"))
+        .body("debug")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(loglevel_res.status(), 200);
+    assert_eq!(loglevel_res.text().await.unwrap(), "DEBUG");
+    assert_eq!(log::verbosity(), Level::Debug);
+
+    assert_eq!(
+        reqwest::Client::new()
+            .put(format!("http://{server_addr}/loglevel"))
+            .body("verbose")
+            .send()
+            .await
+            .unwrap()
+            .status(),
+        400
+    );
+
+    log::set_verbosity(Level::Info).unwrap();
+
+    #[cfg(feature = "diagnostics")]
+    {
+        let diagnostics_res = reqwest::get(format!("http://{server_addr}/debug/diagnostics"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            diagnostics_res.headers()["content-type"],
+            "application/gzip"
+        );
+        // NOTE: gzip magic bytes.
+        assert!(diagnostics_res
+            .bytes()
+            .await
+            .unwrap()
+            .starts_with(&[0x1f, 0x8b]));
+    }
+
     let metrics_res = reqwest::get(format!("http://{server_addr}/metrics"))
         .await
         .unwrap()
@@ -77,6 +239,85 @@ async fn telemetry_server() {
 
     assert!(metrics_res.contains("# HELP"));
     assert!(metrics_res.ends_with("# EOF\n"));
+    assert!(metrics_res.contains("sidecar_sidecar_metrics_requests_total 1"));
+    assert!(metrics_res.contains(&format!(
+        r#"build_info{{version="{}",git_sha="{}",rustc_version=""#,
+        env!("CARGO_PKG_VERSION"),
+        option_env!("GIT_SHA").unwrap_or_default()
+    )));
+    assert!(!metrics_res.contains("evictions_total"));
+    assert!(metrics_res.contains(
+        r#"foundations_foundations_telemetry_server_requests_total{route="/health",status="200"} 2"#
+    ));
+    assert!(metrics_res.contains(
+        r#"foundations_foundations_telemetry_server_requests_total{route="/health",status="500"} 1"#
+    ));
+    assert!(metrics_res.contains(
+        r#"foundations_foundations_telemetry_server_request_duration_seconds_count{route="/custom-route"} 1"#
+    ));
+
+    let internal_metrics_res = reqwest::get(format!("http://{server_addr}/metrics/internal"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(internal_metrics_res.contains("foundations_internal_metrics_evictions_total 1"));
+    assert!(!internal_metrics_res.contains("sidecar_sidecar_metrics_requests_total"));
+
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/metrics/unknown"))
+            .await
+            .unwrap()
+            .status(),
+        404
+    );
+
+    let cardinality_res = reqwest::get(format!("http://{server_addr}/debug/cardinality"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(
+        cardinality_res.contains(r#"{"name":"sidecar_sidecar_metrics_requests_total","series":1,"#)
+    );
+
+    let protobuf_res = reqwest::Client::new()
+        .get(format!("http://{server_addr}/metrics"))
+        .header(
+            "accept",
+            "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;\
+            encoding=delimited;q=0.7,text/plain;version=0.0.4;q=0.3",
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        protobuf_res.headers()["content-type"],
+        metrics::PROTOBUF_CONTENT_TYPE
+    );
+
+    let protobuf_res = protobuf_res.bytes().await.unwrap();
+    let needle = b"sidecar_sidecar_metrics_requests_total";
+
+    assert!(protobuf_res.windows(needle.len()).any(|w| w == needle));
+
+    #[cfg(feature = "compression")]
+    {
+        let gzip_res = reqwest::Client::new()
+            .get(format!("http://{server_addr}/metrics"))
+            .header("accept-encoding", "gzip")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(gzip_res.headers()["content-encoding"], "gzip");
+        assert!(gzip_res.bytes().await.unwrap().starts_with(&[0x1f, 0x8b]));
+    }
 
     #[cfg(target_os = "linux")]
     assert!(reqwest::get(format!("http://{server_addr}/pprof/heap"))
@@ -98,3 +339,85 @@ async fn telemetry_server() {
             .contains("Allocated")
     );
 }
+
+#[tokio::test]
+async fn read_only_telemetry_server() {
+    let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 1338));
+    let updated = Arc::new(AtomicBool::new(false));
+
+    let settings = TelemetrySettings {
+        server: TelemetryServerSettings {
+            enabled: true,
+            addr: server_addr.into(),
+            read_only: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    tokio::spawn(
+        foundations::telemetry::init_with_server(
+            &foundations::service_info!(),
+            &settings,
+            vec![
+                TelemetryServerRoute {
+                    path: "/state".into(),
+                    methods: vec![Method::GET, Method::PUT],
+                    handler: Box::new({
+                        let updated = Arc::clone(&updated);
+
+                        move |req, _| {
+                            if req.method() == Method::PUT {
+                                updated.store(true, Ordering::SeqCst);
+                            }
+
+                            async { Ok(Response::builder().body("state".into()).unwrap()) }.boxed()
+                        }
+                    }),
+                },
+                log::telemetry_server_route(),
+            ],
+        )
+        .unwrap(),
+    );
+
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/state"))
+            .await
+            .unwrap()
+            .status(),
+        200
+    );
+
+    assert_eq!(
+        reqwest::Client::new()
+            .put(format!("http://{server_addr}/state"))
+            .send()
+            .await
+            .unwrap()
+            .status(),
+        403
+    );
+
+    assert!(!updated.load(Ordering::SeqCst));
+
+    assert_eq!(
+        reqwest::Client::new()
+            .put(format!("http://{server_addr}/loglevel"))
+            .body("trace")
+            .send()
+            .await
+            .unwrap()
+            .status(),
+        403
+    );
+
+    #[cfg(target_os = "linux")]
+    assert_eq!(
+        reqwest::get(format!("http://{server_addr}/pprof/heap"))
+            .await
+            .unwrap()
+            .status(),
+        404
+    );
+}
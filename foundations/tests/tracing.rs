@@ -31,3 +31,57 @@ fn test_rate_limiter(mut ctx: TestTelemetryContext) {
 
     assert!(ctx.traces(Default::default()).len() < 20);
 }
+
+#[with_test_telemetry(test)]
+fn test_baggage_from_upstream(ctx: TestTelemetryContext) {
+    use foundations::telemetry::tracing::{test_trace, StartTraceOptions, TestTraceOptions};
+
+    tracing::register_baggage_hook("upstream.route", |route| {
+        tracing::add_span_tags!("route" => route.to_string());
+    });
+
+    let (state, baggage) = {
+        let _span = tracing::span("upstream");
+
+        tracing::set_baggage_item("upstream.route", "canary");
+
+        (tracing::state_for_trace_stitching(), tracing::baggage())
+    };
+
+    assert_eq!(
+        baggage,
+        [("upstream.route".to_string(), "canary".to_string())]
+    );
+
+    {
+        let _root = tracing::start_trace(
+            "downstream",
+            StartTraceOptions {
+                stitch_with_trace: state,
+                baggage,
+                ..Default::default()
+            },
+        );
+
+        let _child = tracing::span("child");
+
+        assert_eq!(
+            tracing::baggage_item("upstream.route").as_deref(),
+            Some("canary")
+        );
+    }
+
+    assert_eq!(
+        ctx.traces(TestTraceOptions {
+            include_tags: true,
+            ..Default::default()
+        }),
+        vec![test_trace! {
+            "upstream"; { tags: [("route", "canary")] } => {
+                "downstream"; { tags: [("route", "canary")] } => {
+                    "child"
+                }
+            }
+        }]
+    );
+}
@@ -1,5 +1,14 @@
+use foundations::telemetry::metrics::{metrics, Counter};
 use foundations::telemetry::tracing::{self, test_trace};
-use foundations::telemetry::{with_test_telemetry, TestTelemetryContext};
+use foundations::telemetry::{
+    with_test_telemetry, ScopeLeakDetector, TelemetryContext, TestTelemetryContext,
+};
+
+#[metrics]
+mod test_lib {
+    /// Number of processed jobs, by the kind.
+    pub fn jobs_total(kind: &'static str) -> Counter;
+}
 
 #[with_test_telemetry(tokio::test)]
 async fn wrap_tokio_test(ctx: TestTelemetryContext) {
@@ -39,3 +48,50 @@ fn wrap_rust_test(ctx: TestTelemetryContext) {
         }]
     );
 }
+
+#[with_test_telemetry(tokio::test)]
+async fn isolate_metrics_of_test_contexts(ctx: TestTelemetryContext) {
+    let other_ctx = TelemetryContext::test();
+
+    test_lib::jobs_total("sync").inc();
+
+    other_ctx
+        .apply(async {
+            test_lib::jobs_total("async").inc();
+            tokio::task::yield_now().await;
+            test_lib::jobs_total("async").inc();
+        })
+        .await;
+
+    let metrics = ctx.metrics();
+    let other_metrics = other_ctx.metrics();
+
+    assert!(metrics.contains("undefined_test_lib_jobs_total{kind=\"sync\"} 1\n"));
+    assert!(!metrics.contains("kind=\"async\""));
+    assert!(other_metrics.contains("undefined_test_lib_jobs_total{kind=\"async\"} 2\n"));
+    assert!(!other_metrics.contains("kind=\"sync\""));
+}
+
+#[test]
+fn detect_leaked_scope() {
+    let detector = ScopeLeakDetector::new();
+    let ctx = TelemetryContext::test();
+    let scope = ctx.scope();
+    let span = tracing::span("leaked");
+
+    let res = std::panic::catch_unwind(|| detector.assert_no_leaks());
+
+    // NOTE: scopes are kept for the thread id that can be reused by the other tests,
+    // so we don't actually leak them.
+    drop(span);
+    drop(scope);
+
+    let msg = res.unwrap_err();
+
+    assert_eq!(
+        msg.downcast_ref::<String>().unwrap(),
+        "1 telemetry scope(s) of log leaked on the current thread"
+    );
+
+    detector.assert_no_leaks();
+}